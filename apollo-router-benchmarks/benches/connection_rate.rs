@@ -0,0 +1,92 @@
+//! Measures how fast the router accepts new connections, by opening connections that each send
+//! a single request, with several connections opened at once.
+//!
+//! To compare accepting the connections with Tokio and through io_uring, on Linux:
+//!
+//! ```
+//! cargo bench -p apollo-router-benchmarks --bench connection_rate -- --save-baseline tokio
+//! cargo bench -p apollo-router-benchmarks --bench connection_rate --features apollo-router/io-uring -- --baseline tokio
+//! ```
+//!
+//! Both ends of each connection are open in the same process, so the limit of open files must
+//! be at least twice the largest number of connections opened at once.
+
+use std::net::SocketAddr;
+
+use apollo_router::Configuration;
+use apollo_router::ListenAddr;
+use apollo_router::RouterHttpServer;
+use apollo_router::ShutdownSource;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use serde_json::json;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+/// Answered by the router itself, so that the subgraphs are not involved
+const BODY: &str = r#"{"query":"{ __typename }"}"#;
+
+/// Sends the request on a new connection, and waits until the router closes it
+async fn request(address: SocketAddr) {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let request = format!(
+        "POST / HTTP/1.1\r\nhost: {address}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{BODY}",
+        BODY.len()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 200"));
+}
+
+fn connection_rate(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let configuration: Configuration = serde_json::from_value(json!({
+        "supergraph": { "listen": "127.0.0.1:0" },
+        "health_check": { "enabled": false },
+    }))
+    .unwrap();
+    let (mut router, address) = runtime.block_on(async {
+        let router = RouterHttpServer::builder()
+            .configuration(configuration)
+            .schema(include_str!("fixtures/supergraph.graphql"))
+            .shutdown(ShutdownSource::None)
+            .start();
+        let address = match router.listen_address().await {
+            Some(ListenAddr::SocketAddr(address)) => address,
+            _ => panic!("the router did not start"),
+        };
+        (router, address)
+    });
+
+    let mut group = c.benchmark_group("connection_rate");
+    for connections in [32, 128, 384] {
+        group.throughput(Throughput::Elements(connections));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(connections),
+            &connections,
+            |b, &connections| {
+                b.to_async(&runtime).iter(|| async move {
+                    let mut requests = JoinSet::new();
+                    for _ in 0..connections {
+                        requests.spawn(request(address));
+                    }
+                    while let Some(result) = requests.join_next().await {
+                        result.unwrap();
+                    }
+                });
+            },
+        );
+    }
+    group.finish();
+
+    runtime.block_on(router.shutdown()).unwrap();
+}
+
+criterion_group!(benches, connection_rate);
+criterion_main!(benches);
@@ -0,0 +1,114 @@
+//! Measures the execution of queries fetching many entities, whose representations are
+//! deduplicated before they are sent to the subgraphs, at several concurrency levels.
+//!
+//! To compare the global allocator with the per-thread arenas of the router:
+//!
+//! ```
+//! cargo bench -p apollo-router-benchmarks --bench entity_representations -- --save-baseline global
+//! cargo bench -p apollo-router-benchmarks --bench entity_representations --features apollo-router/arena-allocation -- --baseline global
+//! ```
+
+use apollo_router::graphql::Response;
+use apollo_router::services::subgraph;
+use apollo_router::services::supergraph;
+use apollo_router::TestHarness;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use serde_json::json;
+use tokio::task::JoinSet;
+use tower::ServiceExt;
+
+/// Number of products, each with the reviews of the same few authors
+const PRODUCTS: usize = 1000;
+const AUTHORS: usize = 10;
+
+const QUERY: &str =
+    "query($first: Int) { topProducts(first: $first) { upc reviews { id author { id name } } } }";
+
+/// Answers the fetches of the query, echoing the representations they receive
+fn subgraph(name: &str, request: subgraph::Request) -> subgraph::Response {
+    let body = serde_json::to_value(request.subgraph_request.body()).unwrap();
+    let data = match body["variables"]["representations"].as_array() {
+        None => json!({
+            "topProducts": (0..PRODUCTS)
+                .map(|upc| json!({ "__typename": "Product", "upc": upc.to_string() }))
+                .collect::<Vec<_>>()
+        }),
+        Some(representations) => json!({
+            "_entities": representations
+                .iter()
+                .map(|representation| match name {
+                    "reviews" => json!({
+                        "reviews": (0..AUTHORS)
+                            .map(|author| json!({
+                                "id": format!("{}-{author}", representation["upc"]),
+                                "author": { "__typename": "User", "id": author.to_string() }
+                            }))
+                            .collect::<Vec<_>>()
+                    }),
+                    _ => json!({ "name": format!("User {}", representation["id"]) }),
+                })
+                .collect::<Vec<_>>()
+        }),
+    };
+    subgraph::Response::fake_builder()
+        .data(data)
+        .context(request.context)
+        .build()
+}
+
+fn entity_representations(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let service = runtime
+        .block_on(
+            TestHarness::builder()
+                .try_log_level("error")
+                .schema(include_str!("fixtures/supergraph.graphql"))
+                .subgraph_hook(|name, _| {
+                    let name = name.to_string();
+                    tower::service_fn(move |request| {
+                        std::future::ready(Ok(subgraph(&name, request)))
+                    })
+                    .boxed()
+                })
+                .build_supergraph(),
+        )
+        .unwrap();
+
+    let mut group = c.benchmark_group("entity_representations");
+    for concurrency in [1, 16, 64] {
+        group.throughput(Throughput::Elements(concurrency));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(concurrency),
+            &concurrency,
+            |b, &concurrency| {
+                b.to_async(&runtime).iter(|| {
+                    let service = service.clone();
+                    async move {
+                        let mut requests = JoinSet::new();
+                        for _ in 0..concurrency {
+                            let request = supergraph::Request::fake_builder()
+                                .query(QUERY)
+                                .variable("first", PRODUCTS)
+                                .build()
+                                .unwrap();
+                            requests.spawn(service.clone().oneshot(request));
+                        }
+                        while let Some(response) = requests.join_next().await {
+                            let response: Response =
+                                response.unwrap().unwrap().next_response().await.unwrap();
+                            assert!(response.errors.is_empty(), "{:?}", response.errors);
+                        }
+                    }
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, entity_representations);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! Per-thread arenas for the scratch space of the synchronous steps of the execution.
+//!
+//! Structures that only live during one step are bump allocated in the arena of the thread
+//! running it, and freed all at once when the step ends, instead of going through the global
+//! allocator for each of their allocations.
+use std::cell::RefCell;
+
+use bumpalo::Bump;
+
+/// Above this size, the memory of an arena is given back to the global allocator after each step
+/// instead of being kept for the next one
+const MAX_RETAINED_BYTES: usize = 1024 * 1024;
+
+thread_local! {
+    static ARENA: RefCell<Bump> = RefCell::new(Bump::new());
+}
+
+/// Runs `f` with the arena of the current thread, and frees everything `f` allocated in it once
+/// it returns. Nested calls get an arena of their own.
+pub(crate) fn with_scratch<R>(f: impl FnOnce(&Bump) -> R) -> R {
+    ARENA.with(|arena| match arena.try_borrow_mut() {
+        Ok(mut arena) => {
+            let result = f(&arena);
+            if arena.allocated_bytes() > MAX_RETAINED_BYTES {
+                *arena = Bump::new();
+            } else {
+                arena.reset();
+            }
+            result
+        }
+        Err(_) => f(&Bump::new()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reuses_the_arena_of_the_thread() {
+        let first = with_scratch(|arena| arena.alloc(1u64) as *const u64);
+        let second = with_scratch(|arena| {
+            // Nested steps don't allocate over the scratch space of the outer step
+            let nested = with_scratch(|nested| nested.alloc(3u64) as *const u64);
+            let second = arena.alloc(2u64) as *const u64;
+            assert_ne!(nested, second);
+            second
+        });
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn it_gives_back_large_arenas() {
+        with_scratch(|arena| {
+            arena.alloc_slice_fill_copy(2 * MAX_RETAINED_BYTES, 0u8);
+        });
+        ARENA.with(|arena| assert!(arena.borrow().allocated_bytes() <= MAX_RETAINED_BYTES));
+    }
+}
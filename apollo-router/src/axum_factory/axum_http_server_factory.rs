@@ -38,14 +38,20 @@ use tower_http::trace::TraceLayer;
 use super::listeners::ensure_endpoints_consistency;
 use super::listeners::ensure_listenaddrs_consistency;
 use super::listeners::extra_endpoints;
+use super::listeners::group_internal_endpoints;
+use super::listeners::health_check_listen;
 use super::listeners::ListenersAndRouters;
 use super::utils::decompress_request_body;
+use super::utils::ConnectionInfo;
 use super::utils::PropagatingMakeSpan;
 use super::ListenAddrAndRouter;
 use crate::axum_factory::compression::Compressor;
+use crate::axum_factory::grpc_health;
 use crate::axum_factory::listeners::get_extra_listeners;
 use crate::axum_factory::listeners::serve_router_on_listen_addr;
+use crate::configuration::client_address::ClientAddressConfig;
 use crate::configuration::Configuration;
+use crate::configuration::ErrorStatusCodes;
 use crate::configuration::ListenAddr;
 use crate::http_server_factory::HttpServerFactory;
 use crate::http_server_factory::HttpServerHandle;
@@ -97,22 +103,28 @@ pub(crate) fn make_axum_router<RF>(
     ready: Arc<AtomicBool>,
     service_factory: RF,
     configuration: &Configuration,
-    mut endpoints: MultiMap<ListenAddr, Endpoint>,
+    endpoints: MultiMap<ListenAddr, Endpoint>,
     license: LicenseState,
 ) -> Result<ListenersAndRouters, ApolloRouterError>
 where
     RF: RouterFactory,
 {
+    let mut endpoints = group_internal_endpoints(configuration, endpoints);
     ensure_listenaddrs_consistency(configuration, &endpoints)?;
+    let health_check_listen = health_check_listen(configuration).clone();
+
+    let grpc_health = (configuration.health_check.enabled
+        && configuration.health_check.grpc.enabled)
+        .then(|| grpc_health::router(live.clone(), ready.clone()));
 
     if configuration.health_check.enabled {
         tracing::info!(
             "Health check exposed at {}{}",
-            configuration.health_check.listen,
+            health_check_listen,
             configuration.health_check.path
         );
         endpoints.insert(
-            configuration.health_check.listen.clone(),
+            health_check_listen.clone(),
             Endpoint::from_router_service(
                 configuration.health_check.path.clone(),
                 service_fn(move |req: router::Request| {
@@ -180,6 +192,10 @@ where
         license,
     )?;
     let mut extra_endpoints = extra_endpoints(endpoints);
+    if let Some(grpc_health) = grpc_health {
+        tracing::info!("gRPC health check exposed at {}", health_check_listen);
+        extra_endpoints.insert(health_check_listen, grpc_health);
+    }
 
     // put any extra endpoint that uses the main ListenAddr into the main router
     if let Some(routers) = extra_endpoints.remove(&main_endpoint.0) {
@@ -291,8 +307,28 @@ impl HttpServerFactory for AxumHttpServerFactory {
 
             // serve extra routers
 
+            let internal_tls = match (
+                configuration.experimental_internal_listener.listen(),
+                &configuration.experimental_internal_listener.tls,
+            ) {
+                (Some(listen_addr), Some(tls)) => {
+                    Some((listen_addr, TlsAcceptor::from(tls.tls_config()?)))
+                }
+                _ => None,
+            };
+            if let Some(listen_addr) = configuration.experimental_internal_listener.listen() {
+                tracing::info!(
+                    "Internal endpoints exposed at {}{}",
+                    listen_addr,
+                    if internal_tls.is_some() {
+                        " with TLS"
+                    } else {
+                        ""
+                    }
+                );
+            }
             let listeners_and_routers =
-                get_extra_listeners(previous_listeners, all_routers.extra).await?;
+                get_extra_listeners(previous_listeners, all_routers.extra, internal_tls).await?;
 
             let actual_extra_listen_adresses = listeners_and_routers
                 .iter()
@@ -505,16 +541,30 @@ pub(super) fn main_router<RF>(configuration: &Configuration) -> axum::Router
 where
     RF: RouterFactory,
 {
+    let client_address = Arc::new(configuration.supergraph.client_address.clone());
+    let error_status_codes = configuration.supergraph.error_status_codes;
     let mut router = Router::new().route(
         &configuration.supergraph.sanitized_path(),
         get({
+            let client_address = client_address.clone();
             move |Extension(service): Extension<RF>, request: Request<Body>| {
-                handle_graphql(service.create().boxed(), request)
+                handle_graphql(
+                    service.create().boxed(),
+                    client_address,
+                    error_status_codes,
+                    request,
+                )
             }
         })
         .post({
+            let client_address = client_address.clone();
             move |Extension(service): Extension<RF>, request: Request<Body>| {
-                handle_graphql(service.create().boxed(), request)
+                handle_graphql(
+                    service.create().boxed(),
+                    client_address,
+                    error_status_codes,
+                    request,
+                )
             }
         }),
     );
@@ -523,13 +573,25 @@ where
         router = router.route(
             "/",
             get({
+                let client_address = client_address.clone();
                 move |Extension(service): Extension<RF>, request: Request<Body>| {
-                    handle_graphql(service.create().boxed(), request)
+                    handle_graphql(
+                        service.create().boxed(),
+                        client_address,
+                        error_status_codes,
+                        request,
+                    )
                 }
             })
             .post({
+                let client_address = client_address.clone();
                 move |Extension(service): Extension<RF>, request: Request<Body>| {
-                    handle_graphql(service.create().boxed(), request)
+                    handle_graphql(
+                        service.create().boxed(),
+                        client_address,
+                        error_status_codes,
+                        request,
+                    )
                 }
             }),
         );
@@ -540,11 +602,28 @@ where
 
 async fn handle_graphql(
     service: router::BoxService,
-    http_request: Request<Body>,
+    client_address: Arc<ClientAddressConfig>,
+    error_status_codes: ErrorStatusCodes,
+    mut http_request: Request<Body>,
 ) -> impl IntoResponse {
     let session_count = ACTIVE_SESSION_COUNT.fetch_add(1, Ordering::Release) + 1;
     tracing::info!(value.apollo_router_session_count_active = session_count,);
 
+    let peer_address = http_request
+        .extensions()
+        .get::<ConnectionInfo>()
+        .and_then(|connection_info| connection_info.peer_address);
+    // Without trusted proxies, the consumers of the client address fall back to the TCP peer
+    if !client_address.trusted_proxies.is_empty() {
+        if let Some(client_address) = client_address.resolve(http_request.headers(), peer_address) {
+            tracing::debug!(
+                client.address = %client_address.ip,
+                "resolved the client address through trusted proxies"
+            );
+            http_request.extensions_mut().insert(client_address);
+        }
+    }
+
     let request: router::Request = http_request.into();
     let context = request.context.clone();
     let accept_encoding = request
@@ -564,19 +643,35 @@ async fn handle_graphql(
             let session_count = ACTIVE_SESSION_COUNT.fetch_sub(1, Ordering::Release) - 1;
             tracing::info!(value.apollo_router_session_count_active = session_count,);
 
+            let with_status = |mut response: axum::response::Response, status| {
+                *response.status_mut() = status;
+                response
+            };
             if let Some(source_err) = e.source() {
-                if source_err.is::<RateLimited>() {
-                    return RateLimited::new().into_response();
+                if let Some(rate_limited) = source_err.downcast_ref::<RateLimited>() {
+                    return with_status(
+                        rate_limited.clone().into_response(),
+                        error_status_codes.rate_limited,
+                    );
                 }
                 if source_err.is::<Elapsed>() {
-                    return Elapsed::new().into_response();
+                    return with_status(
+                        Elapsed::new().into_response(),
+                        error_status_codes.router_timeout,
+                    );
                 }
             }
-            if e.is::<RateLimited>() {
-                return RateLimited::new().into_response();
+            if let Some(rate_limited) = e.downcast_ref::<RateLimited>() {
+                return with_status(
+                    rate_limited.clone().into_response(),
+                    error_status_codes.rate_limited,
+                );
             }
             if e.is::<Elapsed>() {
-                return Elapsed::new().into_response();
+                return with_status(
+                    Elapsed::new().into_response(),
+                    error_status_codes.router_timeout,
+                );
             }
 
             (
@@ -0,0 +1,321 @@
+//! Implementation of the [gRPC health checking protocol](https://github.com/grpc/grpc/blob/master/doc/health-checking.md).
+//!
+//! The `grpc.health.v1.Health` service reflects the same liveness and readiness state as the
+//! HTTP health check:
+//! - the empty service name and `ready` report readiness,
+//! - `live` reports liveness,
+//! - any other service name is unknown.
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+
+use axum::body::boxed;
+use axum::body::Bytes;
+use axum::body::StreamBody;
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::post;
+use axum::Router;
+use bytes::Buf;
+use bytes::BufMut;
+use bytes::BytesMut;
+use futures::stream;
+use http::header::CONTENT_TYPE;
+use http::HeaderMap;
+use http::HeaderValue;
+use http_body::Body;
+use prost::Message;
+
+const CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+const WATCH_PATH: &str = "/grpc.health.v1.Health/Watch";
+const GRPC_CONTENT_TYPE: &str = "application/grpc";
+const GRPC_STATUS: &str = "grpc-status";
+const GRPC_MESSAGE: &str = "grpc-message";
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `grpc-status` codes used by this service
+const CODE_OK: u16 = 0;
+const CODE_INVALID_ARGUMENT: u16 = 3;
+const CODE_NOT_FOUND: u16 = 5;
+const CODE_UNIMPLEMENTED: u16 = 12;
+
+#[derive(Clone, PartialEq, Message)]
+struct HealthCheckRequest {
+    #[prost(string, tag = "1")]
+    service: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct HealthCheckResponse {
+    #[prost(enumeration = "ServingStatus", tag = "1")]
+    status: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+enum ServingStatus {
+    Unknown = 0,
+    Serving = 1,
+    NotServing = 2,
+    ServiceUnknown = 3,
+}
+
+#[derive(Clone)]
+struct HealthState {
+    live: Arc<AtomicBool>,
+    ready: Arc<AtomicBool>,
+}
+
+impl HealthState {
+    fn status(&self, service: &str) -> Option<ServingStatus> {
+        let flag = match service {
+            "" | "ready" => &self.ready,
+            "live" => &self.live,
+            _ => return None,
+        };
+        Some(if flag.load(Ordering::SeqCst) {
+            ServingStatus::Serving
+        } else {
+            ServingStatus::NotServing
+        })
+    }
+}
+
+/// Create an [`axum::Router`] serving the `grpc.health.v1.Health` service.
+pub(crate) fn router(live: Arc<AtomicBool>, ready: Arc<AtomicBool>) -> Router {
+    Router::new()
+        .route(CHECK_PATH, post(check))
+        .route(WATCH_PATH, post(watch))
+        .with_state(HealthState { live, ready })
+}
+
+async fn check(State(state): State<HealthState>, body: Bytes) -> Response {
+    let request = match decode_request(body) {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+    tracing::trace!(service = %request.service, "gRPC health check");
+    match state.status(&request.service) {
+        Some(status) => unary_response(status),
+        None => status_response(CODE_NOT_FOUND, "unknown service"),
+    }
+}
+
+async fn watch(State(state): State<HealthState>, body: Bytes) -> Response {
+    let request = match decode_request(body) {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+    tracing::trace!(service = %request.service, "gRPC health watch");
+
+    // Send the current status right away, then every time it changes. Unknown services are
+    // reported as such but the stream is kept open, as required by the protocol.
+    let messages = stream::unfold(
+        (state, request.service, None),
+        |(state, service, previous)| async move {
+            loop {
+                let status = state
+                    .status(&service)
+                    .unwrap_or(ServingStatus::ServiceUnknown);
+                if previous != Some(status) {
+                    let frame = encode_frame(status);
+                    return Some((Ok::<_, Infallible>(frame), (state, service, Some(status))));
+                }
+                tokio::time::sleep(WATCH_INTERVAL).await;
+            }
+        },
+    );
+
+    let mut response = Response::new(boxed(StreamBody::new(messages)));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(GRPC_CONTENT_TYPE));
+    response
+}
+
+/// Decode a length prefixed message, as described in the
+/// [gRPC over HTTP2 protocol](https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md).
+#[allow(clippy::result_large_err)]
+fn decode_request(mut body: Bytes) -> Result<HealthCheckRequest, Response> {
+    if body.is_empty() {
+        return Ok(HealthCheckRequest::default());
+    }
+    if body.len() < 5 {
+        return Err(status_response(
+            CODE_INVALID_ARGUMENT,
+            "invalid message frame",
+        ));
+    }
+    let compressed = body.get_u8();
+    let length = body.get_u32() as usize;
+    if compressed != 0 {
+        return Err(status_response(
+            CODE_UNIMPLEMENTED,
+            "compressed messages are not supported",
+        ));
+    }
+    if body.len() != length {
+        return Err(status_response(
+            CODE_INVALID_ARGUMENT,
+            "invalid message frame",
+        ));
+    }
+    HealthCheckRequest::decode(body)
+        .map_err(|_| status_response(CODE_INVALID_ARGUMENT, "invalid health check request"))
+}
+
+fn encode_frame(status: ServingStatus) -> Bytes {
+    let message = HealthCheckResponse {
+        status: status as i32,
+    };
+    let mut frame = BytesMut::with_capacity(5 + message.encoded_len());
+    frame.put_u8(0);
+    frame.put_u32(message.encoded_len() as u32);
+    message
+        .encode(&mut frame)
+        .expect("buffer has enough capacity");
+    frame.freeze()
+}
+
+fn unary_response(status: ServingStatus) -> Response {
+    let mut trailers = HeaderMap::new();
+    trailers.insert(GRPC_STATUS, HeaderValue::from(CODE_OK));
+    let mut response = Response::new(boxed(UnaryBody {
+        message: Some(encode_frame(status)),
+        trailers: Some(trailers),
+    }));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(GRPC_CONTENT_TYPE));
+    response
+}
+
+/// A "Trailers-Only" response, used for errors.
+fn status_response(code: u16, message: &'static str) -> Response {
+    let mut response = Response::new(boxed(http_body::Empty::<Bytes>::new()));
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(GRPC_CONTENT_TYPE));
+    headers.insert(GRPC_STATUS, HeaderValue::from(code));
+    headers.insert(GRPC_MESSAGE, HeaderValue::from_static(message));
+    response
+}
+
+/// A body made of a single message followed by trailers.
+struct UnaryBody {
+    message: Option<Bytes>,
+    trailers: Option<HeaderMap>,
+}
+
+impl Body for UnaryBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_data(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        Poll::Ready(self.message.take().map(Ok))
+    }
+
+    fn poll_trailers(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        Poll::Ready(Ok(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.message.is_none() && self.trailers.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Method;
+    use http::Request;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn request(path: &str, service: &str) -> Request<hyper::Body> {
+        let message = HealthCheckRequest {
+            service: service.to_string(),
+        };
+        let mut frame = BytesMut::new();
+        frame.put_u8(0);
+        frame.put_u32(message.encoded_len() as u32);
+        message.encode(&mut frame).unwrap();
+        Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header(CONTENT_TYPE, GRPC_CONTENT_TYPE)
+            .body(frame.freeze().into())
+            .unwrap()
+    }
+
+    fn decode_response(mut frame: Bytes) -> ServingStatus {
+        assert_eq!(frame.get_u8(), 0);
+        assert_eq!(frame.get_u32() as usize, frame.len());
+        HealthCheckResponse::decode(frame).unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn it_reflects_liveness_and_readiness() {
+        let live = Arc::new(AtomicBool::new(true));
+        let ready = Arc::new(AtomicBool::new(false));
+        let router = router(live, ready.clone());
+
+        for (service, expected) in [
+            ("", ServingStatus::NotServing),
+            ("ready", ServingStatus::NotServing),
+            ("live", ServingStatus::Serving),
+        ] {
+            let mut response = router
+                .clone()
+                .oneshot(request(CHECK_PATH, service))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let frame = response.body_mut().data().await.unwrap().unwrap();
+            assert_eq!(decode_response(frame), expected, "service {service:?}");
+            let trailers = response.body_mut().trailers().await.unwrap().unwrap();
+            assert_eq!(trailers.get(GRPC_STATUS).unwrap(), "0");
+        }
+
+        ready.store(true, Ordering::SeqCst);
+        let mut response = router.oneshot(request(CHECK_PATH, "")).await.unwrap();
+        let frame = response.body_mut().data().await.unwrap().unwrap();
+        assert_eq!(decode_response(frame), ServingStatus::Serving);
+    }
+
+    #[tokio::test]
+    async fn it_rejects_unknown_services() {
+        let router = router(Default::default(), Default::default());
+        let response = router
+            .oneshot(request(CHECK_PATH, "unknown"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers().get(GRPC_STATUS).unwrap(), "5");
+    }
+
+    #[tokio::test]
+    async fn it_streams_status_changes() {
+        let ready = Arc::new(AtomicBool::new(false));
+        let router = router(Default::default(), ready.clone());
+        let mut response = router.oneshot(request(WATCH_PATH, "")).await.unwrap();
+
+        let frame = response.body_mut().data().await.unwrap().unwrap();
+        assert_eq!(decode_response(frame), ServingStatus::NotServing);
+
+        ready.store(true, Ordering::SeqCst);
+        let frame = response.body_mut().data().await.unwrap().unwrap();
+        assert_eq!(decode_response(frame), ServingStatus::Serving);
+    }
+}
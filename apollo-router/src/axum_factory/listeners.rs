@@ -19,13 +19,16 @@ use multimap::MultiMap;
 use tokio::net::UnixListener;
 use tokio::sync::mpsc;
 use tokio::sync::Notify;
+use tokio_rustls::TlsAcceptor;
 use tower_service::Service;
 
 use crate::axum_factory::utils::ConnectionInfo;
 use crate::axum_factory::utils::InjectConnectionInfo;
 use crate::configuration::Configuration;
+use crate::http_server_factory::Acceptor;
 use crate::http_server_factory::Listener;
 use crate::http_server_factory::NetworkStream;
+use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN_NAME;
 use crate::router::ApolloRouterError;
 use crate::router_factory::Endpoint;
 use crate::ListenAddr;
@@ -83,6 +86,55 @@ pub(super) fn ensure_endpoints_consistency(
     Ok(())
 }
 
+/// Returns the listen address of the health check, which moves to the internal listener if enabled
+pub(super) fn health_check_listen(configuration: &Configuration) -> &ListenAddr {
+    configuration
+        .experimental_internal_listener
+        .listen()
+        .unwrap_or(&configuration.health_check.listen)
+}
+
+/// Moves the endpoints to the internal listener, if enabled, so that they cannot be exposed with
+/// the GraphQL endpoint by mistake. Subscription callbacks stay where they are configured, since
+/// subgraphs reach them through the public URL of the router
+pub(super) fn group_internal_endpoints(
+    configuration: &Configuration,
+    endpoints: MultiMap<ListenAddr, Endpoint>,
+) -> MultiMap<ListenAddr, Endpoint> {
+    let Some(internal) = configuration.experimental_internal_listener.listen() else {
+        return endpoints;
+    };
+    let callback_path = subscription_callback_path(configuration);
+    let mut grouped = MultiMap::new();
+    for (listen_addr, endpoints) in endpoints.into_iter() {
+        for endpoint in endpoints {
+            let is_callback = callback_path
+                .as_deref()
+                .is_some_and(|path| endpoint.path.starts_with(path));
+            if is_callback {
+                grouped.insert(listen_addr.clone(), endpoint);
+            } else {
+                grouped.insert(internal.clone(), endpoint);
+            }
+        }
+    }
+    grouped
+}
+
+/// Returns the path prefix of the subscription callbacks, if callback mode is configured
+fn subscription_callback_path(configuration: &Configuration) -> Option<String> {
+    let callback = configuration
+        .apollo_plugins
+        .plugins
+        .get(APOLLO_SUBSCRIPTION_PLUGIN_NAME)?
+        .pointer("/mode/callback")?;
+    let path = callback
+        .get("path")
+        .and_then(|path| path.as_str())
+        .unwrap_or("/callback");
+    Some(format!("{}/", path.trim_end_matches('/')))
+}
+
 pub(super) fn extra_endpoints(
     endpoints: MultiMap<ListenAddr, Endpoint>,
 ) -> MultiMap<ListenAddr, Router> {
@@ -117,7 +169,7 @@ pub(super) fn ensure_listenaddrs_consistency(
     }
 
     if configuration.health_check.enabled {
-        if let Some((ip, port)) = configuration.health_check.listen.ip_and_port() {
+        if let Some((ip, port)) = health_check_listen(configuration).ip_and_port() {
             if let Some(previous_ip) = all_ports.insert(port, ip) {
                 if ip != previous_ip {
                     return Err(ApolloRouterError::DifferentListenAddrsOnSamePort(
@@ -150,13 +202,28 @@ pub(super) fn ensure_listenaddrs_consistency(
 pub(super) async fn get_extra_listeners(
     previous_listeners: Vec<(ListenAddr, Listener)>,
     mut extra_routers: MultiMap<ListenAddr, Router>,
+    internal_tls: Option<(&ListenAddr, TlsAcceptor)>,
 ) -> Result<Vec<((ListenAddr, Listener), axum::Router)>, ApolloRouterError> {
     let mut listeners_and_routers: Vec<((ListenAddr, Listener), axum::Router)> =
         Vec::with_capacity(extra_routers.len());
+    let tls_acceptor = |listen_addr: &ListenAddr| {
+        internal_tls
+            .as_ref()
+            .filter(|(internal, _)| *internal == listen_addr)
+            .map(|(_, acceptor)| acceptor.clone())
+    };
 
     // reuse previous extra listen addrs
     for (listen_addr, listener) in previous_listeners.into_iter() {
         if let Some(routers) = extra_routers.remove(&listen_addr) {
+            // the TLS configuration may have changed
+            let listener = match listener {
+                Listener::Tcp(listener) | Listener::Tls { listener, .. } => {
+                    Listener::new_from_listener(listener, tls_acceptor(&listen_addr))
+                }
+                #[cfg(unix)]
+                listener => listener,
+            };
             listeners_and_routers.push((
                 (listen_addr, listener),
                 routers
@@ -171,7 +238,9 @@ pub(super) async fn get_extra_listeners(
         // if we received a TCP listener, reuse it, otherwise create a new one
         #[cfg_attr(not(unix), allow(unused_mut))]
         let listener = match listen_addr.clone() {
-            ListenAddr::SocketAddr(addr) => Listener::new_from_socket_addr(addr, None).await?,
+            ListenAddr::SocketAddr(addr) => {
+                Listener::new_from_socket_addr(addr, tls_acceptor(&listen_addr)).await?
+            }
             #[cfg(unix)]
             ListenAddr::UnixSocket(path) => Listener::Unix(
                 UnixListener::bind(path).map_err(ApolloRouterError::ServerCreationError)?,
@@ -189,7 +258,7 @@ pub(super) async fn get_extra_listeners(
 }
 
 pub(super) fn serve_router_on_listen_addr(
-    mut listener: Listener,
+    listener: Listener,
     address: ListenAddr,
     router: axum::Router,
     all_connections_stopped_sender: mpsc::Sender<()>,
@@ -205,6 +274,7 @@ pub(super) fn serve_router_on_listen_addr(
 
         let connection_shutdown = Arc::new(Notify::new());
         let mut max_open_file_warning = None;
+        let mut acceptor = Acceptor::new(listener);
 
         let address = address.to_string();
 
@@ -213,7 +283,7 @@ pub(super) fn serve_router_on_listen_addr(
                 _ = &mut shutdown_receiver => {
                     break;
                 }
-                res = listener.accept() => {
+                res = acceptor.accept() => {
                     let app = router.clone();
                     let connection_shutdown = connection_shutdown.clone();
                     let connection_stop_signal = all_connections_stopped_sender.clone();
@@ -437,7 +507,7 @@ pub(super) fn serve_router_on_listen_addr(
         // the server loop, tell the currently active connections to stop
         // then return the TCP listen socket
         connection_shutdown.notify_waiters();
-        listener
+        acceptor.into_listener()
     };
     (server, shutdown_sender)
 }
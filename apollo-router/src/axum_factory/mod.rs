@@ -1,6 +1,7 @@
 //! axum factory is useful to create an [`AxumHttpServerFactory`] which implements [`crate::http_server_factory::HttpServerFactory`]
 mod axum_http_server_factory;
 mod compression;
+mod grpc_health;
 mod listeners;
 #[cfg(test)]
 pub(crate) mod tests;
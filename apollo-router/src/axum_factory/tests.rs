@@ -55,8 +55,12 @@ use tower::ServiceExt;
 pub(crate) use super::axum_http_server_factory::make_axum_router;
 use super::*;
 use crate::configuration::cors::Cors;
+use crate::configuration::load_certs;
+use crate::configuration::load_key;
 use crate::configuration::HealthCheck;
 use crate::configuration::Homepage;
+use crate::configuration::InternalListener;
+use crate::configuration::InternalListenerTls;
 use crate::configuration::Sandbox;
 use crate::configuration::Supergraph;
 use crate::graphql;
@@ -2291,11 +2295,149 @@ async fn test_supergraph_and_health_check_same_port_different_listener() {
 }
 
 #[tokio::test]
-async fn test_supergraph_timeout() {
+async fn it_groups_endpoints_on_the_internal_listener() {
+    let conf = Configuration::fake_builder()
+        .health_check(
+            HealthCheck::fake_builder()
+                .listen(ListenAddr::SocketAddr("127.0.0.1:4015".parse().unwrap()))
+                .enabled(true)
+                .build(),
+        )
+        .experimental_internal_listener(InternalListener {
+            enabled: true,
+            listen: ListenAddr::SocketAddr("127.0.0.1:4014".parse().unwrap()),
+            tls: None,
+        })
+        .build()
+        .unwrap();
+    let endpoint = |path: &str, body: &'static str| {
+        Endpoint::from_router_service(
+            path.to_string(),
+            service_fn(move |req: router::Request| async move {
+                Ok::<_, BoxError>(router::Response {
+                    response: http::Response::builder()
+                        .body::<hyper::Body>(body.into())
+                        .unwrap(),
+                    context: req.context,
+                })
+            })
+            .boxed(),
+        )
+    };
+    let mut web_endpoints = MultiMap::new();
+    web_endpoints.insert(
+        ListenAddr::SocketAddr("127.0.0.1:4016".parse().unwrap()),
+        endpoint("/metrics", "metrics"),
+    );
+    // endpoints on the GraphQL listener move too
+    web_endpoints.insert(conf.supergraph.listen.clone(), endpoint("/admin", "admin"));
+
+    // keep the server handle around otherwise it will immediately shutdown
+    let (server, client) = init_with_config(
+        router::service::empty().await,
+        Arc::new(conf),
+        web_endpoints,
+    )
+    .await
+    .unwrap();
+
+    let response = client
+        .get("http://localhost:4014/health")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = client
+        .get("http://localhost:4014/metrics")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "metrics");
+    let response = client
+        .get("http://localhost:4014/admin")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.text().await.unwrap(), "admin");
+    let response = client
+        .get(format!(
+            "{}/admin",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(response.status(), StatusCode::OK);
+
+    // the configured listen addresses are not bound
+    assert!(client
+        .get("http://localhost:4015/health")
+        .send()
+        .await
+        .is_err());
+    assert!(client
+        .get("http://localhost:4016/metrics")
+        .send()
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn it_requires_client_certificates_on_the_internal_listener() {
+    let certificate_pem = include_str!("../services/testdata/server.crt");
+    let key_pem = include_str!("../services/testdata/server.key");
+    let ca_pem = include_str!("../services/testdata/CA/ca.crt");
+    let client_certificate_pem = include_str!("../services/testdata/client.crt");
+    let client_key_pem = include_str!("../services/testdata/client.key");
+
+    let ca = load_certs(ca_pem).unwrap();
+    let conf = Configuration::fake_builder()
+        .health_check(HealthCheck::fake_builder().enabled(true).build())
+        .experimental_internal_listener(InternalListener {
+            enabled: true,
+            listen: ListenAddr::SocketAddr("127.0.0.1:4017".parse().unwrap()),
+            tls: Some(InternalListenerTls {
+                certificate: load_certs(certificate_pem).unwrap().remove(0),
+                key: load_key(key_pem).unwrap(),
+                certificate_chain: ca.clone(),
+                client_certificate_authorities: ca,
+            }),
+        })
+        .build()
+        .unwrap();
+    let (_server, _) = init_with_config(
+        router::service::empty().await,
+        Arc::new(conf),
+        MultiMap::new(),
+    )
+    .await
+    .unwrap();
+
+    let client = |identity: Option<reqwest::Identity>| {
+        let mut builder = reqwest::Client::builder()
+            .use_rustls_tls()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_pem(ca_pem.as_bytes()).unwrap());
+        if let Some(identity) = identity {
+            builder = builder.identity(identity);
+        }
+        builder.build().unwrap()
+    };
+    let url = "https://localhost:4017/health";
+
+    assert!(client(None).get(url).send().await.is_err());
+
+    let identity = reqwest::Identity::from_pem(
+        format!("{client_certificate_pem}\n{client_key_pem}").as_bytes(),
+    )
+    .unwrap();
+    let response = client(Some(identity)).get(url).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+async fn supergraph_timeout_response(supergraph: serde_json::Value) -> reqwest::Response {
     let config = serde_json::json!({
-        "supergraph": {
-            "defer_support": false,
-        },
+        "supergraph": supergraph,
         "traffic_shaping": {
             "router": {
                 "timeout": "1ns"
@@ -2340,14 +2482,44 @@ async fn test_supergraph_timeout() {
         .unwrap();
     let url = "http://localhost:4000/";
 
-    let response = client
+    client
         .post(url)
         .body(r#"{ "query": "{ me }" }"#)
         .send()
         .await
-        .unwrap();
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_supergraph_timeout() {
+    let response = supergraph_timeout_response(serde_json::json!({
+        "defer_support": false,
+    }))
+    .await;
 
     assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
-    let body = response.bytes().await.unwrap();
-    assert_eq!(std::str::from_utf8(&body).unwrap(), "request timed out");
+    let body: graphql::Response = response.json().await.unwrap();
+    assert_eq!(body.errors[0].message, "request timed out");
+    assert_eq!(
+        body.errors[0].extensions.get("code").unwrap(),
+        "ROUTER_TIMEOUT"
+    );
+}
+
+#[tokio::test]
+async fn test_supergraph_timeout_status_code() {
+    let response = supergraph_timeout_response(serde_json::json!({
+        "defer_support": false,
+        "error_status_codes": {
+            "router_timeout": 503
+        }
+    }))
+    .await;
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: graphql::Response = response.json().await.unwrap();
+    assert_eq!(
+        body.errors[0].extensions.get("code").unwrap(),
+        "ROUTER_TIMEOUT"
+    );
 }
@@ -12,6 +12,8 @@ use futures::prelude::*;
 use http::header::CONTENT_ENCODING;
 use http::Request;
 use hyper::Body;
+use opentelemetry::baggage::Baggage;
+use opentelemetry::baggage::BaggageExt;
 use opentelemetry::global;
 use opentelemetry::trace::TraceContextExt;
 use tokio::io::AsyncWriteExt;
@@ -19,6 +21,7 @@ use tower_http::trace::MakeSpan;
 use tower_service::Service;
 use tracing::Span;
 
+use crate::plugins::telemetry::debug_token::ForcedSampling;
 use crate::plugins::telemetry::SpanMode;
 use crate::plugins::telemetry::OTEL_STATUS_CODE;
 use crate::uplink::license_enforcement::LicenseState;
@@ -132,7 +135,23 @@ impl<B> MakeSpan<B> for PropagatingMakeSpan {
                 self.span_mode.create_router(request)
             }
         } else {
-            // No remote span, we can go ahead and create the span without context.
+            // No remote span, we can go ahead and create the span without context. The baggage is
+            // still attached, so that it is available to selectors and propagated to subgraphs,
+            // along with the sampling forced by a debug token.
+            let baggage: Baggage = context
+                .baggage()
+                .iter()
+                .map(|(key, (value, metadata))| (key.clone(), (value.clone(), metadata.clone())))
+                .collect();
+            let forced_sampling = context.get::<ForcedSampling>().cloned();
+            let _context_guard = (!baggage.is_empty() || forced_sampling.is_some()).then(|| {
+                let context = opentelemetry::Context::new().with_value(baggage);
+                match forced_sampling {
+                    Some(forced_sampling) => context.with_value(forced_sampling),
+                    None => context,
+                }
+                .attach()
+            });
             if use_legacy_request_span {
                 self.span_mode.create_request(request, self.license)
             } else {
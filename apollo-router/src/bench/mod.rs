@@ -0,0 +1,497 @@
+//! `router bench`: measure the throughput and latency of the router pipeline without a network.
+//!
+//! Requests are sent to the router service built by the [`TestHarness`], so they go through
+//! query analysis, planning, execution and response formatting like in a running router.
+//! Subgraphs are replaced by mocks that wait for a configured latency and return synthetic
+//! responses.
+//!
+//! The operation and the latency of each subgraph fetch can also be reconstructed from an
+//! exported trace, to reproduce a slow request locally.
+use std::collections::HashMap;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::Context as _;
+use clap::Args;
+use futures::StreamExt;
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::json_ext::Object;
+use crate::services::router;
+use crate::services::subgraph;
+use crate::TestHarness;
+
+mod trace;
+
+use self::trace::TraceCase;
+
+/// Options of the `bench` subcommand.
+#[derive(Args, Debug)]
+pub(crate) struct BenchArgs {
+    /// Schema location relative to the current directory.
+    #[clap(short, long = "supergraph", value_parser)]
+    supergraph_path: PathBuf,
+
+    /// Configuration location relative to the current directory.
+    #[clap(short, long = "config", value_parser)]
+    config_path: Option<PathBuf>,
+
+    /// Operations to send: a GraphQL file, a directory of GraphQL files, or a JSON file
+    /// containing an array of GraphQL requests.
+    #[clap(long, value_parser, required_unless_present = "trace")]
+    operations: Option<PathBuf>,
+
+    /// Reconstruct the operation and the subgraph latencies from a trace exported in the
+    /// OTLP JSON format.
+    #[clap(long, value_parser, conflicts_with = "operations")]
+    trace: Option<PathBuf>,
+
+    /// Id of the trace to reconstruct. Defaults to the slowest trace of the file.
+    #[clap(long, requires = "trace")]
+    trace_id: Option<String>,
+
+    /// JSON file containing the variables of the operation reconstructed from a trace, as
+    /// traces do not record them.
+    #[clap(long, value_parser, requires = "trace")]
+    variables: Option<PathBuf>,
+
+    /// Number of requests to send. Operations are sent in turn.
+    #[clap(long, default_value = "1000")]
+    requests: NonZeroUsize,
+
+    /// Number of requests in flight at the same time.
+    #[clap(long, default_value = "10")]
+    concurrency: NonZeroUsize,
+
+    /// Latency of the mocked subgraphs, as `<duration>` for all subgraphs or
+    /// `<subgraph>=<duration>` for one subgraph. Can be repeated.
+    #[clap(long = "subgraph-latency", value_parser = parse_subgraph_latency)]
+    subgraph_latencies: Vec<SubgraphLatency>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct SubgraphLatency {
+    subgraph: Option<String>,
+    latency: Duration,
+}
+
+fn parse_subgraph_latency(value: &str) -> Result<SubgraphLatency, String> {
+    let (subgraph, latency) = match value.split_once('=') {
+        Some((subgraph, latency)) => (Some(subgraph.to_string()), latency),
+        None => (None, value),
+    };
+    let latency = humantime::parse_duration(latency)
+        .map_err(|error| format!("invalid latency '{latency}': {error}"))?;
+    Ok(SubgraphLatency { subgraph, latency })
+}
+
+#[derive(Clone, Debug, Default)]
+struct Latencies {
+    default: Duration,
+    subgraphs: HashMap<String, Duration>,
+    /// Latencies of fetches by subgraph and document, from a trace
+    fetches: HashMap<(String, String), Duration>,
+}
+
+impl Latencies {
+    fn new(latencies: &[SubgraphLatency]) -> Self {
+        let mut result = Latencies::default();
+        for SubgraphLatency { subgraph, latency } in latencies {
+            match subgraph {
+                Some(subgraph) => {
+                    result.subgraphs.insert(subgraph.clone(), *latency);
+                }
+                None => result.default = *latency,
+            }
+        }
+        result
+    }
+
+    /// Trace latencies take precedence over the command line. When a fetch appears several
+    /// times in the trace, the longest latency is used.
+    fn extend_from_trace(&mut self, case: &TraceCase) {
+        let mut subgraphs: HashMap<String, Duration> = HashMap::new();
+        for fetch in &case.fetches {
+            let latency = subgraphs.entry(fetch.subgraph.clone()).or_default();
+            *latency = (*latency).max(fetch.latency);
+            if let Some(document) = &fetch.document {
+                let latency = self
+                    .fetches
+                    .entry((fetch.subgraph.clone(), document.clone()))
+                    .or_default();
+                *latency = (*latency).max(fetch.latency);
+            }
+        }
+        self.subgraphs.extend(subgraphs);
+    }
+
+    fn get(&self, subgraph: &str, document: Option<&str>) -> Duration {
+        document
+            .and_then(|document| {
+                self.fetches
+                    .get(&(subgraph.to_string(), document.to_string()))
+            })
+            .or_else(|| self.subgraphs.get(subgraph))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+impl BenchArgs {
+    pub(crate) async fn run(&self) -> anyhow::Result<()> {
+        let schema = std::fs::read_to_string(&self.supergraph_path).with_context(|| {
+            format!(
+                "could not read the supergraph from {}",
+                self.supergraph_path.display()
+            )
+        })?;
+        let configuration = self
+            .config_path
+            .as_ref()
+            .map(|path| {
+                std::fs::read_to_string(path).with_context(|| {
+                    format!("could not read the configuration from {}", path.display())
+                })
+            })
+            .transpose()?;
+        let mut latencies = Latencies::new(&self.subgraph_latencies);
+        let operations = match (&self.operations, &self.trace) {
+            (_, Some(trace)) => {
+                let case = TraceCase::from_file(trace, self.trace_id.as_deref())?;
+                println!("{case}");
+                latencies.extend_from_trace(&case);
+                let variables = self
+                    .variables
+                    .as_ref()
+                    .map(|path| {
+                        let variables = std::fs::read_to_string(path).with_context(|| {
+                            format!("could not read variables from {}", path.display())
+                        })?;
+                        serde_json::from_str(&variables).with_context(|| {
+                            format!("could not parse variables from {}", path.display())
+                        })
+                    })
+                    .transpose()?
+                    .unwrap_or_default();
+                vec![graphql::Request::builder()
+                    .query(case.query)
+                    .and_operation_name(case.operation_name)
+                    .variables(variables)
+                    .build()]
+            }
+            (Some(operations), None) => load_operations(operations)?,
+            (None, None) => return Err(anyhow!("--operations or --trace is required")),
+        };
+        if operations.is_empty() {
+            return Err(anyhow!("no operations to send"));
+        }
+
+        let latencies = Arc::new(latencies);
+        let mut harness = TestHarness::builder()
+            .schema(&schema)
+            // Replaces the subgraph services instead of the default empty responses
+            .with_subgraph_network_requests()
+            .subgraph_hook(move |name, _| mock_subgraph(name, latencies.clone()));
+        if let Some(configuration) = &configuration {
+            harness = harness.configuration_yaml(configuration)?;
+        }
+        let router = harness
+            .build_router()
+            .await
+            .map_err(|error| anyhow!("could not create the router: {error}"))?;
+
+        // Fill the query plan cache before measuring
+        for operation in &operations {
+            send(router.clone(), operation)
+                .await
+                .map_err(|error| anyhow!("could not send an operation: {error}"))?;
+        }
+
+        let started = Instant::now();
+        let samples: Vec<_> = futures::stream::iter(0..self.requests.get())
+            .map(|index| send(router.clone(), &operations[index % operations.len()]))
+            .buffer_unordered(self.concurrency.get())
+            .collect()
+            .await;
+        let report = Report::new(samples, started.elapsed());
+
+        println!("{report}");
+        Ok(())
+    }
+}
+
+fn load_operations(path: &Path) -> anyhow::Result<Vec<graphql::Request>> {
+    if path.is_dir() {
+        let mut paths = std::fs::read_dir(path)
+            .with_context(|| format!("could not read operations from {}", path.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| {
+            matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("graphql" | "gql")
+            )
+        });
+        paths.sort();
+        paths.iter().map(|path| read_operation(path)).collect()
+    } else if path
+        .extension()
+        .map_or(false, |extension| extension == "json")
+    {
+        let requests = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read operations from {}", path.display()))?;
+        serde_json::from_str(&requests)
+            .with_context(|| format!("could not parse operations from {}", path.display()))
+    } else {
+        Ok(vec![read_operation(path)?])
+    }
+}
+
+fn read_operation(path: &Path) -> anyhow::Result<graphql::Request> {
+    let query = std::fs::read_to_string(path)
+        .with_context(|| format!("could not read operation from {}", path.display()))?;
+    Ok(graphql::Request::builder().query(query).build())
+}
+
+fn mock_subgraph(name: &str, latencies: Arc<Latencies>) -> subgraph::BoxService {
+    let name = name.to_string();
+    tower::service_fn(move |request: subgraph::Request| {
+        let latency = latencies.get(&name, request.subgraph_request.body().query.as_deref());
+        async move {
+            if !latency.is_zero() {
+                tokio::time::sleep(latency).await;
+            }
+            Ok(subgraph::Response::builder()
+                .data(synthetic_data(request.subgraph_request.body()))
+                .extensions(Object::new())
+                .context(request.context)
+                .build())
+        }
+    })
+    .boxed()
+}
+
+/// Entity fetches get their representations back, other fetches get an empty object
+fn synthetic_data(request: &graphql::Request) -> Value {
+    let mut data = Object::new();
+    if let Some(representations) = request.variables.get("representations") {
+        data.insert("_entities", representations.clone());
+    }
+    Value::Object(data)
+}
+
+struct Sample {
+    latency: Duration,
+    has_errors: bool,
+}
+
+async fn send(
+    router: router::BoxCloneService,
+    operation: &graphql::Request,
+) -> Result<Sample, BoxError> {
+    let request = http::Request::post("http://localhost/")
+        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+        .body(router::Body::from(serde_json::to_vec(operation)?))?;
+
+    let started = Instant::now();
+    let response = router.oneshot(request.into()).await?;
+    let status = response.response.status();
+    let body = hyper::body::to_bytes(response.response.into_body()).await?;
+    let latency = started.elapsed();
+
+    // Multipart responses are not inspected
+    let has_errors = !status.is_success()
+        || serde_json::from_slice::<serde_json::Value>(&body)
+            .map(|response| response.get("errors").is_some())
+            .unwrap_or(false);
+    Ok(Sample {
+        latency,
+        has_errors,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+struct Report {
+    requests: usize,
+    errors: usize,
+    elapsed: Duration,
+    /// Latencies of the requests that got a response, sorted
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    fn new(samples: Vec<Result<Sample, BoxError>>, elapsed: Duration) -> Self {
+        let requests = samples.len();
+        let mut errors = 0;
+        let mut latencies = Vec::with_capacity(requests);
+        for sample in samples {
+            match sample {
+                Ok(sample) => {
+                    errors += usize::from(sample.has_errors);
+                    latencies.push(sample.latency);
+                }
+                Err(_) => errors += 1,
+            }
+        }
+        latencies.sort();
+        Report {
+            requests,
+            errors,
+            elapsed,
+            latencies,
+        }
+    }
+
+    fn throughput(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Nearest-rank percentile
+    fn percentile(&self, percentile: usize) -> Duration {
+        let rank = (percentile * self.latencies.len() + 99) / 100;
+        self.latencies
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "requests:   {} ({} with errors)",
+            self.requests, self.errors
+        )?;
+        writeln!(f, "duration:   {:.2?}", self.elapsed)?;
+        writeln!(f, "throughput: {:.1} requests/s", self.throughput())?;
+        write!(
+            f,
+            "latency:    p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+            self.percentile(50),
+            self.percentile(90),
+            self.percentile(99),
+            self.percentile(100)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_subgraph_latencies() {
+        assert_eq!(
+            parse_subgraph_latency("20ms").unwrap(),
+            SubgraphLatency {
+                subgraph: None,
+                latency: Duration::from_millis(20)
+            }
+        );
+        assert_eq!(
+            parse_subgraph_latency("products=1s").unwrap(),
+            SubgraphLatency {
+                subgraph: Some("products".to_string()),
+                latency: Duration::from_secs(1)
+            }
+        );
+        assert!(parse_subgraph_latency("products=fast").is_err());
+
+        let latencies = Latencies::new(&[
+            parse_subgraph_latency("products=1s").unwrap(),
+            parse_subgraph_latency("20ms").unwrap(),
+        ]);
+        assert_eq!(latencies.get("products", None), Duration::from_secs(1));
+        assert_eq!(latencies.get("reviews", None), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn it_uses_trace_latencies() {
+        let mut latencies = Latencies::new(&[
+            parse_subgraph_latency("accounts=1s").unwrap(),
+            parse_subgraph_latency("20ms").unwrap(),
+        ]);
+        let fetch = |subgraph: &str, document: &str, millis| trace::Fetch {
+            subgraph: subgraph.to_string(),
+            document: Some(document.to_string()),
+            latency: Duration::from_millis(millis),
+        };
+        latencies.extend_from_trace(&TraceCase {
+            trace_id: "trace".to_string(),
+            duration: Duration::from_millis(500),
+            query: "{ me { id } }".to_string(),
+            operation_name: None,
+            fetches: vec![
+                fetch("accounts", "{ me { id } }", 100),
+                fetch("reviews", "{ _entities { a } }", 200),
+                fetch("reviews", "{ _entities { b } }", 50),
+                fetch("reviews", "{ _entities { b } }", 80),
+            ],
+        });
+
+        assert_eq!(
+            latencies.get("accounts", Some("{ me { id } }")),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            latencies.get("reviews", Some("{ _entities { b } }")),
+            Duration::from_millis(80)
+        );
+        // Unknown fetches of a subgraph from the trace use its longest latency
+        assert_eq!(
+            latencies.get("reviews", Some("{ other }")),
+            Duration::from_millis(200)
+        );
+        assert_eq!(latencies.get("products", None), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn it_echoes_representations() {
+        let representations = serde_json_bytes::json!([{ "__typename": "User", "id": "1" }]);
+        let request = graphql::Request::builder()
+            .query("query($representations: [_Any!]!) { _entities(representations: $representations) { ... on User { name } } }")
+            .variable("representations", representations.clone())
+            .build();
+        assert_eq!(
+            synthetic_data(&request),
+            serde_json_bytes::json!({ "_entities": representations })
+        );
+        assert_eq!(
+            synthetic_data(&graphql::Request::builder().query("{ me { id } }").build()),
+            serde_json_bytes::json!({})
+        );
+    }
+
+    #[test]
+    fn it_computes_percentiles() {
+        let samples = (1..=100)
+            .map(|ms| {
+                Ok(Sample {
+                    latency: Duration::from_millis(ms),
+                    has_errors: ms == 7,
+                })
+            })
+            .chain(std::iter::once(Err("connection closed".into())))
+            .collect();
+        let report = Report::new(samples, Duration::from_secs(2));
+
+        assert_eq!(report.requests, 101);
+        assert_eq!(report.errors, 2);
+        assert_eq!(report.throughput(), 50.5);
+        assert_eq!(report.percentile(50), Duration::from_millis(50));
+        assert_eq!(report.percentile(99), Duration::from_millis(99));
+        assert_eq!(report.percentile(100), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,345 @@
+//! Reconstruct a benchmark case from an exported trace.
+//!
+//! Traces are read in the OTLP JSON format, as written by the OpenTelemetry collector's file
+//! exporter. The operation comes from the `supergraph` span, and the latency of each subgraph
+//! fetch from its `subgraph_request` span.
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use anyhow::Context as _;
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::plugins::telemetry::SUBGRAPH_SPAN_NAME;
+use crate::plugins::telemetry::SUPERGRAPH_SPAN_NAME;
+
+const SUBGRAPH_REQUEST_SPAN_NAME: &str = "subgraph_request";
+const DOCUMENT: &str = "graphql.document";
+const OPERATION_NAME: &str = "graphql.operation.name";
+const SUBGRAPH_NAME: &str = "apollo.subgraph.name";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportTraceServiceRequest {
+    #[serde(default)]
+    resource_spans: Vec<ResourceSpans>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceSpans {
+    #[serde(default, alias = "instrumentationLibrarySpans")]
+    scope_spans: Vec<ScopeSpans>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ScopeSpans {
+    #[serde(default)]
+    spans: Vec<Span>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Span {
+    trace_id: String,
+    span_id: String,
+    #[serde(default)]
+    parent_span_id: String,
+    name: String,
+    #[serde(deserialize_with = "deserialize_nanos")]
+    start_time_unix_nano: u64,
+    #[serde(deserialize_with = "deserialize_nanos")]
+    end_time_unix_nano: u64,
+    #[serde(default)]
+    attributes: Vec<KeyValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyValue {
+    key: String,
+    value: AnyValue,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AnyValue {
+    string_value: Option<String>,
+}
+
+/// 64 bit integers are encoded as strings in OTLP JSON, but some exporters write numbers
+fn deserialize_nanos<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Nanos {
+        Number(u64),
+        String(String),
+    }
+    match Nanos::deserialize(deserializer)? {
+        Nanos::Number(nanos) => Ok(nanos),
+        Nanos::String(nanos) => nanos.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+impl Span {
+    fn duration(&self) -> Duration {
+        Duration::from_nanos(
+            self.end_time_unix_nano
+                .saturating_sub(self.start_time_unix_nano),
+        )
+    }
+
+    fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.key == key)
+            .and_then(|attribute| attribute.value.string_value.as_deref())
+    }
+}
+
+/// A request reconstructed from a trace
+#[derive(Debug, PartialEq)]
+pub(crate) struct TraceCase {
+    pub(crate) trace_id: String,
+    /// Duration of the `supergraph` span
+    pub(crate) duration: Duration,
+    pub(crate) query: String,
+    pub(crate) operation_name: Option<String>,
+    pub(crate) fetches: Vec<Fetch>,
+}
+
+/// A subgraph fetch recorded in a trace
+#[derive(Debug, PartialEq)]
+pub(crate) struct Fetch {
+    pub(crate) subgraph: String,
+    /// Missing with the spec compliant span mode
+    pub(crate) document: Option<String>,
+    pub(crate) latency: Duration,
+}
+
+impl TraceCase {
+    /// Reads the trace with the given id, or the slowest trace of the file
+    pub(crate) fn from_file(path: &Path, trace_id: Option<&str>) -> anyhow::Result<Self> {
+        let export = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read the trace from {}", path.display()))?;
+        Self::from_export(&export, trace_id)
+            .with_context(|| format!("could not reconstruct a trace from {}", path.display()))
+    }
+
+    fn from_export(export: &str, trace_id: Option<&str>) -> anyhow::Result<Self> {
+        // The collector's file exporter writes one export request per line
+        let mut traces: HashMap<String, Vec<Span>> = HashMap::new();
+        for request in serde_json::Deserializer::from_str(export).into_iter() {
+            let request: ExportTraceServiceRequest = request?;
+            for span in request
+                .resource_spans
+                .into_iter()
+                .flat_map(|resource_spans| resource_spans.scope_spans)
+                .flat_map(|scope_spans| scope_spans.spans)
+            {
+                traces.entry(span.trace_id.clone()).or_default().push(span);
+            }
+        }
+
+        let spans = match trace_id {
+            Some(trace_id) => traces
+                .remove(trace_id)
+                .ok_or_else(|| anyhow!("trace {trace_id} not found"))?,
+            None => traces
+                .into_values()
+                .filter(|spans| supergraph_span(spans).is_some())
+                .max_by_key(|spans| supergraph_span(spans).map(Span::duration))
+                .ok_or_else(|| anyhow!("no trace with a `{SUPERGRAPH_SPAN_NAME}` span"))?,
+        };
+        Self::from_spans(&spans)
+    }
+
+    fn from_spans(spans: &[Span]) -> anyhow::Result<Self> {
+        let supergraph = supergraph_span(spans)
+            .ok_or_else(|| anyhow!("the trace has no `{SUPERGRAPH_SPAN_NAME}` span"))?;
+        let query = supergraph.attribute(DOCUMENT).ok_or_else(|| {
+            anyhow!(
+                "the `{SUPERGRAPH_SPAN_NAME}` span has no `{DOCUMENT}` attribute, \
+                it is only recorded with `telemetry.instrumentation.spans.mode: deprecated`"
+            )
+        })?;
+
+        let mut fetches: Vec<(u64, Fetch)> = spans
+            .iter()
+            .filter(|span| span.name == SUBGRAPH_SPAN_NAME)
+            .filter_map(|span| {
+                // The HTTP request to the subgraph does not include the time spent in plugins
+                let request_span = spans.iter().find(|child| {
+                    child.name == SUBGRAPH_REQUEST_SPAN_NAME && child.parent_span_id == span.span_id
+                });
+                let subgraph = span
+                    .attribute(SUBGRAPH_NAME)
+                    .or_else(|| request_span.and_then(|child| child.attribute(SUBGRAPH_NAME)))?;
+                Some((
+                    span.start_time_unix_nano,
+                    Fetch {
+                        subgraph: subgraph.to_string(),
+                        document: span.attribute(DOCUMENT).map(str::to_string),
+                        latency: request_span.unwrap_or(span).duration(),
+                    },
+                ))
+            })
+            .collect();
+        fetches.sort_by_key(|(start, _)| *start);
+
+        Ok(TraceCase {
+            trace_id: supergraph.trace_id.clone(),
+            duration: supergraph.duration(),
+            query: query.to_string(),
+            operation_name: supergraph
+                .attribute(OPERATION_NAME)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string),
+            fetches: fetches.into_iter().map(|(_, fetch)| fetch).collect(),
+        })
+    }
+}
+
+impl fmt::Display for TraceCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "trace {}: {} took {:.2?}",
+            self.trace_id,
+            self.operation_name
+                .as_deref()
+                .unwrap_or("anonymous operation"),
+            self.duration
+        )?;
+        for fetch in &self.fetches {
+            writeln!(f, "  {:<24} {:.2?}", fetch.subgraph, fetch.latency)?;
+        }
+        Ok(())
+    }
+}
+
+fn supergraph_span(spans: &[Span]) -> Option<&Span> {
+    spans.iter().find(|span| span.name == SUPERGRAPH_SPAN_NAME)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(
+        trace_id: &str,
+        span_id: &str,
+        parent_span_id: &str,
+        name: &str,
+        start_ms: u64,
+        end_ms: u64,
+        attributes: &[(&str, &str)],
+    ) -> serde_json::Value {
+        serde_json::json!({
+            "traceId": trace_id,
+            "spanId": span_id,
+            "parentSpanId": parent_span_id,
+            "name": name,
+            "kind": 1,
+            "startTimeUnixNano": (start_ms * 1_000_000).to_string(),
+            "endTimeUnixNano": end_ms * 1_000_000,
+            "attributes": attributes.iter().map(|(key, value)| serde_json::json!({
+                "key": key,
+                "value": { "stringValue": value }
+            })).collect::<Vec<_>>()
+        })
+    }
+
+    fn export(spans: Vec<serde_json::Value>) -> String {
+        serde_json::json!({
+            "resourceSpans": [{
+                "resource": { "attributes": [] },
+                "scopeSpans": [{ "scope": { "name": "apollo-router" }, "spans": spans }]
+            }]
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn it_reconstructs_the_slowest_trace() {
+        let fast = export(vec![span(
+            "fast",
+            "1",
+            "",
+            "supergraph",
+            0,
+            10,
+            &[(DOCUMENT, "{ me { id } }")],
+        )]);
+        let slow = export(vec![
+            span(
+                "slow",
+                "1",
+                "",
+                "supergraph",
+                0,
+                500,
+                &[(DOCUMENT, "query Me { me { id } }"), (OPERATION_NAME, "Me")],
+            ),
+            span(
+                "slow",
+                "3",
+                "1",
+                "subgraph",
+                200,
+                450,
+                &[
+                    (SUBGRAPH_NAME, "reviews"),
+                    (DOCUMENT, "{ _entities { ... } }"),
+                ],
+            ),
+            span("slow", "4", "3", "subgraph_request", 210, 440, &[]),
+            span(
+                "slow",
+                "2",
+                "1",
+                "subgraph",
+                10,
+                190,
+                &[(SUBGRAPH_NAME, "accounts"), (DOCUMENT, "{ me { id } }")],
+            ),
+        ]);
+        // One export request per line
+        let case = TraceCase::from_export(&format!("{fast}\n{slow}\n"), None).unwrap();
+
+        assert_eq!(
+            case,
+            TraceCase {
+                trace_id: "slow".to_string(),
+                duration: Duration::from_millis(500),
+                query: "query Me { me { id } }".to_string(),
+                operation_name: Some("Me".to_string()),
+                fetches: vec![
+                    Fetch {
+                        subgraph: "accounts".to_string(),
+                        document: Some("{ me { id } }".to_string()),
+                        latency: Duration::from_millis(180),
+                    },
+                    Fetch {
+                        subgraph: "reviews".to_string(),
+                        document: Some("{ _entities { ... } }".to_string()),
+                        latency: Duration::from_millis(230),
+                    },
+                ],
+            }
+        );
+
+        let case = TraceCase::from_export(&format!("{fast}\n{slow}\n"), Some("fast")).unwrap();
+        assert_eq!(case.duration, Duration::from_millis(10));
+        assert!(case.fetches.is_empty());
+    }
+
+    #[test]
+    fn it_requires_the_operation() {
+        let export = export(vec![span("trace", "1", "", "supergraph", 0, 10, &[])]);
+        let error = TraceCase::from_export(&export, None).unwrap_err();
+        assert!(error.to_string().contains("graphql.document"));
+    }
+}
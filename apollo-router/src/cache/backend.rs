@@ -0,0 +1,393 @@
+//! Distributed cache backends, shared by the router instances.
+//!
+//! The APQ, query plan and entity caches store their entries in Redis, or in a backend registered
+//! with [`register_cache_backend`] such as Memcached, DynamoDB or an in-house key-value store.
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tower::BoxError;
+
+use super::redis::RedisCacheStorage;
+use super::redis::RedisKey;
+use super::redis::RedisValue;
+use super::KeyType;
+use super::ValueType;
+use crate::configuration::CacheBackendConfig;
+use crate::configuration::CacheCompression;
+use crate::configuration::CacheWrite;
+use crate::configuration::RedisCache;
+
+/// The first bytes of a zstd frame, which JSON values never start with
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+static CACHE_BACKENDS: Lazy<Mutex<HashMap<String, Arc<dyn CacheBackend>>>> =
+    Lazy::new(Default::default);
+
+/// Storage of a distributed cache.
+///
+/// Entries are serialized by the router, and their keys already contain everything that
+/// distinguishes them, so a backend only stores bytes. Errors are logged by the router, which
+/// then behaves as if the entries were missing.
+#[async_trait::async_trait]
+pub trait CacheBackend: Send + Sync + 'static {
+    /// Get an entry, or `None` if it is missing or expired.
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, BoxError>;
+
+    /// Get several entries, in the order of their keys.
+    async fn get_multiple(&self, keys: &[String]) -> Result<Vec<Option<Bytes>>, BoxError> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.get(key).await?);
+        }
+        Ok(values)
+    }
+
+    /// Insert an entry, expiring after its TTL if there is one.
+    async fn insert(
+        &self,
+        key: String,
+        value: Bytes,
+        ttl: Option<Duration>,
+    ) -> Result<(), BoxError>;
+
+    /// Insert several entries, expiring after their TTL if there is one.
+    async fn insert_multiple(
+        &self,
+        data: Vec<(String, Bytes)>,
+        ttl: Option<Duration>,
+    ) -> Result<(), BoxError> {
+        for (key, value) in data {
+            self.insert(key, value, ttl).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Register a cache backend under a name, so that the caches configured with this `backend` name
+/// use it instead of Redis.
+///
+/// Backends must be registered before the router starts, or before the configuration using them is
+/// reloaded.
+pub fn register_cache_backend(name: impl Into<String>, backend: Arc<dyn CacheBackend>) {
+    CACHE_BACKENDS.lock().insert(name.into(), backend);
+}
+
+/// A distributed cache, with the serialization of its keys and values
+#[derive(Clone)]
+pub(crate) struct DistributedCache {
+    name: Arc<str>,
+    backend: Arc<dyn CacheBackend>,
+    pub(crate) ttl: Option<Duration>,
+    write: CacheWrite,
+    compression: CacheCompression,
+}
+
+impl DistributedCache {
+    /// The distributed cache of a configuration, if it configures one
+    pub(crate) async fn new(
+        redis: Option<RedisCache>,
+        backend: Option<CacheBackendConfig>,
+        write: CacheWrite,
+        compression: CacheCompression,
+    ) -> Result<Option<Self>, BoxError> {
+        match (redis, backend) {
+            (Some(_), Some(_)) => {
+                Err("a cache can be configured with either redis or backend, not both".into())
+            }
+            (Some(redis), None) => {
+                let ttl = redis.ttl;
+                Ok(Some(Self {
+                    name: "redis".into(),
+                    backend: Arc::new(RedisCacheStorage::new(redis).await?),
+                    ttl,
+                    write,
+                    compression,
+                }))
+            }
+            (None, Some(config)) => {
+                let backend = CACHE_BACKENDS
+                    .lock()
+                    .get(&config.name)
+                    .cloned()
+                    .ok_or_else(|| format!("cache backend '{}' is not registered", config.name))?;
+                Ok(Some(Self {
+                    name: config.name.into(),
+                    backend,
+                    ttl: config.ttl,
+                    write,
+                    compression,
+                }))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// The name of the backend, for metrics
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn ttl(&self) -> Option<Duration> {
+        self.ttl
+    }
+
+    /// Gets an entry, returning the errors of the backend instead of treating them as misses
+    pub(crate) async fn try_get<K: KeyType, V: ValueType>(
+        &self,
+        key: RedisKey<K>,
+    ) -> Result<Option<RedisValue<V>>, BoxError> {
+        Ok(self
+            .backend
+            .get(&key.to_string())
+            .await?
+            .and_then(|value| self.deserialize(&value)))
+    }
+
+    pub(crate) async fn get_multiple<K: KeyType, V: ValueType>(
+        &self,
+        keys: Vec<RedisKey<K>>,
+    ) -> Option<Vec<Option<RedisValue<V>>>> {
+        tracing::trace!("getting multiple values from {}: {:?}", self.name, keys);
+        let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        let res = match self.backend.get_multiple(&keys).await {
+            Ok(values) => Some(
+                values
+                    .into_iter()
+                    .map(|value| value.and_then(|value| self.deserialize(&value)))
+                    .collect(),
+            ),
+            Err(e) => {
+                tracing::error!("get error: {}", e);
+                None
+            }
+        };
+        tracing::trace!("result for '{:?}': {:?}", keys, res);
+
+        res
+    }
+
+    pub(crate) async fn insert<K: KeyType, V: ValueType>(
+        &self,
+        key: RedisKey<K>,
+        value: RedisValue<V>,
+        ttl: Option<Duration>,
+    ) {
+        tracing::trace!("inserting into {}: {:?}, {:?}", self.name, key, value);
+        let Some(value) = self.serialize(&value) else {
+            return;
+        };
+        let key = key.to_string();
+        let backend = self.backend.clone();
+        let ttl = ttl.or(self.ttl);
+        self.write(async move {
+            let r = backend.insert(key, value, ttl).await;
+            tracing::trace!("insert result {:?}", r);
+        })
+        .await
+    }
+
+    pub(crate) async fn insert_multiple<K: KeyType, V: ValueType>(
+        &self,
+        data: &[(RedisKey<K>, RedisValue<V>)],
+        ttl: Option<Duration>,
+    ) {
+        tracing::trace!("inserting into {}: {:#?}", self.name, data);
+        let data = data
+            .iter()
+            .filter_map(|(key, value)| Some((key.to_string(), self.serialize(value)?)))
+            .collect();
+        let backend = self.backend.clone();
+        let ttl = ttl.or(self.ttl);
+        self.write(async move {
+            let r = backend.insert_multiple(data, ttl).await;
+            tracing::trace!("insert result {:?}", r);
+        })
+        .await
+    }
+
+    /// Waits for a write, or lets it run in the background with write-behind
+    async fn write(&self, write: impl Future<Output = ()> + Send + 'static) {
+        match self.write {
+            CacheWrite::Through => write.await,
+            CacheWrite::Behind => {
+                tokio::spawn(write);
+            }
+        }
+    }
+
+    fn serialize<V: ValueType>(&self, value: &RedisValue<V>) -> Option<Bytes> {
+        let value = value
+            .to_bytes()
+            .map_err(|e| {
+                tracing::error!("couldn't serialize value for the distributed cache {}. This is a bug in the router, please file an issue: https://github.com/apollographql/router/issues/new", e);
+            })
+            .ok()?;
+        match self.compression {
+            CacheCompression::None => Some(value),
+            CacheCompression::Zstd => zstd::bulk::compress(&value, 0)
+                .map(Bytes::from)
+                .map_err(|e| {
+                    tracing::error!("couldn't compress value for {}: {}", self.name, e);
+                })
+                .ok(),
+        }
+    }
+
+    /// Compressed entries are recognized whatever the configuration, so that the compression
+    /// can be changed while entries written with the previous configuration are still cached
+    fn deserialize<V: ValueType>(&self, value: &[u8]) -> Option<RedisValue<V>> {
+        let decompressed;
+        let value = if value.starts_with(&ZSTD_MAGIC) {
+            decompressed = zstd::stream::decode_all(value)
+                .map_err(|e| {
+                    tracing::error!("can't decompress value from {}: {}", self.name, e);
+                })
+                .ok()?;
+            decompressed.as_slice()
+        } else {
+            value
+        };
+        RedisValue::from_bytes(value)
+            .map_err(|e| {
+                tracing::error!("can't deserialize from JSON from {}: {}", self.name, e);
+            })
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct InMemory(Mutex<HashMap<String, Bytes>>);
+
+    #[async_trait::async_trait]
+    impl CacheBackend for InMemory {
+        async fn get(&self, key: &str) -> Result<Option<Bytes>, BoxError> {
+            Ok(self.0.lock().get(key).cloned())
+        }
+
+        async fn insert(
+            &self,
+            key: String,
+            value: Bytes,
+            _ttl: Option<Duration>,
+        ) -> Result<(), BoxError> {
+            self.0.lock().insert(key, value);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn it_uses_registered_backends() {
+        assert!(DistributedCache::new(
+            None,
+            Some(CacheBackendConfig {
+                name: "test_unregistered".to_string(),
+                ttl: None,
+            }),
+            CacheWrite::Through,
+            CacheCompression::None,
+        )
+        .await
+        .is_err());
+
+        let backend = Arc::new(InMemory::default());
+        register_cache_backend("test_in_memory", backend.clone());
+        let cache = DistributedCache::new(
+            None,
+            Some(CacheBackendConfig {
+                name: "test_in_memory".to_string(),
+                ttl: None,
+            }),
+            CacheWrite::Through,
+            CacheCompression::None,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(cache.name(), "test_in_memory");
+
+        cache
+            .insert(RedisKey("a"), RedisValue(vec![1, 2]), None)
+            .await;
+        cache
+            .insert_multiple(&[(RedisKey("b"), RedisValue(vec![3]))], None)
+            .await;
+        assert_eq!(
+            backend.0.lock().get("a").cloned(),
+            Some(Bytes::from_static(b"[1,2]"))
+        );
+        assert_eq!(
+            cache
+                .try_get::<_, Vec<u8>>(RedisKey("a"))
+                .await
+                .unwrap()
+                .unwrap()
+                .0,
+            vec![1, 2]
+        );
+        let values = cache
+            .get_multiple::<_, Vec<u8>>(vec![RedisKey("a"), RedisKey("b"), RedisKey("c")])
+            .await
+            .unwrap();
+        assert_eq!(
+            values
+                .into_iter()
+                .map(|value| value.map(|value| value.0))
+                .collect::<Vec<_>>(),
+            vec![Some(vec![1, 2]), Some(vec![3]), None]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_compresses_entries() {
+        let backend = Arc::new(InMemory::default());
+        register_cache_backend("test_compression", backend.clone());
+        let cache = |compression| {
+            DistributedCache::new(
+                None,
+                Some(CacheBackendConfig {
+                    name: "test_compression".to_string(),
+                    ttl: None,
+                }),
+                CacheWrite::Through,
+                compression,
+            )
+        };
+        let compressed = cache(CacheCompression::Zstd).await.unwrap().unwrap();
+        let uncompressed = cache(CacheCompression::None).await.unwrap().unwrap();
+
+        let value = "a".repeat(1000);
+        compressed
+            .insert(RedisKey("a"), RedisValue(value.clone()), None)
+            .await;
+        let stored = backend.0.lock().get("a").cloned().unwrap();
+        assert!(stored.starts_with(&ZSTD_MAGIC));
+        assert!(stored.len() < value.len());
+
+        // entries are read whatever the compression they were written with
+        uncompressed
+            .insert(RedisKey("b"), RedisValue(value.clone()), None)
+            .await;
+        for cache in [&compressed, &uncompressed] {
+            for key in ["a", "b"] {
+                assert_eq!(
+                    cache
+                        .try_get::<_, String>(RedisKey(key))
+                        .await
+                        .unwrap()
+                        .unwrap()
+                        .0,
+                    value
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Extra dimensions of the keys of the APQ and query plan caches, so that the entries of requests
+//! differing by them, like their tenant or a feature flag, do not collide.
+use http::HeaderMap;
+use serde_json_bytes::json;
+use serde_json_bytes::Map;
+use serde_json_bytes::Value;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::configuration::CacheKeyConfig;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::Context;
+
+/// A context entry that Rhai scripts and coprocessors set to add any value to the cache keys
+pub(crate) const CONTEXT_CACHE_KEY: &str = "apollo_cache::key";
+
+/// The extra dimensions of the cache keys of a request, hashed, or `None` if it has none
+pub(crate) fn cache_key_extension(
+    config: &CacheKeyConfig,
+    headers: &HeaderMap,
+    context: &Context,
+) -> Option<String> {
+    let mut dimensions = Map::new();
+
+    if !config.request_headers.is_empty() {
+        let headers: Map<_, _> = config
+            .request_headers
+            .iter()
+            .map(|name| {
+                let values: Vec<Value> = headers
+                    .get_all(name.as_str())
+                    .iter()
+                    .map(|value| {
+                        String::from_utf8_lossy(value.as_bytes())
+                            .into_owned()
+                            .into()
+                    })
+                    .collect();
+                (name.to_ascii_lowercase().into(), Value::Array(values))
+            })
+            .collect();
+        dimensions.insert("headers", Value::Object(headers));
+    }
+
+    if config.scopes {
+        let mut scopes: Vec<&str> = Vec::new();
+        let claims = context.get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS);
+        if let Some(scope) = claims
+            .as_ref()
+            .and_then(|claims| claims.as_object())
+            .and_then(|claims| claims.get("scope"))
+            .and_then(|scope| scope.as_str())
+        {
+            scopes = scope.split(' ').filter(|s| !s.is_empty()).collect();
+            scopes.sort_unstable();
+            scopes.dedup();
+        }
+        dimensions.insert("scopes", json!(scopes));
+    }
+
+    if let Some(value) = context.get_json_value(CONTEXT_CACHE_KEY) {
+        dimensions.insert("context", value);
+    }
+
+    if dimensions.is_empty() {
+        return None;
+    }
+    let mut digest = Sha256::new();
+    digest.update(serde_json::to_vec(&dimensions).expect("serialization should not fail"));
+    Some(hex::encode(digest.finalize()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn extension(
+        config: &CacheKeyConfig,
+        headers: &[(&str, &str)],
+        context: &Context,
+    ) -> Option<String> {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(
+                http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        cache_key_extension(config, &map, context)
+    }
+
+    #[test]
+    fn it_extends_keys_with_headers_scopes_and_context() {
+        let context = Context::new();
+        assert_eq!(
+            extension(&CacheKeyConfig::default(), &[("x-flag", "a")], &context),
+            None
+        );
+
+        let config = CacheKeyConfig {
+            request_headers: vec!["X-Flag".to_string()],
+            scopes: false,
+        };
+        let flag_a = extension(&config, &[("x-flag", "a"), ("x-other", "1")], &context);
+        assert!(flag_a.is_some());
+        assert_eq!(flag_a, extension(&config, &[("x-flag", "a")], &context));
+        assert_ne!(flag_a, extension(&config, &[("x-flag", "b")], &context));
+
+        context
+            .insert(CONTEXT_CACHE_KEY, "tenant1".to_string())
+            .unwrap();
+        assert_ne!(flag_a, extension(&config, &[("x-flag", "a")], &context));
+
+        let config = CacheKeyConfig {
+            request_headers: vec![],
+            scopes: true,
+        };
+        let scopes = |scope: &str| {
+            let context = Context::new();
+            context
+                .insert(APOLLO_AUTHENTICATION_JWT_CLAIMS, json!({ "scope": scope }))
+                .unwrap();
+            extension(&config, &[], &context)
+        };
+        assert_eq!(scopes("read write"), scopes("write  read"));
+        assert_ne!(scopes("read write"), scopes("read"));
+    }
+}
@@ -9,9 +9,12 @@ use tokio::sync::Mutex;
 use self::storage::CacheStorage;
 use self::storage::KeyType;
 use self::storage::ValueType;
-use crate::configuration::RedisCache;
+use crate::configuration::Cache;
 
+pub(crate) mod backend;
+pub(crate) mod key;
 pub(crate) mod redis;
+pub(crate) mod registry;
 pub(crate) mod storage;
 
 type WaitMap<K, V> = Arc<Mutex<HashMap<K, broadcast::Sender<V>>>>;
@@ -32,24 +35,35 @@ where
     K: KeyType + 'static,
     V: ValueType + 'static,
 {
+    #[cfg(test)]
     pub(crate) async fn with_capacity(
         capacity: NonZeroUsize,
-        redis: Option<RedisCache>,
+        redis: Option<crate::configuration::RedisCache>,
+        backend: Option<crate::configuration::CacheBackendConfig>,
         caller: &str,
     ) -> Self {
+        Self::from_configuration(
+            &Cache {
+                in_memory: crate::configuration::InMemoryCache {
+                    limit: capacity,
+                    ttl: None,
+                },
+                redis,
+                backend,
+                ..Default::default()
+            },
+            caller,
+        )
+        .await
+    }
+
+    pub(crate) async fn from_configuration(config: &Cache, caller: &str) -> Self {
         Self {
             wait_map: Arc::new(Mutex::new(HashMap::new())),
-            storage: CacheStorage::new(capacity, redis, caller).await,
+            storage: CacheStorage::new(config.clone(), caller).await,
         }
     }
 
-    pub(crate) async fn from_configuration(
-        config: &crate::configuration::Cache,
-        caller: &str,
-    ) -> Self {
-        Self::with_capacity(config.in_memory.limit, config.redis.clone(), caller).await
-    }
-
     pub(crate) async fn get(&self, key: &K) -> Entry<K, V> {
         // waiting on a value from the cache is a potentially long(millisecond scale) task that
         // can involve a network call to an external database. To reduce the waiting time, we
@@ -156,6 +170,11 @@ where
         matches!(self.inner, EntryInner::First { .. })
     }
 
+    /// The value is being computed by another task, and will be received once it's ready
+    pub(crate) fn is_pending(&self) -> bool {
+        matches!(self.inner, EntryInner::Receiver { .. })
+    }
+
     pub(crate) async fn get(self) -> Result<V, EntryError> {
         match self.inner {
             // there was already a value in cache
@@ -207,7 +226,8 @@ mod tests {
     async fn example_cache_usage() {
         let k = "key".to_string();
         let cache =
-            DeduplicatingCache::with_capacity(NonZeroUsize::new(1).unwrap(), None, "test").await;
+            DeduplicatingCache::with_capacity(NonZeroUsize::new(1).unwrap(), None, None, "test")
+                .await;
 
         let entry = cache.get(&k).await;
 
@@ -224,7 +244,8 @@ mod tests {
     #[test(tokio::test)]
     async fn it_should_enforce_cache_limits() {
         let cache: DeduplicatingCache<usize, usize> =
-            DeduplicatingCache::with_capacity(NonZeroUsize::new(13).unwrap(), None, "test").await;
+            DeduplicatingCache::with_capacity(NonZeroUsize::new(13).unwrap(), None, None, "test")
+                .await;
 
         for i in 0..14 {
             let entry = cache.get(&i).await;
@@ -247,7 +268,8 @@ mod tests {
         mock.expect_retrieve().times(1).return_const(1usize);
 
         let cache: DeduplicatingCache<usize, usize> =
-            DeduplicatingCache::with_capacity(NonZeroUsize::new(10).unwrap(), None, "test").await;
+            DeduplicatingCache::with_capacity(NonZeroUsize::new(10).unwrap(), None, None, "test")
+                .await;
 
         // Let's trigger 100 concurrent gets of the same value and ensure only
         // one delegated retrieve is made
@@ -2,6 +2,7 @@ use std::fmt;
 use std::sync::Arc;
 use std::time::Duration;
 
+use bytes::Bytes;
 use fred::interfaces::EventInterface;
 use fred::prelude::ClientLike;
 use fred::prelude::KeysInterface;
@@ -9,7 +10,6 @@ use fred::prelude::RedisClient;
 use fred::prelude::RedisError;
 use fred::prelude::RedisErrorKind;
 use fred::types::Expiration;
-use fred::types::FromRedis;
 use fred::types::PerformanceConfig;
 use fred::types::ReconnectPolicy;
 use fred::types::RedisConfig;
@@ -18,6 +18,7 @@ use fred::types::TlsHostMapping;
 use tower::BoxError;
 use url::Url;
 
+use super::backend::CacheBackend;
 use super::KeyType;
 use super::ValueType;
 use crate::configuration::RedisCache;
@@ -45,7 +46,6 @@ where
 #[derive(Clone)]
 pub(crate) struct RedisCacheStorage {
     inner: Arc<RedisClient>,
-    pub(crate) ttl: Option<Duration>,
 }
 
 fn get_type_of<T>(_: &T) -> &'static str {
@@ -61,15 +61,6 @@ where
     }
 }
 
-impl<K> From<RedisKey<K>> for fred::types::RedisKey
-where
-    K: KeyType,
-{
-    fn from(val: RedisKey<K>) -> Self {
-        val.to_string().into()
-    }
-}
-
 impl<V> fmt::Display for RedisValue<V>
 where
     V: ValueType,
@@ -79,55 +70,16 @@ where
     }
 }
 
-impl<V> FromRedis for RedisValue<V>
+impl<V> RedisValue<V>
 where
     V: ValueType,
 {
-    fn from_value(value: fred::types::RedisValue) -> Result<Self, RedisError> {
-        match value {
-            fred::types::RedisValue::Bytes(data) => {
-                serde_json::from_slice(&data).map(RedisValue).map_err(|e| {
-                    RedisError::new(
-                        RedisErrorKind::Parse,
-                        format!("can't deserialize from JSON: {e}"),
-                    )
-                })
-            }
-            fred::types::RedisValue::String(s) => {
-                serde_json::from_str(&s).map(RedisValue).map_err(|e| {
-                    RedisError::new(
-                        RedisErrorKind::Parse,
-                        format!("can't deserialize from JSON: {e}"),
-                    )
-                })
-            }
-            fred::types::RedisValue::Null => {
-                Err(RedisError::new(RedisErrorKind::NotFound, "not found"))
-            }
-            _res => Err(RedisError::new(
-                RedisErrorKind::Parse,
-                "the data is the wrong type",
-            )),
-        }
+    pub(crate) fn to_bytes(&self) -> Result<Bytes, serde_json::Error> {
+        serde_json::to_vec(&self.0).map(Bytes::from)
     }
-}
 
-impl<V> TryInto<fred::types::RedisValue> for RedisValue<V>
-where
-    V: ValueType,
-{
-    type Error = RedisError;
-
-    fn try_into(self) -> Result<fred::types::RedisValue, Self::Error> {
-        let v = serde_json::to_vec(&self.0).map_err(|e| {
-            tracing::error!("couldn't serialize value to redis {}. This is a bug in the router, please file an issue: https://github.com/apollographql/router/issues/new", e);
-            RedisError::new(
-                RedisErrorKind::Parse,
-                format!("couldn't serialize value to redis {}", e),
-            )
-        })?;
-
-        Ok(fred::types::RedisValue::Bytes(v.into()))
+    pub(crate) fn from_bytes(data: &[u8]) -> Result<Self, serde_json::Error> {
+        serde_json::from_slice(data).map(RedisValue)
     }
 }
 
@@ -184,14 +136,9 @@ impl RedisCacheStorage {
         tracing::trace!("redis connection established");
         Ok(Self {
             inner: Arc::new(client),
-            ttl: config.ttl,
         })
     }
 
-    pub(crate) fn ttl(&self) -> Option<Duration> {
-        self.ttl
-    }
-
     fn preprocess_urls(urls: Vec<Url>) -> Result<Url, RedisError> {
         let url_len = urls.len();
         let mut urls_iter = urls.into_iter();
@@ -279,118 +226,55 @@ impl RedisCacheStorage {
             }
         }
     }
+}
 
-    #[allow(dead_code)]
-    pub(crate) fn set_ttl(&mut self, ttl: Option<Duration>) {
-        self.ttl = ttl;
-    }
-
-    pub(crate) async fn get<K: KeyType, V: ValueType>(
-        &self,
-        key: RedisKey<K>,
-    ) -> Option<RedisValue<V>> {
-        self.inner
-            .get::<RedisValue<V>, _>(key.to_string())
-            .await
-            .map_err(|e| {
-                if !e.is_not_found() {
-                    tracing::error!("get error: {}", e);
-                }
-                e
-            })
-            .ok()
+#[async_trait::async_trait]
+impl CacheBackend for RedisCacheStorage {
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, BoxError> {
+        Ok(self.inner.get::<Option<Bytes>, _>(key).await?)
     }
 
-    pub(crate) async fn get_multiple<K: KeyType, V: ValueType>(
-        &self,
-        keys: Vec<RedisKey<K>>,
-    ) -> Option<Vec<Option<RedisValue<V>>>> {
-        tracing::trace!("getting multiple values from redis: {:?}", keys);
-
-        let res = if keys.len() == 1 {
-            let res = self
-                .inner
-                .get::<RedisValue<V>, _>(keys.first().unwrap().to_string())
-                .await
-                .map_err(|e| {
-                    if !e.is_not_found() {
-                        tracing::error!("get error: {}", e);
-                    }
-                    e
-                })
-                .ok();
-
-            Some(vec![res])
+    async fn get_multiple(&self, keys: &[String]) -> Result<Vec<Option<Bytes>>, BoxError> {
+        if keys.len() == 1 {
+            Ok(vec![self.get(&keys[0]).await?])
         } else {
-            self.inner
-                .mget(
-                    keys.clone()
-                        .into_iter()
-                        .map(|k| k.to_string())
-                        .collect::<Vec<_>>(),
-                )
-                .await
-                .map_err(|e| {
-                    if !e.is_not_found() {
-                        tracing::error!("get error: {}", e);
-                    }
-                    e
-                })
-                .ok()
-        };
-        tracing::trace!("result for '{:?}': {:?}", keys, res);
-
-        res
+            Ok(self.inner.mget(keys.to_vec()).await?)
+        }
     }
 
-    pub(crate) async fn insert<K: KeyType, V: ValueType>(
+    async fn insert(
         &self,
-        key: RedisKey<K>,
-        value: RedisValue<V>,
+        key: String,
+        value: Bytes,
         ttl: Option<Duration>,
-    ) {
-        tracing::trace!("inserting into redis: {:?}, {:?}", key, value);
-        let expiration = ttl
-            .as_ref()
-            .or(self.ttl.as_ref())
-            .map(|ttl| Expiration::EX(ttl.as_secs() as i64));
-
-        let r = self
+    ) -> Result<(), BoxError> {
+        let expiration = ttl.map(|ttl| Expiration::EX(ttl.as_secs() as i64));
+        Ok(self
             .inner
             .set::<(), _, _>(key, value, expiration, None, false)
-            .await;
-        tracing::trace!("insert result {:?}", r);
+            .await?)
     }
 
-    pub(crate) async fn insert_multiple<K: KeyType, V: ValueType>(
+    async fn insert_multiple(
         &self,
-        data: &[(RedisKey<K>, RedisValue<V>)],
+        data: Vec<(String, Bytes)>,
         ttl: Option<Duration>,
-    ) {
-        tracing::trace!("inserting into redis: {:#?}", data);
-
-        let r = match ttl.as_ref().or(self.ttl.as_ref()) {
-            None => self.inner.mset(data.to_owned()).await,
+    ) -> Result<(), BoxError> {
+        match ttl {
+            None => Ok(self.inner.mset(data).await?),
             Some(ttl) => {
                 let expiration = Some(Expiration::EX(ttl.as_secs() as i64));
                 let pipeline = self.inner.pipeline();
 
                 for (key, value) in data {
                     let _ = pipeline
-                        .set::<(), _, _>(
-                            key.clone(),
-                            value.clone(),
-                            expiration.clone(),
-                            None,
-                            false,
-                        )
+                        .set::<(), _, _>(key, value, expiration.clone(), None, false)
                         .await;
                 }
 
-                pipeline.last().await
+                Ok(pipeline.last().await?)
             }
-        };
-        tracing::trace!("insert result {:?}", r);
+        }
     }
 }
 
@@ -412,9 +296,7 @@ mod test {
             time: std::time::UNIX_EPOCH - std::time::Duration::new(1, 0),
         });
 
-        let as_value: Result<fred::types::RedisValue, _> = invalid_json_payload.try_into();
-
-        assert!(as_value.is_err());
+        assert!(invalid_json_payload.to_bytes().is_err());
     }
 
     #[test]
@@ -0,0 +1,59 @@
+//! The in-memory caches of the router, listed so that they can be inspected and cleared at
+//! runtime.
+use std::sync::Arc;
+use std::sync::Weak;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+static IN_MEMORY_CACHES: Lazy<Mutex<Vec<Weak<dyn InMemoryCache>>>> = Lazy::new(Default::default);
+
+/// Statistics of an in-memory cache, since it was created
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct CacheStats {
+    pub(crate) name: String,
+    pub(crate) size: usize,
+    pub(crate) capacity: usize,
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) evictions: u64,
+}
+
+/// A key of an in-memory cache, with the number of times it was found
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct KeyHits {
+    pub(crate) key: String,
+    pub(crate) hits: u64,
+}
+
+/// An in-memory cache that can be inspected and cleared at runtime
+#[async_trait::async_trait]
+pub(crate) trait InMemoryCache: Send + Sync {
+    /// The name of the cache, such as `query planner`, `APQ`, `introspection` or `document`
+    fn name(&self) -> &str;
+
+    async fn stats(&self) -> CacheStats;
+
+    /// The keys found the most often, in decreasing order of hits
+    async fn hottest_keys(&self, limit: usize) -> Vec<KeyHits>;
+
+    async fn clear(&self);
+}
+
+/// Lists an in-memory cache until it is dropped
+pub(crate) fn register(cache: Weak<dyn InMemoryCache>) {
+    let mut caches = IN_MEMORY_CACHES.lock();
+    caches.retain(|cache| cache.strong_count() > 0);
+    caches.push(cache);
+}
+
+/// The in-memory caches in use, all of them or only the ones with that name
+pub(crate) fn in_memory_caches(name: Option<&str>) -> Vec<Arc<dyn InMemoryCache>> {
+    IN_MEMORY_CACHES
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .filter(|cache| name.map_or(true, |name| cache.name() == name))
+        .collect()
+}
@@ -1,8 +1,9 @@
-use std::fmt::Display;
 use std::fmt::{self};
 use std::hash::Hash;
-use std::num::NonZeroUsize;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use lru::LruCache;
 use serde::de::DeserializeOwned;
@@ -10,8 +11,18 @@ use serde::Serialize;
 use tokio::sync::Mutex;
 use tokio::time::Instant;
 
+use super::backend::DistributedCache;
 use super::redis::*;
-use crate::configuration::RedisCache;
+use super::registry;
+use super::registry::CacheStats;
+use super::registry::InMemoryCache;
+use super::registry::KeyHits;
+use super::DEFAULT_CACHE_CAPACITY;
+use crate::configuration;
+use crate::configuration::Cache;
+use crate::configuration::NegativeCaching;
+
+const MEMORY_STORAGE: &str = "memory";
 
 pub(crate) trait KeyType:
     Clone + fmt::Debug + fmt::Display + Hash + Eq + Send + Sync
@@ -40,97 +51,293 @@ where
     // It has the functions it needs already
 }
 
-// placeholder storage module
-//
-// this will be replaced by the multi level (in memory + redis/memcached) once we find
-// a suitable implementation.
+/// Two tier cache storage: entries are looked up in memory first, then in the distributed cache
+/// if there is one
 #[derive(Clone)]
 pub(crate) struct CacheStorage<K: KeyType, V: ValueType> {
     caller: String,
-    inner: Arc<Mutex<LruCache<K, V>>>,
-    redis: Option<RedisCacheStorage>,
+    /// The in-memory tier, which only the caches in front of a distributed cache can go without
+    inner: Option<Arc<InMemory<K, V>>>,
+    memory_ttl: Option<Duration>,
+    distributed: Option<DistributedCache>,
+    negative: Option<NegativeCache<K>>,
+    stale_while_revalidate: Option<Duration>,
+    stale_if_error: Option<Duration>,
+}
+
+/// The recent misses of the distributed cache, with their expiration
+#[derive(Clone)]
+struct NegativeCache<K: KeyType> {
+    ttl: Duration,
+    misses: Arc<Mutex<LruCache<K, Instant>>>,
+}
+
+/// The in-memory tier of a cache, with its statistics
+struct InMemory<K: KeyType, V: ValueType> {
+    caller: String,
+    entries: Mutex<LruCache<K, MemoryEntry<V>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+#[derive(Clone)]
+struct MemoryEntry<V> {
+    value: V,
+    expires_at: Option<Instant>,
+    /// Whether the entry is being refreshed from the distributed cache
+    revalidating: bool,
+    /// How many times the entry was found
+    hits: u64,
+}
+
+/// The result of a lookup in memory
+enum MemoryLookup<V> {
+    /// The entry can be used
+    Hit(V),
+    /// The entry expired, but can be used while it is refreshed from the distributed cache
+    Stale {
+        value: V,
+        revalidate: bool,
+    },
+    /// The entry expired, but can be used if the distributed cache fails
+    StaleIfError(V),
+    Miss,
 }
 
 impl<K, V> CacheStorage<K, V>
 where
-    K: KeyType,
-    V: ValueType,
+    K: KeyType + 'static,
+    V: ValueType + 'static,
 {
-    pub(crate) async fn new(
-        max_capacity: NonZeroUsize,
-        config: Option<RedisCache>,
+    pub(crate) async fn new(config: Cache, caller: &str) -> Self {
+        let distributed = match DistributedCache::new(
+            config.redis,
+            config.backend,
+            config.write,
+            config.compression,
+        )
+        .await
+        {
+            Err(e) => {
+                tracing::error!(
+                    "could not open connection to the distributed cache for {} caching: {:?}",
+                    caller,
+                    e
+                );
+                None
+            }
+            Ok(storage) => storage,
+        };
+        Self {
+            stale_while_revalidate: config.stale_while_revalidate,
+            stale_if_error: config.stale_if_error,
+            ..Self::with_tiers(
+                caller,
+                Some(config.in_memory),
+                distributed,
+                config.negative_caching,
+            )
+        }
+    }
+
+    /// A cache storage in front of a distributed cache, with an optional in-memory tier
+    pub(crate) fn with_distributed(
+        caller: &str,
+        distributed: DistributedCache,
+        in_memory: Option<configuration::InMemoryCache>,
+        negative_caching: Option<NegativeCaching>,
+    ) -> Self {
+        Self::with_tiers(caller, in_memory, Some(distributed), negative_caching)
+    }
+
+    fn with_tiers(
         caller: &str,
+        in_memory: Option<configuration::InMemoryCache>,
+        distributed: Option<DistributedCache>,
+        negative_caching: Option<NegativeCaching>,
     ) -> Self {
+        let max_capacity = in_memory
+            .as_ref()
+            .map_or(DEFAULT_CACHE_CAPACITY, |in_memory| in_memory.limit);
+        let inner = in_memory.as_ref().map(|in_memory| {
+            let inner = Arc::new(InMemory {
+                caller: caller.to_string(),
+                entries: Mutex::new(LruCache::new(in_memory.limit)),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+                evictions: AtomicU64::new(0),
+            });
+            registry::register(Arc::downgrade(&inner) as _);
+            inner
+        });
         Self {
             caller: caller.to_string(),
-            inner: Arc::new(Mutex::new(LruCache::new(max_capacity))),
-            redis: if let Some(config) = config {
-                match RedisCacheStorage::new(config).await {
-                    Err(e) => {
-                        tracing::error!(
-                            "could not open connection to Redis for {} caching: {:?}",
-                            caller,
-                            e
-                        );
-                        None
+            inner,
+            memory_ttl: in_memory.and_then(|in_memory| in_memory.ttl),
+            distributed,
+            negative: negative_caching.map(|negative_caching| NegativeCache {
+                ttl: negative_caching.ttl,
+                misses: Arc::new(Mutex::new(LruCache::new(max_capacity))),
+            }),
+            stale_while_revalidate: None,
+            stale_if_error: None,
+        }
+    }
+
+    /// The TTL of the distributed cache
+    pub(crate) fn ttl(&self) -> Option<Duration> {
+        self.distributed
+            .as_ref()
+            .and_then(|distributed| distributed.ttl())
+    }
+
+    /// Looks up an entry in memory, removing it if it expired and cannot be used anymore
+    async fn get_in_memory(&self, key: &K) -> MemoryLookup<V> {
+        let Some(inner) = &self.inner else {
+            return MemoryLookup::Miss;
+        };
+        let mut in_memory = inner.entries.lock().await;
+        let Some(entry) = in_memory.get_mut(key) else {
+            return MemoryLookup::Miss;
+        };
+        let Some(expired_for) = entry.expired_for() else {
+            entry.hits += 1;
+            return MemoryLookup::Hit(entry.value.clone());
+        };
+        // the stale entries can only be refreshed from the distributed cache
+        let within = |window: Option<Duration>| {
+            self.distributed.is_some() && window.map(|w| expired_for < w).unwrap_or(false)
+        };
+        if within(self.stale_while_revalidate) {
+            let revalidate = !entry.revalidating;
+            entry.revalidating = true;
+            entry.hits += 1;
+            MemoryLookup::Stale {
+                value: entry.value.clone(),
+                revalidate,
+            }
+        } else if within(self.stale_if_error) {
+            MemoryLookup::StaleIfError(entry.value.clone())
+        } else {
+            in_memory.pop(key);
+            MemoryLookup::Miss
+        }
+    }
+
+    /// Refreshes a stale entry from the distributed cache in the background
+    fn revalidate(&self, key: K) {
+        let storage = self.clone();
+        tokio::spawn(async move {
+            let Some(distributed) = storage.distributed.as_ref() else {
+                return;
+            };
+            match distributed.try_get::<K, V>(RedisKey(key.clone())).await {
+                Ok(Some(value)) => storage.put_in_memory(key, value.0, None).await,
+                Ok(None) => storage.remove_from_memory(&key).await,
+                Err(e) => {
+                    tracing::error!(
+                        "could not revalidate a {} cache entry: {}",
+                        storage.caller,
+                        e
+                    );
+                    // the stale entry is kept, and revalidated again on the next lookup
+                    if let Some(inner) = &storage.inner {
+                        if let Some(entry) = inner.entries.lock().await.get_mut(&key) {
+                            entry.revalidating = false;
+                        }
                     }
-                    Ok(storage) => Some(storage),
                 }
-            } else {
-                None
-            },
-        }
+            }
+        });
     }
 
     pub(crate) async fn get(&self, key: &K) -> Option<V> {
         let instant_memory = Instant::now();
-        let res = self.inner.lock().await.get(key).cloned();
+        let (res, stale_if_error) = match self.get_in_memory(key).await {
+            MemoryLookup::Hit(value) => (Some(value), None),
+            MemoryLookup::Stale { value, revalidate } => {
+                if revalidate {
+                    self.revalidate(key.clone());
+                }
+                (Some(value), None)
+            }
+            MemoryLookup::StaleIfError(value) => (None, Some(value)),
+            MemoryLookup::Miss => (None, None),
+        };
 
         match res {
             Some(v) => {
+                if let Some(inner) = &self.inner {
+                    inner.hits.fetch_add(1, Ordering::Relaxed);
+                }
                 tracing::info!(
                     monotonic_counter.apollo_router_cache_hit_count = 1u64,
                     kind = %self.caller,
-                    storage = &tracing::field::display(CacheStorageName::Memory),
+                    storage = MEMORY_STORAGE,
                 );
                 let duration = instant_memory.elapsed().as_secs_f64();
                 tracing::info!(
                     histogram.apollo_router_cache_hit_time = duration,
                     kind = %self.caller,
-                    storage = &tracing::field::display(CacheStorageName::Memory),
+                    storage = MEMORY_STORAGE,
                 );
                 Some(v)
             }
             None => {
-                let duration = instant_memory.elapsed().as_secs_f64();
-                tracing::info!(
-                    histogram.apollo_router_cache_miss_time = duration,
-                    kind = %self.caller,
-                    storage = &tracing::field::display(CacheStorageName::Memory),
-                );
-                tracing::info!(
-                    monotonic_counter.apollo_router_cache_miss_count = 1u64,
-                    kind = %self.caller,
-                    storage = &tracing::field::display(CacheStorageName::Memory),
-                );
+                if let Some(inner) = &self.inner {
+                    inner.misses.fetch_add(1, Ordering::Relaxed);
+                    let duration = instant_memory.elapsed().as_secs_f64();
+                    tracing::info!(
+                        histogram.apollo_router_cache_miss_time = duration,
+                        kind = %self.caller,
+                        storage = MEMORY_STORAGE,
+                    );
+                    tracing::info!(
+                        monotonic_counter.apollo_router_cache_miss_count = 1u64,
+                        kind = %self.caller,
+                        storage = MEMORY_STORAGE,
+                    );
+                }
 
-                let instant_redis = Instant::now();
-                if let Some(redis) = self.redis.as_ref() {
+                if self.is_known_miss(key).await {
+                    if stale_if_error.is_some() {
+                        self.remove_from_memory(key).await;
+                    }
+                    return None;
+                }
+
+                let instant_distributed = Instant::now();
+                if let Some(distributed) = self.distributed.as_ref() {
                     let inner_key = RedisKey(key.clone());
-                    match redis.get::<K, V>(inner_key).await {
+                    let value = match distributed.try_get::<K, V>(inner_key).await {
+                        Ok(value) => value,
+                        Err(e) => {
+                            tracing::error!("get error: {}", e);
+                            if let Some(value) = stale_if_error {
+                                tracing::debug!(
+                                    "using a stale {} cache entry as the distributed cache failed",
+                                    self.caller
+                                );
+                                return Some(value);
+                            }
+                            None
+                        }
+                    };
+                    match value {
                         Some(v) => {
-                            self.inner.lock().await.put(key.clone(), v.0.clone());
+                            self.put_in_memory(key.clone(), v.0.clone(), None).await;
 
                             tracing::info!(
                                 monotonic_counter.apollo_router_cache_hit_count = 1u64,
                                 kind = %self.caller,
-                                storage = &tracing::field::display(CacheStorageName::Redis),
+                                storage = distributed.name(),
                             );
-                            let duration = instant_redis.elapsed().as_secs_f64();
+                            let duration = instant_distributed.elapsed().as_secs_f64();
                             tracing::info!(
                                 histogram.apollo_router_cache_hit_time = duration,
                                 kind = %self.caller,
-                                storage = &tracing::field::display(CacheStorageName::Redis),
+                                storage = distributed.name(),
                             );
                             Some(v.0)
                         }
@@ -138,14 +345,18 @@ where
                             tracing::info!(
                                 monotonic_counter.apollo_router_cache_miss_count = 1u64,
                                 kind = %self.caller,
-                                storage = &tracing::field::display(CacheStorageName::Redis),
+                                storage = distributed.name(),
                             );
-                            let duration = instant_redis.elapsed().as_secs_f64();
+                            let duration = instant_distributed.elapsed().as_secs_f64();
                             tracing::info!(
                                 histogram.apollo_router_cache_miss_time = duration,
                                 kind = %self.caller,
-                                storage = &tracing::field::display(CacheStorageName::Redis),
+                                storage = distributed.name(),
                             );
+                            self.record_miss(key.clone()).await;
+                            if stale_if_error.is_some() {
+                                self.remove_from_memory(key).await;
+                            }
                             None
                         }
                     }
@@ -156,25 +367,200 @@ where
         }
     }
 
+    /// Looks up several entries, in the order of their keys, with a single lookup in the
+    /// distributed cache for the entries that are not in memory. The entries expired in memory
+    /// are only served while they are revalidated, the distributed cache errors are logged and
+    /// its entries are then missing
+    pub(crate) async fn get_multiple(&self, keys: &[K]) -> Vec<Option<V>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(match self.get_in_memory(key).await {
+                MemoryLookup::Hit(value) => Some(value),
+                MemoryLookup::Stale { value, revalidate } => {
+                    if revalidate {
+                        self.revalidate(key.clone());
+                    }
+                    Some(value)
+                }
+                MemoryLookup::StaleIfError(_) | MemoryLookup::Miss => None,
+            });
+        }
+        if let Some(inner) = &self.inner {
+            let hits = values.iter().filter(|value| value.is_some()).count();
+            inner.hits.fetch_add(hits as u64, Ordering::Relaxed);
+            inner
+                .misses
+                .fetch_add((values.len() - hits) as u64, Ordering::Relaxed);
+            self.record_lookups(MEMORY_STORAGE, hits, values.len() - hits);
+        }
+
+        let Some(distributed) = self.distributed.as_ref() else {
+            return values;
+        };
+        let mut remaining = Vec::new();
+        for (index, key) in keys.iter().enumerate() {
+            if values[index].is_none() && !self.is_known_miss(key).await {
+                remaining.push(index);
+            }
+        }
+        if remaining.is_empty() {
+            return values;
+        }
+        let remaining_keys = remaining
+            .iter()
+            .map(|index| RedisKey(keys[*index].clone()))
+            .collect();
+        let Some(found) = distributed.get_multiple::<K, V>(remaining_keys).await else {
+            return values;
+        };
+        let hits = found.iter().filter(|value| value.is_some()).count();
+        self.record_lookups(distributed.name(), hits, found.len() - hits);
+        for (index, value) in remaining.into_iter().zip(found) {
+            let key = &keys[index];
+            match value {
+                Some(value) => {
+                    self.put_in_memory(key.clone(), value.0.clone(), None).await;
+                    values[index] = Some(value.0);
+                }
+                None => self.record_miss(key.clone()).await,
+            }
+        }
+        values
+    }
+
     pub(crate) async fn insert(&self, key: K, value: V) {
-        if let Some(redis) = self.redis.as_ref() {
-            redis
-                .insert(RedisKey(key.clone()), RedisValue(value.clone()), None)
+        self.insert_with_ttl(key, value, None).await
+    }
+
+    /// Inserts an entry expiring after `ttl` instead of the TTL of the distributed cache. It
+    /// expires in memory after the shortest of `ttl` and the in-memory TTL
+    pub(crate) async fn insert_with_ttl(&self, key: K, value: V, ttl: Option<Duration>) {
+        if let Some(distributed) = self.distributed.as_ref() {
+            distributed
+                .insert(RedisKey(key.clone()), RedisValue(value.clone()), ttl)
                 .await;
         }
+        if let Some(negative) = &self.negative {
+            negative.misses.lock().await.pop(&key);
+        }
+
+        self.put_in_memory(key, value, ttl).await;
+    }
 
-        let mut in_memory = self.inner.lock().await;
-        in_memory.put(key, value);
+    /// Inserts several entries with the same TTL, with a single write to the distributed cache
+    pub(crate) async fn insert_multiple(&self, data: Vec<(K, V)>, ttl: Option<Duration>) {
+        if let Some(distributed) = self.distributed.as_ref() {
+            let data: Vec<_> = data
+                .iter()
+                .map(|(key, value)| (RedisKey(key.clone()), RedisValue(value.clone())))
+                .collect();
+            distributed.insert_multiple(&data, ttl).await;
+        }
+        for (key, value) in data {
+            if let Some(negative) = &self.negative {
+                negative.misses.lock().await.pop(&key);
+            }
+            self.put_in_memory(key, value, ttl).await;
+        }
+    }
+
+    /// Keeps an entry in memory, expiring with the in-memory TTL, or with `ttl` if it is shorter
+    async fn put_in_memory(&self, key: K, value: V, ttl: Option<Duration>) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let ttl = match (self.memory_ttl, ttl) {
+            (Some(memory_ttl), Some(ttl)) => Some(memory_ttl.min(ttl)),
+            (memory_ttl, ttl) => memory_ttl.or(ttl),
+        };
+        let mut in_memory = inner.entries.lock().await;
+        // pushing an existing key replaces its entry, any other key pushed out was evicted
+        let evicted = in_memory
+            .push(
+                key.clone(),
+                MemoryEntry {
+                    value,
+                    expires_at: ttl.map(|ttl| Instant::now() + ttl),
+                    revalidating: false,
+                    hits: 0,
+                },
+            )
+            .map_or(false, |(previous, _)| previous != key);
         let size = in_memory.len() as u64;
+        drop(in_memory);
         tracing::info!(
             value.apollo_router_cache_size = size,
             kind = %self.caller,
-            storage = &tracing::field::display(CacheStorageName::Memory),
+            storage = MEMORY_STORAGE,
         );
+        if evicted {
+            inner.evictions.fetch_add(1, Ordering::Relaxed);
+            u64_counter!(
+                "apollo_router_cache_eviction_count",
+                "Number of entries evicted from an in-memory cache",
+                1,
+                kind = self.caller.clone(),
+                storage = MEMORY_STORAGE
+            );
+        }
+    }
+
+    async fn remove_from_memory(&self, key: &K) {
+        if let Some(inner) = &self.inner {
+            inner.entries.lock().await.pop(key);
+        }
+    }
+
+    async fn record_miss(&self, key: K) {
+        if let Some(negative) = &self.negative {
+            negative
+                .misses
+                .lock()
+                .await
+                .put(key, Instant::now() + negative.ttl);
+        }
+    }
+
+    fn record_lookups(&self, storage: &str, hits: usize, misses: usize) {
+        if hits > 0 {
+            tracing::info!(
+                monotonic_counter.apollo_router_cache_hit_count = hits as u64,
+                kind = %self.caller,
+                storage = storage,
+            );
+        }
+        if misses > 0 {
+            tracing::info!(
+                monotonic_counter.apollo_router_cache_miss_count = misses as u64,
+                kind = %self.caller,
+                storage = storage,
+            );
+        }
+    }
+
+    /// Whether the distributed cache missed this key recently, in which case it is not looked up
+    /// again until the negative caching TTL expires
+    async fn is_known_miss(&self, key: &K) -> bool {
+        let Some(negative) = &self.negative else {
+            return false;
+        };
+        let mut misses = negative.misses.lock().await;
+        match misses.get(key) {
+            Some(expires_at) if *expires_at > Instant::now() => true,
+            Some(_) => {
+                misses.pop(key);
+                false
+            }
+            None => false,
+        }
     }
 
     pub(crate) async fn in_memory_keys(&self) -> Vec<K> {
-        self.inner
+        let Some(inner) = &self.inner else {
+            return Vec::new();
+        };
+        inner
+            .entries
             .lock()
             .await
             .iter()
@@ -184,20 +570,368 @@ where
 
     #[cfg(test)]
     pub(crate) async fn len(&self) -> usize {
-        self.inner.lock().await.len()
+        match &self.inner {
+            Some(inner) => inner.entries.lock().await.len(),
+            None => 0,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<K, V> InMemoryCache for InMemory<K, V>
+where
+    K: KeyType + 'static,
+    V: ValueType + 'static,
+{
+    fn name(&self) -> &str {
+        &self.caller
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().await;
+        CacheStats {
+            name: self.caller.clone(),
+            size: entries.len(),
+            capacity: entries.cap().get(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn hottest_keys(&self, limit: usize) -> Vec<KeyHits> {
+        let entries = self.entries.lock().await;
+        let mut keys = entries
+            .iter()
+            .map(|(key, entry)| (key, entry.hits))
+            .collect::<Vec<_>>();
+        keys.sort_by(|(_, a), (_, b)| b.cmp(a));
+        keys.into_iter()
+            .take(limit)
+            .map(|(key, hits)| KeyHits {
+                key: key.to_string(),
+                hits,
+            })
+            .collect()
+    }
+
+    async fn clear(&self) {
+        self.entries.lock().await.clear();
     }
 }
 
-enum CacheStorageName {
-    Redis,
-    Memory,
+impl<V> MemoryEntry<V> {
+    /// How long ago the entry expired, if it did
+    fn expired_for(&self) -> Option<Duration> {
+        let now = Instant::now();
+        self.expires_at
+            .filter(|expires_at| *expires_at <= now)
+            .map(|expires_at| now - expires_at)
+    }
 }
 
-impl Display for CacheStorageName {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            CacheStorageName::Redis => write!(f, "redis"),
-            CacheStorageName::Memory => write!(f, "memory"),
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use bytes::Bytes;
+    use tower::BoxError;
+
+    use super::*;
+    use crate::cache::backend::register_cache_backend;
+    use crate::cache::backend::CacheBackend;
+    use crate::configuration::CacheBackendConfig;
+    use crate::configuration::CacheWrite;
+    use crate::configuration::InMemoryCache;
+    use crate::configuration::NegativeCaching;
+
+    #[derive(Default)]
+    struct CountingBackend {
+        entries: parking_lot::Mutex<HashMap<String, Bytes>>,
+        gets: AtomicUsize,
+        failing: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl CacheBackend for CountingBackend {
+        async fn get(&self, key: &str) -> Result<Option<Bytes>, BoxError> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                return Err("backend failure".into());
+            }
+            Ok(self.entries.lock().get(key).cloned())
+        }
+
+        async fn insert(
+            &self,
+            key: String,
+            value: Bytes,
+            _ttl: Option<Duration>,
+        ) -> Result<(), BoxError> {
+            self.entries.lock().insert(key, value);
+            Ok(())
         }
     }
+
+    fn config(memory_ttl: Option<Duration>, backend: Option<&str>) -> Cache {
+        Cache {
+            in_memory: InMemoryCache {
+                limit: NonZeroUsize::new(10).unwrap(),
+                ttl: memory_ttl,
+            },
+            backend: backend.map(|name| CacheBackendConfig {
+                name: name.to_string(),
+                ttl: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn it_expires_entries_in_memory() {
+        let storage: CacheStorage<String, usize> =
+            CacheStorage::new(config(Some(Duration::from_millis(20)), None), "test").await;
+        storage.insert("a".to_string(), 1).await;
+        assert_eq!(storage.get(&"a".to_string()).await, Some(1));
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(storage.get(&"a".to_string()).await, None);
+        assert_eq!(storage.len().await, 0);
+    }
+
+    #[tokio::test]
+    async fn it_records_in_memory_statistics() {
+        let mut config = config(None, None);
+        config.in_memory.limit = NonZeroUsize::new(2).unwrap();
+        let storage: CacheStorage<String, usize> =
+            CacheStorage::new(config, "statistics test").await;
+        storage.insert("a".to_string(), 1).await;
+        storage.insert("b".to_string(), 2).await;
+        storage.get(&"a".to_string()).await;
+        storage.get(&"a".to_string()).await;
+        storage.get(&"b".to_string()).await;
+        assert_eq!(storage.get(&"c".to_string()).await, None);
+
+        let caches = registry::in_memory_caches(Some("statistics test"));
+        assert_eq!(caches.len(), 1);
+        assert_eq!(
+            caches[0].hottest_keys(1).await,
+            vec![KeyHits {
+                key: "a".to_string(),
+                hits: 2
+            }]
+        );
+
+        // replacing an entry does not evict it, "b" is the least recently used entry
+        storage.insert("a".to_string(), 3).await;
+        storage.insert("c".to_string(), 3).await;
+        assert_eq!(
+            caches[0].stats().await,
+            CacheStats {
+                name: "statistics test".to_string(),
+                size: 2,
+                capacity: 2,
+                hits: 3,
+                misses: 1,
+                evictions: 1,
+            }
+        );
+
+        caches[0].clear().await;
+        assert_eq!(storage.len().await, 0);
+
+        drop(caches);
+        drop(storage);
+        assert!(registry::in_memory_caches(Some("statistics test")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_caches_distributed_misses() {
+        let backend = Arc::new(CountingBackend::default());
+        register_cache_backend("test_negative_caching", backend.clone());
+        let storage: CacheStorage<String, usize> = CacheStorage::new(
+            Cache {
+                negative_caching: Some(NegativeCaching {
+                    ttl: Duration::from_secs(60),
+                }),
+                ..config(None, Some("test_negative_caching"))
+            },
+            "test",
+        )
+        .await;
+
+        assert_eq!(storage.get(&"a".to_string()).await, None);
+        assert_eq!(storage.get(&"a".to_string()).await, None);
+        assert_eq!(backend.gets.load(Ordering::SeqCst), 1);
+
+        // Inserting forgets the miss
+        storage.insert("a".to_string(), 1).await;
+        assert_eq!(backend.entries.lock().get("a"), Some(&Bytes::from("1")));
+        assert_eq!(storage.get(&"a".to_string()).await, Some(1));
+    }
+
+    #[tokio::test]
+    async fn it_looks_up_multiple_entries_in_memory_first() {
+        let backend = Arc::new(CountingBackend::default());
+        register_cache_backend("test_get_multiple", backend.clone());
+        let distributed = DistributedCache::new(
+            None,
+            Some(CacheBackendConfig {
+                name: "test_get_multiple".to_string(),
+                ttl: None,
+            }),
+            CacheWrite::Through,
+            Default::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let storage: CacheStorage<String, usize> = CacheStorage::with_distributed(
+            "test",
+            distributed,
+            Some(InMemoryCache {
+                limit: NonZeroUsize::new(10).unwrap(),
+                ttl: None,
+            }),
+            Some(NegativeCaching {
+                ttl: Duration::from_secs(60),
+            }),
+        );
+        let keys = ["a".to_string(), "b".to_string(), "c".to_string()];
+
+        // The entry expires in memory with its own TTL
+        storage
+            .insert_with_ttl(keys[0].clone(), 1, Some(Duration::from_millis(20)))
+            .await;
+        storage
+            .insert_multiple(vec![(keys[1].clone(), 2)], None)
+            .await;
+        assert_eq!(
+            storage.get_multiple(&keys).await,
+            vec![Some(1), Some(2), None]
+        );
+        assert_eq!(backend.gets.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(
+            storage.get_multiple(&keys).await,
+            vec![Some(1), Some(2), None]
+        );
+        // Only the expired entry was looked up, the miss is cached
+        assert_eq!(backend.gets.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn it_can_go_without_the_in_memory_tier() {
+        let backend = Arc::new(CountingBackend::default());
+        register_cache_backend("test_without_memory", backend.clone());
+        let distributed = DistributedCache::new(
+            None,
+            Some(CacheBackendConfig {
+                name: "test_without_memory".to_string(),
+                ttl: None,
+            }),
+            CacheWrite::Through,
+            Default::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let storage: CacheStorage<String, usize> =
+            CacheStorage::with_distributed("test", distributed, None, None);
+
+        storage.insert("a".to_string(), 1).await;
+        assert_eq!(storage.get(&"a".to_string()).await, Some(1));
+        assert_eq!(storage.len().await, 0);
+        assert_eq!(backend.gets.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn it_writes_behind() {
+        let backend = Arc::new(CountingBackend::default());
+        register_cache_backend("test_write_behind", backend.clone());
+        let storage: CacheStorage<String, usize> = CacheStorage::new(
+            Cache {
+                write: CacheWrite::Behind,
+                ..config(None, Some("test_write_behind"))
+            },
+            "test",
+        )
+        .await;
+
+        storage.insert("a".to_string(), 1).await;
+        assert_eq!(storage.get(&"a".to_string()).await, Some(1));
+        for _ in 0..50 {
+            if backend.entries.lock().contains_key("a") {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the entry was not written to the distributed cache");
+    }
+
+    #[tokio::test]
+    async fn it_serves_stale_entries_while_revalidating() {
+        let backend = Arc::new(CountingBackend::default());
+        register_cache_backend("test_stale_while_revalidate", backend.clone());
+        let storage: CacheStorage<String, usize> = CacheStorage::new(
+            Cache {
+                stale_while_revalidate: Some(Duration::from_secs(60)),
+                ..config(
+                    Some(Duration::from_millis(20)),
+                    Some("test_stale_while_revalidate"),
+                )
+            },
+            "test",
+        )
+        .await;
+
+        storage.insert("a".to_string(), 1).await;
+        backend
+            .entries
+            .lock()
+            .insert("a".to_string(), Bytes::from("2"));
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        // The stale entry is served while it is refreshed in the background
+        assert_eq!(storage.get(&"a".to_string()).await, Some(1));
+        for _ in 0..50 {
+            if storage.get(&"a".to_string()).await == Some(2) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the entry was not revalidated");
+    }
+
+    #[tokio::test]
+    async fn it_serves_stale_entries_if_the_distributed_cache_fails() {
+        let backend = Arc::new(CountingBackend::default());
+        register_cache_backend("test_stale_if_error", backend.clone());
+        let storage: CacheStorage<String, usize> = CacheStorage::new(
+            Cache {
+                stale_if_error: Some(Duration::from_secs(60)),
+                ..config(Some(Duration::from_millis(20)), Some("test_stale_if_error"))
+            },
+            "test",
+        )
+        .await;
+
+        storage.insert("a".to_string(), 1).await;
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        backend.failing.store(true, Ordering::SeqCst);
+        assert_eq!(storage.get(&"a".to_string()).await, Some(1));
+
+        // Without failure, the stale entry is not used
+        backend.failing.store(false, Ordering::SeqCst);
+        backend.entries.lock().clear();
+        assert_eq!(storage.get(&"a".to_string()).await, None);
+        backend.failing.store(true, Ordering::SeqCst);
+        assert_eq!(storage.get(&"a".to_string()).await, None);
+    }
 }
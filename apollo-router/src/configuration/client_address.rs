@@ -0,0 +1,238 @@
+//! Resolution of the address of the client that sent a request, through trusted proxies
+
+use std::net::IpAddr;
+use std::net::SocketAddr;
+
+use http::header::FORWARDED;
+use http::HeaderMap;
+use http::HeaderName;
+use ipnet::IpNet;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// How the address of the client is determined
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ClientAddressConfig {
+    /// The header in which proxies list the addresses they forward requests for
+    /// Default: forwarded
+    pub(crate) header: ForwardedHeader,
+
+    /// Networks of the proxies whose header is trusted, such as `10.0.0.0/8`. Without trusted
+    /// proxies, the client address is the address of the TCP peer
+    #[schemars(with = "Vec<String>")]
+    pub(crate) trusted_proxies: Vec<IpNet>,
+}
+
+/// The header in which proxies list the addresses they forward requests for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ForwardedHeader {
+    /// The `for` parameters of the standard `Forwarded` header
+    #[default]
+    Forwarded,
+    /// The `X-Forwarded-For` header
+    XForwardedFor,
+}
+
+/// The address of the client that sent a request, stored in the extensions of the router request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ClientAddress {
+    pub(crate) ip: IpAddr,
+    /// The port of the client, if known
+    pub(crate) port: Option<u16>,
+}
+
+impl ClientAddressConfig {
+    /// The address of the client that sent a request through the TCP peer.
+    ///
+    /// The forwarded addresses are walked from the closest proxy to the client: each address
+    /// recorded by a trusted proxy is the next hop, until an untrusted address, which is the
+    /// client. An address that cannot be parsed stops the walk at the last trusted hop.
+    pub(crate) fn resolve(
+        &self,
+        headers: &HeaderMap,
+        peer: Option<SocketAddr>,
+    ) -> Option<ClientAddress> {
+        let peer = peer?;
+        let mut client = ClientAddress {
+            ip: peer.ip(),
+            port: Some(peer.port()),
+        };
+        if !self.is_trusted(client.ip) {
+            return Some(client);
+        }
+
+        for hop in self.forwarded_addresses(headers).into_iter().rev() {
+            let Some(hop) = hop else {
+                break;
+            };
+            client = hop;
+            if !self.is_trusted(hop.ip) {
+                break;
+            }
+        }
+        Some(client)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|network| network.contains(&ip))
+    }
+
+    /// The forwarded addresses, from the client to the closest proxy
+    fn forwarded_addresses(&self, headers: &HeaderMap) -> Vec<Option<ClientAddress>> {
+        let name = match self.header {
+            ForwardedHeader::Forwarded => &FORWARDED,
+            ForwardedHeader::XForwardedFor => &X_FORWARDED_FOR,
+        };
+        headers
+            .get_all(name)
+            .iter()
+            .flat_map(|value| match value.to_str() {
+                Ok(value) => value
+                    .split(',')
+                    .map(|element| match self.header {
+                        ForwardedHeader::Forwarded => forwarded_for(element).and_then(parse_node),
+                        ForwardedHeader::XForwardedFor => parse_node(element),
+                    })
+                    .collect(),
+                Err(_) => vec![None],
+            })
+            .collect()
+    }
+}
+
+/// The `for` parameter of an element of the `Forwarded` header
+fn forwarded_for(element: &str) -> Option<&str> {
+    element.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("for")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// An address, with an optional port. IPv6 addresses with a port are enclosed in brackets
+fn parse_node(node: &str) -> Option<ClientAddress> {
+    let node = node.trim();
+    if let Ok(address) = node.parse::<SocketAddr>() {
+        return Some(ClientAddress {
+            ip: address.ip(),
+            port: Some(address.port()),
+        });
+    }
+    node.trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .ok()
+        .map(|ip| ClientAddress { ip, port: None })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(header: ForwardedHeader, trusted_proxies: &[&str]) -> ClientAddressConfig {
+        ClientAddressConfig {
+            header,
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|network| network.parse().unwrap())
+                .collect(),
+        }
+    }
+
+    fn resolve(
+        config: &ClientAddressConfig,
+        name: &HeaderName,
+        values: &[&str],
+        peer: &str,
+    ) -> Option<ClientAddress> {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name, value.parse().unwrap());
+        }
+        config.resolve(&headers, Some(peer.parse().unwrap()))
+    }
+
+    fn address(address: &str, port: Option<u16>) -> Option<ClientAddress> {
+        Some(ClientAddress {
+            ip: address.parse().unwrap(),
+            port,
+        })
+    }
+
+    #[test]
+    fn it_ignores_headers_from_untrusted_peers() {
+        let config = config(ForwardedHeader::Forwarded, &[]);
+        assert_eq!(
+            resolve(&config, &FORWARDED, &["for=2.4.6.8"], "192.168.0.8:6060"),
+            address("192.168.0.8", Some(6060))
+        );
+        assert_eq!(
+            ClientAddressConfig::default().resolve(&HeaderMap::new(), None),
+            None
+        );
+    }
+
+    #[test]
+    fn it_walks_the_forwarded_chain() {
+        let config = config(ForwardedHeader::Forwarded, &["10.0.0.0/8", "fd00::/8"]);
+        assert_eq!(
+            resolve(
+                &config,
+                &FORWARDED,
+                &[
+                    "for=1.1.1.1, for=\"[2001:db8:cafe::17]:4711\";proto=https",
+                    "For=10.0.0.2;by=10.0.0.1",
+                ],
+                "10.0.0.1:6060"
+            ),
+            address("2001:db8:cafe::17", Some(4711))
+        );
+        // Spoofed addresses before the first untrusted address are ignored
+        assert_eq!(
+            resolve(
+                &config,
+                &FORWARDED,
+                &["for=1.1.1.1, for=2.4.6.8:8000, for=\"[fd00::1]\""],
+                "10.0.0.1:6060"
+            ),
+            address("2.4.6.8", Some(8000))
+        );
+        // Unknown or obfuscated addresses stop the walk
+        assert_eq!(
+            resolve(
+                &config,
+                &FORWARDED,
+                &["for=1.1.1.1, for=unknown, for=10.0.0.3"],
+                "10.0.0.1:6060"
+            ),
+            address("10.0.0.3", None)
+        );
+    }
+
+    #[test]
+    fn it_reads_x_forwarded_for() {
+        let config = config(ForwardedHeader::XForwardedFor, &["10.0.0.0/8"]);
+        assert_eq!(
+            resolve(
+                &config,
+                &X_FORWARDED_FOR,
+                &["2.4.6.8, 10.0.0.2"],
+                "10.0.0.1:6060"
+            ),
+            address("2.4.6.8", None)
+        );
+        // The Forwarded header is ignored
+        assert_eq!(
+            resolve(&config, &FORWARDED, &["for=2.4.6.8"], "10.0.0.1:6060"),
+            address("10.0.0.1", Some(6060))
+        );
+    }
+}
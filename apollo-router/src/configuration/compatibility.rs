@@ -0,0 +1,203 @@
+//! Compatibility of configuration files shared by routers of different versions
+
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::paths::JSONPointer;
+use jsonschema::JSONSchema;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Relax the validation of the configuration, so that a configuration file can be shared by
+/// routers of different versions during an upgrade of a fleet
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct ConfigCompatibility {
+    /// How unknown fields are handled (default: deny)
+    pub(crate) unknown_fields: UnknownFieldsMode,
+
+    /// The top-level sections, such as `telemetry`, in which unknown fields are handled by
+    /// `unknown_fields`, or every section if empty. Listing a section also allows it to be unknown.
+    pub(crate) sections: Vec<String>,
+}
+
+/// How unknown fields are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UnknownFieldsMode {
+    /// The router fails to start
+    #[default]
+    Deny,
+    /// The unknown fields are ignored, with a warning
+    Warn,
+}
+
+/// An unknown field removed from the configuration
+#[derive(Debug, PartialEq)]
+pub(crate) struct UnknownField {
+    /// The object containing the field
+    pub(crate) parent: JSONPointer,
+    /// The name of the field
+    pub(crate) name: String,
+    /// The closest known field of the object, if any is close enough
+    pub(crate) suggestion: Option<String>,
+}
+
+impl UnknownField {
+    /// The YAML path of the field, such as `telemetry.exporters.unknown`
+    pub(crate) fn path(&self) -> String {
+        self.parent
+            .clone()
+            .into_vec()
+            .into_iter()
+            .chain(std::iter::once(self.name.clone()))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+impl ConfigCompatibility {
+    /// Reads the compatibility of a configuration before it is validated. Invalid settings are
+    /// ignored here, as the validation reports them.
+    pub(crate) fn from_config(config: &Value) -> Self {
+        config
+            .get("experimental_config_compatibility")
+            .and_then(|compatibility| serde_json::from_value(compatibility.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    fn applies_to(&self, section: &str) -> bool {
+        self.unknown_fields == UnknownFieldsMode::Warn
+            && (self.sections.is_empty() || self.sections.iter().any(|name| name == section))
+    }
+
+    /// Removes the unknown fields of the lenient sections from a configuration
+    pub(crate) fn remove_unknown_fields(
+        &self,
+        schema: &JSONSchema,
+        schema_json: &Value,
+        config: &mut Value,
+    ) -> Vec<UnknownField> {
+        if self.unknown_fields == UnknownFieldsMode::Deny {
+            return Vec::new();
+        }
+        let mut unknown_fields = Vec::new();
+        if let Err(errors) = schema.validate(config) {
+            for error in errors {
+                let ValidationErrorKind::AdditionalProperties { unexpected } = &error.kind else {
+                    continue;
+                };
+                let parent = error.instance_path.clone().into_vec();
+                let known_fields = known_fields(schema_json, error.schema_path.clone().into_vec());
+                for name in unexpected {
+                    let section = parent.first().unwrap_or(name);
+                    if !self.applies_to(section) {
+                        continue;
+                    }
+                    unknown_fields.push(UnknownField {
+                        parent: error.instance_path.clone(),
+                        name: name.clone(),
+                        suggestion: suggestion(name, &known_fields),
+                    });
+                }
+            }
+        }
+        for field in &unknown_fields {
+            let path = field.parent.clone().into_vec();
+            let parent = path
+                .iter()
+                .try_fold(&mut *config, |value, chunk| match value {
+                    Value::Object(object) => object.get_mut(chunk),
+                    Value::Array(array) => array.get_mut(chunk.parse::<usize>().ok()?),
+                    _ => None,
+                });
+            if let Some(Value::Object(object)) = parent {
+                object.remove(&field.name);
+            }
+        }
+        unknown_fields
+    }
+}
+
+/// The fields of the object whose `additionalProperties` keyword rejected a field
+fn known_fields(schema_json: &Value, mut schema_path: Vec<String>) -> Vec<String> {
+    schema_path.pop();
+    schema_path
+        .iter()
+        .try_fold(schema_json, |value, chunk| match value {
+            Value::Object(object) => object.get(chunk),
+            Value::Array(array) => array.get(chunk.parse::<usize>().ok()?),
+            _ => None,
+        })
+        .and_then(|object| object.get("properties"))
+        .and_then(Value::as_object)
+        .map(|properties| properties.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// The known field closest to an unknown one, if any is close enough
+fn suggestion(name: &str, known_fields: &[String]) -> Option<String> {
+    known_fields
+        .iter()
+        .map(|known| (strsim::jaro(name, known), known))
+        .filter(|(confidence, _)| *confidence > 0.7)
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, known)| known.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonschema::Draft;
+    use serde_json::json;
+
+    use super::*;
+    use crate::configuration::generate_config_schema;
+
+    #[test]
+    fn it_removes_the_unknown_fields() {
+        let schema_json = serde_json::to_value(generate_config_schema()).unwrap();
+        let schema = JSONSchema::options()
+            .with_draft(Draft::Draft7)
+            .compile(&schema_json)
+            .unwrap();
+        let mut config = json!({
+            "experimental_config_compatibility": { "unknown_fields": "warn" },
+            "telemetry": { "exporter": {} },
+            "supergraph": { "listen": "127.0.0.1:4000" }
+        });
+        let compatibility = ConfigCompatibility::from_config(&config);
+        let unknown_fields =
+            compatibility.remove_unknown_fields(&schema, &schema_json, &mut config);
+        assert_eq!(unknown_fields.len(), 1);
+        assert_eq!(unknown_fields[0].path(), "telemetry.exporter");
+        assert_eq!(unknown_fields[0].suggestion.as_deref(), Some("exporters"));
+        assert_eq!(config["telemetry"], json!({}));
+        assert!(schema.is_valid(&config));
+
+        // Unknown fields are rejected by default
+        let mut config = json!({ "telemetry": { "exporter": {} } });
+        assert!(ConfigCompatibility::default()
+            .remove_unknown_fields(&schema, &schema_json, &mut config)
+            .is_empty());
+        assert_eq!(config["telemetry"], json!({ "exporter": {} }));
+    }
+
+    #[test]
+    fn it_suggests_the_closest_known_field() {
+        let known_fields = vec![
+            "exporters".to_string(),
+            "instrumentation".to_string(),
+            "apollo".to_string(),
+        ];
+        assert_eq!(
+            suggestion("exporter", &known_fields),
+            Some("exporters".to_string())
+        );
+        assert_eq!(
+            suggestion("instrumentations", &known_fields),
+            Some("instrumentation".to_string())
+        );
+        assert_eq!(suggestion("zzz", &known_fields), None);
+    }
+}
@@ -11,12 +11,15 @@ use serde_json::Value;
 
 use super::ConfigurationError;
 use crate::executable::APOLLO_ROUTER_DEV_ENV;
+use crate::executable::APOLLO_ROUTER_PROFILE_ENV;
 
 #[derive(buildstructor::Builder, Clone)]
 pub(crate) struct Expansion {
     prefix: Option<String>,
     supported_modes: Vec<String>,
     override_configs: Vec<Override>,
+    /// The configuration profile to merge into the configuration
+    profile: Option<String>,
 }
 
 #[derive(buildstructor::Builder, Clone)]
@@ -90,6 +93,15 @@ impl Expansion {
             Vec::new()
         };
 
+        let profile = match env::var(APOLLO_ROUTER_PROFILE_ENV) {
+            Ok(v) if !v.is_empty() => Some(v),
+            Ok(_) | Err(VarError::NotPresent) => None,
+            Err(VarError::NotUnicode(_)) => Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid configuration profile",
+                error: format!("{APOLLO_ROUTER_PROFILE_ENV} must be valid unicode"),
+            })?,
+        };
+
         Ok(Expansion::builder()
             .and_prefix(prefix)
             .supported_modes(supported_modes)
@@ -116,9 +128,14 @@ impl Expansion {
                     .build(),
             )
             .override_configs(dev_mode_defaults)
+            .and_profile(profile)
             .build())
     }
 
+    pub(crate) fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
     pub(crate) fn default_rhai() -> Result<Self, ConfigurationError> {
         Ok(Expansion::builder()
             .and_prefix(Expansion::prefix_from_env()?)
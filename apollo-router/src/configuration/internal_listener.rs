@@ -0,0 +1,108 @@
+//! Internal listener configuration
+
+use std::sync::Arc;
+
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::Certificate;
+use rustls::PrivateKey;
+use rustls::RootCertStore;
+use rustls::ServerConfig;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use super::deserialize_certificate;
+use super::deserialize_certificate_chain;
+use super::deserialize_key;
+use crate::ApolloRouterError;
+use crate::ListenAddr;
+
+/// Serve the health check, metrics, and every other endpoint that is not on the GraphQL
+/// listener from a single internal listener
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct InternalListener {
+    /// Set to true to move the endpoints to the internal listener (disabled by default)
+    pub(crate) enabled: bool,
+
+    /// The listen address of the internal endpoints. It overrides the listen address of the
+    /// health check, metrics and other endpoints, except those on the GraphQL listener
+    /// Defaults to 127.0.0.1:8088
+    pub(crate) listen: ListenAddr,
+
+    /// TLS configuration of the internal listener
+    pub(crate) tls: Option<InternalListenerTls>,
+}
+
+impl Default for InternalListener {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: ListenAddr::SocketAddr("127.0.0.1:8088".parse().expect("valid listenAddr")),
+            tls: None,
+        }
+    }
+}
+
+impl InternalListener {
+    /// Returns the listen address of the internal endpoints, if enabled
+    pub(crate) fn listen(&self) -> Option<&ListenAddr> {
+        self.enabled.then_some(&self.listen)
+    }
+}
+
+/// TLS configuration of the internal listener
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct InternalListenerTls {
+    /// server certificate in PEM format
+    #[serde(deserialize_with = "deserialize_certificate", skip_serializing)]
+    #[schemars(with = "String")]
+    pub(crate) certificate: Certificate,
+    /// server key in PEM format
+    #[serde(deserialize_with = "deserialize_key", skip_serializing)]
+    #[schemars(with = "String")]
+    pub(crate) key: PrivateKey,
+    /// list of certificate authorities in PEM format
+    #[serde(
+        deserialize_with = "deserialize_certificate_chain",
+        skip_serializing,
+        default
+    )]
+    #[schemars(with = "String")]
+    pub(crate) certificate_chain: Vec<Certificate>,
+    /// certificate authorities in PEM format. If set, clients must present a certificate signed
+    /// by one of them (mTLS)
+    #[serde(
+        deserialize_with = "deserialize_certificate_chain",
+        skip_serializing,
+        default
+    )]
+    #[schemars(with = "Option<String>")]
+    pub(crate) client_certificate_authorities: Vec<Certificate>,
+}
+
+impl InternalListenerTls {
+    pub(crate) fn tls_config(&self) -> Result<Arc<ServerConfig>, ApolloRouterError> {
+        let mut certificates = vec![self.certificate.clone()];
+        certificates.extend(self.certificate_chain.iter().cloned());
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = if self.client_certificate_authorities.is_empty() {
+            builder.with_no_client_auth()
+        } else {
+            let mut roots = RootCertStore::empty();
+            for certificate in &self.client_certificate_authorities {
+                roots.add(certificate).map_err(ApolloRouterError::Rustls)?;
+            }
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        };
+        let mut config = builder
+            .with_single_cert(certificates, self.key.clone())
+            .map_err(ApolloRouterError::Rustls)?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Arc::new(config))
+    }
+}
@@ -0,0 +1,292 @@
+//! Layering of several configuration files
+//!
+//! The files are merged in order: the mappings are merged recursively, and any other value of a
+//! later file replaces the one of the earlier files. A `null` value removes the key. The YAML
+//! files of a directory are merged in the alphabetical order of their names.
+//!
+//! A configuration can also contain profiles, named overrides merged into it the same way when
+//! they are selected.
+
+use std::path::Path;
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use serde_json::Value;
+
+use super::ConfigurationError;
+
+/// The key of the configuration profiles
+pub(crate) const PROFILES: &str = "profiles";
+
+/// The configuration files to merge, in order, with the directories replaced by their YAML files
+pub(crate) fn config_files(paths: &[PathBuf]) -> Result<Vec<PathBuf>, ConfigurationError> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(directory_files(path)?);
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+fn directory_files(directory: &Path) -> Result<Vec<PathBuf>, ConfigurationError> {
+    let mut files = std::fs::read_dir(directory)
+        .map_err(|e| read_error(directory, e))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| read_error(directory, e))?;
+    files.retain(|path| {
+        path.is_file()
+            && matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("yaml" | "yml")
+            )
+    });
+    files.sort();
+    Ok(files)
+}
+
+fn read_error(path: &Path, error: std::io::Error) -> ConfigurationError {
+    ConfigurationError::InvalidConfiguration {
+        message: "could not read configuration",
+        error: format!("{}: {error}", path.display()),
+    }
+}
+
+/// Reads and merges configuration files and directories, returning the merged YAML
+pub(crate) fn merge_config_files(paths: &[PathBuf]) -> Result<String, ConfigurationError> {
+    let configs = config_files(paths)?
+        .iter()
+        .map(|path| {
+            std::fs::read_to_string(path)
+                .map(|config| (path.display().to_string(), config))
+                .map_err(|e| read_error(path, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    merge_configs(&configs)
+}
+
+/// Merges configurations, given with their names, returning the merged YAML
+pub(crate) fn merge_configs(configs: &[(String, String)]) -> Result<String, ConfigurationError> {
+    let mut merged = Value::Object(Default::default());
+    for (name, config) in configs {
+        if config.trim().is_empty() {
+            continue;
+        }
+        let layer: Value =
+            serde_yaml::from_str(config).map_err(|e| ConfigurationError::InvalidConfiguration {
+                message: "failed to parse configuration",
+                error: format!("{name}: {e}"),
+            })?;
+        match layer {
+            Value::Null => {}
+            Value::Object(_) => merge(&mut merged, layer),
+            _ => {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "failed to parse configuration",
+                    error: format!("{name}: the configuration must be a mapping"),
+                })
+            }
+        }
+    }
+    serde_yaml::to_string(&merged).map_err(|e| ConfigurationError::InvalidConfiguration {
+        message: "failed to serialize the merged configuration",
+        error: e.to_string(),
+    })
+}
+
+/// Merges a layer into a configuration: the mappings are merged recursively, and any other value
+/// of the layer replaces the one of the configuration. A `null` value removes the key.
+pub(crate) fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Object(base), Value::Object(layer)) => {
+            for (key, value) in layer {
+                if value.is_null() {
+                    base.remove(&key);
+                } else if let Some(base_value) = base.get_mut(&key) {
+                    merge(base_value, value);
+                } else {
+                    base.insert(key, value);
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Merges the selected profile into a configuration, and removes the profiles from it
+pub(crate) fn apply_profile(
+    config: &mut Value,
+    profile: Option<&str>,
+) -> Result<(), ConfigurationError> {
+    let profiles = match config.as_object_mut() {
+        Some(config) => config.remove(PROFILES),
+        None => None,
+    };
+    let Some(profile) = profile else {
+        return Ok(());
+    };
+    let mut profiles = match profiles {
+        Some(Value::Object(profiles)) => profiles,
+        _ => {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "unknown configuration profile",
+                error: format!("'{profile}' was selected but the configuration has no profiles"),
+            })
+        }
+    };
+    match profiles.remove(profile) {
+        Some(Value::Null) => Ok(()),
+        Some(layer @ Value::Object(_)) => {
+            merge(config, layer);
+            Ok(())
+        }
+        Some(_) => Err(ConfigurationError::InvalidConfiguration {
+            message: "invalid configuration profile",
+            error: format!("the profile '{profile}' must be a mapping"),
+        }),
+        None => Err(ConfigurationError::InvalidConfiguration {
+            message: "unknown configuration profile",
+            error: format!(
+                "'{profile}' is not one of the profiles of the configuration: {}",
+                profiles.keys().join(", ")
+            ),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(configs: &[&str]) -> serde_json::Value {
+        let configs = configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| (format!("config{index}.yaml"), config.to_string()))
+            .collect::<Vec<_>>();
+        serde_yaml::from_str(&merge_configs(&configs).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn later_files_take_precedence() {
+        let base = r#"
+supergraph:
+  listen: 0.0.0.0:4000
+  introspection: false
+cors:
+  origins: [https://studio.apollographql.com]
+telemetry:
+  exporters:
+    tracing:
+      common:
+        service_name: router
+"#;
+        let production = r#"
+supergraph:
+  introspection: true
+cors:
+  origins: [https://example.com]
+telemetry: ~
+homepage:
+  enabled: false
+"#;
+        assert_eq!(
+            merged(&[base, "", production]),
+            serde_json::json!({
+                "supergraph": {
+                    "listen": "0.0.0.0:4000",
+                    "introspection": true
+                },
+                "cors": {
+                    "origins": ["https://example.com"]
+                },
+                "homepage": {
+                    "enabled": false
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn configurations_must_be_mappings() {
+        let configs = vec![("config.yaml".to_string(), "- a\n- b\n".to_string())];
+        assert!(merge_configs(&configs)
+            .unwrap_err()
+            .to_string()
+            .contains("config.yaml: the configuration must be a mapping"));
+    }
+
+    #[test]
+    fn selected_profile_is_merged() {
+        let config = serde_json::json!({
+            "supergraph": {
+                "listen": "0.0.0.0:4000",
+                "introspection": false
+            },
+            "homepage": {
+                "enabled": true
+            },
+            "profiles": {
+                "dev": {
+                    "supergraph": {
+                        "introspection": true
+                    },
+                    "homepage": null
+                },
+                "production": {}
+            }
+        });
+
+        let mut dev = config.clone();
+        apply_profile(&mut dev, Some("dev")).unwrap();
+        assert_eq!(
+            dev,
+            serde_json::json!({
+                "supergraph": {
+                    "listen": "0.0.0.0:4000",
+                    "introspection": true
+                }
+            })
+        );
+
+        let mut base = config.clone();
+        apply_profile(&mut base, None).unwrap();
+        assert!(base.get(PROFILES).is_none());
+        assert_eq!(base["supergraph"]["introspection"], false);
+
+        let mut unknown = config;
+        assert!(apply_profile(&mut unknown, Some("staging"))
+            .unwrap_err()
+            .to_string()
+            .contains(
+                "'staging' is not one of the profiles of the configuration: dev, production"
+            ));
+    }
+
+    #[test]
+    fn directories_are_merged_in_alphabetical_order() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("20-env.yaml"), "a: 2\n").unwrap();
+        std::fs::write(directory.path().join("10-base.yml"), "a: 1\nb: 1\n").unwrap();
+        std::fs::write(directory.path().join("README.md"), "not a configuration").unwrap();
+        std::fs::write(directory.path().join("30-overrides.yaml"), "b: 3\n").unwrap();
+
+        let files = config_files(&[directory.path().to_path_buf()]).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                directory.path().join("10-base.yml"),
+                directory.path().join("20-env.yaml"),
+                directory.path().join("30-overrides.yaml"),
+            ]
+        );
+
+        let merged: serde_json::Value =
+            serde_yaml::from_str(&merge_config_files(&[directory.path().to_path_buf()]).unwrap())
+                .unwrap();
+        assert_eq!(merged, serde_json::json!({ "a": 2, "b": 3 }));
+    }
+}
@@ -1,7 +1,11 @@
 //! Logic for loading configuration in to an object model
+pub(crate) mod client_address;
+mod compatibility;
 pub(crate) mod cors;
 pub(crate) mod expansion;
 mod experimental;
+mod internal_listener;
+mod layering;
 pub(crate) mod metrics;
 mod persisted_queries;
 mod schema;
@@ -18,12 +22,14 @@ use std::iter;
 use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use derivative::Derivative;
 use displaydoc::Display;
+use http::StatusCode;
 use itertools::Itertools;
 use once_cell::sync::Lazy;
 pub(crate) use persisted_queries::PersistedQueries;
@@ -48,9 +54,16 @@ use serde_json::Map;
 use serde_json::Value;
 use thiserror::Error;
 
+use self::client_address::ClientAddressConfig;
+pub(crate) use self::compatibility::ConfigCompatibility;
 use self::cors::Cors;
 use self::expansion::Expansion;
 pub(crate) use self::experimental::Discussed;
+pub(crate) use self::internal_listener::InternalListener;
+#[cfg(test)]
+pub(crate) use self::internal_listener::InternalListenerTls;
+pub(crate) use self::layering::config_files;
+pub(crate) use self::layering::merge_config_files;
 pub(crate) use self::schema::generate_config_schema;
 pub(crate) use self::schema::generate_upgrade;
 use self::subgraph::SubgraphConfiguration;
@@ -61,12 +74,14 @@ use crate::notification::Notify;
 #[cfg(not(test))]
 use crate::notification::RouterBroadcasts;
 use crate::plugin::plugins;
+use crate::plugins::shadow::ShadowPlugins;
 #[cfg(not(test))]
 use crate::plugins::subscription::SubscriptionConfig;
 #[cfg(not(test))]
 use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN;
 #[cfg(not(test))]
 use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN_NAME;
+use crate::spec::validation_rules::ListenerValidationRules;
 use crate::uplink::UplinkConfig;
 use crate::ApolloRouterError;
 
@@ -191,6 +206,24 @@ pub struct Configuration {
     /// Batching configuration.
     #[serde(default)]
     pub(crate) experimental_batching: Batching,
+
+    /// Internal listener configuration
+    #[serde(default)]
+    pub(crate) experimental_internal_listener: InternalListener,
+
+    /// Compatibility of the configuration file with routers of other versions
+    #[serde(default)]
+    pub(crate) experimental_config_compatibility: ConfigCompatibility,
+
+    /// Plugins evaluated in shadow mode: they run on a copy of a sample of the requests and their
+    /// decisions and latency are recorded, but they never change the requests or the responses
+    #[serde(default)]
+    pub(crate) experimental_shadow_plugins: ShadowPlugins,
+
+    /// Actions for GraphQL validation rules, by listener address. Requires
+    /// `experimental_graphql_validation_mode: new`
+    #[serde(default)]
+    pub(crate) experimental_validation_rules: ListenerValidationRules,
 }
 
 impl PartialEq for Configuration {
@@ -256,6 +289,10 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             experimental_chaos: Chaos,
             experimental_graphql_validation_mode: GraphQLValidationMode,
             experimental_batching: Batching,
+            experimental_internal_listener: InternalListener,
+            experimental_config_compatibility: ConfigCompatibility,
+            experimental_shadow_plugins: ShadowPlugins,
+            experimental_validation_rules: ListenerValidationRules,
         }
         let ad_hoc: AdHocConfiguration = serde::Deserialize::deserialize(deserializer)?;
 
@@ -275,6 +312,10 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             .uplink(ad_hoc.uplink)
             .graphql_validation_mode(ad_hoc.experimental_graphql_validation_mode)
             .experimental_batching(ad_hoc.experimental_batching)
+            .experimental_internal_listener(ad_hoc.experimental_internal_listener)
+            .experimental_config_compatibility(ad_hoc.experimental_config_compatibility)
+            .experimental_shadow_plugins(ad_hoc.experimental_shadow_plugins)
+            .experimental_validation_rules(ad_hoc.experimental_validation_rules)
             .build()
             .map_err(|e| serde::de::Error::custom(e.to_string()))
     }
@@ -313,6 +354,10 @@ impl Configuration {
         graphql_validation_mode: Option<GraphQLValidationMode>,
         experimental_api_schema_generation_mode: Option<ApiSchemaMode>,
         experimental_batching: Option<Batching>,
+        experimental_internal_listener: Option<InternalListener>,
+        experimental_config_compatibility: Option<ConfigCompatibility>,
+        experimental_shadow_plugins: Option<ShadowPlugins>,
+        experimental_validation_rules: Option<ListenerValidationRules>,
     ) -> Result<Self, ConfigurationError> {
         #[cfg(not(test))]
         let notify_queue_cap = match apollo_plugins.get(APOLLO_SUBSCRIPTION_PLUGIN_NAME) {
@@ -349,6 +394,10 @@ impl Configuration {
             tls: tls.unwrap_or_default(),
             uplink,
             experimental_batching: experimental_batching.unwrap_or_default(),
+            experimental_internal_listener: experimental_internal_listener.unwrap_or_default(),
+            experimental_config_compatibility: experimental_config_compatibility.unwrap_or_default(),
+            experimental_shadow_plugins: experimental_shadow_plugins.unwrap_or_default(),
+            experimental_validation_rules: experimental_validation_rules.unwrap_or_default(),
             #[cfg(test)]
             notify: notify.unwrap_or_default(),
             #[cfg(not(test))]
@@ -389,6 +438,10 @@ impl Configuration {
         graphql_validation_mode: Option<GraphQLValidationMode>,
         experimental_batching: Option<Batching>,
         experimental_api_schema_generation_mode: Option<ApiSchemaMode>,
+        experimental_internal_listener: Option<InternalListener>,
+        experimental_config_compatibility: Option<ConfigCompatibility>,
+        experimental_shadow_plugins: Option<ShadowPlugins>,
+        experimental_validation_rules: Option<ListenerValidationRules>,
     ) -> Result<Self, ConfigurationError> {
         let configuration = Self {
             validated_yaml: Default::default(),
@@ -414,6 +467,11 @@ impl Configuration {
             persisted_queries: persisted_query.unwrap_or_default(),
             uplink,
             experimental_batching: experimental_batching.unwrap_or_default(),
+            experimental_internal_listener: experimental_internal_listener.unwrap_or_default(),
+            experimental_config_compatibility: experimental_config_compatibility
+                .unwrap_or_default(),
+            experimental_shadow_plugins: experimental_shadow_plugins.unwrap_or_default(),
+            experimental_validation_rules: experimental_validation_rules.unwrap_or_default(),
         };
 
         configuration.validate()
@@ -470,6 +528,32 @@ impl Configuration {
             );
         }
 
+        if !self.experimental_validation_rules.is_default()
+            && self.experimental_graphql_validation_mode != GraphQLValidationMode::New
+        {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "validation rules require the new GraphQL validation",
+                error: "set experimental_graphql_validation_mode: new in your router yaml configuration, the legacy validation cannot be configured".into(),
+            });
+        }
+
+        if let Some(listen) = self.experimental_internal_listener.listen() {
+            if *listen == self.supergraph.listen {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "the internal listener must not use the GraphQL listen address",
+                    error: format!("set experimental_internal_listener.listen to an address other than {listen}"),
+                });
+            }
+            if self.experimental_internal_listener.tls.is_some()
+                && !matches!(listen, ListenAddr::SocketAddr(_))
+            {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "TLS requires the internal listener to use a socket address",
+                    error: format!("{listen} is not a socket address"),
+                });
+            }
+        }
+
         // PQs.
         if self.persisted_queries.enabled {
             if self.persisted_queries.safelist.enabled && self.apq.enabled {
@@ -605,6 +689,10 @@ pub(crate) struct Supergraph {
     /// Default: false
     pub(crate) introspection: bool,
 
+    /// Types and fields hidden from introspection, as schema coordinates such as `Query.internal`
+    /// or `Internal*`. They can still be queried
+    pub(crate) experimental_introspection_hidden: Vec<String>,
+
     /// Enable reuse of query fragments
     /// Default: depends on the federation version
     #[serde(rename = "experimental_reuse_query_fragments")]
@@ -615,6 +703,59 @@ pub(crate) struct Supergraph {
 
     /// Query planning options
     pub(crate) query_planning: QueryPlanning,
+
+    /// Cache of the parsed and validated documents, shared by all the operations of a document
+    pub(crate) experimental_document_cache: DocumentCache,
+
+    /// How the address of the client is determined, through trusted proxies
+    pub(crate) client_address: ClientAddressConfig,
+
+    /// HTTP status codes of the requests failed by the router itself
+    pub(crate) error_status_codes: ErrorStatusCodes,
+}
+
+/// Parsed document cache configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct DocumentCache {
+    /// Number of documents in the Least Recently Used cache
+    pub(crate) limit: NonZeroUsize,
+    /// Documents larger than this number of bytes are not cached (default: no limit)
+    pub(crate) max_document_bytes: Option<usize>,
+}
+
+impl Default for DocumentCache {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_CACHE_CAPACITY,
+            max_document_bytes: None,
+        }
+    }
+}
+
+/// HTTP status codes of the requests failed by the router itself. Their GraphQL error codes are
+/// `ROUTER_TIMEOUT` and `REQUEST_RATE_LIMITED`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ErrorStatusCodes {
+    /// Status code of the requests that reached the router timeout (default: 504)
+    #[serde(with = "http_serde::status_code")]
+    #[schemars(with = "u16")]
+    pub(crate) router_timeout: StatusCode,
+
+    /// Status code of the requests rejected by a router rate limit (default: 429)
+    #[serde(with = "http_serde::status_code")]
+    #[schemars(with = "u16")]
+    pub(crate) rate_limited: StatusCode,
+}
+
+impl Default for ErrorStatusCodes {
+    fn default() -> Self {
+        Self {
+            router_timeout: StatusCode::GATEWAY_TIMEOUT,
+            rate_limited: StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
 }
 
 fn default_defer_support() -> bool {
@@ -630,7 +771,11 @@ impl Supergraph {
         introspection: Option<bool>,
         defer_support: Option<bool>,
         query_planning: Option<QueryPlanning>,
+        experimental_document_cache: Option<DocumentCache>,
         reuse_query_fragments: Option<bool>,
+        experimental_introspection_hidden: Option<Vec<String>>,
+        client_address: Option<ClientAddressConfig>,
+        error_status_codes: Option<ErrorStatusCodes>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(default_graphql_listen),
@@ -638,7 +783,12 @@ impl Supergraph {
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             defer_support: defer_support.unwrap_or_else(default_defer_support),
             query_planning: query_planning.unwrap_or_default(),
+            experimental_document_cache: experimental_document_cache.unwrap_or_default(),
             reuse_query_fragments,
+            experimental_introspection_hidden: experimental_introspection_hidden
+                .unwrap_or_default(),
+            client_address: client_address.unwrap_or_default(),
+            error_status_codes: error_status_codes.unwrap_or_default(),
         }
     }
 }
@@ -653,7 +803,11 @@ impl Supergraph {
         introspection: Option<bool>,
         defer_support: Option<bool>,
         query_planning: Option<QueryPlanning>,
+        experimental_document_cache: Option<DocumentCache>,
         reuse_query_fragments: Option<bool>,
+        experimental_introspection_hidden: Option<Vec<String>>,
+        client_address: Option<ClientAddressConfig>,
+        error_status_codes: Option<ErrorStatusCodes>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(test_listen),
@@ -661,7 +815,12 @@ impl Supergraph {
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             defer_support: defer_support.unwrap_or_else(default_defer_support),
             query_planning: query_planning.unwrap_or_default(),
+            experimental_document_cache: experimental_document_cache.unwrap_or_default(),
             reuse_query_fragments,
+            experimental_introspection_hidden: experimental_introspection_hidden
+                .unwrap_or_default(),
+            client_address: client_address.unwrap_or_default(),
+            error_status_codes: error_status_codes.unwrap_or_default(),
         }
     }
 }
@@ -764,9 +923,32 @@ pub(crate) struct Limits {
     /// Limit the number of tokens the GraphQL parser processes before aborting.
     pub(crate) parser_max_tokens: usize,
 
+    /// If set, requests with documents larger than this number of bytes are rejected
+    /// before they are parsed, with a HTTP 400 Bad Request response and GraphQL error with
+    /// `"extensions": {"code": "MAX_DOCUMENT_BYTES_LIMIT"}`
+    pub(crate) experimental_max_document_bytes: Option<usize>,
+
+    /// If set, requests with documents nesting selection sets, arguments, lists and objects
+    /// deeper than this maximum are rejected before they are parsed, with a HTTP 400 Bad Request
+    /// response and GraphQL error with `"extensions": {"code": "MAX_NESTING_LIMIT"}`.
+    /// Unlike `max_depth`, this counts the raw nesting of the document, fragments excluded,
+    /// to protect the parser against deeply nested documents.
+    pub(crate) experimental_max_nesting: Option<usize>,
+
     /// Limit the size of incoming HTTP requests read from the network,
     /// to protect against running out of memory. Default: 2000000 (2 MB)
     pub(crate) experimental_http_max_request_bytes: usize,
+
+    /// Parse and validate large documents on a bounded pool of blocking threads,
+    /// so that a burst of huge documents does not stall the async worker threads
+    pub(crate) experimental_parser_offload: ParserOffload,
+
+    /// Approximate memory budget of a request, in bytes. The bodies buffered for the request's
+    /// subgraph fetches are counted against it. Once it is exhausted, the remaining fetches are
+    /// skipped and the request is aborted with a HTTP 500 Internal Server Error response and
+    /// GraphQL error with `"extensions": {"code": "MEMORY_LIMIT_EXCEEDED"}`.
+    /// Default: none
+    pub(crate) experimental_max_request_memory: Option<usize>,
 }
 
 impl Default for Limits {
@@ -780,11 +962,50 @@ impl Default for Limits {
             warn_only: false,
             experimental_http_max_request_bytes: 2_000_000,
             parser_max_tokens: 15_000,
+            experimental_max_document_bytes: None,
+            experimental_max_nesting: None,
 
             // This is `apollo-parser`’s default, which protects against stack overflow
             // but is still very high for "reasonable" queries.
             // https://github.com/apollographql/apollo-rs/blob/apollo-parser%400.7.3/crates/apollo-parser/src/parser/mod.rs#L93-L104
             parser_max_recursion: 500,
+            experimental_parser_offload: ParserOffload::default(),
+            experimental_max_request_memory: None,
+        }
+    }
+}
+
+/// Configuration for parsing and validating documents on a bounded pool of blocking threads
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ParserOffload {
+    /// Documents of at least this size (in bytes) are parsed and validated on the pool.
+    /// Default: none, documents are parsed on the async worker threads
+    pub(crate) min_document_bytes: Option<usize>,
+
+    /// Maximum number of documents parsed at the same time on the pool. Default: 4
+    pub(crate) max_concurrency: NonZeroUsize,
+
+    /// Maximum number of documents waiting for a thread of the pool. When the queue is full,
+    /// requests are rejected with a HTTP 503 Service Unavailable response and GraphQL error with
+    /// `"extensions": {"code": "PARSING_QUEUE_FULL"}`. Default: 64
+    pub(crate) max_queue_depth: usize,
+
+    /// Time budget to parse and validate a document, including the time spent in the queue.
+    /// Requests exceeding it are rejected with a HTTP 503 Service Unavailable response and
+    /// GraphQL error with `"extensions": {"code": "PARSING_TIMEOUT"}`. Default: none
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl Default for ParserOffload {
+    fn default() -> Self {
+        Self {
+            min_document_bytes: None,
+            max_concurrency: NonZeroUsize::new(4).expect("not zero"),
+            max_queue_depth: 64,
+            timeout: None,
         }
     }
 }
@@ -856,6 +1077,9 @@ pub(crate) struct QueryPlanning {
     #[serde(default)]
     pub(crate) warmed_up_queries: Option<usize>,
 
+    /// Warms up the cache on startup and reloads, before accepting traffic
+    pub(crate) experimental_warmup: WarmUp,
+
     /// Sets a limit to the number of generated query plans.
     /// The planning process generates many different query plans as it
     /// explores the graph, and the list can grow large. By using this
@@ -880,6 +1104,34 @@ pub(crate) struct QueryPlanning {
     pub(crate) experimental_paths_limit: Option<u32>,
 }
 
+/// Query plan cache warm up configuration
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct WarmUp {
+    /// Path to a persisted query manifest, or to a JSON array of operations with a `query` and an
+    /// optional `operationName`, planned on startup and reloads
+    pub(crate) manifest: Option<PathBuf>,
+
+    /// Number of queries planned at the same time. Default: 1
+    pub(crate) concurrency: NonZeroUsize,
+
+    /// Time budget of the warm up. When it is exceeded, no more queries are planned and the
+    /// remaining ones will be planned on their first request. Default: none
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "Option<String>", default)]
+    pub(crate) time_budget: Option<Duration>,
+}
+
+impl Default for WarmUp {
+    fn default() -> Self {
+        Self {
+            manifest: None,
+            concurrency: NonZeroUsize::new(1).expect("not zero"),
+            time_budget: None,
+        }
+    }
+}
+
 /// Cache configuration
 #[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields, default)]
@@ -888,6 +1140,29 @@ pub(crate) struct Cache {
     pub(crate) in_memory: InMemoryCache,
     /// Configures and activates the Redis cache
     pub(crate) redis: Option<RedisCache>,
+    /// Configures and activates a custom cache backend, instead of Redis
+    pub(crate) backend: Option<CacheBackendConfig>,
+    /// How entries are written to the distributed cache
+    pub(crate) write: CacheWrite,
+    /// How entries are compressed in the distributed cache
+    pub(crate) compression: CacheCompression,
+    /// Extra dimensions of the cache keys, so that the entries of requests differing by them do
+    /// not collide
+    pub(crate) key: CacheKeyConfig,
+    /// Caches the misses of the distributed cache in memory, to avoid looking them up again
+    pub(crate) negative_caching: Option<NegativeCaching>,
+
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    /// How long the entries expired in memory are still used, while they are refreshed from the
+    /// distributed cache in the background (default: not used)
+    pub(crate) stale_while_revalidate: Option<Duration>,
+
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    /// How long the entries expired in memory are still used if the distributed cache fails
+    /// (default: not used)
+    pub(crate) stale_if_error: Option<Duration>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
@@ -896,16 +1171,65 @@ pub(crate) struct Cache {
 pub(crate) struct InMemoryCache {
     /// Number of entries in the Least Recently Used cache
     pub(crate) limit: NonZeroUsize,
+
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    /// TTL for entries in memory, independent from the TTL of the distributed cache (default: no TTL)
+    pub(crate) ttl: Option<Duration>,
 }
 
 impl Default for InMemoryCache {
     fn default() -> Self {
         Self {
             limit: DEFAULT_CACHE_CAPACITY,
+            ttl: None,
         }
     }
 }
 
+/// How entries are written to the distributed cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CacheWrite {
+    /// Wait for the distributed cache before returning (default)
+    #[default]
+    Through,
+    /// Write to the distributed cache in the background
+    Behind,
+}
+
+/// Extra dimensions of the cache keys
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct CacheKeyConfig {
+    /// Request headers whose values are added to the cache keys, like a feature flag header
+    pub(crate) request_headers: Vec<String>,
+    /// Adds the scopes of the authenticated user, from the `scope` claim of their JWT, to the
+    /// cache keys
+    pub(crate) scopes: bool,
+}
+
+/// Compression of the entries of the distributed cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CacheCompression {
+    /// Entries are stored uncompressed (default)
+    #[default]
+    None,
+    /// Entries are compressed with zstd
+    Zstd,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// Negative caching configuration
+pub(crate) struct NegativeCaching {
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// How long a miss of the distributed cache is remembered
+    pub(crate) ttl: Duration,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 /// Redis cache configuration
@@ -928,6 +1252,19 @@ pub(crate) struct RedisCache {
     pub(crate) tls: Option<TlsClient>,
 }
 
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+/// Custom cache backend configuration
+pub(crate) struct CacheBackendConfig {
+    /// Name of the backend, as registered by a native plugin or by the application embedding the router
+    pub(crate) name: String,
+
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "Option<String>", default)]
+    /// TTL for entries
+    pub(crate) ttl: Option<Duration>,
+}
+
 /// TLS related configuration options.
 #[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
@@ -1199,6 +1536,19 @@ pub(crate) struct HealthCheck {
     /// Optionally set a custom healthcheck path
     /// Defaults to /health
     pub(crate) path: String,
+
+    /// gRPC health checking protocol configuration
+    pub(crate) grpc: GrpcHealthCheck,
+}
+
+/// Configuration options for the gRPC health checking protocol.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub(crate) struct GrpcHealthCheck {
+    /// Set to true to serve the `grpc.health.v1.Health` service on the health check listener.
+    /// The `ready` service (and the empty service name) reports readiness, the `live` service reports liveness.
+    pub(crate) enabled: bool,
 }
 
 fn default_health_check_listen() -> ListenAddr {
@@ -1220,6 +1570,7 @@ impl HealthCheck {
         listen: Option<ListenAddr>,
         enabled: Option<bool>,
         path: Option<String>,
+        grpc: Option<GrpcHealthCheck>,
     ) -> Self {
         let mut path = path.unwrap_or_else(default_health_check_path);
         if !path.starts_with('/') {
@@ -1230,6 +1581,7 @@ impl HealthCheck {
             listen: listen.unwrap_or_else(default_health_check_listen),
             enabled: enabled.unwrap_or_else(default_health_check_enabled),
             path,
+            grpc: grpc.unwrap_or_default(),
         }
     }
 }
@@ -1242,6 +1594,7 @@ impl HealthCheck {
         listen: Option<ListenAddr>,
         enabled: Option<bool>,
         path: Option<String>,
+        grpc: Option<GrpcHealthCheck>,
     ) -> Self {
         let mut path = path.unwrap_or_else(default_health_check_path);
         if !path.starts_with('/') {
@@ -1252,6 +1605,7 @@ impl HealthCheck {
             listen: listen.unwrap_or_else(test_listen),
             enabled: enabled.unwrap_or_else(default_health_check_enabled),
             path,
+            grpc: grpc.unwrap_or_default(),
         }
     }
 }
@@ -6,14 +6,23 @@ use std::fmt::Write;
 
 use itertools::Itertools;
 use jsonschema::error::ValidationErrorKind;
+use jsonschema::paths::JSONPointer;
+use jsonschema::paths::PathChunk;
 use jsonschema::Draft;
 use jsonschema::JSONSchema;
 use schemars::gen::SchemaSettings;
 use schemars::schema::RootSchema;
+use schemars::schema::Schema;
+use schemars::schema::SchemaObject;
+use schemars::schema::SubschemaValidation;
+use schemars::visit;
+use schemars::visit::Visitor;
 use yaml_rust::scanner::Marker;
 
+use super::compatibility::ConfigCompatibility;
 use super::expansion::coerce;
 use super::expansion::Expansion;
+use super::layering::PROFILES;
 use super::plugins;
 use super::yaml;
 use super::Configuration;
@@ -35,13 +44,121 @@ pub(crate) fn generate_config_schema() -> RootSchema {
     // Manually patch up the schema
     // We don't want to allow unknown fields, but serde doesn't work if we put the annotation on Configuration as the struct has a flattened type.
     // It's fine to just add it here.
-    let gen = settings.into_generator();
+    let mut gen = settings.into_generator();
+    let shared = SharedSchemas::new(
+        gen.settings().definitions_path.clone(),
+        crate::plugins::telemetry::shared_schemas(&mut gen),
+    );
     let mut schema = gen.into_root_schema_for::<Configuration>();
     let root = schema.schema.object.as_mut().expect("schema not generated");
     root.additional_properties = Some(Box::new(schemars::schema::Schema::Bool(false)));
+    // The profiles are merged into the configuration before it is validated, so they are only
+    // declared here for editors
+    root.properties.insert(
+        PROFILES.to_string(),
+        serde_json::from_value(serde_json::json!({
+            "description": "Named overrides of the configuration, merged into it when the profile is selected with the `--profile` option",
+            "type": "object",
+            "additionalProperties": {
+                "type": "object"
+            }
+        }))
+        .expect("profiles schema must be valid"),
+    );
+    shared.apply(&mut schema);
     schema
 }
 
+/// Subschemas shared as definitions rather than inlined wherever they are used. The telemetry
+/// selectors would otherwise be repeated in every attribute, condition, instrument and event,
+/// making up most of the schema.
+struct SharedSchemas {
+    definitions_path: String,
+    /// The inlined schema of each definition, to find where it is used
+    schemas: Vec<(String, SchemaObject)>,
+}
+
+impl SharedSchemas {
+    fn new(definitions_path: String, schemas: Vec<(String, Schema)>) -> Self {
+        Self {
+            definitions_path,
+            schemas: schemas
+                .into_iter()
+                .map(|(name, schema)| (name, schema.into_object()))
+                .collect(),
+        }
+    }
+
+    /// Replaces the inlined schemas with references to their definition
+    fn apply(mut self, root: &mut RootSchema) {
+        visit::visit_root_schema(&mut self, root);
+        for (name, schema) in self.schemas.clone() {
+            let mut definition = schema;
+            // The subschemas of a definition are shared too, but not the definition itself
+            visit::visit_schema_object(&mut self, &mut definition);
+            root.definitions.insert(name, definition.into());
+        }
+    }
+
+    /// The reference to the definition of an inlined schema, keeping the description of where it
+    /// is used
+    fn reference(&self, schema: &SchemaObject) -> Option<SchemaObject> {
+        let (name, shared) = self
+            .schemas
+            .iter()
+            .find(|(_, shared)| same_validation(schema, shared))?;
+        let reference = SchemaObject::new_ref(format!("{}{name}", self.definitions_path));
+        let nullable = schema.extensions.get("nullable");
+        let metadata =
+            |schema: &SchemaObject| schema.metadata.as_deref().cloned().unwrap_or_default();
+        if nullable.is_none() && metadata(schema) == metadata(shared) {
+            return Some(reference);
+        }
+        Some(SchemaObject {
+            metadata: schema.metadata.clone(),
+            subschemas: Some(Box::new(SubschemaValidation {
+                all_of: Some(vec![reference.into()]),
+                ..Default::default()
+            })),
+            extensions: nullable
+                .map(|nullable| ("nullable".to_string(), nullable.clone()))
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        })
+    }
+}
+
+impl Visitor for SharedSchemas {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        match self.reference(schema) {
+            Some(reference) => *schema = reference,
+            None => visit::visit_schema_object(self, schema),
+        }
+    }
+}
+
+/// Whether two schemas validate the same values, whatever their description and nullability
+fn same_validation(a: &SchemaObject, b: &SchemaObject) -> bool {
+    fn extensions(schema: &SchemaObject) -> impl Iterator<Item = (&String, &serde_json::Value)> {
+        schema
+            .extensions
+            .iter()
+            .filter(|(key, _)| *key != "nullable")
+    }
+    a.instance_type == b.instance_type
+        && a.format == b.format
+        && a.enum_values == b.enum_values
+        && a.const_value == b.const_value
+        && a.subschemas == b.subschemas
+        && a.number == b.number
+        && a.string == b.string
+        && a.array == b.array
+        && a.object == b.object
+        && a.reference == b.reference
+        && extensions(a).eq(extensions(b))
+}
+
 #[derive(Eq, PartialEq)]
 pub(crate) enum Mode {
     Upgrade,
@@ -82,7 +199,10 @@ pub(crate) fn validate_yaml_configuration(
             error: e.to_string(),
         }
     })?;
-    let schema = serde_json::to_value(generate_config_schema()).map_err(|e| {
+    // The selected profile overrides the configuration before anything else, so that they are
+    // validated together
+    super::layering::apply_profile(&mut yaml, expansion.profile())?;
+    let schema_json = serde_json::to_value(generate_config_schema()).map_err(|e| {
         ConfigurationError::InvalidConfiguration {
             message: "failed to parse schema",
             error: e.to_string(),
@@ -90,15 +210,17 @@ pub(crate) fn validate_yaml_configuration(
     })?;
     let schema = JSONSchema::options()
         .with_draft(Draft::Draft7)
-        .compile(&schema)
+        .compile(&schema_json)
         .map_err(|e| ConfigurationError::InvalidConfiguration {
             message: "failed to compile schema",
             error: e.to_string(),
         })?;
 
+    let compatibility = ConfigCompatibility::from_config(&yaml);
     if migration == Mode::Upgrade {
         let upgraded = upgrade_configuration(&yaml, true)?;
-        let expanded_yaml = expansion.expand(&upgraded)?;
+        let mut expanded_yaml = expansion.expand(&upgraded)?;
+        compatibility.remove_unknown_fields(&schema, &schema_json, &mut expanded_yaml);
         if schema.validate(&expanded_yaml).is_ok() {
             yaml = upgraded;
         } else {
@@ -106,8 +228,33 @@ pub(crate) fn validate_yaml_configuration(
         }
     }
 
-    let expanded_yaml = expansion.expand(&yaml)?;
+    let mut expanded_yaml = expansion.expand(&yaml)?;
     let parsed_yaml = super::yaml::parse(raw_yaml)?;
+    // Unknown fields of the lenient sections are removed rather than failing the validation
+    for field in compatibility.remove_unknown_fields(&schema, &schema_json, &mut expanded_yaml) {
+        let line = match get_element(&parsed_yaml, expansion.profile(), &field.parent) {
+            Some(yaml::Value::Mapping(_, map, _)) => map
+                .iter()
+                .find(|(label, _)| label.name == field.name)
+                .and_then(|(label, _)| label.marker.as_ref())
+                .map(|marker| marker.line()),
+            _ => None,
+        };
+        let path = field.path();
+        match &field.suggestion {
+            Some(suggestion) => tracing::warn!(
+                config.path = %path,
+                config.line = line,
+                config.suggestion = %suggestion,
+                "ignoring the unknown configuration field '{path}', did you mean '{suggestion}'?"
+            ),
+            None => tracing::warn!(
+                config.path = %path,
+                config.line = line,
+                "ignoring the unknown configuration field '{path}'"
+            ),
+        }
+    }
     if let Err(errors_it) = schema.validate(&expanded_yaml) {
         // Validation failed, translate the errors into something nice for the user
         // We have to reparse the yaml to get the line number information for each error.
@@ -116,7 +263,8 @@ pub(crate) fn validate_yaml_configuration(
         let mut errors = String::new();
 
         for (idx, mut e) in errors_it.enumerate() {
-            if let Some(element) = parsed_yaml.get_element(&e.instance_path) {
+            if let Some(element) = get_element(&parsed_yaml, expansion.profile(), &e.instance_path)
+            {
                 match element {
                     yaml::Value::String(value, marker) => {
                         let start_marker = marker;
@@ -263,6 +411,27 @@ pub(crate) fn validate_yaml_configuration(
     Ok(config)
 }
 
+/// The element of the raw configuration at a path, looked up first in the selected profile, as its
+/// values take precedence
+fn get_element<'a>(
+    parsed_yaml: &'a yaml::MarkedYaml,
+    profile: Option<&str>,
+    path: &JSONPointer,
+) -> Option<&'a yaml::Value> {
+    profile
+        .and_then(|profile| {
+            let profile_path = [
+                PathChunk::Property(PROFILES.into()),
+                PathChunk::Property(profile.into()),
+            ]
+            .into_iter()
+            .chain(path.iter().cloned())
+            .collect::<Vec<_>>();
+            parsed_yaml.get_element(&JSONPointer::from(profile_path.as_slice()))
+        })
+        .or_else(|| parsed_yaml.get_element(path))
+}
+
 fn context_lines(
     yaml_split_by_lines: &[&str],
     start_marker: &Marker,
@@ -156,6 +156,55 @@ fn bad_graphql_path_configuration_with_wildcard_as_prefix() {
     assert_eq!(error.to_string(), String::from("invalid 'server.graphql_path' configuration: '/*/test' is invalid, if you need to set a path like '/*/graphql' then specify it as a path parameter with a name, for example '/:my_project_key/graphql'"));
 }
 
+#[test]
+fn validation_rules_require_new_validation() {
+    let rules = crate::spec::validation_rules::ListenerValidationRules {
+        listeners: [(
+            "127.0.0.1:4000".parse().unwrap(),
+            crate::spec::validation_rules::ValidationRules {
+                unknown_directives: crate::spec::validation_rules::RuleAction::Warn,
+                ..Default::default()
+            },
+        )]
+        .into_iter()
+        .collect(),
+        ..Default::default()
+    };
+    let error = Configuration::fake_builder()
+        .experimental_validation_rules(rules.clone())
+        .build()
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("validation rules require the new GraphQL validation"));
+
+    Configuration::fake_builder()
+        .experimental_validation_rules(rules)
+        .graphql_validation_mode(GraphQLValidationMode::New)
+        .build()
+        .unwrap();
+}
+
+#[test]
+fn internal_listener_cannot_use_the_graphql_listener() {
+    let error = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .listen(ListenAddr::SocketAddr("127.0.0.1:4000".parse().unwrap()))
+                .build(),
+        )
+        .experimental_internal_listener(InternalListener {
+            enabled: true,
+            listen: ListenAddr::SocketAddr("127.0.0.1:4000".parse().unwrap()),
+            tls: None,
+        })
+        .build()
+        .unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("the internal listener must not use the GraphQL listen address"));
+}
+
 #[test]
 fn unknown_fields() {
     let error = validate_yaml_configuration(
@@ -214,6 +263,89 @@ unknown:
     );
 }
 
+#[test]
+fn unknown_fields_of_lenient_sections() {
+    let config = validate_yaml_configuration(
+        r#"
+experimental_config_compatibility:
+  unknown_fields: warn
+  sections: [telemetry, future_plugin]
+telemetry:
+  exporters:
+    logging:
+      stdout:
+        enabled: true
+        future_option: true
+future_plugin:
+  enabled: true
+  "#,
+        Expansion::default().unwrap(),
+        Mode::NoUpgrade,
+    )
+    .expect("unknown fields of lenient sections should be ignored");
+    let validated_yaml = config.validated_yaml.unwrap();
+    assert_eq!(
+        validated_yaml["telemetry"]["exporters"]["logging"]["stdout"],
+        json!({ "enabled": true })
+    );
+    assert!(validated_yaml.get("future_plugin").is_none());
+
+    let error = validate_yaml_configuration(
+        r#"
+experimental_config_compatibility:
+  unknown_fields: warn
+  sections: [telemetry]
+supergraph:
+  future_option: true
+  "#,
+        Expansion::default().unwrap(),
+        Mode::NoUpgrade,
+    )
+    .expect_err("unknown fields of other sections should be rejected");
+    assert!(error
+        .to_string()
+        .contains("Additional properties are not allowed ('future_option' was unexpected)"));
+}
+
+#[test]
+fn profiles() {
+    let config = r#"
+supergraph:
+  introspection: false
+homepage:
+  enabled: false
+profiles:
+  dev:
+    supergraph:
+      introspection: true
+    homepage: ~
+  production:
+    supergraph:
+      unknown: true
+  "#;
+    let dev = validate_yaml_configuration(
+        config,
+        Expansion::builder().profile("dev").build(),
+        Mode::NoUpgrade,
+    )
+    .expect("the dev profile should be valid");
+    assert!(dev.supergraph.introspection);
+    assert!(dev.homepage.enabled);
+
+    let base = validate_yaml_configuration(config, Expansion::builder().build(), Mode::NoUpgrade)
+        .expect("profiles should be ignored if none is selected");
+    assert!(!base.supergraph.introspection);
+
+    let error = validate_yaml_configuration(
+        config,
+        Expansion::builder().profile("production").build(),
+        Mode::NoUpgrade,
+    )
+    .expect_err("the production profile should be invalid");
+    // The error points to the profile
+    assert!(error.to_string().contains("at line 13"));
+}
+
 #[test]
 fn empty_config() {
     validate_yaml_configuration(
@@ -400,6 +532,7 @@ fn validate_project_config_files() {
     std::env::set_var("ZIPKIN_HOST", "http://example.com");
     std::env::set_var("TEST_CONFIG_ENDPOINT", "http://example.com");
     std::env::set_var("TEST_CONFIG_COLLECTOR_ENDPOINT", "http://example.com");
+    std::env::set_var("SCHEMA_WEBHOOK_SECRET", "secret");
     std::env::set_var("PARSER_MAX_RECURSION", "500");
 
     #[cfg(not(unix))]
@@ -950,7 +1083,12 @@ fn it_defaults_health_check_configuration() {
 #[test]
 fn it_sets_custom_health_check_path() {
     let conf = Configuration::builder()
-        .health_check(HealthCheck::new(None, None, Some("/healthz".to_string())))
+        .health_check(HealthCheck::new(
+            None,
+            None,
+            Some("/healthz".to_string()),
+            None,
+        ))
         .build()
         .unwrap();
 
@@ -961,7 +1099,12 @@ fn it_sets_custom_health_check_path() {
 fn it_adds_slash_to_custom_health_check_path_if_missing() {
     let conf = Configuration::builder()
         // NB the missing `/`
-        .health_check(HealthCheck::new(None, None, Some("healthz".to_string())))
+        .health_check(HealthCheck::new(
+            None,
+            None,
+            Some("healthz".to_string()),
+            None,
+        ))
         .build()
         .unwrap();
 
@@ -11,6 +11,8 @@ use tracing_core::Level;
 
 use crate::error::ConfigurationError;
 
+mod telemetry;
+
 #[derive(RustEmbed)]
 #[folder = "src/configuration/migrations"]
 struct Asset;
@@ -188,16 +190,25 @@ fn apply_migration(config: &Value, migration: &Migration) -> Result<Value, Confi
     Ok(new_config)
 }
 
-pub(crate) fn generate_upgrade(config: &str, diff: bool) -> Result<String, ConfigurationError> {
+pub(crate) fn generate_upgrade(
+    config: &str,
+    diff: bool,
+    migrate_telemetry: bool,
+) -> Result<String, ConfigurationError> {
     let parsed_config =
         serde_yaml::from_str(config).map_err(|e| ConfigurationError::MigrationFailure {
             error: e.to_string(),
         })?;
-    let upgraded_config = upgrade_configuration(&parsed_config, true).map_err(|e| {
+    let mut upgraded_config = upgrade_configuration(&parsed_config, true).map_err(|e| {
         ConfigurationError::MigrationFailure {
             error: e.to_string(),
         }
     })?;
+    if migrate_telemetry {
+        for note in telemetry::migrate_legacy_telemetry(&mut upgraded_config) {
+            tracing::warn!("could not migrate {note}");
+        }
+    }
     let upgraded_config = serde_yaml::to_string(&upgraded_config).map_err(|e| {
         ConfigurationError::MigrationFailure {
             error: e.to_string(),
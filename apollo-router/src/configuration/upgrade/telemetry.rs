@@ -0,0 +1,707 @@
+//! Rewrites the legacy telemetry configuration into the selectors format, for
+//! `router config upgrade --migrate-telemetry`:
+//! * the legacy metrics attributes of `telemetry.exporters.metrics.common.attributes` move to the
+//!   custom instruments of their service, without adding instruments
+//! * the spans of the `deprecated` mode switch to the `spec_compliant` mode, keeping the legacy
+//!   span attributes as standard attributes or selectors
+//!
+//! Unlike the migrations applied whenever the configuration is loaded, this changes the telemetry
+//! of the router. Options without an equivalent are left in place and reported.
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+
+const LEGACY_ATTRIBUTES: &str = "telemetry.exporters.metrics.common.attributes";
+const SPANS: &str = "telemetry.instrumentation.spans";
+const DEFAULT_CLIENT_NAME_HEADER: &str = "apollographql-client-name";
+const DEFAULT_CLIENT_VERSION_HEADER: &str = "apollographql-client-version";
+
+/// The attribute name and selector of a legacy option, or why it has no equivalent
+type Migration = Result<(String, Value), &'static str>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Supergraph,
+    Subgraph,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Supergraph => "supergraph",
+            Stage::Subgraph => "subgraph",
+        }
+    }
+
+    /// The names of the custom instruments of the service, which record the migrated attributes
+    fn custom_instruments(self, config: &Value) -> Vec<String> {
+        config
+            .pointer(&format!(
+                "/telemetry/instrumentation/instruments/{}",
+                self.name()
+            ))
+            .and_then(Value::as_object)
+            .map(|instruments| {
+                instruments
+                    .iter()
+                    // Standard instruments are booleans or only have attributes
+                    .filter(|(_, instrument)| instrument.get("type").is_some())
+                    .map(|(name, _)| name.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn header(self, item: &Value, response: bool) -> Migration {
+        let Some(named) = item.get("named").and_then(Value::as_str) else {
+            return Err("headers matching a regular expression have no equivalent selector");
+        };
+        let selector = match (self, response) {
+            (Stage::Supergraph, false) => "request_header",
+            (Stage::Supergraph, true) => "response_header",
+            (Stage::Subgraph, false) => "subgraph_request_header",
+            (Stage::Subgraph, true) => "subgraph_response_header",
+        };
+        // Header selectors only have string defaults
+        let default = item.get("default").map(|default| match default {
+            Value::String(default) => Value::String(default.clone()),
+            default => Value::String(default.to_string()),
+        });
+        Ok((
+            rename(item, named),
+            with_default(json!({ selector: named }), default),
+        ))
+    }
+
+    fn request_body(self, item: &Value) -> Migration {
+        let (name, path) = body(item)?;
+        let variable = match simple_path(path).as_deref() {
+            Some(["variables", variable]) => variable.to_string(),
+            _ => {
+                return Err(
+                    "only `.variables.<name>` request body paths have an equivalent selector",
+                )
+            }
+        };
+        let selector = match self {
+            Stage::Supergraph => "query_variable",
+            Stage::Subgraph => "subgraph_query_variable",
+        };
+        Ok((
+            name,
+            with_default(json!({ selector: variable }), item.get("default").cloned()),
+        ))
+    }
+
+    fn response_body(self, item: &Value) -> Migration {
+        let (name, path) = body(item)?;
+        match self {
+            Stage::Supergraph => Err("supergraph response body paths have no equivalent selector"),
+            Stage::Subgraph => Ok((
+                name,
+                with_default(
+                    json!({ "subgraph_response_body": path }),
+                    item.get("default").cloned(),
+                ),
+            )),
+        }
+    }
+
+    fn context(self, item: &Value) -> Migration {
+        let named = item
+            .get("named")
+            .and_then(Value::as_str)
+            .ok_or("malformed context attribute")?;
+        Ok((
+            rename(item, named),
+            with_default(
+                json!({ "response_context": named }),
+                item.get("default").cloned(),
+            ),
+        ))
+    }
+
+    fn error_extension(self, item: &Value) -> Migration {
+        let (name, path) = body(item)?;
+        let path = simple_path(path)
+            .ok_or("only dot separated error extension paths have an equivalent selector")?;
+        Ok((
+            name,
+            with_default(
+                json!({ "error_extension": path.join(".") }),
+                item.get("default").cloned(),
+            ),
+        ))
+    }
+}
+
+/// Migrates the legacy telemetry configuration, and returns the options that could not be migrated
+pub(crate) fn migrate_legacy_telemetry(config: &mut Value) -> Vec<String> {
+    let mut notes = migrate_legacy_attributes(config);
+    notes.extend(migrate_legacy_spans(config));
+    notes
+}
+
+/// Moves the legacy metrics attributes to the custom instruments of their service
+fn migrate_legacy_attributes(config: &mut Value) -> Vec<String> {
+    let mut notes = Vec::new();
+    let mut migrated = Vec::new();
+    let stages = [
+        (Stage::Supergraph, "/supergraph"),
+        (Stage::Subgraph, "/subgraph/all"),
+    ]
+    .map(|(stage, pointer)| (stage, pointer, stage.custom_instruments(config)));
+    let pointer = format!("/{}", LEGACY_ATTRIBUTES.replace('.', "/"));
+    let Some(legacy) = config.pointer_mut(&pointer) else {
+        return notes;
+    };
+
+    for (stage, pointer, names) in stages {
+        let Some(conf) = legacy.pointer_mut(pointer) else {
+            continue;
+        };
+        let path = format!("{LEGACY_ATTRIBUTES}{}", pointer.replace('/', "."));
+        // Instruments are not added, as they would be new metrics
+        if names.is_empty() {
+            notes.push(format!(
+                "{path}: there is no custom instrument of the {} service to record the attributes on",
+                stage.name()
+            ));
+            continue;
+        }
+        let attributes = migrate(conf, stage, &path, &mut notes);
+        migrated.push((stage, names, path, attributes));
+    }
+    if let Some(Value::Object(subgraphs)) = legacy.pointer("/subgraph/subgraphs") {
+        for name in subgraphs.keys() {
+            notes.push(format!(
+                "{LEGACY_ATTRIBUTES}.subgraph.subgraphs.{name}: the attributes of a single subgraph have no equivalent, use a custom instrument with a condition on the subgraph name"
+            ));
+        }
+    }
+    remove_empty(legacy);
+    if legacy.as_object().map_or(false, Map::is_empty) {
+        if let Some(Value::Object(common)) =
+            config.pointer_mut("/telemetry/exporters/metrics/common")
+        {
+            common.remove("attributes");
+        }
+    }
+
+    for (stage, names, path, attributes) in migrated {
+        for name in names {
+            let instrument_attributes = entry(
+                config,
+                &[
+                    "telemetry",
+                    "instrumentation",
+                    "instruments",
+                    stage.name(),
+                    &name,
+                    "attributes",
+                ],
+            );
+            for (key, selector) in &attributes {
+                if instrument_attributes.contains_key(key) {
+                    notes.push(format!(
+                        "{path}: telemetry.instrumentation.instruments.{}.{name} already has a `{key}` attribute",
+                        stage.name()
+                    ));
+                } else {
+                    instrument_attributes.insert(key.clone(), selector.clone());
+                }
+            }
+        }
+    }
+    notes
+}
+
+/// Switches the spans of the legacy `deprecated` mode to the `spec_compliant` mode, with the
+/// legacy span attributes that have an equivalent
+fn migrate_legacy_spans(config: &mut Value) -> Vec<String> {
+    if config.pointer("/telemetry/instrumentation/spans/mode") == Some(&json!("spec_compliant")) {
+        return Vec::new();
+    }
+    let header = |name: &str, default: &str| {
+        config
+            .pointer(&format!("/telemetry/apollo/{name}"))
+            .and_then(Value::as_str)
+            .unwrap_or(default)
+            .to_string()
+    };
+    let client_name_header = header("client_name_header", DEFAULT_CLIENT_NAME_HEADER);
+    let client_version_header = header("client_version_header", DEFAULT_CLIENT_VERSION_HEADER);
+    let spans = [
+        (
+            "router",
+            vec![
+                ("trace_id", json!(true)),
+                ("network.protocol.version", json!(true)),
+                (
+                    "client.name",
+                    json!({ "request_header": client_name_header }),
+                ),
+                (
+                    "client.version",
+                    json!({ "request_header": client_version_header }),
+                ),
+            ],
+        ),
+        (
+            "supergraph",
+            vec![
+                ("graphql.document", json!(true)),
+                ("graphql.operation.name", json!(true)),
+            ],
+        ),
+        (
+            "subgraph",
+            vec![
+                ("subgraph.name", json!(true)),
+                ("graphql.document", json!({ "subgraph_query": "string" })),
+                (
+                    "graphql.operation.name",
+                    json!({ "subgraph_operation_name": "string" }),
+                ),
+            ],
+        ),
+    ];
+    for (span, legacy_attributes) in spans {
+        let attributes = entry(
+            config,
+            &["telemetry", "instrumentation", "spans", span, "attributes"],
+        );
+        // The attributes configured for the span are kept
+        for (key, attribute) in legacy_attributes {
+            attributes.entry(key).or_insert(attribute);
+        }
+    }
+    entry(config, &["telemetry", "instrumentation", "spans"])
+        .insert("mode".to_string(), json!("spec_compliant"));
+
+    vec![
+        format!("{SPANS}: the `request` span has no equivalent, the `router` span is the root span of the traces"),
+        format!("{SPANS}.router: the `http.method` and `http.flavor` attributes are replaced by the standard `http.request.method` and `network.protocol.version` attributes"),
+        format!("{SPANS}.subgraph: the `apollo.subgraph.name` attribute is replaced by the standard `subgraph.name` attribute"),
+    ]
+}
+
+/// Moves the attributes with an equivalent selector out of a legacy configuration
+fn migrate(
+    conf: &mut Value,
+    stage: Stage,
+    path: &str,
+    notes: &mut Vec<String>,
+) -> Map<String, Value> {
+    let mut attributes = Map::new();
+    let mut list = |pointer: &str, map: &dyn Fn(&Value) -> Migration| {
+        let Some(Value::Array(items)) = conf.pointer_mut(pointer) else {
+            return;
+        };
+        let display = format!("{path}{}", pointer.replace('/', "."));
+        let mut index = 0;
+        items.retain(|item| {
+            let retained = match map(item) {
+                Ok((name, selector)) => {
+                    attributes.insert(name, selector);
+                    false
+                }
+                Err(reason) => {
+                    notes.push(format!("{display}[{index}]: {reason}"));
+                    true
+                }
+            };
+            index += 1;
+            retained
+        });
+    };
+    list("/static", &|_| {
+        Err("static attributes have no equivalent selector")
+    });
+    list("/request/header", &|item| stage.header(item, false));
+    list("/request/body", &|item| stage.request_body(item));
+    list("/response/header", &|item| stage.header(item, true));
+    list("/response/body", &|item| stage.response_body(item));
+    list("/context", &|item| stage.context(item));
+    list("/errors/extensions", &|item| stage.error_extension(item));
+
+    if let Some(Value::Object(errors)) = conf.get_mut("errors") {
+        match errors.get("include_messages") {
+            Some(Value::Bool(true)) => notes.push(format!(
+                "{path}.errors.include_messages: error messages have no equivalent selector"
+            )),
+            Some(_) => {
+                errors.remove("include_messages");
+            }
+            None => {}
+        }
+    }
+    attributes
+}
+
+fn rename(item: &Value, named: &str) -> String {
+    item.get("rename")
+        .and_then(Value::as_str)
+        .unwrap_or(named)
+        .to_string()
+}
+
+fn body(item: &Value) -> Result<(String, &str), &'static str> {
+    match (
+        item.get("name").and_then(Value::as_str),
+        item.get("path").and_then(Value::as_str),
+    ) {
+        (Some(name), Some(path)) => Ok((name.to_string(), path)),
+        _ => Err("malformed body attribute"),
+    }
+}
+
+/// The segments of a path such as `.variables.id`, if it only selects object fields
+fn simple_path(path: &str) -> Option<Vec<&str>> {
+    let segments: Vec<&str> = path.strip_prefix('.')?.split('.').collect();
+    segments
+        .iter()
+        .all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .then_some(segments)
+}
+
+fn with_default(mut selector: Value, default: Option<Value>) -> Value {
+    if let (Some(default), Value::Object(selector)) = (default, &mut selector) {
+        selector.insert("default".to_string(), default);
+    }
+    selector
+}
+
+/// The object at a path, created if needed
+fn entry<'a>(config: &'a mut Value, path: &[&str]) -> &'a mut Map<String, Value> {
+    let mut value = config;
+    for key in path {
+        if !value.is_object() {
+            *value = Value::Object(Map::new());
+        }
+        value = value
+            .as_object_mut()
+            .expect("must be an object")
+            .entry(*key)
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    if !value.is_object() {
+        *value = Value::Object(Map::new());
+    }
+    value.as_object_mut().expect("must be an object")
+}
+
+/// Removes the empty objects and arrays left by the migration
+fn remove_empty(value: &mut Value) {
+    if let Value::Object(object) = value {
+        for child in object.values_mut() {
+            remove_empty(child);
+        }
+        object.retain(|_, child| match child {
+            Value::Object(child) => !child.is_empty(),
+            Value::Array(child) => !child.is_empty(),
+            _ => true,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::configuration::expansion::Expansion;
+    use crate::configuration::schema::validate_yaml_configuration;
+    use crate::configuration::schema::Mode;
+
+    fn assert_valid(config: &Value) {
+        validate_yaml_configuration(
+            &serde_yaml::to_string(config).unwrap(),
+            Expansion::builder().build(),
+            Mode::NoUpgrade,
+        )
+        .expect("the migrated configuration must be valid");
+    }
+
+    #[test]
+    fn it_migrates_the_legacy_metrics_attributes() {
+        let mut config = json!({
+            "telemetry": {
+                "exporters": {
+                    "metrics": {
+                        "common": {
+                            "service_name": "router",
+                            "attributes": {
+                                "supergraph": {
+                                    "static": [{ "name": "env", "value": "prod" }],
+                                    "request": {
+                                        "header": [
+                                            { "named": "x-tenant", "rename": "tenant", "default": "unknown" },
+                                            { "matching": "x-.*" }
+                                        ],
+                                        "body": [{ "path": ".variables.id", "name": "id" }]
+                                    },
+                                    "context": [{ "named": "client_tier" }],
+                                    "errors": {
+                                        "include_messages": false,
+                                        "extensions": [{ "path": ".code", "name": "error_code" }]
+                                    }
+                                },
+                                "subgraph": {
+                                    "all": {
+                                        "response": {
+                                            "body": [{ "path": ".data.me.id", "name": "me" }]
+                                        }
+                                    },
+                                    "subgraphs": {
+                                        "accounts": {
+                                            "request": { "header": [{ "named": "x-account" }] }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+                "instrumentation": {
+                    "spans": { "mode": "spec_compliant" },
+                    "instruments": {
+                        "supergraph": {
+                            "acme.requests": {
+                                "type": "counter",
+                                "value": "unit",
+                                "unit": "request",
+                                "description": "Supergraph requests"
+                            }
+                        },
+                        "subgraph": {
+                            "http.client.request.duration": true,
+                            "acme.subgraph.duration": {
+                                "type": "histogram",
+                                "value": "duration",
+                                "unit": "s",
+                                "description": "Subgraph request duration",
+                                "attributes": { "subgraph.name": true }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        let notes = migrate_legacy_telemetry(&mut config);
+
+        assert_eq!(
+            config,
+            json!({
+                "telemetry": {
+                    "exporters": {
+                        "metrics": {
+                            "common": {
+                                "service_name": "router",
+                                "attributes": {
+                                    "supergraph": {
+                                        "static": [{ "name": "env", "value": "prod" }],
+                                        "request": {
+                                            "header": [{ "matching": "x-.*" }]
+                                        }
+                                    },
+                                    "subgraph": {
+                                        "subgraphs": {
+                                            "accounts": {
+                                                "request": { "header": [{ "named": "x-account" }] }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    },
+                    "instrumentation": {
+                        "spans": { "mode": "spec_compliant" },
+                        "instruments": {
+                            "supergraph": {
+                                "acme.requests": {
+                                    "type": "counter",
+                                    "value": "unit",
+                                    "unit": "request",
+                                    "description": "Supergraph requests",
+                                    "attributes": {
+                                        "tenant": { "request_header": "x-tenant", "default": "unknown" },
+                                        "id": { "query_variable": "id" },
+                                        "client_tier": { "response_context": "client_tier" },
+                                        "error_code": { "error_extension": "code" }
+                                    }
+                                }
+                            },
+                            "subgraph": {
+                                "http.client.request.duration": true,
+                                "acme.subgraph.duration": {
+                                    "type": "histogram",
+                                    "value": "duration",
+                                    "unit": "s",
+                                    "description": "Subgraph request duration",
+                                    "attributes": {
+                                        "subgraph.name": true,
+                                        "me": { "subgraph_response_body": ".data.me.id" }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            })
+        );
+        assert_eq!(
+            notes,
+            vec![
+                "telemetry.exporters.metrics.common.attributes.supergraph.static[0]: static attributes have no equivalent selector",
+                "telemetry.exporters.metrics.common.attributes.supergraph.request.header[1]: headers matching a regular expression have no equivalent selector",
+                "telemetry.exporters.metrics.common.attributes.subgraph.subgraphs.accounts: the attributes of a single subgraph have no equivalent, use a custom instrument with a condition on the subgraph name",
+            ]
+        );
+        assert_valid(&config);
+    }
+
+    #[test]
+    fn it_does_not_add_instruments() {
+        let legacy = json!({
+            "subgraph": {
+                "all": {
+                    "request": { "header": [{ "named": "x-tenant" }] }
+                }
+            }
+        });
+        let mut config = json!({
+            "telemetry": {
+                "exporters": { "metrics": { "common": { "attributes": legacy } } },
+                "instrumentation": {
+                    "spans": { "mode": "spec_compliant" },
+                    "instruments": { "subgraph": { "http.client.request.duration": true } }
+                }
+            }
+        });
+        assert_eq!(
+            migrate_legacy_telemetry(&mut config),
+            vec!["telemetry.exporters.metrics.common.attributes.subgraph.all: there is no custom instrument of the subgraph service to record the attributes on"]
+        );
+        assert_eq!(
+            config["telemetry"]["exporters"]["metrics"]["common"]["attributes"],
+            legacy
+        );
+        assert_eq!(
+            config["telemetry"]["instrumentation"]["instruments"],
+            json!({ "subgraph": { "http.client.request.duration": true } })
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_attributes_of_existing_instruments() {
+        let mut config = json!({
+            "telemetry": {
+                "exporters": {
+                    "metrics": {
+                        "common": {
+                            "attributes": {
+                                "subgraph": {
+                                    "all": {
+                                        "request": { "header": [{ "named": "x-tenant" }] }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                },
+                "instrumentation": {
+                    "spans": { "mode": "spec_compliant" },
+                    "instruments": {
+                        "subgraph": {
+                            "acme.subgraph.requests": {
+                                "type": "counter",
+                                "value": "unit",
+                                "unit": "request",
+                                "description": "Subgraph requests",
+                                "attributes": { "x-tenant": { "subgraph_request_header": "x-org" } }
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        assert_eq!(
+            migrate_legacy_telemetry(&mut config),
+            vec!["telemetry.exporters.metrics.common.attributes.subgraph.all: telemetry.instrumentation.instruments.subgraph.acme.subgraph.requests already has a `x-tenant` attribute"]
+        );
+        // The fully migrated legacy attributes are removed
+        assert_eq!(
+            config["telemetry"]["exporters"]["metrics"]["common"],
+            json!({})
+        );
+        assert_eq!(
+            config["telemetry"]["instrumentation"]["instruments"]["subgraph"]
+                ["acme.subgraph.requests"]["attributes"],
+            json!({ "x-tenant": { "subgraph_request_header": "x-org" } })
+        );
+    }
+
+    #[test]
+    fn it_migrates_the_legacy_spans() {
+        let mut config = json!({
+            "telemetry": {
+                "apollo": { "client_name_header": "x-client" },
+                "instrumentation": {
+                    "spans": {
+                        "router": {
+                            "attributes": { "client.version": { "request_header": "x-version" } }
+                        }
+                    }
+                }
+            }
+        });
+        let notes = migrate_legacy_telemetry(&mut config);
+
+        assert_eq!(
+            config["telemetry"]["instrumentation"]["spans"],
+            json!({
+                "mode": "spec_compliant",
+                "router": {
+                    "attributes": {
+                        "client.version": { "request_header": "x-version" },
+                        "trace_id": true,
+                        "network.protocol.version": true,
+                        "client.name": { "request_header": "x-client" }
+                    }
+                },
+                "supergraph": {
+                    "attributes": {
+                        "graphql.document": true,
+                        "graphql.operation.name": true
+                    }
+                },
+                "subgraph": {
+                    "attributes": {
+                        "subgraph.name": true,
+                        "graphql.document": { "subgraph_query": "string" },
+                        "graphql.operation.name": { "subgraph_operation_name": "string" }
+                    }
+                }
+            })
+        );
+        assert_eq!(
+            notes,
+            vec![
+                "telemetry.instrumentation.spans: the `request` span has no equivalent, the `router` span is the root span of the traces",
+                "telemetry.instrumentation.spans.router: the `http.method` and `http.flavor` attributes are replaced by the standard `http.request.method` and `network.protocol.version` attributes",
+                "telemetry.instrumentation.spans.subgraph: the `apollo.subgraph.name` attribute is replaced by the standard `subgraph.name` attribute",
+            ]
+        );
+        assert_valid(&config);
+
+        // The spec compliant spans are already migrated
+        let migrated = config.clone();
+        assert!(migrate_legacy_telemetry(&mut config).is_empty());
+        assert_eq!(config, migrated);
+    }
+}
@@ -83,6 +83,57 @@ pub(crate) enum FetchError {
         /// The reason the fetch failed.
         reason: String,
     },
+    /// response from '{service}' exceeded the maximum size of {limit} bytes
+    SubrequestResponseTooLarge {
+        /// The service that returned the response.
+        service: String,
+
+        /// The maximum response size, in bytes.
+        limit: usize,
+    },
+
+    /// the request exceeded its memory budget of {limit} bytes while fetching from '{service}'
+    SubrequestMemoryLimitExceeded {
+        /// The service that was being fetched.
+        service: String,
+
+        /// The memory budget of the request, in bytes.
+        limit: usize,
+    },
+
+    /// response body from '{service}' stalled: no data received for {timeout_ms}ms
+    SubrequestBodyReadTimeout {
+        /// The service that returned the response.
+        service: String,
+
+        /// The body read timeout, in milliseconds.
+        timeout_ms: u64,
+    },
+
+    /// request to '{service}' timed out
+    SubrequestTimeout {
+        /// The service that did not respond in time.
+        service: String,
+    },
+
+    /// request to '{service}' was rate limited by the router
+    SubrequestRateLimited {
+        /// The service whose rate limit was reached.
+        service: String,
+    },
+
+    /// request to '{service}' was rejected because its circuit breaker is open
+    SubrequestCircuitOpen {
+        /// The service whose circuit breaker is open.
+        service: String,
+    },
+
+    /// request to '{service}' was rejected because its concurrency limit is reached
+    SubrequestConcurrencyLimited {
+        /// The service whose concurrency limit was reached.
+        service: String,
+    },
+
     /// Websocket fetch failed from '{service}': {reason}
     ///
     /// note that this relates to a transport error and not a GraphQL error
@@ -132,6 +183,13 @@ impl FetchError {
                 FetchError::SubrequestMalformedResponse { service, .. }
                 | FetchError::SubrequestUnexpectedPatchResponse { service }
                 | FetchError::SubrequestWsError { service, .. }
+                | FetchError::SubrequestResponseTooLarge { service, .. }
+                | FetchError::SubrequestBodyReadTimeout { service, .. }
+                | FetchError::SubrequestTimeout { service }
+                | FetchError::SubrequestRateLimited { service }
+                | FetchError::SubrequestCircuitOpen { service }
+                | FetchError::SubrequestConcurrencyLimited { service }
+                | FetchError::SubrequestMemoryLimitExceeded { service, .. }
                 | FetchError::CompressionError { service, .. } => {
                     extensions
                         .entry("service")
@@ -174,6 +232,13 @@ impl ErrorExtension for FetchError {
             }
             FetchError::SubrequestHttpError { .. } => "SUBREQUEST_HTTP_ERROR",
             FetchError::SubrequestWsError { .. } => "SUBREQUEST_WEBSOCKET_ERROR",
+            FetchError::SubrequestResponseTooLarge { .. } => "SUBREQUEST_RESPONSE_TOO_LARGE",
+            FetchError::SubrequestBodyReadTimeout { .. } => "SUBREQUEST_BODY_READ_TIMEOUT",
+            FetchError::SubrequestTimeout { .. } => "SUBGRAPH_TIMEOUT",
+            FetchError::SubrequestRateLimited { .. } => "SUBGRAPH_RATE_LIMITED",
+            FetchError::SubrequestCircuitOpen { .. } => "SUBGRAPH_CIRCUIT_OPEN",
+            FetchError::SubrequestConcurrencyLimited { .. } => "SUBGRAPH_CONCURRENCY_LIMITED",
+            FetchError::SubrequestMemoryLimitExceeded { .. } => "MEMORY_LIMIT_EXCEEDED",
             FetchError::ExecutionPathNotFound { .. } => "EXECUTION_PATH_NOT_FOUND",
             FetchError::CompressionError { .. } => "COMPRESSION_ERROR",
             FetchError::MalformedRequest { .. } => "MALFORMED_REQUEST",
@@ -455,9 +520,7 @@ impl From<SpecError> for QueryPlannerError {
 
 impl From<ValidationErrors> for QueryPlannerError {
     fn from(err: ValidationErrors) -> Self {
-        QueryPlannerError::OperationValidationErrors(
-            err.errors.iter().map(|e| e.to_json()).collect(),
-        )
+        QueryPlannerError::OperationValidationErrors(err.errors)
     }
 }
 
@@ -558,29 +621,39 @@ impl std::fmt::Display for ParseErrors {
 }
 
 /// Collection of schema validation errors.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct ValidationErrors {
-    pub(crate) errors: apollo_compiler::validation::DiagnosticList,
+    pub(crate) errors: Vec<apollo_compiler::execution::GraphQLError>,
+}
+
+impl From<apollo_compiler::validation::DiagnosticList> for ValidationErrors {
+    fn from(diagnostics: apollo_compiler::validation::DiagnosticList) -> Self {
+        Self {
+            errors: diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_json())
+                .collect(),
+        }
+    }
 }
 
 impl IntoGraphQLErrors for ValidationErrors {
     fn into_graphql_errors(self) -> Result<Vec<Error>, Self> {
         Ok(self
             .errors
-            .iter()
-            .map(|diagnostic| {
+            .into_iter()
+            .map(|error| {
                 Error::builder()
-                    .message(diagnostic.message().to_string())
+                    .message(error.message)
                     .locations(
-                        diagnostic
-                            .get_line_column()
-                            .map(|location| {
-                                vec![ErrorLocation {
-                                    line: location.line as u32,
-                                    column: location.column as u32,
-                                }]
+                        error
+                            .locations
+                            .into_iter()
+                            .map(|location| ErrorLocation {
+                                line: location.line as u32,
+                                column: location.column as u32,
                             })
-                            .unwrap_or_default(),
+                            .collect(),
                     )
                     .extension_code("GRAPHQL_VALIDATION_FAILED")
                     .build()
@@ -595,16 +668,14 @@ impl std::fmt::Display for ValidationErrors {
             if index > 0 {
                 f.write_str("\n")?;
             }
-            if let Some(location) = error.get_line_column() {
+            if let Some(location) = error.locations.first() {
                 write!(
                     f,
                     "[{}:{}] {}",
-                    location.line,
-                    location.column,
-                    error.message()
+                    location.line, location.column, error.message
                 )?;
             } else {
-                write!(f, "{}", error.message())?;
+                write!(f, "{}", error.message)?;
             }
         }
         Ok(())
@@ -637,6 +708,20 @@ mod tests {
         assert_eq!(expected_gql_error, error.to_graphql_error(None));
     }
 
+    #[test]
+    fn test_subgraph_timeout_into_graphql_error() {
+        let error = FetchError::SubrequestTimeout {
+            service: String::from("my_service"),
+        };
+        let expected_gql_error = graphql::Error::builder()
+            .message("request to 'my_service' timed out")
+            .extension_code("SUBGRAPH_TIMEOUT")
+            .extension("service", Value::String("my_service".into()))
+            .build();
+
+        assert_eq!(expected_gql_error, error.to_graphql_error(None));
+    }
+
     #[test]
     fn test_into_graphql_error_introspection_with_message_handled_correctly() {
         let expected_message = "no can introspect".to_string();
@@ -5,6 +5,7 @@ use std::ffi::OsStr;
 use std::fmt;
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
@@ -25,12 +26,15 @@ use regex::Regex;
 use url::ParseError;
 use url::Url;
 
+use crate::bench::BenchArgs;
 use crate::configuration::generate_config_schema;
 use crate::configuration::generate_upgrade;
+use crate::configuration::merge_config_files;
 use crate::configuration::Discussed;
 use crate::metrics::meter_provider;
 use crate::plugin::plugins;
 use crate::plugins::telemetry::reload::init_telemetry;
+use crate::plugins::telemetry::runtime::init_export_runtime;
 use crate::router::ConfigurationSource;
 use crate::router::RouterHttpServer;
 use crate::router::SchemaSource;
@@ -61,6 +65,7 @@ pub(crate) static mut DHAT_HEAP_PROFILER: OnceCell<dhat::Profiler> = OnceCell::n
 pub(crate) static mut DHAT_AD_HOC_PROFILER: OnceCell<dhat::Profiler> = OnceCell::new();
 
 pub(crate) const APOLLO_ROUTER_DEV_ENV: &str = "APOLLO_ROUTER_DEV";
+pub(crate) const APOLLO_ROUTER_PROFILE_ENV: &str = "APOLLO_ROUTER_PROFILE";
 
 // Note: Constructor/Destructor functions may not play nicely with tracing, since they run after
 // main completes, so don't use tracing, use println!() and eprintln!()..
@@ -115,6 +120,9 @@ extern "C" fn drop_ad_hoc_profiler() {
 enum Commands {
     /// Configuration subcommands.
     Config(ConfigSubcommandArgs),
+
+    /// Measure the throughput and latency of the router with mocked subgraphs, without a network.
+    Bench(BenchArgs),
 }
 
 #[derive(Args, Debug)]
@@ -138,6 +146,21 @@ enum ConfigSubcommand {
         /// Print a diff.
         #[clap(action = ArgAction::SetTrue, long)]
         diff: bool,
+
+        /// Rewrite the legacy metrics attributes and spans into the selectors format.
+        #[clap(action = ArgAction::SetTrue, long)]
+        migrate_telemetry: bool,
+    },
+    /// Print the configuration merged from several files or directories.
+    Merge {
+        /// The locations of the configs to merge, in order.
+        #[clap(
+            value_parser,
+            required = true,
+            env = "APOLLO_ROUTER_CONFIG_PATH",
+            value_delimiter = ','
+        )]
+        config_paths: Vec<PathBuf>,
     },
     /// List all the available experimental configurations with related GitHub discussion
     Experimental,
@@ -169,14 +192,16 @@ pub struct Opt {
     )]
     hot_reload: bool,
 
-    /// Configuration location relative to the project directory.
+    /// Configuration location relative to the project directory. Can be repeated, or comma separated, to merge several files or directories of files in order, the later ones taking precedence.
     #[clap(
         short,
         long = "config",
         value_parser,
-        env = "APOLLO_ROUTER_CONFIG_PATH"
+        action = ArgAction::Append,
+        env = "APOLLO_ROUTER_CONFIG_PATH",
+        value_delimiter = ','
     )]
-    config_path: Option<PathBuf>,
+    config_path: Vec<PathBuf>,
 
     /// Enable development mode.
     #[clap(
@@ -186,6 +211,10 @@ pub struct Opt {
     )]
     dev: bool,
 
+    /// Configuration profile to merge into the configuration.
+    #[clap(long = "profile", env = APOLLO_ROUTER_PROFILE_ENV)]
+    profile: Option<String>,
+
     /// Schema location relative to the project directory.
     #[clap(
         short,
@@ -247,6 +276,117 @@ pub struct Opt {
     /// Display version and exit.
     #[clap(action = ArgAction::SetTrue, long, short = 'V')]
     pub(crate) version: bool,
+
+    /// Number of worker threads of the Tokio runtime. Defaults to the number of CPU cores.
+    #[clap(long, env = "APOLLO_ROUTER_WORKER_THREADS")]
+    worker_threads: Option<NonZeroUsize>,
+
+    /// Maximum number of threads spawned by the Tokio runtime for blocking operations. Defaults to 512.
+    #[clap(long, env = "APOLLO_ROUTER_MAX_BLOCKING_THREADS")]
+    max_blocking_threads: Option<NonZeroUsize>,
+
+    /// Name of the threads of the Tokio runtime.
+    #[clap(long, env = "APOLLO_ROUTER_THREAD_NAME")]
+    thread_name: Option<String>,
+
+    /// Number of worker threads of a separate Tokio runtime exporting traces and metrics. By default, telemetry is exported from the main runtime.
+    #[clap(long, env = "APOLLO_ROUTER_TELEMETRY_WORKER_THREADS")]
+    telemetry_worker_threads: Option<NonZeroUsize>,
+
+    /// CPUs the threads of the Tokio runtime are pinned to, as a comma separated list of CPU ids or ranges (e.g. `0-15,32-47`). Linux only. Unless set otherwise, the number of worker threads is the number of CPUs in the list.
+    #[clap(long, value_parser = parse_cpu_list, env = "APOLLO_ROUTER_WORKER_CPUS")]
+    worker_cpus: Option<CpuList>,
+}
+
+impl Opt {
+    fn runtime_builder(&self) -> tokio::runtime::Builder {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all();
+        // `APOLLO_ROUTER_NUM_CORES` predates `--worker-threads`
+        let worker_threads = self.worker_threads.map(NonZeroUsize::get).or_else(|| {
+            std::env::var("APOLLO_ROUTER_NUM_CORES")
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+        });
+        let worker_threads =
+            worker_threads.or_else(|| self.worker_cpus.as_ref().map(|cpus| cpus.0.len()));
+        if let Some(nb) = worker_threads {
+            builder.worker_threads(nb);
+        }
+        if let Some(nb) = self.max_blocking_threads {
+            builder.max_blocking_threads(nb.get());
+        }
+        if let Some(name) = &self.thread_name {
+            builder.thread_name(name);
+        }
+        if let Some(cpus) = self.worker_cpus.clone() {
+            builder.on_thread_start(move || {
+                // Errors were reported when pinning the main thread
+                let _ = cpus.pin_current_thread();
+            });
+        }
+        builder
+    }
+}
+
+/// A set of CPU ids
+#[derive(Clone, Debug, PartialEq)]
+struct CpuList(Vec<usize>);
+
+/// The number of CPUs a thread can be pinned to
+#[cfg(target_os = "linux")]
+const CPU_SET_SIZE: usize = libc::CPU_SETSIZE as usize;
+#[cfg(not(target_os = "linux"))]
+const CPU_SET_SIZE: usize = 1024;
+
+fn parse_cpu_list(raw: &str) -> Result<CpuList, String> {
+    // CPU ids are checked before ranges are expanded, so that a huge range is not allocated
+    let parse = |cpu: &str| match cpu.trim().parse::<usize>() {
+        Ok(id) if id >= CPU_SET_SIZE => Err(format!("CPU id {id} is too large")),
+        Ok(id) => Ok(id),
+        Err(_) => Err(format!("invalid CPU id '{cpu}'")),
+    };
+    let mut cpus = Vec::new();
+    for item in raw.split(',') {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(format!("invalid CPU range '{item}'"));
+                }
+                cpus.extend(start..=end);
+            }
+            None => cpus.push(parse(item)?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(CpuList(cpus))
+}
+
+impl CpuList {
+    #[cfg(target_os = "linux")]
+    fn pin_current_thread(&self) -> std::io::Result<()> {
+        // SAFETY: `cpu_set_t` is a plain bit mask, and CPU ids were checked against its size
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for cpu in &self.0 {
+                libc::CPU_SET(*cpu, &mut set);
+            }
+            if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn pin_current_thread(&self) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "CPU pinning is only supported on Linux",
+        ))
+    }
 }
 
 // Add a filter to global log level settings so that the level only applies to the router.
@@ -356,16 +496,18 @@ pub fn main() -> Result<()> {
     #[cfg(feature = "dhat-ad-hoc")]
     create_ad_hoc_profiler();
 
-    let mut builder = tokio::runtime::Builder::new_multi_thread();
-    builder.enable_all();
-    if let Some(nb) = std::env::var("APOLLO_ROUTER_NUM_CORES")
-        .ok()
-        .and_then(|value| value.parse::<usize>().ok())
-    {
-        builder.worker_threads(nb);
+    // Options are parsed before starting the runtime, as they configure it
+    let opt = Opt::parse();
+    if let Some(cpus) = &opt.worker_cpus {
+        // The main thread only waits for the runtime, pinning it validates the CPU list
+        cpus.pin_current_thread()
+            .map_err(|e| anyhow!("could not pin threads to CPUs {:?}: {e}", cpus.0))?;
     }
-    let runtime = builder.build()?;
-    runtime.block_on(Executable::builder().start())
+    if let Some(nb) = opt.telemetry_worker_threads {
+        init_export_runtime(nb.get())?;
+    }
+    let runtime = opt.runtime_builder().build()?;
+    runtime.block_on(Executable::builder().cli_args(opt).start())
 }
 
 /// Entry point into creating a router executable with more customization than [`main`].
@@ -450,10 +592,22 @@ impl Executable {
                 Ok(())
             }
             Some(Commands::Config(ConfigSubcommandArgs {
-                command: ConfigSubcommand::Upgrade { config_path, diff },
+                command:
+                    ConfigSubcommand::Upgrade {
+                        config_path,
+                        diff,
+                        migrate_telemetry,
+                    },
             })) => {
                 let config_string = std::fs::read_to_string(config_path)?;
-                let output = generate_upgrade(&config_string, *diff)?;
+                let output = generate_upgrade(&config_string, *diff, *migrate_telemetry)?;
+                println!("{output}");
+                Ok(())
+            }
+            Some(Commands::Config(ConfigSubcommandArgs {
+                command: ConfigSubcommand::Merge { config_paths },
+            })) => {
+                let output = merge_config_files(config_paths)?;
                 println!("{output}");
                 Ok(())
             }
@@ -469,6 +623,7 @@ impl Executable {
                 Discussed::new().print_preview();
                 Ok(())
             }
+            Some(Commands::Bench(args)) => args.run().await,
             None => Self::inner_start(shutdown, schema, config, license, opt).await,
         };
 
@@ -495,28 +650,37 @@ impl Executable {
         // Enable hot reload when dev mode is enabled
         opt.hot_reload = opt.hot_reload || opt.dev;
 
-        let configuration = match (config, opt.config_path.as_ref()) {
-            (Some(_), Some(_)) => {
-                return Err(anyhow!(
-                    "--config and APOLLO_ROUTER_CONFIG_PATH cannot be used when a custom configuration source is in use"
-                ));
-            }
-            (Some(config), None) => config,
-            _ => match opt.config_path.as_ref().map(|path| {
-                let path = if path.is_relative() {
+        if let Some(profile) = &opt.profile {
+            tracing::info!("using the configuration profile '{profile}'");
+        }
+
+        let config_paths: Vec<PathBuf> = opt
+            .config_path
+            .iter()
+            .map(|path| {
+                if path.is_relative() {
                     current_directory.join(path)
                 } else {
                     path.to_path_buf()
-                };
-
-                ConfigurationSource::File {
-                    path,
-                    watch: opt.hot_reload,
-                    delay: None,
                 }
-            }) {
-                Some(configuration) => configuration,
-                None => Default::default(),
+            })
+            .collect();
+        let configuration = match (config, config_paths.as_slice()) {
+            (Some(_), [_, ..]) => {
+                return Err(anyhow!(
+                    "--config and APOLLO_ROUTER_CONFIG_PATH cannot be used when a custom configuration source is in use"
+                ));
+            }
+            (Some(config), []) => config,
+            (None, []) => Default::default(),
+            (None, [path]) if !path.is_dir() => ConfigurationSource::File {
+                path: path.clone(),
+                watch: opt.hot_reload,
+                delay: None,
+            },
+            (None, paths) => ConfigurationSource::Files {
+                paths: paths.to_vec(),
+                watch: opt.hot_reload,
             },
         };
 
@@ -725,7 +889,14 @@ fn copy_args_to_env() {
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
     use crate::executable::add_log_filter;
+    use crate::executable::parse_cpu_list;
+    use crate::executable::CpuList;
+    use crate::executable::Opt;
 
     #[test]
     fn simplest_logging_modifications() {
@@ -788,4 +959,67 @@ mod tests {
             "apollo_router::plugins=debug"
         );
     }
+
+    #[test]
+    fn runtime_options() {
+        let opt = Opt::parse_from([
+            "router",
+            "--worker-threads",
+            "2",
+            "--max-blocking-threads",
+            "4",
+            "--thread-name",
+            "router-worker",
+        ]);
+        assert_eq!(opt.worker_threads.map(|nb| nb.get()), Some(2));
+        assert_eq!(opt.max_blocking_threads.map(|nb| nb.get()), Some(4));
+        assert_eq!(opt.thread_name.as_deref(), Some("router-worker"));
+
+        let runtime = opt.runtime_builder().build().unwrap();
+        let thread_name = runtime
+            .block_on(runtime.spawn(async { std::thread::current().name().map(str::to_string) }))
+            .unwrap();
+        assert_eq!(thread_name.as_deref(), Some("router-worker"));
+
+        assert!(Opt::try_parse_from(["router", "--worker-threads", "0"]).is_err());
+    }
+
+    #[test]
+    fn multiple_config_paths() {
+        let opt = Opt::parse_from([
+            "router",
+            "--config",
+            "base.yaml",
+            "-c",
+            "overrides/,production.yaml",
+        ]);
+        assert_eq!(
+            opt.config_path,
+            vec![
+                PathBuf::from("base.yaml"),
+                PathBuf::from("overrides/"),
+                PathBuf::from("production.yaml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn cpu_lists() {
+        assert_eq!(parse_cpu_list("3"), Ok(CpuList(vec![3])));
+        assert_eq!(
+            parse_cpu_list("0-2, 8,2,10-11"),
+            Ok(CpuList(vec![0, 1, 2, 8, 10, 11]))
+        );
+        assert!(parse_cpu_list("").is_err());
+        assert!(parse_cpu_list("4-2").is_err());
+        assert!(parse_cpu_list("a-b").is_err());
+        assert_eq!(
+            parse_cpu_list("0-4000000000"),
+            Err("CPU id 4000000000 is too large".to_string())
+        );
+        assert!(parse_cpu_list("1024").is_err());
+
+        let opt = Opt::parse_from(["router", "--worker-cpus", "0"]);
+        assert_eq!(opt.worker_cpus, Some(CpuList(vec![0])));
+    }
 }
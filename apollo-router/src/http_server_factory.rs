@@ -16,6 +16,9 @@ use crate::router_factory::Endpoint;
 use crate::router_factory::RouterFactory;
 use crate::uplink::license_enforcement::LicenseState;
 
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+
 /// Factory for creating the http server component.
 ///
 /// This trait enables us to test that `StateMachine` correctly recreates the http server when
@@ -261,6 +264,68 @@ impl Listener {
     }
 }
 
+/// Accepts the connections of a listener, through io_uring with the `io-uring` feature
+pub(crate) struct Acceptor {
+    listener: Listener,
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    uring: Option<uring::UringAcceptor>,
+}
+
+impl Acceptor {
+    pub(crate) fn new(listener: Listener) -> Self {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        let uring = match &listener {
+            Listener::Tcp(tcp) | Listener::Tls { listener: tcp, .. } => {
+                match uring::UringAcceptor::new(tcp) {
+                    Ok(uring) => Some(uring),
+                    Err(e) => {
+                        tracing::warn!(
+                            "io_uring is not available, accepting connections with Tokio: {e}"
+                        );
+                        None
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Listener::Unix(_) => None,
+        };
+        Self {
+            listener,
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            uring,
+        }
+    }
+
+    pub(crate) async fn accept(&mut self) -> std::io::Result<NetworkStream> {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        if let Some(uring) = &mut self.uring {
+            match uring.accept().await {
+                Some(stream) => {
+                    let stream = stream?;
+                    return match &self.listener {
+                        Listener::Tls { acceptor, .. } => {
+                            Ok(NetworkStream::Tls(acceptor.accept(stream).await?))
+                        }
+                        _ => Ok(NetworkStream::Tcp(stream)),
+                    };
+                }
+                None => {
+                    tracing::warn!(
+                        "io_uring stopped accepting connections, accepting them with Tokio"
+                    );
+                    self.uring = None;
+                }
+            }
+        }
+        self.listener.accept().await
+    }
+
+    /// Stops accepting connections, and gives the listener back
+    pub(crate) fn into_listener(self) -> Listener {
+        self.listener
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::SocketAddr;
@@ -0,0 +1,270 @@
+//! Accepting the connections of a TCP listener through io_uring.
+//!
+//! A thread per listener polls it through an io_uring, and queues more accept operations as long as
+//! connections keep coming, so that a burst of connections is accepted with a few system calls
+//! instead of one `accept` each. The accepted sockets are then handed to Tokio, which serves them
+//! like any other connection.
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::fd::RawFd;
+use std::thread::JoinHandle;
+
+use io_uring::opcode;
+use io_uring::squeue;
+use io_uring::types;
+use io_uring::IoUring;
+use tokio::sync::mpsc;
+
+/// Maximum number of accept operations queued during a burst of connections
+const QUEUED_ACCEPTS: u32 = 64;
+
+/// The operations of the ring, by user data
+const ACCEPT: u64 = 0;
+/// A poll of the listener linked to the accept that follows it, after an accept found no
+/// connection waiting
+const POLL: u64 = 1;
+const WAKE_UP: u64 = 2;
+const CANCEL: u64 = 3;
+
+/// Accepts the connections of a TCP listener through io_uring, until it is dropped
+pub(crate) struct UringAcceptor {
+    receiver: mpsc::Receiver<io::Result<std::net::TcpStream>>,
+    /// Eventfd waking the thread of the ring up so that it stops
+    wake_up: File,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl UringAcceptor {
+    /// Fails if io_uring is not available, for example on kernels older than 5.5 or when it is
+    /// blocked by a seccomp profile
+    pub(crate) fn new(listener: &tokio::net::TcpListener) -> io::Result<Self> {
+        let ring = IoUring::new(QUEUED_ACCEPTS * 4)?;
+        let listener = listener.as_fd().try_clone_to_owned()?;
+        // SAFETY: eventfd returns a new file descriptor owned by nobody else, or -1
+        let wake_up = match unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) } {
+            -1 => return Err(io::Error::last_os_error()),
+            fd => unsafe { File::from_raw_fd(fd) },
+        };
+        let ring_wake_up = OwnedFd::from(wake_up.try_clone()?);
+        let (sender, receiver) = mpsc::channel(QUEUED_ACCEPTS as usize);
+        let thread = std::thread::Builder::new()
+            .name("io_uring accept".to_string())
+            .spawn(move || {
+                let ring = Ring {
+                    ring,
+                    listener: listener.as_raw_fd(),
+                    accepts: 0,
+                    polls: 0,
+                };
+                if let Err(error) = ring.run(&ring_wake_up, &sender) {
+                    tracing::error!("io_uring accept loop failed: {error}");
+                }
+            })?;
+        Ok(Self {
+            receiver,
+            wake_up,
+            thread: Some(thread),
+        })
+    }
+
+    /// The next accepted connection, or `None` if the ring stopped
+    pub(crate) async fn accept(&mut self) -> Option<io::Result<tokio::net::TcpStream>> {
+        let stream = self.receiver.recv().await?;
+        Some(stream.and_then(tokio::net::TcpStream::from_std))
+    }
+}
+
+impl Drop for UringAcceptor {
+    /// Waits until the ring does not accept connections anymore, so that the listener can be
+    /// used again right away
+    fn drop(&mut self) {
+        self.receiver.close();
+        let _ = self.wake_up.write_all(&1u64.to_ne_bytes());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+struct Ring {
+    ring: IoUring,
+    listener: RawFd,
+    /// Number of accepts in flight, including those waiting for a poll
+    accepts: usize,
+    /// Number of polls in flight
+    polls: usize,
+}
+
+impl Ring {
+    fn run(
+        mut self,
+        wake_up: &OwnedFd,
+        sender: &mpsc::Sender<io::Result<std::net::TcpStream>>,
+    ) -> io::Result<()> {
+        let mut wake_up_buffer = [0u8; 8];
+        let wake_up = opcode::Read::new(
+            types::Fd(wake_up.as_raw_fd()),
+            wake_up_buffer.as_mut_ptr(),
+            wake_up_buffer.len() as u32,
+        )
+        .build()
+        .user_data(WAKE_UP);
+        self.push(&[wake_up])?;
+        self.accept_when_ready()?;
+
+        let mut stopping = false;
+        loop {
+            if stopping {
+                // The ring is only dropped once every accept completed, otherwise an accept
+                // completing while it is dropped would leak its connection
+                if self.accepts == 0 {
+                    return Ok(());
+                }
+                self.cancel()?;
+            }
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+            let completions: Vec<(u64, i32)> = self
+                .ring
+                .completion()
+                .map(|completion| (completion.user_data(), completion.result()))
+                .collect();
+            for (user_data, result) in completions {
+                match user_data {
+                    WAKE_UP => stopping = true,
+                    CANCEL => {}
+                    POLL => self.polls -= 1,
+                    _ => {
+                        self.accepts -= 1;
+                        if result >= 0 {
+                            // SAFETY: the accepted socket is owned by nobody else
+                            let stream =
+                                unsafe { std::net::TcpStream::from_raw_fd(result as RawFd) };
+                            // Like the connections accepted right before a shutdown, the
+                            // connections accepted while the ring stops are closed
+                            if stopping || sender.blocking_send(Ok(stream)).is_err() {
+                                stopping = true;
+                                continue;
+                            }
+                            // More connections are likely to follow
+                            self.accept()?;
+                            if self.accepts < QUEUED_ACCEPTS as usize {
+                                self.accept()?;
+                            }
+                        } else if stopping {
+                        } else if -result == libc::EAGAIN {
+                            // The listener is non blocking, so an accept with no connection
+                            // waiting fails instead of waiting for one. A single poll waits for
+                            // the next connections once the burst is over
+                            if self.polls == 0 {
+                                self.accept_when_ready()?;
+                            }
+                        } else if -result == libc::ECANCELED {
+                            // The poll before the accept failed
+                            self.accept()?;
+                        } else {
+                            let error = io::Error::from_raw_os_error(-result);
+                            if sender.blocking_send(Err(error)).is_err() {
+                                stopping = true;
+                            } else {
+                                self.accept()?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        self.push(&[self.accept_entry()])?;
+        self.accepts += 1;
+        Ok(())
+    }
+
+    /// Accepts a connection once the listener is ready
+    fn accept_when_ready(&mut self) -> io::Result<()> {
+        let poll = opcode::PollAdd::new(types::Fd(self.listener), libc::POLLIN as u32)
+            .build()
+            .flags(squeue::Flags::IO_LINK)
+            .user_data(POLL);
+        self.push(&[poll, self.accept_entry()])?;
+        self.polls += 1;
+        self.accepts += 1;
+        Ok(())
+    }
+
+    /// Cancels the accepts in flight. Cancelling a poll also cancels the accept linked to it
+    fn cancel(&mut self) -> io::Result<()> {
+        let cancels: Vec<squeue::Entry> = std::iter::repeat(ACCEPT)
+            .take(self.accepts - self.polls)
+            .chain(std::iter::repeat(POLL).take(self.polls))
+            .map(|user_data| {
+                opcode::AsyncCancel::new(user_data)
+                    .build()
+                    .user_data(CANCEL)
+            })
+            .collect();
+        self.push(&cancels)
+    }
+
+    fn accept_entry(&self) -> squeue::Entry {
+        opcode::Accept::new(
+            types::Fd(self.listener),
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+        .flags(libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK)
+        .build()
+        .user_data(ACCEPT)
+    }
+
+    fn push(&mut self, entries: &[squeue::Entry]) -> io::Result<()> {
+        // SAFETY: the operations only refer to the listener and to the eventfd, which outlive
+        // the ring, and to the buffer of the eventfd, which outlives the loop of the ring
+        unsafe { self.ring.submission().push_multiple(entries) }.map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::Other,
+                "the io_uring submission queue is full",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_accepts_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let Ok(mut acceptor) = UringAcceptor::new(&listener) else {
+            // io_uring is not available in this environment
+            return;
+        };
+
+        for i in 0..3u8 {
+            let mut client = tokio::net::TcpStream::connect(address).await.unwrap();
+            let mut server = acceptor.accept().await.unwrap().unwrap();
+            client.write_all(&[i]).await.unwrap();
+            assert_eq!(server.read_u8().await.unwrap(), i);
+        }
+
+        // The listener can still be used once the acceptor is dropped
+        drop(acceptor);
+        let _client = tokio::net::TcpStream::connect(address).await.unwrap();
+        assert!(listener.accept().await.is_ok());
+    }
+}
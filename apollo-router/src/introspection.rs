@@ -3,12 +3,27 @@ use std::collections::HashMap;
 use std::num::NonZeroUsize;
 use std::sync::Arc;
 
+use apollo_compiler::ast;
+use apollo_compiler::name;
+use apollo_compiler::schema::ExtendedType;
+use apollo_compiler::Node;
+use regex::Regex;
 use router_bridge::introspect::IntrospectionError;
 use router_bridge::planner::Planner;
+use serde_json::Value;
 
 use crate::cache::storage::CacheStorage;
+use crate::configuration::Cache;
+use crate::configuration::InMemoryCache;
+use crate::error::SchemaError;
 use crate::graphql::Response;
 use crate::query_planner::QueryPlanResult;
+use crate::spec::Schema;
+use crate::Configuration;
+
+/// Aliases of the fields added to introspection queries to find the hidden elements
+const HIDDEN_TYPENAME_ALIAS: ast::Name = name!("_hiddenTypename");
+const HIDDEN_NAME_ALIAS: ast::Name = name!("_hiddenName");
 
 const DEFAULT_INTROSPECTION_CACHE_CAPACITY: NonZeroUsize =
     unsafe { NonZeroUsize::new_unchecked(5) };
@@ -17,6 +32,7 @@ const DEFAULT_INTROSPECTION_CACHE_CAPACITY: NonZeroUsize =
 pub(crate) struct Introspection {
     cache: CacheStorage<String, Response>,
     planner: Arc<Planner<QueryPlanResult>>,
+    hidden: Option<HiddenCoordinates>,
 }
 
 impl Introspection {
@@ -25,8 +41,19 @@ impl Introspection {
         capacity: NonZeroUsize,
     ) -> Self {
         Self {
-            cache: CacheStorage::new(capacity, None, "introspection").await,
+            cache: CacheStorage::new(
+                Cache {
+                    in_memory: InMemoryCache {
+                        limit: capacity,
+                        ttl: None,
+                    },
+                    ..Default::default()
+                },
+                "introspection",
+            )
+            .await,
             planner,
+            hidden: None,
         }
     }
 
@@ -34,6 +61,21 @@ impl Introspection {
         Self::with_capacity(planner, DEFAULT_INTROSPECTION_CACHE_CAPACITY).await
     }
 
+    /// Answers introspection with the planner of the supergraph, removing the types and fields
+    /// hidden from introspection from its responses
+    pub(crate) async fn with_hidden_coordinates(
+        planner: Arc<Planner<QueryPlanResult>>,
+        schema: Arc<Schema>,
+        configuration: &Configuration,
+    ) -> Result<Self, SchemaError> {
+        let hidden = &configuration.supergraph.experimental_introspection_hidden;
+        let mut introspection = Self::new(planner).await;
+        if !hidden.is_empty() {
+            introspection.hidden = Some(HiddenCoordinates::new(hidden, schema)?);
+        }
+        Ok(introspection)
+    }
+
     #[cfg(test)]
     pub(crate) async fn from_cache(
         planner: Arc<Planner<QueryPlanResult>>,
@@ -54,25 +96,33 @@ impl Introspection {
         }
 
         // Do the introspection query and cache it
+        let introspected = match &self.hidden {
+            Some(hidden) => hidden.select_names(&query),
+            None => query.clone(),
+        };
         let response =
             self.planner
-                .introspect(query.clone())
+                .introspect(introspected)
                 .await
                 .map_err(|_e| IntrospectionError {
                     message: String::from("cannot find the introspection response").into(),
                 })?;
 
-        let introspection_result = response.into_result().map_err(|err| IntrospectionError {
-            message: format!(
-                "introspection error : {}",
-                err.into_iter()
-                    .map(|err| err.to_string())
-                    .collect::<Vec<String>>()
-                    .join(", "),
-            )
-            .into(),
-        })?;
+        let mut introspection_result =
+            response.into_result().map_err(|err| IntrospectionError {
+                message: format!(
+                    "introspection error : {}",
+                    err.into_iter()
+                        .map(|err| err.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                )
+                .into(),
+            })?;
 
+        if let Some(hidden) = &self.hidden {
+            hidden.remove(&mut introspection_result, Parent::default());
+        }
         let response = Response::builder().data(introspection_result).build();
 
         self.cache.insert(query, response.clone()).await;
@@ -81,6 +131,291 @@ impl Introspection {
     }
 }
 
+/// Types and fields hidden from introspection. Introspection queries also select the names of
+/// the types and fields they return, so that the hidden ones can be removed from the responses of
+/// the planner
+struct HiddenCoordinates {
+    patterns: Vec<CoordinatePattern>,
+    schema: Arc<Schema>,
+}
+
+/// The type and field an introspection object belongs to
+#[derive(Clone, Copy, Default)]
+struct Parent<'a> {
+    ty: Option<&'a str>,
+    field: Option<&'a str>,
+}
+
+impl HiddenCoordinates {
+    fn new(hidden: &[String], schema: Arc<Schema>) -> Result<Self, SchemaError> {
+        let patterns = hidden
+            .iter()
+            .map(|coordinate| CoordinatePattern::new(coordinate))
+            .collect::<Result<Vec<_>, _>>()?;
+        let this = Self { patterns, schema };
+
+        let definitions = &this.schema.api_schema().definitions;
+        if let Some(root) = [
+            ast::OperationType::Query,
+            ast::OperationType::Mutation,
+            ast::OperationType::Subscription,
+        ]
+        .into_iter()
+        .filter_map(|operation_type| definitions.root_operation(operation_type))
+        .find(|root| this.hides_type(root))
+        {
+            return Err(SchemaError::Api(format!(
+                "experimental_introspection_hidden cannot hide the root type {root}"
+            )));
+        }
+        let matched = definitions.types.iter().any(|(name, ty)| {
+            this.hides_type(name)
+                || match ty {
+                    ExtendedType::Object(def) => def
+                        .fields
+                        .keys()
+                        .any(|field| this.hides_member(name, field)),
+                    ExtendedType::Interface(def) => def
+                        .fields
+                        .keys()
+                        .any(|field| this.hides_member(name, field)),
+                    ExtendedType::InputObject(def) => def
+                        .fields
+                        .keys()
+                        .any(|field| this.hides_member(name, field)),
+                    ExtendedType::Enum(def) => def
+                        .values
+                        .keys()
+                        .any(|value| this.hides_member(name, value)),
+                    _ => false,
+                }
+        });
+        if !matched {
+            tracing::warn!(
+                "no type or field of the supergraph matches experimental_introspection_hidden"
+            );
+        }
+        Ok(this)
+    }
+
+    fn hides_type(&self, name: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches(name, None))
+    }
+
+    fn hides_member(&self, ty: &str, member: &str) -> bool {
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches(ty, Some(member)))
+    }
+
+    /// Returns the introspection query, also selecting the names of the types, fields, arguments
+    /// and enum values it returns
+    fn select_names(&self, query: &str) -> String {
+        let Ok(mut document) = ast::Document::parse(query, "query.graphql") else {
+            // the planner reports the errors
+            return query.to_string();
+        };
+        let definitions = &self.schema.api_schema().definitions;
+        for definition in &mut document.definitions {
+            match definition {
+                ast::Definition::OperationDefinition(operation) => {
+                    let operation = operation.make_mut();
+                    if let Some(root) = definitions.root_operation(operation.operation_type) {
+                        let root = root.clone();
+                        self.select_names_in(&mut operation.selection_set, &root);
+                    }
+                }
+                ast::Definition::FragmentDefinition(fragment) => {
+                    let fragment = fragment.make_mut();
+                    let ty = fragment.type_condition.clone();
+                    self.select_names_in(&mut fragment.selection_set, &ty);
+                }
+                _ => {}
+            }
+        }
+        document.to_string()
+    }
+
+    fn select_names_in(&self, selection_set: &mut Vec<ast::Selection>, ty: &ast::NamedType) {
+        let definitions = &self.schema.api_schema().definitions;
+        for selection in selection_set.iter_mut() {
+            match selection {
+                ast::Selection::Field(field) => {
+                    if let Ok(definition) = definitions.type_field(ty, &field.name) {
+                        let field_type = definition.ty.inner_named_type().clone();
+                        self.select_names_in(&mut field.make_mut().selection_set, &field_type);
+                    }
+                }
+                ast::Selection::InlineFragment(inline) => {
+                    let inline = inline.make_mut();
+                    let ty = inline.type_condition.clone().unwrap_or_else(|| ty.clone());
+                    self.select_names_in(&mut inline.selection_set, &ty);
+                }
+                ast::Selection::FragmentSpread(_) => {}
+            }
+        }
+        if matches!(
+            ty.as_str(),
+            "__Type" | "__Field" | "__InputValue" | "__EnumValue"
+        ) {
+            for (alias, name) in [
+                (HIDDEN_TYPENAME_ALIAS, name!("__typename")),
+                (HIDDEN_NAME_ALIAS, name!("name")),
+            ] {
+                selection_set.push(ast::Selection::Field(Node::new(ast::Field {
+                    alias: Some(alias),
+                    name,
+                    arguments: Vec::new(),
+                    directives: Default::default(),
+                    selection_set: Vec::new(),
+                })));
+            }
+        }
+    }
+
+    /// Removes the hidden elements and the added names from an introspection response. Returns
+    /// false if the value itself is hidden
+    fn remove(&self, value: &mut Value, parent: Parent<'_>) -> bool {
+        match value {
+            Value::Array(values) => {
+                values.retain_mut(|value| self.remove(value, parent));
+                true
+            }
+            Value::Object(object) => {
+                let typename = object.remove(HIDDEN_TYPENAME_ALIAS.as_str());
+                let name = object.remove(HIDDEN_NAME_ALIAS.as_str());
+                let name = name.as_ref().and_then(Value::as_str);
+                let (hidden, parent) = match (typename.as_ref().and_then(Value::as_str), name) {
+                    (Some("__Type"), _) => (
+                        name.is_some_and(|name| self.hides_type(name)),
+                        Parent {
+                            ty: name,
+                            field: None,
+                        },
+                    ),
+                    (Some("__Field"), Some(name)) => (
+                        parent.ty.is_some_and(|ty| self.hides_field(ty, name)),
+                        Parent {
+                            ty: parent.ty,
+                            field: Some(name),
+                        },
+                    ),
+                    (Some("__InputValue"), Some(name)) => (
+                        parent
+                            .ty
+                            .is_some_and(|ty| self.hides_input_value(ty, parent.field, name)),
+                        parent,
+                    ),
+                    (Some("__EnumValue"), Some(name)) => (
+                        parent.ty.is_some_and(|ty| self.hides_member(ty, name)),
+                        parent,
+                    ),
+                    _ => (false, parent),
+                };
+                if hidden {
+                    return false;
+                }
+                for value in object.values_mut() {
+                    if !self.remove(value, parent) {
+                        *value = Value::Null;
+                    }
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Fields are hidden with their type
+    fn hides_field(&self, ty: &str, field: &str) -> bool {
+        self.hides_member(ty, field)
+            || self
+                .schema
+                .api_schema()
+                .definitions
+                .type_field(ty, field)
+                .is_ok_and(|definition| self.hides_type(definition.ty.inner_named_type()))
+    }
+
+    /// Arguments and input fields are hidden with their type
+    fn hides_input_value(&self, ty: &str, field: Option<&str>, name: &str) -> bool {
+        let definitions = &self.schema.api_schema().definitions;
+        let value_type = match field {
+            Some(field) => definitions
+                .type_field(ty, field)
+                .ok()
+                .and_then(|definition| {
+                    definition
+                        .arguments
+                        .iter()
+                        .find(|argument| argument.name == name)
+                        .map(|argument| argument.ty.inner_named_type())
+                }),
+            None => {
+                if self.hides_member(ty, name) {
+                    return true;
+                }
+                match definitions.types.get(ty) {
+                    Some(ExtendedType::InputObject(def)) => def
+                        .fields
+                        .get(name)
+                        .map(|field| field.ty.inner_named_type()),
+                    _ => None,
+                }
+            }
+        };
+        value_type.is_some_and(|value_type| self.hides_type(value_type))
+    }
+}
+
+/// A schema coordinate of a type or of a field, where `*` matches any sequence of characters
+struct CoordinatePattern {
+    ty: Regex,
+    member: Option<Regex>,
+}
+
+impl CoordinatePattern {
+    fn new(coordinate: &str) -> Result<Self, SchemaError> {
+        let glob = |pattern: &str| {
+            let valid = !pattern.is_empty()
+                && pattern
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '*');
+            if !valid {
+                return Err(SchemaError::Api(format!(
+                    "invalid schema coordinate in experimental_introspection_hidden: {coordinate}"
+                )));
+            }
+            let regex = pattern
+                .split('*')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".*");
+            Regex::new(&format!("^{regex}$")).map_err(|error| SchemaError::Api(error.to_string()))
+        };
+        Ok(match coordinate.split_once('.') {
+            Some((ty, member)) => CoordinatePattern {
+                ty: glob(ty)?,
+                member: Some(glob(member)?),
+            },
+            None => CoordinatePattern {
+                ty: glob(coordinate)?,
+                member: None,
+            },
+        })
+    }
+
+    fn matches(&self, ty: &str, member: Option<&str>) -> bool {
+        match (&self.member, member) {
+            (None, None) => self.ty.is_match(ty),
+            (Some(pattern), Some(member)) => self.ty.is_match(ty) && pattern.is_match(member),
+            _ => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod introspection_tests {
     use std::sync::Arc;
@@ -132,4 +467,87 @@ mod introspection_tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn it_selects_the_names_of_introspection_objects() {
+        let schema = include_str!("../tests/fixtures/supergraph.graphql");
+        let schema = Arc::new(Schema::parse_test(schema, &Default::default()).unwrap());
+        let hidden = HiddenCoordinates::new(&["Review".to_string()], schema).unwrap();
+
+        let query = hidden.select_names(
+            r#"{ __type(name: "Query") { ...fields } __schema { types { kind } } }
+            fragment fields on __Type { fields { name type { name } args { name } } }"#,
+        );
+        // __type, types, the fragment, fields, type and args
+        assert_eq!(query.matches("_hiddenName: name").count(), 6);
+        assert_eq!(query.matches("_hiddenTypename: __typename").count(), 6);
+    }
+
+    #[test]
+    fn it_does_not_hide_root_types() {
+        let schema = include_str!("../tests/fixtures/supergraph.graphql");
+        let schema = Arc::new(Schema::parse_test(schema, &Default::default()).unwrap());
+        assert!(HiddenCoordinates::new(&["Que*".to_string()], schema).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_hides_coordinates_from_introspection() {
+        let schema = include_str!("../tests/fixtures/supergraph.graphql");
+        let configuration = Configuration::fake_builder()
+            .supergraph(
+                crate::configuration::Supergraph::fake_builder()
+                    .introspection(true)
+                    .experimental_introspection_hidden(vec![
+                        "Query.top*".to_string(),
+                        "Review".to_string(),
+                    ])
+                    .build(),
+            )
+            .build()
+            .unwrap();
+        let planner = Arc::new(
+            Planner::new(
+                schema.to_string(),
+                QueryPlannerConfig {
+                    incremental_delivery: None,
+                    graphql_validation: true,
+                    reuse_query_fragments: None,
+                    debug: None,
+                },
+            )
+            .await
+            .unwrap(),
+        );
+        let schema = Arc::new(Schema::parse_test(schema, &configuration).unwrap());
+        let introspection = Introspection::with_hidden_coordinates(planner, schema, &configuration)
+            .await
+            .unwrap();
+
+        let response = introspection
+            .execute(
+                r#"{
+                    query: __type(name: "Query") { fields { name } }
+                    review: __type(name: "Review") { name }
+                    product: __type(name: "Product") { fields { name } }
+                }"#
+                .to_string(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.data.unwrap(),
+            serde_json_bytes::json!({
+                "query": { "fields": [{ "name": "me" }] },
+                "review": null,
+                // fields returning a hidden type are hidden too
+                "product": { "fields": [
+                    { "name": "name" },
+                    { "name": "price" },
+                    { "name": "shippingEstimate" },
+                    { "name": "upc" },
+                    { "name": "weight" },
+                ] },
+            })
+        );
+    }
 }
@@ -50,7 +50,10 @@ pub mod plugin;
 #[macro_use]
 pub(crate) mod metrics;
 
+#[cfg(feature = "arena-allocation")]
+mod arena;
 pub(crate) mod axum_factory;
+mod bench;
 mod cache;
 mod configuration;
 mod context;
@@ -79,6 +82,8 @@ pub mod test_harness;
 pub mod tracer;
 mod uplink;
 
+pub use crate::cache::backend::register_cache_backend;
+pub use crate::cache::backend::CacheBackend;
 pub use crate::configuration::Configuration;
 pub use crate::configuration::ListenAddr;
 pub use crate::context::Context;
@@ -87,8 +92,10 @@ pub use crate::executable::Executable;
 pub use crate::notification::Notify;
 pub use crate::router::ApolloRouterError;
 pub use crate::router::ConfigurationSource;
+pub use crate::router::LicenseProvider;
 pub use crate::router::LicenseSource;
 pub use crate::router::RouterHttpServer;
+pub use crate::router::SchemaProvider;
 pub use crate::router::SchemaSource;
 pub use crate::router::ShutdownSource;
 pub use crate::router_factory::Endpoint;
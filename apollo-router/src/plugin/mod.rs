@@ -25,6 +25,7 @@ use std::task::Poll;
 
 use ::serde::de::DeserializeOwned;
 use ::serde::Deserialize;
+use ::serde::Serialize;
 use async_trait::async_trait;
 use futures::future::BoxFuture;
 use multimap::MultiMap;
@@ -263,6 +264,27 @@ impl PluginFactory {
     }
 }
 
+/// A router lifecycle transition, passed to [`Plugin::lifecycle_event`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum LifecycleEvent {
+    /// A new supergraph schema was loaded and is now serving traffic.
+    SchemaChanged {
+        /// The hash of the schema that was replaced.
+        previous_schema_id: String,
+        /// The hash of the schema now in use.
+        schema_id: String,
+        /// The schema definition language of the schema that was replaced.
+        #[serde(skip)]
+        previous_supergraph_sdl: Arc<String>,
+    },
+    /// A new configuration was loaded and is now serving traffic.
+    ConfigurationChanged,
+    /// The router stopped accepting new connections and is draining in-flight requests before shutting down.
+    Draining,
+}
+
 // If we wanted to create a custom subset of plugins, this is where we would do it
 /// Get a copy of the registered plugin factories.
 pub(crate) fn plugins() -> impl Iterator<Item = &'static Lazy<PluginFactory>> {
@@ -340,6 +362,13 @@ pub trait Plugin: Send + Sync + 'static {
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         MultiMap::new()
     }
+
+    /// This function is EXPERIMENTAL and its signature is subject to change.
+    ///
+    /// Called when the router goes through a lifecycle transition, such as a schema or
+    /// configuration reload, or when it starts draining connections before shutting down.
+    /// This is invoked synchronously by the router state machine, so it should return quickly.
+    fn lifecycle_event(&self, _event: &LifecycleEvent) {}
 }
 
 /// Plugin trait for unstable features
@@ -415,6 +444,13 @@ pub trait PluginUnstable: Send + Sync + 'static {
         MultiMap::new()
     }
 
+    /// This function is EXPERIMENTAL and its signature is subject to change.
+    ///
+    /// Called when the router goes through a lifecycle transition, such as a schema or
+    /// configuration reload, or when it starts draining connections before shutting down.
+    /// This is invoked synchronously by the router state machine, so it should return quickly.
+    fn lifecycle_event(&self, _event: &LifecycleEvent) {}
+
     /// test
     fn unstable_method(&self);
 }
@@ -465,6 +501,10 @@ where
         Plugin::web_endpoints(self)
     }
 
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        Plugin::lifecycle_event(self, event)
+    }
+
     fn unstable_method(&self) {
         todo!()
     }
@@ -543,6 +583,13 @@ pub(crate) trait PluginPrivate: Send + Sync + 'static {
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         MultiMap::new()
     }
+
+    /// This function is EXPERIMENTAL and its signature is subject to change.
+    ///
+    /// Called when the router goes through a lifecycle transition, such as a schema or
+    /// configuration reload, or when it starts draining connections before shutting down.
+    /// This is invoked synchronously by the router state machine, so it should return quickly.
+    fn lifecycle_event(&self, _event: &LifecycleEvent) {}
 }
 
 #[async_trait]
@@ -590,6 +637,10 @@ where
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         PluginUnstable::web_endpoints(self)
     }
+
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        PluginUnstable::lifecycle_event(self, event)
+    }
 }
 
 fn get_type_of<T>(_: &T) -> &'static str {
@@ -633,6 +684,9 @@ pub(crate) trait DynPlugin: Send + Sync + 'static {
     /// Return one or several `Endpoint`s and `ListenAddr` and the router will serve your custom web Endpoint(s).
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
 
+    /// Called when the router goes through a lifecycle transition.
+    fn lifecycle_event(&self, event: &LifecycleEvent);
+
     /// Support downcasting
     fn as_any(&self) -> &dyn std::any::Any;
 
@@ -671,6 +725,10 @@ where
         self.web_endpoints()
     }
 
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        self.lifecycle_event(event)
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
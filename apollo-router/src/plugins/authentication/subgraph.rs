@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use aws_credential_types::provider::ProvideCredentials;
 use aws_credential_types::Credentials;
@@ -12,16 +13,28 @@ use aws_sigv4::http_request::SignableRequest;
 use aws_sigv4::http_request::SigningSettings;
 use aws_sigv4::signing_params;
 use aws_types::region::Region;
+use hmac::Hmac;
+use hmac::Mac;
+use http::HeaderName;
+use http::HeaderValue;
+use http::Method;
 use http::Request;
+use http::Uri;
 use hyper::Body;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
 use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
 use crate::services::SubgraphRequest;
 
+type HmacSha256 = Hmac<Sha256>;
+
+const HMAC_SIGNATURE_HEADER_NAME: &str = "apollo-router-signature";
+
 /// Hardcoded Config using access_key and secret.
 /// Prefer using DefaultChain instead.
 #[derive(Clone, JsonSchema, Deserialize, Debug)]
@@ -163,11 +176,40 @@ impl AWSSigV4Config {
     }
 }
 
+/// Configure HMAC request signing.
+#[derive(Clone, JsonSchema, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HmacConfig {
+    /// The keys that sign requests. Each request is signed with every key, so that subgraphs
+    /// can verify requests with either the old or the new key while a key is rotated.
+    keys: Vec<HmacKey>,
+    /// The header containing the signatures (default: `apollo-router-signature`)
+    #[serde(default = "default_hmac_header")]
+    header: String,
+}
+
+/// A key signing subgraph requests
+#[derive(Clone, JsonSchema, Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct HmacKey {
+    /// The identifier of the key, sent with its signature
+    id: String,
+    /// The secret used to sign requests with HMAC-SHA256
+    secret: String,
+}
+
+fn default_hmac_header() -> String {
+    HMAC_SIGNATURE_HEADER_NAME.to_string()
+}
+
 #[derive(Clone, Debug, JsonSchema, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub(crate) enum AuthConfig {
     #[serde(rename = "aws_sig_v4")]
     AWSSigV4(AWSSigV4Config),
+    /// Sign requests with HMAC-SHA256 over their method, path, body hash and timestamp
+    #[serde(rename = "hmac")]
+    Hmac(HmacConfig),
 }
 
 /// Configure subgraph authentication
@@ -190,14 +232,45 @@ pub(crate) struct SigningParams {
 }
 
 #[derive(Clone)]
-pub(crate) struct SigningParamsConfig {
+pub(crate) enum SigningParamsConfig {
+    AWSSigV4(AWSSigV4SigningParams),
+    Hmac(HmacSigningParams),
+}
+
+impl SigningParamsConfig {
+    pub(crate) async fn sign(
+        self,
+        req: Request<Body>,
+        subgraph_name: &str,
+    ) -> Result<Request<Body>, BoxError> {
+        match self {
+            Self::AWSSigV4(params) => params.sign(req, subgraph_name).await,
+            Self::Hmac(params) => params.sign(req).await,
+        }
+    }
+
+    // Subscriptions over WebSocket are signed when the connection is opened
+    pub(crate) async fn sign_empty(
+        self,
+        req: Request<()>,
+        subgraph_name: &str,
+    ) -> Result<Request<()>, BoxError> {
+        match self {
+            Self::AWSSigV4(params) => params.sign_empty(req, subgraph_name).await,
+            Self::Hmac(params) => params.sign_empty(req),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct AWSSigV4SigningParams {
     credentials_provider: Arc<dyn ProvideCredentials>,
     region: Region,
     service_name: String,
     subgraph_name: String,
 }
 
-impl SigningParamsConfig {
+impl AWSSigV4SigningParams {
     pub(crate) async fn sign(
         self,
         mut req: Request<Body>,
@@ -304,6 +377,87 @@ impl SigningParamsConfig {
     }
 }
 
+#[derive(Clone)]
+pub(crate) struct HmacSigningParams {
+    keys: Arc<Vec<(String, HmacSha256)>>,
+    header: HeaderName,
+}
+
+impl HmacSigningParams {
+    fn new(config: &HmacConfig) -> Result<Self, BoxError> {
+        if config.keys.is_empty() {
+            return Err("HMAC signing requires at least one key".into());
+        }
+        let keys = config
+            .keys
+            .iter()
+            .map(|key| {
+                if key.id.is_empty() || key.id == "t" || key.id.contains([',', '=']) {
+                    return Err(format!("invalid HMAC key id '{}'", key.id).into());
+                }
+                Ok((
+                    key.id.clone(),
+                    HmacSha256::new_from_slice(key.secret.as_bytes())?,
+                ))
+            })
+            .collect::<Result<_, BoxError>>()?;
+        Ok(Self {
+            keys: Arc::new(keys),
+            header: HeaderName::try_from(config.header.as_str())?,
+        })
+    }
+
+    async fn sign(self, req: Request<Body>) -> Result<Request<Body>, BoxError> {
+        let (mut parts, body) = req.into_parts();
+        let body_bytes = hyper::body::to_bytes(body).await?;
+        let signature = self.signature(&parts.method, &parts.uri, &body_bytes, unix_time())?;
+        parts.headers.insert(self.header, signature);
+        Ok(Request::from_parts(parts, body_bytes.into()))
+    }
+
+    fn sign_empty(self, req: Request<()>) -> Result<Request<()>, BoxError> {
+        let (mut parts, body) = req.into_parts();
+        let signature = self.signature(&parts.method, &parts.uri, &[], unix_time())?;
+        parts.headers.insert(self.header, signature);
+        Ok(Request::from_parts(parts, body))
+    }
+
+    /// The timestamp followed by the signature of each key, as `t=<timestamp>,<key id>=<hex signature>`
+    ///
+    /// The signed message is the timestamp, method, path and query, and hex encoded SHA-256 hash
+    /// of the body, separated by newlines.
+    fn signature(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        body: &[u8],
+        timestamp: u64,
+    ) -> Result<HeaderValue, BoxError> {
+        let message = format!(
+            "{timestamp}\n{method}\n{}\n{}",
+            uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"),
+            hex::encode(Sha256::digest(body))
+        );
+        let mut value = format!("t={timestamp}");
+        for (id, mac) in self.keys.iter() {
+            let mut mac = mac.clone();
+            mac.update(message.as_bytes());
+            value.push_str(&format!(
+                ",{id}={}",
+                hex::encode(mac.finalize().into_bytes())
+            ));
+        }
+        Ok(HeaderValue::try_from(value)?)
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
 fn increment_success_counter(subgraph_name: &str) {
     tracing::info!(
         monotonic_counter.apollo.router.operations.authentication.aws.sigv4 = 1u64,
@@ -339,19 +493,29 @@ pub(super) async fn make_signing_params(
                 .into());
             }
 
-            Ok(SigningParamsConfig {
+            Ok(SigningParamsConfig::AWSSigV4(AWSSigV4SigningParams {
                 region: config.region(),
                 service_name: config.service_name(),
                 credentials_provider,
                 subgraph_name: subgraph_name.to_string(),
-            })
+            }))
         }
+        AuthConfig::Hmac(config) => HmacSigningParams::new(config)
+            .map(SigningParamsConfig::Hmac)
+            .map_err(|e| {
+                let error_subgraph_name = if subgraph_name == "all" {
+                    "all subgraphs".to_string()
+                } else {
+                    format!("{} subgraph", subgraph_name)
+                };
+                format!("auth: {}: {}", error_subgraph_name, e).into()
+            }),
     }
 }
 
 /// There are three possible cases
 /// https://github.com/awslabs/aws-sdk-rust/blob/9c3168dafa4fd8885ce4e1fd41cec55ce982a33c/sdk/aws-sigv4/src/http_request/sign.rs#L264C1-L271C6
-fn get_signing_settings(signing_params: &SigningParamsConfig) -> SigningSettings {
+fn get_signing_settings(signing_params: &AWSSigV4SigningParams) -> SigningSettings {
     let mut settings = SigningSettings::default();
     settings.payload_checksum_kind = match signing_params.service_name.as_str() {
         "appsync" | "s3" | "vpc-lattice-svcs" => PayloadChecksumKind::XAmzSha256,
@@ -414,7 +578,7 @@ mod test {
     use crate::Context;
 
     async fn test_signing_settings(service_name: &str) -> SigningSettings {
-        let params: SigningParamsConfig = make_signing_params(
+        let SigningParamsConfig::AWSSigV4(params) = make_signing_params(
             &AuthConfig::AWSSigV4(AWSSigV4Config::Hardcoded(AWSSigV4HardcodedConfig {
                 access_key_id: "id".to_string(),
                 secret_access_key: "secret".to_string(),
@@ -425,7 +589,9 @@ mod test {
             "all",
         )
         .await
-        .unwrap();
+        .unwrap() else {
+            panic!("expected AWS SigV4 signing parameters")
+        };
         get_signing_settings(&params)
     }
 
@@ -602,6 +768,117 @@ mod test {
         Ok(())
     }
 
+    fn hmac_params(keys: &[(&str, &str)]) -> HmacSigningParams {
+        HmacSigningParams::new(&HmacConfig {
+            keys: keys
+                .iter()
+                .map(|(id, secret)| HmacKey {
+                    id: id.to_string(),
+                    secret: secret.to_string(),
+                })
+                .collect(),
+            header: default_hmac_header(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_hmac_signature() {
+        let params = hmac_params(&[("current", "secret"), ("previous", "old secret")]);
+        let uri = Uri::from_static("https://test-endpoint.com/graphql?a=b");
+        let body = br#"{"query":"query"}"#;
+        let signature = params
+            .signature(&Method::POST, &uri, body, 1700000000)
+            .unwrap();
+
+        let message = format!(
+            "1700000000\nPOST\n/graphql?a=b\n{}",
+            hex::encode(Sha256::digest(body))
+        );
+        let expected = |secret: &str| {
+            let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+            mac.update(message.as_bytes());
+            hex::encode(mac.finalize().into_bytes())
+        };
+        assert_eq!(
+            signature.to_str().unwrap(),
+            format!(
+                "t=1700000000,current={},previous={}",
+                expected("secret"),
+                expected("old secret")
+            )
+        );
+
+        // The body is part of the signature
+        assert_ne!(
+            params
+                .signature(&Method::POST, &uri, b"{}", 1700000000)
+                .unwrap(),
+            signature
+        );
+    }
+
+    #[test]
+    fn test_hmac_config_validation() {
+        let config = |keys: Vec<HmacKey>| HmacConfig {
+            keys,
+            header: default_hmac_header(),
+        };
+        assert!(HmacSigningParams::new(&config(Vec::new())).is_err());
+        assert!(HmacSigningParams::new(&config(vec![HmacKey {
+            id: "t".to_string(),
+            secret: "secret".to_string(),
+        }]))
+        .is_err());
+        assert!(serde_yaml::from_str::<Config>(
+            r#"
+        subgraphs:
+            products:
+                hmac:
+                    keys:
+                        - id: current
+                          secret: secret
+        "#,
+        )
+        .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_hmac_headers() -> Result<(), BoxError> {
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call()
+            .times(1)
+            .withf(|request| {
+                let http_request = get_signed_request(request, "products".to_string());
+                let signature_regex = Regex::new(r"^t=\d+,current=[a-f0-9]{64}$").unwrap();
+                let signature = http_request
+                    .headers()
+                    .get(HMAC_SIGNATURE_HEADER_NAME)
+                    .unwrap()
+                    .to_str()
+                    .unwrap();
+                assert!(signature_regex.is_match(signature), "{signature}");
+                true
+            })
+            .returning(example_response);
+
+        let mut service = SubgraphAuth {
+            signing_params: SigningParams {
+                all: None,
+                subgraphs: [(
+                    "products".to_string(),
+                    SigningParamsConfig::Hmac(hmac_params(&[("current", "secret")])),
+                )]
+                .into_iter()
+                .collect(),
+            },
+        }
+        .subgraph_service("products", mock.boxed());
+
+        service.ready().await?.call(example_request()).await?;
+        Ok(())
+    }
+
     fn example_response(_: SubgraphRequest) -> Result<SubgraphResponse, BoxError> {
         Ok(SubgraphResponse::new_from_response(
             http::Response::default(),
@@ -40,14 +40,50 @@ pub(crate) struct CacheControl {
     no_transform: bool,
     #[serde(skip_serializing_if = "is_false", default)]
     immutable: bool,
-    #[serde(skip_serializing_if = "is_false", default)]
-    stale_if_error: bool,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_stale_if_error",
+        default
+    )]
+    stale_if_error: Option<u32>,
+}
+
+/// How a cached entry can be used, depending on its age
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Freshness {
+    /// the entry has not expired
+    Fresh,
+    /// the entry has expired but can be used while it is refreshed in the background
+    StaleWhileRevalidate,
+    /// the entry has expired but can be used if the subgraph fails
+    StaleIfError,
+    /// the entry cannot be used anymore
+    Expired,
 }
 
 fn is_false(b: &bool) -> bool {
     !b
 }
 
+// entries cached by earlier versions stored `stale-if-error` as a boolean, without a duration
+fn deserialize_stale_if_error<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StaleIfError {
+        Seconds(u32),
+        Flag(bool),
+    }
+
+    Ok(match Option::<StaleIfError>::deserialize(deserializer)? {
+        Some(StaleIfError::Seconds(seconds)) => Some(seconds),
+        Some(StaleIfError::Flag(true)) => Some(0),
+        Some(StaleIfError::Flag(false)) | None => None,
+    })
+}
+
 fn now_epoch_seconds() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -72,7 +108,7 @@ impl Default for CacheControl {
             must_understand: false,
             no_transform: false,
             immutable: false,
-            stale_if_error: false,
+            stale_if_error: None,
         }
     }
 }
@@ -132,8 +168,11 @@ impl CacheControl {
                     ("immutable", None) => {
                         result.immutable = true;
                     }
+                    ("stale-if-error", Some(v)) => {
+                        result.stale_if_error = Some(v.parse()?);
+                    }
                     ("stale-if-error", None) => {
-                        result.stale_if_error = true;
+                        result.stale_if_error = Some(0);
                     }
                     _ => {
                         return Err("invalid Cache-Control header value".into());
@@ -151,6 +190,16 @@ impl CacheControl {
         Ok(result)
     }
 
+    /// Cache control from the `@cacheControl` hints of a query
+    pub(crate) fn from_hint(max_age: Option<u32>, private: bool) -> Self {
+        CacheControl {
+            max_age,
+            private,
+            public: !private,
+            ..Default::default()
+        }
+    }
+
     pub(crate) fn to_headers(&self, headers: &mut HeaderMap) -> Result<(), BoxError> {
         let mut s = String::new();
         let mut prev = false;
@@ -212,8 +261,13 @@ impl CacheControl {
             write!(&mut s, "{}immutable", if prev { "," } else { "" },)?;
             prev = true;
         }
-        if self.stale_if_error {
-            write!(&mut s, "{}stale-if-error", if prev { "," } else { "" },)?;
+        if let Some(sie) = self.stale_if_error {
+            write!(
+                &mut s,
+                "{}stale-if-error={}",
+                if prev { "," } else { "" },
+                sie
+            )?;
         }
         headers.insert(CACHE_CONTROL, HeaderValue::from_str(&s)?);
 
@@ -260,7 +314,12 @@ impl CacheControl {
             must_understand: self.must_understand || other.must_understand,
             no_transform: self.no_transform || other.no_transform,
             immutable: self.immutable || other.immutable,
-            stale_if_error: self.stale_if_error || other.stale_if_error,
+            stale_if_error: match (self.stale_if_error, other.stale_if_error) {
+                (None, None) => None,
+                (None, Some(ttl)) => Some(ttl),
+                (Some(ttl), None) => Some(ttl),
+                (Some(ttl1), Some(ttl2)) => Some(std::cmp::min(ttl1, ttl2)),
+            },
         }
     }
 
@@ -305,15 +364,98 @@ impl CacheControl {
         false
     }
 
-    pub(crate) fn can_use(&self) -> bool {
-        let elapsed = now_epoch_seconds() - self.created;
-        let expired = self
-            .ttl()
-            .map(|ttl| (ttl as u64) < elapsed)
-            .unwrap_or(false);
+    pub(crate) fn freshness(&self) -> Freshness {
+        let elapsed = now_epoch_seconds().saturating_sub(self.created);
+        let ttl = match self.ttl() {
+            None => return Freshness::Fresh,
+            Some(ttl) => ttl as u64,
+        };
+        if elapsed <= ttl {
+            return Freshness::Fresh;
+        }
+        // must-revalidate forbids using a stale response
+        if self.must_revalidate || self.proxy_revalidate {
+            return Freshness::Expired;
+        }
+
+        let within = |window: Option<u32>| window.map_or(false, |w| elapsed <= ttl + w as u64);
+        if within(self.stale_while_revalidate) {
+            Freshness::StaleWhileRevalidate
+        } else if within(self.stale_if_error) {
+            Freshness::StaleIfError
+        } else {
+            Freshness::Expired
+        }
+    }
+
+    /// How long an entry can still be used after it expires
+    pub(crate) fn stale_window(&self) -> Duration {
+        let window = std::cmp::max(
+            self.stale_while_revalidate.unwrap_or(0),
+            self.stale_if_error.unwrap_or(0),
+        );
+        Duration::from_secs(window as u64)
+    }
+
+    /// Uses the configured stale windows if the subgraph response did not define them
+    pub(crate) fn with_stale_defaults(
+        mut self,
+        stale_while_revalidate: Option<Duration>,
+        stale_if_error: Option<Duration>,
+    ) -> Self {
+        if self.stale_while_revalidate.is_none() {
+            self.stale_while_revalidate = stale_while_revalidate.map(|d| d.as_secs() as u32);
+        }
+        if self.stale_if_error.is_none() {
+            self.stale_if_error = stale_if_error.map(|d| d.as_secs() as u32);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache_control(header: &'static str, elapsed: u64) -> CacheControl {
+        let mut headers = HeaderMap::new();
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static(header));
+        let mut cache_control = CacheControl::new(&headers, None).unwrap();
+        cache_control.created -= elapsed;
+        cache_control
+    }
+
+    #[test]
+    fn freshness() {
+        let header = "max-age=10,stale-while-revalidate=20,stale-if-error=60";
+        assert_eq!(cache_control(header, 5).freshness(), Freshness::Fresh);
+        assert_eq!(
+            cache_control(header, 25).freshness(),
+            Freshness::StaleWhileRevalidate
+        );
+        assert_eq!(
+            cache_control(header, 50).freshness(),
+            Freshness::StaleIfError
+        );
+        assert_eq!(cache_control(header, 100).freshness(), Freshness::Expired);
+        assert_eq!(
+            cache_control("max-age=10,stale-while-revalidate=20,must-revalidate", 25).freshness(),
+            Freshness::Expired
+        );
+        assert_eq!(
+            cache_control(header, 0).stale_window(),
+            Duration::from_secs(60)
+        );
+
+        let mut headers = HeaderMap::new();
+        cache_control(header, 0).to_headers(&mut headers).unwrap();
+        assert_eq!(headers[CACHE_CONTROL], header);
+    }
 
-        // FIXME: we don't honor stale-while-revalidate yet
-        // !expired || self.stale_while_revalidate
-        !expired
+    #[test]
+    fn stale_if_error_flag_from_older_entries() {
+        let cache_control: CacheControl =
+            serde_json::from_str(r#"{"created":0,"stale_if_error":true}"#).unwrap();
+        assert_eq!(cache_control.stale_if_error, Some(0));
     }
 }
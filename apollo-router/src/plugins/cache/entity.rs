@@ -1,9 +1,11 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::ops::ControlFlow;
 use std::sync::Arc;
 use std::time::Duration;
 
 use http::header;
+use parking_lot::Mutex;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
@@ -18,9 +20,17 @@ use tower_service::Service;
 use tracing::Level;
 
 use super::cache_control::CacheControl;
-use crate::cache::redis::RedisCacheStorage;
-use crate::cache::redis::RedisKey;
-use crate::cache::redis::RedisValue;
+use super::cache_control::Freshness;
+use super::hints::CacheHint;
+use super::hints::CacheHintConfig;
+use super::hints::CacheHints;
+use crate::cache::backend::DistributedCache;
+use crate::cache::storage::CacheStorage;
+use crate::configuration::CacheBackendConfig;
+use crate::configuration::CacheCompression;
+use crate::configuration::CacheWrite;
+use crate::configuration::InMemoryCache;
+use crate::configuration::NegativeCaching;
 use crate::configuration::RedisCache;
 use crate::error::FetchError;
 use crate::graphql;
@@ -45,22 +55,55 @@ pub(crate) const CONTEXT_CACHE_KEY: &str = "apollo_entity_cache::key";
 register_plugin!("apollo", "experimental_entity_cache", EntityCache);
 
 struct EntityCache {
-    storage: RedisCacheStorage,
+    storage: CacheStorage<String, CacheEntry>,
     subgraphs: Arc<HashMap<String, Subgraph>>,
     enabled: Option<bool>,
+    types: HashMap<String, TypePolicy>,
+    stale: StalePolicy,
+    hints: Option<Arc<CacheHints>>,
+    revalidations: Revalidations,
 }
 
 /// Configuration for entity caching
 #[derive(Clone, Debug, JsonSchema, Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 struct Config {
-    redis: RedisCache,
+    /// Redis cache configuration
+    #[serde(default)]
+    redis: Option<RedisCache>,
+    /// Custom cache backend configuration, instead of Redis
+    #[serde(default)]
+    backend: Option<CacheBackendConfig>,
+    /// How entries are written to the distributed cache
+    #[serde(default)]
+    write: CacheWrite,
+    /// In memory cache in front of the distributed cache (default: disabled)
+    #[serde(default)]
+    in_memory: Option<InMemoryCache>,
+    /// Caches the misses of the distributed cache in memory, to avoid looking them up again
+    #[serde(default)]
+    negative_caching: Option<NegativeCaching>,
     /// activates caching for all subgraphs, unless overriden in subgraph specific configuration
     #[serde(default)]
     enabled: Option<bool>,
     /// Per subgraph configuration
     #[serde(default)]
     subgraphs: HashMap<String, Subgraph>,
+    /// Per type configuration, keyed by entity type name, or `Query` for root fields
+    #[serde(default)]
+    types: HashMap<String, TypePolicy>,
+    /// How long an expired entry is still returned while it is refreshed in the background,
+    /// if the subgraph response does not define `stale-while-revalidate`
+    #[serde(default)]
+    stale_while_revalidate: Option<Ttl>,
+    /// How long an expired entry is still returned when the subgraph fails,
+    /// if the subgraph response does not define `stale-if-error`
+    #[serde(default)]
+    stale_if_error: Option<Ttl>,
+    /// Cache hints, equivalent to the `@cacheControl` directive, keyed by `Type` or `Type.field`.
+    /// They take precedence over the `@cacheControl` directives from the schema
+    #[serde(default)]
+    cache_hints: HashMap<String, CacheHintConfig>,
 }
 
 /// Per subgraph configuration for entity caching
@@ -73,6 +116,101 @@ struct Subgraph {
     /// activates caching for this subgraph, overrides the global configuration
     #[serde(default)]
     enabled: Option<bool>,
+
+    /// Per type configuration for this subgraph, overrides the global per type configuration
+    #[serde(default)]
+    types: HashMap<String, TypePolicy>,
+
+    /// stale-while-revalidate window for this subgraph, overrides the global configuration
+    #[serde(default)]
+    stale_while_revalidate: Option<Ttl>,
+
+    /// stale-if-error window for this subgraph, overrides the global configuration
+    #[serde(default)]
+    stale_if_error: Option<Ttl>,
+}
+
+/// How long the expired entries of a subgraph can still be used, when the `Cache-Control`
+/// header of the subgraph response does not define it
+#[derive(Clone, Copy, Debug, Default)]
+struct StalePolicy {
+    while_revalidate: Option<Duration>,
+    if_error: Option<Duration>,
+}
+
+impl StalePolicy {
+    fn new(global: &StalePolicy, subgraph: Option<&Subgraph>) -> Self {
+        StalePolicy {
+            while_revalidate: subgraph
+                .and_then(|s| s.stale_while_revalidate.as_ref())
+                .map(|ttl| ttl.0)
+                .or(global.while_revalidate),
+            if_error: subgraph
+                .and_then(|s| s.stale_if_error.as_ref())
+                .map(|ttl| ttl.0)
+                .or(global.if_error),
+        }
+    }
+
+    fn apply(&self, cache_control: CacheControl) -> CacheControl {
+        cache_control.with_stale_defaults(self.while_revalidate, self.if_error)
+    }
+}
+
+/// Per type configuration for entity caching
+#[derive(Clone, Debug, Default, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct TypePolicy {
+    /// expiration for the entries of this type, if the subgraph response does not define a max age
+    ttl: Option<Ttl>,
+
+    /// activates caching for this type
+    #[serde(default)]
+    enabled: Option<bool>,
+}
+
+impl TypePolicy {
+    /// The settings of this policy, completed by the ones of a more general policy
+    fn or(&self, other: Option<&TypePolicy>) -> TypePolicy {
+        TypePolicy {
+            ttl: self
+                .ttl
+                .clone()
+                .or_else(|| other.and_then(|o| o.ttl.clone())),
+            enabled: self.enabled.or_else(|| other.and_then(|o| o.enabled)),
+        }
+    }
+}
+
+/// The per type configuration of a subgraph
+#[derive(Clone, Debug, Default)]
+struct TypePolicies(HashMap<String, TypePolicy>);
+
+impl TypePolicies {
+    fn new(global: &HashMap<String, TypePolicy>, subgraph: Option<&Subgraph>) -> Self {
+        let mut policies = global.clone();
+        if let Some(subgraph) = subgraph {
+            for (typename, policy) in &subgraph.types {
+                let policy = policy.or(global.get(typename));
+                policies.insert(typename.clone(), policy);
+            }
+        }
+        Self(policies)
+    }
+
+    fn is_enabled(&self, typename: &str) -> bool {
+        self.0
+            .get(typename)
+            .and_then(|policy| policy.enabled)
+            .unwrap_or(true)
+    }
+
+    fn ttl(&self, typename: &str) -> Option<Duration> {
+        self.0
+            .get(typename)
+            .and_then(|policy| policy.ttl.as_ref())
+            .map(|ttl| ttl.0)
+    }
 }
 
 /// Per subgraph configuration for entity caching
@@ -92,12 +230,33 @@ impl Plugin for EntityCache {
     where
         Self: Sized,
     {
-        let storage = RedisCacheStorage::new(init.config.redis).await?;
+        let storage = DistributedCache::new(
+            init.config.redis,
+            init.config.backend,
+            init.config.write,
+            CacheCompression::None,
+        )
+        .await?
+        .ok_or("the entity cache requires redis or a backend")?;
+        let storage = CacheStorage::with_distributed(
+            "entity",
+            storage,
+            init.config.in_memory,
+            init.config.negative_caching,
+        );
+        let hints = CacheHints::new(&init.supergraph_sdl, &init.config.cache_hints);
 
         Ok(Self {
             storage,
             enabled: init.config.enabled,
             subgraphs: Arc::new(init.config.subgraphs),
+            types: init.config.types,
+            stale: StalePolicy {
+                while_revalidate: init.config.stale_while_revalidate.map(|ttl| ttl.0),
+                if_error: init.config.stale_if_error.map(|ttl| ttl.0),
+            },
+            hints,
+            revalidations: Revalidations::default(),
         })
     }
 
@@ -134,6 +293,8 @@ impl Plugin for EntityCache {
         } else {
             (self.storage.ttl(), self.enabled.unwrap_or(false))
         };
+        let types = Arc::new(TypePolicies::new(&self.types, self.subgraphs.get(name)));
+        let stale = StalePolicy::new(&self.stale, self.subgraphs.get(name));
         let name = name.to_string();
 
         if subgraph_enabled {
@@ -142,6 +303,10 @@ impl Plugin for EntityCache {
                 name: name.to_string(),
                 storage,
                 subgraph_ttl,
+                types,
+                stale,
+                hints: self.hints.clone(),
+                revalidations: self.revalidations.clone(),
             })))
         } else {
             service
@@ -153,8 +318,47 @@ struct CacheService(Option<InnerCacheService>);
 struct InnerCacheService {
     service: subgraph::BoxService,
     name: String,
-    storage: RedisCacheStorage,
+    storage: CacheStorage<String, CacheEntry>,
     subgraph_ttl: Option<Duration>,
+    types: Arc<TypePolicies>,
+    stale: StalePolicy,
+    hints: Option<Arc<CacheHints>>,
+    revalidations: Revalidations,
+}
+
+/// The keys of the stale entries being refreshed in the background, so that the requests served
+/// during the stale window trigger a single refresh of each entry
+#[derive(Clone, Default)]
+struct Revalidations(Arc<Mutex<HashSet<String>>>);
+
+/// The keys claimed for a refresh, released when it is dropped
+struct RevalidationClaim {
+    revalidations: Revalidations,
+    keys: HashSet<String>,
+}
+
+impl Revalidations {
+    /// Claims the keys that are not already being refreshed
+    fn claim(&self, keys: impl IntoIterator<Item = String>) -> RevalidationClaim {
+        let mut revalidating = self.0.lock();
+        let keys = keys
+            .into_iter()
+            .filter(|key| revalidating.insert(key.clone()))
+            .collect();
+        RevalidationClaim {
+            revalidations: self.clone(),
+            keys,
+        }
+    }
+}
+
+impl Drop for RevalidationClaim {
+    fn drop(&mut self) {
+        let mut revalidating = self.revalidations.0.lock();
+        for key in &self.keys {
+            revalidating.remove(key);
+        }
+    }
 }
 
 impl Service<subgraph::Request> for CacheService {
@@ -181,71 +385,184 @@ impl Service<subgraph::Request> for CacheService {
 }
 
 impl InnerCacheService {
-    async fn call_inner(
-        mut self,
-        request: subgraph::Request,
-    ) -> Result<subgraph::Response, BoxError> {
+    async fn call_inner(self, request: subgraph::Request) -> Result<subgraph::Response, BoxError> {
+        let hint = self
+            .hints
+            .as_ref()
+            .and_then(|hints| hints.for_request(&request));
+
         if !request
             .subgraph_request
             .body()
             .variables
             .contains_key(REPRESENTATIONS)
         {
-            if request.operation_kind == OperationKind::Query {
-                match cache_lookup_root(self.name, self.storage.clone(), request).await? {
-                    ControlFlow::Break(response) => Ok(response),
-                    ControlFlow::Continue((request, root_cache_key)) => {
-                        let response = self.service.call(request).await?;
-
-                        let cache_control =
-                            CacheControl::new(response.response.headers(), self.storage.ttl)?;
-                        update_cache_control(&response.context, &cache_control);
-
-                        cache_store_root_from_response(
-                            self.storage,
-                            self.subgraph_ttl,
-                            &response,
-                            cache_control,
-                            root_cache_key,
-                        )
-                        .await?;
-
+            if request.operation_kind == OperationKind::Query && self.types.is_enabled("Query") {
+                match cache_lookup_root(self.name.clone(), self.storage.clone(), request).await? {
+                    RootCacheResult::Hit(response) => Ok(response),
+                    RootCacheResult::Stale {
+                        response,
+                        request,
+                        key,
+                    } => {
+                        // the stale response is used while it is refreshed in the background,
+                        // unless another request is already refreshing it
+                        let claim = self.revalidations.claim([key.clone()]);
+                        if !claim.keys.is_empty() {
+                            tokio::spawn(async move {
+                                let _claim = claim;
+                                if let Err(e) = self.fetch_root(request, key, hint, None).await {
+                                    tracing::error!("could not refresh the cached response: {e}");
+                                }
+                            });
+                        }
                         Ok(response)
                     }
+                    RootCacheResult::Miss {
+                        request,
+                        key,
+                        stale,
+                    } => self.fetch_root(request, key, hint, stale).await,
                 }
             } else {
-                self.service.call(request).await
+                let mut service = self.service;
+                service.call(request).await
             }
         } else {
-            match cache_lookup_entities(self.name, self.storage.clone(), request).await? {
-                ControlFlow::Break(response) => Ok(response),
-                ControlFlow::Continue((request, cache_result)) => {
-                    let mut response = self.service.call(request).await?;
-
-                    let cache_control =
-                        CacheControl::new(response.response.headers(), self.storage.ttl)?;
-                    update_cache_control(&response.context, &cache_control);
-
-                    cache_store_entities_from_response(
-                        self.storage,
-                        self.subgraph_ttl,
-                        &mut response,
-                        cache_control,
-                        cache_result.0,
-                    )
-                    .await?;
+            match cache_lookup_entities(
+                self.name.clone(),
+                self.storage.clone(),
+                self.types.clone(),
+                &self.revalidations,
+                request,
+            )
+            .await?
+            {
+                ControlFlow::Break((response, None)) => Ok(response),
+                ControlFlow::Break((response, Some((request, cache_result, claim)))) => {
+                    // the stale entities are used while they are refreshed in the background
+                    tokio::spawn(async move {
+                        let _claim = claim;
+                        if let Err(e) = self.fetch_entities(request, hint, cache_result).await {
+                            tracing::error!("could not refresh the cached entities: {e}");
+                        }
+                    });
                     Ok(response)
                 }
+                ControlFlow::Continue((request, cache_result)) => {
+                    self.fetch_entities(request, hint, cache_result).await
+                }
+            }
+        }
+    }
+
+    async fn fetch_root(
+        mut self,
+        request: subgraph::Request,
+        root_cache_key: String,
+        hint: Option<CacheHint>,
+        stale: Option<CacheEntry>,
+    ) -> Result<subgraph::Response, BoxError> {
+        let context = request.context.clone();
+        let response = match (self.service.call(request).await, stale) {
+            (Ok(response), Some(stale)) if !response.response.body().errors.is_empty() => {
+                return Ok(root_response_from_cache(stale, context));
+            }
+            (Err(e), Some(stale)) => {
+                tracing::debug!("using a stale cached response after a subgraph error: {e}");
+                return Ok(root_response_from_cache(stale, context));
+            }
+            (response, _) => response?,
+        };
+
+        let cache_control =
+            response_cache_control(response.response.headers(), hint, self.storage.ttl())?;
+        update_cache_control(&response.context, &cache_control);
+
+        cache_store_root_from_response(
+            self.storage,
+            self.types.ttl("Query").or(self.subgraph_ttl),
+            &response,
+            self.stale.apply(cache_control),
+            root_cache_key,
+        )
+        .await?;
+
+        Ok(response)
+    }
+
+    async fn fetch_entities(
+        mut self,
+        request: subgraph::Request,
+        hint: Option<CacheHint>,
+        cache_result: EntityCacheResults,
+    ) -> Result<subgraph::Response, BoxError> {
+        let context = request.context.clone();
+        let response = self.service.call(request).await;
+
+        // if the subgraph fails, stale entries are used if there is one for every missing entity
+        let failed = match &response {
+            Ok(response) => !response.response.body().errors.is_empty(),
+            Err(_) => true,
+        };
+        if failed
+            && cache_result.0.iter().any(|r| r.stale.is_some())
+            && cache_result
+                .0
+                .iter()
+                .all(|r| r.cache_entry.is_some() || r.stale.is_some())
+        {
+            if let Err(e) = &response {
+                tracing::debug!("using stale cached entities after a subgraph error: {e}");
             }
+            let entries = cache_result
+                .0
+                .into_iter()
+                .filter_map(|r| r.cache_entry.or(r.stale));
+            return Ok(entities_response_from_cache(entries, context));
         }
+        let mut response = response?;
+
+        let cache_control =
+            response_cache_control(response.response.headers(), hint, self.storage.ttl())?;
+        update_cache_control(&response.context, &cache_control);
+
+        cache_store_entities_from_response(
+            self.storage,
+            self.subgraph_ttl,
+            &self.types,
+            &mut response,
+            self.stale.apply(cache_control),
+            cache_result.0,
+        )
+        .await?;
+        Ok(response)
     }
 }
 
+/// The result of a cache lookup for a root query
+enum RootCacheResult {
+    /// the response was found in the cache
+    Hit(subgraph::Response),
+    /// an expired response was found in the cache, and can be used while it is refreshed
+    Stale {
+        response: subgraph::Response,
+        request: subgraph::Request,
+        key: String,
+    },
+    /// the subgraph must be queried, with an expired response to use if it fails
+    Miss {
+        request: subgraph::Request,
+        key: String,
+        stale: Option<CacheEntry>,
+    },
+}
+
 async fn cache_lookup_root(
     name: String,
-    cache: RedisCacheStorage,
+    cache: CacheStorage<String, CacheEntry>,
     mut request: subgraph::Request,
-) -> Result<ControlFlow<subgraph::Response, (subgraph::Request, String)>, BoxError> {
+) -> Result<RootCacheResult, BoxError> {
     let body = request.subgraph_request.body_mut();
 
     let key = extract_cache_key_root(
@@ -256,35 +573,82 @@ async fn cache_lookup_root(
         &request.authorization,
     );
 
-    let cache_result: Option<RedisValue<CacheEntry>> = cache.get(RedisKey(key.clone())).await;
+    let cache_result: Option<CacheEntry> = cache.get(&key).await;
 
     match cache_result {
-        Some(value) => {
-            request
-                .context
-                .private_entries
-                .lock()
-                .insert(value.0.control);
-
-            Ok(ControlFlow::Break(
-                subgraph::Response::builder()
-                    .data(value.0.data)
-                    .extensions(Object::new())
-                    .context(request.context)
-                    .build(),
-            ))
-        }
-        None => Ok(ControlFlow::Continue((request, key))),
+        Some(entry) => match entry.control.freshness() {
+            Freshness::Fresh => Ok(RootCacheResult::Hit(root_response_from_cache(
+                entry,
+                request.context,
+            ))),
+            Freshness::StaleWhileRevalidate => Ok(RootCacheResult::Stale {
+                response: root_response_from_cache(entry, request.context.clone()),
+                request,
+                key,
+            }),
+            Freshness::StaleIfError => Ok(RootCacheResult::Miss {
+                request,
+                key,
+                stale: Some(entry),
+            }),
+            Freshness::Expired => Ok(RootCacheResult::Miss {
+                request,
+                key,
+                stale: None,
+            }),
+        },
+        None => Ok(RootCacheResult::Miss {
+            request,
+            key,
+            stale: None,
+        }),
     }
 }
 
+fn root_response_from_cache(entry: CacheEntry, context: Context) -> subgraph::Response {
+    context.private_entries.lock().insert(entry.control);
+
+    subgraph::Response::builder()
+        .data(entry.data)
+        .extensions(Object::new())
+        .context(context)
+        .build()
+}
+
+fn entities_response_from_cache(
+    entries: impl Iterator<Item = CacheEntry>,
+    context: Context,
+) -> subgraph::Response {
+    let entities = entries.map(|entry| entry.data).collect::<Vec<_>>();
+    let mut data = Object::default();
+    data.insert(ENTITIES, entities.into());
+
+    subgraph::Response::builder()
+        .data(data)
+        .extensions(Object::new())
+        .context(context)
+        .build()
+}
+
 struct EntityCacheResults(Vec<IntermediateResult>);
 
+/// The subgraph request and the cache results to refresh stale entities in the background, with
+/// the claim of their keys
+type Revalidation = (subgraph::Request, EntityCacheResults, RevalidationClaim);
+
 async fn cache_lookup_entities(
     name: String,
-    cache: RedisCacheStorage,
+    cache: CacheStorage<String, CacheEntry>,
+    types: Arc<TypePolicies>,
+    revalidations: &Revalidations,
     mut request: subgraph::Request,
-) -> Result<ControlFlow<subgraph::Response, (subgraph::Request, EntityCacheResults)>, BoxError> {
+) -> Result<
+    ControlFlow<
+        (subgraph::Response, Option<Revalidation>),
+        (subgraph::Request, EntityCacheResults),
+    >,
+    BoxError,
+> {
     let body = request.subgraph_request.body_mut();
 
     let keys = extract_cache_keys(
@@ -295,11 +659,28 @@ async fn cache_lookup_entities(
         &request.authorization,
     )?;
 
-    let cache_result: Vec<Option<CacheEntry>> = cache
-        .get_multiple(keys.iter().map(|k| RedisKey(k.clone())).collect::<Vec<_>>())
-        .await
-        .map(|res| res.into_iter().map(|r| r.map(|v| v.0)).collect())
-        .unwrap_or_else(|| std::iter::repeat(None).take(keys.len()).collect());
+    // only look up the entities of the types for which caching is enabled
+    let enabled_keys = keys
+        .iter()
+        .filter(|key| types.is_enabled(&key.typename))
+        .map(|key| key.key.clone())
+        .collect::<Vec<_>>();
+    let mut enabled_results = if enabled_keys.is_empty() {
+        Vec::new()
+    } else {
+        cache.get_multiple(&enabled_keys).await
+    }
+    .into_iter();
+    let cache_result: Vec<Option<CacheEntry>> = keys
+        .iter()
+        .map(|key| {
+            if types.is_enabled(&key.typename) {
+                enabled_results.next().flatten()
+            } else {
+                None
+            }
+        })
+        .collect();
 
     let representations = body
         .variables
@@ -308,7 +689,7 @@ async fn cache_lookup_entities(
         .expect("we already checked that representations exist");
     // remove from representations the entities we already obtained from the cache
     let (new_representations, cache_result, cache_control) =
-        filter_representations(&name, representations, keys, cache_result)?;
+        filter_representations(&name, &types, representations, keys, cache_result)?;
 
     if let Some(control) = cache_control {
         update_cache_control(&request.context, &control);
@@ -323,21 +704,67 @@ async fn cache_lookup_entities(
             EntityCacheResults(cache_result),
         )))
     } else {
-        let entities = cache_result
-            .into_iter()
-            .filter_map(|res| res.cache_entry)
-            .map(|entry| entry.data)
+        let context = request.context.clone();
+
+        // the stale entities are fetched again, without the ones still fresh or already being
+        // refreshed by another request
+        let claim = revalidations.claim(
+            cache_result
+                .iter()
+                .filter(|result| result.revalidate)
+                .map(|result| result.key.clone()),
+        );
+        let revalidated = cache_result
+            .iter()
+            .filter(|result| result.revalidate && claim.keys.contains(&result.key))
+            .map(|result| IntermediateResult {
+                key: result.key.clone(),
+                typename: result.typename.clone(),
+                representation: result.representation.clone(),
+                cache_entry: None,
+                stale: None,
+                revalidate: false,
+            })
             .collect::<Vec<_>>();
-        let mut data = Object::default();
-        data.insert(ENTITIES, entities.into());
+        let revalidation = if revalidated.is_empty() {
+            None
+        } else {
+            let representations = revalidated
+                .iter()
+                .map(|result| result.representation.clone())
+                .collect::<Vec<_>>();
+            body.variables
+                .insert(REPRESENTATIONS, representations.into());
+            Some((request, EntityCacheResults(revalidated), claim))
+        };
 
-        Ok(ControlFlow::Break(
-            subgraph::Response::builder()
-                .data(data)
-                .extensions(Object::new())
-                .context(request.context)
-                .build(),
-        ))
+        let entries = cache_result
+            .into_iter()
+            .filter_map(|result| result.cache_entry);
+        Ok(ControlFlow::Break((
+            entities_response_from_cache(entries, context),
+            revalidation,
+        )))
+    }
+}
+
+/// The cache control of a subgraph response is the one from its headers, restricted by the
+/// cache hints of the query. The default TTL only applies if neither defines a max age
+fn response_cache_control(
+    headers: &http::HeaderMap,
+    hint: Option<CacheHint>,
+    default_ttl: Option<Duration>,
+) -> Result<CacheControl, BoxError> {
+    match hint {
+        None => CacheControl::new(headers, default_ttl),
+        Some(hint) => {
+            let cache_control = CacheControl::new(headers, None)?.merge(&hint.to_cache_control());
+            if cache_control.ttl().is_none() {
+                Ok(cache_control.merge(&CacheControl::new(&http::HeaderMap::new(), default_ttl)?))
+            } else {
+                Ok(cache_control)
+            }
+        }
     }
 }
 
@@ -357,26 +784,27 @@ struct CacheEntry {
 }
 
 async fn cache_store_root_from_response(
-    cache: RedisCacheStorage,
+    cache: CacheStorage<String, CacheEntry>,
     subgraph_ttl: Option<Duration>,
     response: &subgraph::Response,
     cache_control: CacheControl,
     cache_key: String,
 ) -> Result<(), BoxError> {
     if let Some(data) = response.response.body().data.as_ref() {
+        // expired entries are kept while they can still be used
         let ttl: Option<Duration> = cache_control
             .ttl()
-            .map(|secs| Duration::from_secs(secs as u64))
+            .map(|secs| Duration::from_secs(secs as u64) + cache_control.stale_window())
             .or(subgraph_ttl);
 
         if response.response.body().errors.is_empty() && cache_control.should_store() {
             cache
-                .insert(
-                    RedisKey(cache_key),
-                    RedisValue(CacheEntry {
+                .insert_with_ttl(
+                    cache_key,
+                    CacheEntry {
                         control: cache_control,
                         data: data.clone(),
-                    }),
+                    },
                     ttl,
                 )
                 .await;
@@ -387,14 +815,13 @@ async fn cache_store_root_from_response(
 }
 
 async fn cache_store_entities_from_response(
-    cache: RedisCacheStorage,
+    cache: CacheStorage<String, CacheEntry>,
     subgraph_ttl: Option<Duration>,
+    types: &TypePolicies,
     response: &mut subgraph::Response,
     cache_control: CacheControl,
     mut result_from_cache: Vec<IntermediateResult>,
 ) -> Result<(), BoxError> {
-    update_cache_control(&response.context, &cache_control);
-
     let mut data = response.response.body_mut().data.take();
 
     if let Some(mut entities) = data
@@ -411,6 +838,7 @@ async fn cache_store_entities_from_response(
             &response.response.body().errors,
             &cache,
             subgraph_ttl,
+            types,
             cache_control,
             &mut result_from_cache,
         )
@@ -522,7 +950,7 @@ fn extract_cache_keys(
     body: &mut graphql::Request,
     context: &Context,
     cache_key: &CacheKeyMetadata,
-) -> Result<Vec<String>, BoxError> {
+) -> Result<Vec<EntityKey>, BoxError> {
     // hash the query and operation name
     let query_hash = hash_query(query_hash, body);
     // hash more data like variables and authorization status
@@ -561,76 +989,97 @@ fn extract_cache_keys(
             subgraph_name, &typename, hashed_entity_key, query_hash, additional_data_hash
         );
 
+        let typename = typename.to_string();
         representation
             .as_object_mut()
             .map(|o| o.insert(TYPENAME, opt_type));
-        res.push(key);
+        res.push(EntityKey { key, typename });
     }
     Ok(res)
 }
 
+/// the cache key of an entity, with its type
+struct EntityKey {
+    key: String,
+    typename: String,
+}
+
 /// represents the result of a cache lookup for an entity type and key
 struct IntermediateResult {
     key: String,
     typename: String,
+    /// the representation to send to the subgraph if the entity must be fetched
+    representation: Value,
+    /// the cached entity, if it can be used
     cache_entry: Option<CacheEntry>,
+    /// an expired cached entity, to use if the subgraph fails
+    stale: Option<CacheEntry>,
+    /// the cached entity is stale and must be refreshed in the background
+    revalidate: bool,
 }
 
 // build a new list of representations without the ones we got from the cache
 #[allow(clippy::type_complexity)]
 fn filter_representations(
     subgraph_name: &str,
+    types: &TypePolicies,
     representations: &mut Vec<Value>,
-    keys: Vec<String>,
+    keys: Vec<EntityKey>,
     mut cache_result: Vec<Option<CacheEntry>>,
 ) -> Result<(Vec<Value>, Vec<IntermediateResult>, Option<CacheControl>), BoxError> {
-    let mut new_representations: Vec<Value> = Vec::new();
     let mut result = Vec::new();
     let mut cache_hit: HashMap<String, (usize, usize)> = HashMap::new();
     let mut cache_control = None;
 
-    for ((mut representation, key), mut cache_entry) in representations
+    for ((representation, EntityKey { key, typename }), cache_entry) in representations
         .drain(..)
         .zip(keys)
         .zip(cache_result.drain(..))
     {
-        let opt_type = representation
-            .as_object_mut()
-            .and_then(|o| o.remove(TYPENAME))
-            .ok_or_else(|| FetchError::MalformedRequest {
-                reason: "missing __typename in representation".to_string(),
-            })?;
+        let freshness = cache_entry.as_ref().map(|c| c.control.freshness());
+        let (cache_entry, stale, revalidate) = match freshness {
+            Some(Freshness::Fresh) => (cache_entry, None, false),
+            Some(Freshness::StaleWhileRevalidate) => (cache_entry, None, true),
+            Some(Freshness::StaleIfError) => (None, cache_entry, false),
+            Some(Freshness::Expired) | None => (None, None, false),
+        };
 
-        let typename = opt_type.as_str().unwrap_or("-").to_string();
+        result.push(IntermediateResult {
+            key,
+            typename,
+            representation,
+            cache_entry,
+            stale,
+            revalidate,
+        });
+    }
 
-        // do not use that cache entry if it is stale
-        if let Some(false) = cache_entry.as_ref().map(|c| c.control.can_use()) {
-            cache_entry = None;
+    // if some entities must be fetched, the stale ones are fetched with them, and only used if
+    // the subgraph fails
+    if result.iter().any(|r| r.cache_entry.is_none()) {
+        for r in result.iter_mut().filter(|r| r.revalidate) {
+            r.stale = r.cache_entry.take();
+            r.revalidate = false;
         }
+    }
 
-        match cache_entry.as_ref() {
+    let mut new_representations: Vec<Value> = Vec::new();
+    for r in &result {
+        match r.cache_entry.as_ref() {
             None => {
-                cache_hit.entry(typename.clone()).or_default().1 += 1;
-
-                representation
-                    .as_object_mut()
-                    .map(|o| o.insert(TYPENAME, opt_type));
-                new_representations.push(representation);
+                if types.is_enabled(&r.typename) {
+                    cache_hit.entry(r.typename.clone()).or_default().1 += 1;
+                }
+                new_representations.push(r.representation.clone());
             }
             Some(entry) => {
-                cache_hit.entry(typename.clone()).or_default().0 += 1;
+                cache_hit.entry(r.typename.clone()).or_default().0 += 1;
                 match cache_control.as_mut() {
                     None => cache_control = Some(entry.control.clone()),
                     Some(c) => *c = c.merge(&entry.control),
                 }
             }
         }
-
-        result.push(IntermediateResult {
-            key,
-            typename,
-            cache_entry,
-        });
     }
 
     for (ty, (hit, miss)) in cache_hit {
@@ -661,21 +1110,23 @@ fn filter_representations(
 async fn insert_entities_in_result(
     entities: &mut Vec<Value>,
     errors: &[Error],
-    cache: &RedisCacheStorage,
+    cache: &CacheStorage<String, CacheEntry>,
     subgraph_ttl: Option<Duration>,
+    types: &TypePolicies,
     cache_control: CacheControl,
     result: &mut Vec<IntermediateResult>,
 ) -> Result<(Vec<Value>, Vec<Error>), BoxError> {
+    // expired entries are kept while they can still be used
     let ttl: Option<Duration> = cache_control
         .ttl()
-        .map(|secs| Duration::from_secs(secs as u64))
-        .or(subgraph_ttl);
+        .map(|secs| Duration::from_secs(secs as u64) + cache_control.stale_window());
 
     let mut new_entities = Vec::new();
     let mut new_errors = Vec::new();
 
     let mut inserted_types: HashMap<String, usize> = HashMap::new();
-    let mut to_insert: Vec<_> = Vec::new();
+    // entries are inserted in batches sharing the same TTL
+    let mut to_insert: HashMap<Option<Duration>, Vec<_>> = HashMap::new();
     let mut entities_it = entities.drain(..).enumerate();
 
     // insert requested entities and cached entities in the same order as
@@ -686,6 +1137,7 @@ async fn insert_entities_in_result(
             key,
             typename,
             cache_entry,
+            ..
         },
     ) in result.drain(..).enumerate()
     {
@@ -701,7 +1153,8 @@ async fn insert_entities_in_result(
                             reason: "invalid number of entities".to_string(),
                         })?;
 
-                if cache_control.should_store() {
+                if cache_control.should_store() && types.is_enabled(&typename) {
+                    let entity_ttl = ttl.or_else(|| types.ttl(&typename)).or(subgraph_ttl);
                     *inserted_types.entry(typename).or_default() += 1;
 
                     let mut has_errors = false;
@@ -727,12 +1180,12 @@ async fn insert_entities_in_result(
                     }
 
                     if !has_errors {
-                        to_insert.push((
-                            RedisKey(key),
-                            RedisValue(CacheEntry {
+                        to_insert.entry(entity_ttl).or_default().push((
+                            key,
+                            CacheEntry {
                                 control: cache_control.clone(),
                                 data: value.clone(),
-                            }),
+                            },
                         ));
                     }
                 }
@@ -742,8 +1195,8 @@ async fn insert_entities_in_result(
         }
     }
 
-    if !to_insert.is_empty() {
-        cache.insert_multiple(&to_insert, ttl).await;
+    for (ttl, to_insert) in to_insert {
+        cache.insert_multiple(to_insert, ttl).await;
     }
 
     for (ty, nb) in inserted_types {
@@ -759,3 +1212,84 @@ struct Key {
     opt_type: Option<Value>,
     id: Value,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subgraph_type_policies_override_global_ones() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "redis": { "urls": ["redis://localhost:6379"] },
+            "enabled": true,
+            "types": {
+                "Product": { "ttl": "60s" },
+                "Inventory": { "enabled": false },
+                "Query": { "ttl": "5s" }
+            },
+            "subgraphs": {
+                "products": {
+                    "types": {
+                        "Product": { "enabled": false },
+                        "Query": { "ttl": "10s" }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        let policies = TypePolicies::new(&config.types, config.subgraphs.get("products"));
+        assert!(!policies.is_enabled("Product"));
+        assert_eq!(policies.ttl("Product"), Some(Duration::from_secs(60)));
+        assert!(!policies.is_enabled("Inventory"));
+        assert_eq!(policies.ttl("Query"), Some(Duration::from_secs(10)));
+        assert!(policies.is_enabled("User"));
+        assert_eq!(policies.ttl("User"), None);
+
+        let policies = TypePolicies::new(&config.types, config.subgraphs.get("reviews"));
+        assert!(policies.is_enabled("Product"));
+        assert_eq!(policies.ttl("Query"), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn subgraph_stale_policy_overrides_global_one() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "redis": { "urls": ["redis://localhost:6379"] },
+            "stale_while_revalidate": "30s",
+            "stale_if_error": "5m",
+            "subgraphs": {
+                "products": {
+                    "stale_while_revalidate": "5s"
+                }
+            }
+        }))
+        .unwrap();
+        let global = StalePolicy {
+            while_revalidate: config.stale_while_revalidate.map(|ttl| ttl.0),
+            if_error: config.stale_if_error.map(|ttl| ttl.0),
+        };
+
+        let products = StalePolicy::new(&global, config.subgraphs.get("products"));
+        assert_eq!(products.while_revalidate, Some(Duration::from_secs(5)));
+        assert_eq!(products.if_error, Some(Duration::from_secs(300)));
+
+        let cache_control =
+            products.apply(CacheControl::new(&http::HeaderMap::new(), None).unwrap());
+        assert_eq!(cache_control.stale_window(), Duration::from_secs(300));
+    }
+
+    #[test]
+    fn stale_entries_are_refreshed_once_at_a_time() {
+        let revalidations = Revalidations::default();
+        let claim = revalidations.claim(["a".to_string(), "b".to_string()]);
+        assert_eq!(claim.keys.len(), 2);
+
+        // the keys being refreshed are not claimed again
+        let concurrent = revalidations.claim(["b".to_string(), "c".to_string()]);
+        assert_eq!(concurrent.keys, HashSet::from(["c".to_string()]));
+
+        // they are released when the refresh is done
+        drop(claim);
+        assert_eq!(revalidations.claim(["b".to_string()]).keys.len(), 1);
+    }
+}
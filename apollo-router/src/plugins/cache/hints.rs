@@ -0,0 +1,425 @@
+//! Cache hints
+//!
+//! Interpretation of the `@cacheControl` directive, as defined by Apollo Server:
+//!
+//! ```graphql
+//! enum CacheControlScope {
+//!   PUBLIC
+//!   PRIVATE
+//! }
+//!
+//! directive @cacheControl(
+//!   maxAge: Int
+//!   scope: CacheControlScope
+//! ) on FIELD_DEFINITION | OBJECT | INTERFACE | UNION
+//! ```
+//!
+//! The directive must be kept in the supergraph schema with `@composeDirective`. Hints can also
+//! be defined in the entity cache configuration.
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use apollo_compiler::ast;
+use apollo_compiler::schema;
+use apollo_compiler::schema::ExtendedType;
+use lru::LruCache;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use super::cache_control::CacheControl;
+use super::entity::REPRESENTATIONS;
+use crate::services::subgraph;
+use crate::spec::query::transform;
+use crate::spec::query::traverse;
+
+pub(crate) const CACHE_CONTROL_DIRECTIVE_NAME: &str = "cacheControl";
+const HINTS_CACHE_CAPACITY: usize = 512;
+
+/// Cache hint for a type or field, equivalent to the `@cacheControl` directive
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) struct CacheHintConfig {
+    /// maximum age of the cached data
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    max_age: Option<Duration>,
+    /// whether the data can be shared between users (default: public)
+    #[serde(default)]
+    scope: CacheScope,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CacheScope {
+    /// the data can be shared between users
+    #[default]
+    Public,
+    /// the data is specific to a user
+    Private,
+}
+
+/// Aggregated cache hint: the smallest max age and the most restrictive scope
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct CacheHint {
+    pub(crate) max_age: Option<u32>,
+    pub(crate) private: bool,
+}
+
+impl CacheHint {
+    fn merge(&mut self, other: &CacheHint) {
+        self.max_age = match (self.max_age, other.max_age) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.private |= other.private;
+    }
+
+    pub(crate) fn to_cache_control(self) -> CacheControl {
+        CacheControl::from_hint(self.max_age, self.private)
+    }
+}
+
+impl From<&CacheHintConfig> for CacheHint {
+    fn from(config: &CacheHintConfig) -> Self {
+        CacheHint {
+            max_age: config.max_age.map(|max_age| max_age.as_secs() as u32),
+            private: config.scope == CacheScope::Private,
+        }
+    }
+}
+
+/// Cache hints of the types and fields of the supergraph schema
+pub(crate) struct CacheHints {
+    schema: schema::Schema,
+    types: HashMap<String, CacheHint>,
+    fields: HashMap<(String, String), CacheHint>,
+    /// hints already computed for subgraph queries, by query hash
+    queries: Mutex<LruCache<Vec<u8>, Option<CacheHint>>>,
+}
+
+impl CacheHints {
+    /// Extract the hints from the schema, then apply the hints from the configuration,
+    /// keyed by `Type` or `Type.field`. Returns `None` if there is no hint.
+    pub(crate) fn new(
+        supergraph_sdl: &str,
+        configured: &HashMap<String, CacheHintConfig>,
+    ) -> Option<Arc<Self>> {
+        let schema = schema::Schema::parse(supergraph_sdl, "schema.graphql")
+            .unwrap_or_else(|invalid| invalid.partial);
+
+        let mut types = HashMap::new();
+        let mut fields = HashMap::new();
+        for (type_name, ty) in &schema.types {
+            if let Some(hint) = directive_hint(ty.directives().get(CACHE_CONTROL_DIRECTIVE_NAME)) {
+                types.insert(type_name.to_string(), hint);
+            }
+            let type_fields = match ty {
+                ExtendedType::Object(ty) => &ty.fields,
+                ExtendedType::Interface(ty) => &ty.fields,
+                _ => continue,
+            };
+            for (field_name, field) in type_fields {
+                if let Some(hint) =
+                    directive_hint(field.directives.get(CACHE_CONTROL_DIRECTIVE_NAME))
+                {
+                    fields.insert((type_name.to_string(), field_name.to_string()), hint);
+                }
+            }
+        }
+
+        for (coordinate, hint) in configured {
+            match coordinate.split_once('.') {
+                Some((type_name, field_name)) => {
+                    fields.insert((type_name.to_string(), field_name.to_string()), hint.into());
+                }
+                None => {
+                    types.insert(coordinate.clone(), hint.into());
+                }
+            }
+        }
+
+        if types.is_empty() && fields.is_empty() {
+            return None;
+        }
+
+        Some(Arc::new(Self {
+            schema,
+            types,
+            fields,
+            queries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(HINTS_CACHE_CAPACITY).expect("capacity is not zero"),
+            )),
+        }))
+    }
+
+    /// Aggregate the hints of the types and fields queried by a subgraph request
+    pub(crate) fn for_request(&self, request: &subgraph::Request) -> Option<CacheHint> {
+        let query_hash = &request.query_hash.0;
+        if !query_hash.is_empty() {
+            if let Some(hint) = self.queries.lock().unwrap().get(query_hash) {
+                return *hint;
+            }
+        }
+
+        let body = request.subgraph_request.body();
+        let hint = body.query.as_deref().and_then(|query| {
+            let entity_query = body.variables.contains_key(REPRESENTATIONS);
+            self.for_query(query, entity_query)
+                .map_err(|err| {
+                    tracing::debug!("cannot extract cache hints from subgraph query: {err}")
+                })
+                .ok()
+                .flatten()
+        });
+
+        if !query_hash.is_empty() {
+            self.queries.lock().unwrap().put(query_hash.clone(), hint);
+        }
+        hint
+    }
+
+    fn for_query(&self, query: &str, entity_query: bool) -> Result<Option<CacheHint>, BoxError> {
+        let doc =
+            ast::Document::parse(query, "query.graphql").unwrap_or_else(|invalid| invalid.partial);
+        let mut visitor = CacheHintVisitor {
+            hints: self,
+            fragments: transform::collect_fragments(&doc),
+            entity_query,
+            hint: None,
+        };
+        traverse::document(&mut visitor, &doc)?;
+        Ok(visitor.hint)
+    }
+}
+
+fn directive_hint(directive: Option<&impl AsRef<ast::Directive>>) -> Option<CacheHint> {
+    let directive = directive?.as_ref();
+    Some(CacheHint {
+        max_age: directive
+            .argument_by_name("maxAge")
+            .and_then(|value| value.to_i32())
+            .map(|max_age| max_age.max(0) as u32),
+        private: directive
+            .argument_by_name("scope")
+            .and_then(|value| value.as_enum())
+            .map(|scope| scope.as_str() == "PRIVATE")
+            .unwrap_or(false),
+    })
+}
+
+struct CacheHintVisitor<'a> {
+    hints: &'a CacheHints,
+    fragments: HashMap<&'a ast::Name, &'a ast::FragmentDefinition>,
+    entity_query: bool,
+    hint: Option<CacheHint>,
+}
+
+impl<'a> CacheHintVisitor<'a> {
+    fn add(&mut self, hint: Option<&CacheHint>) {
+        if let Some(hint) = hint {
+            match self.hint.as_mut() {
+                None => self.hint = Some(*hint),
+                Some(current) => current.merge(hint),
+            }
+        }
+    }
+
+    fn add_type(&mut self, type_name: &str) {
+        self.add(self.hints.types.get(type_name));
+    }
+
+    fn entities_operation(&mut self, node: &ast::OperationDefinition) -> Result<(), BoxError> {
+        use crate::spec::query::traverse::Visitor;
+
+        for selection in &node.selection_set {
+            let ast::Selection::Field(field) = selection else {
+                return Err("expected _entities field".into());
+            };
+            for selection in &field.selection_set {
+                if let ast::Selection::InlineFragment(f) = selection {
+                    if let Some(condition) = f.type_condition.as_ref() {
+                        self.inline_fragment(condition.as_str(), f)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a> traverse::Visitor for CacheHintVisitor<'a> {
+    fn operation(
+        &mut self,
+        root_type: &str,
+        node: &ast::OperationDefinition,
+    ) -> Result<(), BoxError> {
+        if !self.entity_query {
+            traverse::operation(self, root_type, node)
+        } else {
+            self.entities_operation(node)
+        }
+    }
+
+    fn field(
+        &mut self,
+        parent_type: &str,
+        field_def: &ast::FieldDefinition,
+        node: &ast::Field,
+    ) -> Result<(), BoxError> {
+        let hints = self.hints;
+        self.add(
+            hints
+                .fields
+                .get(&(parent_type.to_string(), field_def.name.to_string())),
+        );
+        self.add_type(field_def.ty.inner_named_type());
+
+        traverse::field(self, field_def, node)
+    }
+
+    fn fragment_definition(&mut self, node: &ast::FragmentDefinition) -> Result<(), BoxError> {
+        self.add_type(&node.type_condition);
+        traverse::fragment_definition(self, node)
+    }
+
+    fn fragment_spread(&mut self, node: &ast::FragmentSpread) -> Result<(), BoxError> {
+        let type_condition = &self
+            .fragments
+            .get(&node.fragment_name)
+            .ok_or("MissingFragment")?
+            .type_condition;
+        self.add_type(type_condition);
+        traverse::fragment_spread(self, node)
+    }
+
+    fn inline_fragment(
+        &mut self,
+        parent_type: &str,
+        node: &ast::InlineFragment,
+    ) -> Result<(), BoxError> {
+        if let Some(type_condition) = &node.type_condition {
+            self.add_type(type_condition);
+        }
+        traverse::inline_fragment(self, parent_type, node)
+    }
+
+    fn schema(&self) -> &apollo_compiler::Schema {
+        &self.hints.schema
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static SCHEMA: &str = r#"
+    directive @cacheControl(maxAge: Int, scope: CacheControlScope) on FIELD_DEFINITION | OBJECT | INTERFACE | UNION
+    enum CacheControlScope { PUBLIC PRIVATE }
+
+    type Query {
+        topProducts: [Product] @cacheControl(maxAge: 60)
+        me: User
+    }
+
+    type Product @cacheControl(maxAge: 300) {
+        upc: String!
+        name: String
+        price: Int @cacheControl(maxAge: 30)
+    }
+
+    type User {
+        id: ID!
+        name: String
+    }
+    "#;
+
+    fn hints(configured: &str) -> Arc<CacheHints> {
+        let configured: HashMap<String, CacheHintConfig> =
+            serde_yaml::from_str(configured).unwrap();
+        CacheHints::new(SCHEMA, &configured).unwrap()
+    }
+
+    #[test]
+    fn it_aggregates_directive_hints() {
+        let hints = hints("{}");
+
+        assert_eq!(
+            hints
+                .for_query("{ topProducts { upc name } }", false)
+                .unwrap(),
+            Some(CacheHint {
+                max_age: Some(60),
+                private: false,
+            })
+        );
+        assert_eq!(
+            hints
+                .for_query(
+                    "{ topProducts { upc ...P } } fragment P on Product { price }",
+                    false
+                )
+                .unwrap(),
+            Some(CacheHint {
+                max_age: Some(30),
+                private: false,
+            })
+        );
+        assert_eq!(hints.for_query("{ me { id } }", false).unwrap(), None);
+        assert_eq!(
+            hints
+                .for_query(
+                    "query($representations: [_Any!]!) { _entities(representations: $representations) { ... on Product { name } } }",
+                    true
+                )
+                .unwrap(),
+            Some(CacheHint {
+                max_age: Some(300),
+                private: false,
+            })
+        );
+    }
+
+    #[test]
+    fn it_applies_configured_hints() {
+        let hints = hints(
+            r#"
+            Query.me:
+                max_age: 10s
+                scope: private
+            Product:
+                max_age: 120s
+            "#,
+        );
+
+        assert_eq!(
+            hints.for_query("{ me { id } }", false).unwrap(),
+            Some(CacheHint {
+                max_age: Some(10),
+                private: true,
+            })
+        );
+        assert_eq!(
+            hints.for_query("{ topProducts { name } }", false).unwrap(),
+            Some(CacheHint {
+                max_age: Some(60),
+                private: false,
+            })
+        );
+        assert_eq!(
+            hints
+                .for_query(
+                    "query($representations: [_Any!]!) { _entities(representations: $representations) { ... on Product { name } } }",
+                    true
+                )
+                .unwrap(),
+            Some(CacheHint {
+                max_age: Some(120),
+                private: false,
+            })
+        );
+    }
+}
@@ -1,2 +1,3 @@
 pub(crate) mod cache_control;
 pub(crate) mod entity;
+pub(crate) mod hints;
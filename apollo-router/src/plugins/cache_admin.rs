@@ -0,0 +1,246 @@
+//! An endpoint inspecting the in-memory caches of the router at runtime: their statistics, their
+//! most used keys, and clearing them, to size them or to recover from a bad entry without
+//! restarting the router.
+//!
+//! The endpoint is disabled by default and served on its own listener, which should only be
+//! reachable by administrators.
+use futures::future::join_all;
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::cache::registry::in_memory_caches;
+use crate::cache::registry::KeyHits;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::router;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+register_plugin!("apollo", "experimental_cache_admin", CacheAdminEndpoint);
+
+/// How many keys are returned by default
+const DEFAULT_KEYS_LIMIT: usize = 20;
+
+/// Expose an endpoint inspecting and clearing the in-memory caches at runtime
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Set to true to enable the cache endpoint
+    enabled: bool,
+    /// The listen address of the cache endpoint. It should only be reachable by administrators
+    listen: ListenAddr,
+    /// The path of the cache endpoint
+    path: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: ListenAddr::SocketAddr("127.0.0.1:6060".parse().expect("valid listenAddr")),
+            path: "/caches".to_string(),
+        }
+    }
+}
+
+struct CacheAdminEndpoint {
+    config: Config,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct CacheParameters {
+    /// The name of a cache: `query planner`, `APQ`, `introspection` or `document`
+    cache: Option<String>,
+    /// How many keys to return
+    limit: Option<usize>,
+}
+
+fn response(
+    status: StatusCode,
+    body: impl Serialize,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(&body)?.into())?,
+        context,
+    })
+}
+
+fn error(
+    status: StatusCode,
+    message: impl Into<String>,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    response(
+        status,
+        serde_json::json!({ "error": message.into() }),
+        context,
+    )
+}
+
+/// `GET` returns the statistics of every in-memory cache, or the hottest keys of the cache named
+/// by the `cache` parameter, and `DELETE` clears the cache named by the `cache` parameter
+async fn cache_admin(request: router::Request) -> Result<router::Response, BoxError> {
+    let context = request.context;
+    let parameters: CacheParameters = match serde_urlencoded::from_str(
+        request.router_request.uri().query().unwrap_or_default(),
+    ) {
+        Ok(parameters) => parameters,
+        Err(e) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid parameters: {e}"),
+                context,
+            )
+        }
+    };
+    let method = request.router_request.method().clone();
+    if method != Method::GET && method != Method::DELETE {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "", context);
+    }
+
+    let Some(name) = parameters.cache else {
+        if method == Method::DELETE {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "the `cache` parameter is required",
+                context,
+            );
+        }
+        let caches = in_memory_caches(None);
+        let stats = join_all(caches.iter().map(|cache| cache.stats())).await;
+        return response(StatusCode::OK, stats, context);
+    };
+
+    // several instances of a cache can be in use while the router reloads
+    let caches = in_memory_caches(Some(&name));
+    if caches.is_empty() {
+        return error(
+            StatusCode::NOT_FOUND,
+            format!("unknown cache '{name}'"),
+            context,
+        );
+    }
+
+    if method == Method::DELETE {
+        join_all(caches.iter().map(|cache| cache.clear())).await;
+        tracing::info!("the {name} cache was cleared");
+        let stats = join_all(caches.iter().map(|cache| cache.stats())).await;
+        response(StatusCode::OK, stats, context)
+    } else {
+        let limit = parameters.limit.unwrap_or(DEFAULT_KEYS_LIMIT);
+        let mut keys: Vec<KeyHits> = join_all(caches.iter().map(|cache| cache.hottest_keys(limit)))
+            .await
+            .into_iter()
+            .flatten()
+            .collect();
+        keys.sort_by(|a, b| b.hits.cmp(&a.hits));
+        keys.truncate(limit);
+        response(StatusCode::OK, keys, context)
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for CacheAdminEndpoint {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(CacheAdminEndpoint {
+            config: init.config,
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if !self.config.enabled {
+            return map;
+        }
+
+        map.insert(
+            self.config.listen.clone(),
+            Endpoint::from_router_service(
+                self.config.path.clone(),
+                tower::service_fn(cache_admin).boxed(),
+            ),
+        );
+        tracing::info!(
+            "Cache endpoint exposed at {}{}",
+            self.config.listen,
+            self.config.path
+        );
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::storage::CacheStorage;
+    use crate::configuration::Cache;
+
+    async fn call(method: Method, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(router::Body::empty())
+            .unwrap()
+            .into();
+        let response = cache_admin(request).await.unwrap().response;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_inspects_and_clears_caches() {
+        let storage: CacheStorage<String, usize> =
+            CacheStorage::new(Cache::default(), "admin_test").await;
+        storage.insert("a".to_string(), 1).await;
+        storage.insert("b".to_string(), 2).await;
+        storage.get(&"b".to_string()).await;
+        storage.get(&"c".to_string()).await;
+
+        let (status, caches) = call(Method::GET, "/caches").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(caches
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|cache| cache["name"] == "admin_test"
+                && cache["size"] == 2
+                && cache["hits"] == 1
+                && cache["misses"] == 1));
+
+        assert_eq!(
+            call(Method::GET, "/caches?cache=admin_test&limit=1").await,
+            (
+                StatusCode::OK,
+                serde_json::json!([{ "key": "b", "hits": 1 }])
+            )
+        );
+
+        let (status, _) = call(Method::DELETE, "/caches?cache=admin_test").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(storage.len().await, 0);
+
+        assert_eq!(
+            call(Method::DELETE, "/caches?cache=unknown").await.0,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            call(Method::DELETE, "/caches").await.0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+}
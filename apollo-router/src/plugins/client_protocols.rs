@@ -0,0 +1,385 @@
+//! Report which protocols and features each client uses, such as APQ, `@defer` or batching.
+//!
+//! Every request is metered by client name and feature, and a summary is logged periodically, so
+//! that legacy behaviors can be deprecated once no client relies on them anymore.
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
+
+use http::Method;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::configuration::Batching;
+use crate::context::OPERATION_KIND;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::query_planner::OperationKind;
+use crate::register_plugin;
+use crate::services::router::ClientRequestAccepts;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "experimental_client_protocols", ClientProtocols);
+
+/// The client name of the requests without one
+const UNKNOWN_CLIENT: &str = "unknown";
+/// The client name of the requests from clients beyond `max_clients`
+const OTHER_CLIENTS: &str = "other";
+
+/// Report the protocols and features used by each client
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Meter and log the protocols and features used by each client (default: false)
+    enabled: bool,
+    /// Interval between the summaries logged of the protocols and features used by each client
+    /// since the previous summary (default: 1h)
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    summary_interval: Duration,
+    /// Maximum number of client names reported. The requests of the other clients are reported
+    /// under the `other` client name (default: 100)
+    max_clients: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            summary_interval: Duration::from_secs(3600),
+            max_clients: 100,
+        }
+    }
+}
+
+/// A protocol or feature used by a request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Feature {
+    /// Automatic persisted queries
+    Apq,
+    /// Several operations in a single HTTP request
+    Batching,
+    /// Accepts the multipart responses of `@defer`
+    DeferMultipart,
+    /// A GET request
+    Get,
+    /// A subscription, whose events are sent as multipart responses
+    SubscriptionMultipart,
+    /// A subscription from a client that does not accept multipart responses, which the router
+    /// rejects
+    SubscriptionUnsupported,
+}
+
+impl Feature {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Apq => "apq",
+            Feature::Batching => "batching",
+            Feature::DeferMultipart => "defer_multipart",
+            Feature::Get => "get",
+            Feature::SubscriptionMultipart => "subscription_multipart",
+            Feature::SubscriptionUnsupported => "subscription_unsupported",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Usage {
+    /// The client names reported so far, at most `max_clients`
+    clients: HashSet<String>,
+    /// The number of requests using each feature by client name, since the previous summary
+    counts: BTreeMap<(String, Feature), u64>,
+}
+
+impl Usage {
+    /// The client name to report, which is `other` once `max_clients` client names were reported
+    fn client_name(&mut self, client_name: String, max_clients: usize) -> String {
+        if self.clients.contains(&client_name) {
+            client_name
+        } else if self.clients.len() < max_clients {
+            self.clients.insert(client_name.clone());
+            client_name
+        } else {
+            OTHER_CLIENTS.to_string()
+        }
+    }
+}
+
+struct ClientProtocols {
+    enabled: bool,
+    max_clients: usize,
+    usage: Arc<Mutex<Usage>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ClientProtocols {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let usage = Arc::new(Mutex::new(Usage::default()));
+        if init.config.enabled {
+            tokio::spawn(log_summaries(
+                Arc::downgrade(&usage),
+                init.config.summary_interval,
+            ));
+        }
+        Ok(ClientProtocols {
+            enabled: init.config.enabled,
+            max_clients: init.config.max_clients,
+            usage,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.enabled {
+            return service;
+        }
+
+        let usage = self.usage.clone();
+        let max_clients = self.max_clients;
+        ServiceBuilder::new()
+            .map_request(move |request: supergraph::Request| {
+                let client_name: String = request
+                    .context
+                    .get(CLIENT_NAME)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| UNKNOWN_CLIENT.to_string());
+                let features = features(&request);
+                let mut usage = usage.lock();
+                let client_name = usage.client_name(client_name, max_clients);
+                for feature in features {
+                    u64_counter!(
+                        "apollo.router.client.protocols",
+                        "Number of requests using a protocol or feature, by client name",
+                        1,
+                        client.name = client_name.clone(),
+                        feature = feature.as_str()
+                    );
+                    *usage
+                        .counts
+                        .entry((client_name.clone(), feature))
+                        .or_default() += 1;
+                }
+                drop(usage);
+                request
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+/// The protocols and features used by a request
+fn features(request: &supergraph::Request) -> Vec<Feature> {
+    let mut features = Vec::new();
+    if request
+        .supergraph_request
+        .body()
+        .extensions
+        .contains_key("persistedQuery")
+    {
+        features.push(Feature::Apq);
+    }
+    let is_subscription = matches!(
+        request.context.get(OPERATION_KIND),
+        Ok(Some(OperationKind::Subscription))
+    );
+    let entries = request.context.private_entries.lock();
+    if entries.contains_key::<Batching>() {
+        features.push(Feature::Batching);
+    }
+    let accepts = entries.get::<ClientRequestAccepts>();
+    if accepts.map(|accepts| accepts.multipart_defer) == Some(true) {
+        features.push(Feature::DeferMultipart);
+    }
+    if is_subscription {
+        if accepts.map(|accepts| accepts.multipart_subscription) == Some(true) {
+            features.push(Feature::SubscriptionMultipart);
+        } else {
+            features.push(Feature::SubscriptionUnsupported);
+        }
+    }
+    drop(entries);
+    if request.supergraph_request.method() == Method::GET {
+        features.push(Feature::Get);
+    }
+    features
+}
+
+/// Logs a summary of the usage after every interval, until the plugin is dropped
+async fn log_summaries(usage: Weak<Mutex<Usage>>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    // The first tick completes immediately
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let Some(usage) = usage.upgrade() else {
+            break;
+        };
+        let summary = summary(&std::mem::take(&mut usage.lock().counts));
+        if !summary.is_empty() {
+            tracing::info!("protocols and features used by clients: {summary}");
+        }
+    }
+}
+
+/// Summarizes the usage as `client: feature=count, ...; client: ...`
+fn summary(usage: &BTreeMap<(String, Feature), u64>) -> String {
+    let mut summary = String::new();
+    let mut current_client = None;
+    for ((client_name, feature), count) in usage {
+        if current_client != Some(client_name) {
+            if current_client.is_some() {
+                summary.push_str("; ");
+            }
+            let _ = write!(summary, "{client_name}: ");
+            current_client = Some(client_name);
+        } else {
+            summary.push_str(", ");
+        }
+        let _ = write!(summary, "{}={count}", feature.as_str());
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::FutureMetricsExt;
+
+    #[tokio::test]
+    async fn it_reports_the_features_of_clients() {
+        async {
+            let plugin = ClientProtocols::new(PluginInit::fake_new(
+                serde_json::from_value(serde_json::json!({ "enabled": true })).unwrap(),
+                Default::default(),
+            ))
+            .await
+            .unwrap();
+            let service = plugin.supergraph_service(
+                tower::service_fn(|request: supergraph::Request| async move {
+                    Ok(supergraph::Response::fake_builder()
+                        .context(request.context)
+                        .build()
+                        .unwrap())
+                })
+                .boxed(),
+            );
+
+            let context = crate::Context::new();
+            context.insert(CLIENT_NAME, "web".to_string()).unwrap();
+            context.private_entries.lock().insert(ClientRequestAccepts {
+                multipart_defer: true,
+                ..Default::default()
+            });
+            let mut extensions = serde_json_bytes::Map::new();
+            extensions.insert("persistedQuery", serde_json_bytes::json!({ "version": 1 }));
+            let request = supergraph::Request::fake_builder()
+                .query("{ me }")
+                .extensions(extensions)
+                .context(context)
+                .build()
+                .unwrap();
+            service.oneshot(request).await.unwrap();
+
+            assert_counter!(
+                "apollo.router.client.protocols",
+                1,
+                "client.name" = "web",
+                "feature" = "apq"
+            );
+            assert_counter!(
+                "apollo.router.client.protocols",
+                1,
+                "client.name" = "web",
+                "feature" = "defer_multipart"
+            );
+            assert_eq!(
+                summary(&plugin.usage.lock().counts),
+                "web: apq=1, defer_multipart=1"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn it_reports_subscriptions_and_bounds_the_client_names() {
+        async {
+            let plugin = ClientProtocols::new(PluginInit::fake_new(
+                serde_json::from_value(serde_json::json!({ "enabled": true, "max_clients": 1 }))
+                    .unwrap(),
+                Default::default(),
+            ))
+            .await
+            .unwrap();
+
+            for (client_name, multipart_subscription) in [("web", true), ("android", false)] {
+                let service = plugin.supergraph_service(
+                    tower::service_fn(|request: supergraph::Request| async move {
+                        Ok(supergraph::Response::fake_builder()
+                            .context(request.context)
+                            .build()
+                            .unwrap())
+                    })
+                    .boxed(),
+                );
+                let context = crate::Context::new();
+                context
+                    .insert(CLIENT_NAME, client_name.to_string())
+                    .unwrap();
+                context
+                    .insert(OPERATION_KIND, OperationKind::Subscription)
+                    .unwrap();
+                context.private_entries.lock().insert(ClientRequestAccepts {
+                    multipart_subscription,
+                    ..Default::default()
+                });
+                let request = supergraph::Request::fake_builder()
+                    .query("subscription { userWasCreated { id } }")
+                    .context(context)
+                    .build()
+                    .unwrap();
+                service.oneshot(request).await.unwrap();
+            }
+
+            assert_counter!(
+                "apollo.router.client.protocols",
+                1,
+                "client.name" = "web",
+                "feature" = "subscription_multipart"
+            );
+            assert_counter!(
+                "apollo.router.client.protocols",
+                1,
+                "client.name" = "other",
+                "feature" = "subscription_unsupported"
+            );
+            assert_eq!(
+                summary(&plugin.usage.lock().counts),
+                "other: subscription_unsupported=1; web: subscription_multipart=1"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[test]
+    fn it_summarizes_the_usage() {
+        let usage = BTreeMap::from([
+            (("android".to_string(), Feature::Batching), 2),
+            (("android".to_string(), Feature::Get), 1),
+            (("web".to_string(), Feature::Apq), 3),
+        ]);
+        assert_eq!(summary(&usage), "android: batching=2, get=1; web: apq=3");
+        assert_eq!(summary(&BTreeMap::new()), "");
+    }
+}
@@ -0,0 +1,394 @@
+//! Caching of the coprocessor responses of request stages.
+//!
+//! Coprocessors often make the same decision for the same caller and operation, for example
+//! when they authorize a request from its `authorization` header. The responses of a stage can
+//! be cached, keyed by the parts of the request they depend on, so that the coprocessor is only
+//! called once per key until the cached response expires.
+
+use std::collections::HashMap;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use bytes::Bytes;
+use http::HeaderMap;
+use lru::LruCache;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::json_ext::Value;
+use crate::services::external::Control;
+use crate::services::external::Externalizable;
+use crate::services::external::PipelineStep;
+use crate::services::external::EXTERNALIZABLE_VERSION;
+use crate::Context;
+
+/// Caches the coprocessor responses of a request stage. Only use it if the coprocessor decision
+/// depends on nothing but the parts of the request in the cache key
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CacheConf {
+    /// Time to live of the cached responses
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(super) ttl: Duration,
+    /// Maximum number of cached responses (default: 1000)
+    #[serde(default = "default_capacity")]
+    pub(super) capacity: usize,
+    /// Request headers included in the cache key. Their values are hashed
+    #[serde(default)]
+    pub(super) headers: Vec<String>,
+    /// Context entries included in the cache key
+    #[serde(default)]
+    pub(super) context: Vec<String>,
+    /// Include the request body in the cache key. For GraphQL requests, it contains the
+    /// operation and its variables
+    #[serde(default)]
+    pub(super) body: bool,
+}
+
+fn default_capacity() -> usize {
+    1000
+}
+
+pub(super) type CacheKey = [u8; 32];
+
+/// What a coprocessor decided for a request: the control flow, and the changes it made to the
+/// payload it was sent. Only the changes are cached, so that a hit does not copy the headers,
+/// body or context of the request that populated the cache into another request
+#[derive(Deserialize, Serialize)]
+struct Decision<T> {
+    control: Control,
+    /// The headers of a `Break` response, or the headers the coprocessor added or changed
+    headers: Option<HashMap<String, Vec<String>>>,
+    /// The headers the coprocessor removed from the request
+    removed_headers: Vec<String>,
+    /// The body of a `Break` response, or the body the coprocessor rewrote
+    body: Option<T>,
+    /// The context entries the coprocessor added or changed
+    context: HashMap<String, Value>,
+    uri: Option<String>,
+}
+
+impl<T> Decision<T>
+where
+    T: Clone + PartialEq,
+{
+    fn new(sent: &Externalizable<T>, output: &Externalizable<T>) -> Self {
+        let control = output.control.clone().unwrap_or_default();
+        let is_break = matches!(control, Control::Break(_));
+
+        let (headers, removed_headers) = match (&sent.headers, &output.headers) {
+            (_, None) => (None, Vec::new()),
+            (_, Some(headers)) if is_break => (Some(headers.clone()), Vec::new()),
+            (sent, Some(headers)) => {
+                let sent = sent.clone().unwrap_or_default();
+                let changed: HashMap<_, _> = headers
+                    .iter()
+                    .filter(|(name, values)| sent.get(*name) != Some(*values))
+                    .map(|(name, values)| (name.clone(), values.clone()))
+                    .collect();
+                let removed = sent
+                    .keys()
+                    .filter(|name| !headers.contains_key(*name))
+                    .cloned()
+                    .collect();
+                (Some(changed), removed)
+            }
+        };
+        let body = output
+            .body
+            .as_ref()
+            .filter(|body| is_break || sent.body.as_ref() != Some(*body))
+            .cloned();
+        let context = output
+            .context
+            .iter()
+            .flat_map(|context| context.iter())
+            .filter(|entry| {
+                sent.context
+                    .as_ref()
+                    .and_then(|sent| sent.get_json_value(entry.key()))
+                    .as_ref()
+                    != Some(entry.value())
+            })
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        let uri = output
+            .uri
+            .clone()
+            .filter(|uri| sent.uri.as_ref() != Some(uri));
+
+        Self {
+            control,
+            headers,
+            removed_headers,
+            body,
+            context,
+            uri,
+        }
+    }
+
+    /// Builds the coprocessor output this decision stands for, for the payload of a new request
+    fn apply(self, payload: &Externalizable<T>) -> Externalizable<T> {
+        let headers = match self.control {
+            Control::Break(_) => self.headers,
+            Control::Continue if self.headers.is_none() && self.removed_headers.is_empty() => None,
+            Control::Continue => {
+                let mut headers_to_apply = payload.headers.clone().unwrap_or_default();
+                for name in &self.removed_headers {
+                    headers_to_apply.remove(name);
+                }
+                headers_to_apply.extend(self.headers.unwrap_or_default());
+                Some(headers_to_apply)
+            }
+        };
+        let context = (!self.context.is_empty()).then(|| {
+            let context = Context::new();
+            for (key, value) in self.context {
+                context.insert_json_value(key, value);
+            }
+            context
+        });
+
+        Externalizable {
+            version: EXTERNALIZABLE_VERSION,
+            stage: payload.stage.clone(),
+            control: Some(self.control),
+            id: payload.id.clone(),
+            headers,
+            body: self.body,
+            context,
+            sdl: None,
+            uri: self.uri,
+            method: None,
+            path: None,
+            service_name: None,
+            status_code: None,
+            has_next: None,
+        }
+    }
+}
+
+/// The cached decisions of a stage, stored serialized so that each hit gets its own copy
+#[derive(Clone, Debug)]
+pub(crate) struct ResponseCache {
+    config: Arc<CacheConf>,
+    stage: PipelineStep,
+    entries: Arc<Mutex<LruCache<CacheKey, (Instant, Bytes)>>>,
+}
+
+impl ResponseCache {
+    pub(super) fn new(config: &CacheConf, stage: PipelineStep) -> Self {
+        let capacity = NonZeroUsize::new(config.capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            config: Arc::new(config.clone()),
+            stage,
+            entries: Arc::new(Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    /// Returns true if the request body is part of the cache key
+    pub(super) fn includes_body(&self) -> bool {
+        self.config.body
+    }
+
+    /// Computes the cache key of a request. Subgraph requests share a cache, and are keyed by
+    /// subgraph name too
+    pub(super) fn key(
+        &self,
+        service_name: Option<&str>,
+        headers: &HeaderMap,
+        context: &Context,
+        body: &[u8],
+    ) -> CacheKey {
+        let mut digest = Sha256::new();
+        if let Some(service_name) = service_name {
+            digest.update(service_name.as_bytes());
+            digest.update([0xffu8]);
+        }
+        for name in &self.config.headers {
+            digest.update(name.as_bytes());
+            for value in headers.get_all(name) {
+                digest.update([0u8]);
+                digest.update(value.as_bytes());
+            }
+            digest.update([0xffu8]);
+        }
+        for key in &self.config.context {
+            digest.update(key.as_bytes());
+            if let Some(value) = context.get_json_value(key) {
+                digest.update(value.to_string().as_bytes());
+            }
+            digest.update([0xffu8]);
+        }
+        if self.config.body {
+            digest.update(body);
+        }
+        digest.finalize().into()
+    }
+
+    /// Returns the cached coprocessor output for the payload of a request, if there is one
+    pub(super) fn get<T>(
+        &self,
+        key: &CacheKey,
+        payload: &Externalizable<T>,
+    ) -> Option<Externalizable<T>>
+    where
+        T: Clone + PartialEq + DeserializeOwned,
+    {
+        let mut entries = self.entries.lock();
+        let decision: Option<Decision<T>> = match entries.get(key) {
+            Some((expires_at, _)) if *expires_at <= Instant::now() => {
+                entries.pop(key);
+                None
+            }
+            Some((_, decision)) => serde_json::from_slice(decision).ok(),
+            None => None,
+        };
+        drop(entries);
+        u64_counter!(
+            "apollo_router_coprocessor_cache_total",
+            "Number of coprocessor cache lookups, by stage and result",
+            1,
+            coprocessor.stage = self.stage.to_string(),
+            hit = decision.is_some()
+        );
+        decision.map(|decision| decision.apply(payload))
+    }
+
+    /// Caches the decision of the coprocessor, from the payload it was sent and its output
+    pub(super) fn insert<T>(
+        &self,
+        key: CacheKey,
+        sent: &Externalizable<T>,
+        output: &Externalizable<T>,
+    ) where
+        T: Clone + PartialEq + Serialize,
+    {
+        match serde_json::to_vec(&Decision::new(sent, output)) {
+            Ok(decision) => {
+                self.entries
+                    .lock()
+                    .put(key, (Instant::now() + self.config.ttl, decision.into()));
+            }
+            Err(error) => {
+                tracing::warn!("could not cache the coprocessor decision: {error}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+    use serde_json::json;
+
+    use super::*;
+
+    fn cache(ttl: Duration) -> ResponseCache {
+        let config: CacheConf = serde_json::from_value(json!({
+            "ttl": format!("{}ms", ttl.as_millis()),
+            "headers": ["authorization"],
+            "body": true
+        }))
+        .unwrap();
+        ResponseCache::new(&config, PipelineStep::SupergraphRequest)
+    }
+
+    fn payload(request_id: &str) -> Externalizable<serde_json::Value> {
+        let context = Context::new();
+        context.insert_json_value("request_id", request_id.into());
+        Externalizable::supergraph_builder()
+            .stage(PipelineStep::SupergraphRequest)
+            .control(Control::Continue)
+            .id(request_id.to_string())
+            .headers(HashMap::from([
+                ("authorization".to_string(), vec!["Bearer a".to_string()]),
+                ("x-request-id".to_string(), vec![request_id.to_string()]),
+                ("x-internal".to_string(), vec!["true".to_string()]),
+            ]))
+            .body(json!({ "query": "{ me { id } }" }))
+            .context(context)
+            .build()
+    }
+
+    #[test]
+    fn it_keys_on_the_configured_parts_of_the_request() {
+        let cache = cache(Duration::from_secs(60));
+        let context = Context::new();
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer a"));
+        let key = cache.key(None, &headers, &context, b"{ me { id } }");
+
+        // Other headers are not part of the key
+        headers.insert("x-other", HeaderValue::from_static("value"));
+        assert_eq!(cache.key(None, &headers, &context, b"{ me { id } }"), key);
+
+        headers.insert("authorization", HeaderValue::from_static("Bearer b"));
+        assert_ne!(cache.key(None, &headers, &context, b"{ me { id } }"), key);
+        headers.insert("authorization", HeaderValue::from_static("Bearer a"));
+        assert_ne!(cache.key(None, &headers, &context, b"{ me { name } }"), key);
+        assert_ne!(
+            cache.key(Some("products"), &headers, &context, b"{ me { id } }"),
+            key
+        );
+    }
+
+    #[test]
+    fn it_only_applies_the_changes_of_the_coprocessor() {
+        let cache = cache(Duration::from_secs(60));
+        let key = [0; 32];
+        let sent = payload("1");
+        assert!(cache.get(&key, &sent).is_none());
+
+        // The coprocessor adds a header and a context entry, and removes a header
+        let mut output = payload("1");
+        let headers = output.headers.as_mut().unwrap();
+        headers.remove("x-internal");
+        headers.insert("x-user-id".to_string(), vec!["42".to_string()]);
+        output
+            .context
+            .as_ref()
+            .unwrap()
+            .insert_json_value("user_id", 42.into());
+        cache.insert(key, &sent, &output);
+
+        let hit = cache.get(&key, &payload("2")).unwrap();
+        assert_eq!(hit.control, Some(Control::Continue));
+        assert_eq!(hit.body, None);
+        assert_eq!(
+            hit.headers.unwrap(),
+            HashMap::from([
+                ("authorization".to_string(), vec!["Bearer a".to_string()]),
+                ("x-request-id".to_string(), vec!["2".to_string()]),
+                ("x-user-id".to_string(), vec!["42".to_string()]),
+            ])
+        );
+        let context = hit.context.unwrap();
+        assert_eq!(context.get_json_value("user_id"), Some(42.into()));
+        assert_eq!(context.get_json_value("request_id"), None);
+    }
+
+    #[test]
+    fn it_expires_decisions() {
+        let key = [0; 32];
+        let mut output = payload("1");
+        output.control = Some(Control::Break(401));
+
+        let cache = cache(Duration::from_secs(60));
+        cache.insert(key, &payload("1"), &output);
+        let hit = cache.get(&key, &payload("2")).unwrap();
+        assert_eq!(hit.control, Some(Control::Break(401)));
+
+        let cache = self::cache(Duration::ZERO);
+        cache.insert(key, &payload("1"), &output);
+        assert!(cache.get(&key, &payload("2")).is_none());
+    }
+}
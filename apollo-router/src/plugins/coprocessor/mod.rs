@@ -10,6 +10,7 @@ use std::time::Instant;
 use bytes::Bytes;
 use futures::future::ready;
 use futures::stream::once;
+use futures::FutureExt;
 use futures::StreamExt;
 use futures::TryStreamExt;
 use http::header;
@@ -30,9 +31,12 @@ use tower::Service;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use self::cache::CacheConf;
+use self::cache::ResponseCache;
 use crate::error::Error;
 use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
 use crate::layers::ServiceBuilderExt;
+use crate::plugin::LifecycleEvent;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::register_plugin;
@@ -47,10 +51,12 @@ use crate::services::router;
 use crate::services::subgraph;
 use crate::services::trust_dns_connector::new_async_http_connector;
 use crate::services::trust_dns_connector::AsyncHyperResolver;
+use crate::Context;
 
 #[cfg(test)]
 mod test;
 
+mod cache;
 mod supergraph;
 
 pub(crate) const EXTERNAL_SPAN_NAME: &str = "external_plugin";
@@ -108,6 +114,10 @@ impl Plugin for CoprocessorPlugin<HTTPClientService> {
     fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
         self.subgraph_service(name, service)
     }
+
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        self.lifecycle_event(event)
+    }
 }
 
 // This macro allows us to use it in our plugin registry!
@@ -140,6 +150,9 @@ where
     http_client: C,
     configuration: Conf,
     sdl: Arc<String>,
+    router_request_cache: Option<ResponseCache>,
+    supergraph_request_cache: Option<ResponseCache>,
+    subgraph_request_cache: Option<ResponseCache>,
 }
 
 impl<C> CoprocessorPlugin<C>
@@ -152,10 +165,33 @@ where
     <C as tower::Service<http::Request<Body>>>::Future: Send + Sync + 'static,
 {
     fn new(http_client: C, configuration: Conf, sdl: Arc<String>) -> Result<Self, BoxError> {
+        // The stage services are created for each request, so the caches are owned by the plugin
+        let router_request_cache = configuration
+            .router
+            .request
+            .cache
+            .as_ref()
+            .map(|cache| ResponseCache::new(cache, PipelineStep::RouterRequest));
+        let supergraph_request_cache = configuration
+            .supergraph
+            .request
+            .cache
+            .as_ref()
+            .map(|cache| ResponseCache::new(cache, PipelineStep::SupergraphRequest));
+        let subgraph_request_cache = configuration
+            .subgraph
+            .all
+            .request
+            .cache
+            .as_ref()
+            .map(|cache| ResponseCache::new(cache, PipelineStep::SubgraphRequest));
         Ok(Self {
             http_client,
             configuration,
             sdl,
+            router_request_cache,
+            supergraph_request_cache,
+            subgraph_request_cache,
         })
     }
 
@@ -165,6 +201,7 @@ where
             service,
             self.configuration.url.clone(),
             self.sdl.clone(),
+            self.router_request_cache.clone(),
         )
     }
 
@@ -177,6 +214,7 @@ where
             service,
             self.configuration.url.clone(),
             self.sdl.clone(),
+            self.supergraph_request_cache.clone(),
         )
     }
 
@@ -186,17 +224,161 @@ where
             service,
             self.configuration.url.clone(),
             name.to_string(),
+            self.subgraph_request_cache.clone(),
         )
     }
+
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        self.configuration.lifecycle.notify(
+            self.http_client.clone(),
+            self.configuration.url.clone(),
+            event,
+        )
+    }
+}
+
+/// What information is passed for router lifecycle events
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct LifecycleConf {
+    /// Send schema reload, configuration reload and draining notifications to the coprocessor
+    pub(super) enabled: bool,
+}
+
+impl LifecycleConf {
+    /// Lifecycle events are notifications: they are sent in the background and the
+    /// coprocessor response does not affect the router.
+    pub(crate) fn notify<C>(&self, http_client: C, coprocessor_url: String, event: &LifecycleEvent)
+    where
+        C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        <C as tower::Service<http::Request<Body>>>::Future: Send + 'static,
+    {
+        if !self.enabled {
+            return;
+        }
+        let body = match serde_json::to_value(event) {
+            Ok(body) => body,
+            Err(error) => {
+                tracing::error!("could not serialize lifecycle event: {error}");
+                return;
+            }
+        };
+        let payload = Externalizable::lifecycle_builder()
+            .stage(PipelineStep::LifecycleEvent)
+            .id(uuid::Uuid::new_v4().to_string())
+            .body(body)
+            .build();
+
+        tokio::spawn(async move {
+            tracing::debug!(?payload, "externalized output");
+            if let Err(error) = payload.call(http_client, &coprocessor_url).await {
+                tracing::error!("coprocessor lifecycle event notification failed: {error}");
+            }
+        });
+    }
+}
+
+/// Which headers or context entries are passed to a stage
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub(super) enum Include {
+    /// Send all of them (`true`) or none of them (`false`)
+    All(bool),
+    /// Only send these header names or context keys. Headers returned by the coprocessor only
+    /// replace the headers of the same name, the other headers are kept
+    Only(Vec<String>),
+}
+
+impl Default for Include {
+    fn default() -> Self {
+        Include::All(false)
+    }
+}
+
+impl From<bool> for Include {
+    fn from(include: bool) -> Self {
+        Include::All(include)
+    }
+}
+
+impl Include {
+    fn includes(&self, key: &str) -> bool {
+        match self {
+            Include::All(include) => *include,
+            Include::Only(keys) => keys.iter().any(|k| k.eq_ignore_ascii_case(key)),
+        }
+    }
+
+    /// Headers to send to the coprocessor
+    pub(super) fn externalize_headers(
+        &self,
+        headers: &HeaderMap<HeaderValue>,
+    ) -> Result<Option<HashMap<String, Vec<String>>>, BoxError> {
+        match self {
+            Include::All(false) => Ok(None),
+            Include::All(true) => externalize_header_map(headers).map(Some),
+            Include::Only(_) => {
+                let selected = headers
+                    .iter()
+                    .filter(|(name, _)| self.includes(name.as_str()))
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                externalize_header_map(&selected).map(Some)
+            }
+        }
+    }
+
+    /// Context to send to the coprocessor
+    pub(super) fn externalize_context(&self, context: &Context) -> Option<Context> {
+        match self {
+            Include::All(false) => None,
+            Include::All(true) => Some(context.clone()),
+            Include::Only(keys) => {
+                let selected = Context::new();
+                for key in keys {
+                    if let Some(value) = context.get_json_value(key.as_str()) {
+                        selected.insert_json_value(key.clone(), value);
+                    }
+                }
+                Some(selected)
+            }
+        }
+    }
+
+    /// Applies the headers returned by the coprocessor
+    pub(super) fn internalize_headers(
+        &self,
+        headers: &mut HeaderMap<HeaderValue>,
+        output: HashMap<String, Vec<String>>,
+    ) -> Result<(), BoxError> {
+        let output = internalize_header_map(output)?;
+        match self {
+            Include::Only(_) => {
+                // The coprocessor only returns the headers it changes: they replace the existing
+                // values, and the other headers are kept
+                for name in output.keys() {
+                    headers.remove(name);
+                }
+                headers.extend(output);
+            }
+            Include::All(_) => *headers = output,
+        }
+        Ok(())
+    }
 }
+
 /// What information is passed to a router request/response stage
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct RouterRequestConf {
-    /// Send the headers
-    pub(super) headers: bool,
-    /// Send the context
-    pub(super) context: bool,
+    /// Send the headers: `true`, or a list of header names
+    pub(super) headers: Include,
+    /// Send the context: `true`, or a list of context keys
+    pub(super) context: Include,
     /// Send the body
     pub(super) body: bool,
     /// Send the SDL
@@ -205,16 +387,18 @@ pub(super) struct RouterRequestConf {
     pub(super) path: bool,
     /// Send the method
     pub(super) method: bool,
+    /// Cache the coprocessor decisions, keyed by parts of the request
+    pub(super) cache: Option<CacheConf>,
 }
 
 /// What information is passed to a router request/response stage
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct RouterResponseConf {
-    /// Send the headers
-    pub(super) headers: bool,
-    /// Send the context
-    pub(super) context: bool,
+    /// Send the headers: `true`, or a list of header names
+    pub(super) headers: Include,
+    /// Send the context: `true`, or a list of context keys
+    pub(super) context: Include,
     /// Send the body
     pub(super) body: bool,
     /// Send the SDL
@@ -226,10 +410,10 @@ pub(super) struct RouterResponseConf {
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SubgraphRequestConf {
-    /// Send the headers
-    pub(super) headers: bool,
-    /// Send the context
-    pub(super) context: bool,
+    /// Send the headers: `true`, or a list of header names
+    pub(super) headers: Include,
+    /// Send the context: `true`, or a list of context keys
+    pub(super) context: Include,
     /// Send the body
     pub(super) body: bool,
     /// Send the subgraph URI
@@ -238,16 +422,18 @@ pub(super) struct SubgraphRequestConf {
     pub(super) method: bool,
     /// Send the service name
     pub(super) service_name: bool,
+    /// Cache the coprocessor decisions, keyed by parts of the request
+    pub(super) cache: Option<CacheConf>,
 }
 
 /// What information is passed to a subgraph request/response stage
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SubgraphResponseConf {
-    /// Send the headers
-    pub(super) headers: bool,
-    /// Send the context
-    pub(super) context: bool,
+    /// Send the headers: `true`, or a list of header names
+    pub(super) headers: Include,
+    /// Send the context: `true`, or a list of context keys
+    pub(super) context: Include,
     /// Send the body
     pub(super) body: bool,
     /// Send the service name
@@ -276,6 +462,9 @@ struct Conf {
     /// The subgraph stage request/response configuration
     #[serde(default)]
     subgraph: SubgraphStages,
+    /// The lifecycle event configuration
+    #[serde(default)]
+    lifecycle: LifecycleConf,
 }
 
 fn default_timeout() -> Duration {
@@ -298,6 +487,7 @@ impl RouterStage {
         service: router::BoxService,
         coprocessor_url: String,
         sdl: Arc<String>,
+        cache: Option<ResponseCache>,
     ) -> router::BoxService
     where
         C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
@@ -318,15 +508,18 @@ impl RouterStage {
                 let coprocessor_url = coprocessor_url.clone();
                 let http_client = http_client.clone();
                 let sdl = sdl.clone();
+                let cache = cache.clone();
 
                 async move {
+                    let start = Instant::now();
                     let mut succeeded = true;
                     let result = process_router_request_stage(
                         http_client,
-                        coprocessor_url,
+                        coprocessor_url.clone(),
                         sdl,
                         request,
                         request_config,
+                        cache,
                     )
                     .await
                     .map_err(|error| {
@@ -342,6 +535,7 @@ impl RouterStage {
                         coprocessor.succeeded = succeeded,
                         "Total operations with co-processors enabled"
                     );
+                    record_stage_duration(PipelineStep::RouterRequest, &coprocessor_url, start);
                     result
                 }
             })
@@ -358,10 +552,11 @@ impl RouterStage {
                 async move {
                     let response: router::Response = fut.await?;
 
+                    let start = Instant::now();
                     let mut succeeded = true;
                     let result = process_router_response_stage(
                         http_client,
-                        coprocessor_url,
+                        coprocessor_url.clone(),
                         sdl,
                         response,
                         response_config,
@@ -380,6 +575,7 @@ impl RouterStage {
                         coprocessor.succeeded = succeeded,
                         "Total operations with co-processors enabled"
                     );
+                    record_stage_duration(PipelineStep::RouterResponse, &coprocessor_url, start);
                     result
                 }
             })
@@ -431,6 +627,7 @@ impl SubgraphStage {
         service: subgraph::BoxService,
         coprocessor_url: String,
         service_name: String,
+        cache: Option<ResponseCache>,
     ) -> subgraph::BoxService
     where
         C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
@@ -450,15 +647,18 @@ impl SubgraphStage {
                 let coprocessor_url = coprocessor_url.clone();
                 let service_name = service_name.clone();
                 let request_config = request_config.clone();
+                let cache = cache.clone();
 
                 async move {
+                    let start = Instant::now();
                     let mut succeeded = true;
                     let result = process_subgraph_request_stage(
                         http_client,
-                        coprocessor_url,
+                        coprocessor_url.clone(),
                         service_name,
                         request,
                         request_config,
+                        cache,
                     )
                     .await
                     .map_err(|error| {
@@ -474,6 +674,7 @@ impl SubgraphStage {
                         coprocessor.succeeded = succeeded,
                         "Total operations with co-processors enabled"
                     );
+                    record_stage_duration(PipelineStep::SubgraphRequest, &coprocessor_url, start);
                     result
                 }
             })
@@ -491,10 +692,11 @@ impl SubgraphStage {
                 async move {
                     let response: subgraph::Response = fut.await?;
 
+                    let start = Instant::now();
                     let mut succeeded = true;
                     let result = process_subgraph_response_stage(
                         http_client,
-                        coprocessor_url,
+                        coprocessor_url.clone(),
                         service_name,
                         response,
                         response_config,
@@ -513,6 +715,7 @@ impl SubgraphStage {
                         coprocessor.succeeded = succeeded,
                         "Total operations with co-processors enabled"
                     );
+                    record_stage_duration(PipelineStep::SubgraphResponse, &coprocessor_url, start);
                     result
                 }
             })
@@ -544,6 +747,7 @@ async fn process_router_request_stage<C>(
     sdl: Arc<String>,
     mut request: router::Request,
     request_config: RouterRequestConf,
+    cache: Option<ResponseCache>,
 ) -> Result<ControlFlow<router::Response, router::Request>, BoxError>
 where
     C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
@@ -559,10 +763,7 @@ where
     let (parts, body) = request.router_request.into_parts();
     let bytes = hyper::body::to_bytes(body).await?;
 
-    let headers_to_send = request_config
-        .headers
-        .then(|| externalize_header_map(&parts.headers))
-        .transpose()?;
+    let headers_to_send = request_config.headers.externalize_headers(&parts.headers)?;
 
     // HTTP GET requests don't have a body
     let body_to_send = request_config
@@ -573,7 +774,7 @@ where
 
     let path_to_send = request_config.path.then(|| parts.uri.to_string());
 
-    let context_to_send = request_config.context.then(|| request.context.clone());
+    let context_to_send = request_config.context.externalize_context(&request.context);
     let sdl_to_send = request_config.sdl.then(|| sdl.clone().to_string());
 
     let payload = Externalizable::router_builder()
@@ -588,21 +789,38 @@ where
         .method(parts.method.to_string())
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = request.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::RouterRequest,
-    );
+    let cache = cache.map(|cache| {
+        let key = cache.key(None, &parts.headers, &request.context, &bytes);
+        (cache, key)
+    });
+    let cached_output = cache
+        .as_ref()
+        .and_then(|(cache, key)| cache.get(key, &payload));
+    let mut co_processor_output = match cached_output {
+        Some(co_processor_output) => co_processor_output,
+        None => {
+            let sent = cache.as_ref().map(|_| payload.clone());
+            tracing::debug!(?payload, "externalized output");
+            let guard = request.context.enter_active_request();
+            let start = Instant::now();
+            let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+            let duration = start.elapsed().as_secs_f64();
+            drop(guard);
+            tracing::info!(
+                histogram.apollo.router.operations.coprocessor.duration = duration,
+                coprocessor.stage = %PipelineStep::RouterRequest,
+            );
 
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let mut co_processor_output = co_processor_result?;
+            tracing::debug!(?co_processor_result, "co-processor returned");
+            let co_processor_output = co_processor_result?;
 
-    validate_coprocessor_output(&co_processor_output, PipelineStep::RouterRequest)?;
+            validate_coprocessor_output(&co_processor_output, PipelineStep::RouterRequest)?;
+            if let (Some((cache, key)), Some(sent)) = (cache, sent) {
+                cache.insert(key, &sent, &co_processor_output);
+            }
+            co_processor_output
+        }
+    };
     // unwrap is safe here because validate_coprocessor_output made sure control is available
     let control = co_processor_output.control.expect("validated above; qed");
 
@@ -685,7 +903,9 @@ where
     }
 
     if let Some(headers) = co_processor_output.headers {
-        *request.router_request.headers_mut() = internalize_header_map(headers)?;
+        request_config
+            .headers
+            .internalize_headers(request.router_request.headers_mut(), headers)?;
     }
 
     Ok(ControlFlow::Continue(request))
@@ -731,14 +951,15 @@ where
     // Encode headers, body, status, context, sdl to create a payload
     let headers_to_send = response_config
         .headers
-        .then(|| externalize_header_map(&parts.headers))
-        .transpose()?;
+        .externalize_headers(&parts.headers)?;
     let body_to_send = response_config
         .body
         .then(|| std::str::from_utf8(&bytes).map(|s| s.to_string()))
         .transpose()?;
     let status_to_send = response_config.status_code.then(|| parts.status.as_u16());
-    let context_to_send = response_config.context.then(|| response.context.clone());
+    let context_to_send = response_config
+        .context
+        .externalize_context(&response.context);
     let sdl_to_send = response_config.sdl.then(|| sdl.clone().to_string());
 
     let payload = Externalizable::router_builder()
@@ -793,7 +1014,9 @@ where
     }
 
     if let Some(headers) = co_processor_output.headers {
-        *response.response.headers_mut() = internalize_header_map(headers)?;
+        response_config
+            .headers
+            .internalize_headers(response.response.headers_mut(), headers)?;
     }
 
     // Now break our co-processor modified response back into parts
@@ -812,6 +1035,10 @@ where
             let generator_map_context = map_context.clone();
             let generator_sdl_to_send = sdl_to_send.clone();
             let generator_id = map_context.id.clone();
+            let generator_context_conf = response_config.context.clone();
+            // Each deferred chunk goes through the coprocessor, and is timed on its own
+            let stage_coprocessor_url = coprocessor_url.clone();
+            let start = Instant::now();
 
             async move {
                 let bytes = deferred_response.to_vec();
@@ -819,9 +1046,8 @@ where
                     .body
                     .then(|| String::from_utf8(bytes.clone()))
                     .transpose()?;
-                let context_to_send = response_config
-                    .context
-                    .then(|| generator_map_context.clone());
+                let context_to_send =
+                    generator_context_conf.externalize_context(&generator_map_context);
 
                 // Note: We deliberately DO NOT send headers or status_code even if the user has
                 // requested them. That's because they are meaningless on a deferred response and
@@ -864,6 +1090,10 @@ where
                 // We return the final_bytes into our stream of response chunks
                 Ok(final_bytes)
             }
+            .map(move |result| {
+                record_stage_duration(PipelineStep::RouterResponse, &stage_coprocessor_url, start);
+                result
+            })
         });
 
     // Create our response stream which consists of the bytes from our first body chained with the
@@ -885,6 +1115,7 @@ async fn process_subgraph_request_stage<C>(
     service_name: String,
     mut request: subgraph::Request,
     request_config: SubgraphRequestConf,
+    cache: Option<ResponseCache>,
 ) -> Result<ControlFlow<subgraph::Response, subgraph::Request>, BoxError>
 where
     C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
@@ -899,18 +1130,15 @@ where
     // external call. Use our configuration to figure out which data to send.
     let (parts, body) = request.subgraph_request.into_parts();
 
-    let headers_to_send = request_config
-        .headers
-        .then(|| externalize_header_map(&parts.headers))
-        .transpose()?;
+    let headers_to_send = request_config.headers.externalize_headers(&parts.headers)?;
 
     let body_to_send = request_config
         .body
         .then(|| serde_json::to_value(&body))
         .transpose()?;
-    let context_to_send = request_config.context.then(|| request.context.clone());
+    let context_to_send = request_config.context.externalize_context(&request.context);
     let uri = request_config.uri.then(|| parts.uri.to_string());
-    let service_name = request_config.service_name.then_some(service_name);
+    let service_name_to_send = request_config.service_name.then(|| service_name.clone());
 
     let payload = Externalizable::subgraph_builder()
         .stage(PipelineStep::SubgraphRequest)
@@ -920,24 +1148,48 @@ where
         .and_body(body_to_send)
         .and_context(context_to_send)
         .method(parts.method.to_string())
-        .and_service_name(service_name)
+        .and_service_name(service_name_to_send)
         .and_uri(uri)
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = request.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::SubgraphRequest,
-    );
+    let cache = cache
+        .map(|cache| {
+            let body = if cache.includes_body() {
+                serde_json::to_vec(&body)?
+            } else {
+                Vec::new()
+            };
+            let key = cache.key(Some(&service_name), &parts.headers, &request.context, &body);
+            Ok::<_, BoxError>((cache, key))
+        })
+        .transpose()?;
+    let cached_output = cache
+        .as_ref()
+        .and_then(|(cache, key)| cache.get(key, &payload));
+    let co_processor_output = match cached_output {
+        Some(co_processor_output) => co_processor_output,
+        None => {
+            let sent = cache.as_ref().map(|_| payload.clone());
+            tracing::debug!(?payload, "externalized output");
+            let guard = request.context.enter_active_request();
+            let start = Instant::now();
+            let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+            let duration = start.elapsed().as_secs_f64();
+            drop(guard);
+            tracing::info!(
+                histogram.apollo.router.operations.coprocessor.duration = duration,
+                coprocessor.stage = %PipelineStep::SubgraphRequest,
+            );
 
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
-    validate_coprocessor_output(&co_processor_output, PipelineStep::SubgraphRequest)?;
+            tracing::debug!(?co_processor_result, "co-processor returned");
+            let co_processor_output = co_processor_result?;
+            validate_coprocessor_output(&co_processor_output, PipelineStep::SubgraphRequest)?;
+            if let (Some((cache, key)), Some(sent)) = (cache, sent) {
+                cache.insert(key, &sent, &co_processor_output);
+            }
+            co_processor_output
+        }
+    };
     // unwrap is safe here because validate_coprocessor_output made sure control is available
     let control = co_processor_output.control.expect("validated above; qed");
 
@@ -1014,7 +1266,9 @@ where
     }
 
     if let Some(headers) = co_processor_output.headers {
-        *request.subgraph_request.headers_mut() = internalize_header_map(headers)?;
+        request_config
+            .headers
+            .internalize_headers(request.subgraph_request.headers_mut(), headers)?;
     }
 
     if let Some(uri) = co_processor_output.uri {
@@ -1047,8 +1301,7 @@ where
 
     let headers_to_send = response_config
         .headers
-        .then(|| externalize_header_map(&parts.headers))
-        .transpose()?;
+        .externalize_headers(&parts.headers)?;
 
     let status_to_send = response_config.status_code.then(|| parts.status.as_u16());
 
@@ -1056,7 +1309,9 @@ where
         .body
         .then(|| serde_json::to_value(&body))
         .transpose()?;
-    let context_to_send = response_config.context.then(|| response.context.clone());
+    let context_to_send = response_config
+        .context
+        .externalize_context(&response.context);
     let service_name = response_config.service_name.then_some(service_name);
 
     let payload = Externalizable::subgraph_builder()
@@ -1110,7 +1365,9 @@ where
     }
 
     if let Some(headers) = co_processor_output.headers {
-        *response.response.headers_mut() = internalize_header_map(headers)?;
+        response_config
+            .headers
+            .internalize_headers(response.response.headers_mut(), headers)?;
     }
 
     Ok(response)
@@ -1118,6 +1375,18 @@ where
 
 // -----------------------------------------------------------------------------------------
 
+/// Records the time a stage spent on the coprocessor: building the payload, calling the
+/// coprocessor and applying its output
+fn record_stage_duration(stage: PipelineStep, coprocessor_url: &str, start: Instant) {
+    f64_histogram!(
+        "apollo.router.coprocessor.stage.duration",
+        "Time spent on a coprocessor stage, in seconds",
+        start.elapsed().as_secs_f64(),
+        "coprocessor.stage" = stage.to_string(),
+        "coprocessor.url" = coprocessor_url.to_string()
+    );
+}
+
 fn validate_coprocessor_output<T>(
     co_processor_output: &Externalizable<T>,
     expected_step: PipelineStep,
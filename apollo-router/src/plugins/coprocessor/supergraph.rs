@@ -10,7 +10,6 @@ use tower::BoxError;
 use tower::ServiceBuilder;
 use tower_service::Service;
 
-use super::externalize_header_map;
 use super::*;
 use crate::graphql;
 use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
@@ -23,26 +22,28 @@ use crate::services::supergraph;
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SupergraphRequestConf {
-    /// Send the headers
-    pub(super) headers: bool,
-    /// Send the context
-    pub(super) context: bool,
+    /// Send the headers: `true`, or a list of header names
+    pub(super) headers: Include,
+    /// Send the context: `true`, or a list of context keys
+    pub(super) context: Include,
     /// Send the body
     pub(super) body: bool,
     /// Send the SDL
     pub(super) sdl: bool,
     /// Send the method
     pub(super) method: bool,
+    /// Cache the coprocessor decisions, keyed by parts of the request
+    pub(super) cache: Option<CacheConf>,
 }
 
 /// What information is passed to a router request/response stage
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize, JsonSchema)]
 #[serde(default, deny_unknown_fields)]
 pub(super) struct SupergraphResponseConf {
-    /// Send the headers
-    pub(super) headers: bool,
-    /// Send the context
-    pub(super) context: bool,
+    /// Send the headers: `true`, or a list of header names
+    pub(super) headers: Include,
+    /// Send the context: `true`, or a list of context keys
+    pub(super) context: Include,
     /// Send the body
     pub(super) body: bool,
     /// Send the SDL
@@ -67,6 +68,7 @@ impl SupergraphStage {
         service: supergraph::BoxService,
         coprocessor_url: String,
         sdl: Arc<String>,
+        cache: Option<ResponseCache>,
     ) -> supergraph::BoxService
     where
         C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
@@ -87,15 +89,18 @@ impl SupergraphStage {
                 let coprocessor_url = coprocessor_url.clone();
                 let http_client = http_client.clone();
                 let sdl = sdl.clone();
+                let cache = cache.clone();
 
                 async move {
+                    let start = Instant::now();
                     let mut succeeded = true;
                     let result = process_supergraph_request_stage(
                         http_client,
-                        coprocessor_url,
+                        coprocessor_url.clone(),
                         sdl,
                         request,
                         request_config,
+                        cache,
                     )
                     .await
                     .map_err(|error| {
@@ -111,6 +116,7 @@ impl SupergraphStage {
                         coprocessor.succeeded = succeeded,
                         "Total operations with co-processors enabled"
                     );
+                    record_stage_duration(PipelineStep::SupergraphRequest, &coprocessor_url, start);
                     result
                 }
             })
@@ -128,10 +134,11 @@ impl SupergraphStage {
                 async move {
                     let response: supergraph::Response = fut.await?;
 
+                    let start = Instant::now();
                     let mut succeeded = true;
                     let result = process_supergraph_response_stage(
                         http_client,
-                        coprocessor_url,
+                        coprocessor_url.clone(),
                         sdl,
                         response,
                         response_config,
@@ -150,6 +157,11 @@ impl SupergraphStage {
                         coprocessor.succeeded = succeeded,
                         "Total operations with co-processors enabled"
                     );
+                    record_stage_duration(
+                        PipelineStep::SupergraphResponse,
+                        &coprocessor_url,
+                        start,
+                    );
                     result
                 }
             })
@@ -180,6 +192,7 @@ async fn process_supergraph_request_stage<C>(
     sdl: Arc<String>,
     mut request: supergraph::Request,
     request_config: SupergraphRequestConf,
+    cache: Option<ResponseCache>,
 ) -> Result<ControlFlow<supergraph::Response, supergraph::Request>, BoxError>
 where
     C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
@@ -195,16 +208,13 @@ where
     let (parts, body) = request.supergraph_request.into_parts();
     let bytes = Bytes::from(serde_json::to_vec(&body)?);
 
-    let headers_to_send = request_config
-        .headers
-        .then(|| externalize_header_map(&parts.headers))
-        .transpose()?;
+    let headers_to_send = request_config.headers.externalize_headers(&parts.headers)?;
 
     let body_to_send = request_config
         .body
         .then(|| serde_json::from_slice::<serde_json::Value>(&bytes))
         .transpose()?;
-    let context_to_send = request_config.context.then(|| request.context.clone());
+    let context_to_send = request_config.context.externalize_context(&request.context);
     let sdl_to_send = request_config.sdl.then(|| sdl.clone().to_string());
     let method = request_config.method.then(|| parts.method.to_string());
 
@@ -219,20 +229,37 @@ where
         .and_sdl(sdl_to_send)
         .build();
 
-    tracing::debug!(?payload, "externalized output");
-    let guard = request.context.enter_active_request();
-    let start = Instant::now();
-    let co_processor_result = payload.call(http_client, &coprocessor_url).await;
-    let duration = start.elapsed().as_secs_f64();
-    drop(guard);
-    tracing::info!(
-        histogram.apollo.router.operations.coprocessor.duration = duration,
-        coprocessor.stage = %PipelineStep::SupergraphRequest,
-    );
-
-    tracing::debug!(?co_processor_result, "co-processor returned");
-    let co_processor_output = co_processor_result?;
-    validate_coprocessor_output(&co_processor_output, PipelineStep::SupergraphRequest)?;
+    let cache = cache.map(|cache| {
+        let key = cache.key(None, &parts.headers, &request.context, &bytes);
+        (cache, key)
+    });
+    let cached_output = cache
+        .as_ref()
+        .and_then(|(cache, key)| cache.get(key, &payload));
+    let co_processor_output = match cached_output {
+        Some(co_processor_output) => co_processor_output,
+        None => {
+            let sent = cache.as_ref().map(|_| payload.clone());
+            tracing::debug!(?payload, "externalized output");
+            let guard = request.context.enter_active_request();
+            let start = Instant::now();
+            let co_processor_result = payload.call(http_client, &coprocessor_url).await;
+            let duration = start.elapsed().as_secs_f64();
+            drop(guard);
+            tracing::info!(
+                histogram.apollo.router.operations.coprocessor.duration = duration,
+                coprocessor.stage = %PipelineStep::SupergraphRequest,
+            );
+
+            tracing::debug!(?co_processor_result, "co-processor returned");
+            let co_processor_output = co_processor_result?;
+            validate_coprocessor_output(&co_processor_output, PipelineStep::SupergraphRequest)?;
+            if let (Some((cache, key)), Some(sent)) = (cache, sent) {
+                cache.insert(key, &sent, &co_processor_output);
+            }
+            co_processor_output
+        }
+    };
     // unwrap is safe here because validate_coprocessor_output made sure control is available
     let control = co_processor_output.control.expect("validated above; qed");
 
@@ -302,7 +329,9 @@ where
     }
 
     if let Some(headers) = co_processor_output.headers {
-        *request.supergraph_request.headers_mut() = internalize_header_map(headers)?;
+        request_config
+            .headers
+            .internalize_headers(request.supergraph_request.headers_mut(), headers)?;
     }
 
     if let Some(uri) = co_processor_output.uri {
@@ -344,13 +373,14 @@ where
     // Encode headers, body, status, context, sdl to create a payload
     let headers_to_send = response_config
         .headers
-        .then(|| externalize_header_map(&parts.headers))
-        .transpose()?;
+        .externalize_headers(&parts.headers)?;
     let body_to_send = response_config
         .body
         .then(|| serde_json::to_value(&first).expect("serialization will not fail"));
     let status_to_send = response_config.status_code.then(|| parts.status.as_u16());
-    let context_to_send = response_config.context.then(|| response.context.clone());
+    let context_to_send = response_config
+        .context
+        .externalize_context(&response.context);
     let sdl_to_send = response_config.sdl.then(|| sdl.clone().to_string());
 
     let payload = Externalizable::supergraph_builder()
@@ -403,7 +433,9 @@ where
     }
 
     if let Some(headers) = co_processor_output.headers {
-        parts.headers = internalize_header_map(headers)?;
+        response_config
+            .headers
+            .internalize_headers(&mut parts.headers, headers)?;
     }
 
     // Clone all the bits we need
@@ -418,14 +450,17 @@ where
             let generator_map_context = map_context.clone();
             let generator_sdl_to_send = sdl_to_send.clone();
             let generator_id = map_context.id.clone();
+            let generator_context_conf = response_config.context.clone();
+            // Each deferred chunk goes through the coprocessor, and is timed on its own
+            let stage_coprocessor_url = coprocessor_url.clone();
+            let start = Instant::now();
 
             async move {
                 let body_to_send = response_config.body.then(|| {
                     serde_json::to_value(&deferred_response).expect("serialization will not fail")
                 });
-                let context_to_send = response_config
-                    .context
-                    .then(|| generator_map_context.clone());
+                let context_to_send =
+                    generator_context_conf.externalize_context(&generator_map_context);
 
                 // Note: We deliberately DO NOT send headers or status_code even if the user has
                 // requested them. That's because they are meaningless on a deferred response and
@@ -473,6 +508,14 @@ where
                 // We return the deferred_response into our stream of response chunks
                 Ok(new_deferred_response)
             }
+            .map(move |result| {
+                record_stage_duration(
+                    PipelineStep::SupergraphResponse,
+                    &stage_coprocessor_url,
+                    start,
+                );
+                result
+            })
         })
         .map(|res: Result<response::Response, BoxError>| match res {
             Ok(response) => response,
@@ -513,6 +556,7 @@ mod tests {
 
     use super::super::*;
     use super::*;
+    use crate::metrics::FutureMetricsExt;
     use crate::plugin::test::MockHttpClientService;
     use crate::plugin::test::MockSupergraphService;
     use crate::services::supergraph;
@@ -566,11 +610,12 @@ mod tests {
     async fn external_plugin_supergraph_request() {
         let supergraph_stage = SupergraphStage {
             request: SupergraphRequestConf {
-                headers: false,
-                context: false,
+                headers: Include::All(false),
+                context: Include::All(false),
                 body: true,
                 sdl: false,
                 method: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -675,6 +720,7 @@ mod tests {
             mock_supergraph_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::fake_builder().build().unwrap();
@@ -699,11 +745,12 @@ mod tests {
     async fn external_plugin_supergraph_request_controlflow_break() {
         let supergraph_stage = SupergraphStage {
             request: SupergraphRequestConf {
-                headers: false,
-                context: false,
+                headers: Include::All(false),
+                context: Include::All(false),
                 body: true,
                 sdl: false,
                 method: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -743,6 +790,7 @@ mod tests {
             mock_supergraph_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::fake_builder().build().unwrap();
@@ -770,8 +818,8 @@ mod tests {
     async fn external_plugin_supergraph_response() {
         let supergraph_stage = SupergraphStage {
             response: SupergraphResponseConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 status_code: false,
@@ -868,6 +916,7 @@ mod tests {
             mock_supergraph_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -901,8 +950,8 @@ mod tests {
     async fn multi_part() {
         let supergraph_stage = SupergraphStage {
             response: SupergraphResponseConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 status_code: false,
@@ -979,6 +1028,7 @@ mod tests {
             mock_supergraph_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder()
@@ -1004,4 +1054,62 @@ mod tests {
             json!({ "data": { "test": 3, "has_next": false }, "hasNext": false }),
         );
     }
+
+    #[tokio::test]
+    async fn multi_part_stage_duration() {
+        async {
+            let supergraph_stage = SupergraphStage {
+                response: SupergraphResponseConf {
+                    body: true,
+                    ..Default::default()
+                },
+                request: Default::default(),
+            };
+
+            let mut mock_supergraph_service = MockSupergraphService::new();
+            mock_supergraph_service
+                .expect_call()
+                .returning(|req: supergraph::Request| {
+                    Ok(supergraph::Response::fake_stream_builder()
+                        .response(graphql::Response::builder().has_next(true).build())
+                        .response(graphql::Response::builder().has_next(true).build())
+                        .response(graphql::Response::builder().has_next(false).build())
+                        .context(req.context)
+                        .build()
+                        .unwrap())
+                });
+
+            // The coprocessor returns the chunks unchanged
+            let mock_http_client = mock_with_deferred_callback(move |req: hyper::Request<Body>| {
+                Box::pin(async {
+                    Ok(hyper::Response::builder()
+                        .body(Body::from(
+                            hyper::body::to_bytes(req.into_body()).await.unwrap(),
+                        ))
+                        .unwrap())
+                })
+            });
+
+            let service = supergraph_stage.as_service(
+                mock_http_client,
+                mock_supergraph_service.boxed(),
+                "http://test".to_string(),
+                Arc::new("".to_string()),
+                None,
+            );
+            let request = supergraph::Request::canned_builder().build().unwrap();
+            let mut res = service.oneshot(request).await.unwrap();
+            while res.response.body_mut().next().await.is_some() {}
+
+            // The first response and each deferred chunk are timed
+            assert_histogram!(
+                "apollo.router.coprocessor.stage.duration",
+                3,
+                "coprocessor.stage" = "SupergraphResponse",
+                "coprocessor.url" = "http://test"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
 }
@@ -2,6 +2,7 @@
 mod tests {
     use std::collections::HashMap;
     use std::sync::Arc;
+    use std::time::Duration;
 
     use futures::future::BoxFuture;
     use http::header::ACCEPT;
@@ -21,6 +22,7 @@ mod tests {
     use crate::plugin::test::MockHttpClientService;
     use crate::plugin::test::MockRouterService;
     use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::LifecycleEvent;
     use crate::services::external::Externalizable;
     use crate::services::external::PipelineStep;
     use crate::services::external::EXTERNALIZABLE_VERSION;
@@ -93,12 +95,13 @@ mod tests {
     async fn coprocessor_returning_the_wrong_version_should_fail() {
         let router_stage = RouterStage {
             request: RouterRequestConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 path: false,
                 method: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -134,6 +137,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -152,12 +156,13 @@ mod tests {
     async fn coprocessor_returning_the_wrong_stage_should_fail() {
         let router_stage = RouterStage {
             request: RouterRequestConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 path: false,
                 method: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -193,6 +198,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -211,12 +217,13 @@ mod tests {
     async fn coprocessor_missing_request_control_should_fail() {
         let router_stage = RouterStage {
             request: RouterRequestConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 path: false,
                 method: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -251,6 +258,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -269,12 +277,13 @@ mod tests {
     async fn coprocessor_subgraph_with_invalid_response_body_should_fail() {
         let subgraph_stage = SubgraphStage {
             request: SubgraphRequestConf {
-                headers: false,
-                context: false,
+                headers: Include::All(false),
+                context: Include::All(false),
                 body: true,
                 uri: false,
                 method: false,
                 service_name: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -309,6 +318,7 @@ mod tests {
             mock_subgraph_service.boxed(),
             "http://test".to_string(),
             "my_subgraph_service_name".to_string(),
+            None,
         );
 
         let request = subgraph::Request::fake_builder().build();
@@ -331,12 +341,13 @@ mod tests {
     async fn external_plugin_subgraph_request() {
         let subgraph_stage = SubgraphStage {
             request: SubgraphRequestConf {
-                headers: false,
-                context: false,
+                headers: Include::All(false),
+                context: Include::All(false),
                 body: true,
                 uri: false,
                 method: false,
                 service_name: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -439,6 +450,7 @@ mod tests {
             mock_subgraph_service.boxed(),
             "http://test".to_string(),
             "my_subgraph_service_name".to_string(),
+            None,
         );
 
         let request = subgraph::Request::fake_builder().build();
@@ -456,16 +468,94 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn external_plugin_subgraph_request_cached() {
+        static COPROCESSOR_CALLS: std::sync::atomic::AtomicUsize =
+            std::sync::atomic::AtomicUsize::new(0);
+
+        let cache_config = json!({ "ttl": "60s", "body": true });
+        let subgraph_stage = SubgraphStage {
+            request: SubgraphRequestConf {
+                headers: Include::All(false),
+                context: Include::All(false),
+                body: true,
+                uri: false,
+                method: false,
+                service_name: false,
+                cache: Some(serde_json::from_value(cache_config.clone()).unwrap()),
+            },
+            response: Default::default(),
+        };
+        // The services are created for each request, the cache is shared between them
+        let cache = ResponseCache::new(
+            &serde_json::from_value(cache_config).unwrap(),
+            PipelineStep::SubgraphRequest,
+        );
+
+        // The same request to another subgraph is not answered from the cache
+        for service_name in [
+            "my_subgraph_service_name",
+            "my_subgraph_service_name",
+            "other",
+        ] {
+            // This will never be called because the coprocessor denies the request.
+            let mock_subgraph_service = MockSubgraphService::new();
+
+            let mock_http_client = mock_with_callback(move |_: hyper::Request<Body>| {
+                Box::pin(async {
+                    COPROCESSOR_CALLS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    Ok(hyper::Response::builder()
+                        .body(Body::from(
+                            r#"{
+                                "version": 1,
+                                "stage": "SubgraphRequest",
+                                "control": {
+                                    "break": 401
+                                },
+                                "body": "Errors need a message, this will fail",
+                                "context": {
+                                    "entries": {
+                                      "denied": true
+                                    }
+                                }
+                            }"#,
+                        ))
+                        .unwrap())
+                })
+            });
+
+            let service = subgraph_stage.as_service(
+                mock_http_client,
+                mock_subgraph_service.boxed(),
+                "http://test".to_string(),
+                service_name.to_string(),
+                Some(cache.clone()),
+            );
+
+            let response = service
+                .oneshot(subgraph::Request::fake_builder().build())
+                .await
+                .unwrap();
+            assert_eq!(response.response.status(), StatusCode::UNAUTHORIZED);
+            assert!(response.context.get::<_, bool>("denied").unwrap().unwrap());
+        }
+        assert_eq!(
+            COPROCESSOR_CALLS.load(std::sync::atomic::Ordering::SeqCst),
+            2
+        );
+    }
+
     #[tokio::test]
     async fn external_plugin_subgraph_request_controlflow_break() {
         let subgraph_stage = SubgraphStage {
             request: SubgraphRequestConf {
-                headers: false,
-                context: false,
+                headers: Include::All(false),
+                context: Include::All(false),
                 body: true,
                 uri: false,
                 method: false,
                 service_name: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -505,6 +595,7 @@ mod tests {
             mock_subgraph_service.boxed(),
             "http://test".to_string(),
             "my_subgraph_service_name".to_string(),
+            None,
         );
 
         let request = subgraph::Request::fake_builder().build();
@@ -528,12 +619,13 @@ mod tests {
     async fn external_plugin_subgraph_request_controlflow_break_with_message_string() {
         let subgraph_stage = SubgraphStage {
             request: SubgraphRequestConf {
-                headers: false,
-                context: false,
+                headers: Include::All(false),
+                context: Include::All(false),
                 body: true,
                 uri: false,
                 method: false,
                 service_name: false,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -563,6 +655,7 @@ mod tests {
             mock_subgraph_service.boxed(),
             "http://test".to_string(),
             "my_subgraph_service_name".to_string(),
+            None,
         );
 
         let request = subgraph::Request::fake_builder().build();
@@ -592,8 +685,8 @@ mod tests {
         let subgraph_stage = SubgraphStage {
             request: Default::default(),
             response: SubgraphResponseConf {
-                headers: false,
-                context: false,
+                headers: Include::All(false),
+                context: Include::All(false),
                 body: true,
                 service_name: false,
                 status_code: false,
@@ -671,6 +764,7 @@ mod tests {
             mock_subgraph_service.boxed(),
             "http://test".to_string(),
             "my_subgraph_service_name".to_string(),
+            None,
         );
 
         let request = subgraph::Request::fake_builder().build();
@@ -702,12 +796,13 @@ mod tests {
     async fn external_plugin_router_request() {
         let router_stage = RouterStage {
             request: RouterRequestConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 path: true,
                 method: true,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -809,6 +904,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -816,16 +912,177 @@ mod tests {
         service.oneshot(request.try_into().unwrap()).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn external_plugin_router_request_with_allow_lists() {
+        let router_stage = RouterStage {
+            request: RouterRequestConf {
+                headers: Include::Only(vec!["Authorization".to_string()]),
+                context: Include::Only(vec!["client-id".to_string()]),
+                ..Default::default()
+            },
+            response: Default::default(),
+        };
+
+        let mock_router_service = router::service::from_supergraph_mock_callback(move |req| {
+            // The headers that were not sent are kept
+            let headers = req.supergraph_request.headers();
+            assert_eq!(headers.get("x-request-id").unwrap(), "1234");
+            assert_eq!(headers.get("authorization").unwrap(), "Bearer rewritten");
+            assert_eq!(headers.get("x-user-id").unwrap(), "42");
+
+            // The context entries that were not sent are kept
+            assert_eq!(
+                req.context.get::<&str, String>("secret").unwrap().unwrap(),
+                "not sent"
+            );
+            assert_eq!(
+                req.context
+                    .get::<&str, String>("client-id")
+                    .unwrap()
+                    .unwrap(),
+                "rewritten"
+            );
+
+            Ok(supergraph::Response::builder()
+                .data(json!({ "test": 1234_u32 }))
+                .context(req.context)
+                .build()
+                .unwrap())
+        })
+        .await;
+
+        let mock_http_client = mock_with_callback(move |req: hyper::Request<Body>| {
+            Box::pin(async {
+                let deserialized_request: Externalizable<serde_json::Value> =
+                    serde_json::from_slice(&hyper::body::to_bytes(req.into_body()).await.unwrap())
+                        .unwrap();
+
+                // Only the listed headers and context entries are sent
+                assert_eq!(
+                    deserialized_request.headers,
+                    Some(HashMap::from([(
+                        "authorization".to_string(),
+                        vec!["Bearer token".to_string()]
+                    )]))
+                );
+                let context = deserialized_request.context.unwrap();
+                assert_eq!(context.iter().count(), 1);
+                assert_eq!(
+                    context.get::<&str, String>("client-id").unwrap().unwrap(),
+                    "manual"
+                );
+                assert!(deserialized_request.body.is_none());
+
+                // The response only contains what the coprocessor changes
+                let input = json!({
+                    "version": 1,
+                    "stage": "RouterRequest",
+                    "control": "continue",
+                    "headers": {
+                        "authorization": ["Bearer rewritten"],
+                        "x-user-id": ["42"]
+                    },
+                    "context": {
+                        "entries": {
+                            "client-id": "rewritten"
+                        }
+                    }
+                });
+                Ok(hyper::Response::builder()
+                    .body(Body::from(serde_json::to_string(&input).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        let service = router_stage.as_service(
+            mock_http_client,
+            mock_router_service.boxed(),
+            "http://test".to_string(),
+            Arc::new("".to_string()),
+            None,
+        );
+
+        let context = Context::new();
+        context.insert("client-id", "manual".to_string()).unwrap();
+        context.insert("secret", "not sent".to_string()).unwrap();
+        let request = supergraph::Request::canned_builder()
+            .header("authorization", "Bearer token")
+            .header("x-request-id", "1234")
+            .context(context)
+            .build()
+            .unwrap();
+
+        service.oneshot(request.try_into().unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn external_plugin_router_request_keeps_the_listed_headers_not_returned() {
+        let router_stage = RouterStage {
+            request: RouterRequestConf {
+                headers: Include::Only(vec!["Authorization".to_string(), "x-tenant".to_string()]),
+                ..Default::default()
+            },
+            response: Default::default(),
+        };
+
+        let mock_router_service = router::service::from_supergraph_mock_callback(move |req| {
+            // The listed headers that the coprocessor did not return are unchanged
+            let headers = req.supergraph_request.headers();
+            assert_eq!(headers.get("authorization").unwrap(), "Bearer token");
+            assert_eq!(headers.get("x-tenant").unwrap(), "rewritten");
+
+            Ok(supergraph::Response::builder()
+                .data(json!({ "test": 1234_u32 }))
+                .context(req.context)
+                .build()
+                .unwrap())
+        })
+        .await;
+
+        let mock_http_client = mock_with_callback(move |_: hyper::Request<Body>| {
+            Box::pin(async {
+                let input = json!({
+                    "version": 1,
+                    "stage": "RouterRequest",
+                    "control": "continue",
+                    "headers": {
+                        "x-tenant": ["rewritten"]
+                    }
+                });
+                Ok(hyper::Response::builder()
+                    .body(Body::from(serde_json::to_string(&input).unwrap()))
+                    .unwrap())
+            })
+        });
+
+        let service = router_stage.as_service(
+            mock_http_client,
+            mock_router_service.boxed(),
+            "http://test".to_string(),
+            Arc::new("".to_string()),
+            None,
+        );
+
+        let request = supergraph::Request::canned_builder()
+            .header("authorization", "Bearer token")
+            .header("x-tenant", "tenant")
+            .build()
+            .unwrap();
+
+        service.oneshot(request.try_into().unwrap()).await.unwrap();
+    }
+
     #[tokio::test]
     async fn external_plugin_router_request_http_get() {
         let router_stage = RouterStage {
             request: RouterRequestConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 path: true,
                 method: true,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -934,6 +1191,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::fake_builder()
@@ -948,12 +1206,13 @@ mod tests {
     async fn external_plugin_router_request_controlflow_break() {
         let router_stage = RouterStage {
             request: RouterRequestConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 path: true,
                 method: true,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -1004,6 +1263,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -1036,12 +1296,13 @@ mod tests {
     async fn external_plugin_router_request_controlflow_break_with_message_string() {
         let router_stage = RouterStage {
             request: RouterRequestConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 path: true,
                 method: true,
+                cache: None,
             },
             response: Default::default(),
         };
@@ -1082,6 +1343,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -1115,8 +1377,8 @@ mod tests {
     async fn external_plugin_router_response() {
         let router_stage = RouterStage {
             response: RouterResponseConf {
-                headers: true,
-                context: true,
+                headers: Include::All(true),
+                context: Include::All(true),
                 body: true,
                 sdl: true,
                 status_code: false,
@@ -1208,6 +1470,7 @@ mod tests {
             mock_router_service.boxed(),
             "http://test".to_string(),
             Arc::new("".to_string()),
+            None,
         );
 
         let request = supergraph::Request::canned_builder().build().unwrap();
@@ -1308,6 +1571,63 @@ mod tests {
         assert_eq!(expected, actual);
     }
 
+    #[tokio::test]
+    async fn external_plugin_lifecycle_event() {
+        let lifecycle = LifecycleConf { enabled: true };
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let mut mock_http_client = MockHttpClientService::new();
+        mock_http_client
+            .expect_call()
+            .returning(move |req: hyper::Request<Body>| {
+                let sender = sender.clone();
+                Box::pin(async move {
+                    let body = hyper::body::to_bytes(req.into_body()).await?;
+                    sender.send(serde_json::from_slice::<serde_json::Value>(&body)?)?;
+                    Ok::<_, BoxError>(hyper::Response::builder().body(Body::from(body))?)
+                })
+            });
+
+        lifecycle.notify(
+            mock_http_client,
+            "http://test".to_string(),
+            &LifecycleEvent::SchemaChanged {
+                previous_schema_id: "previous".to_string(),
+                schema_id: "current".to_string(),
+                previous_supergraph_sdl: Default::default(),
+            },
+        );
+
+        let mut payload = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .expect("lifecycle event was sent")
+            .unwrap();
+        assert!(payload["id"].as_str().is_some());
+        payload.as_object_mut().unwrap().remove("id");
+        assert_eq!(
+            payload,
+            json!({
+                "version": 1,
+                "stage": "LifecycleEvent",
+                "body": {
+                    "event": "schema_changed",
+                    "previous_schema_id": "previous",
+                    "schema_id": "current"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn external_plugin_lifecycle_event_disabled() {
+        // No call expectation: sending anything would panic
+        LifecycleConf::default().notify(
+            MockHttpClientService::new(),
+            "http://test".to_string(),
+            &LifecycleEvent::Draining,
+        );
+    }
+
     #[allow(clippy::type_complexity)]
     fn mock_with_callback(
         callback: fn(
@@ -0,0 +1,581 @@
+//! Enforcement of the removal dates of deprecated fields.
+//!
+//! Once the enforcement date of a deprecated field has passed, operations using it are rejected
+//! with a hint on how to migrate, except for the clients exempted from it, so that the field can
+//! actually be removed from the schema on schedule.
+//!
+//! Clients are exempted by the value of a claim of their JWT if `exempt_claim` is configured, and
+//! by their client name otherwise. Any client can send the client name of another one, so the
+//! client name only fits the exemptions that are not worth securing.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use apollo_compiler::executable;
+use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Schema;
+use http::StatusCode;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Deserializer;
+use time::Date;
+use time::Month;
+use time::OffsetDateTime;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::plugins::telemetry::CLIENT_NAME;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!(
+    "apollo",
+    "experimental_deprecation_enforcement",
+    DeprecationEnforcement
+);
+
+/// The client name of the requests without one
+const UNKNOWN_CLIENT: &str = "unknown";
+/// The client name reported for the clients beyond `max_clients`
+const OTHER_CLIENTS: &str = "other";
+
+/// Reject the operations using deprecated fields after their enforcement date
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Deprecated fields, by schema coordinate (`Type.field`)
+    fields: HashMap<String, FieldEnforcement>,
+    /// Maximum number of client names reported in the metrics. The operations of the other
+    /// clients are reported under the `other` client name (default: 100)
+    max_clients: usize,
+    /// Claim of the authenticated JWT that the exempt clients are compared with. The exempt
+    /// clients are compared with the client name header if unset
+    exempt_claim: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fields: HashMap::new(),
+            max_clients: 100,
+            exempt_claim: None,
+        }
+    }
+}
+
+/// When and for which clients the use of a deprecated field is rejected
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct FieldEnforcement {
+    /// Date from which the operations using the field are rejected, as `YYYY-MM-DD` in UTC
+    #[serde(deserialize_with = "deserialize_date")]
+    #[schemars(with = "String")]
+    enforced_from: Date,
+    /// Clients that can still use the field after its enforcement date, by the value of their
+    /// `exempt_claim`, or by client name if it is unset
+    #[serde(default)]
+    exempt_clients: Vec<String>,
+    /// How to migrate away from the field, added to the errors. Defaults to the reason of its
+    /// `@deprecated` directive
+    #[serde(default)]
+    hint: Option<String>,
+}
+
+fn deserialize_date<'de, D>(deserializer: D) -> Result<Date, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let date = String::deserialize(deserializer)?;
+    parse_date(&date).ok_or_else(|| {
+        serde::de::Error::custom(format!("invalid date '{date}', expected YYYY-MM-DD"))
+    })
+}
+
+fn parse_date(date: &str) -> Option<Date> {
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = Month::try_from(parts.next()?.parse::<u8>().ok()?).ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Date::from_calendar_date(year, month, day).ok()
+}
+
+/// The client names reported in the metrics
+struct ReportedClients {
+    max_clients: usize,
+    /// The client names reported so far, at most `max_clients`
+    names: Mutex<HashSet<String>>,
+}
+
+impl ReportedClients {
+    /// The client name to report, which is `other` once `max_clients` client names were reported
+    fn client_name(&self, client_name: &str) -> String {
+        let mut names = self.names.lock();
+        if names.contains(client_name) {
+            client_name.to_string()
+        } else if names.len() < self.max_clients {
+            names.insert(client_name.to_string());
+            client_name.to_string()
+        } else {
+            OTHER_CLIENTS.to_string()
+        }
+    }
+}
+
+struct DeprecationEnforcement {
+    fields: HashMap<String, FieldEnforcement>,
+    clients: Arc<ReportedClients>,
+    exempt_claim: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for DeprecationEnforcement {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let unknown = unknown_fields(&init.supergraph_sdl, &init.config.fields);
+        if !unknown.is_empty() {
+            return Err(format!(
+                "the deprecated fields {} are not in the supergraph schema",
+                unknown.join(", ")
+            )
+            .into());
+        }
+        Ok(DeprecationEnforcement {
+            fields: init.config.fields,
+            clients: Arc::new(ReportedClients {
+                max_clients: init.config.max_clients,
+                names: Mutex::new(HashSet::new()),
+            }),
+            exempt_claim: init.config.exempt_claim,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if self.fields.is_empty() {
+            return service;
+        }
+
+        let fields = self.fields.clone();
+        let clients = self.clients.clone();
+        let exempt_claim = self.exempt_claim.clone();
+        ServiceBuilder::new()
+            .checkpoint(move |request: supergraph::Request| {
+                let Some(document) = request
+                    .context
+                    .private_entries
+                    .lock()
+                    .get::<ParsedDocument>()
+                    .cloned()
+                else {
+                    return Ok(ControlFlow::Continue(request));
+                };
+                let client_name: String = request
+                    .context
+                    .get(CLIENT_NAME)
+                    .ok()
+                    .flatten()
+                    .unwrap_or_else(|| UNKNOWN_CLIENT.to_string());
+                let exempt_as = exempt_as(&request.context, &client_name, exempt_claim.as_deref());
+                let errors = enforce(
+                    &fields,
+                    &document.executable,
+                    request.supergraph_request.body().operation_name.as_deref(),
+                    &client_name,
+                    exempt_as.as_deref(),
+                    &clients,
+                    OffsetDateTime::now_utc().date(),
+                );
+                if errors.is_empty() {
+                    return Ok(ControlFlow::Continue(request));
+                }
+
+                let response = supergraph::Response::builder()
+                    .errors(errors)
+                    .status_code(StatusCode::BAD_REQUEST)
+                    .context(request.context)
+                    .build()?;
+                Ok(ControlFlow::Break(response))
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+/// What the exempt clients are compared with: the `exempt_claim` of the authenticated JWT if it is
+/// configured, or the client name
+fn exempt_as(context: &Context, client_name: &str, exempt_claim: Option<&str>) -> Option<String> {
+    let Some(claim) = exempt_claim else {
+        return Some(client_name.to_string());
+    };
+    let claims = context.get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS)?;
+    Some(claims.get(claim)?.as_str()?.to_string())
+}
+
+/// The configured coordinates that are not fields of the supergraph schema, sorted
+fn unknown_fields(supergraph_sdl: &str, fields: &HashMap<String, FieldEnforcement>) -> Vec<String> {
+    if fields.is_empty() {
+        return Vec::new();
+    }
+    let schema =
+        Schema::parse(supergraph_sdl, "schema.graphql").unwrap_or_else(|invalid| invalid.partial);
+    let mut unknown: Vec<String> = fields
+        .keys()
+        .filter(|coordinate| {
+            coordinate
+                .split_once('.')
+                .map_or(true, |(type_name, field_name)| {
+                    schema.type_field(type_name, field_name).is_err()
+                })
+        })
+        .cloned()
+        .collect();
+    unknown.sort_unstable();
+    unknown
+}
+
+/// The errors of the enforced deprecated fields used by an operation. The client is exempted as
+/// `exempt_as`, and never if it is `None`
+fn enforce(
+    fields: &HashMap<String, FieldEnforcement>,
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    client_name: &str,
+    exempt_as: Option<&str>,
+    clients: &ReportedClients,
+    today: Date,
+) -> Vec<graphql::Error> {
+    let Ok(operation) = document.get_operation(operation_name) else {
+        return Vec::new();
+    };
+    let mut used = Vec::new();
+    collect_fields(
+        document,
+        &operation.selection_set,
+        fields,
+        &mut HashSet::new(),
+        &mut used,
+    );
+
+    if used.is_empty() {
+        return Vec::new();
+    }
+
+    let reported_client = clients.client_name(client_name);
+    let mut errors = Vec::new();
+    for (coordinate, field) in used {
+        let enforcement = &fields[&coordinate];
+        let action = if today < enforcement.enforced_from {
+            "allowed"
+        } else if enforcement
+            .exempt_clients
+            .iter()
+            .any(|exempt| Some(exempt.as_str()) == exempt_as)
+        {
+            "exempted"
+        } else {
+            "rejected"
+        };
+        u64_counter!(
+            "apollo.router.operations.deprecated_fields",
+            "Number of operations using a deprecated field with an enforcement date, by client name",
+            1,
+            client.name = reported_client.clone(),
+            coordinate = coordinate.clone(),
+            action = action
+        );
+        if action != "rejected" {
+            continue;
+        }
+
+        let hint = enforcement.hint.clone().or_else(|| {
+            field
+                .definition
+                .directives
+                .get("deprecated")?
+                .argument_by_name("reason")?
+                .as_str()
+                .map(str::to_string)
+        });
+        let mut message = format!(
+            "the field `{coordinate}` cannot be used since {}",
+            enforcement.enforced_from
+        );
+        if let Some(hint) = &hint {
+            message.push_str(": ");
+            message.push_str(hint);
+        }
+        let mut error = graphql::Error::builder()
+            .message(message)
+            .extension_code("DEPRECATED_FIELD_ENFORCED")
+            .extension("coordinate", coordinate.clone())
+            .extension("enforcedFrom", enforcement.enforced_from.to_string())
+            .build();
+        if let Some(hint) = hint {
+            error.extensions.insert("migrationHint", hint.into());
+        }
+        errors.push(error);
+    }
+    errors
+}
+
+/// Collects the enforced fields used by a selection set and its fragments, once per coordinate
+fn collect_fields<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a executable::SelectionSet,
+    fields: &HashMap<String, FieldEnforcement>,
+    fragments_seen: &mut HashSet<&'a executable::Name>,
+    used: &mut Vec<(String, &'a executable::Field)>,
+) {
+    for selection in &selection_set.selections {
+        match selection {
+            executable::Selection::Field(field) => {
+                let coordinate = format!("{}.{}", selection_set.ty, field.name);
+                if fields.contains_key(&coordinate)
+                    && !used.iter().any(|(seen, _)| *seen == coordinate)
+                {
+                    used.push((coordinate, field));
+                }
+                collect_fields(document, &field.selection_set, fields, fragments_seen, used);
+            }
+            executable::Selection::InlineFragment(inline) => {
+                collect_fields(
+                    document,
+                    &inline.selection_set,
+                    fields,
+                    fragments_seen,
+                    used,
+                );
+            }
+            executable::Selection::FragmentSpread(spread) => {
+                if fragments_seen.insert(&spread.fragment_name) {
+                    if let Some(fragment) = document.fragments.get(&spread.fragment_name) {
+                        collect_fields(
+                            document,
+                            &fragment.selection_set,
+                            fields,
+                            fragments_seen,
+                            used,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apollo_compiler::ast;
+
+    use super::*;
+    use crate::metrics::FutureMetricsExt;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            me: User
+        }
+        type User {
+            id: ID!
+            name: String @deprecated(reason: "use `fullName`")
+            fullName: String
+            nickname: String @deprecated
+        }
+    "#;
+
+    fn config() -> HashMap<String, FieldEnforcement> {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "fields": {
+                "User.name": {
+                    "enforced_from": "2026-03-01",
+                    "exempt_clients": ["legacy-ios"]
+                },
+                "User.nickname": {
+                    "enforced_from": "2026-06-01",
+                    "hint": "use `User.fullName` instead"
+                }
+            }
+        }))
+        .unwrap();
+        config.fields
+    }
+
+    fn clients(max_clients: usize) -> ReportedClients {
+        ReportedClients {
+            max_clients,
+            names: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn errors(query: &str, client_name: &str, today: &str) -> Vec<String> {
+        errors_with_clients(query, client_name, Some(client_name), &clients(100), today)
+    }
+
+    fn errors_with_clients(
+        query: &str,
+        client_name: &str,
+        exempt_as: Option<&str>,
+        clients: &ReportedClients,
+        today: &str,
+    ) -> Vec<String> {
+        let schema = apollo_compiler::Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let document = ast::Document::parse(query, "query.graphql")
+            .unwrap()
+            .to_executable_validate(&schema)
+            .unwrap();
+        enforce(
+            &config(),
+            &document,
+            None,
+            client_name,
+            exempt_as,
+            clients,
+            parse_date(today).unwrap(),
+        )
+        .into_iter()
+        .map(|error| error.message)
+        .collect()
+    }
+
+    #[test]
+    fn it_rejects_deprecated_fields_after_their_enforcement_date() {
+        let query = "{ me { ...user name } } fragment user on User { id name nickname }";
+        assert!(errors(query, "web", "2026-02-28").is_empty());
+        assert_eq!(
+            errors(query, "web", "2026-03-01"),
+            ["the field `User.name` cannot be used since 2026-03-01: use `fullName`"]
+        );
+        assert_eq!(
+            errors(query, "web", "2026-06-01"),
+            [
+                "the field `User.name` cannot be used since 2026-03-01: use `fullName`",
+                "the field `User.nickname` cannot be used since 2026-06-01: use `User.fullName` instead"
+            ]
+        );
+        assert!(errors("{ me { id fullName } }", "web", "2026-06-01").is_empty());
+    }
+
+    #[test]
+    fn it_exempts_clients() {
+        let query = "{ me { name } }";
+        assert!(errors(query, "legacy-ios", "2026-06-01").is_empty());
+        assert_eq!(errors(query, "legacy-android", "2026-06-01").len(), 1);
+    }
+
+    #[test]
+    fn it_exempts_clients_by_jwt_claim() {
+        let context = Context::new();
+        context
+            .insert(CLIENT_NAME, "legacy-ios".to_string())
+            .unwrap();
+        assert_eq!(
+            exempt_as(&context, "legacy-ios", None).as_deref(),
+            Some("legacy-ios")
+        );
+        // The client name header is ignored once the exemptions are bound to a claim
+        assert_eq!(exempt_as(&context, "legacy-ios", Some("client_id")), None);
+        context
+            .insert(
+                APOLLO_AUTHENTICATION_JWT_CLAIMS,
+                serde_json::json!({ "sub": "user", "client_id": "legacy-ios" }),
+            )
+            .unwrap();
+        assert_eq!(
+            exempt_as(&context, "web", Some("client_id")).as_deref(),
+            Some("legacy-ios")
+        );
+        assert_eq!(
+            exempt_as(&context, "legacy-ios", Some("sub")).as_deref(),
+            Some("user")
+        );
+
+        let query = "{ me { name } }";
+        assert!(errors_with_clients(
+            query,
+            "web",
+            Some("legacy-ios"),
+            &clients(100),
+            "2026-06-01"
+        )
+        .is_empty());
+        assert_eq!(
+            errors_with_clients(query, "legacy-ios", None, &clients(100), "2026-06-01").len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn it_bounds_the_reported_client_names() {
+        async {
+            let clients = clients(1);
+            let query = "{ me { name } }";
+            // the clients not using enforced fields are not reported
+            errors_with_clients("{ me { id } }", "ios", Some("ios"), &clients, "2026-06-01");
+            errors_with_clients(query, "web", Some("web"), &clients, "2026-06-01");
+            errors_with_clients(query, "android", Some("android"), &clients, "2026-06-01");
+            assert_counter!(
+                "apollo.router.operations.deprecated_fields",
+                1,
+                "client.name" = "web",
+                "coordinate" = "User.name",
+                "action" = "rejected"
+            );
+            assert_counter!(
+                "apollo.router.operations.deprecated_fields",
+                1,
+                "client.name" = "other",
+                "coordinate" = "User.name",
+                "action" = "rejected"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn it_rejects_unknown_fields() {
+        let mut fields = config();
+        assert!(unknown_fields(SCHEMA, &fields).is_empty());
+        let typo = fields["User.name"].clone();
+        fields.insert("User.nmae".to_string(), typo.clone());
+        fields.insert("Account.name".to_string(), typo.clone());
+        fields.insert("User".to_string(), typo);
+        assert_eq!(
+            unknown_fields(SCHEMA, &fields),
+            ["Account.name", "User", "User.nmae"]
+        );
+
+        let plugin = DeprecationEnforcement::new(PluginInit::fake_new(
+            Config {
+                fields,
+                ..Default::default()
+            },
+            Arc::new(SCHEMA.to_string()),
+        ))
+        .await;
+        assert!(plugin.is_err());
+    }
+
+    #[test]
+    fn it_rejects_invalid_dates() {
+        assert_eq!(
+            parse_date("2026-03-01"),
+            Date::from_calendar_date(2026, Month::March, 1).ok()
+        );
+        assert_eq!(parse_date("2026-02-30"), None);
+        assert_eq!(parse_date("03/01/2026"), None);
+        assert!(serde_json::from_value::<Config>(serde_json::json!({
+            "fields": { "User.name": { "enforced_from": "tomorrow" } }
+        }))
+        .is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! An endpoint changing the log level of the router at runtime, such as to enable the trace logs of
+//! one module for a few minutes without restarting the router.
+//!
+//! The endpoint is disabled by default and served on its own listener, which should only be
+//! reachable by administrators.
+use std::sync::Arc;
+use std::time::Duration;
+
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::reload::log_levels;
+use crate::plugins::telemetry::reload::LogLevels;
+use crate::register_plugin;
+use crate::services::router;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+register_plugin!("apollo", "experimental_log_levels", LogLevelsEndpoint);
+
+/// Expose an endpoint changing the log level of the router at runtime
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Set to true to enable the log level endpoint
+    enabled: bool,
+    /// The listen address of the log level endpoint. It should only be reachable by administrators
+    listen: ListenAddr,
+    /// The path of the log level endpoint
+    path: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: ListenAddr::SocketAddr("127.0.0.1:6060".parse().expect("valid listenAddr")),
+            path: "/log_level".to_string(),
+        }
+    }
+}
+
+struct LogLevelsEndpoint {
+    config: Config,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct LogLevelParameters {
+    /// How long the new directives apply, such as `5m`, before going back to the initial log level
+    duration: Option<String>,
+}
+
+fn response(
+    status: StatusCode,
+    body: impl Into<router::Body>,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "text/plain")
+            .body(body.into())?,
+        context,
+    })
+}
+
+/// `GET` returns the directives in effect, `PUT` adds the directives of its body to the initial
+/// ones, optionally for a `duration`, and `DELETE` goes back to the initial log level
+async fn log_level(
+    request: router::Request,
+    log_levels: Option<Arc<LogLevels>>,
+) -> Result<router::Response, BoxError> {
+    let context = request.context;
+    let Some(log_levels) = log_levels else {
+        return response(
+            StatusCode::NOT_IMPLEMENTED,
+            "the log level can only be changed when the router manages its own logging",
+            context,
+        );
+    };
+    let method = request.router_request.method().clone();
+    let result = if method == Method::GET {
+        Ok(())
+    } else if method == Method::PUT || method == Method::POST {
+        let parameters: LogLevelParameters = match serde_urlencoded::from_str(
+            request.router_request.uri().query().unwrap_or_default(),
+        ) {
+            Ok(parameters) => parameters,
+            Err(error) => {
+                return response(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid parameters: {error}"),
+                    context,
+                )
+            }
+        };
+        let duration = match parameters
+            .duration
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()
+        {
+            Ok(duration) => duration,
+            Err(error) => {
+                return response(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid duration: {error}"),
+                    context,
+                )
+            }
+        };
+        let body = hyper::body::to_bytes(request.router_request.into_body()).await?;
+        let directives = String::from_utf8_lossy(&body);
+        let directives = directives.trim();
+        if directives.is_empty() {
+            return response(
+                StatusCode::BAD_REQUEST,
+                "the body must contain log directives, such as `apollo_router::query_planner=trace`",
+                context,
+            );
+        }
+        log_levels.set(directives, duration.filter(|d| *d > Duration::ZERO))
+    } else if method == Method::DELETE {
+        log_levels.reset()
+    } else {
+        return response(StatusCode::METHOD_NOT_ALLOWED, "", context);
+    };
+
+    match result {
+        Ok(()) => response(StatusCode::OK, log_levels.current(), context),
+        Err(error) => response(
+            StatusCode::BAD_REQUEST,
+            format!("invalid log directives: {error}"),
+            context,
+        ),
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for LogLevelsEndpoint {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(LogLevelsEndpoint {
+            config: init.config,
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if !self.config.enabled {
+            return map;
+        }
+
+        map.insert(
+            self.config.listen.clone(),
+            Endpoint::from_router_service(
+                self.config.path.clone(),
+                tower::service_fn(|request| log_level(request, log_levels())).boxed(),
+            ),
+        );
+        tracing::info!(
+            "Log level endpoint exposed at {}{}",
+            self.config.listen,
+            self.config.path
+        );
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use parking_lot::Mutex;
+    use tracing_subscriber::EnvFilter;
+
+    use super::*;
+
+    fn request(method: Method, uri: &str, body: &'static str) -> router::Request {
+        http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(router::Body::from(body))
+            .unwrap()
+            .into()
+    }
+
+    async fn call(
+        log_levels: &Arc<LogLevels>,
+        method: Method,
+        uri: &str,
+        body: &'static str,
+    ) -> (StatusCode, String) {
+        let response = log_level(request(method, uri, body), Some(log_levels.clone()))
+            .await
+            .unwrap()
+            .response;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_changes_the_log_level() {
+        let reloaded = Arc::new(Mutex::new(Vec::new()));
+        let reloaded_filters = reloaded.clone();
+        let log_levels = Arc::new(LogLevels::new(
+            "info".to_string(),
+            Box::new(move |filter: EnvFilter| {
+                reloaded_filters.lock().push(filter.to_string());
+                Ok(())
+            }),
+        ));
+
+        assert_eq!(
+            call(&log_levels, Method::GET, "/log_level", "").await,
+            (StatusCode::OK, "info".to_string())
+        );
+        assert_eq!(
+            call(
+                &log_levels,
+                Method::PUT,
+                "/log_level",
+                "apollo_router::query_planner=trace"
+            )
+            .await,
+            (
+                StatusCode::OK,
+                "info,apollo_router::query_planner=trace".to_string()
+            )
+        );
+        assert_eq!(
+            call(&log_levels, Method::PUT, "/log_level", "apollo_router=loud")
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            call(&log_levels, Method::DELETE, "/log_level", "").await,
+            (StatusCode::OK, "info".to_string())
+        );
+        assert_eq!(reloaded.lock().len(), 2);
+
+        // Directives with a duration are reset once it elapsed
+        assert_eq!(
+            call(
+                &log_levels,
+                Method::PUT,
+                "/log_level?duration=50ms",
+                "apollo_router=debug"
+            )
+            .await,
+            (StatusCode::OK, "info,apollo_router=debug".to_string())
+        );
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(log_levels.current(), "info");
+        assert_eq!(
+            call(
+                &log_levels,
+                Method::PUT,
+                "/log_level?duration=soon",
+                "apollo_router=debug"
+            )
+            .await
+            .0,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_directives_changed_before_the_expiration() {
+        let log_levels = Arc::new(LogLevels::new(
+            "info".to_string(),
+            Box::new(|_: EnvFilter| Ok(())),
+        ));
+        log_levels
+            .set("apollo_router=debug", Some(Duration::from_millis(50)))
+            .unwrap();
+        log_levels.set("apollo_router=trace", None).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(log_levels.current(), "info,apollo_router=trace");
+    }
+
+    #[tokio::test]
+    async fn it_requires_the_router_logging() {
+        let response = log_level(request(Method::GET, "/log_level", ""), None)
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+}
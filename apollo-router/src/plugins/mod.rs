@@ -23,15 +23,28 @@ macro_rules! schemar_fn {
 pub(crate) mod authentication;
 pub(crate) mod authorization;
 pub(crate) mod cache;
+mod cache_admin;
+mod client_protocols;
 mod coprocessor;
 pub(crate) mod csrf;
+mod deprecation_enforcement;
 mod expose_query_plan;
 mod forbid_mutations;
 mod headers;
 mod include_subgraph_errors;
+mod log_levels;
+mod operation_stubs;
 pub(crate) mod override_url;
+mod profiling;
 mod record_replay;
 pub(crate) mod rhai;
+mod schema_change_webhook;
+mod schema_endpoint;
+mod serve_as_subgraph;
+mod server_timing;
+pub(crate) mod shadow;
+mod subgraph_error_mapping;
 pub(crate) mod subscription;
 pub(crate) mod telemetry;
+mod trace_sampling;
 pub(crate) mod traffic_shaping;
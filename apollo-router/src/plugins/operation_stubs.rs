@@ -0,0 +1,312 @@
+//! Answer specific operations with canned responses from the configuration.
+//!
+//! Stubbed operations are matched by name and/or GraphOS operation ID once their query plan is
+//! known, and answered without executing the plan, so no subgraph is called. This keeps
+//! decommissioned features answering, bypasses failing subgraphs during incidents, and gives
+//! synthetic monitors a target that does not depend on subgraphs.
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::context::OPERATION_NAME;
+use crate::graphql;
+use crate::json_ext::Object;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::APOLLO_OPERATION_ID;
+use crate::register_plugin;
+use crate::services::execution;
+use crate::Context;
+
+register_plugin!("apollo", "experimental_operation_stubs", OperationStubs);
+
+/// Canned responses for specific operations, returned without calling subgraphs
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Stubs are evaluated in order, the first stub matching an operation is used
+    stubs: Vec<StubConfig>,
+}
+
+/// A canned response for the operations matching all of the configured conditions
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct StubConfig {
+    /// Match the name of the operation
+    #[serde(default)]
+    operation_name: Option<String>,
+    /// Match the GraphOS operation ID, the hash of the operation signature
+    #[serde(default)]
+    operation_id: Option<String>,
+    /// The `data` of the response
+    #[serde(default)]
+    #[schemars(with = "Option<serde_json::Value>")]
+    data: Option<Value>,
+    /// The `errors` of the response
+    #[serde(default)]
+    errors: Vec<StubError>,
+    /// HTTP status code of the response (default: 200)
+    #[serde(default)]
+    status: Option<u16>,
+}
+
+/// A GraphQL error of a canned response
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct StubError {
+    /// The message of the error
+    message: String,
+    /// The extensions of the error
+    #[serde(default)]
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+    extensions: Object,
+}
+
+struct Stub {
+    operation_name: Option<String>,
+    operation_id: Option<String>,
+    data: Option<Value>,
+    errors: Vec<graphql::Error>,
+    status: StatusCode,
+}
+
+impl Stub {
+    fn new(stub: StubConfig) -> Result<Self, BoxError> {
+        if stub.operation_name.is_none() && stub.operation_id.is_none() {
+            return Err("operation stubs must set operation_name or operation_id".into());
+        }
+        let status = match stub.status {
+            Some(status) => StatusCode::from_u16(status)
+                .map_err(|_| format!("invalid HTTP status code {status} in operation stub"))?,
+            None => StatusCode::OK,
+        };
+        Ok(Stub {
+            operation_name: stub.operation_name,
+            operation_id: stub.operation_id,
+            data: stub.data,
+            errors: stub
+                .errors
+                .into_iter()
+                .map(|error| graphql::Error {
+                    message: error.message,
+                    extensions: error.extensions,
+                    ..Default::default()
+                })
+                .collect(),
+            status,
+        })
+    }
+
+    fn matches(&self, operation_name: Option<&str>, operation_id: Option<&str>) -> bool {
+        self.operation_name
+            .as_deref()
+            .map_or(true, |name| operation_name == Some(name))
+            && self
+                .operation_id
+                .as_deref()
+                .map_or(true, |id| operation_id == Some(id))
+    }
+
+    fn response(&self, context: Context) -> Result<execution::Response, BoxError> {
+        execution::Response::builder()
+            .and_data(self.data.clone())
+            .errors(self.errors.clone())
+            .status_code(self.status)
+            .context(context)
+            .build()
+    }
+}
+
+struct OperationStubs {
+    stubs: Arc<Vec<Stub>>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for OperationStubs {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let stubs = init
+            .config
+            .stubs
+            .into_iter()
+            .map(Stub::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(OperationStubs {
+            stubs: Arc::new(stubs),
+        })
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        if self.stubs.is_empty() {
+            return service;
+        }
+
+        let stubs = self.stubs.clone();
+        ServiceBuilder::new()
+            .checkpoint(move |req: execution::Request| {
+                let operation_name: Option<String> = req.context.get(OPERATION_NAME).ok().flatten();
+                let operation_id: Option<String> =
+                    req.context.get(APOLLO_OPERATION_ID).ok().flatten();
+                let stub = stubs
+                    .iter()
+                    .find(|stub| stub.matches(operation_name.as_deref(), operation_id.as_deref()));
+                match stub {
+                    Some(stub) => {
+                        u64_counter!(
+                            "apollo_router_operation_stub_hits_total",
+                            "Number of operations answered by an operation stub",
+                            1,
+                            operation_name = operation_name.unwrap_or_default()
+                        );
+                        Ok(ControlFlow::Break(stub.response(req.context)?))
+                    }
+                    None => Ok(ControlFlow::Continue(req)),
+                }
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+    use tower::Service;
+
+    use super::*;
+    use crate::metrics::FutureMetricsExt;
+    use crate::plugin::test::MockExecutionService;
+
+    async fn plugin(config: serde_json::Value) -> Result<OperationStubs, BoxError> {
+        OperationStubs::new(PluginInit::fake_new(
+            serde_json::from_value(config).unwrap(),
+            Default::default(),
+        ))
+        .await
+    }
+
+    fn request(operation_name: Option<&str>, operation_id: &str) -> execution::Request {
+        let request = execution::Request::fake_builder().build();
+        if let Some(operation_name) = operation_name {
+            request
+                .context
+                .insert(OPERATION_NAME, operation_name.to_string())
+                .unwrap();
+        }
+        request
+            .context
+            .insert(APOLLO_OPERATION_ID, operation_id.to_string())
+            .unwrap();
+        request
+    }
+
+    #[tokio::test]
+    async fn it_answers_stubbed_operations() {
+        async {
+            let plugin = plugin(serde_json::json!({
+                "stubs": [
+                    {
+                        "operation_name": "Legacy",
+                        "data": { "legacy": null },
+                        "errors": [
+                            {
+                                "message": "this feature was removed",
+                                "extensions": { "code": "DECOMMISSIONED" }
+                            }
+                        ]
+                    },
+                    {
+                        "operation_name": "Monitor",
+                        "operation_id": "1234",
+                        "data": { "ok": true },
+                        "status": 203
+                    }
+                ]
+            }))
+            .await
+            .unwrap();
+            let mut mock_service = MockExecutionService::new();
+            mock_service.expect_call().times(2).returning(|req| {
+                Ok(execution::Response::fake_builder()
+                    .data(json!({ "passed": true }))
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+            let mut service = plugin.execution_service(mock_service.boxed());
+
+            let mut response = service
+                .ready()
+                .await
+                .unwrap()
+                .call(request(Some("Legacy"), "abcd"))
+                .await
+                .unwrap();
+            assert_eq!(response.response.status(), StatusCode::OK);
+            let response = response.next_response().await.unwrap();
+            assert_eq!(response.data, Some(json!({ "legacy": null })));
+            assert_eq!(response.errors[0].message, "this feature was removed");
+            assert_eq!(
+                response.errors[0].extensions.get("code"),
+                Some(&json!("DECOMMISSIONED"))
+            );
+            assert_counter!(
+                "apollo_router_operation_stub_hits_total",
+                1,
+                "operation_name" = "Legacy"
+            );
+
+            let mut response = service
+                .ready()
+                .await
+                .unwrap()
+                .call(request(Some("Monitor"), "1234"))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.response.status(),
+                StatusCode::NON_AUTHORITATIVE_INFORMATION
+            );
+            let response = response.next_response().await.unwrap();
+            assert_eq!(response.data, Some(json!({ "ok": true })));
+
+            // Every condition must match
+            for request in [request(Some("Monitor"), "5678"), request(None, "1234")] {
+                let response = service
+                    .ready()
+                    .await
+                    .unwrap()
+                    .call(request)
+                    .await
+                    .unwrap()
+                    .next_response()
+                    .await
+                    .unwrap();
+                assert_eq!(response.data, Some(json!({ "passed": true })));
+            }
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_stubs() {
+        assert!(plugin(serde_json::json!({ "stubs": [{ "data": {} }] }))
+            .await
+            .is_err());
+        assert!(plugin(serde_json::json!({
+            "stubs": [{ "operation_name": "A", "status": 1000 }]
+        }))
+        .await
+        .is_err());
+    }
+}
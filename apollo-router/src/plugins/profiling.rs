@@ -0,0 +1,318 @@
+//! Endpoints to diagnose stalls in production: on-demand CPU profiles and async task dumps.
+//!
+//! The endpoints are disabled by default and served on their own listener, which should only be
+//! reachable by administrators.
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::router;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+const DEFAULT_PROFILE_DURATION: Duration = Duration::from_secs(10);
+const DEFAULT_FREQUENCY: i32 = 99;
+
+/// Only one CPU profile can be captured at a time
+static PROFILING: AtomicBool = AtomicBool::new(false);
+
+register_plugin!("apollo", "experimental_profiling", Profiling);
+
+/// Expose endpoints capturing a CPU profile or a dump of the async tasks of the router
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Set to true to enable the profiling endpoints
+    enabled: bool,
+    /// The listen address of the profiling endpoints. It should only be reachable by administrators
+    listen: ListenAddr,
+    /// The path prefix of the profiling endpoints
+    path: String,
+    /// The longest CPU profile that can be requested (default: 60s)
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    max_duration: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: ListenAddr::SocketAddr("127.0.0.1:6060".parse().expect("valid listenAddr")),
+            path: "/debug/pprof".to_string(),
+            max_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+struct Profiling {
+    config: Config,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ProfileParameters {
+    /// Duration of the profile, in seconds
+    seconds: Option<u64>,
+    /// Sampling frequency, in Hz
+    frequency: Option<i32>,
+}
+
+/// Marks a CPU profile as running until dropped
+struct ProfilingGuard;
+
+impl ProfilingGuard {
+    fn acquire() -> Option<Self> {
+        PROFILING
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| ProfilingGuard)
+    }
+}
+
+impl Drop for ProfilingGuard {
+    fn drop(&mut self) {
+        PROFILING.store(false, Ordering::Release);
+    }
+}
+
+fn response(
+    status: StatusCode,
+    content_type: &'static str,
+    body: impl Into<router::Body>,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(body.into())?,
+        context,
+    })
+}
+
+async fn profile(
+    request: router::Request,
+    max_duration: Duration,
+) -> Result<router::Response, BoxError> {
+    let context = request.context;
+    let parameters: ProfileParameters = match serde_urlencoded::from_str(
+        request.router_request.uri().query().unwrap_or_default(),
+    ) {
+        Ok(parameters) => parameters,
+        Err(error) => {
+            return response(
+                StatusCode::BAD_REQUEST,
+                "text/plain",
+                format!("invalid parameters: {error}"),
+                context,
+            )
+        }
+    };
+    let duration = parameters
+        .seconds
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_PROFILE_DURATION);
+    if duration.is_zero() || duration > max_duration {
+        return response(
+            StatusCode::BAD_REQUEST,
+            "text/plain",
+            format!(
+                "the profile duration must be between 1s and {}",
+                humantime::format_duration(max_duration)
+            ),
+            context,
+        );
+    }
+    let frequency = parameters.frequency.unwrap_or(DEFAULT_FREQUENCY);
+    if !(1..=1000).contains(&frequency) {
+        return response(
+            StatusCode::BAD_REQUEST,
+            "text/plain",
+            "the sampling frequency must be between 1 and 1000 Hz",
+            context,
+        );
+    }
+
+    let Some(guard) = ProfilingGuard::acquire() else {
+        return response(
+            StatusCode::CONFLICT,
+            "text/plain",
+            "a CPU profile is already being captured",
+            context,
+        );
+    };
+    tracing::info!("capturing a CPU profile for {}s", duration.as_secs());
+    // The profiler samples every thread, the blocking thread only waits for the end of the profile
+    let profile = tokio::task::spawn_blocking(move || {
+        let _guard = guard;
+        cpu_profile(duration, frequency)
+    })
+    .await?;
+
+    match profile {
+        Ok(profile) => response(StatusCode::OK, "application/octet-stream", profile, context),
+        Err(error) => {
+            tracing::error!("could not capture a CPU profile: {error}");
+            response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "text/plain",
+                format!("could not capture a CPU profile: {error}"),
+                context,
+            )
+        }
+    }
+}
+
+/// Returns the profile encoded in the pprof protobuf format
+#[cfg(unix)]
+fn cpu_profile(duration: Duration, frequency: i32) -> Result<Vec<u8>, BoxError> {
+    use pprof::protos::Message;
+
+    let profiler = pprof::ProfilerGuardBuilder::default()
+        .frequency(frequency)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()?;
+    std::thread::sleep(duration);
+    let profile = profiler.report().build()?.pprof()?;
+    Ok(profile.encode_to_vec())
+}
+
+#[cfg(not(unix))]
+fn cpu_profile(_duration: Duration, _frequency: i32) -> Result<Vec<u8>, BoxError> {
+    Err("CPU profiles are not supported on this platform".into())
+}
+
+async fn tasks(request: router::Request) -> Result<router::Response, BoxError> {
+    match task_dump().await {
+        Ok(dump) => response(StatusCode::OK, "text/plain", dump, request.context),
+        Err((status, error)) => response(status, "text/plain", error, request.context),
+    }
+}
+
+/// Returns the backtraces of the tasks of the router's runtime
+#[cfg(all(tokio_unstable, tokio_taskdump))]
+async fn task_dump() -> Result<String, (StatusCode, String)> {
+    use std::fmt::Write;
+
+    // Tasks are traced when they yield, a busy task delays the dump
+    let dump = tokio::time::timeout(
+        Duration::from_secs(10),
+        tokio::runtime::Handle::current().dump(),
+    )
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the task dump timed out".to_string(),
+        )
+    })?;
+    let mut result = String::new();
+    for (index, task) in dump.tasks().iter().enumerate() {
+        let _ = writeln!(result, "task {index}:\n{}\n", task.trace());
+    }
+    Ok(result)
+}
+
+#[cfg(not(all(tokio_unstable, tokio_taskdump)))]
+async fn task_dump() -> Result<String, (StatusCode, String)> {
+    Err((
+        StatusCode::NOT_IMPLEMENTED,
+        "task dumps require a router built with RUSTFLAGS=\"--cfg tokio_unstable --cfg tokio_taskdump\" on Linux".to_string(),
+    ))
+}
+
+#[async_trait::async_trait]
+impl Plugin for Profiling {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Profiling {
+            config: init.config,
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if !self.config.enabled {
+            return map;
+        }
+
+        let path = self.config.path.trim_end_matches('/');
+        let max_duration = self.config.max_duration;
+        map.insert(
+            self.config.listen.clone(),
+            Endpoint::from_router_service(
+                format!("{path}/profile"),
+                tower::service_fn(move |request| profile(request, max_duration)).boxed(),
+            ),
+        );
+        map.insert(
+            self.config.listen.clone(),
+            Endpoint::from_router_service(
+                format!("{path}/tasks"),
+                tower::service_fn(tasks).boxed(),
+            ),
+        );
+        tracing::info!(
+            "Profiling endpoints exposed at {}{path}/profile and {}{path}/tasks",
+            self.config.listen,
+            self.config.listen
+        );
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str) -> router::Request {
+        http::Request::get(uri)
+            .body(router::Body::empty())
+            .unwrap()
+            .into()
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_profile_parameters() {
+        let max_duration = Duration::from_secs(60);
+        for uri in [
+            "/debug/pprof/profile?seconds=120",
+            "/debug/pprof/profile?seconds=0",
+            "/debug/pprof/profile?seconds=ten",
+            "/debug/pprof/profile?frequency=0",
+        ] {
+            let response = profile(request(uri), max_duration).await.unwrap();
+            assert_eq!(response.response.status(), StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[test]
+    fn it_captures_one_profile_at_a_time() {
+        let guard = ProfilingGuard::acquire().unwrap();
+        assert!(ProfilingGuard::acquire().is_none());
+        drop(guard);
+        assert!(ProfilingGuard::acquire().is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn it_encodes_cpu_profiles() {
+        use pprof::protos::Message;
+
+        let profile = cpu_profile(Duration::from_millis(100), DEFAULT_FREQUENCY).unwrap();
+        let profile = pprof::protos::Profile::decode(profile.as_slice()).unwrap();
+        assert!(!profile.sample_type.is_empty());
+    }
+}
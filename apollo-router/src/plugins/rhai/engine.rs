@@ -43,6 +43,7 @@ use crate::configuration::expansion;
 use crate::graphql::Request;
 use crate::graphql::Response;
 use crate::http_ext;
+use crate::plugin::LifecycleEvent;
 use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::plugins::cache::entity::CONTEXT_CACHE_KEY;
 use crate::plugins::subscription::SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS;
@@ -173,6 +174,80 @@ mod router_json {
     }
 }
 
+#[export_module]
+#[allow(unreachable_pub)]
+mod router_error {
+    pub const UNAUTHENTICATED: &str = "UNAUTHENTICATED";
+    pub const FORBIDDEN: &str = "FORBIDDEN";
+    pub const BAD_USER_INPUT: &str = "BAD_USER_INPUT";
+    pub const GRAPHQL_VALIDATION_FAILED: &str = "GRAPHQL_VALIDATION_FAILED";
+    pub const PERSISTED_QUERY_NOT_FOUND: &str = "PERSISTED_QUERY_NOT_FOUND";
+    pub const INTERNAL_SERVER_ERROR: &str = "INTERNAL_SERVER_ERROR";
+
+    /// A GraphQL error with a message
+    #[rhai_fn(name = "graphql")]
+    pub(crate) fn graphql(message: ImmutableString) -> Map {
+        let mut error = Map::new();
+        error.insert("message".into(), message.into());
+        error
+    }
+
+    /// A GraphQL error with a message and an error code
+    #[rhai_fn(name = "graphql")]
+    pub(crate) fn graphql_with_code(message: ImmutableString, code: ImmutableString) -> Map {
+        graphql_with_extensions(message, code, Map::new())
+    }
+
+    /// A GraphQL error with a message, an error code and custom extensions
+    #[rhai_fn(name = "graphql")]
+    pub(crate) fn graphql_with_extensions(
+        message: ImmutableString,
+        code: ImmutableString,
+        mut extensions: Map,
+    ) -> Map {
+        extensions.insert("code".into(), code.into());
+        let mut error = graphql(message);
+        error.insert("extensions".into(), extensions.into());
+        error
+    }
+
+    /// The value to `throw` to stop the pipeline with the given HTTP status and GraphQL errors.
+    /// `errors` is an error or an array of errors
+    #[rhai_fn(return_raw)]
+    pub(crate) fn response(status: i64, errors: Dynamic) -> Result<Map, Box<EvalAltResult>> {
+        let status = u16::try_from(status)
+            .ok()
+            .and_then(|status| http::StatusCode::from_u16(status).ok())
+            .ok_or_else(|| format!("invalid HTTP status code: {status}"))?;
+        let errors = if errors.is_array() {
+            errors.cast::<Array>()
+        } else {
+            vec![errors]
+        };
+        for error in &errors {
+            from_dynamic::<crate::graphql::Error>(error)
+                .map_err(|e| format!("invalid GraphQL error {error}: {e}"))?;
+        }
+
+        let mut body = Map::new();
+        body.insert("errors".into(), errors.into());
+        let mut response = Map::new();
+        response.insert("status".into(), (status.as_u16() as i64).into());
+        response.insert("body".into(), body.into());
+        Ok(response)
+    }
+
+    /// Stops the pipeline with the given HTTP status and GraphQL errors, like throwing the value
+    /// returned by `response`
+    #[rhai_fn(return_raw)]
+    pub(crate) fn abort(status: i64, errors: Dynamic) -> Result<(), Box<EvalAltResult>> {
+        Err(
+            EvalAltResult::ErrorRuntime(response(status, errors)?.into(), rhai::Position::NONE)
+                .into(),
+        )
+    }
+}
+
 #[export_module]
 mod router_expansion {
     pub(crate) type Expansion = expansion::Expansion;
@@ -1548,6 +1623,7 @@ pub(crate) struct RhaiService {
     pub(super) service: ServiceStep,
     pub(super) engine: Arc<Engine>,
     pub(super) ast: AST,
+    pub(super) script: Arc<str>,
 }
 
 impl Rhai {
@@ -1564,6 +1640,7 @@ impl Rhai {
             service,
             engine: block.engine.clone(),
             ast: block.ast.clone(),
+            script: block.script.clone(),
         };
         let mut guard = scope.lock().unwrap();
         // Note: We don't use `process_error()` here, because this code executes in the context of
@@ -1612,6 +1689,7 @@ impl Rhai {
 
         let base64_module = exported_module!(router_base64);
         let json_module = exported_module!(router_json);
+        let error_module = exported_module!(router_error);
 
         let expansion_module = exported_module!(router_expansion);
 
@@ -1638,6 +1716,8 @@ impl Rhai {
             .register_static_module("base64", base64_module.into())
             // Register our json module (not global)
             .register_static_module("json", json_module.into())
+            // Register our error module (not global)
+            .register_static_module("error", error_module.into())
             // Register our expansion module (not global)
             // Hide the fact that it is an expansion module by calling it "env"
             .register_static_module("env", expansion_module.into())
@@ -1697,6 +1777,22 @@ impl Rhai {
         engine
     }
 
+    pub(super) fn run_rhai_lifecycle_event(
+        &self,
+        function_name: &str,
+        event: &LifecycleEvent,
+    ) -> Result<(), String> {
+        let block = self.block.load();
+        let event = to_dynamic(event).map_err(|err| err.to_string())?;
+        let mut guard = block.scope.lock().unwrap();
+        let _ = block
+            .engine
+            .call_fn::<Dynamic>(&mut guard, &block.ast, function_name, (event,))
+            .map_err(|err| err.to_string())?;
+
+        Ok(())
+    }
+
     pub(super) fn ast_has_function(&self, name: &str) -> bool {
         self.block
             .load()
@@ -0,0 +1,90 @@
+//! Execution limits of Rhai scripts.
+
+use std::cell::Cell;
+use std::time::Duration;
+
+use rhai::Dynamic;
+use rhai::Engine;
+use rhai::EvalAltResult;
+use rhai::Instant;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+pub(super) const RHAI_LIMIT_EXCEEDED: &str = "RHAI_LIMIT_EXCEEDED";
+
+/// How often the duration of a callback is checked, in operations
+const DURATION_CHECK_INTERVAL: u64 = 64;
+
+thread_local! {
+    /// Start of the evaluation running on this thread. Rhai evaluations are synchronous, so they
+    /// cannot move to another thread
+    static EVALUATION_START: Cell<Option<Instant>> = Cell::new(None);
+}
+
+/// Limits of each Rhai callback, so that a buggy script cannot hang request processing.
+/// There are no limits by default
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Limits {
+    /// Maximum number of operations a callback can run
+    pub(super) max_operations: Option<u64>,
+    /// Maximum duration of a callback
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "Option<String>", default)]
+    pub(super) max_duration: Option<Duration>,
+    /// Maximum length of strings, in bytes
+    pub(super) max_string_size: Option<usize>,
+    /// Maximum number of items of arrays
+    pub(super) max_array_size: Option<usize>,
+    /// Maximum number of properties of object maps
+    pub(super) max_map_size: Option<usize>,
+    /// Maximum depth of function calls
+    pub(super) max_call_levels: Option<usize>,
+}
+
+impl Limits {
+    pub(super) fn apply(&self, engine: &mut Engine) {
+        if let Some(max_operations) = self.max_operations {
+            engine.set_max_operations(max_operations);
+        }
+        if let Some(max_string_size) = self.max_string_size {
+            engine.set_max_string_size(max_string_size);
+        }
+        if let Some(max_array_size) = self.max_array_size {
+            engine.set_max_array_size(max_array_size);
+        }
+        if let Some(max_map_size) = self.max_map_size {
+            engine.set_max_map_size(max_map_size);
+        }
+        if let Some(max_call_levels) = self.max_call_levels {
+            engine.set_max_call_levels(max_call_levels);
+        }
+        if let Some(max_duration) = self.max_duration {
+            // The operation count starts over with each evaluation
+            engine.on_progress(move |operations| {
+                if operations == 1 {
+                    EVALUATION_START.with(|start| start.set(Some(Instant::now())));
+                } else if operations % DURATION_CHECK_INTERVAL == 0 {
+                    let elapsed = EVALUATION_START
+                        .with(|start| start.get())
+                        .map(|start| start.elapsed());
+                    if matches!(elapsed, Some(elapsed) if elapsed > max_duration) {
+                        return Some(Dynamic::from("max_duration"));
+                    }
+                }
+                None
+            });
+        }
+    }
+}
+
+/// Returns the name of the limit that stopped a script, if any
+pub(super) fn exceeded_limit(error: &EvalAltResult) -> Option<&'static str> {
+    match error.unwrap_inner() {
+        EvalAltResult::ErrorTooManyOperations(..) => Some("max_operations"),
+        EvalAltResult::ErrorTerminated(..) => Some("max_duration"),
+        EvalAltResult::ErrorDataTooLarge(..) => Some("max_data_size"),
+        EvalAltResult::ErrorStackOverflow(..) => Some("max_call_levels"),
+        _ => None,
+    }
+}
@@ -40,8 +40,12 @@ use tower::ServiceExt;
 
 use self::engine::RhaiService;
 use self::engine::SharedMut;
+use self::limits::exceeded_limit;
+use self::limits::Limits;
+use self::limits::RHAI_LIMIT_EXCEEDED;
 use crate::error::Error;
 use crate::layers::ServiceBuilderExt;
+use crate::plugin::LifecycleEvent;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::plugins::rhai::engine::OptionDance;
@@ -52,6 +56,7 @@ mod engine;
 pub(crate) const RHAI_SPAN_NAME: &str = "rhai_plugin";
 
 mod execution;
+mod limits;
 mod router;
 mod subgraph;
 mod supergraph;
@@ -60,6 +65,8 @@ struct EngineBlock {
     ast: AST,
     engine: Arc<Engine>,
     scope: Arc<Mutex<Scope<'static>>>,
+    /// The file name of the main script, used in metrics
+    script: Arc<str>,
 }
 
 impl EngineBlock {
@@ -67,12 +74,15 @@ impl EngineBlock {
         scripts: Option<PathBuf>,
         main: PathBuf,
         sdl: Arc<String>,
+        limits: &Limits,
     ) -> Result<Self, BoxError> {
-        let engine = Arc::new(Rhai::new_rhai_engine(
-            scripts,
-            sdl.to_string(),
-            main.clone(),
-        ));
+        let script = main
+            .file_name()
+            .map(|name| name.to_string_lossy().into())
+            .unwrap_or_else(|| main.display().to_string().into());
+        let mut engine = Rhai::new_rhai_engine(scripts, sdl.to_string(), main.clone());
+        limits.apply(&mut engine);
+        let engine = Arc::new(engine);
         let ast = engine
             .compile_file(main.clone())
             .map_err(|err| format!("in Rhai script {}: {}", main.display(), err))?;
@@ -92,6 +102,7 @@ impl EngineBlock {
             ast,
             engine,
             scope: Arc::new(Mutex::new(scope)),
+            script,
         })
     }
 }
@@ -115,6 +126,9 @@ pub(crate) struct Conf {
     scripts: Option<PathBuf>,
     /// The main entry point for Rhai script evaluation
     main: Option<String>,
+    /// Execution limits of each Rhai callback
+    #[serde(default)]
+    limits: Limits,
 }
 
 #[async_trait::async_trait]
@@ -138,11 +152,13 @@ impl Plugin for Rhai {
         let watched_path = scripts_path.clone();
         let watched_main = main.clone();
         let watched_sdl = sdl.clone();
+        let watched_limits = init.config.limits.clone();
 
         let block = Arc::new(ArcSwap::from_pointee(EngineBlock::try_new(
             Some(scripts_path),
             main,
             sdl,
+            &init.config.limits,
         )?));
         let watched_block = block.clone();
 
@@ -182,6 +198,7 @@ impl Plugin for Rhai {
                                         Some(watching_path.clone()),
                                         watched_main.clone(),
                                         watched_sdl.clone(),
+                                        &watched_limits,
                                     ) {
                                         Ok(eb) => {
                                             tracing::info!("updating rhai execution engine");
@@ -293,6 +310,17 @@ impl Plugin for Rhai {
         }
         shared_service.take_unwrap()
     }
+
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        const FUNCTION_NAME_EVENT: &str = "lifecycle_event";
+        if !self.ast_has_function(FUNCTION_NAME_EVENT) {
+            return;
+        }
+        tracing::debug!("lifecycle_event function found");
+        if let Err(error) = self.run_rhai_lifecycle_event(FUNCTION_NAME_EVENT, event) {
+            tracing::error!("lifecycle event callback failed: {error}");
+        }
+    }
 }
 
 impl Drop for Rhai {
@@ -703,6 +731,15 @@ macro_rules! gen_map_deferred_response {
 }
 
 impl ServiceStep {
+    fn stage(&self) -> &'static str {
+        match self {
+            ServiceStep::Router(_) => "router",
+            ServiceStep::Supergraph(_) => "supergraph",
+            ServiceStep::Execution(_) => "execution",
+            ServiceStep::Subgraph(_) => "subgraph",
+        }
+    }
+
     fn map_request(&mut self, rhai_service: RhaiService, callback: FnPtr) {
         match self {
             ServiceStep::Router(service) => {
@@ -785,6 +822,32 @@ fn default_thrown_status_code() -> StatusCode {
 }
 
 fn process_error(error: Box<EvalAltResult>) -> ErrorDetails {
+    if let Some(limit) = exceeded_limit(&error) {
+        u64_counter!(
+            "apollo_router_rhai_limit_exceeded_total",
+            "Number of Rhai callbacks stopped because they exceeded a limit",
+            1,
+            limit = limit
+        );
+        let message = format!("rhai execution error: the script exceeded its {limit} limit");
+        return ErrorDetails {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: Some(message.clone()),
+            position: Some((&error.position()).into()),
+            body: Some(
+                crate::graphql::Response::builder()
+                    .error(
+                        Error::builder()
+                            .message(message)
+                            .extension_code(RHAI_LIMIT_EXCEEDED)
+                            .extension("limit", limit)
+                            .build(),
+                    )
+                    .build(),
+            ),
+        };
+    }
+
     let mut error_details = ErrorDetails {
         status: StatusCode::INTERNAL_SERVER_ERROR,
         message: Some(format!("rhai execution error: '{error}'")),
@@ -815,14 +878,24 @@ fn execute(
     callback: &FnPtr,
     args: impl FuncArgs,
 ) -> Result<Dynamic, Box<EvalAltResult>> {
-    if callback.is_curried() {
+    let start = std::time::Instant::now();
+    let result = if callback.is_curried() {
         callback.call(&rhai_service.engine, &rhai_service.ast, args)
     } else {
         let mut guard = rhai_service.scope.lock().unwrap();
         rhai_service
             .engine
             .call_fn(&mut guard, &rhai_service.ast, callback.fn_name(), args)
-    }
+    };
+    f64_histogram!(
+        "apollo.router.rhai.function.duration",
+        "Time spent running a Rhai callback, in seconds",
+        start.elapsed().as_secs_f64(),
+        "rhai.script" = rhai_service.script.to_string(),
+        "rhai.stage" = rhai_service.service.stage(),
+        "rhai.function" = callback.fn_name().to_string()
+    );
+    result
 }
 
 register_plugin!("apollo", "rhai", Rhai);
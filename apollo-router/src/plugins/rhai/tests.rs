@@ -3,13 +3,16 @@
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 use std::time::SystemTime;
 
 use http::HeaderMap;
 use http::Method;
 use http::StatusCode;
+use rhai::Dynamic;
 use rhai::Engine;
 use rhai::EvalAltResult;
+use rhai::Scope;
 use serde_json::Value;
 use tower::util::BoxService;
 use tower::BoxError;
@@ -17,6 +20,7 @@ use tower::Service;
 use tower::ServiceExt;
 use uuid::Uuid;
 
+use super::limits::Limits;
 use super::process_error;
 use super::subgraph;
 use super::PathBuf;
@@ -24,9 +28,11 @@ use super::Rhai;
 use crate::graphql::Error;
 use crate::graphql::Request;
 use crate::http_ext;
+use crate::metrics::FutureMetricsExt;
 use crate::plugin::test::MockExecutionService;
 use crate::plugin::test::MockSupergraphService;
 use crate::plugin::DynPlugin;
+use crate::plugin::LifecycleEvent;
 use crate::plugins::rhai::engine::RhaiExecutionDeferredResponse;
 use crate::plugins::rhai::engine::RhaiExecutionResponse;
 use crate::plugins::rhai::engine::RhaiSupergraphDeferredResponse;
@@ -91,6 +97,57 @@ async fn rhai_plugin_supergraph_service() -> Result<(), BoxError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn rhai_plugin_records_callback_durations() {
+    async {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .find(|factory| factory.name == "apollo.rhai")
+            .expect("Plugin not found")
+            .create_instance_without_schema(
+                &Value::from_str(r#"{"scripts":"tests/fixtures", "main":"test.rhai"}"#).unwrap(),
+            )
+            .await
+            .unwrap();
+        let mut router_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+        let context = Context::new();
+        context.insert("test", 5i64).unwrap();
+        let supergraph_req = SupergraphRequest::fake_builder()
+            .context(context)
+            .build()
+            .unwrap();
+
+        let mut supergraph_resp = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(supergraph_req)
+            .await
+            .unwrap();
+        supergraph_resp.next_response().await.unwrap();
+
+        assert_histogram!(
+            "apollo.router.rhai.function.duration",
+            1,
+            "rhai.script" = "test.rhai",
+            "rhai.stage" = "supergraph",
+            "rhai.function" = "supergraph_response"
+        );
+    }
+    .with_metrics()
+    .await;
+}
+
 #[tokio::test]
 async fn rhai_plugin_execution_service_error() -> Result<(), BoxError> {
     let mut mock_service = MockExecutionService::new();
@@ -159,6 +216,64 @@ fn new_rhai_test_engine() -> Engine {
     Rhai::new_rhai_engine(None, "".to_string(), PathBuf::new())
 }
 
+fn exceeded_limit_error(limits: Limits, script: &str) -> Option<crate::graphql::Response> {
+    let mut engine = new_rhai_test_engine();
+    limits.apply(&mut engine);
+    let ast = engine.compile(script).unwrap();
+    let error = engine
+        .call_fn::<Dynamic>(&mut Scope::new(), &ast, "process", ())
+        .unwrap_err();
+    let processed_error = process_error(error);
+    assert_eq!(processed_error.status, StatusCode::INTERNAL_SERVER_ERROR);
+    processed_error.body
+}
+
+#[test]
+fn it_stops_scripts_exceeding_their_limits() {
+    let body = exceeded_limit_error(
+        Limits {
+            max_operations: Some(1000),
+            ..Default::default()
+        },
+        "fn process() { loop {} }",
+    )
+    .unwrap();
+    assert_eq!(
+        body.errors[0].extensions.get("code").unwrap(),
+        "RHAI_LIMIT_EXCEEDED"
+    );
+    assert_eq!(
+        body.errors[0].extensions.get("limit").unwrap(),
+        "max_operations"
+    );
+
+    let body = exceeded_limit_error(
+        Limits {
+            max_duration: Some(Duration::from_millis(10)),
+            ..Default::default()
+        },
+        "fn process() { loop {} }",
+    )
+    .unwrap();
+    assert_eq!(
+        body.errors[0].extensions.get("limit").unwrap(),
+        "max_duration"
+    );
+
+    let body = exceeded_limit_error(
+        Limits {
+            max_string_size: Some(10),
+            ..Default::default()
+        },
+        r#"fn process() { let s = ""; loop { s += "0123456789"; } }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        body.errors[0].extensions.get("limit").unwrap(),
+        "max_data_size"
+    );
+}
+
 // Some of these tests rely extensively on internal implementation details of the tracing_test crate.
 // These are unstable, so these test may break if the tracing_test crate is updated.
 //
@@ -747,6 +862,69 @@ async fn it_cannot_process_om_subgraph_missing_message_and_body() {
     }
 }
 
+#[tokio::test]
+async fn it_can_build_errors_with_helpers() {
+    let error = base_process_function("process_subgraph_response_error_helpers")
+        .await
+        .unwrap_err();
+
+    let processed_error = process_error(error);
+    assert_eq!(processed_error.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        processed_error.body,
+        Some(
+            crate::response::Response::builder()
+                .errors(vec![Error::builder()
+                    .message("you must be logged in")
+                    .extension_code("UNAUTHENTICATED")
+                    .extension("reason", "expired")
+                    .build()])
+                .build()
+        )
+    );
+}
+
+#[tokio::test]
+async fn it_can_abort_with_helpers() {
+    let error = base_process_function("process_subgraph_response_error_abort")
+        .await
+        .unwrap_err();
+
+    let processed_error = process_error(error);
+    assert_eq!(processed_error.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        processed_error.body,
+        Some(
+            crate::response::Response::builder()
+                .errors(vec![
+                    Error::builder()
+                        .message("forbidden")
+                        .extension_code("FORBIDDEN")
+                        .build(),
+                    Error {
+                        message: "also forbidden".to_string(),
+                        ..Default::default()
+                    },
+                ])
+                .build()
+        )
+    );
+}
+
+#[tokio::test]
+async fn it_rejects_invalid_errors_in_helpers() {
+    let error = base_process_function("process_subgraph_response_error_invalid")
+        .await
+        .unwrap_err();
+
+    let processed_error = process_error(error);
+    assert_eq!(processed_error.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(processed_error
+        .message
+        .unwrap()
+        .contains("invalid GraphQL error"));
+}
+
 #[tokio::test]
 async fn it_mentions_source_when_syntax_error_occurs() {
     let err: Box<dyn std::error::Error> = crate::plugin::plugins()
@@ -812,3 +990,46 @@ fn it_can_compare_method_strings() {
         .expect("can compare properly");
     assert!(method);
 }
+
+#[tokio::test]
+async fn it_can_process_lifecycle_events() {
+    let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+        .find(|factory| factory.name == "apollo.rhai")
+        .expect("Plugin not found")
+        .create_instance_without_schema(
+            &Value::from_str(r#"{"scripts":"tests/fixtures", "main":"lifecycle_event_test.rhai"}"#)
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    // Downcast our generic plugin. We know it must be Rhai
+    let it: &dyn std::any::Any = dyn_plugin.as_any();
+    let rhai_instance: &Rhai = it.downcast_ref::<Rhai>().expect("downcast");
+
+    for event in [
+        LifecycleEvent::SchemaChanged {
+            previous_schema_id: "previous".to_string(),
+            schema_id: "current".to_string(),
+            previous_supergraph_sdl: Default::default(),
+        },
+        LifecycleEvent::ConfigurationChanged,
+        LifecycleEvent::Draining,
+    ] {
+        rhai_instance
+            .run_rhai_lifecycle_event("lifecycle_event", &event)
+            .expect("event is accepted by the script");
+    }
+
+    let error = rhai_instance
+        .run_rhai_lifecycle_event(
+            "lifecycle_event",
+            &LifecycleEvent::SchemaChanged {
+                previous_schema_id: "previous".to_string(),
+                schema_id: "other".to_string(),
+                previous_supergraph_sdl: Default::default(),
+            },
+        )
+        .expect_err("mismatched schema id is rejected by the script");
+    assert!(error.contains("schema_id: expected"));
+}
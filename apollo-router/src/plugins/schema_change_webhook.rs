@@ -0,0 +1,273 @@
+//! Notify external systems when the router starts serving a new supergraph schema.
+//!
+//! After a schema reload, whatever the schema source, a JSON payload with the previous and new
+//! schema hashes and a summary of the type changes is POSTed to the configured URLs. When a
+//! secret is configured, the payload is signed with HMAC-SHA256.
+use std::sync::Arc;
+use std::time::Duration;
+
+use apollo_compiler::Schema;
+use hmac::Hmac;
+use hmac::Mac;
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use http::Uri;
+use hyper::client::HttpConnector;
+use hyper::Body;
+use hyper_rustls::ConfigBuilderExt;
+use hyper_rustls::HttpsConnector;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+
+use crate::plugin::LifecycleEvent;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::trust_dns_connector::new_async_http_connector;
+use crate::services::trust_dns_connector::AsyncHyperResolver;
+
+type HmacSha256 = Hmac<sha2::Sha256>;
+type HttpClient = hyper::Client<HttpsConnector<HttpConnector<AsyncHyperResolver>>, Body>;
+
+const SIGNATURE_HEADER_NAME: &str = "apollo-router-signature";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+register_plugin!("apollo", "schema_change_webhook", SchemaChangeWebhook);
+
+/// Send a webhook notification when a new supergraph schema is loaded
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// URLs receiving a POST request when a new schema is loaded
+    urls: Vec<String>,
+    /// Key used to sign the notification with HMAC-SHA256. The hex encoded signature is sent
+    /// in the `apollo-router-signature` header, prefixed with `sha256=`
+    secret: Option<String>,
+    /// The timeout for notification requests (default: 5s)
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "Option<String>", default)]
+    timeout: Option<Duration>,
+}
+
+struct SchemaChangeWebhook {
+    urls: Vec<Uri>,
+    secret: Option<String>,
+    timeout: Duration,
+    http_client: HttpClient,
+    /// The schema served by this plugin instance, compared to the schema it replaced
+    supergraph_sdl: Arc<String>,
+}
+
+/// The body of the webhook request
+#[derive(Debug, Serialize, PartialEq)]
+struct Notification {
+    /// The hash of the schema that was replaced
+    previous_schema_id: String,
+    /// The hash of the schema now in use
+    schema_id: String,
+    /// `None` if the previous schema is not known
+    changes: Option<SchemaChanges>,
+}
+
+/// Names of the types that changed between two schemas
+#[derive(Debug, Default, Serialize, PartialEq)]
+struct SchemaChanges {
+    added_types: Vec<String>,
+    removed_types: Vec<String>,
+    modified_types: Vec<String>,
+}
+
+impl SchemaChanges {
+    fn new(previous_sdl: &str, sdl: &str) -> Self {
+        let parse = |sdl: &str| {
+            Schema::parse(sdl, "schema.graphql").unwrap_or_else(|invalid| invalid.partial)
+        };
+        let previous = parse(previous_sdl);
+        let current = parse(sdl);
+
+        let mut changes = SchemaChanges::default();
+        for (name, ty) in &current.types {
+            if ty.is_built_in() {
+                continue;
+            }
+            match previous.types.get(name) {
+                None => changes.added_types.push(name.to_string()),
+                Some(previous_ty) if previous_ty.to_string() != ty.to_string() => {
+                    changes.modified_types.push(name.to_string())
+                }
+                Some(_) => {}
+            }
+        }
+        for (name, ty) in &previous.types {
+            if !ty.is_built_in() && !current.types.contains_key(name) {
+                changes.removed_types.push(name.to_string());
+            }
+        }
+        changes.added_types.sort();
+        changes.removed_types.sort();
+        changes.modified_types.sort();
+        changes
+    }
+}
+
+fn signature(secret: &str, body: &[u8]) -> Result<String, BoxError> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())?;
+    mac.update(body);
+    Ok(format!(
+        "sha256={}",
+        hex::encode(mac.finalize().into_bytes())
+    ))
+}
+
+impl SchemaChangeWebhook {
+    fn notify(&self, previous_schema_id: &str, schema_id: &str, previous_sdl: &str) {
+        let changes = (!previous_sdl.is_empty())
+            .then(|| SchemaChanges::new(previous_sdl, &self.supergraph_sdl));
+        let notification = Notification {
+            previous_schema_id: previous_schema_id.to_string(),
+            schema_id: schema_id.to_string(),
+            changes,
+        };
+        let body = match serde_json::to_vec(&notification) {
+            Ok(body) => body,
+            Err(error) => {
+                tracing::error!("could not serialize schema change notification: {error}");
+                return;
+            }
+        };
+        let signature = match self
+            .secret
+            .as_deref()
+            .map(|secret| signature(secret, &body))
+        {
+            Some(Ok(signature)) => Some(signature),
+            Some(Err(error)) => {
+                tracing::error!("could not sign schema change notification: {error}");
+                return;
+            }
+            None => None,
+        };
+
+        for url in &self.urls {
+            let mut request = http::Request::post(url.clone())
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER_NAME, signature);
+            }
+            let request = match request.body(Body::from(body.clone())) {
+                Ok(request) => request,
+                Err(error) => {
+                    tracing::error!("could not create schema change notification: {error}");
+                    continue;
+                }
+            };
+
+            let http_client = self.http_client.clone();
+            let timeout = self.timeout;
+            let url = url.clone();
+            tokio::spawn(async move {
+                let error = match tokio::time::timeout(timeout, http_client.request(request)).await
+                {
+                    Ok(Ok(response)) if response.status().is_success() => return,
+                    Ok(Ok(response)) => format!("status code {}", response.status()),
+                    Ok(Err(error)) => error.to_string(),
+                    Err(_) => "request timed out".to_string(),
+                };
+                tracing::error!(%url, "schema change notification failed: {error}");
+            });
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for SchemaChangeWebhook {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let urls = init
+            .config
+            .urls
+            .iter()
+            .map(|url| {
+                url.parse::<Uri>()
+                    .map_err(|error| format!("invalid schema change webhook URL '{url}': {error}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut http_connector = new_async_http_connector()?;
+        http_connector.enforce_http(false);
+        let tls_config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_native_roots()
+            .with_no_client_auth();
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(tls_config)
+            .https_or_http()
+            .enable_http1()
+            .wrap_connector(http_connector);
+
+        Ok(SchemaChangeWebhook {
+            urls,
+            secret: init.config.secret,
+            timeout: init.config.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            http_client: hyper::Client::builder().build(connector),
+            supergraph_sdl: init.supergraph_sdl,
+        })
+    }
+
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        if let LifecycleEvent::SchemaChanged {
+            previous_schema_id,
+            schema_id,
+            previous_supergraph_sdl,
+        } = event
+        {
+            if !self.urls.is_empty() {
+                self.notify(previous_schema_id, schema_id, previous_supergraph_sdl);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_summarizes_type_changes() {
+        let previous = r#"
+            type Query { me: User, product: Product }
+            type User { id: ID! }
+            type Product { upc: String }
+        "#;
+        let current = r#"
+            type Query { me: User, review: Review }
+            type User { id: ID!, name: String }
+            type Review { id: ID! }
+        "#;
+
+        assert_eq!(
+            SchemaChanges::new(previous, current),
+            SchemaChanges {
+                added_types: vec!["Review".to_string()],
+                removed_types: vec!["Product".to_string()],
+                modified_types: vec!["Query".to_string(), "User".to_string()],
+            }
+        );
+        assert_eq!(
+            SchemaChanges::new(previous, previous),
+            SchemaChanges::default()
+        );
+    }
+
+    #[test]
+    fn it_signs_notifications() {
+        // echo -n '{"schema_id":"b"}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            signature("secret", br#"{"schema_id":"b"}"#).unwrap(),
+            "sha256=288b6b831b73084371f7f78baf87f1adbe3896934bdc0636871f2d60dcffbb47"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Serve the schema the router is running with, so that tooling can fetch the exact live schema
+//! rather than getting it from out-of-band sources.
+//!
+//! The API schema is served at the configured path, and the supergraph schema, which also
+//! contains the subgraph URLs and inaccessible elements, at `{path}/supergraph` if enabled.
+//! Requests must present one of the configured bearer tokens.
+use std::sync::Arc;
+
+use http::header::AUTHORIZATION;
+use http::header::CONTENT_TYPE;
+use http::header::WWW_AUTHENTICATE;
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::router;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+register_plugin!("apollo", "experimental_schema_endpoint", SchemaEndpoint);
+
+/// Serve the API schema, and optionally the supergraph schema, of the router
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Set to true to enable the schema endpoint
+    enabled: bool,
+    /// The listen address of the schema endpoint
+    listen: ListenAddr,
+    /// The path of the API schema
+    path: String,
+    /// Also serve the supergraph schema at `{path}/supergraph`
+    supergraph: bool,
+    /// Requests must send one of these tokens in the `authorization` header, as
+    /// `Bearer <token>`. At least one token is required
+    bearer_tokens: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: ListenAddr::SocketAddr("127.0.0.1:8088".parse().expect("valid listenAddr")),
+            path: "/schema".to_string(),
+            supergraph: false,
+            bearer_tokens: Vec::new(),
+        }
+    }
+}
+
+struct SchemaEndpoint {
+    config: Config,
+    api_schema: Arc<String>,
+    supergraph_schema: Arc<String>,
+}
+
+/// Checks the bearer token of the request, comparing digests so that the comparison does not
+/// reveal how much of a token matched
+#[derive(Clone)]
+struct Tokens(Arc<Vec<[u8; 32]>>);
+
+impl Tokens {
+    fn new(tokens: &[String]) -> Self {
+        Self(Arc::new(
+            tokens
+                .iter()
+                .map(|token| digest(token.as_bytes()))
+                .collect(),
+        ))
+    }
+
+    fn accepts(&self, request: &router::Request) -> bool {
+        let Some(token) = request
+            .router_request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return false;
+        };
+        let token = digest(token.trim().as_bytes());
+        self.0.iter().any(|accepted| *accepted == token)
+    }
+}
+
+fn digest(token: &[u8]) -> [u8; 32] {
+    Sha256::digest(token).into()
+}
+
+fn response(
+    status: StatusCode,
+    body: impl Into<router::Body>,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    let mut builder = http::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8");
+    if status == StatusCode::UNAUTHORIZED {
+        builder = builder.header(WWW_AUTHENTICATE, "Bearer");
+    }
+    Ok(router::Response {
+        response: builder.body(body.into())?,
+        context,
+    })
+}
+
+fn schema_service(schema: Arc<String>, tokens: Tokens) -> router::BoxService {
+    tower::service_fn(move |request: router::Request| {
+        let schema = schema.clone();
+        let tokens = tokens.clone();
+        async move {
+            if request.router_request.method() != Method::GET {
+                return response(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "the schema can only be fetched with GET requests",
+                    request.context,
+                );
+            }
+            if !tokens.accepts(&request) {
+                return response(
+                    StatusCode::UNAUTHORIZED,
+                    "a valid bearer token is required to fetch the schema",
+                    request.context,
+                );
+            }
+            response(StatusCode::OK, schema.to_string(), request.context)
+        }
+    })
+    .boxed()
+}
+
+#[async_trait::async_trait]
+impl Plugin for SchemaEndpoint {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        if init.config.enabled && init.config.bearer_tokens.is_empty() {
+            return Err("the schema endpoint requires at least one bearer token".into());
+        }
+        let api_schema = if init.config.enabled {
+            apollo_federation::Supergraph::new(&init.supergraph_sdl)
+                .map_err(|error| format!("could not compute the API schema: {error}"))?
+                .to_api_schema()
+                .to_string()
+        } else {
+            String::new()
+        };
+        Ok(SchemaEndpoint {
+            config: init.config,
+            api_schema: Arc::new(api_schema),
+            supergraph_schema: init.supergraph_sdl,
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if !self.config.enabled {
+            return map;
+        }
+
+        let tokens = Tokens::new(&self.config.bearer_tokens);
+        let path = self.config.path.trim_end_matches('/');
+        map.insert(
+            self.config.listen.clone(),
+            Endpoint::from_router_service(
+                path.to_string(),
+                schema_service(self.api_schema.clone(), tokens.clone()),
+            ),
+        );
+        if self.config.supergraph {
+            map.insert(
+                self.config.listen.clone(),
+                Endpoint::from_router_service(
+                    format!("{path}/supergraph"),
+                    schema_service(self.supergraph_schema.clone(), tokens),
+                ),
+            );
+        }
+        tracing::info!("Schema endpoint exposed at {}{path}", self.config.listen);
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = include_str!("../testdata/minimal_supergraph.graphql");
+
+    async fn endpoints(config: serde_json::Value) -> MultiMap<ListenAddr, Endpoint> {
+        let plugin = SchemaEndpoint::new(
+            PluginInit::fake_builder()
+                .config(serde_json::from_value(config).unwrap())
+                .supergraph_sdl(Arc::new(SCHEMA.to_string()))
+                .build(),
+        )
+        .await
+        .unwrap();
+        plugin.web_endpoints()
+    }
+
+    async fn get(endpoint: &Endpoint, path: &str, token: Option<&str>) -> (StatusCode, String) {
+        let mut request = http::Request::get(format!("http://127.0.0.1:8088{path}"));
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        let response = endpoint
+            .clone()
+            .into_router()
+            .oneshot(request.body(hyper::Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_requires_a_token() {
+        let result = SchemaEndpoint::new(
+            PluginInit::fake_builder()
+                .config(serde_json::from_value(serde_json::json!({ "enabled": true })).unwrap())
+                .supergraph_sdl(Arc::new(SCHEMA.to_string()))
+                .build(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_serves_the_schemas() {
+        let endpoints = endpoints(serde_json::json!({
+            "enabled": true,
+            "supergraph": true,
+            "bearer_tokens": ["secret"]
+        }))
+        .await;
+        let endpoints = endpoints
+            .get_vec(&ListenAddr::SocketAddr("127.0.0.1:8088".parse().unwrap()))
+            .unwrap();
+        assert_eq!(endpoints.len(), 2);
+
+        let (status, _) = get(&endpoints[0], "/schema", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = get(&endpoints[0], "/schema", Some("wrong")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, api_schema) = get(&endpoints[0], "/schema", Some("secret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!api_schema.contains("join__"));
+
+        let (status, supergraph_schema) =
+            get(&endpoints[1], "/schema/supergraph", Some("secret")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(supergraph_schema, SCHEMA);
+    }
+}
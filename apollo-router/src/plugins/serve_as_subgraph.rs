@@ -0,0 +1,294 @@
+//! Serve the router's API schema as a federation subgraph, so that another router can compose
+//! it into a larger supergraph.
+//!
+//! The router answers `_service { sdl }` queries with its API schema, as a Federation 2 subgraph
+//! schema whose object types are `@shareable`. The outer router then plans root fields of this
+//! router's graph like those of any other subgraph.
+//!
+//! The scope is limited to queries and mutations: `@defer` is planned by the outer router rather
+//! than passed through, error paths are not remapped since they are already relative to the root
+//! fields, entities are not exposed since the API schema has no keys, and subscriptions are left
+//! out because the router does not serve them over WebSocket or callbacks. Passing `@defer` and
+//! subscriptions through and remapping error paths are left to a follow-up request.
+use std::ops::ControlFlow;
+
+use apollo_compiler::ast;
+use apollo_compiler::name;
+use apollo_compiler::schema::Component;
+use apollo_compiler::schema::ExtendedType;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::json_ext::Object;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "experimental_serve_as_subgraph", ServeAsSubgraph);
+
+const FEDERATION_LINK: &str = r#"extend schema @link(url: "https://specs.apollo.dev/federation/v2.3", import: ["@shareable"])"#;
+const SERVICE_FIELD: &str = "_service";
+const TYPENAME_FIELD: &str = "__typename";
+
+/// Serve the API schema as a federation subgraph, for router-to-router federation
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Set to true to answer `_service { sdl }` queries with the API schema
+    enabled: bool,
+}
+
+struct ServeAsSubgraph {
+    enabled: bool,
+    sdl: String,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ServeAsSubgraph {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let sdl = if init.config.enabled {
+            subgraph_sdl(&init.supergraph_sdl)?
+        } else {
+            String::new()
+        };
+        Ok(ServeAsSubgraph {
+            enabled: init.config.enabled,
+            sdl,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.enabled {
+            return service;
+        }
+
+        let sdl = self.sdl.clone();
+        ServiceBuilder::new()
+            .checkpoint(move |req: supergraph::Request| {
+                let document = req
+                    .context
+                    .private_entries
+                    .lock()
+                    .get::<ParsedDocument>()
+                    .cloned();
+                let data = document.and_then(|document| {
+                    service_query_data(
+                        &document.ast,
+                        req.supergraph_request.body().operation_name.as_deref(),
+                        &sdl,
+                    )
+                });
+                match data {
+                    Some(data) => Ok(ControlFlow::Break(
+                        supergraph::Response::builder()
+                            .data(data)
+                            .context(req.context)
+                            .build()?,
+                    )),
+                    None => Ok(ControlFlow::Continue(req)),
+                }
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+/// The API schema of the supergraph, as a Federation 2 subgraph schema
+fn subgraph_sdl(supergraph_sdl: &str) -> Result<String, BoxError> {
+    let mut schema = apollo_federation::Supergraph::new(supergraph_sdl)
+        .map_err(|error| format!("could not compute the API schema: {error}"))?
+        .to_api_schema();
+
+    if let Some(subscription) = schema.schema_definition.make_mut().subscription.take() {
+        tracing::warn!(
+            "subscriptions are not supported when serving the router as a subgraph, the `{}` type is not exposed",
+            subscription.name
+        );
+        schema.types.remove(&subscription.name);
+    }
+
+    let shareable = Component::new(ast::Directive {
+        name: name!("shareable"),
+        arguments: Vec::new(),
+    });
+    for ty in schema.types.values_mut() {
+        if let ExtendedType::Object(object) = ty {
+            if !object.is_built_in() {
+                object.make_mut().directives.push(shareable.clone());
+            }
+        }
+    }
+
+    Ok(format!("{FEDERATION_LINK}\n\n{schema}"))
+}
+
+/// The response data of the operation if it only selects `_service`, `None` otherwise
+fn service_query_data(
+    document: &ast::Document,
+    operation_name: Option<&str>,
+    sdl: &str,
+) -> Option<Value> {
+    let mut operations = document.definitions.iter().filter_map(|definition| {
+        if let ast::Definition::OperationDefinition(operation) = definition {
+            Some(operation)
+        } else {
+            None
+        }
+    });
+    let operation = match operation_name {
+        Some(name) => operations.find(|operation| {
+            operation
+                .name
+                .as_ref()
+                .is_some_and(|operation| operation.as_str() == name)
+        })?,
+        None => {
+            let operation = operations.next()?;
+            if operations.next().is_some() {
+                return None;
+            }
+            operation
+        }
+    };
+    if operation.operation_type != ast::OperationType::Query {
+        return None;
+    }
+
+    let mut selects_service = false;
+    let mut data = Object::new();
+    for selection in &operation.selection_set {
+        let ast::Selection::Field(field) = selection else {
+            return None;
+        };
+        let value = match field.name.as_str() {
+            TYPENAME_FIELD => Value::String("Query".into()),
+            SERVICE_FIELD => {
+                selects_service = true;
+                service_object(field, sdl)?
+            }
+            _ => return None,
+        };
+        data.insert(field.response_name().as_str(), value);
+    }
+
+    selects_service.then_some(Value::Object(data))
+}
+
+fn service_object(field: &ast::Field, sdl: &str) -> Option<Value> {
+    let mut object = Object::new();
+    for selection in &field.selection_set {
+        let ast::Selection::Field(field) = selection else {
+            return None;
+        };
+        let value = match field.name.as_str() {
+            TYPENAME_FIELD => Value::String("_Service".into()),
+            "sdl" => Value::String(sdl.into()),
+            _ => return None,
+        };
+        object.insert(field.response_name().as_str(), value);
+    }
+    Some(Value::Object(object))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::spec::Query;
+    use crate::spec::Schema;
+    use crate::Configuration;
+
+    const SCHEMA: &str = include_str!("../testdata/supergraph.graphql");
+
+    async fn call(query: &str, operation_name: Option<&str>) -> Option<Value> {
+        let plugin = ServeAsSubgraph::new(
+            PluginInit::fake_builder()
+                .config(Config { enabled: true })
+                .supergraph_sdl(Arc::new(SCHEMA.to_string()))
+                .build(),
+        )
+        .await
+        .unwrap();
+
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().returning(|req| {
+            Ok(supergraph::Response::fake_builder()
+                .data(json!({ "passed": true }))
+                .context(req.context)
+                .build()
+                .unwrap())
+        });
+
+        let configuration = Configuration::default();
+        let schema = Schema::parse(SCHEMA, &configuration).unwrap();
+        let request = supergraph::Request::fake_builder()
+            .query(query)
+            .and_operation_name(operation_name)
+            .build()
+            .unwrap();
+        request
+            .context
+            .private_entries
+            .lock()
+            .insert::<ParsedDocument>(Query::parse_document(query, &schema, &configuration));
+
+        plugin
+            .supergraph_service(mock_service.boxed())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap()
+            .data
+    }
+
+    #[test]
+    fn it_generates_a_shareable_subgraph_schema() {
+        let sdl = subgraph_sdl(SCHEMA).unwrap();
+        assert!(sdl.starts_with(FEDERATION_LINK));
+        assert!(sdl.contains("type User @shareable"));
+        assert!(sdl.contains("type Query @shareable"));
+        assert!(!sdl.contains("join__"));
+    }
+
+    #[tokio::test]
+    async fn it_answers_service_queries() {
+        let data = call(
+            "query Sdl { __typename schema: _service { sdl } }",
+            Some("Sdl"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(data["__typename"], json!("Query"));
+        assert_eq!(data["schema"]["sdl"], json!(subgraph_sdl(SCHEMA).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn it_passes_other_queries_through() {
+        let passed = Some(json!({ "passed": true }));
+        assert_eq!(call("{ me { name } }", None).await, passed);
+        assert_eq!(call("{ me { name } _service { sdl } }", None).await, passed);
+        assert_eq!(
+            call(
+                "query A { me { name } } query B { _service { sdl } }",
+                Some("A")
+            )
+            .await,
+            passed
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Summarize the latency of a request in a `Server-Timing` response header.
+//!
+//! Browser developer tools and frontend performance tooling read the
+//! [`Server-Timing`](https://www.w3.org/TR/server-timing/) header, so the time spent in each
+//! subgraph and the cache hits can be attributed without access to the traces.
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+use http::header::HeaderName;
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::query_planner::PlanSource;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+register_plugin!("apollo", "experimental_server_timing", ServerTiming);
+
+static SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+const PERSISTED_QUERY_HIT: &str = "persisted_query_hit";
+
+/// Summarize the subgraph fetch durations and the cache hits of each request in a `Server-Timing`
+/// response header
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Add the `Server-Timing` header to responses (default: false)
+    enabled: bool,
+    /// Number of decimals of the durations, in milliseconds (default: 1)
+    precision: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            precision: 1,
+        }
+    }
+}
+
+/// The fetches of a request to each subgraph, stored in the context's private entries
+#[derive(Default)]
+struct SubgraphTimings {
+    subgraphs: BTreeMap<String, SubgraphTiming>,
+}
+
+#[derive(Default)]
+struct SubgraphTiming {
+    fetches: usize,
+    duration: Duration,
+}
+
+struct ServerTiming {
+    enabled: bool,
+    precision: usize,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ServerTiming {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(ServerTiming {
+            enabled: init.config.enabled,
+            precision: init.config.precision,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if !self.enabled {
+            return service;
+        }
+
+        let precision = self.precision;
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |_: &supergraph::Request| Instant::now(),
+                move |start: Instant, fut| async move {
+                    let mut response: supergraph::Response = fut.await?;
+                    let header = server_timing(&response.context, start.elapsed(), precision);
+                    if let Ok(value) = HeaderValue::from_str(&header) {
+                        response
+                            .response
+                            .headers_mut()
+                            .append(SERVER_TIMING.clone(), value);
+                    }
+                    Ok(response)
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if !self.enabled {
+            return service;
+        }
+
+        let name = name.to_string();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |req: &subgraph::Request| (req.context.clone(), Instant::now()),
+                move |(context, start): (Context, Instant), fut| {
+                    let name = name.clone();
+                    async move {
+                        let result: Result<subgraph::Response, BoxError> = fut.await;
+                        let duration = start.elapsed();
+                        let mut entries = context.private_entries.lock();
+                        if !entries.contains_key::<SubgraphTimings>() {
+                            entries.insert(SubgraphTimings::default());
+                        }
+                        if let Some(timings) = entries.get_mut::<SubgraphTimings>() {
+                            let timing = timings.subgraphs.entry(name).or_default();
+                            timing.fetches += 1;
+                            timing.duration += duration;
+                        }
+                        result
+                    }
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+/// The `Server-Timing` header of a request: its cache hits, the total duration of the fetches to
+/// each subgraph, and its duration in the router
+fn server_timing(context: &Context, total: Duration, precision: usize) -> String {
+    let mut metrics = Vec::new();
+    if let Ok(Some(hit)) = context.get::<_, bool>(PERSISTED_QUERY_HIT) {
+        metrics.push(format!("apq;desc=\"{}\"", if hit { "hit" } else { "miss" }));
+    }
+    let entries = context.private_entries.lock();
+    if let Some(plan_source) = entries.get::<PlanSource>() {
+        let desc = match plan_source {
+            PlanSource::CacheHit => "hit",
+            PlanSource::Reused => "reused",
+            PlanSource::Planned => "miss",
+        };
+        metrics.push(format!("plan-cache;desc=\"{desc}\""));
+    }
+    if let Some(timings) = entries.get::<SubgraphTimings>() {
+        for (name, timing) in &timings.subgraphs {
+            let mut metric = format!("subgraph-{name};dur={}", millis(timing.duration, precision));
+            if timing.fetches > 1 {
+                let _ = write!(metric, ";desc=\"{} fetches\"", timing.fetches);
+            }
+            metrics.push(metric);
+        }
+    }
+    metrics.push(format!("total;dur={}", millis(total, precision)));
+    metrics.join(", ")
+}
+
+fn millis(duration: Duration, precision: usize) -> String {
+    format!("{:.precision$}", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+    use tower::Service;
+
+    use super::*;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::test::MockSupergraphService;
+
+    #[test]
+    fn it_formats_the_server_timing_header() {
+        let context = Context::new();
+        context.insert(PERSISTED_QUERY_HIT, true).unwrap();
+        let mut subgraphs = BTreeMap::new();
+        subgraphs.insert(
+            "reviews".to_string(),
+            SubgraphTiming {
+                fetches: 2,
+                duration: Duration::from_micros(31_250),
+            },
+        );
+        subgraphs.insert(
+            "products".to_string(),
+            SubgraphTiming {
+                fetches: 1,
+                duration: Duration::from_micros(12_345),
+            },
+        );
+        {
+            let mut entries = context.private_entries.lock();
+            entries.insert(PlanSource::CacheHit);
+            entries.insert(SubgraphTimings { subgraphs });
+        }
+
+        assert_eq!(
+            server_timing(&context, Duration::from_millis(50), 1),
+            "apq;desc=\"hit\", plan-cache;desc=\"hit\", subgraph-products;dur=12.3, subgraph-reviews;dur=31.2;desc=\"2 fetches\", total;dur=50.0"
+        );
+        assert_eq!(
+            server_timing(&Context::new(), Duration::from_micros(1_500), 0),
+            "total;dur=2"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_adds_the_server_timing_header() {
+        let plugin = ServerTiming::new(PluginInit::fake_new(
+            serde_json::from_value(serde_json::json!({ "enabled": true })).unwrap(),
+            Default::default(),
+        ))
+        .await
+        .unwrap();
+
+        let mut mock_subgraph = MockSubgraphService::new();
+        mock_subgraph.expect_call().times(2).returning(|req| {
+            Ok(subgraph::Response::fake_builder()
+                .context(req.context)
+                .build())
+        });
+        let mut subgraph_service = plugin.subgraph_service("products", mock_subgraph.boxed());
+
+        let mut mock_supergraph = MockSupergraphService::new();
+        mock_supergraph.expect_call().times(1).returning(|req| {
+            supergraph::Response::fake_builder()
+                .data(json!({ "ok": true }))
+                .context(req.context)
+                .build()
+        });
+        let mut supergraph_service = plugin.supergraph_service(mock_supergraph.boxed());
+
+        let request = supergraph::Request::fake_builder().build().unwrap();
+        for _ in 0..2 {
+            subgraph_service
+                .ready()
+                .await
+                .unwrap()
+                .call(
+                    subgraph::Request::fake_builder()
+                        .context(request.context.clone())
+                        .build(),
+                )
+                .await
+                .unwrap();
+        }
+        let response = supergraph_service
+            .ready()
+            .await
+            .unwrap()
+            .call(request)
+            .await
+            .unwrap();
+        let header = response.response.headers()[&SERVER_TIMING]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(header.starts_with("subgraph-products;dur="));
+        assert!(header.contains(";desc=\"2 fetches\", total;dur="));
+    }
+}
@@ -0,0 +1,604 @@
+//! Shadow evaluation of plugins
+//!
+//! A shadowed plugin runs on a copy of a sample of the supergraph, execution and subgraph
+//! requests, in the background. Its pipeline ends on a stub service instead of the rest of the
+//! router: the stub waits for the response the router actually returned and passes a copy of it
+//! back to the plugin (the first response for deferred queries and subscriptions). The changes
+//! the plugin makes to the requests, responses and context are discarded, and the requests are
+//! processed as if the plugin was not there. The router stage is not evaluated, since copying
+//! the requests would require buffering their body.
+//!
+//! The decision of the plugin is recorded for each request: `continue` if the request went
+//! through the whole pipeline, `reject` if the plugin returned a response without calling the
+//! next service, and `error` if the pipeline failed. The recorded duration excludes the time
+//! spent waiting for the response of the router.
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future;
+use futures::future::BoxFuture;
+use futures::stream;
+use futures::FutureExt;
+use futures::StreamExt;
+use http::HeaderMap;
+use http::StatusCode;
+use multimap::MultiMap;
+use rand::thread_rng;
+use rand::Rng;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tokio::sync::Semaphore;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::DynPlugin;
+use crate::plugin::LifecycleEvent;
+use crate::services::execution;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::services::router;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+/// Plugins the router depends on, which cannot be shadowed
+pub(crate) const NOT_SHADOWABLE: [&str; 3] = ["telemetry", "traffic_shaping", "subscription"];
+
+/// Plugins evaluated in shadow mode
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ShadowPlugins {
+    /// Names of the plugins evaluated in shadow mode
+    pub(crate) plugins: Vec<String>,
+
+    /// Ratio of the requests evaluated by each shadow plugin, between 0 and 1 (default: 1)
+    pub(crate) sample_rate: f64,
+
+    /// Maximum number of requests evaluated at the same time by each shadow plugin. The requests
+    /// sampled above this limit are not evaluated (default: 64)
+    pub(crate) max_concurrency: usize,
+}
+
+impl Default for ShadowPlugins {
+    fn default() -> Self {
+        Self {
+            plugins: Vec::new(),
+            sample_rate: 1.0,
+            max_concurrency: 64,
+        }
+    }
+}
+
+/// Marks the shadow requests that went through the whole pipeline of the plugin, with the time
+/// spent waiting for the response of the router
+struct Continued {
+    waited: Duration,
+}
+
+/// Copy of the response the router returned for a request
+struct RealResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: graphql::Response,
+}
+
+impl RealResponse {
+    fn into_http_response(self) -> http::Response<graphql::Response> {
+        let mut response = http::Response::new(self.body);
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers;
+        response
+    }
+}
+
+/// Receives the response of the router in the stub service of a shadow pipeline
+struct RealResponseReceiver(oneshot::Receiver<RealResponse>);
+
+/// A plugin evaluated without affecting the requests and the responses
+pub(crate) struct ShadowPlugin {
+    name: Arc<str>,
+    plugin: Box<dyn DynPlugin>,
+    sample_rate: f64,
+    permits: Arc<Semaphore>,
+}
+
+impl ShadowPlugin {
+    pub(crate) fn new(name: &str, plugin: Box<dyn DynPlugin>, config: &ShadowPlugins) -> Self {
+        Self {
+            name: name.into(),
+            plugin,
+            sample_rate: config.sample_rate,
+            permits: Arc::new(Semaphore::new(config.max_concurrency)),
+        }
+    }
+
+    /// Runs a copy of a sample of the requests through the pipeline of the plugin in the
+    /// background, then passes the original request to the next service and the copy of its
+    /// response to the pipeline
+    fn shadowed<Request, Response>(
+        &self,
+        stage: &'static str,
+        pipeline: tower::util::BoxService<Request, Response, BoxError>,
+        service: tower::util::BoxService<Request, Response, BoxError>,
+        copy: fn(&Request) -> Request,
+        context: fn(&Request) -> &Context,
+    ) -> tower::util::BoxService<Request, Response, BoxError>
+    where
+        Request: Send + 'static,
+        Response: ShadowResponse + Send + 'static,
+    {
+        let pipeline = ServiceBuilder::new().buffered().service(pipeline);
+        let plugin = self.name.clone();
+        let sample_rate = self.sample_rate;
+        let permits = self.permits.clone();
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                move |request: &Request| {
+                    if !thread_rng().gen_bool(sample_rate) {
+                        return None;
+                    }
+                    let Ok(permit) = permits.clone().try_acquire_owned() else {
+                        tracing::debug!(
+                            "shadow plugin {plugin} is at its concurrency limit, skipping a {stage} request"
+                        );
+                        return None;
+                    };
+                    let (sender, receiver) = oneshot::channel();
+                    let shadow_request = copy(request);
+                    let shadow_context = context(&shadow_request).clone();
+                    shadow_context
+                        .private_entries
+                        .lock()
+                        .insert(RealResponseReceiver(receiver));
+                    // The future is boxed outside of the task, so that it is known to be `Send`
+                    let shadow: BoxFuture<'static, Result<Response, BoxError>> =
+                        pipeline.clone().oneshot(shadow_request).boxed();
+                    let plugin = plugin.clone();
+                    tokio::spawn(async move {
+                        let start = Instant::now();
+                        let failed = match shadow.await {
+                            Ok(response) => {
+                                response.drain().await;
+                                false
+                            }
+                            Err(_) => true,
+                        };
+                        drop(permit);
+                        let waited = shadow_context
+                            .private_entries
+                            .lock()
+                            .get::<Continued>()
+                            .map(|continued| continued.waited);
+                        let decision = match (waited, failed) {
+                            (Some(_), _) => "continue",
+                            (None, true) => "error",
+                            (None, false) => "reject",
+                        };
+                        f64_histogram!(
+                            "apollo.router.plugin.shadow.duration",
+                            "Duration of the pipeline of the plugins evaluated in shadow mode",
+                            start
+                                .elapsed()
+                                .saturating_sub(waited.unwrap_or_default())
+                                .as_secs_f64(),
+                            "plugin.name" = plugin.to_string(),
+                            "plugin.stage" = stage
+                        );
+                        u64_counter!(
+                            "apollo.router.plugin.shadow.decisions",
+                            "Decisions of the plugins evaluated in shadow mode",
+                            1,
+                            "plugin.name" = plugin.to_string(),
+                            "plugin.stage" = stage,
+                            "decision" = decision
+                        );
+                        tracing::debug!(
+                            "shadow plugin {plugin} made the decision '{decision}' for a {stage} request"
+                        );
+                    });
+                    Some(sender)
+                },
+                move |sender: Option<oneshot::Sender<RealResponse>>, fut| async move {
+                    let result: Result<Response, BoxError> = fut.await;
+                    let response = result?;
+                    Ok::<_, BoxError>(match sender {
+                        Some(sender) => response.tee(sender),
+                        None => response,
+                    })
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+/// Copies the public entries and the parsed document of a context, so that the changes made by
+/// the plugin are not visible to the router
+fn copy_context(context: &Context) -> Context {
+    let copy = Context::new();
+    copy.extend(context);
+    let document = context
+        .private_entries
+        .lock()
+        .get::<ParsedDocument>()
+        .cloned();
+    if let Some(document) = document {
+        copy.private_entries.lock().insert(document);
+    }
+    copy
+}
+
+fn copy_http_request(request: &http::Request<graphql::Request>) -> http::Request<graphql::Request> {
+    // http::Request is not clonable so we have to rebuild a new one
+    let mut builder = http::Request::builder()
+        .method(request.method())
+        .version(request.version())
+        .uri(request.uri());
+    if let Some(headers) = builder.headers_mut() {
+        headers.extend(
+            request
+                .headers()
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+    }
+    builder
+        .body(request.body().clone())
+        .expect("the request was already valid; qed")
+}
+
+/// Responses of the stages evaluated in shadow mode
+trait ShadowResponse: Sized {
+    /// Sends a copy of the response to the shadow pipeline, when the client receives it
+    fn tee(self, sender: oneshot::Sender<RealResponse>) -> Self;
+
+    /// Consumes the response of the shadow pipeline, so that the plugin processes all of it
+    fn drain(self) -> BoxFuture<'static, ()>;
+}
+
+impl ShadowResponse for supergraph::Response {
+    // only the first response of the stream is copied
+    fn tee(self, sender: oneshot::Sender<RealResponse>) -> Self {
+        let (parts, body) = self.response.into_parts();
+        let mut real = Some((sender, parts.status, parts.headers.clone()));
+        let body = body
+            .map(move |response| {
+                if let Some((sender, status, headers)) = real.take() {
+                    let _ = sender.send(RealResponse {
+                        status,
+                        headers,
+                        body: response.clone(),
+                    });
+                }
+                response
+            })
+            .boxed();
+        supergraph::Response {
+            response: http::Response::from_parts(parts, body),
+            context: self.context,
+        }
+    }
+
+    fn drain(self) -> BoxFuture<'static, ()> {
+        self.response
+            .into_body()
+            .for_each(|_| future::ready(()))
+            .boxed()
+    }
+}
+
+impl ShadowResponse for subgraph::Response {
+    fn tee(self, sender: oneshot::Sender<RealResponse>) -> Self {
+        let _ = sender.send(RealResponse {
+            status: self.response.status(),
+            headers: self.response.headers().clone(),
+            body: self.response.body().clone(),
+        });
+        self
+    }
+
+    fn drain(self) -> BoxFuture<'static, ()> {
+        future::ready(()).boxed()
+    }
+}
+
+/// Waits for the response of the router to the original request, in the stub service of a
+/// shadow pipeline
+async fn real_response(context: &Context) -> Result<RealResponse, BoxError> {
+    let receiver = context
+        .private_entries
+        .lock()
+        .remove::<RealResponseReceiver>()
+        .ok_or("the shadow request has no original request")?;
+    let start = Instant::now();
+    let response = receiver.0.await;
+    context.private_entries.lock().insert(Continued {
+        waited: start.elapsed(),
+    });
+    response.map_err(|_| "the router did not return a response to the original request".into())
+}
+
+async fn continued_response(context: Context) -> Result<supergraph::Response, BoxError> {
+    let real = real_response(&context).await?;
+    Ok(supergraph::Response {
+        response: real
+            .into_http_response()
+            .map(|body| stream::once(future::ready(body)).boxed()),
+        context,
+    })
+}
+
+impl DynPlugin for ShadowPlugin {
+    fn router_service(&self, service: router::BoxService) -> router::BoxService {
+        service
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let pipeline = self.plugin.supergraph_service(
+            tower::service_fn(|request: supergraph::Request| continued_response(request.context))
+                .boxed(),
+        );
+        self.shadowed(
+            "supergraph",
+            pipeline,
+            service,
+            |request| supergraph::Request {
+                supergraph_request: copy_http_request(&request.supergraph_request),
+                context: copy_context(&request.context),
+            },
+            |request| &request.context,
+        )
+    }
+
+    fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let pipeline = self.plugin.execution_service(
+            tower::service_fn(|request: execution::Request| continued_response(request.context))
+                .boxed(),
+        );
+        self.shadowed(
+            "execution",
+            pipeline,
+            service,
+            |request| execution::Request {
+                supergraph_request: copy_http_request(&request.supergraph_request),
+                query_plan: request.query_plan.clone(),
+                context: copy_context(&request.context),
+                source_stream_value: request.source_stream_value.clone(),
+                subscription_tx: None,
+            },
+            |request| &request.context,
+        )
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let pipeline = self.plugin.subgraph_service(
+            name,
+            tower::service_fn(|request: subgraph::Request| async move {
+                let real = real_response(&request.context).await?;
+                Ok::<_, BoxError>(subgraph::Response::new_from_response(
+                    real.into_http_response(),
+                    request.context,
+                ))
+            })
+            .boxed(),
+        );
+        self.shadowed(
+            "subgraph",
+            pipeline,
+            service,
+            |request| {
+                let mut copy = request.clone();
+                copy.context = copy_context(&request.context);
+                copy.subscription_stream = None;
+                copy.connection_closed_signal = None;
+                copy
+            },
+            |request| &request.context,
+        )
+    }
+
+    fn name(&self) -> &'static str {
+        self.plugin.name()
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        self.plugin.web_endpoints()
+    }
+
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        self.plugin.lifecycle_event(event)
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::plugin::Plugin;
+    use crate::plugin::PluginInit;
+
+    /// Rejects every request, after setting a context entry
+    struct Rejecting {
+        calls: mpsc::UnboundedSender<()>,
+    }
+
+    #[async_trait::async_trait]
+    impl Plugin for Rejecting {
+        type Config = ();
+
+        async fn new(_init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+            unreachable!()
+        }
+
+        fn supergraph_service(&self, _service: supergraph::BoxService) -> supergraph::BoxService {
+            let calls = self.calls.clone();
+            tower::service_fn(move |request: supergraph::Request| {
+                let _ = calls.send(());
+                async move {
+                    request.context.insert("rejected", true).unwrap();
+                    supergraph::Response::error_builder()
+                        .error(
+                            graphql::Error::builder()
+                                .message("rejected")
+                                .extension_code("REJECTED")
+                                .build(),
+                        )
+                        .context(request.context)
+                        .build()
+                }
+            })
+            .boxed()
+        }
+    }
+
+    /// Sends the data of the responses it receives from the router
+    struct Observing {
+        responses: mpsc::UnboundedSender<Option<serde_json_bytes::Value>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Plugin for Observing {
+        type Config = ();
+
+        async fn new(_init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+            unreachable!()
+        }
+
+        fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+            let responses = self.responses.clone();
+            service
+                .map_response(move |mut response: supergraph::Response| {
+                    let responses = responses.clone();
+                    let body = std::mem::replace(
+                        response.response.body_mut(),
+                        futures::stream::empty().boxed(),
+                    );
+                    *response.response.body_mut() = body
+                        .map(move |response| {
+                            let _ = responses.send(response.data.clone());
+                            response
+                        })
+                        .boxed();
+                    response
+                })
+                .boxed()
+        }
+    }
+
+    fn mock_service() -> MockSupergraphService {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(|request| {
+            supergraph::Response::fake_builder()
+                .data(serde_json_bytes::json!({ "me": { "name": "Ada" } }))
+                .context(request.context)
+                .build()
+        });
+        mock_service
+    }
+
+    #[tokio::test]
+    async fn shadow_plugins_receive_the_real_responses() {
+        let (responses, mut received) = mpsc::unbounded_channel();
+        let plugin = ShadowPlugin::new(
+            "observing",
+            Box::new(Observing { responses }),
+            &ShadowPlugins::default(),
+        );
+        let service = plugin.supergraph_service(mock_service().boxed());
+        let mut response = service
+            .oneshot(supergraph::Request::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        response.next_response().await.unwrap();
+
+        assert_eq!(
+            received.recv().await,
+            Some(Some(serde_json_bytes::json!({ "me": { "name": "Ada" } })))
+        );
+    }
+
+    #[tokio::test]
+    async fn shadow_plugins_only_evaluate_a_sample_of_the_requests() {
+        let (calls, mut received) = mpsc::unbounded_channel();
+        let plugin = ShadowPlugin::new(
+            "rejecting",
+            Box::new(Rejecting { calls }),
+            &ShadowPlugins {
+                sample_rate: 0.0,
+                ..Default::default()
+            },
+        );
+        let service = plugin.supergraph_service(mock_service().boxed());
+        service
+            .oneshot(supergraph::Request::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        assert!(received.try_recv().is_err());
+
+        let (calls, mut received) = mpsc::unbounded_channel();
+        let plugin = ShadowPlugin::new(
+            "rejecting",
+            Box::new(Rejecting { calls }),
+            &ShadowPlugins {
+                max_concurrency: 0,
+                ..Default::default()
+            },
+        );
+        let service = plugin.supergraph_service(mock_service().boxed());
+        service
+            .oneshot(supergraph::Request::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn shadow_plugins_do_not_change_responses() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(|request| {
+            assert!(!request.context.contains_key("rejected"));
+            supergraph::Response::fake_builder()
+                .data(serde_json_bytes::json!({ "me": { "name": "Ada" } }))
+                .context(request.context)
+                .build()
+        });
+
+        let (calls, mut received) = mpsc::unbounded_channel();
+        let plugin = ShadowPlugin::new(
+            "rejecting",
+            Box::new(Rejecting { calls }),
+            &ShadowPlugins::default(),
+        );
+        let service = plugin.supergraph_service(mock_service.boxed());
+        let mut response = service
+            .oneshot(supergraph::Request::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        let response = response.next_response().await.unwrap();
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data,
+            Some(serde_json_bytes::json!({ "me": { "name": "Ada" } }))
+        );
+
+        // the plugin still ran, in the background
+        assert!(received.recv().await.is_some());
+    }
+}
@@ -0,0 +1,386 @@
+//! Map subgraph errors onto the error codes, messages and HTTP status presented to clients.
+//!
+//! Subgraphs often follow different conventions to report the same kind of failure. Rules
+//! configured per subgraph match errors by the subgraph's HTTP status code and/or the
+//! `extensions.code` of the GraphQL error, and rewrite them in a uniform way.
+use std::sync::Arc;
+
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::configuration::subgraph::SubgraphConfiguration;
+use crate::graphql;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "subgraph_error_mapping", SubgraphErrorMapping);
+
+/// Error mapping rules for a subgraph
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ErrorMappingConfig {
+    /// Rules are evaluated in order, the first rule matching an error is applied
+    rules: Vec<ErrorMappingRule>,
+}
+
+/// Rewrites the subgraph errors matching all of the configured conditions
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ErrorMappingRule {
+    /// Match the HTTP status code of the subgraph response
+    #[serde(default)]
+    subgraph_status: Option<u16>,
+    /// Match the `extensions.code` of the subgraph error
+    #[serde(default)]
+    subgraph_code: Option<String>,
+    /// Replace the `extensions.code` of the error
+    #[serde(default)]
+    code: Option<String>,
+    /// Replace the message of the error
+    #[serde(default)]
+    message: Option<String>,
+    /// HTTP status code of the response sent to the client
+    #[serde(default)]
+    status: Option<u16>,
+}
+
+/// The client HTTP status requested by error mapping rules during a request
+#[derive(Clone, Copy, Debug)]
+struct MappedStatus(StatusCode);
+
+struct Rule {
+    subgraph_status: Option<StatusCode>,
+    subgraph_code: Option<String>,
+    code: Option<String>,
+    message: Option<String>,
+    status: Option<StatusCode>,
+}
+
+impl Rule {
+    fn new(subgraph_name: &str, rule: &ErrorMappingRule) -> Result<Self, BoxError> {
+        if rule.subgraph_status.is_none() && rule.subgraph_code.is_none() {
+            return Err(format!(
+                "error mapping rule for subgraph '{subgraph_name}' must set subgraph_status or subgraph_code"
+            )
+            .into());
+        }
+        let status_code = |status: Option<u16>| {
+            status.map(StatusCode::from_u16).transpose().map_err(|_| {
+                format!(
+                    "invalid HTTP status code in error mapping rule for subgraph '{subgraph_name}'"
+                )
+            })
+        };
+        Ok(Self {
+            subgraph_status: status_code(rule.subgraph_status)?,
+            subgraph_code: rule.subgraph_code.clone(),
+            code: rule.code.clone(),
+            message: rule.message.clone(),
+            status: status_code(rule.status)?,
+        })
+    }
+
+    fn matches(&self, status: StatusCode, error: &graphql::Error) -> bool {
+        self.subgraph_status
+            .map_or(true, |subgraph_status| subgraph_status == status)
+            && self.subgraph_code.as_deref().map_or(true, |subgraph_code| {
+                error.extensions.get("code").and_then(Value::as_str) == Some(subgraph_code)
+            })
+    }
+
+    fn apply(&self, error: &mut graphql::Error) {
+        if let Some(code) = &self.code {
+            error
+                .extensions
+                .insert("code", Value::String(code.clone().into()));
+        }
+        if let Some(message) = &self.message {
+            error.message = message.clone();
+        }
+    }
+}
+
+struct SubgraphErrorMapping {
+    config: SubgraphConfiguration<ErrorMappingConfig>,
+}
+
+impl SubgraphErrorMapping {
+    fn rules(&self, subgraph_name: &str) -> Result<Vec<Rule>, BoxError> {
+        self.config
+            .get(subgraph_name)
+            .rules
+            .iter()
+            .map(|rule| Rule::new(subgraph_name, rule))
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for SubgraphErrorMapping {
+    type Config = SubgraphConfiguration<ErrorMappingConfig>;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let plugin = SubgraphErrorMapping {
+            config: init.config,
+        };
+        plugin.rules("all")?;
+        for name in plugin.config.subgraphs.keys() {
+            plugin.rules(name)?;
+        }
+        Ok(plugin)
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if self.config.all.rules.is_empty()
+            && self
+                .config
+                .subgraphs
+                .values()
+                .all(|config| config.rules.is_empty())
+        {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .map_response(|mut response: supergraph::Response| {
+                let mapped = response
+                    .context
+                    .private_entries
+                    .lock()
+                    .get::<MappedStatus>()
+                    .copied();
+                if let Some(MappedStatus(status)) = mapped {
+                    *response.response.status_mut() = status;
+                }
+                response
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let rules = match self.rules(name) {
+            Ok(rules) if !rules.is_empty() => Arc::new(rules),
+            // Rules were validated when the plugin was created
+            _ => return service,
+        };
+
+        service
+            .map_response(move |mut response: subgraph::Response| {
+                let subgraph_status = response.response.status();
+                let mut client_status = None;
+                for error in response.response.body_mut().errors.iter_mut() {
+                    if let Some(rule) = rules
+                        .iter()
+                        .find(|rule| rule.matches(subgraph_status, error))
+                    {
+                        rule.apply(error);
+                        client_status = client_status.max(rule.status);
+                    }
+                }
+
+                if let Some(status) = client_status {
+                    let mut entries = response.context.private_entries.lock();
+                    // When several subgraphs request a status, the highest one is used
+                    let status = match entries.get::<MappedStatus>() {
+                        Some(MappedStatus(previous)) if *previous > status => *previous,
+                        _ => status,
+                    };
+                    entries.insert(MappedStatus(status));
+                }
+                response
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::services::SubgraphRequest;
+    use crate::services::SubgraphResponse;
+    use crate::services::SupergraphRequest;
+    use crate::services::SupergraphResponse;
+    use crate::Context;
+
+    async fn plugin(config: serde_json::Value) -> Result<SubgraphErrorMapping, BoxError> {
+        SubgraphErrorMapping::new(PluginInit::fake_new(
+            serde_json::from_value(config).unwrap(),
+            Default::default(),
+        ))
+        .await
+    }
+
+    fn config() -> serde_json::Value {
+        serde_json::json!({
+            "all": {
+                "rules": [
+                    { "subgraph_code": "NOT_AUTHENTICATED", "code": "UNAUTHENTICATED", "status": 401 }
+                ]
+            },
+            "subgraphs": {
+                "products": {
+                    "rules": [
+                        { "subgraph_status": 503, "code": "SERVICE_UNAVAILABLE", "message": "products are unavailable", "status": 503 },
+                        { "subgraph_code": "AUTH", "code": "UNAUTHENTICATED", "status": 401 }
+                    ]
+                }
+            }
+        })
+    }
+
+    fn subgraph_response(
+        status: StatusCode,
+        code: &str,
+        context: Context,
+    ) -> Result<SubgraphResponse, BoxError> {
+        Ok(SubgraphResponse::fake_builder()
+            .status_code(status)
+            .error(
+                graphql::Error::builder()
+                    .message("subgraph error")
+                    .extension_code(code)
+                    .build(),
+            )
+            .context(context)
+            .build())
+    }
+
+    #[tokio::test]
+    async fn it_maps_subgraph_errors() {
+        let plugin = plugin(config()).await.unwrap();
+
+        let mut mock_service = MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(|req: SubgraphRequest| {
+                subgraph_response(StatusCode::SERVICE_UNAVAILABLE, "DOWN", req.context)
+            });
+        let context = Context::new();
+        let response = plugin
+            .subgraph_service("products", mock_service.boxed())
+            .oneshot(
+                SubgraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let error = &response.response.body().errors[0];
+        assert_eq!(error.message, "products are unavailable");
+        assert_eq!(
+            error.extensions.get("code").and_then(Value::as_str),
+            Some("SERVICE_UNAVAILABLE")
+        );
+
+        // Subgraphs without specific rules use the rules from `all`
+        let mut mock_service = MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(|req: SubgraphRequest| {
+                subgraph_response(StatusCode::OK, "NOT_AUTHENTICATED", req.context)
+            });
+        let response = plugin
+            .subgraph_service("reviews", mock_service.boxed())
+            .oneshot(
+                SubgraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let error = &response.response.body().errors[0];
+        assert_eq!(error.message, "subgraph error");
+        assert_eq!(
+            error.extensions.get("code").and_then(Value::as_str),
+            Some("UNAUTHENTICATED")
+        );
+
+        // The highest status requested by the rules is sent to the client
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(|req: SupergraphRequest| {
+                SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .build()
+            });
+        let response = plugin
+            .supergraph_service(mock_service.boxed())
+            .oneshot(
+                SupergraphRequest::fake_builder()
+                    .context(context)
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn it_leaves_unmatched_errors_unchanged() {
+        let plugin = plugin(config()).await.unwrap();
+
+        let mut mock_service = MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(|req: SubgraphRequest| {
+                subgraph_response(StatusCode::OK, "NOT_AUTHENTICATED", req.context)
+            });
+        let context = Context::new();
+        let response = plugin
+            .subgraph_service("products", mock_service.boxed())
+            .oneshot(
+                SubgraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let error = &response.response.body().errors[0];
+        assert_eq!(error.message, "subgraph error");
+        assert_eq!(
+            error.extensions.get("code").and_then(Value::as_str),
+            Some("NOT_AUTHENTICATED")
+        );
+        assert!(context
+            .private_entries
+            .lock()
+            .get::<MappedStatus>()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn it_rejects_rules_without_conditions() {
+        assert!(plugin(serde_json::json!({
+            "subgraphs": {
+                "products": { "rules": [ { "code": "UNAUTHENTICATED" } ] }
+            }
+        }))
+        .await
+        .is_err());
+        assert!(plugin(serde_json::json!({
+            "all": { "rules": [ { "subgraph_code": "AUTH", "status": 42 } ] }
+        }))
+        .await
+        .is_err());
+    }
+}
@@ -6,6 +6,9 @@ use std::time::Duration;
 
 use bytes::Buf;
 use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::Stream;
+use futures::StreamExt;
 use hmac::Hmac;
 use hmac::Mac;
 use http::HeaderName;
@@ -14,6 +17,9 @@ use http::Method;
 use http::StatusCode;
 use multimap::MultiMap;
 use once_cell::sync::OnceCell;
+use opentelemetry::global::get_text_map_propagator;
+use opentelemetry::trace::TraceContextExt;
+use regex::Regex;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
@@ -24,6 +30,7 @@ use tower::Service;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 use tracing_futures::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use uuid::Uuid;
 
 use crate::context::Context;
@@ -32,8 +39,10 @@ use crate::graphql::Response;
 use crate::json_ext::Object;
 use crate::layers::ServiceBuilderExt;
 use crate::notification::Notify;
+use crate::plugin::serde::deserialize_regex;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
 use crate::protocols::websocket::WebSocketProtocol;
 use crate::query_planner::OperationKind;
 use crate::register_plugin;
@@ -44,7 +53,6 @@ use crate::ListenAddr;
 
 type HmacSha256 = Hmac<sha2::Sha256>;
 pub(crate) const APOLLO_SUBSCRIPTION_PLUGIN: &str = "apollo.subscription";
-#[cfg(not(test))]
 pub(crate) const APOLLO_SUBSCRIPTION_PLUGIN_NAME: &str = "subscription";
 pub(crate) static SUBSCRIPTION_CALLBACK_HMAC_KEY: OnceCell<String> = OnceCell::new();
 pub(crate) const SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS: &str =
@@ -74,6 +82,9 @@ pub(crate) struct SubscriptionConfig {
     pub(crate) max_opened_subscriptions: Option<usize>,
     /// It represent the capacity of the in memory queue to know how many events we can keep in a buffer
     pub(crate) queue_capacity: Option<usize>,
+    /// Batching of the events of subscriptions, to protect clients from event storms. The first
+    /// rule matching the root field of a subscription applies to it
+    pub(crate) experimental_event_batching: Vec<EventBatching>,
 }
 
 impl Default for SubscriptionConfig {
@@ -84,10 +95,94 @@ impl Default for SubscriptionConfig {
             enable_deduplication: true,
             max_opened_subscriptions: None,
             queue_capacity: None,
+            experimental_event_batching: Vec::new(),
         }
     }
 }
 
+/// Batching of the events of the subscriptions on matching root fields
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EventBatching {
+    /// A regex matching the root fields of the subscriptions to batch, such as `^onPriceChange$`
+    #[serde(
+        deserialize_with = "deserialize_regex",
+        serialize_with = "serialize_regex"
+    )]
+    #[schemars(with = "String")]
+    pub(crate) matching: Regex,
+    /// How long to collect events once one was received, such as `100ms`
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    pub(crate) interval: Duration,
+    /// Deliver the collected events as soon as there are this many, before the end of the interval
+    #[serde(default)]
+    pub(crate) max_events: Option<usize>,
+    /// Deliver all the collected events, or only the latest one
+    #[serde(default)]
+    pub(crate) deliver: EventBatchingDelivery,
+}
+
+fn serialize_regex<S>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.serialize_str(regex.as_str())
+}
+
+impl EventBatching {
+    /// Collects the events of a subscription for the interval, or until there are `max_events` of
+    /// them, then delivers them as a batch, or only the latest one. The events with errors are
+    /// always delivered
+    pub(crate) fn batch<S>(&self, events: S) -> BoxStream<'static, Vec<Response>>
+    where
+        S: Stream<Item = Response> + Send + Unpin + 'static,
+    {
+        let batching = self.clone();
+        futures::stream::unfold((events, false), move |(mut events, ended)| {
+            let batching = batching.clone();
+            async move {
+                if ended {
+                    return None;
+                }
+                let mut batch = vec![events.next().await?];
+                let mut ended = false;
+                let deadline = tokio::time::sleep(batching.interval);
+                tokio::pin!(deadline);
+                while batching.max_events.map_or(true, |max| batch.len() < max) {
+                    tokio::select! {
+                        _ = &mut deadline => break,
+                        event = events.next() => match event {
+                            Some(event) => batch.push(event),
+                            None => {
+                                ended = true;
+                                break;
+                            }
+                        },
+                    }
+                }
+                if batching.deliver == EventBatchingDelivery::Latest {
+                    let latest = batch.pop();
+                    batch.retain(|event| !event.errors.is_empty());
+                    batch.extend(latest);
+                }
+                Some((batch, (events, ended)))
+            }
+        })
+        .boxed()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EventBatchingDelivery {
+    /// Deliver all the collected events, together
+    #[default]
+    All,
+    /// Deliver only the latest collected event, dropping the others unless they have errors
+    Latest,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default, JsonSchema)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct SubscriptionModeConfig {
@@ -205,6 +300,68 @@ pub(crate) struct WebSocketConfiguration {
     pub(crate) path: Option<String>,
     /// Which WebSocket GraphQL protocol to use for this subgraph possible values are: 'graphql_ws' | 'graphql_transport_ws' (default: graphql_ws)
     pub(crate) protocol: WebSocketProtocol,
+    /// URLs of the subgraph replicas that WebSocket connections are distributed across, instead of the routing URL of the subgraph
+    #[schemars(with = "Vec<String>")]
+    pub(crate) urls: Vec<url::Url>,
+    /// Connect the subscriptions of a client to the same replica, by hashing a request header or a JWT claim (default: the subscription is hashed)
+    pub(crate) affinity: Option<Affinity>,
+}
+
+/// The value identifying a client for replica affinity
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum Affinity {
+    /// The value of a header of the client request
+    Header(String),
+    /// A claim of the authenticated JWT
+    Claim(String),
+}
+
+impl WebSocketConfiguration {
+    /// The replica to connect to, if replicas are configured.
+    ///
+    /// Replicas are chosen with rendezvous hashing, so that every router instance picks the same
+    /// replica for a client and only the clients of a removed replica move to another one.
+    pub(crate) fn replica(
+        &self,
+        request: &subgraph::Request,
+        subscription_hash: &str,
+    ) -> Option<&url::Url> {
+        let key = self
+            .affinity
+            .as_ref()
+            .and_then(|affinity| affinity.key(request))
+            .unwrap_or_else(|| subscription_hash.to_string());
+        self.urls.iter().max_by_key(|url| {
+            let digest = Sha256::new()
+                .chain_update(key.as_bytes())
+                .chain_update(url.as_str().as_bytes())
+                .finalize();
+            u64::from_be_bytes(digest[..8].try_into().expect("the digest has 32 bytes"))
+        })
+    }
+}
+
+impl Affinity {
+    fn key(&self, request: &subgraph::Request) -> Option<String> {
+        match self {
+            Affinity::Header(name) => request
+                .supergraph_request
+                .headers()
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            Affinity::Claim(name) => {
+                let claims = request
+                    .context
+                    .get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS)?;
+                match claims.as_object()?.get(name.as_str())? {
+                    serde_json_bytes::Value::String(value) => Some(value.as_str().to_string()),
+                    value => Some(value.to_string()),
+                }
+            }
+        }
+    }
 }
 
 fn default_path() -> String {
@@ -405,6 +562,17 @@ impl Service<router::Request> for CallbackService {
         let mut notify = self.notify.clone();
         let path = self.path.clone();
         let callback_hmac_key = self.callback_hmac_key.clone();
+        // Subgraphs propagating the trace context of the subscription request in their callbacks
+        // connect them to its trace
+        let span = tracing::info_span!("subscription_callback");
+        let parent = get_text_map_propagator(|propagator| {
+            propagator.extract(&opentelemetry_http::HeaderExtractor(
+                req.router_request.headers(),
+            ))
+        });
+        if parent.span().span_context().is_valid() {
+            span.set_parent(parent);
+        }
         Box::pin(
             async move {
                 let (parts, body) = req.router_request.into_parts();
@@ -622,7 +790,7 @@ impl Service<router::Request> for CallbackService {
                     }),
                 }
             }
-            .instrument(tracing::info_span!("subscription_callback")),
+            .instrument(span),
         )
     }
 }
@@ -814,6 +982,63 @@ mod tests {
         assert_eq!(resp.status(), http::StatusCode::NOT_FOUND);
     }
 
+    #[tokio::test]
+    async fn it_parents_callbacks_with_the_propagated_trace_context() {
+        use opentelemetry::trace::SpanId;
+        use opentelemetry::trace::TraceId;
+        use opentelemetry::trace::TracerProvider as _;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        opentelemetry::global::set_text_map_propagator(
+            opentelemetry::sdk::propagation::TraceContextPropagator::new(),
+        );
+        let (exporter, spans, _) = opentelemetry::sdk::testing::trace::new_test_exporter();
+        let tracer_provider = opentelemetry::sdk::trace::TracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let sub_id = uuid::Uuid::new_v4().to_string();
+        let callback_service = CallbackService::new(
+            Notify::builder().build(),
+            "/subscription/callback".to_string(),
+            "key".to_string(),
+        );
+        let request = router::Request {
+            router_request: http::Request::post(format!(
+                "http://localhost:4000/subscription/callback/{sub_id}"
+            ))
+            .header(
+                "traceparent",
+                "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            )
+            .body(hyper::Body::from(
+                serde_json::to_vec(&CallbackPayload::Subscription(SubscriptionPayload::Check {
+                    id: sub_id.clone(),
+                    verifier: create_verifier(&sub_id).unwrap(),
+                }))
+                .unwrap(),
+            ))
+            .unwrap(),
+            context: Context::new(),
+        };
+        let _ = callback_service.oneshot(request).await;
+
+        let span = std::iter::from_fn(|| spans.recv_timeout(Duration::from_secs(5)).ok())
+            .find(|span| span.name == "subscription_callback")
+            .expect("the callback span is exported");
+        assert_eq!(
+            span.span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(
+            span.parent_span_id,
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn it_test_callback_endpoint_with_bad_verifier() {
         let mut notify = Notify::builder().build();
@@ -1341,6 +1566,122 @@ mod tests {
         assert!(sub_config.max_opened_subscriptions.is_none());
         assert!(sub_config.queue_capacity.is_none());
     }
+
+    #[test]
+    fn it_selects_replicas_with_affinity() {
+        let config: WebSocketConfiguration = serde_json::from_value(serde_json::json!({
+            "urls": ["http://replica-1:4001", "http://replica-2:4001", "http://replica-3:4001"],
+            "affinity": { "header": "x-session-id" }
+        }))
+        .unwrap();
+        let request = |session: &str| {
+            SubgraphRequest::fake_builder()
+                .supergraph_request(std::sync::Arc::new(
+                    http::Request::builder()
+                        .header("x-session-id", session)
+                        .body(Request::default())
+                        .unwrap(),
+                ))
+                .build()
+        };
+
+        // The replica of a client doesn't depend on the subscription
+        let replica = config
+            .replica(&request("session"), "subscription-1")
+            .unwrap();
+        assert_eq!(
+            config.replica(&request("session"), "subscription-2"),
+            Some(replica)
+        );
+        let replicas: HashSet<_> = (0..20)
+            .filter_map(|i| config.replica(&request(&format!("session-{i}")), "subscription"))
+            .collect();
+        assert_eq!(replicas.len(), 3);
+
+        // Removing a replica only moves its clients
+        let mut reduced = config.clone();
+        reduced.urls.retain(|url| url != replica);
+        for i in 0..20 {
+            let request = request(&format!("session-{i}"));
+            let before = config.replica(&request, "subscription").unwrap();
+            if before != replica {
+                assert_eq!(reduced.replica(&request, "subscription"), Some(before));
+            }
+        }
+
+        // Without affinity value, the subscription is hashed
+        let no_header = SubgraphRequest::fake_builder().build();
+        assert_eq!(
+            config.replica(&no_header, "subscription"),
+            config.replica(&no_header, "subscription")
+        );
+        assert!(WebSocketConfiguration::default()
+            .replica(&no_header, "subscription")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn it_batches_subscription_events() {
+        fn event(n: i64) -> Response {
+            Response::builder()
+                .data(serde_json_bytes::json!({ "n": n }))
+                .build()
+        }
+        fn numbers(batches: Vec<Vec<Response>>) -> Vec<Vec<i64>> {
+            batches
+                .into_iter()
+                .map(|batch| {
+                    batch
+                        .into_iter()
+                        .map(|event| event.data.unwrap()["n"].as_i64().unwrap())
+                        .collect()
+                })
+                .collect()
+        }
+        let config: SubscriptionConfig = serde_json::from_value(serde_json::json!({
+            "experimental_event_batching": [
+                { "matching": "^onPrice", "interval": "50ms", "deliver": "latest" },
+                { "matching": ".*", "interval": "50ms", "max_events": 2 }
+            ]
+        }))
+        .unwrap();
+        let batching = &config.experimental_event_batching;
+        assert!(batching[0].matching.is_match("onPriceChange"));
+
+        // Only the latest event of each interval is delivered, along with the events with errors
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let mut events = batching[0].batch(tokio_stream::wrappers::ReceiverStream::new(rx));
+        let mut failed = event(0);
+        failed.errors.push(
+            graphql::Error::builder()
+                .message("failed")
+                .extension_code("FAILED")
+                .build(),
+        );
+        tx.send(failed).await.unwrap();
+        for n in 1..3 {
+            tx.send(event(n)).await.unwrap();
+        }
+        assert_eq!(
+            numbers(vec![events.next().await.unwrap()]),
+            vec![vec![0, 2]]
+        );
+        tx.send(event(3)).await.unwrap();
+        drop(tx);
+        assert_eq!(numbers(events.collect().await), vec![vec![3]]);
+
+        // All the events are delivered, by batches of at most `max_events`
+        let (tx, rx) = tokio::sync::mpsc::channel(10);
+        let events = batching[1].batch(tokio_stream::wrappers::ReceiverStream::new(rx));
+        for n in 0..5 {
+            tx.send(event(n)).await.unwrap();
+        }
+        drop(tx);
+        assert_eq!(
+            numbers(events.collect().await),
+            vec![vec![0, 1], vec![2, 3], vec![4]]
+        );
+    }
 }
 
 register_plugin!("apollo", "subscription", Subscription);
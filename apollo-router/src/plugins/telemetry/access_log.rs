@@ -0,0 +1,436 @@
+//! Access logs: one line per router request, written to their own sink rather than to the
+//! application logs.
+use std::collections::LinkedList;
+use std::fs::OpenOptions;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use opentelemetry::KeyValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Map;
+use time::OffsetDateTime;
+use tower::BoxError;
+
+use super::config_new::attributes::RouterAttributes;
+use super::config_new::extendable::Extendable;
+use super::config_new::logging::Rollover;
+use super::config_new::selectors::RouterSelector;
+use super::config_new::Selectors;
+use crate::axum_factory::utils::ConnectionInfo;
+use crate::configuration::client_address::ClientAddress;
+use crate::services::router;
+
+/// Access log lines waiting to be written, beyond which new lines are dropped
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// Access logs, one line per router request
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct AccessLog {
+    /// Set to true to write access logs.
+    pub(crate) enabled: bool,
+    /// Where to write the access logs.
+    target: Target,
+    /// The format of the access logs.
+    format: AccessLogFormat,
+    /// Additional fields of the access logs, selected like the attributes of the router span.
+    fields: Extendable<RouterAttributes, RouterSelector>,
+}
+
+/// Where to write the access logs
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+enum Target {
+    /// Write to stdout.
+    #[default]
+    Stdout,
+    /// Write to a file.
+    File(FileTarget),
+}
+
+/// Write the access logs to a file
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct FileTarget {
+    /// The path of the file. Rolled over files have the date, and the hour for hourly rollovers, as
+    /// suffix.
+    path: PathBuf,
+    /// The period to rollover the file.
+    #[serde(default)]
+    rollover: Rollover,
+}
+
+/// The format of the access logs
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum AccessLogFormat {
+    /// A JSON object per line.
+    #[default]
+    Json,
+    /// The Common Log Format, followed by the additional fields as `name="value"`.
+    Common,
+}
+
+/// Writes the access logs from a dedicated thread, so that requests never wait on the sink
+pub(crate) struct AccessLogger {
+    format: AccessLogFormat,
+    fields: Extendable<RouterAttributes, RouterSelector>,
+    sender: mpsc::SyncSender<String>,
+}
+
+impl AccessLogger {
+    pub(crate) fn new(config: &AccessLog) -> Result<Self, BoxError> {
+        let mut sink = match &config.target {
+            Target::Stdout => Sink::Stdout(std::io::stdout()),
+            Target::File(file) => {
+                Sink::File(RollingFile::new(file.path.clone(), file.rollover.clone())?)
+            }
+        };
+        let (sender, receiver) = mpsc::sync_channel::<String>(QUEUE_CAPACITY);
+        std::thread::Builder::new()
+            .name("access log".to_string())
+            .spawn(move || {
+                // The thread ends once the logger and its sender are dropped
+                while let Ok(line) = receiver.recv() {
+                    sink.write_line(&line);
+                    while let Ok(line) = receiver.try_recv() {
+                        sink.write_line(&line);
+                    }
+                    sink.flush();
+                }
+            })?;
+        Ok(Self {
+            format: config.format,
+            fields: config.fields.clone(),
+            sender,
+        })
+    }
+
+    pub(crate) fn on_request(&self, request: &router::Request) -> AccessLogEntry {
+        let extensions = request.router_request.extensions();
+        let remote_address = extensions
+            .get::<ClientAddress>()
+            .map(|address| address.ip.to_string())
+            .or_else(|| {
+                extensions
+                    .get::<ConnectionInfo>()?
+                    .peer_address
+                    .map(|peer| peer.ip().to_string())
+            });
+        AccessLogEntry {
+            timestamp: OffsetDateTime::now_utc(),
+            start: Instant::now(),
+            remote_address,
+            method: request.router_request.method().to_string(),
+            uri: request.router_request.uri().to_string(),
+            protocol: format!("{:?}", request.router_request.version()),
+            fields: self.fields.on_request(request),
+        }
+    }
+
+    pub(crate) fn on_response(&self, mut entry: AccessLogEntry, response: &router::Response) {
+        entry.fields.extend(self.fields.on_response(response));
+        let length = response
+            .response
+            .headers()
+            .get(http::header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .map(str::to_string);
+        self.log(entry, response.response.status().as_u16(), length);
+    }
+
+    pub(crate) fn on_error(&self, mut entry: AccessLogEntry, error: &BoxError) {
+        entry
+            .fields
+            .extend(self.fields.on_error_after(error, entry.start.elapsed()));
+        self.log(entry, 500, None);
+    }
+
+    fn log(&self, entry: AccessLogEntry, status: u16, length: Option<String>) {
+        let line = match self.format {
+            AccessLogFormat::Json => entry.json(status, length),
+            AccessLogFormat::Common => entry.common(status, length),
+        };
+        if self.sender.try_send(line).is_err() {
+            ::tracing::warn!("the access log queue is full, dropping access log");
+        }
+    }
+}
+
+/// What the access log of a request needs from the request, until its response
+pub(crate) struct AccessLogEntry {
+    timestamp: OffsetDateTime,
+    start: Instant,
+    remote_address: Option<String>,
+    method: String,
+    uri: String,
+    protocol: String,
+    fields: LinkedList<KeyValue>,
+}
+
+impl AccessLogEntry {
+    fn json(self, status: u16, length: Option<String>) -> String {
+        let mut object = Map::new();
+        object.insert(
+            "timestamp".to_string(),
+            self.timestamp
+                .format(&time::format_description::well_known::Iso8601::DEFAULT)
+                .unwrap_or_default()
+                .into(),
+        );
+        object.insert("remote_address".to_string(), self.remote_address.into());
+        object.insert("method".to_string(), self.method.into());
+        object.insert("uri".to_string(), self.uri.into());
+        object.insert("protocol".to_string(), self.protocol.into());
+        object.insert("status".to_string(), status.into());
+        object.insert(
+            "bytes".to_string(),
+            length.and_then(|length| length.parse::<u64>().ok()).into(),
+        );
+        object.insert(
+            "duration".to_string(),
+            self.start.elapsed().as_secs_f64().into(),
+        );
+        for KeyValue { key, value } in self.fields {
+            object.insert(key.to_string(), json_value(value));
+        }
+        serde_json::Value::Object(object).to_string()
+    }
+
+    fn common(self, status: u16, length: Option<String>) -> String {
+        let timestamp = self.timestamp;
+        let mut line = format!(
+            "{} - - [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"{} {} {}\" {} {}",
+            self.remote_address.as_deref().unwrap_or("-"),
+            timestamp.day(),
+            &timestamp.month().to_string()[..3],
+            timestamp.year(),
+            timestamp.hour(),
+            timestamp.minute(),
+            timestamp.second(),
+            self.method,
+            self.uri,
+            self.protocol,
+            status,
+            length.as_deref().unwrap_or("-"),
+        );
+        for KeyValue { key, value } in self.fields {
+            line.push_str(&format!(" {}={:?}", key, value.as_str()));
+        }
+        line
+    }
+}
+
+pub(crate) fn json_value(value: opentelemetry::Value) -> serde_json::Value {
+    match value {
+        opentelemetry::Value::Bool(value) => value.into(),
+        opentelemetry::Value::I64(value) => value.into(),
+        opentelemetry::Value::F64(value) => value.into(),
+        opentelemetry::Value::String(value) => value.as_str().into(),
+        opentelemetry::Value::Array(opentelemetry::Array::Bool(values)) => values.into(),
+        opentelemetry::Value::Array(opentelemetry::Array::I64(values)) => values.into(),
+        opentelemetry::Value::Array(opentelemetry::Array::F64(values)) => values.into(),
+        opentelemetry::Value::Array(opentelemetry::Array::String(values)) => values
+            .iter()
+            .map(|value| serde_json::Value::from(value.as_str()))
+            .collect(),
+    }
+}
+
+enum Sink {
+    Stdout(std::io::Stdout),
+    File(RollingFile),
+}
+
+impl Sink {
+    fn write_line(&mut self, line: &str) {
+        let result = match self {
+            Sink::Stdout(stdout) => writeln!(stdout.lock(), "{line}"),
+            Sink::File(file) => file.write_line(line),
+        };
+        if let Err(e) = result {
+            ::tracing::error!("could not write access log: {e}");
+        }
+    }
+
+    fn flush(&mut self) {
+        let result = match self {
+            Sink::Stdout(stdout) => stdout.flush(),
+            Sink::File(file) => file.flush(),
+        };
+        if let Err(e) = result {
+            ::tracing::error!("could not write access log: {e}");
+        }
+    }
+}
+
+/// A file rolled over every hour or every day, by writing to a new file with the period as suffix
+struct RollingFile {
+    path: PathBuf,
+    rollover: Rollover,
+    period: String,
+    file: BufWriter<std::fs::File>,
+}
+
+impl RollingFile {
+    fn new(path: PathBuf, rollover: Rollover) -> std::io::Result<Self> {
+        let period = Self::period(&rollover, OffsetDateTime::now_utc());
+        let file = Self::open(&path, &period)?;
+        Ok(Self {
+            path,
+            rollover,
+            period,
+            file,
+        })
+    }
+
+    fn period(rollover: &Rollover, now: OffsetDateTime) -> String {
+        match rollover {
+            Rollover::Hourly => format!(
+                "{}-{:02}-{:02}-{:02}",
+                now.year(),
+                now.month() as u8,
+                now.day(),
+                now.hour()
+            ),
+            Rollover::Daily => {
+                format!("{}-{:02}-{:02}", now.year(), now.month() as u8, now.day())
+            }
+            Rollover::Never => String::new(),
+        }
+    }
+
+    fn open(path: &Path, period: &str) -> std::io::Result<BufWriter<std::fs::File>> {
+        let path = if period.is_empty() {
+            path.to_path_buf()
+        } else {
+            let mut path = path.as_os_str().to_os_string();
+            path.push(".");
+            path.push(period);
+            path.into()
+        };
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(BufWriter::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        ))
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        let period = Self::period(&self.rollover, OffsetDateTime::now_utc());
+        if period != self.period {
+            self.file.flush()?;
+            self.file = Self::open(&self.path, &period)?;
+            self.period = period;
+        }
+        writeln!(self.file, "{line}")
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+
+    fn logger(dir: &tempfile::TempDir, format: &str) -> AccessLogger {
+        AccessLogger::new(
+            &serde_json::from_value(json!({
+                "enabled": true,
+                "format": format,
+                "target": { "file": { "path": dir.path().join("access.log"), "rollover": "never" } },
+                "fields": {
+                    "client": { "request_header": "apollographql-client-name" }
+                }
+            }))
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    async fn read_log(dir: &tempfile::TempDir) -> String {
+        for _ in 0..50 {
+            let log = std::fs::read_to_string(dir.path().join("access.log")).unwrap_or_default();
+            if !log.is_empty() {
+                return log;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the access log was not written")
+    }
+
+    fn request() -> router::Request {
+        let mut request = router::Request::fake_builder()
+            .header("apollographql-client-name", "web")
+            .build()
+            .unwrap();
+        request
+            .router_request
+            .extensions_mut()
+            .insert(ClientAddress {
+                ip: "192.0.2.1".parse().unwrap(),
+                port: None,
+            });
+        request
+    }
+
+    #[tokio::test]
+    async fn it_writes_json_access_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = logger(&dir, "json");
+        let entry = logger.on_request(&request());
+        let response = router::Response::fake_builder()
+            .status_code(StatusCode::OK)
+            .build()
+            .unwrap();
+        logger.on_response(entry, &response);
+
+        let log: serde_json::Value = serde_json::from_str(read_log(&dir).await.trim()).unwrap();
+        assert_eq!(log["remote_address"], "192.0.2.1");
+        assert_eq!(log["method"], "GET");
+        assert_eq!(log["status"], 200);
+        assert_eq!(log["client"], "web");
+        assert!(log["duration"].is_f64());
+    }
+
+    #[tokio::test]
+    async fn it_writes_common_access_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let logger = logger(&dir, "common");
+        let entry = logger.on_request(&request());
+        logger.on_error(entry, &BoxError::from("failed"));
+
+        let log = read_log(&dir).await;
+        let log = regex::Regex::new(r"\[[^\]]+\]")
+            .unwrap()
+            .replace(log.trim(), "[date]");
+        assert_eq!(
+            log,
+            r#"192.0.2.1 - - [date] "GET http://example.com/ HTTP/1.1" 500 - client="web""#
+        );
+    }
+
+    #[test]
+    fn it_names_rolled_over_files_by_period() {
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        assert_eq!(RollingFile::period(&Rollover::Hourly, now), "2023-11-14-22");
+        assert_eq!(RollingFile::period(&Rollover::Daily, now), "2023-11-14");
+        assert_eq!(RollingFile::period(&Rollover::Never, now), "");
+    }
+}
@@ -0,0 +1,522 @@
+//! Analytics sink: a metadata record per sampled operation, streamed in batches to an HTTP
+//! collector or to a Kafka topic through a Kafka REST proxy, for analytics built outside GraphOS.
+//!
+//! Records never contain the query, its variables or headers, and contain error messages only
+//! when configured to.
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use tower::BoxError;
+use url::Url;
+
+use super::batching;
+use super::config_new::selectors::FirstResponseErrors;
+use super::kafka::KafkaRecord;
+use super::kafka::KafkaRestProducer;
+use super::tracing::sampling::sample;
+use super::CLIENT_NAME;
+use super::CLIENT_VERSION;
+use crate::context::OPERATION_KIND;
+use crate::context::OPERATION_NAME;
+use crate::graphql;
+use crate::query_planner::APOLLO_OPERATION_ID;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::Context;
+
+/// Records waiting to be sent, beyond which new records are dropped
+const QUEUE_CAPACITY: usize = 10_000;
+/// The version of the record schema, bumped on breaking changes
+const SCHEMA_VERSION: u32 = 1;
+
+/// Operation metadata records sent to an analytics sink
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Analytics {
+    /// Set to true to send operation records.
+    pub(crate) enabled: bool,
+    /// Where to send the records.
+    target: Option<Target>,
+    /// The ratio of operations that are recorded (default: 1.0).
+    sampler: f64,
+    /// The maximum number of records sent at once (default: 100).
+    batch_size: usize,
+    /// The maximum delay before queued records are sent (default: 1s).
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    flush_interval: Duration,
+    /// Include the messages of errors in the records, and not only their codes (default: false).
+    /// Error messages may contain data of the responses.
+    include_error_messages: bool,
+}
+
+impl Default for Analytics {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target: None,
+            sampler: 1.0,
+            batch_size: 100,
+            flush_interval: Duration::from_secs(1),
+            include_error_messages: false,
+        }
+    }
+}
+
+/// Where to send the records
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+enum Target {
+    /// POST batches of records as a JSON array.
+    Http(HttpTarget),
+    /// Produce the records to a Kafka topic through a Kafka REST proxy.
+    Kafka(KafkaTarget),
+}
+
+/// POST the records to an HTTP collector
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct HttpTarget {
+    /// The URL of the collector.
+    endpoint: Url,
+    /// Headers of the requests to the collector, such as credentials.
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+/// Produce the records to a Kafka topic through a Kafka REST proxy
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct KafkaTarget {
+    /// The URL of the Kafka REST proxy.
+    rest_proxy: Url,
+    /// The topic of the records.
+    topic: String,
+    /// Headers of the requests to the proxy, such as credentials.
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+impl Analytics {
+    pub(crate) fn validate(&self) -> Result<(), BoxError> {
+        if self.enabled && self.target.is_none() {
+            return Err(
+                "telemetry.exporters.analytics.target is required to send analytics".into(),
+            );
+        }
+        if self.batch_size == 0 {
+            return Err("telemetry.exporters.analytics.batch_size must be positive".into());
+        }
+        Ok(())
+    }
+}
+
+/// An operation record, as serialized to the sink
+#[derive(Debug, Serialize)]
+struct AnalyticsRecord {
+    schema_version: u32,
+    timestamp: String,
+    operation_id: Option<String>,
+    operation_name: Option<String>,
+    operation_kind: Option<String>,
+    client_name: Option<String>,
+    client_version: Option<String>,
+    status: u16,
+    duration_ms: f64,
+    errors: ErrorsRecord,
+    subgraphs: Vec<SubgraphRecord>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ErrorsRecord {
+    count: usize,
+    codes: BTreeSet<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    messages: Option<Vec<String>>,
+}
+
+impl ErrorsRecord {
+    fn new(errors: &[graphql::Error], include_messages: bool) -> Self {
+        Self {
+            count: errors.len(),
+            codes: errors
+                .iter()
+                .filter_map(|error| error.extensions.get("code")?.as_str())
+                .map(str::to_string)
+                .collect(),
+            messages: include_messages
+                .then(|| errors.iter().map(|error| error.message.clone()).collect()),
+        }
+    }
+}
+
+/// A subgraph request of an operation
+#[derive(Clone, Debug, Serialize)]
+struct SubgraphRecord {
+    name: String,
+    status: Option<u16>,
+    duration_ms: f64,
+    errors: usize,
+}
+
+/// The subgraph requests of a sampled operation, shared through its context
+#[derive(Clone, Default)]
+pub(crate) struct SubgraphBreakdown(Arc<Mutex<Vec<SubgraphRecord>>>);
+
+impl SubgraphBreakdown {
+    pub(crate) fn record(
+        &self,
+        name: &str,
+        duration: Duration,
+        result: &Result<subgraph::Response, BoxError>,
+    ) {
+        let (status, errors) = match result {
+            Ok(response) => (
+                Some(response.response.status().as_u16()),
+                response.response.body().errors.len(),
+            ),
+            Err(_) => (None, 1),
+        };
+        self.0.lock().push(SubgraphRecord {
+            name: name.to_string(),
+            status,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            errors,
+        });
+    }
+}
+
+/// Sends the records from a background task, so that requests never wait on the sink
+pub(crate) struct AnalyticsSink {
+    sampler: f64,
+    include_error_messages: bool,
+    sender: mpsc::Sender<AnalyticsRecord>,
+}
+
+impl AnalyticsSink {
+    pub(crate) fn new(config: &Analytics) -> Result<Self, BoxError> {
+        config.validate()?;
+        let transport = Arc::new(Transport::new(config.target.as_ref().expect("validated"))?);
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(batching::run(
+            receiver,
+            config.batch_size,
+            config.flush_interval,
+            move |batch| {
+                let transport = transport.clone();
+                async move { transport.send(batch).await }
+            },
+        ));
+        Ok(Self {
+            sampler: config.sampler,
+            include_error_messages: config.include_error_messages,
+            sender,
+        })
+    }
+
+    /// Starts recording the operation if it is sampled
+    pub(crate) fn on_request(&self, request: &supergraph::Request) -> Option<AnalyticsEntry> {
+        if !sample(self.sampler) {
+            return None;
+        }
+        let breakdown = SubgraphBreakdown::default();
+        request
+            .context
+            .private_entries
+            .lock()
+            .insert(breakdown.clone());
+        Some(AnalyticsEntry {
+            timestamp: OffsetDateTime::now_utc(),
+            start: Instant::now(),
+            breakdown,
+            context: request.context.clone(),
+        })
+    }
+
+    pub(crate) fn on_response(&self, entry: AnalyticsEntry, response: &supergraph::Response) {
+        let errors = response
+            .context
+            .private_entries
+            .lock()
+            .get::<FirstResponseErrors>()
+            .cloned();
+        let errors = ErrorsRecord::new(
+            errors
+                .as_ref()
+                .map_or(&[][..], |errors| errors.0.as_slice()),
+            self.include_error_messages,
+        );
+        self.send(
+            entry,
+            &response.context,
+            response.response.status().as_u16(),
+            errors,
+        );
+    }
+
+    pub(crate) fn on_error(&self, entry: AnalyticsEntry) {
+        let errors = ErrorsRecord {
+            count: 1,
+            ..Default::default()
+        };
+        let context = entry.context.clone();
+        self.send(entry, &context, 500, errors);
+    }
+
+    fn send(&self, entry: AnalyticsEntry, context: &Context, status: u16, errors: ErrorsRecord) {
+        let get = |key: &str| context.get::<_, String>(key).ok().flatten();
+        let record = AnalyticsRecord {
+            schema_version: SCHEMA_VERSION,
+            timestamp: entry
+                .timestamp
+                .format(&time::format_description::well_known::Iso8601::DEFAULT)
+                .unwrap_or_default(),
+            operation_id: get(APOLLO_OPERATION_ID),
+            operation_name: get(OPERATION_NAME),
+            operation_kind: get(OPERATION_KIND),
+            client_name: get(CLIENT_NAME).filter(|name| !name.is_empty()),
+            client_version: get(CLIENT_VERSION).filter(|version| !version.is_empty()),
+            status,
+            duration_ms: entry.start.elapsed().as_secs_f64() * 1000.0,
+            errors,
+            subgraphs: std::mem::take(&mut *entry.breakdown.0.lock()),
+        };
+        if self.sender.try_send(record).is_err() {
+            ::tracing::warn!("the analytics queue is full, dropping analytics record");
+        }
+    }
+}
+
+/// What the record of an operation needs from its request, until its response
+pub(crate) struct AnalyticsEntry {
+    timestamp: OffsetDateTime,
+    start: Instant,
+    breakdown: SubgraphBreakdown,
+    context: Context,
+}
+
+enum Transport {
+    Http {
+        client: reqwest::Client,
+        url: Url,
+        headers: http::HeaderMap,
+    },
+    Kafka(KafkaRestProducer),
+}
+
+impl Transport {
+    fn new(target: &Target) -> Result<Self, BoxError> {
+        match target {
+            Target::Http(http) => Ok(Self::Http {
+                client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()?,
+                url: http.endpoint.clone(),
+                headers: http
+                    .headers
+                    .iter()
+                    .map(|(name, value)| Ok((name.parse()?, value.parse()?)))
+                    .collect::<Result<_, BoxError>>()?,
+            }),
+            Target::Kafka(kafka) => Ok(Self::Kafka(KafkaRestProducer::new(
+                &kafka.rest_proxy,
+                &kafka.topic,
+                &kafka.headers,
+            )?)),
+        }
+    }
+
+    async fn send(&self, records: Vec<AnalyticsRecord>) {
+        let count = records.len();
+        let result = match self {
+            Self::Http {
+                client,
+                url,
+                headers,
+            } => client
+                .post(url.clone())
+                .headers(headers.clone())
+                .json(&records)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map(|_| ())
+                .map_err(BoxError::from),
+            Self::Kafka(producer) => {
+                let records: Vec<_> = records
+                    .into_iter()
+                    .map(|record| KafkaRecord {
+                        key: None,
+                        value: serde_json::to_value(record).unwrap_or_default(),
+                    })
+                    .collect();
+                producer.produce(&records).await
+            }
+        };
+        if let Err(e) = result {
+            ::tracing::error!("could not send {count} analytics records: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use serde_json::Value;
+    use wiremock::matchers::header;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+
+    async fn received(server: &MockServer) -> Value {
+        for _ in 0..50 {
+            if let Some(request) = server.received_requests().await.unwrap_or_default().first() {
+                return serde_json::from_slice(&request.body).unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("no analytics were sent")
+    }
+
+    fn request() -> supergraph::Request {
+        let request = supergraph::Request::fake_builder().build().unwrap();
+        request
+            .context
+            .insert(APOLLO_OPERATION_ID, "abc123".to_string())
+            .unwrap();
+        request
+            .context
+            .insert(CLIENT_NAME, "web".to_string())
+            .unwrap();
+        request
+            .context
+            .insert(OPERATION_NAME, "TopProducts".to_string())
+            .unwrap();
+        request
+    }
+
+    fn record_operation(sink: &AnalyticsSink) {
+        let request = request();
+        let entry = sink.on_request(&request).unwrap();
+        let subgraph_response = subgraph::Response::fake_builder()
+            .error(
+                graphql::Error::builder()
+                    .message("secret")
+                    .extension_code("FORBIDDEN")
+                    .build(),
+            )
+            .build();
+        request
+            .context
+            .private_entries
+            .lock()
+            .get::<SubgraphBreakdown>()
+            .unwrap()
+            .record("products", Duration::from_millis(5), &Ok(subgraph_response));
+        request
+            .context
+            .private_entries
+            .lock()
+            .insert(FirstResponseErrors(Arc::new(vec![
+                graphql::Error::builder()
+                    .message("secret")
+                    .extension_code("FORBIDDEN")
+                    .build(),
+            ])));
+        let response = supergraph::Response::fake_builder()
+            .context(request.context)
+            .build()
+            .unwrap();
+        sink.on_response(entry, &response);
+    }
+
+    #[tokio::test]
+    async fn it_sends_redacted_records_to_http_collectors() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/analytics"))
+            .and(header("x-api-key", "key"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let sink = AnalyticsSink::new(
+            &serde_json::from_value(json!({
+                "enabled": true,
+                "target": { "http": {
+                    "endpoint": format!("{}/analytics", server.uri()),
+                    "headers": { "x-api-key": "key" }
+                } },
+                "flush_interval": "10ms"
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        record_operation(&sink);
+
+        let records = received(&server).await;
+        let record = &records[0];
+        assert_eq!(record["schema_version"], 1);
+        assert_eq!(record["operation_id"], "abc123");
+        assert_eq!(record["operation_name"], "TopProducts");
+        assert_eq!(record["client_name"], "web");
+        assert_eq!(record["client_version"], Value::Null);
+        assert_eq!(record["status"], 200);
+        assert_eq!(
+            record["errors"],
+            json!({ "count": 1, "codes": ["FORBIDDEN"] })
+        );
+        assert_eq!(record["subgraphs"][0]["name"], "products");
+        assert_eq!(record["subgraphs"][0]["errors"], 1);
+        assert_eq!(record["subgraphs"][0]["duration_ms"], 5.0);
+        assert!(!records.to_string().contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn it_produces_records_through_kafka_rest_proxies() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/topics/operations"))
+            .and(header("content-type", "application/vnd.kafka.json.v2+json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "offsets": [{ "partition": 0, "offset": 0 }] })),
+            )
+            .mount(&server)
+            .await;
+        let sink = AnalyticsSink::new(
+            &serde_json::from_value(json!({
+                "enabled": true,
+                "target": { "kafka": { "rest_proxy": server.uri(), "topic": "operations" } },
+                "flush_interval": "10ms",
+                "include_error_messages": true
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        record_operation(&sink);
+
+        let body = received(&server).await;
+        let record = &body["records"][0]["value"];
+        assert_eq!(record["operation_id"], "abc123");
+        assert_eq!(record["errors"]["messages"], json!(["secret"]));
+    }
+
+    #[test]
+    fn it_requires_a_target() {
+        let config: Analytics = serde_json::from_value(json!({ "enabled": true })).unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -13,6 +13,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use url::Url;
 
+use super::config_new::selectors::Redact;
 use super::metrics::apollo::studio::ContextualizedStats;
 use super::metrics::apollo::studio::SingleStats;
 use super::metrics::apollo::studio::SingleStatsReport;
@@ -28,6 +29,7 @@ use crate::plugins::telemetry::tracing::BatchProcessorConfig;
 use crate::query_planner::OperationKind;
 use crate::services::apollo_graph_reference;
 use crate::services::apollo_key;
+use crate::Context;
 
 pub(crate) const ENDPOINT_DEFAULT: &str =
     "https://usage-reporting.api.apollographql.com/api/ingress/traces";
@@ -69,10 +71,16 @@ pub(crate) struct Config {
     /// Field level instrumentation for subgraphs via ftv1. ftv1 tracing can cause performance issues as it is transmitted in band with subgraph responses.
     pub(crate) field_level_instrumentation_sampler: SamplerOption,
 
+    /// Field level instrumentation samplers of specific subgraphs, overriding `field_level_instrumentation_sampler` for these subgraphs.
+    /// Use `always_off` to never ask a subgraph for ftv1 traces.
+    pub(crate) field_level_instrumentation_subgraphs: HashMap<String, SamplerOption>,
+
     /// To configure which request header names and values are included in trace data that's sent to Apollo Studio.
     pub(crate) send_headers: ForwardHeaders,
     /// To configure which GraphQL variable values are included in trace data that's sent to Apollo Studio
     pub(crate) send_variable_values: ForwardValues,
+    /// Context entries included in trace data that's sent to Apollo Studio, shown with the request headers of the trace under a `context:` prefix
+    pub(crate) send_context: Vec<ContextMetadata>,
 
     // This'll get overridden if a user tries to set it.
     // The purpose is to allow is to pass this in to the plugin.
@@ -84,6 +92,10 @@ pub(crate) struct Config {
 
     /// Configure the way errors are transmitted to Apollo Studio
     pub(crate) errors: ErrorsConfiguration,
+
+    /// Include the operation signature reported to Apollo Studio in the `apolloOperationSignature` extension of responses,
+    /// so that other systems can correlate requests with Apollo Studio operations.
+    pub(crate) expose_operation_signature: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
@@ -174,10 +186,13 @@ impl Default for Config {
             schema_id: "<no_schema_id>".to_string(),
             buffer_size: default_buffer_size(),
             field_level_instrumentation_sampler: default_field_level_instrumentation_sampler(),
+            field_level_instrumentation_subgraphs: HashMap::new(),
             send_headers: ForwardHeaders::None,
             send_variable_values: ForwardValues::None,
+            send_context: Vec::new(),
             batch_processor: BatchProcessorConfig::default(),
             errors: ErrorsConfiguration::default(),
+            expose_operation_signature: false,
         }
     }
 }
@@ -254,6 +269,41 @@ impl Default for ForwardValues {
     }
 }
 
+/// A context entry sent to Apollo Studio as trace metadata
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ContextMetadata {
+    /// The key of the context entry, for example `apollo_authentication::JWT::claims`.
+    pub(crate) key: String,
+    /// A JSON pointer to the value within the context entry, for example `/tenant`.
+    pub(crate) pointer: Option<String>,
+    /// The name of the value in the trace.
+    pub(crate) name: String,
+    /// Optional redaction of the value.
+    pub(crate) redact: Option<Redact>,
+}
+
+impl ContextMetadata {
+    /// Returns the value to send, if the context has one. Strings are sent as is, other values
+    /// as JSON
+    pub(crate) fn value(&self, context: &Context) -> Option<String> {
+        let value = serde_json::to_value(context.get_json_value(&self.key)?).ok()?;
+        let value = match &self.pointer {
+            Some(pointer) => value.pointer(pointer)?.clone(),
+            None => value,
+        };
+        let value = match value {
+            serde_json::Value::Null => return None,
+            serde_json::Value::String(value) => value,
+            value => value.to_string(),
+        };
+        Some(match &self.redact {
+            Some(redact) => redact.apply(value.into()).as_str().into_owned(),
+            None => value,
+        })
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub(crate) enum SingleReport {
     Stats(SingleStatsReport),
@@ -0,0 +1,138 @@
+//! Propagation of the W3C baggage, with filtering of the entries sent by the router.
+
+use std::collections::HashSet;
+
+use opentelemetry::baggage::Baggage;
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::propagation::text_map_propagator::FieldIter;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::propagation::Injector;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::sdk::propagation::BaggagePropagator;
+
+use crate::plugins::telemetry::config::BaggageFilter;
+
+/// Extracts the whole baggage of incoming requests, and only injects the allowed entries in
+/// outgoing requests
+#[derive(Debug)]
+pub(crate) struct FilteredBaggagePropagator {
+    inner: BaggagePropagator,
+    allow: Option<HashSet<String>>,
+    deny: HashSet<String>,
+}
+
+impl FilteredBaggagePropagator {
+    pub(crate) fn new(filter: &BaggageFilter) -> Self {
+        Self {
+            inner: BaggagePropagator::new(),
+            allow: filter
+                .allow
+                .as_ref()
+                .map(|allow| allow.iter().cloned().collect()),
+            deny: filter.deny.iter().cloned().collect(),
+        }
+    }
+
+    fn is_allowed(&self, key: &str) -> bool {
+        !self.deny.contains(key)
+            && self
+                .allow
+                .as_ref()
+                .map_or(true, |allow| allow.contains(key))
+    }
+}
+
+impl TextMapPropagator for FilteredBaggagePropagator {
+    fn inject_context(&self, cx: &opentelemetry::Context, injector: &mut dyn Injector) {
+        let baggage: Baggage = cx
+            .baggage()
+            .iter()
+            .filter(|(key, _)| self.is_allowed(key.as_str()))
+            .map(|(key, (value, metadata))| (key.clone(), (value.clone(), metadata.clone())))
+            .collect();
+        self.inner
+            .inject_context(&opentelemetry::Context::new().with_value(baggage), injector)
+    }
+
+    fn extract_with_context(
+        &self,
+        cx: &opentelemetry::Context,
+        extractor: &dyn Extractor,
+    ) -> opentelemetry::Context {
+        self.inner.extract_with_context(cx, extractor)
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        self.inner.fields()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use opentelemetry::KeyValue;
+
+    use super::*;
+
+    fn inject(filter: BaggageFilter) -> Option<String> {
+        let cx = opentelemetry::Context::new().with_baggage(vec![
+            KeyValue::new("tenant", "acme"),
+            KeyValue::new("user", "ada"),
+            KeyValue::new("session", "secret"),
+        ]);
+        let mut headers = HashMap::new();
+        FilteredBaggagePropagator::new(&filter).inject_context(&cx, &mut headers);
+        headers.get("baggage").map(|header| {
+            let mut entries: Vec<&str> = header.split(',').collect();
+            entries.sort_unstable();
+            entries.join(",")
+        })
+    }
+
+    #[test]
+    fn it_injects_the_allowed_entries() {
+        assert_eq!(
+            inject(BaggageFilter::default()).as_deref(),
+            Some("session=secret,tenant=acme,user=ada")
+        );
+        assert_eq!(
+            inject(BaggageFilter {
+                allow: None,
+                deny: vec!["session".to_string()],
+            })
+            .as_deref(),
+            Some("tenant=acme,user=ada")
+        );
+        assert_eq!(
+            inject(BaggageFilter {
+                allow: Some(vec!["tenant".to_string(), "session".to_string()]),
+                deny: vec!["session".to_string()],
+            })
+            .as_deref(),
+            Some("tenant=acme")
+        );
+        assert_eq!(
+            inject(BaggageFilter {
+                allow: Some(Vec::new()),
+                deny: Vec::new(),
+            }),
+            None
+        );
+    }
+
+    #[test]
+    fn it_extracts_the_whole_baggage() {
+        let mut headers = HashMap::new();
+        headers.insert(
+            "baggage".to_string(),
+            "tenant=acme,session=secret".to_string(),
+        );
+        let propagator = FilteredBaggagePropagator::new(&BaggageFilter {
+            allow: Some(vec!["tenant".to_string()]),
+            deny: Vec::new(),
+        });
+        let cx = propagator.extract(&headers);
+        assert_eq!(cx.baggage().len(), 2);
+    }
+}
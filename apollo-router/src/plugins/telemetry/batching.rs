@@ -0,0 +1,72 @@
+//! Batching of the records that the exporters stream to their sinks from a background task.
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+/// Hands the records to `send` by batches of up to `batch_size`, or every `flush_interval`, until
+/// every sender of the queue is dropped.
+///
+/// A batch is sent before the next records are received, so a slow sink leaves the records
+/// waiting in the queue rather than reordering them.
+pub(crate) async fn run<T, F, Fut>(
+    mut receiver: mpsc::Receiver<T>,
+    batch_size: usize,
+    flush_interval: Duration,
+    mut send: F,
+) where
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut interval = tokio::time::interval(flush_interval);
+    loop {
+        let closed = tokio::select! {
+            record = receiver.recv() => match record {
+                Some(record) => {
+                    batch.push(record);
+                    if batch.len() < batch_size {
+                        continue;
+                    }
+                    false
+                }
+                None => true,
+            },
+            _ = interval.tick() => false,
+        };
+        if !batch.is_empty() {
+            let records = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+            send(records).await;
+        }
+        if closed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parking_lot::Mutex;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_sends_full_batches_in_order_and_flushes_the_rest_when_closed() {
+        let (sender, receiver) = mpsc::channel(10);
+        for record in 0..5 {
+            sender.send(record).await.unwrap();
+        }
+        drop(sender);
+
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let sent = batches.clone();
+        run(receiver, 2, Duration::from_secs(60), move |batch| {
+            sent.lock().push(batch);
+            async {}
+        })
+        .await;
+        assert_eq!(*batches.lock(), vec![vec![0, 1], vec![2, 3], vec![4]]);
+    }
+}
@@ -2,6 +2,11 @@
 use std::collections::BTreeMap;
 
 use axum::headers::HeaderName;
+use opentelemetry::sdk::metrics::Aggregation;
+use opentelemetry::sdk::metrics::Instrument;
+use opentelemetry::sdk::metrics::InstrumentKind;
+use opentelemetry::sdk::metrics::Stream;
+use opentelemetry::sdk::metrics::View;
 use opentelemetry::sdk::trace::SpanLimits;
 use opentelemetry::Array;
 use opentelemetry::Value;
@@ -9,8 +14,10 @@ use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 
+use super::debug_token::DebugToken;
 use super::metrics::MetricsAttributesConf;
 use super::*;
+use crate::configuration::subgraph::SubgraphConfiguration;
 use crate::plugin::serde::deserialize_option_header_name;
 use crate::plugins::telemetry::metrics;
 use crate::plugins::telemetry::resource::ConfigResource;
@@ -19,6 +26,10 @@ use crate::plugins::telemetry::resource::ConfigResource;
 pub(crate) enum Error {
     #[error("field level instrumentation sampler must sample less frequently than tracing level sampler")]
     InvalidFieldLevelInstrumentationSampler,
+    #[error("field level instrumentation sampler of subgraph '{0}' must sample less frequently than tracing level sampler")]
+    InvalidSubgraphFieldLevelInstrumentationSampler(String),
+    #[error("histogram buckets of the '{0}' metric view must be in increasing order")]
+    UnsortedHistogramBuckets(String),
 }
 
 pub(in crate::plugins::telemetry) trait GenericWith<T>
@@ -69,20 +80,56 @@ pub(crate) struct Exporters {
     pub(crate) metrics: Metrics,
     /// Tracing configuration
     pub(crate) tracing: Tracing,
+    /// Operation records sent to an analytics sink
+    pub(crate) analytics: super::analytics::Analytics,
 }
 
 /// Instrumentation configuration
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, default)]
 pub(crate) struct Instrumentation {
-    #[serde(skip)]
     /// Event configuration
     pub(crate) events: config_new::events::Events,
     /// Span configuration
     pub(crate) spans: config_new::spans::Spans,
-    #[serde(skip)]
     /// Instrument configuration
     pub(crate) instruments: config_new::instruments::Instruments,
+    /// Synthetic traffic configuration
+    pub(crate) synthetic_traffic: SyntheticTraffic,
+    /// Redaction of sensitive GraphQL variables in logs and traces
+    pub(crate) variable_redaction: VariableRedactionConfig,
+}
+
+/// Redaction of sensitive GraphQL variables in logs and traces
+#[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct VariableRedactionConfig {
+    /// Redact the variables used in arguments or input fields marked with this schema directive, for example `sensitive`.
+    /// A variable whose input object value contains a marked input field is redacted as a whole.
+    pub(crate) directive: Option<String>,
+    /// Redact the variables with these names.
+    pub(crate) names: Vec<String>,
+}
+
+/// Synthetic traffic configuration
+#[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SyntheticTraffic {
+    /// The request header marking a request as synthetic traffic, such as health checks or smoke tests.
+    /// Synthetic requests are excluded from Apollo usage reporting, and have the `apollo.synthetic` attribute on their router span and request metrics.
+    #[schemars(with = "Option<String>")]
+    #[serde(deserialize_with = "deserialize_option_header_name")]
+    pub(crate) header_name: Option<HeaderName>,
+}
+
+impl SyntheticTraffic {
+    /// Whether the request is marked as synthetic. The header is removed from the request,
+    /// so that it cannot be propagated to subgraphs.
+    pub(crate) fn take_synthetic(&self, headers: &mut http::HeaderMap) -> bool {
+        self.header_name
+            .as_ref()
+            .map_or(false, |header_name| headers.remove(header_name).is_some())
+    }
 }
 
 /// Metrics configuration
@@ -92,7 +139,7 @@ pub(crate) struct Metrics {
     /// Common metrics configuration across all exporters
     pub(crate) common: MetricsCommon,
     /// Open Telemetry native exporter configuration
-    pub(crate) otlp: otlp::Config,
+    pub(crate) otlp: metrics::otlp::Config,
     /// Prometheus exporter configuration
     pub(crate) prometheus: metrics::prometheus::Config,
 }
@@ -110,6 +157,8 @@ pub(crate) struct MetricsCommon {
     pub(crate) resource: BTreeMap<String, AttributeValue>,
     /// Custom buckets for histograms
     pub(crate) buckets: Vec<f64>,
+    /// Views overriding the aggregation of instruments by name
+    pub(crate) views: Vec<MetricView>,
     /// Experimental metrics to know more about caching strategies
     pub(crate) experimental_cache_metrics: ExperimentalCacheMetricsConf,
 }
@@ -144,11 +193,120 @@ impl Default for MetricsCommon {
             buckets: vec![
                 0.001, 0.005, 0.015, 0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 1.0, 5.0, 10.0,
             ],
+            views: Vec::new(),
             experimental_cache_metrics: ExperimentalCacheMetricsConf::default(),
         }
     }
 }
 
+impl MetricsCommon {
+    /// The views of an exporter: the common views, followed by the views of the exporter
+    pub(crate) fn views_of(&self, exporter_views: &[MetricView]) -> Vec<MetricView> {
+        self.views.iter().chain(exporter_views).cloned().collect()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        validate_views(&self.views)
+    }
+}
+
+pub(crate) fn validate_views(views: &[MetricView]) -> Result<(), Error> {
+    for view in views {
+        if let Some(MetricAggregation::Histogram { buckets }) = &view.aggregation {
+            if buckets.windows(2).any(|bounds| bounds[0] >= bounds[1]) {
+                return Err(Error::UnsortedHistogramBuckets(view.name.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A view changing how an instrument is aggregated by the metrics exporters
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MetricView {
+    /// The name of the instrument, or a prefix of instrument names followed by `*`
+    pub(crate) name: String,
+    /// The aggregation of the instrument
+    #[serde(default)]
+    pub(crate) aggregation: Option<MetricAggregation>,
+    /// The attributes kept on the instrument, the others are dropped. All attributes are kept if absent.
+    #[serde(default)]
+    pub(crate) allowed_attributes: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum MetricAggregation {
+    /// An histogram with explicit bucket boundaries
+    Histogram {
+        /// The bucket boundaries, in increasing order
+        buckets: Vec<f64>,
+    },
+}
+
+impl MetricView {
+    fn matches(&self, instrument: &str) -> bool {
+        match self.name.strip_suffix('*') {
+            Some(prefix) => instrument.starts_with(prefix),
+            None => self.name == instrument,
+        }
+    }
+
+    /// The histogram buckets of an instrument set by the last of its views setting them
+    pub(crate) fn buckets_of<'a>(views: &'a [MetricView], instrument: &str) -> Option<&'a [f64]> {
+        views
+            .iter()
+            .rev()
+            .filter(|view| view.matches(instrument))
+            .find_map(|view| {
+                view.aggregation
+                    .as_ref()
+                    .map(|MetricAggregation::Histogram { buckets }| buckets.as_slice())
+            })
+    }
+
+    /// The attributes kept on an instrument by the last of its views filtering them
+    pub(crate) fn allowed_attributes_of<'a>(
+        views: &'a [MetricView],
+        instrument: &str,
+    ) -> Option<&'a [String]> {
+        views
+            .iter()
+            .rev()
+            .filter(|view| view.matches(instrument))
+            .find_map(|view| view.allowed_attributes.as_deref())
+    }
+
+    /// The opentelemetry view applying these views to the instruments they match. Views matching
+    /// the same instrument are merged into a single stream, later views overriding earlier ones.
+    pub(crate) fn to_view(views: Vec<MetricView>) -> impl View {
+        move |instrument: &Instrument| {
+            if !views.iter().any(|view| view.matches(&instrument.name)) {
+                return None;
+            }
+            let mut stream = Stream::new()
+                .name(instrument.name.clone())
+                .description(instrument.description.clone())
+                .unit(instrument.unit.clone());
+            if instrument.kind == Some(InstrumentKind::Histogram) {
+                if let Some(buckets) = Self::buckets_of(&views, &instrument.name) {
+                    stream = stream.aggregation(Aggregation::ExplicitBucketHistogram {
+                        boundaries: buckets.to_vec(),
+                        record_min_max: true,
+                    });
+                }
+            }
+            if let Some(attributes) = Self::allowed_attributes_of(&views, &instrument.name) {
+                stream = stream.allowed_attribute_keys(
+                    attributes.iter().cloned().map(opentelemetry::Key::new),
+                );
+            }
+            Some(stream)
+        }
+    }
+}
+
 /// Tracing configuration
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, default)]
@@ -170,6 +328,9 @@ pub(crate) struct Tracing {
     pub(crate) zipkin: tracing::zipkin::Config,
     /// Datadog exporter configuration
     pub(crate) datadog: tracing::datadog::Config,
+    /// Force the tracing of requests with a signed debug token
+    #[serde(rename = "experimental_debug_token")]
+    pub(crate) debug_token: DebugToken,
 }
 
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
@@ -191,7 +352,7 @@ pub(crate) struct Propagation {
     /// Select a custom request header to set your own trace_id (header value must be convertible from hexadecimal to set a correct trace_id)
     pub(crate) request: RequestPropagation,
     /// Propagate baggage https://www.w3.org/TR/baggage/
-    pub(crate) baggage: bool,
+    pub(crate) baggage: BaggagePropagation,
     /// Propagate trace context https://www.w3.org/TR/trace-context/
     pub(crate) trace_context: bool,
     /// Propagate Jaeger
@@ -200,10 +361,47 @@ pub(crate) struct Propagation {
     pub(crate) datadog: bool,
     /// Propagate Zipkin
     pub(crate) zipkin: bool,
+    /// Also inject the Zipkin B3 multi headers (`X-B3-*`) along with the single `b3` header in
+    /// the requests to these subgraphs
+    pub(crate) zipkin_multi_header: SubgraphConfiguration<MultiHeaderPropagation>,
     /// Propagate AWS X-Ray
     pub(crate) aws_xray: bool,
 }
 
+/// Propagation of the Zipkin B3 multi headers to a subgraph
+#[derive(Clone, Default, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct MultiHeaderPropagation {
+    /// Inject the `X-B3-*` headers along with the single `b3` header
+    pub(crate) enabled: bool,
+}
+
+/// Baggage propagation, either a boolean or the entries sent by the router
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum BaggagePropagation {
+    /// Propagate the whole baggage
+    Enabled(bool),
+    /// Propagate the baggage, and only send the allowed entries in subgraph requests
+    Filtered(BaggageFilter),
+}
+
+impl Default for BaggagePropagation {
+    fn default() -> Self {
+        BaggagePropagation::Enabled(false)
+    }
+}
+
+/// The baggage entries sent by the router in subgraph requests
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct BaggageFilter {
+    /// Names of the baggage entries that are sent. All the entries are sent if not set
+    pub(crate) allow: Option<Vec<String>>,
+    /// Names of the baggage entries that are never sent
+    pub(crate) deny: Vec<String>,
+}
+
 #[derive(Clone, Debug, Deserialize, JsonSchema, Default)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct RequestPropagation {
@@ -225,6 +423,9 @@ pub(crate) struct TracingCommon {
     pub(crate) sampler: SamplerOption,
     /// Whether to use parent based sampling
     pub(crate) parent_based_sampler: bool,
+    /// Sampling rules, evaluated in order once the query plan of a request is known. The sampler
+    /// of the first matching rule is used instead of `sampler`
+    pub(crate) sampling_rules: Vec<SamplingRule>,
     /// The maximum events per span before discarding
     pub(crate) max_events_per_span: u32,
     /// The maximum attributes per span before discarding
@@ -278,6 +479,7 @@ impl Default for TracingCommon {
             service_namespace: Default::default(),
             sampler: default_sampler(),
             parent_based_sampler: default_parent_based_sampler(),
+            sampling_rules: Default::default(),
             max_events_per_span: default_max_events_per_span(),
             max_attributes_per_span: default_max_attributes_per_span(),
             max_links_per_span: default_max_links_per_span(),
@@ -459,6 +661,34 @@ pub(crate) enum SamplerOption {
     Always(Sampler),
 }
 
+impl SamplerOption {
+    /// The fraction of sampled requests, between 0 and 1
+    pub(crate) fn ratio(&self) -> f64 {
+        match self {
+            SamplerOption::TraceIdRatioBased(ratio) => ratio.min(1.0),
+            SamplerOption::Always(Sampler::AlwaysOn) => 1.0,
+            SamplerOption::Always(Sampler::AlwaysOff) => 0.0,
+        }
+    }
+}
+
+/// Samples the requests matching all of the configured conditions with a specific sampler
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SamplingRule {
+    /// Match the operation name with a regular expression
+    #[serde(default)]
+    pub(crate) operation_name: Option<String>,
+    /// Match the client name, as sent in the client name header
+    #[serde(default)]
+    pub(crate) client_name: Option<String>,
+    /// Match the requests whose query plan fetches from this subgraph
+    #[serde(default)]
+    pub(crate) subgraph: Option<String>,
+    /// The sampler of the matching requests, always_on, always_off or a decimal between 0.0 and 1.0
+    pub(crate) sampler: SamplerOption,
+}
+
 #[derive(Clone, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub(crate) enum Sampler {
@@ -514,60 +744,95 @@ fn parent_based(sampler: opentelemetry::sdk::trace::Sampler) -> opentelemetry::s
     opentelemetry::sdk::trace::Sampler::ParentBased(Box::new(sampler))
 }
 
+/// The ratios of sampled requests for which subgraphs are asked for ftv1 traces
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FieldLevelInstrumentationRatios {
+    /// The ratio of the subgraphs without a specific sampler
+    pub(crate) all: f64,
+    /// The ratios of the subgraphs with a specific sampler
+    pub(crate) subgraphs: HashMap<String, f64>,
+}
+
+impl FieldLevelInstrumentationRatios {
+    pub(crate) fn subgraph(&self, subgraph: &str) -> f64 {
+        self.subgraphs.get(subgraph).copied().unwrap_or(self.all)
+    }
+}
+
 impl Conf {
-    pub(crate) fn calculate_field_level_instrumentation_ratio(&self) -> Result<f64, Error> {
-        Ok(
-            match (
-                &self.exporters.tracing.common.sampler,
-                &self.apollo.field_level_instrumentation_sampler,
-            ) {
-                // Error conditions
-                (
-                    SamplerOption::TraceIdRatioBased(global_ratio),
-                    SamplerOption::TraceIdRatioBased(field_ratio),
-                ) if field_ratio > global_ratio => {
-                    Err(Error::InvalidFieldLevelInstrumentationSampler)?
-                }
-                (
-                    SamplerOption::Always(Sampler::AlwaysOff),
-                    SamplerOption::Always(Sampler::AlwaysOn),
-                ) => Err(Error::InvalidFieldLevelInstrumentationSampler)?,
-                (
-                    SamplerOption::Always(Sampler::AlwaysOff),
-                    SamplerOption::TraceIdRatioBased(ratio),
-                ) if *ratio != 0.0 => Err(Error::InvalidFieldLevelInstrumentationSampler)?,
-                (
-                    SamplerOption::TraceIdRatioBased(ratio),
-                    SamplerOption::Always(Sampler::AlwaysOn),
-                ) if *ratio != 1.0 => Err(Error::InvalidFieldLevelInstrumentationSampler)?,
-
-                // Happy paths
-                (_, SamplerOption::TraceIdRatioBased(ratio)) if *ratio == 0.0 => 0.0,
-                (SamplerOption::TraceIdRatioBased(ratio), _) if *ratio == 0.0 => 0.0,
-                (_, SamplerOption::Always(Sampler::AlwaysOn)) => 1.0,
-                // the `field_ratio` should be a ratio of the entire set of requests. But FTV1 would only be reported
-                // if a trace was generated with the Apollo exporter, which has its own sampling `global_ratio`.
-                // in telemetry::request_ftv1, we activate FTV1 if the current trace is sampled and depending on
-                // the ratio returned by this function.
-                // This means that:
-                // - field_ratio cannot be larger than global_ratio (see above, we return an error in that case)
-                // - we have to divide field_ratio by global_ratio
-                // Example: we want to measure FTV1 on 30% of total requests, but we the Apollo tracer samples at 50%.
-                // If we measure FTV1 on 60% (0.3 / 0.5) of these sampled requests, that amounts to 30% of the total traffic
-                (
-                    SamplerOption::TraceIdRatioBased(global_ratio),
-                    SamplerOption::TraceIdRatioBased(field_ratio),
-                ) => field_ratio / global_ratio,
-                (
-                    SamplerOption::Always(Sampler::AlwaysOn),
-                    SamplerOption::TraceIdRatioBased(field_ratio),
-                ) => *field_ratio,
-                (_, _) => 0.0,
-            },
-        )
+    pub(crate) fn calculate_field_level_instrumentation_ratios(
+        &self,
+    ) -> Result<FieldLevelInstrumentationRatios, Error> {
+        let global_sampler = &self.exporters.tracing.common.sampler;
+        let all = field_level_instrumentation_ratio(
+            global_sampler,
+            &self.apollo.field_level_instrumentation_sampler,
+        )?;
+        let subgraphs = self
+            .apollo
+            .field_level_instrumentation_subgraphs
+            .iter()
+            .map(|(subgraph, field_sampler)| {
+                field_level_instrumentation_ratio(global_sampler, field_sampler)
+                    .map(|ratio| (subgraph.clone(), ratio))
+                    .map_err(|_| {
+                        Error::InvalidSubgraphFieldLevelInstrumentationSampler(subgraph.clone())
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(FieldLevelInstrumentationRatios { all, subgraphs })
     }
 }
 
+fn field_level_instrumentation_ratio(
+    global_sampler: &SamplerOption,
+    field_sampler: &SamplerOption,
+) -> Result<f64, Error> {
+    Ok(match (global_sampler, field_sampler) {
+        // Error conditions
+        (
+            SamplerOption::TraceIdRatioBased(global_ratio),
+            SamplerOption::TraceIdRatioBased(field_ratio),
+        ) if field_ratio > global_ratio => Err(Error::InvalidFieldLevelInstrumentationSampler)?,
+        (SamplerOption::Always(Sampler::AlwaysOff), SamplerOption::Always(Sampler::AlwaysOn)) => {
+            Err(Error::InvalidFieldLevelInstrumentationSampler)?
+        }
+        (SamplerOption::Always(Sampler::AlwaysOff), SamplerOption::TraceIdRatioBased(ratio))
+            if *ratio != 0.0 =>
+        {
+            Err(Error::InvalidFieldLevelInstrumentationSampler)?
+        }
+        (SamplerOption::TraceIdRatioBased(ratio), SamplerOption::Always(Sampler::AlwaysOn))
+            if *ratio != 1.0 =>
+        {
+            Err(Error::InvalidFieldLevelInstrumentationSampler)?
+        }
+
+        // Happy paths
+        (_, SamplerOption::TraceIdRatioBased(ratio)) if *ratio == 0.0 => 0.0,
+        (SamplerOption::TraceIdRatioBased(ratio), _) if *ratio == 0.0 => 0.0,
+        (_, SamplerOption::Always(Sampler::AlwaysOn)) => 1.0,
+        // the `field_ratio` should be a ratio of the entire set of requests. But FTV1 would only be reported
+        // if a trace was generated with the Apollo exporter, which has its own sampling `global_ratio`.
+        // in telemetry::request_ftv1, we activate FTV1 if the current trace is sampled and depending on
+        // the ratio returned by this function.
+        // This means that:
+        // - field_ratio cannot be larger than global_ratio (see above, we return an error in that case)
+        // - we have to divide field_ratio by global_ratio
+        // Example: we want to measure FTV1 on 30% of total requests, but we the Apollo tracer samples at 50%.
+        // If we measure FTV1 on 60% (0.3 / 0.5) of these sampled requests, that amounts to 30% of the total traffic
+        (
+            SamplerOption::TraceIdRatioBased(global_ratio),
+            SamplerOption::TraceIdRatioBased(field_ratio),
+        ) => field_ratio / global_ratio,
+        (
+            SamplerOption::Always(Sampler::AlwaysOn),
+            SamplerOption::TraceIdRatioBased(field_ratio),
+        ) => *field_ratio,
+        (_, _) => 0.0,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use serde_json::json;
@@ -620,4 +885,71 @@ mod tests {
         AttributeValue::try_from(json!([1.1, true])).expect_err("mixed conversion must fail");
         AttributeValue::try_from(json!([true, "bar"])).expect_err("mixed conversion must fail");
     }
+
+    #[test]
+    fn test_subgraph_field_level_instrumentation_ratios() {
+        let config: Conf = serde_json::from_value(json!({
+            "apollo": {
+                "field_level_instrumentation_sampler": 0.25,
+                "field_level_instrumentation_subgraphs": {
+                    "products": 0.1,
+                    "reviews": "always_off"
+                }
+            },
+            "exporters": {
+                "tracing": {
+                    "common": {
+                        "sampler": 0.5
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let ratios = config
+            .calculate_field_level_instrumentation_ratios()
+            .unwrap();
+        assert_eq!(ratios.subgraph("accounts"), 0.5);
+        assert_eq!(ratios.subgraph("products"), 0.2);
+        assert_eq!(ratios.subgraph("reviews"), 0.0);
+
+        let config: Conf = serde_json::from_value(json!({
+            "apollo": {
+                "field_level_instrumentation_subgraphs": {
+                    "products": 0.8
+                }
+            },
+            "exporters": {
+                "tracing": {
+                    "common": {
+                        "sampler": 0.5
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            config
+                .calculate_field_level_instrumentation_ratios()
+                .unwrap_err()
+                .to_string(),
+            "field level instrumentation sampler of subgraph 'products' must sample less frequently than tracing level sampler"
+        );
+    }
+
+    #[test]
+    fn test_synthetic_traffic_header_is_stripped() {
+        let synthetic_traffic: SyntheticTraffic = serde_json::from_value(json!({
+            "header_name": "apollo-synthetic-traffic"
+        }))
+        .unwrap();
+        let mut headers = http::HeaderMap::new();
+        headers.insert("apollo-synthetic-traffic", "true".parse().unwrap());
+        headers.insert("x-custom", "value".parse().unwrap());
+
+        assert!(synthetic_traffic.take_synthetic(&mut headers));
+        assert!(!headers.contains_key("apollo-synthetic-traffic"));
+        assert!(headers.contains_key("x-custom"));
+        assert!(!synthetic_traffic.take_synthetic(&mut headers));
+        assert!(!SyntheticTraffic::default().take_synthetic(&mut headers));
+    }
 }
@@ -2,13 +2,17 @@ use std::collections::LinkedList;
 use std::fmt::Debug;
 use std::net::SocketAddr;
 
+use http::header::HeaderName;
 use http::header::CONTENT_LENGTH;
 use http::header::FORWARDED;
 use http::header::USER_AGENT;
+use http::HeaderMap;
 use http::StatusCode;
 use http::Uri;
+use opentelemetry::Array;
 use opentelemetry::Key;
 use opentelemetry::KeyValue;
+use opentelemetry::StringValue;
 use opentelemetry_semantic_conventions::trace::CLIENT_ADDRESS;
 use opentelemetry_semantic_conventions::trace::CLIENT_PORT;
 use opentelemetry_semantic_conventions::trace::GRAPHQL_DOCUMENT;
@@ -36,16 +40,22 @@ use serde::Serialize;
 use tower::BoxError;
 
 use crate::axum_factory::utils::ConnectionInfo;
+use crate::configuration::client_address::ClientAddress;
 use crate::context::OPERATION_KIND;
 use crate::context::OPERATION_NAME;
+use crate::plugin::serde::deserialize_vec_header_name;
 use crate::plugins::telemetry::config_new::trace_id;
 use crate::plugins::telemetry::config_new::DatadogId;
 use crate::plugins::telemetry::config_new::DefaultForLevel;
 use crate::plugins::telemetry::config_new::Selectors;
+use crate::query_planner::PlanSource;
+use crate::services::execution;
 use crate::services::router;
 use crate::services::router::Request;
 use crate::services::subgraph;
 use crate::services::supergraph;
+use crate::services::QueryPlannerContent;
+use crate::services::QueryPlannerResponse;
 
 pub(crate) const SUBGRAPH_NAME: Key = Key::from_static_str("subgraph.name");
 pub(crate) const SUBGRAPH_GRAPHQL_DOCUMENT: Key = Key::from_static_str("subgraph.graphql.document");
@@ -54,6 +64,13 @@ pub(crate) const SUBGRAPH_GRAPHQL_OPERATION_NAME: Key =
 pub(crate) const SUBGRAPH_GRAPHQL_OPERATION_TYPE: Key =
     Key::from_static_str("subgraph.graphql.operation.type");
 
+pub(crate) const QUERY_PLANNING_CACHE_HIT: Key = Key::from_static_str("query_planning.cache.hit");
+pub(crate) const QUERY_PLANNING_PLAN_REUSED: Key =
+    Key::from_static_str("query_planning.plan.reused");
+pub(crate) const QUERY_PLANNING_FETCH_NODES: Key =
+    Key::from_static_str("query_planning.fetch_nodes");
+pub(crate) const QUERY_PLANNING_DEFER: Key = Key::from_static_str("query_planning.defer");
+
 const ERROR_TYPE: Key = Key::from_static_str("error.type");
 
 const NETWORK_LOCAL_ADDRESS: Key = Key::from_static_str("network.local.address");
@@ -87,6 +104,30 @@ pub(crate) struct RouterAttributes {
     #[serde(rename = "trace_id")]
     trace_id: Option<bool>,
 
+    /// The request headers captured as `http.request.header.<name>` attributes, with the values
+    /// of each header as an array of strings.
+    /// Examples:
+    /// * [x-tenant, accept]
+    /// Requirement level: Opt-In
+    #[serde(
+        rename = "http.request.header",
+        deserialize_with = "deserialize_vec_header_name"
+    )]
+    #[schemars(with = "Vec<String>")]
+    http_request_header: Vec<HeaderName>,
+
+    /// The response headers captured as `http.response.header.<name>` attributes, with the values
+    /// of each header as an array of strings.
+    /// Examples:
+    /// * [content-type]
+    /// Requirement level: Opt-In
+    #[serde(
+        rename = "http.response.header",
+        deserialize_with = "deserialize_vec_header_name"
+    )]
+    #[schemars(with = "Vec<String>")]
+    http_response_header: Vec<HeaderName>,
+
     /// Http attributes from Open Telemetry semantic conventions.
     #[serde(flatten)]
     common: HttpCommonAttributes,
@@ -206,6 +247,77 @@ impl DefaultForLevel for SubgraphAttributes {
     }
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ExecutionAttributes {
+    /// The name of the operation being executed.
+    /// Examples:
+    /// * findBookById
+    /// Requirement level: Recommended
+    #[serde(rename = "graphql.operation.name")]
+    graphql_operation_name: Option<bool>,
+}
+
+impl DefaultForLevel for ExecutionAttributes {
+    fn defaults_for_level(&mut self, requirement_level: DefaultAttributeRequirementLevel) {
+        match requirement_level {
+            DefaultAttributeRequirementLevel::Required => {}
+            DefaultAttributeRequirementLevel::Recommended => {
+                if self.graphql_operation_name.is_none() {
+                    self.graphql_operation_name = Some(true);
+                }
+            }
+            DefaultAttributeRequirementLevel::None => {}
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct QueryPlanningAttributes {
+    /// Whether the query plan was found in the query plan cache.
+    /// Requirement level: Recommended
+    #[serde(rename = "query_planning.cache.hit")]
+    cache_hit: Option<bool>,
+    /// Whether the query plan was computed by a concurrent request for the same query.
+    /// Requirement level: Recommended
+    #[serde(rename = "query_planning.plan.reused")]
+    plan_reused: Option<bool>,
+    /// The number of fetch nodes in the query plan.
+    /// Requirement level: Recommended
+    #[serde(rename = "query_planning.fetch_nodes")]
+    fetch_nodes: Option<bool>,
+    /// Whether the query uses `@defer`.
+    /// Requirement level: Recommended
+    #[serde(rename = "query_planning.defer")]
+    defer: Option<bool>,
+}
+
+impl DefaultForLevel for QueryPlanningAttributes {
+    fn defaults_for_level(&mut self, requirement_level: DefaultAttributeRequirementLevel) {
+        match requirement_level {
+            DefaultAttributeRequirementLevel::Required => {}
+            DefaultAttributeRequirementLevel::Recommended => {
+                if self.cache_hit.is_none() {
+                    self.cache_hit = Some(true);
+                }
+                if self.plan_reused.is_none() {
+                    self.plan_reused = Some(true);
+                }
+                if self.fetch_nodes.is_none() {
+                    self.fetch_nodes = Some(true);
+                }
+                if self.defer.is_none() {
+                    self.defer = Some(true);
+                }
+            }
+            DefaultAttributeRequirementLevel::None => {}
+        }
+    }
+}
+
 /// Common attributes for http server and client.
 /// See https://opentelemetry.io/docs/specs/semconv/http/http-spans/#common-attributes
 #[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
@@ -545,6 +657,11 @@ impl Selectors for RouterAttributes {
                 ));
             }
         }
+        attrs.extend(captured_headers(
+            "http.request.header",
+            &self.http_request_header,
+            request.router_request.headers(),
+        ));
 
         attrs
     }
@@ -552,6 +669,11 @@ impl Selectors for RouterAttributes {
     fn on_response(&self, response: &router::Response) -> LinkedList<KeyValue> {
         let mut attrs = self.common.on_response(response);
         attrs.extend(self.server.on_response(response));
+        attrs.extend(captured_headers(
+            "http.response.header",
+            &self.http_response_header,
+            response.response.headers(),
+        ));
         attrs
     }
 
@@ -562,6 +684,44 @@ impl Selectors for RouterAttributes {
     }
 }
 
+/// The `<prefix>.<name>` attributes of the captured headers present in a header map, with all the
+/// values of each header, as recommended by the OpenTelemetry semantic conventions
+fn captured_headers<'a>(
+    prefix: &'a str,
+    names: &'a [HeaderName],
+    headers: &'a HeaderMap,
+) -> impl Iterator<Item = KeyValue> + 'a {
+    names.iter().filter_map(move |name| {
+        let values: Vec<StringValue> = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(|value| value.to_string().into())
+            .collect();
+        (!values.is_empty()).then(|| {
+            KeyValue::new(
+                format!("{prefix}.{name}"),
+                opentelemetry::Value::Array(Array::String(values)),
+            )
+        })
+    })
+}
+
+/// The value of a header as a typed attribute value: an integer, a float or a boolean if it parses
+/// as one, a string otherwise
+pub(crate) fn header_value(value: &http::HeaderValue) -> Option<opentelemetry::Value> {
+    let value = value.to_str().ok()?.trim();
+    Some(if let Ok(value) = value.parse::<i64>() {
+        value.into()
+    } else if let Some(value) = value.parse::<f64>().ok().filter(|value| value.is_finite()) {
+        value.into()
+    } else if let Ok(value) = value.parse::<bool>() {
+        value.into()
+    } else {
+        value.to_string().into()
+    })
+}
+
 impl Selectors for HttpCommonAttributes {
     type Request = router::Request;
     type Response = router::Response;
@@ -580,12 +740,9 @@ impl Selectors for HttpCommonAttributes {
                 .router_request
                 .headers()
                 .get(&CONTENT_LENGTH)
-                .and_then(|h| h.to_str().ok())
+                .and_then(header_value)
             {
-                attrs.push_back(KeyValue::new(
-                    HTTP_REQUEST_BODY_SIZE,
-                    content_length.to_string(),
-                ));
+                attrs.push_back(KeyValue::new(HTTP_REQUEST_BODY_SIZE, content_length));
             }
         }
         if let Some(true) = &self.network_protocol_name {
@@ -626,12 +783,9 @@ impl Selectors for HttpCommonAttributes {
                 .response
                 .headers()
                 .get(&CONTENT_LENGTH)
-                .and_then(|h| h.to_str().ok())
+                .and_then(header_value)
             {
-                attrs.push_back(KeyValue::new(
-                    HTTP_RESPONSE_BODY_SIZE,
-                    content_length.to_string(),
-                ));
+                attrs.push_back(KeyValue::new(HTTP_RESPONSE_BODY_SIZE, content_length));
             }
         }
         if let Some(true) = &self.http_response_status_code {
@@ -691,25 +845,13 @@ impl Selectors for HttpServerAttributes {
             ));
         }
         if let Some(true) = &self.client_address {
-            if let Some(forwarded) = Self::forwarded_for(request) {
-                attrs.push_back(KeyValue::new(CLIENT_ADDRESS, forwarded.ip().to_string()));
-            } else if let Some(connection_info) =
-                request.router_request.extensions().get::<ConnectionInfo>()
-            {
-                if let Some(socket) = connection_info.peer_address {
-                    attrs.push_back(KeyValue::new(CLIENT_ADDRESS, socket.ip().to_string()));
-                }
+            if let Some(client) = Self::client_address(request) {
+                attrs.push_back(KeyValue::new(CLIENT_ADDRESS, client.ip.to_string()));
             }
         }
         if let Some(true) = &self.client_port {
-            if let Some(forwarded) = Self::forwarded_for(request) {
-                attrs.push_back(KeyValue::new(CLIENT_PORT, forwarded.port() as i64));
-            } else if let Some(connection_info) =
-                request.router_request.extensions().get::<ConnectionInfo>()
-            {
-                if let Some(socket) = connection_info.peer_address {
-                    attrs.push_back(KeyValue::new(CLIENT_PORT, socket.port() as i64));
-                }
+            if let Some(port) = Self::client_address(request).and_then(|client| client.port) {
+                attrs.push_back(KeyValue::new(CLIENT_PORT, port as i64));
             }
         }
 
@@ -817,6 +959,20 @@ impl Selectors for HttpServerAttributes {
 }
 
 impl HttpServerAttributes {
+    /// The client address resolved through the trusted proxies if they are configured, otherwise
+    /// the address of the `Forwarded` header or of the TCP peer
+    fn client_address(request: &Request) -> Option<ClientAddress> {
+        let extensions = request.router_request.extensions();
+        extensions.get::<ClientAddress>().copied().or_else(|| {
+            let address = Self::forwarded_for(request)
+                .or_else(|| extensions.get::<ConnectionInfo>()?.peer_address)?;
+            Some(ClientAddress {
+                ip: address.ip(),
+                port: Some(address.port()),
+            })
+        })
+    }
+
     fn forwarded_for(request: &Request) -> Option<SocketAddr> {
         request
             .router_request
@@ -952,10 +1108,85 @@ impl Selectors for SubgraphAttributes {
     }
 }
 
+impl Selectors for ExecutionAttributes {
+    type Request = execution::Request;
+    type Response = execution::Response;
+
+    fn on_request(&self, request: &execution::Request) -> LinkedList<KeyValue> {
+        let mut attrs = LinkedList::new();
+        if let Some(true) = &self.graphql_operation_name {
+            if let Some(operation_name) = &request
+                .context
+                .get::<_, String>(OPERATION_NAME)
+                .unwrap_or_default()
+            {
+                attrs.push_back(KeyValue::new(
+                    GRAPHQL_OPERATION_NAME,
+                    operation_name.clone(),
+                ));
+            }
+        }
+
+        attrs
+    }
+
+    fn on_response(&self, _response: &execution::Response) -> LinkedList<KeyValue> {
+        LinkedList::default()
+    }
+
+    fn on_error(&self, _error: &BoxError) -> LinkedList<KeyValue> {
+        LinkedList::default()
+    }
+}
+
+impl QueryPlanningAttributes {
+    /// Attributes of the query planning span, known once the plan has been obtained
+    pub(crate) fn on_response(&self, response: &QueryPlannerResponse) -> LinkedList<KeyValue> {
+        let mut attrs = LinkedList::new();
+        let plan_source = response
+            .context
+            .private_entries
+            .lock()
+            .get::<PlanSource>()
+            .copied();
+        if let Some(plan_source) = plan_source {
+            if let Some(true) = &self.cache_hit {
+                attrs.push_back(KeyValue::new(
+                    QUERY_PLANNING_CACHE_HIT,
+                    plan_source == PlanSource::CacheHit,
+                ));
+            }
+            if let Some(true) = &self.plan_reused {
+                attrs.push_back(KeyValue::new(
+                    QUERY_PLANNING_PLAN_REUSED,
+                    plan_source == PlanSource::Reused,
+                ));
+            }
+        }
+        if let Some(QueryPlannerContent::Plan { plan }) = &response.content {
+            if let Some(true) = &self.fetch_nodes {
+                attrs.push_back(KeyValue::new(
+                    QUERY_PLANNING_FETCH_NODES,
+                    plan.subgraph_fetches() as i64,
+                ));
+            }
+            if let Some(true) = &self.defer {
+                attrs.push_back(KeyValue::new(
+                    QUERY_PLANNING_DEFER,
+                    plan.query.defer_stats.has_defer,
+                ));
+            }
+        }
+
+        attrs
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::net::SocketAddr;
     use std::str::FromStr;
+    use std::sync::Arc;
 
     use anyhow::anyhow;
     use http::header::FORWARDED;
@@ -969,7 +1200,10 @@ mod test {
     use opentelemetry::trace::TraceFlags;
     use opentelemetry::trace::TraceId;
     use opentelemetry::trace::TraceState;
+    use opentelemetry::Array;
     use opentelemetry::Context;
+    use opentelemetry::Key;
+    use opentelemetry::KeyValue;
     use opentelemetry_semantic_conventions::trace::CLIENT_ADDRESS;
     use opentelemetry_semantic_conventions::trace::CLIENT_PORT;
     use opentelemetry_semantic_conventions::trace::GRAPHQL_DOCUMENT;
@@ -995,11 +1229,16 @@ mod test {
     use tracing_subscriber::layer::SubscriberExt;
 
     use crate::axum_factory::utils::ConnectionInfo;
+    use crate::configuration::client_address::ClientAddress;
     use crate::context::OPERATION_KIND;
     use crate::context::OPERATION_NAME;
     use crate::graphql;
+    use crate::plugins::telemetry::config_new::attributes::header_value;
+    use crate::plugins::telemetry::config_new::attributes::DefaultAttributeRequirementLevel;
+    use crate::plugins::telemetry::config_new::attributes::ExecutionAttributes;
     use crate::plugins::telemetry::config_new::attributes::HttpCommonAttributes;
     use crate::plugins::telemetry::config_new::attributes::HttpServerAttributes;
+    use crate::plugins::telemetry::config_new::attributes::QueryPlanningAttributes;
     use crate::plugins::telemetry::config_new::attributes::RouterAttributes;
     use crate::plugins::telemetry::config_new::attributes::SubgraphAttributes;
     use crate::plugins::telemetry::config_new::attributes::SupergraphAttributes;
@@ -1008,14 +1247,27 @@ mod test {
     use crate::plugins::telemetry::config_new::attributes::NETWORK_LOCAL_PORT;
     use crate::plugins::telemetry::config_new::attributes::NETWORK_PEER_ADDRESS;
     use crate::plugins::telemetry::config_new::attributes::NETWORK_PEER_PORT;
+    use crate::plugins::telemetry::config_new::attributes::QUERY_PLANNING_CACHE_HIT;
+    use crate::plugins::telemetry::config_new::attributes::QUERY_PLANNING_DEFER;
+    use crate::plugins::telemetry::config_new::attributes::QUERY_PLANNING_FETCH_NODES;
+    use crate::plugins::telemetry::config_new::attributes::QUERY_PLANNING_PLAN_REUSED;
     use crate::plugins::telemetry::config_new::attributes::SUBGRAPH_GRAPHQL_DOCUMENT;
     use crate::plugins::telemetry::config_new::attributes::SUBGRAPH_GRAPHQL_OPERATION_NAME;
     use crate::plugins::telemetry::config_new::attributes::SUBGRAPH_GRAPHQL_OPERATION_TYPE;
     use crate::plugins::telemetry::config_new::attributes::SUBGRAPH_NAME;
+    use crate::plugins::telemetry::config_new::DefaultForLevel;
     use crate::plugins::telemetry::config_new::Selectors;
+    use crate::query_planner::fetch::FetchNode;
+    use crate::query_planner::fetch::OperationKind;
+    use crate::query_planner::PlanNode;
+    use crate::query_planner::PlanSource;
+    use crate::query_planner::QueryPlan;
+    use crate::services::execution;
     use crate::services::router;
     use crate::services::subgraph;
     use crate::services::supergraph;
+    use crate::services::QueryPlannerContent;
+    use crate::services::QueryPlannerResponse;
 
     #[test]
     fn test_router_trace_attributes() {
@@ -1037,8 +1289,7 @@ mod test {
             let attributes = RouterAttributes {
                 datadog_trace_id: Some(true),
                 trace_id: Some(true),
-                common: Default::default(),
-                server: Default::default(),
+                ..Default::default()
             };
             let attributes =
                 attributes.on_request(&router::Request::fake_builder().build().unwrap());
@@ -1061,6 +1312,53 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_router_captured_headers() {
+        let attributes: RouterAttributes = serde_json::from_value(serde_json::json!({
+            "http.request.header": ["x-tenant", "accept"],
+            "http.response.header": ["Content-Type"]
+        }))
+        .unwrap();
+        let request_attributes = attributes.on_request(
+            &router::Request::fake_builder()
+                .header("x-tenant", "acme")
+                .header("x-other", "ignored")
+                .header("accept", "application/json")
+                .header("accept", "multipart/mixed")
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(
+            request_attributes.into_iter().collect::<Vec<_>>(),
+            vec![
+                KeyValue::new(
+                    "http.request.header.x-tenant",
+                    opentelemetry::Value::Array(Array::String(vec!["acme".into()]))
+                ),
+                KeyValue::new(
+                    "http.request.header.accept",
+                    opentelemetry::Value::Array(Array::String(vec![
+                        "application/json".into(),
+                        "multipart/mixed".into()
+                    ]))
+                ),
+            ]
+        );
+        let response_attributes = attributes.on_response(
+            &router::Response::fake_builder()
+                .header("content-type", "application/json")
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(
+            response_attributes.into_iter().collect::<Vec<_>>(),
+            vec![KeyValue::new(
+                "http.response.header.content-type",
+                opentelemetry::Value::Array(Array::String(vec!["application/json".into()]))
+            )]
+        );
+    }
+
     #[test]
     fn test_supergraph_graphql_document() {
         let attributes = SupergraphAttributes {
@@ -1128,6 +1426,71 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_execution_graphql_operation_name() {
+        let attributes = ExecutionAttributes {
+            graphql_operation_name: Some(true),
+        };
+        let context = crate::Context::new();
+        let _ = context.insert(OPERATION_NAME, "topProducts".to_string());
+        let attributes =
+            attributes.on_request(&execution::Request::fake_builder().context(context).build());
+        assert_eq!(
+            attributes
+                .iter()
+                .find(|key_val| key_val.key == GRAPHQL_OPERATION_NAME)
+                .map(|key_val| &key_val.value),
+            Some(&"topProducts".into())
+        );
+    }
+
+    #[test]
+    fn test_query_planning_attributes() {
+        let mut attributes = QueryPlanningAttributes::default();
+        attributes.defaults_for_levels(DefaultAttributeRequirementLevel::Recommended);
+        let context = crate::Context::new();
+        context.private_entries.lock().insert(PlanSource::Reused);
+        let plan = QueryPlan::fake_builder()
+            .root(PlanNode::Parallel {
+                nodes: vec![fetch_node("products"), fetch_node("reviews")],
+            })
+            .build();
+        let attributes = attributes.on_response(
+            &QueryPlannerResponse::builder()
+                .content(QueryPlannerContent::Plan {
+                    plan: Arc::new(plan),
+                })
+                .context(context)
+                .build(),
+        );
+        let value = |key: Key| {
+            attributes
+                .iter()
+                .find(|key_val| key_val.key == key)
+                .map(|key_val| key_val.value.clone())
+        };
+        assert_eq!(value(QUERY_PLANNING_CACHE_HIT), Some(false.into()));
+        assert_eq!(value(QUERY_PLANNING_PLAN_REUSED), Some(true.into()));
+        assert_eq!(value(QUERY_PLANNING_FETCH_NODES), Some(2.into()));
+        assert_eq!(value(QUERY_PLANNING_DEFER), Some(false.into()));
+    }
+
+    fn fetch_node(service_name: &str) -> PlanNode {
+        PlanNode::Fetch(FetchNode {
+            service_name: service_name.to_string(),
+            requires: vec![],
+            variable_usages: vec![],
+            operation: "{ __typename }".to_string(),
+            operation_name: None,
+            operation_kind: OperationKind::Query,
+            id: None,
+            input_rewrites: None,
+            output_rewrites: None,
+            schema_aware_hash: Default::default(),
+            authorization: Default::default(),
+        })
+    }
+
     #[test]
     fn test_subgraph_graphql_document() {
         let attributes = SubgraphAttributes {
@@ -1305,7 +1668,7 @@ mod test {
                 .iter()
                 .find(|key_val| key_val.key == HTTP_REQUEST_BODY_SIZE)
                 .map(|key_val| &key_val.value),
-            Some(&"256".into())
+            Some(&256.into())
         );
     }
 
@@ -1330,10 +1693,21 @@ mod test {
                 .iter()
                 .find(|key_val| key_val.key == HTTP_RESPONSE_BODY_SIZE)
                 .map(|key_val| &key_val.value),
-            Some(&"256".into())
+            Some(&256.into())
         );
     }
 
+    #[test]
+    fn test_header_value() {
+        let value = |value: &'static str| header_value(&HeaderValue::from_static(value));
+        assert_eq!(value("256"), Some(256.into()));
+        assert_eq!(value(" -3 "), Some((-3).into()));
+        assert_eq!(value("0.5"), Some(0.5.into()));
+        assert_eq!(value("true"), Some(true.into()));
+        assert_eq!(value("NaN"), Some("NaN".into()));
+        assert_eq!(value("256 bytes"), Some("256 bytes".into()));
+    }
+
     #[test]
     fn test_http_common_request_method() {
         let common = HttpCommonAttributes {
@@ -1507,6 +1881,20 @@ mod test {
                 .map(|key_val| &key_val.value),
             Some(&"2.4.6.8".into())
         );
+
+        // The address resolved through trusted proxies takes precedence
+        req.router_request.extensions_mut().insert(ClientAddress {
+            ip: "4.6.8.10".parse().unwrap(),
+            port: Some(9000),
+        });
+        let attributes = server.on_request(&req);
+        assert_eq!(
+            attributes
+                .iter()
+                .find(|key_val| key_val.key == CLIENT_ADDRESS)
+                .map(|key_val| &key_val.value),
+            Some(&"4.6.8.10".into())
+        );
     }
 
     #[test]
@@ -1546,6 +1934,20 @@ mod test {
                 .map(|key_val| &key_val.value),
             Some(&8000.into())
         );
+
+        // The address resolved through trusted proxies takes precedence
+        req.router_request.extensions_mut().insert(ClientAddress {
+            ip: "4.6.8.10".parse().unwrap(),
+            port: Some(9000),
+        });
+        let attributes = server.on_request(&req);
+        assert_eq!(
+            attributes
+                .iter()
+                .find(|key_val| key_val.key == CLIENT_PORT)
+                .map(|key_val| &key_val.value),
+            Some(&9000.into())
+        );
     }
 
     #[test]
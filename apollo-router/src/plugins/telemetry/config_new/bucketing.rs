@@ -0,0 +1,90 @@
+//! Deterministic bucketing of values, so that the same value (a header, a client name...) always
+//! lands in the same bucket. It is shared by the bucket redaction of selectors and the bucket
+//! condition, to make canary routing, sampling and feature flags agree with each other.
+use std::num::NonZeroU64;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::Digest;
+
+/// Assign a value to one of a number of buckets, from a salted hash of the value.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Bucketing {
+    /// The number of buckets. Values are assigned a bucket between 0 and this number minus 1.
+    pub(crate) buckets: NonZeroU64,
+    /// A salt mixed into the hash, to assign values independently for different purposes.
+    #[serde(default)]
+    pub(crate) salt: String,
+}
+
+impl Bucketing {
+    pub(crate) fn bucket(&self, value: &str) -> u64 {
+        bucket(&self.salt, value, self.buckets.get())
+    }
+}
+
+/// A hash of the salt and the value, uniformly distributed over `u64`
+fn hash(salt: &str, value: &str) -> u64 {
+    let mut digest = sha2::Sha256::new();
+    digest.update(salt.as_bytes());
+    // separate the salt from the value so that ("ab", "c") and ("a", "bc") do not collide
+    digest.update([0]);
+    digest.update(value.as_bytes());
+    let digest = digest.finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+/// The bucket of a value, between 0 and `buckets - 1`
+pub(crate) fn bucket(salt: &str, value: &str, buckets: u64) -> u64 {
+    ((hash(salt, value) as u128 * buckets as u128) >> 64) as u64
+}
+
+/// Whether a value falls within the first `ratio` of the values, with `ratio` between 0 and 1.
+///
+/// This is consistent with [`bucket`]: with the same salt, the values in the buckets lower than
+/// `k` out of `n` are the values within a ratio of `k / n`.
+pub(crate) fn in_ratio(salt: &str, value: &str, ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    (hash(salt, value) as f64) < ratio * 2f64.powi(64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buckets_are_deterministic_and_salted() {
+        let values: Vec<String> = (0..1000).map(|i| format!("user-{i}")).collect();
+        let buckets = |salt: &str| -> Vec<u64> {
+            values.iter().map(|value| bucket(salt, value, 10)).collect()
+        };
+
+        assert_eq!(buckets("a"), buckets("a"));
+        assert_ne!(buckets("a"), buckets("b"));
+        assert!(buckets("a").iter().all(|bucket| *bucket < 10));
+
+        let mut counts = [0; 10];
+        for bucket in buckets("a") {
+            counts[bucket as usize] += 1;
+        }
+        assert!(counts.iter().all(|count| (50..150).contains(count)));
+    }
+
+    #[test]
+    fn ratios_agree_with_buckets() {
+        for i in 0..1000 {
+            let value = format!("user-{i}");
+            assert_eq!(
+                in_ratio("salt", &value, 0.3),
+                bucket("salt", &value, 10) < 3,
+            );
+            assert!(!in_ratio("salt", &value, 0.0));
+            assert!(in_ratio("salt", &value, 1.0));
+        }
+    }
+}
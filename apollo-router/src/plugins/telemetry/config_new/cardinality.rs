@@ -0,0 +1,160 @@
+//! Cardinality limits of custom instruments.
+//!
+//! Each distinct attribute set of an instrument is a separate series in the metrics backends, so
+//! attributes such as operation names or client ids can create an unbounded number of series. Once
+//! an instrument reached its limit, the measurements with new attribute sets are recorded with
+//! every attribute value replaced by `other`.
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use dashmap::DashSet;
+use itertools::Itertools;
+use opentelemetry::KeyValue;
+
+pub(crate) const DEFAULT_CARDINALITY_LIMIT: usize = 2000;
+const OVERFLOW_VALUE: &str = "other";
+
+/// Tracks the distinct attribute sets recorded by an instrument
+pub(crate) struct CardinalityLimiter {
+    /// Maximum number of distinct attribute sets, 0 for no limit
+    limit: usize,
+    seen: DashSet<u64>,
+    /// Number of attribute sets in `seen`, reserved before they are inserted
+    count: AtomicUsize,
+    overflowed: AtomicBool,
+}
+
+impl CardinalityLimiter {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            seen: Default::default(),
+            count: AtomicUsize::new(0),
+            overflowed: AtomicBool::new(false),
+        }
+    }
+
+    /// The attributes to record a measurement with: the attributes themselves if their set is
+    /// known or if the limit is not reached, the overflow set otherwise
+    pub(crate) fn limit<'a>(
+        &self,
+        instrument: &str,
+        attributes: &'a [KeyValue],
+    ) -> Cow<'a, [KeyValue]> {
+        if self.limit == 0 || attributes.is_empty() {
+            return Cow::Borrowed(attributes);
+        }
+        let key = attribute_set_key(attributes);
+        if self.seen.contains(&key) {
+            return Cow::Borrowed(attributes);
+        }
+        let reserved = self
+            .count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < self.limit).then_some(count + 1)
+            })
+            .is_ok();
+        if reserved {
+            // Another record may have inserted the same set in the meantime
+            if !self.seen.insert(key) {
+                self.count.fetch_sub(1, Ordering::Relaxed);
+            }
+            return Cow::Borrowed(attributes);
+        }
+
+        if !self.overflowed.swap(true, Ordering::Relaxed) {
+            tracing::warn!(
+                "instrument '{instrument}' reached its limit of {} distinct attribute sets, new attribute values are recorded as '{OVERFLOW_VALUE}'",
+                self.limit
+            );
+        }
+        u64_counter!(
+            "apollo_router_metrics_cardinality_overflow_total",
+            "Number of measurements recorded with overflow attributes because their instrument reached its cardinality limit",
+            1,
+            instrument = instrument.to_string()
+        );
+        Cow::Owned(
+            attributes
+                .iter()
+                .map(|kv| KeyValue::new(kv.key.clone(), OVERFLOW_VALUE))
+                .collect(),
+        )
+    }
+}
+
+/// Attribute sets are the same regardless of the order of their attributes
+fn attribute_set_key(attributes: &[KeyValue]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for kv in attributes.iter().sorted_by(|a, b| a.key.cmp(&b.key)) {
+        kv.key.as_str().hash(&mut hasher);
+        kv.value.as_str().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::metrics::FutureMetricsExt;
+
+    #[tokio::test]
+    async fn it_collapses_the_attribute_sets_beyond_the_limit() {
+        async {
+            let limiter = CardinalityLimiter::new(2);
+            let attributes = |client: &str| {
+                vec![
+                    KeyValue::new("client", client.to_string()),
+                    KeyValue::new("status", 200),
+                ]
+            };
+
+            assert_eq!(
+                limiter.limit("acme.requests", &attributes("a")).as_ref(),
+                attributes("a").as_slice()
+            );
+            assert_eq!(
+                limiter.limit("acme.requests", &attributes("b")).as_ref(),
+                attributes("b").as_slice()
+            );
+            assert_eq!(
+                limiter.limit("acme.requests", &attributes("c")).as_ref(),
+                &[
+                    KeyValue::new("client", "other"),
+                    KeyValue::new("status", "other")
+                ]
+            );
+            // Known attribute sets are still recorded, in any order
+            let mut reversed = attributes("a");
+            reversed.reverse();
+            assert_eq!(
+                limiter.limit("acme.requests", &reversed).as_ref(),
+                reversed.as_slice()
+            );
+            assert_counter!(
+                "apollo_router_metrics_cardinality_overflow_total",
+                1,
+                "instrument" = "acme.requests"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[test]
+    fn it_does_not_limit_without_a_limit() {
+        let limiter = CardinalityLimiter::new(0);
+        for client in 0..10 {
+            let attributes = [KeyValue::new("client", client)];
+            assert_eq!(
+                limiter.limit("acme.requests", &attributes).as_ref(),
+                &attributes
+            );
+        }
+    }
+}
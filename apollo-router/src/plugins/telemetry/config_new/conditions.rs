@@ -1,8 +1,11 @@
+use std::time::Duration;
+
 use opentelemetry::Value;
 use schemars::JsonSchema;
 use serde::Deserialize;
 
 use crate::plugins::telemetry::config::AttributeValue;
+use crate::plugins::telemetry::config_new::bucketing::in_ratio;
 use crate::plugins::telemetry::config_new::Selector;
 
 #[allow(dead_code)]
@@ -11,6 +14,13 @@ use crate::plugins::telemetry::config_new::Selector;
 pub(crate) enum Condition<T> {
     /// A condition to check a selection against a value.
     Eq([SelectorOrValue<T>; 2]),
+    /// A condition to check that a numeric selection is greater than a value.
+    Gt([SelectorOrValue<T>; 2]),
+    /// A condition to check that a numeric selection is less than a value.
+    Lt([SelectorOrValue<T>; 2]),
+    /// A condition to check that a selection falls within a deterministic ratio of the values,
+    /// for canary routing, sampling or feature flags.
+    Bucket(BucketCondition<T>),
     /// All sub-conditions must be true.
     All(Vec<Condition<T>>),
     /// At least one sub-conditions must be true.
@@ -19,9 +29,51 @@ pub(crate) enum Condition<T> {
     Not(Box<Condition<T>>),
 }
 
+/// A selection is kept when its salted hash falls within the first `ratio` of the hashes, so the
+/// same value is always kept or always dropped.
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BucketCondition<T> {
+    /// The selection to assign to a bucket.
+    selector: SelectorOrValue<T>,
+    /// The ratio of the values for which the condition is true, between 0 and 1.
+    ratio: f64,
+    /// A salt mixed into the hash, to assign values independently for different purposes. Use
+    /// the salt of a bucket redaction to agree with it.
+    #[serde(default)]
+    salt: String,
+}
+
+impl<T> BucketCondition<T> {
+    fn matches(&self, value: Option<Value>) -> bool {
+        value.map_or(false, |value| {
+            in_ratio(&self.salt, &value.as_str(), self.ratio)
+        })
+    }
+}
+
 impl Condition<()> {
+    /// A condition that is always true
     pub(crate) fn empty<T>() -> Condition<T> {
-        Condition::Any(vec![])
+        Condition::All(vec![])
+    }
+}
+
+impl<T> Condition<T> {
+    /// Returns true if one of the selectors of the condition matches the predicate
+    pub(crate) fn any_selector(&self, predicate: &impl Fn(&T) -> bool) -> bool {
+        match self {
+            Condition::Eq(arguments) | Condition::Gt(arguments) | Condition::Lt(arguments) => {
+                arguments
+                    .iter()
+                    .any(|argument| argument.is_selector(predicate))
+            }
+            Condition::Bucket(bucket) => bucket.selector.is_selector(predicate),
+            Condition::All(conditions) | Condition::Any(conditions) => conditions
+                .iter()
+                .any(|condition| condition.any_selector(predicate)),
+            Condition::Not(condition) => condition.any_selector(predicate),
+        }
     }
 }
 
@@ -35,28 +87,124 @@ pub(crate) enum SelectorOrValue<T> {
     Selector(T),
 }
 
-#[allow(dead_code)]
+impl<T> SelectorOrValue<T> {
+    fn is_selector(&self, predicate: &impl Fn(&T) -> bool) -> bool {
+        matches!(self, SelectorOrValue::Selector(selector) if predicate(selector))
+    }
+}
+
 impl<T> Condition<T>
 where
     T: Selector,
 {
-    fn evaluate(&self, request: &T::Request, response: &T::Response) -> bool {
+    /// Evaluates the condition on the request. The selectors that return a value are replaced
+    /// with it, so that the rest of the condition can be evaluated on the response.
+    /// Returns `None` if the condition depends on the response.
+    pub(crate) fn evaluate_request(&mut self, request: &T::Request) -> Option<bool> {
+        match self {
+            Condition::Eq(eq) => resolve_request(eq, request).map(|(left, right)| left == right),
+            Condition::Gt(gt) => resolve_request(gt, request).map(|(left, right)| {
+                greater_than(Some(left.clone().into()), Some(right.clone().into()))
+            }),
+            Condition::Lt(lt) => resolve_request(lt, request).map(|(left, right)| {
+                greater_than(Some(right.clone().into()), Some(left.clone().into()))
+            }),
+            Condition::Bucket(bucket) => {
+                if let SelectorOrValue::Selector(selector) = &bucket.selector {
+                    if let Some(value) = selector.on_request(request) {
+                        bucket.selector = SelectorOrValue::Value(value.into());
+                    }
+                }
+                match &bucket.selector {
+                    SelectorOrValue::Value(value) => {
+                        Some(bucket.matches(Some(value.clone().into())))
+                    }
+                    SelectorOrValue::Selector(_) => None,
+                }
+            }
+            Condition::All(all) => {
+                let results: Vec<_> = all
+                    .iter_mut()
+                    .map(|c| c.evaluate_request(request))
+                    .collect();
+                if results.contains(&Some(false)) {
+                    Some(false)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(true)
+                }
+            }
+            Condition::Any(any) => {
+                let results: Vec<_> = any
+                    .iter_mut()
+                    .map(|c| c.evaluate_request(request))
+                    .collect();
+                if results.contains(&Some(true)) {
+                    Some(true)
+                } else if results.contains(&None) {
+                    None
+                } else {
+                    Some(false)
+                }
+            }
+            Condition::Not(not) => not.evaluate_request(request).map(|result| !result),
+        }
+    }
+
+    /// Evaluates the rest of the condition on the response, after [`Self::evaluate_request`]
+    pub(crate) fn evaluate_response(&self, response: &T::Response) -> bool {
+        self.evaluate_with(&|side| side.on_response(response))
+    }
+
+    /// Evaluates the rest of the condition when the service failed after `elapsed`, after
+    /// [`Self::evaluate_request`]. Selectors of the response have no value, except the elapsed time
+    pub(crate) fn evaluate_error(&self, elapsed: Duration) -> bool {
+        self.evaluate_with(&|side| side.on_elapsed(elapsed))
+    }
+
+    fn evaluate_with(&self, value: &dyn Fn(&SelectorOrValue<T>) -> Option<Value>) -> bool {
         match self {
-            Condition::Eq(eq) => {
-                // We don't know if the selection was for the request or result, so we try both.
-                let left = eq[0]
-                    .on_request(request)
-                    .or_else(|| eq[0].on_response(response));
-                let right = eq[1]
-                    .on_request(request)
-                    .or_else(|| eq[1].on_response(response));
-                left == right
+            Condition::Eq(eq) => value(&eq[0]) == value(&eq[1]),
+            Condition::Gt(gt) => greater_than(value(&gt[0]), value(&gt[1])),
+            Condition::Lt(lt) => greater_than(value(&lt[1]), value(&lt[0])),
+            Condition::Bucket(bucket) => bucket.matches(value(&bucket.selector)),
+            Condition::All(all) => all.iter().all(|c| c.evaluate_with(value)),
+            Condition::Any(any) => any.iter().any(|c| c.evaluate_with(value)),
+            Condition::Not(not) => !not.evaluate_with(value),
+        }
+    }
+}
+
+/// Replaces the selectors of a comparison that return a value on the request with it, and
+/// returns both sides once they are values
+fn resolve_request<'a, T: Selector>(
+    sides: &'a mut [SelectorOrValue<T>; 2],
+    request: &T::Request,
+) -> Option<(&'a AttributeValue, &'a AttributeValue)> {
+    for side in sides.iter_mut() {
+        if let SelectorOrValue::Selector(selector) = side {
+            if let Some(value) = selector.on_request(request) {
+                *side = SelectorOrValue::Value(value.into());
             }
-            Condition::All(all) => all.iter().all(|c| c.evaluate(request, response)),
-            Condition::Any(any) => any.iter().any(|c| c.evaluate(request, response)),
-            Condition::Not(not) => !not.evaluate(request, response),
         }
     }
+    match sides {
+        [SelectorOrValue::Value(left), SelectorOrValue::Value(right)] => Some((left, right)),
+        _ => None,
+    }
+}
+
+/// Whether both values are numbers and the left one is greater
+fn greater_than(left: Option<Value>, right: Option<Value>) -> bool {
+    fn number(value: Option<Value>) -> Option<f64> {
+        match value? {
+            Value::I64(value) => Some(value as f64),
+            Value::F64(value) => Some(value),
+            _ => None,
+        }
+    }
+    matches!((number(left), number(right)), (Some(left), Some(right)) if left > right)
 }
 
 impl<T> Selector for SelectorOrValue<T>
@@ -79,14 +227,28 @@ where
             SelectorOrValue::Selector(selector) => selector.on_response(response),
         }
     }
+
+    fn on_elapsed(&self, elapsed: Duration) -> Option<Value> {
+        match self {
+            SelectorOrValue::Value(value) => Some(value.clone().into()),
+            SelectorOrValue::Selector(selector) => selector.on_elapsed(elapsed),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::time::Duration;
+
     use opentelemetry::Value;
 
+    use crate::plugins::telemetry::config::AttributeValue;
+    use crate::plugins::telemetry::config_new::bucketing::bucket;
+    use crate::plugins::telemetry::config_new::conditions::BucketCondition;
     use crate::plugins::telemetry::config_new::conditions::Condition;
     use crate::plugins::telemetry::config_new::conditions::SelectorOrValue;
+    use crate::plugins::telemetry::config_new::selectors::Elapsed;
+    use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
     use crate::plugins::telemetry::config_new::Selector;
 
     struct TestSelector;
@@ -103,6 +265,14 @@ mod test {
         }
     }
 
+    impl Condition<TestSelector> {
+        /// Evaluates the condition the way instruments do, on the request then on the response
+        fn evaluate(mut self, request: &Option<i64>, response: &Option<i64>) -> bool {
+            self.evaluate_request(request)
+                .unwrap_or_else(|| self.evaluate_response(response))
+        }
+    }
+
     #[test]
     fn test_condition_eq() {
         assert!(Condition::<TestSelector>::Eq([
@@ -168,6 +338,122 @@ mod test {
         .evaluate(&None, &None));
     }
 
+    #[test]
+    fn test_condition_request_then_response() {
+        // Resolved on the request
+        let mut condition = Condition::<TestSelector>::Eq([
+            SelectorOrValue::Selector(TestSelector),
+            SelectorOrValue::Value(1i64.into()),
+        ]);
+        assert_eq!(condition.evaluate_request(&Some(1i64)), Some(true));
+        assert!(condition.evaluate_response(&Some(2i64)));
+
+        // Resolved on the response
+        let mut condition = Condition::<TestSelector>::Eq([
+            SelectorOrValue::Selector(TestSelector),
+            SelectorOrValue::Value(2i64.into()),
+        ]);
+        assert_eq!(condition.evaluate_request(&None), None);
+        assert!(condition.evaluate_response(&Some(2i64)));
+        assert!(!condition.evaluate_error(Duration::ZERO));
+
+        let mut condition = Condition::<TestSelector>::All(vec![
+            Condition::Eq([
+                SelectorOrValue::Selector(TestSelector),
+                SelectorOrValue::Value(1i64.into()),
+            ]),
+            Condition::Not(Box::new(Condition::Eq([
+                SelectorOrValue::Selector(TestSelector),
+                SelectorOrValue::Value(1i64.into()),
+            ]))),
+        ]);
+        assert_eq!(condition.evaluate_request(&Some(1i64)), Some(false));
+
+        assert_eq!(
+            Condition::empty::<TestSelector>().evaluate_request(&None),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_condition_gt_lt() {
+        assert!(Condition::<TestSelector>::Gt([
+            SelectorOrValue::Selector(TestSelector),
+            SelectorOrValue::Value(AttributeValue::F64(500.0)),
+        ])
+        .evaluate(&None, &Some(501i64)));
+        assert!(!Condition::<TestSelector>::Gt([
+            SelectorOrValue::Selector(TestSelector),
+            SelectorOrValue::Value(500i64.into()),
+        ])
+        .evaluate(&None, &Some(500i64)));
+        assert!(Condition::<TestSelector>::Lt([
+            SelectorOrValue::Selector(TestSelector),
+            SelectorOrValue::Value(500i64.into()),
+        ])
+        .evaluate(&None, &Some(499i64)));
+        // Missing and non numeric values are never greater or less than a value
+        assert!(!Condition::<TestSelector>::Lt([
+            SelectorOrValue::Selector(TestSelector),
+            SelectorOrValue::Value(500i64.into()),
+        ])
+        .evaluate(&None, &None));
+        assert!(!Condition::<TestSelector>::Gt([
+            SelectorOrValue::Value("b".into()),
+            SelectorOrValue::Value("a".into()),
+        ])
+        .evaluate(&None, &None));
+
+        // Resolved on the response
+        let mut condition = Condition::<TestSelector>::Gt([
+            SelectorOrValue::Selector(TestSelector),
+            SelectorOrValue::Value(500i64.into()),
+        ]);
+        assert_eq!(condition.evaluate_request(&None), None);
+        assert!(condition.evaluate_response(&Some(600i64)));
+        assert!(!condition.evaluate_error(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_condition_elapsed_on_error() {
+        let condition = Condition::<SubgraphSelector>::Gt([
+            SelectorOrValue::Selector(SubgraphSelector::Elapsed {
+                elapsed: Elapsed::Milliseconds,
+            }),
+            SelectorOrValue::Value(500i64.into()),
+        ]);
+        assert!(condition.evaluate_error(Duration::from_millis(600)));
+        assert!(!condition.evaluate_error(Duration::from_millis(400)));
+    }
+
+    #[test]
+    fn test_condition_bucket() {
+        let condition = |ratio: f64| {
+            Condition::<TestSelector>::Bucket(BucketCondition {
+                selector: SelectorOrValue::Selector(TestSelector),
+                ratio,
+                salt: "canary".to_string(),
+            })
+        };
+        for value in 0..100i64 {
+            // Consistent with the buckets of the same salt
+            assert_eq!(
+                condition(0.25).evaluate(&Some(value), &None),
+                bucket("canary", &value.to_string(), 4) == 0
+            );
+            assert!(condition(1.0).evaluate(&Some(value), &None));
+            assert!(!condition(0.0).evaluate(&Some(value), &None));
+        }
+        // Missing values are never selected
+        assert!(!condition(1.0).evaluate(&None, &None));
+
+        // Resolved on the response
+        let mut condition = condition(1.0);
+        assert_eq!(condition.evaluate_request(&None), None);
+        assert!(condition.evaluate_response(&Some(1i64)));
+        assert!(!condition.evaluate_error(Duration::ZERO));
+    }
+
     #[test]
     fn test_condition_all() {
         assert!(Condition::<TestSelector>::All(vec![
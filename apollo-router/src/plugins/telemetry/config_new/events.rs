@@ -1,7 +1,13 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Instant;
 
+use opentelemetry::KeyValue;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
 
 use crate::plugins::telemetry::config_new::attributes::RouterAttributes;
 use crate::plugins::telemetry::config_new::attributes::SubgraphAttributes;
@@ -11,21 +17,25 @@ use crate::plugins::telemetry::config_new::extendable::Extendable;
 use crate::plugins::telemetry::config_new::selectors::RouterSelector;
 use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
 use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
+use crate::plugins::telemetry::config_new::Selector;
+use crate::plugins::telemetry::config_new::Selectors;
+use crate::plugins::telemetry::debug_token::is_forced;
+use crate::plugins::telemetry::dynamic_attribute::EventAttributes;
+use crate::plugins::telemetry::kafka_events::EventExporter;
+use crate::Context;
 
-/// Events are
-#[allow(dead_code)]
+/// Events are structured logs emitted by the router, supergraph and subgraph services
 #[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
 #[serde(deny_unknown_fields, default)]
 pub(crate) struct Events {
     /// Router service events
     router: Extendable<RouterEvents, Event<RouterAttributes, RouterSelector>>,
-    /// Subgraph service events
-    supergraph: Extendable<SupergraphEvents, Event<SupergraphAttributes, SupergraphSelector>>,
     /// Supergraph service events
+    supergraph: Extendable<SupergraphEvents, Event<SupergraphAttributes, SupergraphSelector>>,
+    /// Subgraph service events
     subgraph: Extendable<SubgraphEvents, Event<SubgraphAttributes, SubgraphSelector>>,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
 struct RouterEvents {
@@ -37,7 +47,6 @@ struct RouterEvents {
     error: EventLevel,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
 struct SupergraphEvents {
@@ -49,7 +58,6 @@ struct SupergraphEvents {
     error: EventLevel,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
 struct SubgraphEvents {
@@ -61,8 +69,7 @@ struct SubgraphEvents {
     error: EventLevel,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Debug, Default, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum EventLevel {
     Info,
@@ -75,8 +82,8 @@ pub(crate) enum EventLevel {
 /// An event that can be logged as part of a trace.
 /// The event has an implicit `type` attribute that matches the name of the event in the yaml
 /// and a message that can be used to provide additional information.
-#[allow(dead_code)]
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Event<A, E>
 where
     A: Default + Debug,
@@ -98,11 +105,25 @@ where
     /// The event conditions.
     #[serde(default = "Condition::empty::<E>")]
     condition: Condition<E>,
+
+    /// The partitioning key of the event when events are exported to Kafka.
+    key: Option<E>,
+}
+
+impl<A, E> Event<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    fn selects(&self, predicate: &impl Fn(&E) -> bool) -> bool {
+        self.attributes.custom.values().any(predicate)
+            || self.condition.any_selector(predicate)
+            || self.key.as_ref().is_some_and(predicate)
+    }
 }
 
 /// When to trigger the event.
-#[allow(dead_code)]
-#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[derive(Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum EventOn {
     /// Log the event on request
@@ -112,3 +133,430 @@ pub(crate) enum EventOn {
     /// Log the event on error
     Error,
 }
+
+impl Events {
+    pub(crate) fn new_router_events(&self) -> CustomEvents<RouterAttributes, RouterSelector> {
+        let standard = &self.router.attributes;
+        CustomEvents::new(
+            StandardEvents {
+                service: "router",
+                request: standard.request,
+                response: standard.response,
+                error: standard.error,
+            },
+            &self.router.custom,
+        )
+    }
+
+    pub(crate) fn new_supergraph_events(
+        &self,
+    ) -> CustomEvents<SupergraphAttributes, SupergraphSelector> {
+        let standard = &self.supergraph.attributes;
+        CustomEvents::new(
+            StandardEvents {
+                service: "supergraph",
+                request: standard.request,
+                response: standard.response,
+                error: standard.error,
+            },
+            &self.supergraph.custom,
+        )
+    }
+
+    /// Returns true if a custom supergraph event uses a selector matching the predicate
+    pub(crate) fn supergraph_selects(
+        &self,
+        predicate: impl Fn(&SupergraphSelector) -> bool,
+    ) -> bool {
+        self.supergraph
+            .custom
+            .values()
+            .any(|event| event.selects(&predicate))
+    }
+
+    pub(crate) fn new_subgraph_events(&self) -> CustomEvents<SubgraphAttributes, SubgraphSelector> {
+        let standard = &self.subgraph.attributes;
+        CustomEvents::new(
+            StandardEvents {
+                service: "subgraph",
+                request: standard.request,
+                response: standard.response,
+                error: standard.error,
+            },
+            &self.subgraph.custom,
+        )
+    }
+}
+
+/// The levels of the standard events of a service
+#[derive(Clone, Copy)]
+struct StandardEvents {
+    service: &'static str,
+    request: EventLevel,
+    response: EventLevel,
+    error: EventLevel,
+}
+
+/// The standard and custom events of a service
+pub(crate) struct CustomEvents<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    standard: StandardEvents,
+    events: Arc<Vec<(String, Event<A, E>)>>,
+    exporter: Option<Arc<EventExporter>>,
+}
+
+impl<A, E> Clone for CustomEvents<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    fn clone(&self) -> Self {
+        Self {
+            standard: self.standard,
+            events: self.events.clone(),
+            exporter: self.exporter.clone(),
+        }
+    }
+}
+
+impl<A, E> CustomEvents<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    /// Also exports the events to Kafka
+    pub(crate) fn with_exporter(mut self, exporter: Option<Arc<EventExporter>>) -> Self {
+        self.exporter = exporter;
+        self
+    }
+
+    /// The events of a request: with a valid debug token, the standard events that are off are
+    /// logged too
+    pub(crate) fn for_context(&self, context: &Context) -> Self {
+        let mut events = self.clone();
+        if !is_forced(context) {
+            return events;
+        }
+        let standard = &mut events.standard;
+        for (level, forced) in [
+            (&mut standard.request, EventLevel::Info),
+            (&mut standard.response, EventLevel::Info),
+            (&mut standard.error, EventLevel::Error),
+        ] {
+            if *level == EventLevel::Off {
+                *level = forced;
+            }
+        }
+        events
+    }
+
+    fn log_standard(&self, level: EventLevel, stage: &str, attributes: Vec<KeyValue>) {
+        let service = self.standard.service;
+        self.emit(
+            level,
+            &format!("{service}.{stage}"),
+            attributes,
+            &format!("{service} {stage}"),
+            None,
+        );
+    }
+
+    fn emit(
+        &self,
+        level: EventLevel,
+        kind: &str,
+        attributes: Vec<KeyValue>,
+        message: &str,
+        key: Option<opentelemetry::Value>,
+    ) {
+        if level == EventLevel::Off {
+            return;
+        }
+        if let Some(exporter) = &self.exporter {
+            exporter.export(
+                level,
+                kind,
+                &attributes,
+                message,
+                key.map(|key| key.as_str().into_owned()),
+            );
+        }
+        log_event(level, kind, attributes, message);
+    }
+}
+
+impl<A, E, Request, Response> CustomEvents<A, E>
+where
+    A: Default + Debug + Clone + Selectors<Request = Request, Response = Response>,
+    E: Debug + Clone + Selector<Request = Request, Response = Response>,
+{
+    fn new(standard: StandardEvents, config: &HashMap<String, Event<A, E>>) -> Self {
+        Self {
+            standard,
+            events: Arc::new(
+                config
+                    .iter()
+                    .map(|(name, event)| (name.clone(), event.clone()))
+                    .collect(),
+            ),
+            exporter: None,
+        }
+    }
+
+    /// Logs the request events, and evaluates the request part of the response and error events
+    pub(crate) fn on_request(&self, request: &Request) -> EventsState<A, E> {
+        self.log_standard(self.standard.request, "request", Vec::new());
+        let requests = self
+            .events
+            .iter()
+            .map(|(name, event)| {
+                let mut condition = event.condition.clone();
+                let matched = condition.evaluate_request(request);
+                let attributes: Vec<KeyValue> =
+                    event.attributes.on_request(request).into_iter().collect();
+                let key = event.key.as_ref().and_then(|key| key.on_request(request));
+                if event.on == EventOn::Request {
+                    // Response selectors can't be evaluated yet, so they don't match
+                    if matched == Some(true) {
+                        self.emit(event.level, name, attributes, &event.message, key);
+                    }
+                    None
+                } else {
+                    Some(EventRequest {
+                        matched,
+                        condition,
+                        attributes,
+                        key,
+                    })
+                }
+            })
+            .collect();
+
+        EventsState {
+            events: self.clone(),
+            start: Instant::now(),
+            requests,
+        }
+    }
+}
+
+/// The state of the events of a request, until its response
+pub(crate) struct EventsState<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    events: CustomEvents<A, E>,
+    start: Instant,
+    requests: Vec<Option<EventRequest<E>>>,
+}
+
+struct EventRequest<E> {
+    matched: Option<bool>,
+    condition: Condition<E>,
+    attributes: Vec<KeyValue>,
+    key: Option<opentelemetry::Value>,
+}
+
+impl<A, E, Request, Response> EventsState<A, E>
+where
+    A: Default + Debug + Selectors<Request = Request, Response = Response>,
+    E: Debug + Selector<Request = Request, Response = Response>,
+{
+    /// Logs the response events
+    pub(crate) fn on_response(self, response: &Response) {
+        let events = &self.events;
+        events.log_standard(events.standard.response, "response", Vec::new());
+        for ((name, event), request) in events.events.iter().zip(self.requests) {
+            let Some(mut request) = request else {
+                continue;
+            };
+            if event.on != EventOn::Response
+                || !request
+                    .matched
+                    .unwrap_or_else(|| request.condition.evaluate_response(response))
+            {
+                continue;
+            }
+            request
+                .attributes
+                .extend(event.attributes.on_response(response));
+            let key = request
+                .key
+                .or_else(|| event.key.as_ref()?.on_response(response));
+            events.emit(event.level, name, request.attributes, &event.message, key);
+        }
+    }
+
+    /// Logs the error events when the service failed
+    pub(crate) fn on_error(self, error: &BoxError) {
+        let elapsed = self.start.elapsed();
+        let events = &self.events;
+        events.log_standard(
+            events.standard.error,
+            "error",
+            vec![KeyValue::new("error", error.to_string())],
+        );
+        for ((name, event), request) in events.events.iter().zip(self.requests) {
+            let Some(mut request) = request else {
+                continue;
+            };
+            if event.on != EventOn::Error
+                || !request
+                    .matched
+                    .unwrap_or_else(|| request.condition.evaluate_error(elapsed))
+            {
+                continue;
+            }
+            request
+                .attributes
+                .extend(event.attributes.on_error_after(error, elapsed));
+            let key = request.key.or_else(|| {
+                let key = event.key.as_ref()?;
+                key.on_error(error).or_else(|| key.on_elapsed(elapsed))
+            });
+            events.emit(event.level, name, request.attributes, &event.message, key);
+        }
+    }
+}
+
+/// Logs an event in the current span. The attributes are only known at runtime, so they are
+/// handed to the log formatters alongside the event rather than as tracing fields.
+pub(crate) fn log_event(level: EventLevel, kind: &str, attributes: Vec<KeyValue>, message: &str) {
+    let attributes = std::iter::once(KeyValue::new("type", kind.to_string()))
+        .chain(attributes)
+        .collect();
+    EventAttributes::scope(attributes, || match level {
+        EventLevel::Info => ::tracing::info!("{message}"),
+        EventLevel::Warn => ::tracing::warn!("{message}"),
+        EventLevel::Error => ::tracing::error!("{message}"),
+        EventLevel::Off => {}
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use http::StatusCode;
+    use serde_json::json;
+    use tracing::instrument::WithSubscriber;
+
+    use super::*;
+    use crate::assert_snapshot_subscriber;
+    use crate::services::router;
+
+    #[tokio::test]
+    async fn custom_router_events() {
+        let config: Events = serde_json::from_value(json!({
+            "router": {
+                "request": "info",
+                "error": "error",
+                "acme.request": {
+                    "on": "request",
+                    "level": "info",
+                    "message": "acme request",
+                    "attributes": {
+                        "tenant": {
+                            "request_header": "x-tenant"
+                        }
+                    },
+                    "condition": {
+                        "eq": ["acme", { "request_header": "x-tenant" }]
+                    }
+                },
+                "acme.failure": {
+                    "on": "response",
+                    "level": "warn",
+                    "message": "request failed",
+                    "attributes": {
+                        "http.response.status_code": true
+                    },
+                    "condition": {
+                        "eq": [{ "response_status": "code" }, 500]
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let events = config.new_router_events();
+
+        async {
+            let request = |tenant| {
+                router::Request::fake_builder()
+                    .header("x-tenant", tenant)
+                    .build()
+                    .unwrap()
+            };
+            events
+                .on_request(&request("acme"))
+                .on_response(&router::Response::fake_builder().build().unwrap());
+            events.on_request(&request("other")).on_response(
+                &router::Response::fake_builder()
+                    .status_code(StatusCode::INTERNAL_SERVER_ERROR)
+                    .build()
+                    .unwrap(),
+            );
+            events
+                .on_request(&request("other"))
+                .on_error(&BoxError::from("connection closed"));
+        }
+        .with_subscriber(assert_snapshot_subscriber!())
+        .await
+    }
+    #[tokio::test]
+    async fn it_exports_events_with_their_key() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::method("POST"))
+            .respond_with(
+                wiremock::ResponseTemplate::new(200)
+                    .set_body_json(json!({ "offsets": [{ "partition": 0, "offset": 0 }] })),
+            )
+            .mount(&server)
+            .await;
+        let exporter: crate::plugins::telemetry::kafka_events::KafkaEvents =
+            serde_json::from_value(json!({
+                "enabled": true,
+                "rest_proxy": server.uri(),
+                "topic": "audit",
+                "flush_interval": "10ms"
+            }))
+            .unwrap();
+        let config: Events = serde_json::from_value(json!({
+            "router": {
+                "acme.failure": {
+                    "on": "response",
+                    "level": "warn",
+                    "message": "request failed",
+                    "key": { "request_header": "x-tenant" }
+                }
+            }
+        }))
+        .unwrap();
+        let events = config
+            .new_router_events()
+            .with_exporter(Some(Arc::new(EventExporter::new(&exporter).unwrap())));
+
+        events
+            .on_request(
+                &router::Request::fake_builder()
+                    .header("x-tenant", "acme")
+                    .build()
+                    .unwrap(),
+            )
+            .on_response(&router::Response::fake_builder().build().unwrap());
+
+        for _ in 0..50 {
+            let requests = server.received_requests().await.unwrap_or_default();
+            if let Some(request) = requests.first() {
+                let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+                assert_eq!(body["records"][0]["key"], "acme");
+                assert_eq!(body["records"][0]["value"]["type"], "acme.failure");
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        panic!("the event was not exported")
+    }
+}
@@ -2,6 +2,7 @@ use std::any::type_name;
 use std::collections::HashMap;
 use std::collections::LinkedList;
 use std::fmt::Debug;
+use std::time::Duration;
 
 use opentelemetry::KeyValue;
 use schemars::gen::SchemaGenerator;
@@ -146,6 +147,31 @@ where
     }
 }
 
+impl<A, E, Request, Response> Extendable<A, E>
+where
+    A: Default + Selectors<Request = Request, Response = Response>,
+    E: Selector<Request = Request, Response = Response>,
+{
+    /// The attributes when the service failed after `elapsed`, which the custom selectors of the
+    /// elapsed time are given in place of a response
+    pub(crate) fn on_error_after(
+        &self,
+        error: &BoxError,
+        elapsed: Duration,
+    ) -> LinkedList<KeyValue> {
+        let mut attrs = self.attributes.on_error(error);
+        let custom_attributes = self.custom.iter().filter_map(|(key, value)| {
+            value
+                .on_error(error)
+                .or_else(|| value.on_elapsed(elapsed))
+                .map(|v| KeyValue::new(key.clone(), v))
+        });
+        attrs.extend(custom_attributes);
+
+        attrs
+    }
+}
+
 impl<A, E, Request, Response> Selectors for Extendable<A, E>
 where
     A: Default + Selectors<Request = Request, Response = Response>,
@@ -179,7 +205,13 @@ where
     }
 
     fn on_error(&self, error: &BoxError) -> LinkedList<KeyValue> {
-        self.attributes.on_error(error)
+        let mut attrs = self.attributes.on_error(error);
+        let custom_attributes = self.custom.iter().filter_map(|(key, value)| {
+            value.on_error(error).map(|v| KeyValue::new(key.clone(), v))
+        });
+        attrs.extend(custom_attributes);
+
+        attrs
     }
 }
 
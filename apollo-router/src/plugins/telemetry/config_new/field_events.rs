@@ -0,0 +1,221 @@
+//! Span events describing the top-level fields of an operation, with the attributes of the
+//! GraphQL semantic conventions proposed to OpenTelemetry.
+use std::collections::HashSet;
+
+use apollo_compiler::executable;
+use apollo_compiler::ExecutableDocument;
+use opentelemetry::KeyValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::Span;
+
+use crate::context::OPERATION_NAME;
+use crate::graphql;
+use crate::json_ext::PathElement;
+use crate::plugins::telemetry::config_new::selectors::FirstResponseErrors;
+use crate::plugins::telemetry::dynamic_attribute::DynSpanEvent;
+use crate::plugins::telemetry::tracing::sampling::sample;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::Context;
+
+const FIELD_EVENT_NAME: &str = "graphql.field";
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct FieldEvents {
+    /// Add a `graphql.field` event to the supergraph span for each top-level field of the operation.
+    pub(crate) enabled: bool,
+    /// The ratio of traced requests whose fields are recorded (default: 0.01).
+    pub(crate) sampler: f64,
+    /// The maximum number of field events per request (default: 100).
+    pub(crate) max_events: usize,
+}
+
+impl Default for FieldEvents {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sampler: 0.01,
+            max_events: 100,
+        }
+    }
+}
+
+impl FieldEvents {
+    /// Adds the events of the top-level fields of the operation to the supergraph span, once the
+    /// first response is known. Spans that are not sampled are left untouched.
+    pub(crate) fn emit(&self, span: &Span, context: &Context) {
+        if !self.enabled || !sample(self.sampler) {
+            return;
+        }
+        let Some(document) = context
+            .private_entries
+            .lock()
+            .get::<ParsedDocument>()
+            .cloned()
+        else {
+            return;
+        };
+        let errors = context
+            .private_entries
+            .lock()
+            .get::<FirstResponseErrors>()
+            .cloned();
+        let errors = errors.as_ref().map(|errors| errors.0.as_slice());
+        let operation_name: Option<String> = context.get(OPERATION_NAME).ok().flatten();
+        let Ok(operation) = document.executable.get_operation(operation_name.as_deref()) else {
+            return;
+        };
+
+        let mut fields = Vec::new();
+        collect_fields(
+            &document.executable,
+            &operation.selection_set,
+            &mut HashSet::new(),
+            &mut fields,
+            self.max_events,
+        );
+        for field in fields {
+            let response_key = field.response_key().as_str();
+            span.add_dyn_event(
+                FIELD_EVENT_NAME,
+                vec![
+                    KeyValue::new("graphql.field.name", field.name.to_string()),
+                    KeyValue::new("graphql.field.type", field.ty().to_string()),
+                    KeyValue::new(
+                        "graphql.field.parent_type",
+                        operation.selection_set.ty.to_string(),
+                    ),
+                    KeyValue::new("graphql.field.path", response_key.to_string()),
+                    KeyValue::new(
+                        "graphql.field.errors",
+                        errors.map_or(0, |errors| count_errors(errors, response_key)),
+                    ),
+                ],
+            );
+        }
+    }
+}
+
+/// Collects the fields of a selection set and of its fragments, once per response key
+fn collect_fields<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a executable::SelectionSet,
+    fragments_seen: &mut HashSet<&'a executable::Name>,
+    fields: &mut Vec<&'a executable::Field>,
+    max_fields: usize,
+) {
+    for selection in &selection_set.selections {
+        if fields.len() >= max_fields {
+            return;
+        }
+        match selection {
+            executable::Selection::Field(field) => {
+                if !fields
+                    .iter()
+                    .any(|seen| seen.response_key() == field.response_key())
+                {
+                    fields.push(field);
+                }
+            }
+            executable::Selection::InlineFragment(fragment) => collect_fields(
+                document,
+                &fragment.selection_set,
+                fragments_seen,
+                fields,
+                max_fields,
+            ),
+            executable::Selection::FragmentSpread(spread) => {
+                if !fragments_seen.insert(&spread.fragment_name) {
+                    continue;
+                }
+                if let Some(fragment) = document.fragments.get(&spread.fragment_name) {
+                    collect_fields(
+                        document,
+                        &fragment.selection_set,
+                        fragments_seen,
+                        fields,
+                        max_fields,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// The number of errors located in a top-level field
+fn count_errors(errors: &[graphql::Error], response_key: &str) -> i64 {
+    errors
+        .iter()
+        .filter(|error| {
+            matches!(
+                error.path.as_ref().and_then(|path| path.0.first()),
+                Some(PathElement::Key(key)) if key == response_key
+            )
+        })
+        .count() as i64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::json_ext::Path;
+    use crate::spec::Query;
+    use crate::spec::Schema;
+    use crate::Configuration;
+
+    fn top_level_fields(query: &str, max_fields: usize) -> Vec<String> {
+        let schema = Schema::parse(
+            include_str!("../../../testdata/supergraph.graphql"),
+            &Configuration::default(),
+        )
+        .unwrap();
+        let document = Query::parse_document(query, &schema, &Configuration::default());
+        let operation = document.executable.get_operation(None).unwrap();
+        let mut fields = Vec::new();
+        collect_fields(
+            &document.executable,
+            &operation.selection_set,
+            &mut HashSet::new(),
+            &mut fields,
+            max_fields,
+        );
+        fields
+            .iter()
+            .map(|field| field.response_key().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_collects_top_level_fields_once() {
+        let query = "query { me { name } ... on Query { me { id } topProducts { upc } } ...F } \
+                     fragment F on Query { topProducts { name } other: me { id } }";
+        assert_eq!(
+            top_level_fields(query, 10),
+            vec!["me", "topProducts", "other"]
+        );
+        assert_eq!(top_level_fields(query, 2), vec!["me", "topProducts"]);
+    }
+
+    #[test]
+    fn it_counts_errors_per_top_level_field() {
+        let errors = vec![
+            graphql::Error::builder()
+                .message("a")
+                .path(Path(vec![PathElement::Key("me".to_string())]))
+                .extension_code("ERROR")
+                .build(),
+            graphql::Error::builder()
+                .message("b")
+                .path(Path(vec![
+                    PathElement::Key("topProducts".to_string()),
+                    PathElement::Index(0),
+                ]))
+                .extension_code("ERROR")
+                .build(),
+        ];
+        assert_eq!(count_errors(&errors, "me"), 1);
+        assert_eq!(count_errors(&errors, "topProducts"), 1);
+        assert_eq!(count_errors(&errors, "reviews"), 0);
+    }
+}
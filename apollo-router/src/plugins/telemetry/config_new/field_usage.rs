@@ -0,0 +1,142 @@
+//! Counts of the fields of the schema requested by operations, derived from their query plans, to
+//! track the usage of the schema without reporting traces to GraphOS.
+use std::sync::Arc;
+
+use opentelemetry::KeyValue;
+use router_bridge::planner::UsageReporting;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::plugins::telemetry::config_new::cardinality::CardinalityLimiter;
+use crate::plugins::telemetry::config_new::cardinality::DEFAULT_CARDINALITY_LIMIT;
+
+const FIELD_USAGE_INSTRUMENT: &str = "apollo.router.operations.fields";
+
+#[derive(Clone, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct FieldUsage {
+    /// Set to true to count the fields of the schema requested by each operation, with their
+    /// `graphql.type.name` and `graphql.field.name` attributes.
+    enabled: bool,
+    /// Maximum number of distinct fields counted (default: 2000). Beyond it, new fields are
+    /// counted as `other`. Set to 0 to disable the limit
+    cardinality_limit: usize,
+}
+
+impl Default for FieldUsage {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cardinality_limit: DEFAULT_CARDINALITY_LIMIT,
+        }
+    }
+}
+
+impl FieldUsage {
+    /// The counter of the requested fields, if they are counted
+    pub(crate) fn new_counter(&self) -> Option<FieldUsageCounter> {
+        self.enabled.then(|| FieldUsageCounter {
+            cardinality: Arc::new(CardinalityLimiter::new(self.cardinality_limit)),
+        })
+    }
+}
+
+/// Counts the fields requested by operations
+#[derive(Clone)]
+pub(crate) struct FieldUsageCounter {
+    cardinality: Arc<CardinalityLimiter>,
+}
+
+impl FieldUsageCounter {
+    /// Counts the fields referenced by an operation, known once its query plan has been obtained
+    pub(crate) fn record(&self, usage_reporting: &UsageReporting) {
+        for (type_name, fields) in &usage_reporting.referenced_fields_by_type {
+            for field_name in &fields.field_names {
+                let attributes = [
+                    KeyValue::new("graphql.type.name", type_name.clone()),
+                    KeyValue::new("graphql.field.name", field_name.clone()),
+                ];
+                let attributes = self.cardinality.limit(FIELD_USAGE_INSTRUMENT, &attributes);
+                u64_counter!(
+                    "apollo.router.operations.fields",
+                    "Number of operations requesting each field of the schema",
+                    1,
+                    attributes
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use router_bridge::planner::ReferencedFieldsForType;
+    use serde_json::json;
+
+    use super::*;
+    use crate::metrics::FutureMetricsExt;
+
+    fn usage_reporting(fields: &[(&str, &[&str])]) -> UsageReporting {
+        UsageReporting {
+            stats_report_key: "# -\n{me{name}}".to_string(),
+            referenced_fields_by_type: fields
+                .iter()
+                .map(|(type_name, field_names)| {
+                    (
+                        type_name.to_string(),
+                        ReferencedFieldsForType {
+                            field_names: field_names.iter().map(|name| name.to_string()).collect(),
+                            is_interface: false,
+                        },
+                    )
+                })
+                .collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_counts_the_requested_fields() {
+        async {
+            let config: FieldUsage = serde_json::from_value(json!({
+                "enabled": true,
+                "cardinality_limit": 3
+            }))
+            .unwrap();
+            let counter = config.new_counter().unwrap();
+            counter.record(&usage_reporting(&[
+                ("Query", &["me"]),
+                ("User", &["name", "email"]),
+            ]));
+            counter.record(&usage_reporting(&[("Query", &["me", "topProducts"])]));
+
+            assert_counter!(
+                "apollo.router.operations.fields",
+                2,
+                "graphql.type.name" = "Query",
+                "graphql.field.name" = "me"
+            );
+            assert_counter!(
+                "apollo.router.operations.fields",
+                1,
+                "graphql.type.name" = "User",
+                "graphql.field.name" = "email"
+            );
+            // Beyond the cardinality limit, new fields are counted as `other`
+            assert_counter!(
+                "apollo.router.operations.fields",
+                1,
+                "graphql.type.name" = "other",
+                "graphql.field.name" = "other"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[test]
+    fn it_is_disabled_by_default() {
+        assert!(FieldUsage::default().new_counter().is_none());
+    }
+}
@@ -1,20 +1,45 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Instant;
 
+use arc_swap::ArcSwap;
+use http::header::CONTENT_LENGTH;
+use opentelemetry::metrics::Counter;
+use opentelemetry::metrics::Histogram;
+use opentelemetry::metrics::Unit;
+use opentelemetry::metrics::UpDownCounter;
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use tower::BoxError;
 
+use crate::metrics::aggregation::Inner;
+use crate::metrics::aggregation::InstrumentWrapper;
 use crate::plugins::telemetry::config_new::attributes::DefaultAttributeRequirementLevel;
 use crate::plugins::telemetry::config_new::attributes::RouterAttributes;
 use crate::plugins::telemetry::config_new::attributes::SubgraphAttributes;
 use crate::plugins::telemetry::config_new::attributes::SupergraphAttributes;
+use crate::plugins::telemetry::config_new::cardinality::CardinalityLimiter;
+use crate::plugins::telemetry::config_new::cardinality::DEFAULT_CARDINALITY_LIMIT;
 use crate::plugins::telemetry::config_new::conditions::Condition;
 use crate::plugins::telemetry::config_new::extendable::Extendable;
+use crate::plugins::telemetry::config_new::field_usage::FieldUsage;
+use crate::plugins::telemetry::config_new::field_usage::FieldUsageCounter;
 use crate::plugins::telemetry::config_new::selectors::RouterSelector;
 use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
 use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
+use crate::plugins::telemetry::config_new::DefaultForLevel;
+use crate::plugins::telemetry::config_new::Selector;
+use crate::plugins::telemetry::config_new::Selectors;
+use crate::plugins::telemetry::metrics::prometheus::exemplars_enabled;
+use crate::plugins::telemetry::metrics::prometheus::record_exemplar;
 
-#[allow(dead_code)]
-#[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
+const METER_NAME: &str = "apollo/router";
+
+#[derive(Clone, Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields, default)]
 pub(crate) struct Instruments {
     /// The attributes to include by default in instruments based on their level as specified in the otel semantic conventions and Apollo documentation.
@@ -27,9 +52,23 @@ pub(crate) struct Instruments {
         Extendable<SupergraphInstruments, Instrument<SupergraphAttributes, SupergraphSelector>>,
     /// Subgraph service instruments. For more information see documentation on Router lifecycle.
     subgraph: Extendable<SubgraphInstruments, Instrument<SubgraphAttributes, SubgraphSelector>>,
+    /// Maximum number of distinct attribute sets of each custom instrument (default: 2000). Beyond
+    /// it, the values of new attribute sets are recorded as `other`. Set to 0 to disable the limit
+    cardinality_limit: usize,
+}
+
+impl Default for Instruments {
+    fn default() -> Self {
+        Self {
+            default_attribute_requirement_level: Default::default(),
+            router: Default::default(),
+            supergraph: Default::default(),
+            subgraph: Default::default(),
+            cardinality_limit: DEFAULT_CARDINALITY_LIMIT,
+        }
+    }
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
 struct RouterInstruments {
@@ -38,23 +77,22 @@ struct RouterInstruments {
     http_server_request_duration:
         DefaultedStandardInstrument<Extendable<RouterAttributes, RouterSelector>>,
 
-    /// Gauge of active requests
+    /// Counter of active requests
     #[serde(rename = "http.server.active_requests")]
     http_server_active_requests:
         DefaultedStandardInstrument<Extendable<RouterAttributes, RouterSelector>>,
 
-    /// Histogram of server request body size
+    /// Histogram of server request body size, from the `content-length` header of the request
     #[serde(rename = "http.server.request.body.size")]
     http_server_request_body_size:
         DefaultedStandardInstrument<Extendable<RouterAttributes, RouterSelector>>,
 
-    /// Histogram of server response body size
+    /// Histogram of server response body size, from the `content-length` header of the response
     #[serde(rename = "http.server.response.body.size")]
     http_server_response_body_size:
         DefaultedStandardInstrument<Extendable<RouterAttributes, RouterSelector>>,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields, untagged)]
 enum DefaultedStandardInstrument<T> {
@@ -64,16 +102,35 @@ enum DefaultedStandardInstrument<T> {
 
 impl<T> Default for DefaultedStandardInstrument<T> {
     fn default() -> Self {
-        DefaultedStandardInstrument::Bool(true)
+        DefaultedStandardInstrument::Bool(false)
+    }
+}
+
+impl<T> DefaultedStandardInstrument<T>
+where
+    T: Clone + Default + DefaultForLevel,
+{
+    /// The attributes of the instrument, with the defaults of the requirement level, or `None` if
+    /// the instrument is disabled
+    fn attributes(&self, requirement_level: DefaultAttributeRequirementLevel) -> Option<T> {
+        let mut attributes = match self {
+            DefaultedStandardInstrument::Bool(false) => return None,
+            DefaultedStandardInstrument::Bool(true) => T::default(),
+            DefaultedStandardInstrument::Extendable { attributes } => attributes.clone(),
+        };
+        attributes.defaults_for_levels(requirement_level);
+        Some(attributes)
     }
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
-struct SupergraphInstruments {}
+struct SupergraphInstruments {
+    /// Counter of the fields of the schema requested by the operations
+    #[serde(rename = "experimental_field_usage")]
+    field_usage: FieldUsage,
+}
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug, Default)]
 #[serde(deny_unknown_fields, default)]
 struct SubgraphInstruments {
@@ -81,17 +138,13 @@ struct SubgraphInstruments {
     #[serde(rename = "http.client.request.duration")]
     http_client_request_duration: bool,
 
-    /// Histogram of client request body size
-    #[serde(rename = "http.client.request.body.size")]
-    http_client_request_body_size: bool,
-
-    /// Histogram of client response body size
+    /// Histogram of client response body size, from the `content-length` header of the response
     #[serde(rename = "http.client.response.body.size")]
     http_client_response_body_size: bool,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Instrument<A, E>
 where
     A: Default + Debug,
@@ -119,7 +172,6 @@ where
     condition: Condition<E>,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub(crate) enum InstrumentType {
@@ -131,12 +183,8 @@ pub(crate) enum InstrumentType {
 
     /// A histogram https://opentelemetry.io/docs/specs/otel/metrics/data-model/#histogram
     Histogram,
-
-    /// A gauge https://opentelemetry.io/docs/specs/otel/metrics/data-model/#gauge
-    Gauge,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case", untagged)]
 pub(crate) enum InstrumentValue<T> {
@@ -144,11 +192,698 @@ pub(crate) enum InstrumentValue<T> {
     Custom(T),
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Deserialize, JsonSchema, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub(crate) enum Standard {
+    /// The duration of the request, in seconds
     Duration,
+    /// One for each request
     Unit,
+    /// The number of requests in flight, for an `up_down_counter`
     Active,
 }
+
+impl Instruments {
+    /// Checks that the custom instruments can be created
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        self.router
+            .custom
+            .iter()
+            .try_for_each(|(name, instrument)| instrument.validate(name))?;
+        self.supergraph
+            .custom
+            .iter()
+            .try_for_each(|(name, instrument)| instrument.validate(name))?;
+        self.subgraph
+            .custom
+            .iter()
+            .try_for_each(|(name, instrument)| instrument.validate(name))
+    }
+
+    pub(crate) fn new_router_instruments(
+        &self,
+    ) -> CustomInstruments<RouterAttributes, RouterSelector> {
+        let mut instruments = self
+            .router
+            .attributes
+            .enabled(self.default_attribute_requirement_level);
+        instruments.extend(self.router.custom.clone());
+        CustomInstruments::new(&instruments, self.cardinality_limit)
+    }
+
+    pub(crate) fn new_supergraph_instruments(
+        &self,
+    ) -> CustomInstruments<SupergraphAttributes, SupergraphSelector> {
+        CustomInstruments::new(&self.supergraph.custom, self.cardinality_limit)
+    }
+
+    /// Returns true if a custom supergraph instrument uses a selector matching the predicate
+    pub(crate) fn supergraph_selects(
+        &self,
+        predicate: impl Fn(&SupergraphSelector) -> bool,
+    ) -> bool {
+        self.supergraph
+            .custom
+            .values()
+            .any(|instrument| instrument.selects(&predicate))
+    }
+
+    pub(crate) fn new_field_usage_counter(&self) -> Option<FieldUsageCounter> {
+        self.supergraph.attributes.field_usage.new_counter()
+    }
+
+    pub(crate) fn new_subgraph_instruments(
+        &self,
+    ) -> CustomInstruments<SubgraphAttributes, SubgraphSelector> {
+        let mut instruments = self
+            .subgraph
+            .attributes
+            .enabled(self.default_attribute_requirement_level);
+        instruments.extend(self.subgraph.custom.clone());
+        CustomInstruments::new(&instruments, self.cardinality_limit)
+    }
+}
+
+impl RouterInstruments {
+    /// The standard instruments that are enabled, as instruments of the router service
+    fn enabled(
+        &self,
+        requirement_level: DefaultAttributeRequirementLevel,
+    ) -> HashMap<String, Instrument<RouterAttributes, RouterSelector>> {
+        let request_body_size = RouterSelector::RequestHeader {
+            request_header: CONTENT_LENGTH.to_string(),
+            redact: None,
+            typed: true,
+            default: None,
+        };
+        let response_body_size = RouterSelector::ResponseHeader {
+            response_header: CONTENT_LENGTH.to_string(),
+            redact: None,
+            typed: true,
+            default: None,
+        };
+        [
+            (
+                "http.server.request.duration",
+                &self.http_server_request_duration,
+                InstrumentType::Histogram,
+                InstrumentValue::Standard(Standard::Duration),
+                "Duration of HTTP server requests.",
+                "s",
+            ),
+            (
+                "http.server.active_requests",
+                &self.http_server_active_requests,
+                InstrumentType::UpDownCounter,
+                InstrumentValue::Standard(Standard::Active),
+                "Number of active HTTP server requests.",
+                "{request}",
+            ),
+            (
+                "http.server.request.body.size",
+                &self.http_server_request_body_size,
+                InstrumentType::Histogram,
+                InstrumentValue::Custom(request_body_size),
+                "Size of HTTP server request bodies.",
+                "By",
+            ),
+            (
+                "http.server.response.body.size",
+                &self.http_server_response_body_size,
+                InstrumentType::Histogram,
+                InstrumentValue::Custom(response_body_size),
+                "Size of HTTP server response bodies.",
+                "By",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, instrument, ty, value, description, unit)| {
+            let attributes = instrument.attributes(requirement_level)?;
+            Some((
+                name.to_string(),
+                Instrument::standard(ty, value, description, unit, attributes),
+            ))
+        })
+        .collect()
+    }
+}
+
+impl SubgraphInstruments {
+    /// The standard instruments that are enabled, as instruments of the subgraph service
+    fn enabled(
+        &self,
+        requirement_level: DefaultAttributeRequirementLevel,
+    ) -> HashMap<String, Instrument<SubgraphAttributes, SubgraphSelector>> {
+        let response_body_size = SubgraphSelector::SubgraphResponseHeader {
+            subgraph_response_header: CONTENT_LENGTH.to_string(),
+            redact: None,
+            typed: true,
+            default: None,
+        };
+        [
+            (
+                "http.client.request.duration",
+                self.http_client_request_duration,
+                InstrumentValue::Standard(Standard::Duration),
+                "Duration of HTTP client requests.",
+                "s",
+            ),
+            (
+                "http.client.response.body.size",
+                self.http_client_response_body_size,
+                InstrumentValue::Custom(response_body_size),
+                "Size of HTTP client response bodies.",
+                "By",
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, enabled, ..)| *enabled)
+        .map(|(name, _, value, description, unit)| {
+            let mut attributes = Extendable::empty::<SubgraphAttributes, SubgraphSelector>();
+            attributes.defaults_for_levels(requirement_level);
+            (
+                name.to_string(),
+                Instrument::standard(
+                    InstrumentType::Histogram,
+                    value,
+                    description,
+                    unit,
+                    attributes,
+                ),
+            )
+        })
+        .collect()
+    }
+}
+
+impl<A, E> Instrument<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    /// An instrument of the OpenTelemetry semantic conventions
+    fn standard(
+        ty: InstrumentType,
+        value: InstrumentValue<E>,
+        description: &str,
+        unit: &str,
+        attributes: Extendable<A, E>,
+    ) -> Self {
+        Self {
+            ty,
+            value,
+            description: description.to_string(),
+            unit: unit.to_string(),
+            attributes,
+            condition: Condition::empty::<E>(),
+        }
+    }
+
+    fn selects(&self, predicate: &impl Fn(&E) -> bool) -> bool {
+        matches!(&self.value, InstrumentValue::Custom(selector) if predicate(selector))
+            || self.attributes.custom.values().any(predicate)
+            || self.condition.any_selector(predicate)
+    }
+
+    fn validate(&self, name: &str) -> Result<(), String> {
+        match (&self.ty, &self.value) {
+            (InstrumentType::UpDownCounter, _) | (_, InstrumentValue::Custom(_)) => Ok(()),
+            (_, InstrumentValue::Standard(Standard::Active)) => Err(format!(
+                "instrument '{name}': the 'active' value requires an up_down_counter"
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The standard and custom instruments of a service
+pub(crate) struct CustomInstruments<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    instruments: Arc<Vec<CustomInstrument<A, E>>>,
+}
+
+impl<A, E> Clone for CustomInstruments<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    fn clone(&self) -> Self {
+        Self {
+            instruments: self.instruments.clone(),
+        }
+    }
+}
+
+struct CustomInstrument<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    name: String,
+    config: Instrument<A, E>,
+    handle: InstrumentHandle,
+    cardinality: CardinalityLimiter,
+    /// Whether this is a duration histogram, whose buckets carry the trace ids of the requests as
+    /// exemplars
+    exemplars: bool,
+}
+
+/// The instrument is created when it is first used, and created again after the meter provider
+/// was reloaded
+enum InstrumentHandle {
+    Counter(InstrumentCache<Counter<f64>>),
+    UpDownCounter(InstrumentCache<UpDownCounter<f64>>),
+    Histogram(InstrumentCache<Histogram<f64>>),
+}
+
+/// Records only load the cached instrument, the lock is taken to create it
+struct InstrumentCache<T> {
+    instrument: ArcSwap<Weak<T>>,
+    create: Mutex<()>,
+}
+
+impl<T> Default for InstrumentCache<T> {
+    fn default() -> Self {
+        Self {
+            instrument: ArcSwap::from_pointee(Weak::new()),
+            create: Mutex::new(()),
+        }
+    }
+}
+
+impl<T> InstrumentCache<T>
+where
+    Arc<T>: Into<InstrumentWrapper>,
+{
+    fn get_or_create(&self, create: impl Fn(&mut Inner) -> T) -> Arc<T> {
+        if let Some(instrument) = self.instrument.load().upgrade() {
+            return instrument;
+        }
+        let _create = self.create.lock();
+        if let Some(instrument) = self.instrument.load().upgrade() {
+            return instrument;
+        }
+        let instrument = crate::metrics::meter_provider().create_registered_instrument(create);
+        self.instrument.store(Arc::new(Arc::downgrade(&instrument)));
+        instrument
+    }
+}
+
+impl<A, E> CustomInstrument<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    fn record(&self, value: f64, attributes: &[KeyValue]) {
+        let attributes = self.cardinality.limit(&self.name, attributes);
+        let attributes = attributes.as_ref();
+        match &self.handle {
+            InstrumentHandle::Counter(cache) => cache
+                .get_or_create(|provider| {
+                    provider
+                        .meter(METER_NAME)
+                        .f64_counter(self.name.clone())
+                        .with_description(self.config.description.clone())
+                        .with_unit(Unit::new(self.config.unit.clone()))
+                        .init()
+                })
+                .add(value, attributes),
+            InstrumentHandle::UpDownCounter(cache) => cache
+                .get_or_create(|provider| {
+                    provider
+                        .meter(METER_NAME)
+                        .f64_up_down_counter(self.name.clone())
+                        .with_description(self.config.description.clone())
+                        .with_unit(Unit::new(self.config.unit.clone()))
+                        .init()
+                })
+                .add(value, attributes),
+            InstrumentHandle::Histogram(cache) => cache
+                .get_or_create(|provider| {
+                    provider
+                        .meter(METER_NAME)
+                        .f64_histogram(self.name.clone())
+                        .with_description(self.config.description.clone())
+                        .with_unit(Unit::new(self.config.unit.clone()))
+                        .init()
+                })
+                .record(value, attributes),
+        }
+        if self.exemplars && exemplars_enabled() {
+            record_exemplar(&self.name, &self.config.unit, attributes, value);
+        }
+    }
+}
+
+impl<A, E, Request, Response> CustomInstruments<A, E>
+where
+    A: Default + Debug + Clone + Selectors<Request = Request, Response = Response>,
+    E: Debug + Clone + Selector<Request = Request, Response = Response>,
+{
+    fn new(config: &HashMap<String, Instrument<A, E>>, cardinality_limit: usize) -> Self {
+        let instruments = config
+            .iter()
+            .map(|(name, config)| CustomInstrument {
+                name: name.clone(),
+                config: config.clone(),
+                handle: match config.ty {
+                    InstrumentType::Counter => InstrumentHandle::Counter(Default::default()),
+                    InstrumentType::UpDownCounter => {
+                        InstrumentHandle::UpDownCounter(Default::default())
+                    }
+                    InstrumentType::Histogram => InstrumentHandle::Histogram(Default::default()),
+                },
+                cardinality: CardinalityLimiter::new(cardinality_limit),
+                exemplars: matches!(
+                    (&config.ty, &config.value),
+                    (
+                        InstrumentType::Histogram,
+                        InstrumentValue::Standard(Standard::Duration)
+                    )
+                ),
+            })
+            .collect();
+        Self {
+            instruments: Arc::new(instruments),
+        }
+    }
+
+    /// Evaluates the instruments on the request
+    pub(crate) fn on_request(&self, request: &Request) -> InstrumentsState<A, E> {
+        let mut active_requests = Vec::new();
+        let requests = self
+            .instruments
+            .iter()
+            .enumerate()
+            .map(|(index, instrument)| {
+                let mut condition = instrument.config.condition.clone();
+                let matched = condition.evaluate_request(request);
+                let attributes: Vec<KeyValue> = instrument
+                    .config
+                    .attributes
+                    .on_request(request)
+                    .into_iter()
+                    .collect();
+                let value = match &instrument.config.value {
+                    InstrumentValue::Custom(selector) => {
+                        selector.on_request(request).and_then(to_f64)
+                    }
+                    InstrumentValue::Standard(_) => None,
+                };
+                // Requests in flight only depend on the request, so that each increment is
+                // matched by a decrement with the same attributes
+                let active = matches!(
+                    instrument.config.value,
+                    InstrumentValue::Standard(Standard::Active)
+                ) && matched != Some(false);
+                let attributes = if active {
+                    instrument.record(1.0, &attributes);
+                    active_requests.push((index, attributes));
+                    Vec::new()
+                } else {
+                    attributes
+                };
+                InstrumentRequest {
+                    matched,
+                    condition,
+                    attributes,
+                    value,
+                    active,
+                }
+            })
+            .collect();
+
+        InstrumentsState {
+            instruments: self.clone(),
+            start: Instant::now(),
+            requests,
+            _active: ActiveRequests {
+                instruments: self.clone(),
+                requests: active_requests,
+            },
+        }
+    }
+}
+
+/// The state of the instruments of a request, until its response
+pub(crate) struct InstrumentsState<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    instruments: CustomInstruments<A, E>,
+    start: Instant,
+    requests: Vec<InstrumentRequest<E>>,
+    /// Only dropped, along with the state
+    _active: ActiveRequests<A, E>,
+}
+
+/// Decrements the requests in flight when the request is done, or when its response future is
+/// dropped first
+struct ActiveRequests<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    instruments: CustomInstruments<A, E>,
+    /// The index of each `active` instrument that was incremented, and its attributes
+    requests: Vec<(usize, Vec<KeyValue>)>,
+}
+
+impl<A, E> Drop for ActiveRequests<A, E>
+where
+    A: Default + Debug,
+    E: Debug,
+{
+    fn drop(&mut self) {
+        for (index, attributes) in &self.requests {
+            self.instruments.instruments[*index].record(-1.0, attributes);
+        }
+    }
+}
+
+struct InstrumentRequest<E> {
+    matched: Option<bool>,
+    condition: Condition<E>,
+    attributes: Vec<KeyValue>,
+    value: Option<f64>,
+    active: bool,
+}
+
+impl<A, E, Request, Response> InstrumentsState<A, E>
+where
+    A: Default + Debug + Selectors<Request = Request, Response = Response>,
+    E: Debug + Selector<Request = Request, Response = Response>,
+{
+    /// Records the instruments on the response
+    pub(crate) fn on_response(self, response: &Response) {
+        let elapsed = self.start.elapsed();
+        for (instrument, mut request) in self.instruments.instruments.iter().zip(self.requests) {
+            if request.active {
+                continue;
+            }
+            if !request
+                .matched
+                .unwrap_or_else(|| request.condition.evaluate_response(response))
+            {
+                continue;
+            }
+            request
+                .attributes
+                .extend(instrument.config.attributes.on_response(response));
+            let value = match &instrument.config.value {
+                InstrumentValue::Standard(Standard::Duration) => Some(elapsed.as_secs_f64()),
+                InstrumentValue::Standard(Standard::Unit) => Some(1.0),
+                InstrumentValue::Standard(Standard::Active) => None,
+                InstrumentValue::Custom(selector) => request
+                    .value
+                    .or_else(|| selector.on_response(response).and_then(to_f64)),
+            };
+            if let Some(value) = value {
+                instrument.record(value, &request.attributes);
+            }
+        }
+    }
+
+    /// Records the instruments when the service failed
+    pub(crate) fn on_error(self, error: &BoxError) {
+        let elapsed = self.start.elapsed();
+        for (instrument, mut request) in self.instruments.instruments.iter().zip(self.requests) {
+            if request.active {
+                continue;
+            }
+            if !request
+                .matched
+                .unwrap_or_else(|| request.condition.evaluate_error(elapsed))
+            {
+                continue;
+            }
+            request
+                .attributes
+                .extend(instrument.config.attributes.on_error_after(error, elapsed));
+            let value = match &instrument.config.value {
+                InstrumentValue::Standard(Standard::Duration) => Some(elapsed.as_secs_f64()),
+                InstrumentValue::Standard(Standard::Unit) => Some(1.0),
+                InstrumentValue::Standard(Standard::Active) => None,
+                InstrumentValue::Custom(_) => request.value,
+            };
+            if let Some(value) = value {
+                instrument.record(value, &request.attributes);
+            }
+        }
+    }
+}
+
+/// Selectors return strings for headers and context entries, which are parsed as numbers
+fn to_f64(value: opentelemetry::Value) -> Option<f64> {
+    match value {
+        opentelemetry::Value::I64(value) => Some(value as f64),
+        opentelemetry::Value::F64(value) => Some(value),
+        opentelemetry::Value::String(value) => value.as_str().parse().ok(),
+        opentelemetry::Value::Bool(_) | opentelemetry::Value::Array(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use http::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::metrics::FutureMetricsExt;
+    use crate::services::router;
+
+    #[tokio::test]
+    async fn custom_router_instruments() {
+        async {
+            let config: Instruments = serde_json::from_value(json!({
+                "router": {
+                    "acme.requests": {
+                        "type": "counter",
+                        "value": "unit",
+                        "description": "Successful requests per tenant",
+                        "unit": "request",
+                        "attributes": {
+                            "tenant": {
+                                "request_header": "x-tenant"
+                            }
+                        },
+                        "condition": {
+                            "eq": [{ "response_status": "code" }, 200]
+                        }
+                    },
+                    "acme.request.cost": {
+                        "type": "histogram",
+                        "value": {
+                            "request_header": "x-cost"
+                        },
+                        "description": "Cost of requests",
+                        "unit": "unit"
+                    }
+                }
+            }))
+            .unwrap();
+            config.validate().unwrap();
+            let instruments = config.new_router_instruments();
+
+            let request = || {
+                router::Request::fake_builder()
+                    .header("x-tenant", "acme")
+                    .header("x-cost", "3")
+                    .build()
+                    .unwrap()
+            };
+            instruments
+                .on_request(&request())
+                .on_response(&router::Response::fake_builder().build().unwrap());
+            instruments.on_request(&request()).on_response(
+                &router::Response::fake_builder()
+                    .status_code(StatusCode::BAD_REQUEST)
+                    .build()
+                    .unwrap(),
+            );
+
+            assert_counter!("acme.requests", 1, "tenant" = "acme");
+            assert_histogram!("acme.request.cost", 2);
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn standard_router_instruments() {
+        async {
+            let config: Instruments = serde_json::from_value(json!({
+                "default_attribute_requirement_level": "none",
+                "router": {
+                    "http.server.request.duration": true,
+                    "http.server.active_requests": true,
+                    "http.server.request.body.size": true,
+                    "http.server.response.body.size": {
+                        "attributes": {
+                            "http.response.status_code": true
+                        }
+                    }
+                }
+            }))
+            .unwrap();
+            let instruments = config.new_router_instruments();
+
+            let request = || {
+                router::Request::fake_builder()
+                    .header("content-length", "35")
+                    .build()
+                    .unwrap()
+            };
+            let state = instruments.on_request(&request());
+            assert_up_down_counter!("http.server.active_requests", 1);
+            state.on_response(
+                &router::Response::fake_builder()
+                    .header("content-length", "120")
+                    .build()
+                    .unwrap(),
+            );
+            // Requests whose response future is dropped are not in flight anymore either
+            drop(instruments.on_request(&request()));
+
+            assert_up_down_counter!("http.server.active_requests", 0);
+            assert_histogram!("http.server.request.duration", 1);
+            assert_histogram!("http.server.request.body.size", 1);
+            assert_histogram!(
+                "http.server.response.body.size",
+                1,
+                "http.response.status_code" = 200
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[test]
+    fn unknown_standard_instruments_are_rejected() {
+        let config = serde_json::from_value::<Instruments>(json!({
+            "subgraph": {
+                "http.client.request.body.size": true
+            }
+        }));
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn gauges_are_not_supported() {
+        let config: Result<Instruments, _> = serde_json::from_value(json!({
+            "supergraph": {
+                "acme.active": {
+                    "type": "gauge",
+                    "value": "active",
+                    "description": "Requests in flight",
+                    "unit": "request"
+                }
+            }
+        }));
+        assert!(config.is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! JSONPath expressions selecting values in JSON bodies, such as
+//! `$.errors[?(@.extensions.code == 'FORBIDDEN')].path`.
+//!
+//! Unlike the dot separated paths of the `request_body` and `subgraph_response_body` selectors,
+//! they support filters and wildcards, and can match several nodes, which are then aggregated.
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use jsonpath_rust::JsonPathInst;
+use jsonpath_rust::JsonPtr;
+use schemars::JsonSchema;
+use serde::de;
+use serde::Deserialize;
+use serde::Deserializer;
+#[cfg(test)]
+use serde::Serialize;
+#[cfg(test)]
+use serde::Serializer;
+use serde_json::Value;
+
+/// A parsed JSONPath expression
+#[derive(Clone)]
+pub(crate) struct JsonPath {
+    source: String,
+    path: JsonPathInst,
+}
+
+impl JsonPath {
+    /// The aggregation of the nodes matched by the expression in a value, or `None` if no node
+    /// matches
+    pub(crate) fn select(&self, value: &Value, aggregation: &JsonPathAggregation) -> Option<Value> {
+        let mut nodes = self
+            .path
+            .find_slice(value)
+            .into_iter()
+            .map(|node| match node {
+                JsonPtr::Slice(value) => value.clone(),
+                JsonPtr::NewValue(value) => value,
+            });
+        match aggregation {
+            JsonPathAggregation::First => nodes.next(),
+            JsonPathAggregation::Count => Some(nodes.count().into()),
+            JsonPathAggregation::All => {
+                let nodes: Vec<Value> = nodes.collect();
+                (!nodes.is_empty()).then_some(Value::Array(nodes))
+            }
+        }
+    }
+
+    /// Replaces the nodes matched by the expression in a value
+    pub(crate) fn replace(&self, value: &mut Value, replacement: &Value) {
+        // The matched nodes are borrowed from the value, so they are found again by address
+        let matched: HashSet<*const Value> = self
+            .path
+            .find_slice(value)
+            .into_iter()
+            .filter_map(|node| match node {
+                JsonPtr::Slice(node) => Some(node as *const Value),
+                JsonPtr::NewValue(_) => None,
+            })
+            .collect();
+        if !matched.is_empty() {
+            replace_nodes(value, &matched, replacement);
+        }
+    }
+}
+
+fn replace_nodes(value: &mut Value, matched: &HashSet<*const Value>, replacement: &Value) {
+    if matched.contains(&(value as *const Value)) {
+        *value = replacement.clone();
+        return;
+    }
+    match value {
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| replace_nodes(value, matched, replacement)),
+        Value::Object(object) => object
+            .values_mut()
+            .for_each(|value| replace_nodes(value, matched, replacement)),
+        _ => {}
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        Ok(Self {
+            source: source.to_string(),
+            path: JsonPathInst::from_str(source)
+                .map_err(|error| format!("invalid JSONPath '{source}': {error}"))?,
+        })
+    }
+}
+
+impl fmt::Debug for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("JsonPath").field(&self.source).finish()
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonPath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+impl Serialize for JsonPath {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+/// How the nodes matched by a JSONPath expression are aggregated
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum JsonPathAggregation {
+    /// The first matching node.
+    #[default]
+    First,
+    /// The number of matching nodes, zero if none matches.
+    Count,
+    /// An array of the matching nodes.
+    All,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn it_selects_with_filters_and_wildcards() {
+        let body = json!({
+            "data": { "products": [{ "upc": "1", "price": 10 }, { "upc": "2", "price": 30 }] },
+            "errors": [
+                { "message": "a", "extensions": { "code": "FORBIDDEN" } },
+                { "message": "b", "extensions": { "code": "TIMEOUT" } },
+                { "message": "c", "extensions": { "code": "FORBIDDEN" } }
+            ]
+        });
+        let forbidden: JsonPath = "$.errors[?(@.extensions.code == 'FORBIDDEN')].message"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            forbidden.select(&body, &JsonPathAggregation::First),
+            Some(json!("a"))
+        );
+        assert_eq!(
+            forbidden.select(&body, &JsonPathAggregation::Count),
+            Some(json!(2))
+        );
+        assert_eq!(
+            forbidden.select(&body, &JsonPathAggregation::All),
+            Some(json!(["a", "c"]))
+        );
+
+        let prices: JsonPath = "$.data.products[*].price".parse().unwrap();
+        assert_eq!(
+            prices.select(&body, &JsonPathAggregation::All),
+            Some(json!([10, 30]))
+        );
+
+        let missing: JsonPath = "$.extensions.cost".parse().unwrap();
+        assert_eq!(missing.select(&body, &JsonPathAggregation::First), None);
+        assert_eq!(
+            missing.select(&body, &JsonPathAggregation::Count),
+            Some(json!(0))
+        );
+        assert_eq!(missing.select(&body, &JsonPathAggregation::All), None);
+
+        assert!("$.errors[?(@.code ==".parse::<JsonPath>().is_err());
+    }
+
+    #[test]
+    fn it_replaces_matched_nodes() {
+        let mut body = json!({
+            "variables": { "password": "hunter2", "user": { "email": "a@example.com" } },
+            "data": { "users": [{ "email": "b@example.com", "id": 1 }] }
+        });
+        let email: JsonPath = "$..email".parse().unwrap();
+        email.replace(&mut body, &json!("[REDACTED]"));
+        let password: JsonPath = "$.variables.password".parse().unwrap();
+        password.replace(&mut body, &json!("[REDACTED]"));
+        let missing: JsonPath = "$.extensions".parse().unwrap();
+        missing.replace(&mut body, &json!("[REDACTED]"));
+        assert_eq!(
+            body,
+            json!({
+                "variables": { "password": "[REDACTED]", "user": { "email": "[REDACTED]" } },
+                "data": { "users": [{ "email": "[REDACTED]", "id": 1 }] }
+            })
+        );
+    }
+}
@@ -16,9 +16,14 @@ use serde::Deserialize;
 use serde::Deserializer;
 
 use crate::configuration::ConfigurationError;
+use crate::plugins::telemetry::access_log::AccessLog;
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config_new::experimental_when_header::HeaderLoggingCondition;
+use crate::plugins::telemetry::formatters::redaction::Redaction;
+use crate::plugins::telemetry::kafka_events::KafkaEvents;
+use crate::plugins::telemetry::otlp;
 use crate::plugins::telemetry::resource::ConfigResource;
+use crate::plugins::telemetry::subgraph_bodies::SubgraphBodies;
 use crate::services::SupergraphRequest;
 
 /// Logging configuration.
@@ -37,6 +42,22 @@ pub(crate) struct Logging {
     /// Note that this will be removed when events are implemented.
     #[serde(rename = "experimental_when_header")]
     pub(crate) when_header: Vec<HeaderLoggingCondition>,
+
+    /// Redaction of the fields of the logs, applied to every event.
+    pub(crate) redaction: Redaction,
+
+    /// Access logs, written separately from the application logs.
+    pub(crate) access_log: AccessLog,
+
+    /// Export of the logs to an OTLP endpoint with the OpenTelemetry logs signal, correlated with
+    /// the traces.
+    pub(crate) otlp: otlp::Config,
+
+    /// Export of the events to a Kafka topic through a Kafka REST proxy.
+    pub(crate) kafka: KafkaEvents,
+
+    /// Logging of the bodies of sampled subgraph requests and responses, to debug subgraphs.
+    pub(crate) experimental_subgraph_bodies: SubgraphBodies,
 }
 
 impl Logging {
@@ -372,7 +393,6 @@ impl Default for TextFormat {
 }
 
 /// The period to rollover the log file.
-#[allow(dead_code)]
 #[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub(crate) enum Rollover {
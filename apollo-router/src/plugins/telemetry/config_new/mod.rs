@@ -1,25 +1,38 @@
 use std::collections::LinkedList;
+use std::time::Duration;
 
 use opentelemetry::baggage::BaggageExt;
 use opentelemetry::trace::TraceContextExt;
 use opentelemetry::trace::TraceId;
 use opentelemetry::KeyValue;
 use paste::paste;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::JsonSchema;
 use tower::BoxError;
 use tracing::Span;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config_new::attributes::DefaultAttributeRequirementLevel;
+use crate::plugins::telemetry::config_new::conditions::SelectorOrValue;
+use crate::plugins::telemetry::config_new::selectors::RouterSelector;
+use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
+use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
 
 /// These modules contain a new config structure for telemetry that will progressively move to
 pub(crate) mod attributes;
+pub(crate) mod bucketing;
+pub(crate) mod cardinality;
 pub(crate) mod conditions;
 
 pub(crate) mod events;
 mod experimental_when_header;
 pub(crate) mod extendable;
+pub(crate) mod field_events;
+pub(crate) mod field_usage;
 pub(crate) mod instruments;
+pub(crate) mod json_path;
 pub(crate) mod logging;
 pub(crate) mod selectors;
 pub(crate) mod spans;
@@ -38,6 +51,30 @@ pub(crate) trait Selector {
 
     fn on_request(&self, request: &Self::Request) -> Option<opentelemetry::Value>;
     fn on_response(&self, response: &Self::Response) -> Option<opentelemetry::Value>;
+    fn on_error(&self, _error: &BoxError) -> Option<opentelemetry::Value> {
+        None
+    }
+    /// The value of the selector when the service failed after `elapsed`. Only the selectors of
+    /// the elapsed time have one, as there is no response to read it from
+    fn on_elapsed(&self, _elapsed: Duration) -> Option<opentelemetry::Value> {
+        None
+    }
+}
+
+/// The schemas of the selectors, shared as definitions of the configuration schema rather than
+/// inlined in each of the attributes, conditions, instruments and events using them
+pub(crate) fn shared_schemas(gen: &mut SchemaGenerator) -> Vec<(String, Schema)> {
+    fn shared<T: JsonSchema>(gen: &mut SchemaGenerator) -> (String, Schema) {
+        (T::schema_name(), gen.subschema_for::<T>())
+    }
+    vec![
+        shared::<SelectorOrValue<RouterSelector>>(gen),
+        shared::<RouterSelector>(gen),
+        shared::<SelectorOrValue<SupergraphSelector>>(gen),
+        shared::<SupergraphSelector>(gen),
+        shared::<SelectorOrValue<SubgraphSelector>>(gen),
+        shared::<SubgraphSelector>(gen),
+    ]
 }
 
 pub(crate) trait DefaultForLevel {
@@ -59,12 +96,18 @@ pub(crate) trait DefaultForLevel {
 
 pub(crate) trait DatadogId {
     fn to_datadog(&self) -> String;
+    fn to_datadog_128bit(&self) -> String;
 }
 impl DatadogId for TraceId {
     fn to_datadog(&self) -> String {
         let bytes = &self.to_bytes()[std::mem::size_of::<u64>()..std::mem::size_of::<u128>()];
         u64::from_be_bytes(bytes.try_into().unwrap()).to_string()
     }
+
+    /// 128-bit ids are written in hexadecimal by Datadog tracers
+    fn to_datadog_128bit(&self) -> String {
+        format!("{:032x}", u128::from_be_bytes(self.to_bytes()))
+    }
 }
 
 pub(crate) fn trace_id() -> Option<TraceId> {
@@ -178,6 +221,8 @@ mod test {
         let trace_id = TraceId::from_hex("234e10d9e749a0a19e94ac0e4a896aee").unwrap();
         let dd_id = trace_id.to_datadog();
         assert_eq!(dd_id, "11426947331925830382");
+        let dd_id = trace_id.to_datadog_128bit();
+        assert_eq!(dd_id, "234e10d9e749a0a19e94ac0e4a896aee");
     }
 
     #[test]
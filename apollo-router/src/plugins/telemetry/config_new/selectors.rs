@@ -1,23 +1,45 @@
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+
 use access_json::JSONQuery;
+use http::header::CONTENT_LENGTH;
+use regex::Regex;
+use router_bridge::planner::UsageReporting;
 use schemars::JsonSchema;
 use serde::Deserialize;
 #[cfg(test)]
 use serde::Serialize;
 use serde_json_bytes::ByteString;
 use sha2::Digest;
+use tower::BoxError;
 
 use crate::context::OPERATION_KIND;
 use crate::context::OPERATION_NAME;
+use crate::error::FetchError;
+use crate::graphql;
+use crate::json_ext::Object;
+use crate::json_ext::PathElement;
 use crate::plugin::serde::deserialize_json_query;
+use crate::plugin::serde::deserialize_regex;
 use crate::plugins::telemetry::config::AttributeValue;
+use crate::plugins::telemetry::config_new::attributes::header_value;
+use crate::plugins::telemetry::config_new::bucketing::Bucketing;
 use crate::plugins::telemetry::config_new::get_baggage;
+use crate::plugins::telemetry::config_new::json_path::JsonPath;
+use crate::plugins::telemetry::config_new::json_path::JsonPathAggregation;
 use crate::plugins::telemetry::config_new::trace_id;
 use crate::plugins::telemetry::config_new::DatadogId;
 use crate::plugins::telemetry::config_new::Selector;
 use crate::plugins::telemetry::config_new::ToOtelValue;
+use crate::plugins::telemetry::variable_redaction::RedactedVariables;
+use crate::plugins::telemetry::variable_redaction::REDACTED_VALUE;
+use crate::query_planner::stats_report_key_hash;
+use crate::services::execution;
 use crate::services::router;
 use crate::services::subgraph;
 use crate::services::supergraph;
+use crate::Context;
 
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
@@ -26,6 +48,9 @@ pub(crate) enum TraceIdFormat {
     OpenTelemetry,
     /// Datadog trace ID, a u64.
     Datadog,
+    /// Datadog 128-bit trace ID, a hex string.
+    #[serde(rename = "datadog_128bit")]
+    Datadog128bit,
 }
 
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
@@ -38,6 +63,30 @@ pub(crate) enum OperationName {
     Hash,
 }
 
+impl OperationName {
+    /// Formats an operation name as configured
+    fn format(&self, op_name: Option<String>) -> Option<String> {
+        match self {
+            OperationName::String => op_name,
+            OperationName::Hash => op_name.map(|op_name| {
+                let mut hasher = sha2::Sha256::new();
+                hasher.update(op_name.as_bytes());
+                hex::encode(hasher.finalize())
+            }),
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum OperationSignature {
+    /// The normalized operation signature, as reported to GraphOS.
+    String,
+    /// The operation ID of GraphOS, a hash of the operation signature.
+    Hash,
+}
+
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
 #[cfg_attr(test, derive(Serialize))]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
@@ -56,6 +105,18 @@ pub(crate) enum ResponseStatus {
     Reason,
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum ResponseErrors {
+    /// The number of GraphQL errors.
+    Count,
+    /// The `code` extension of the first GraphQL error.
+    Code,
+    /// The value at this dot separated path in the extensions of the first GraphQL error, such as `service.name`.
+    Extension(String),
+}
+
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
 #[cfg_attr(test, derive(Serialize))]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
@@ -64,6 +125,118 @@ pub(crate) enum OperationKind {
     String,
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[cfg_attr(test, derive(Serialize))]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum Elapsed {
+    /// The elapsed time in milliseconds.
+    Milliseconds,
+    /// The elapsed time in seconds.
+    Seconds,
+}
+
+/// The time a stage took to respond, set by the telemetry plugin in the extensions of the
+/// responses of the router, supergraph and subgraph stages
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StageElapsed(pub(crate) Duration);
+
+/// The JSON body of a subgraph response, converted the first time a `subgraph_response_body_path`
+/// selector needs it. The telemetry plugin sets an empty one in the extensions of the subgraph
+/// responses, so that the body is converted once per response rather than once per selector.
+#[derive(Debug, Default)]
+pub(crate) struct SubgraphResponseJson(OnceLock<Option<serde_json::Value>>);
+
+impl SubgraphResponseJson {
+    fn select(
+        response: &subgraph::Response,
+        path: &JsonPath,
+        aggregation: &JsonPathAggregation,
+    ) -> Option<serde_json::Value> {
+        let convert = || serde_json::to_value(response.response.body()).ok();
+        match response.response.extensions().get::<SubgraphResponseJson>() {
+            Some(json) => path.select(json.0.get_or_init(convert).as_ref()?, aggregation),
+            None => path.select(&convert()?, aggregation),
+        }
+    }
+}
+
+impl Elapsed {
+    fn select(&self, extensions: &http::Extensions) -> Option<opentelemetry::Value> {
+        Some(self.value(extensions.get::<StageElapsed>()?.0))
+    }
+
+    fn value(&self, elapsed: Duration) -> opentelemetry::Value {
+        opentelemetry::Value::F64(match self {
+            Elapsed::Milliseconds => elapsed.as_secs_f64() * 1000.0,
+            Elapsed::Seconds => elapsed.as_secs_f64(),
+        })
+    }
+}
+
+/// Redaction applied to a selected value before it is attached to telemetry
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) enum Redact {
+    /// Replace the value with its SHA-256 hash.
+    Hash,
+    /// Keep at most this number of characters of the value.
+    Truncate(usize),
+    /// Replace the parts of the value matching a regular expression.
+    Replace {
+        /// The regular expression.
+        #[schemars(with = "String")]
+        #[serde(deserialize_with = "deserialize_regex")]
+        pattern: Regex,
+        /// The replacement, which can refer to capture groups like `$1`.
+        #[serde(default = "default_replacement")]
+        replacement: String,
+    },
+    /// Replace the value with its bucket, a number deterministically assigned from the value.
+    Bucket(Bucketing),
+}
+
+fn default_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+impl Redact {
+    /// Values are redacted as strings
+    pub(crate) fn apply(&self, value: opentelemetry::Value) -> opentelemetry::Value {
+        let value = value.as_str();
+        match self {
+            Redact::Hash => hex::encode(sha2::Sha256::digest(value.as_bytes())).into(),
+            Redact::Truncate(length) => value.chars().take(*length).collect::<String>().into(),
+            Redact::Replace {
+                pattern,
+                replacement,
+            } => pattern
+                .replace_all(&value, replacement.as_str())
+                .into_owned()
+                .into(),
+            Redact::Bucket(bucketing) => (bucketing.bucket(&value) as i64).into(),
+        }
+    }
+}
+
+/// The value of a selected header, typed only if the selector asks for it
+fn selected_header_value(value: &http::HeaderValue, typed: bool) -> Option<opentelemetry::Value> {
+    if typed {
+        header_value(value)
+    } else {
+        Some(value.to_str().ok()?.to_string().into())
+    }
+}
+
+fn redact(
+    redact: Option<&Redact>,
+    value: Option<opentelemetry::Value>,
+) -> Option<opentelemetry::Value> {
+    match redact {
+        Some(redact) => value.map(|value| redact.apply(value)),
+        None => value,
+    }
+}
+
 #[derive(Deserialize, JsonSchema, Clone, Debug)]
 #[serde(deny_unknown_fields, untagged)]
 pub(crate) enum RouterSelector {
@@ -71,10 +244,38 @@ pub(crate) enum RouterSelector {
     RequestHeader {
         /// The name of the request header.
         request_header: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    /// A value from the JSON body of the request
+    RequestBody {
+        /// The request body json path, for example `.extensions.clientInfo.tenant`.
+        #[schemars(with = "String")]
+        #[serde(deserialize_with = "deserialize_json_query")]
+        request_body: JSONQuery,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    /// The values matched by a JSONPath expression in the JSON body of the request
+    RequestBodyPath {
+        /// The JSONPath expression, for example `$.variables.ids[*]`.
+        #[schemars(with = "String")]
+        request_body_path: JsonPath,
+        /// How the matching values are aggregated (default: first).
+        #[serde(default)]
+        aggregation: JsonPathAggregation,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
@@ -82,10 +283,12 @@ pub(crate) enum RouterSelector {
     ResponseHeader {
         /// The name of the request header.
         response_header: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
@@ -99,14 +302,18 @@ pub(crate) enum RouterSelector {
         /// The format of the trace ID.
         trace_id: TraceIdFormat,
     },
+    /// The time the router service took to respond, available on the response or error.
+    Elapsed {
+        /// The unit of the elapsed time.
+        elapsed: Elapsed,
+    },
     /// A value from context.
     ResponseContext {
         /// The response context key.
         response_context: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
@@ -114,10 +321,9 @@ pub(crate) enum RouterSelector {
     Baggage {
         /// The name of the baggage item.
         baggage: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
@@ -125,10 +331,9 @@ pub(crate) enum RouterSelector {
     Env {
         /// The name of the environment variable
         env: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
@@ -141,10 +346,18 @@ pub(crate) enum SupergraphSelector {
     OperationName {
         /// The operation name from the query.
         operation_name: OperationName,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<String>,
+    },
+    OperationSignature {
+        /// The operation signature reported to GraphOS, available on the response.
+        operation_signature: OperationSignature,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
@@ -159,80 +372,111 @@ pub(crate) enum SupergraphSelector {
         // Allow dead code is required because there is only one variant in Query and we need to avoid the dead code warning.
         #[allow(dead_code)]
         query: Query,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
     QueryVariable {
         /// The name of a graphql query variable.
         query_variable: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     RequestHeader {
         /// The name of the request header.
         request_header: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
         /// Optional default value.
         default: Option<String>,
     },
     ResponseHeader {
         /// The name of the response header.
         response_header: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
         /// Optional default value.
         default: Option<String>,
     },
+    ResponseErrors {
+        /// The GraphQL errors of the first response.
+        response_errors: ResponseErrors,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    ErrorExtension {
+        /// The dot separated path of a value in the extensions of the first matching GraphQL
+        /// error of the first response or of the error, such as `upstream.code`.
+        error_extension: String,
+        /// Only match the errors with this `code` extension.
+        code: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    Elapsed {
+        /// The time the supergraph service took to send the first response, available on the
+        /// response or error.
+        elapsed: Elapsed,
+    },
+    ResponseData {
+        /// The dot separated path of a value in the `data` of the response, such as
+        /// `checkout.totalAmount`. Array elements are selected by their index.
+        response_data: String,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
     RequestContext {
         /// The request context key.
         request_context: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     ResponseContext {
         /// The response context key.
         response_context: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     Baggage {
         /// The name of the baggage item.
         baggage: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     Env {
         /// The name of the environment variable
         env: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
@@ -244,10 +488,9 @@ pub(crate) enum SubgraphSelector {
     SubgraphOperationName {
         /// The operation name from the subgraph query.
         subgraph_operation_name: OperationName,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
@@ -262,20 +505,18 @@ pub(crate) enum SubgraphSelector {
         // Allow dead code is required because there is only one variant in Query and we need to avoid the dead code warning.
         #[allow(dead_code)]
         subgraph_query: Query,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
     SubgraphQueryVariable {
         /// The name of a subgraph query variable.
         subgraph_query_variable: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
@@ -284,30 +525,47 @@ pub(crate) enum SubgraphSelector {
         #[schemars(with = "String")]
         #[serde(deserialize_with = "deserialize_json_query")]
         subgraph_response_body: JSONQuery,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    SubgraphResponseBodyPath {
+        /// The JSONPath expression matching values in the subgraph response body, for example
+        /// `$.errors[?(@.extensions.code == 'FORBIDDEN')]`.
+        #[schemars(with = "String")]
+        subgraph_response_body_path: JsonPath,
+        /// How the matching values are aggregated (default: first).
+        #[serde(default)]
+        aggregation: JsonPathAggregation,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     SubgraphRequestHeader {
         /// The name of a subgraph request header.
         subgraph_request_header: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
         /// Optional default value.
         default: Option<String>,
     },
     SubgraphResponseHeader {
         /// The name of a subgraph response header.
         subgraph_response_header: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
         /// Optional default value.
         default: Option<String>,
     },
@@ -315,13 +573,43 @@ pub(crate) enum SubgraphSelector {
         /// The subgraph http response status code.
         subgraph_response_status: ResponseStatus,
     },
+    SubgraphResponseErrors {
+        /// The GraphQL errors of the subgraph response.
+        subgraph_response_errors: ResponseErrors,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    Elapsed {
+        /// The time the subgraph service took to respond, available on the response or error.
+        elapsed: Elapsed,
+    },
+    ErrorExtension {
+        /// The dot separated path of a value in the extensions of the first matching GraphQL
+        /// error of the subgraph response or of the error, such as `upstream.code`.
+        error_extension: String,
+        /// Only match the errors with this `code` extension.
+        code: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
     SupergraphOperationName {
         /// The supergraph query operation name.
         supergraph_operation_name: OperationName,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<String>,
+    },
+    SupergraphOperationSignature {
+        /// The supergraph operation signature reported to GraphOS.
+        supergraph_operation_signature: OperationSignature,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
@@ -336,90 +624,237 @@ pub(crate) enum SubgraphSelector {
         // Allow dead code is required because there is only one variant in Query and we need to avoid the dead code warning.
         #[allow(dead_code)]
         supergraph_query: Query,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
     SupergraphQueryVariable {
         /// The supergraph query variable name.
         supergraph_query_variable: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     SupergraphRequestHeader {
         /// The supergraph request header name.
         supergraph_request_header: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
         /// Optional default value.
         default: Option<String>,
     },
     RequestContext {
         /// The request context key.
         request_context: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     ResponseContext {
         /// The response context key.
         response_context: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     Baggage {
         /// The name of the baggage item.
         baggage: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<AttributeValue>,
     },
     Env {
         /// The name of the environment variable
         env: String,
-        #[serde(skip)]
-        #[allow(dead_code)]
-        /// Optional redaction pattern.
-        redact: Option<String>,
+        /// Optional redaction of the value.
+        #[serde(skip_serializing)]
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<String>,
+    },
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug)]
+#[serde(deny_unknown_fields, untagged)]
+pub(crate) enum ExecutionSelector {
+    OperationName {
+        /// The operation name from the query.
+        operation_name: OperationName,
+        /// Optional redaction of the value.
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<String>,
+    },
+    OperationSignature {
+        /// The operation signature reported to GraphOS.
+        operation_signature: OperationSignature,
+        /// Optional redaction of the value.
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<String>,
+    },
+    RequestHeader {
+        /// The name of the request header.
+        request_header: String,
+        /// Optional redaction of the value.
+        redact: Option<Redact>,
+        /// Emit the value as an integer, a float or a boolean when it parses as one, instead of a string (default: false).
+        #[serde(default)]
+        typed: bool,
+        /// Optional default value.
+        default: Option<String>,
+    },
+    RequestContext {
+        /// The request context key.
+        request_context: String,
+        /// Optional redaction of the value.
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    ResponseContext {
+        /// The response context key.
+        response_context: String,
+        /// Optional redaction of the value.
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    Baggage {
+        /// The name of the baggage item.
+        baggage: String,
+        /// Optional redaction of the value.
+        redact: Option<Redact>,
+        /// Optional default value.
+        default: Option<AttributeValue>,
+    },
+    Env {
+        /// The name of the environment variable
+        env: String,
+        /// Optional redaction of the value.
+        redact: Option<Redact>,
         /// Optional default value.
         default: Option<String>,
     },
 }
 
+/// Request bodies larger than this are not buffered for the `request_body` selector
+const MAX_BUFFERED_REQUEST_BODY: usize = 2_000_000;
+
+/// The JSON body of the router request, only available if a `request_body` selector is configured
+pub(crate) struct RouterRequestBody(serde_json::Value);
+
+impl RouterRequestBody {
+    /// Buffers the request body and stores it in the context, so that it can be selected while
+    /// the request is still streamed to the router service. Bodies without a `content-length`
+    /// header or larger than 2MB are left untouched.
+    pub(crate) async fn buffer(request: router::Request) -> Result<router::Request, BoxError> {
+        let content_length = request
+            .router_request
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok());
+        if !matches!(content_length, Some(length) if length <= MAX_BUFFERED_REQUEST_BODY) {
+            return Ok(request);
+        }
+
+        let (parts, body) = request.router_request.into_parts();
+        let bytes = hyper::body::to_bytes(body).await?;
+        if let Ok(body) = serde_json::from_slice(&bytes) {
+            request
+                .context
+                .private_entries
+                .lock()
+                .insert(RouterRequestBody(body));
+        }
+        Ok(router::Request {
+            router_request: http::Request::from_parts(parts, bytes.into()),
+            context: request.context,
+        })
+    }
+}
+
+impl RouterSelector {
+    /// Returns true if the selector needs the request body to be buffered
+    pub(crate) fn needs_request_body(&self) -> bool {
+        matches!(
+            self,
+            RouterSelector::RequestBody { .. } | RouterSelector::RequestBodyPath { .. }
+        )
+    }
+
+    fn redact(&self) -> Option<&Redact> {
+        match self {
+            RouterSelector::RequestHeader { redact, .. }
+            | RouterSelector::RequestBody { redact, .. }
+            | RouterSelector::RequestBodyPath { redact, .. }
+            | RouterSelector::ResponseHeader { redact, .. }
+            | RouterSelector::ResponseContext { redact, .. }
+            | RouterSelector::Baggage { redact, .. }
+            | RouterSelector::Env { redact, .. } => redact.as_ref(),
+            _ => None,
+        }
+    }
+}
+
 impl Selector for RouterSelector {
     type Request = router::Request;
     type Response = router::Response;
 
     fn on_request(&self, request: &router::Request) -> Option<opentelemetry::Value> {
-        match self {
+        let value = match self {
             RouterSelector::RequestHeader {
                 request_header,
                 default,
+                typed,
                 ..
             } => request
                 .router_request
                 .headers()
                 .get(request_header)
-                .and_then(|h| Some(h.to_str().ok()?.to_string().into()))
+                .and_then(|value| selected_header_value(value, *typed))
+                .or_else(|| default.maybe_to_otel_value()),
+            RouterSelector::RequestBody {
+                request_body,
+                default,
+                ..
+            } => request
+                .context
+                .private_entries
+                .lock()
+                .get::<RouterRequestBody>()
+                .and_then(|body| request_body.execute(&body.0).ok().flatten())
+                .as_ref()
+                .and_then(|v| v.maybe_to_otel_value())
+                .or_else(|| default.maybe_to_otel_value()),
+            RouterSelector::RequestBodyPath {
+                request_body_path,
+                aggregation,
+                default,
+                ..
+            } => request
+                .context
+                .private_entries
+                .lock()
+                .get::<RouterRequestBody>()
+                .and_then(|body| request_body_path.select(&body.0, aggregation))
+                .as_ref()
+                .and_then(|v| v.maybe_to_otel_value())
                 .or_else(|| default.maybe_to_otel_value()),
             RouterSelector::Env { env, default, .. } => std::env::var(env)
                 .ok()
@@ -431,6 +866,7 @@ impl Selector for RouterSelector {
                 match trace_id_format {
                     TraceIdFormat::OpenTelemetry => id.to_string(),
                     TraceIdFormat::Datadog => id.to_datadog(),
+                    TraceIdFormat::Datadog128bit => id.to_datadog_128bit(),
                 }
                 .into()
             }),
@@ -439,20 +875,22 @@ impl Selector for RouterSelector {
             } => get_baggage(baggage).or_else(|| default.maybe_to_otel_value()),
             // Related to Response
             _ => None,
-        }
+        };
+        redact(self.redact(), value)
     }
 
     fn on_response(&self, response: &router::Response) -> Option<opentelemetry::Value> {
-        match self {
+        let value = match self {
             RouterSelector::ResponseHeader {
                 response_header,
                 default,
+                typed,
                 ..
             } => response
                 .response
                 .headers()
                 .get(response_header)
-                .and_then(|h| Some(h.to_str().ok()?.to_string().into()))
+                .and_then(|value| selected_header_value(value, *typed))
                 .or_else(|| default.maybe_to_otel_value()),
             RouterSelector::ResponseStatus { response_status } => match response_status {
                 ResponseStatus::Code => Some(opentelemetry::Value::I64(
@@ -479,6 +917,182 @@ impl Selector for RouterSelector {
             RouterSelector::Baggage {
                 baggage, default, ..
             } => get_baggage(baggage).or_else(|| default.maybe_to_otel_value()),
+            RouterSelector::Elapsed { elapsed } => elapsed.select(response.response.extensions()),
+            _ => None,
+        };
+        redact(self.redact(), value)
+    }
+
+    fn on_elapsed(&self, elapsed: Duration) -> Option<opentelemetry::Value> {
+        match self {
+            RouterSelector::Elapsed { elapsed: unit } => Some(unit.value(elapsed)),
+            _ => None,
+        }
+    }
+}
+
+/// The value of a variable, unless the variable is redacted for this request
+fn query_variable_value(
+    context: &Context,
+    variables: &Object,
+    name: &str,
+) -> Option<opentelemetry::Value> {
+    let value = variables
+        .get(&ByteString::from(name))
+        .and_then(|v| v.maybe_to_otel_value())?;
+    let redacted = context
+        .private_entries
+        .lock()
+        .get::<RedactedVariables>()
+        .map(|redacted_variables| redacted_variables.contains(name))
+        .unwrap_or_default();
+    if redacted {
+        Some(REDACTED_VALUE.into())
+    } else {
+        Some(value)
+    }
+}
+
+/// The operation signature of the request, known once its query plan has been obtained
+fn operation_signature(context: &Context, format: &OperationSignature) -> Option<String> {
+    let signature = context
+        .private_entries
+        .lock()
+        .get::<UsageReporting>()
+        .map(|usage_reporting| usage_reporting.stats_report_key.clone())?;
+    match format {
+        OperationSignature::String => Some(signature),
+        OperationSignature::Hash => Some(stats_report_key_hash(&signature)),
+    }
+}
+
+/// The GraphQL errors of the first supergraph response, stored in the context's private entries
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FirstResponseErrors(pub(crate) Arc<Vec<graphql::Error>>);
+
+/// The data of the first supergraph response, stored in the context's private entries if a
+/// `response_data` selector is configured
+#[derive(Clone, Debug)]
+pub(crate) struct FirstResponseData(pub(crate) Arc<serde_json_bytes::Value>);
+
+/// The value at a dot separated path, indexing arrays with the numeric segments
+fn select_path<'a, 'b>(
+    mut value: &'a serde_json_bytes::Value,
+    keys: impl IntoIterator<Item = &'b str>,
+) -> Option<&'a serde_json_bytes::Value> {
+    for key in keys {
+        value = match value {
+            serde_json_bytes::Value::Array(values) => values.get(key.parse::<usize>().ok()?)?,
+            value => value.as_object()?.get(key)?,
+        };
+    }
+    Some(value)
+}
+
+/// The value at a dot separated path in the extensions of the first error with the `code`
+/// extension, or of the first error if no code is set
+fn select_error_extension(
+    errors: &[graphql::Error],
+    path: &str,
+    code: Option<&str>,
+) -> Option<opentelemetry::Value> {
+    let error = errors.iter().find(|error| {
+        code.map_or(true, |code| {
+            error.extensions.get("code").and_then(|c| c.as_str()) == Some(code)
+        })
+    })?;
+    let mut keys = path.split('.');
+    let value = error.extensions.get(keys.next()?)?;
+    select_path(value, keys)?.maybe_to_otel_value()
+}
+
+/// The GraphQL error of a service error, if it is a fetch error
+fn fetch_error(error: &BoxError) -> Option<graphql::Error> {
+    Some(error.downcast_ref::<FetchError>()?.to_graphql_error(None))
+}
+
+impl ResponseErrors {
+    fn select(&self, errors: &[graphql::Error]) -> Option<opentelemetry::Value> {
+        match self {
+            ResponseErrors::Count => Some(opentelemetry::Value::I64(errors.len() as i64)),
+            ResponseErrors::Code => errors
+                .first()?
+                .extensions
+                .get("code")?
+                .maybe_to_otel_value(),
+            ResponseErrors::Extension(path) => {
+                let mut keys = path.split('.');
+                let first = keys.next()?;
+                let mut value = errors.first()?.extensions.get(first)?;
+                for key in keys {
+                    value = value.as_object()?.get(key)?;
+                }
+                value.maybe_to_otel_value()
+            }
+        }
+    }
+}
+
+impl SupergraphSelector {
+    /// Returns true if the selector needs the data of the first response to be recorded
+    pub(crate) fn needs_response_data(&self) -> bool {
+        matches!(self, SupergraphSelector::ResponseData { .. })
+    }
+
+    /// Returns true if the selector needs the errors of the first response to be recorded
+    pub(crate) fn needs_response_errors(&self) -> bool {
+        matches!(
+            self,
+            SupergraphSelector::ResponseErrors { .. } | SupergraphSelector::ErrorExtension { .. }
+        )
+    }
+
+    /// The value selected in the incremental payloads of a deferred response, for the
+    /// `response_data` paths that are not part of the primary response
+    pub(crate) fn on_deferred_response(
+        &self,
+        response: &graphql::Response,
+    ) -> Option<opentelemetry::Value> {
+        let SupergraphSelector::ResponseData {
+            response_data,
+            redact: redaction,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let value = response.incremental.iter().find_map(|incremental| {
+            let mut keys = response_data.split('.');
+            for element in incremental.path.iter().flat_map(|path| path.iter()) {
+                let key = keys.next()?;
+                let matches = match element {
+                    PathElement::Key(name) => name == key,
+                    PathElement::Index(index) => key.parse() == Ok(*index),
+                    _ => false,
+                };
+                if !matches {
+                    return None;
+                }
+            }
+            select_path(incremental.data.as_ref()?, keys)?.maybe_to_otel_value()
+        });
+        redact(redaction.as_ref(), value)
+    }
+
+    fn redact(&self) -> Option<&Redact> {
+        match self {
+            SupergraphSelector::OperationName { redact, .. }
+            | SupergraphSelector::OperationSignature { redact, .. }
+            | SupergraphSelector::Query { redact, .. }
+            | SupergraphSelector::QueryVariable { redact, .. }
+            | SupergraphSelector::RequestHeader { redact, .. }
+            | SupergraphSelector::ResponseHeader { redact, .. }
+            | SupergraphSelector::ResponseData { redact, .. }
+            | SupergraphSelector::ErrorExtension { redact, .. }
+            | SupergraphSelector::RequestContext { redact, .. }
+            | SupergraphSelector::ResponseContext { redact, .. }
+            | SupergraphSelector::Baggage { redact, .. }
+            | SupergraphSelector::Env { redact, .. } => redact.as_ref(),
             _ => None,
         }
     }
@@ -489,23 +1103,16 @@ impl Selector for SupergraphSelector {
     type Response = supergraph::Response;
 
     fn on_request(&self, request: &supergraph::Request) -> Option<opentelemetry::Value> {
-        match self {
+        let value = match self {
             SupergraphSelector::OperationName {
                 operation_name,
                 default,
                 ..
             } => {
                 let op_name = request.context.get(OPERATION_NAME).ok().flatten();
-                match operation_name {
-                    OperationName::String => op_name.or_else(|| default.clone()),
-                    OperationName::Hash => op_name.or_else(|| default.clone()).map(|op_name| {
-                        let mut hasher = sha2::Sha256::new();
-                        hasher.update(op_name.as_bytes());
-                        let result = hasher.finalize();
-                        hex::encode(result)
-                    }),
-                }
-                .map(opentelemetry::Value::from)
+                operation_name
+                    .format(op_name.or_else(|| default.clone()))
+                    .map(opentelemetry::Value::from)
             }
             SupergraphSelector::OperationKind { .. } => request
                 .context
@@ -524,25 +1131,24 @@ impl Selector for SupergraphSelector {
             SupergraphSelector::RequestHeader {
                 request_header,
                 default,
+                typed,
                 ..
             } => request
                 .supergraph_request
                 .headers()
                 .get(request_header)
-                .and_then(|h| Some(h.to_str().ok()?.to_string()))
-                .or_else(|| default.clone())
-                .map(opentelemetry::Value::from),
+                .and_then(|value| selected_header_value(value, *typed))
+                .or_else(|| default.clone().map(opentelemetry::Value::from)),
             SupergraphSelector::QueryVariable {
                 query_variable,
                 default,
                 ..
-            } => request
-                .supergraph_request
-                .body()
-                .variables
-                .get(&ByteString::from(query_variable.as_str()))
-                .and_then(|v| v.maybe_to_otel_value())
-                .or_else(|| default.maybe_to_otel_value()),
+            } => query_variable_value(
+                &request.context,
+                &request.supergraph_request.body().variables,
+                query_variable,
+            )
+            .or_else(|| default.maybe_to_otel_value()),
             SupergraphSelector::RequestContext {
                 request_context,
                 default,
@@ -564,22 +1170,74 @@ impl Selector for SupergraphSelector {
                 .map(opentelemetry::Value::from),
             // For response
             _ => None,
-        }
+        };
+        redact(self.redact(), value)
     }
 
     fn on_response(&self, response: &supergraph::Response) -> Option<opentelemetry::Value> {
-        match self {
+        let value = match self {
+            SupergraphSelector::OperationSignature {
+                operation_signature: signature,
+                default,
+                ..
+            } => operation_signature(&response.context, signature)
+                .or_else(|| default.clone())
+                .map(opentelemetry::Value::from),
             SupergraphSelector::ResponseHeader {
                 response_header,
                 default,
+                typed,
                 ..
             } => response
                 .response
                 .headers()
                 .get(response_header)
-                .and_then(|h| Some(h.to_str().ok()?.to_string()))
-                .or_else(|| default.clone())
-                .map(opentelemetry::Value::from),
+                .and_then(|value| selected_header_value(value, *typed))
+                .or_else(|| default.clone().map(opentelemetry::Value::from)),
+            SupergraphSelector::ResponseErrors {
+                response_errors,
+                default,
+            } => {
+                let errors = response
+                    .context
+                    .private_entries
+                    .lock()
+                    .get::<FirstResponseErrors>()
+                    .cloned()
+                    .unwrap_or_default();
+                response_errors
+                    .select(&errors.0)
+                    .or_else(|| default.maybe_to_otel_value())
+            }
+            SupergraphSelector::ErrorExtension {
+                error_extension,
+                code,
+                default,
+                ..
+            } => {
+                let errors = response
+                    .context
+                    .private_entries
+                    .lock()
+                    .get::<FirstResponseErrors>()
+                    .cloned()
+                    .unwrap_or_default();
+                select_error_extension(&errors.0, error_extension, code.as_deref())
+                    .or_else(|| default.maybe_to_otel_value())
+            }
+            SupergraphSelector::ResponseData {
+                response_data,
+                default,
+                ..
+            } => response
+                .context
+                .private_entries
+                .lock()
+                .get::<FirstResponseData>()
+                .and_then(|data| {
+                    select_path(&data.0, response_data.split('.'))?.maybe_to_otel_value()
+                })
+                .or_else(|| default.maybe_to_otel_value()),
             SupergraphSelector::ResponseContext {
                 response_context,
                 default,
@@ -592,8 +1250,61 @@ impl Selector for SupergraphSelector {
                 .as_ref()
                 .and_then(|v| v.maybe_to_otel_value())
                 .or_else(|| default.maybe_to_otel_value()),
+            SupergraphSelector::Elapsed { elapsed } => {
+                elapsed.select(response.response.extensions())
+            }
             // For request
             _ => None,
+        };
+        redact(self.redact(), value)
+    }
+
+    fn on_error(&self, error: &BoxError) -> Option<opentelemetry::Value> {
+        let value = match self {
+            SupergraphSelector::ErrorExtension {
+                error_extension,
+                code,
+                default,
+                ..
+            } => fetch_error(error)
+                .and_then(|error| {
+                    select_error_extension(&[error], error_extension, code.as_deref())
+                })
+                .or_else(|| default.maybe_to_otel_value()),
+            _ => None,
+        };
+        redact(self.redact(), value)
+    }
+
+    fn on_elapsed(&self, elapsed: Duration) -> Option<opentelemetry::Value> {
+        match self {
+            SupergraphSelector::Elapsed { elapsed: unit } => Some(unit.value(elapsed)),
+            _ => None,
+        }
+    }
+}
+
+impl SubgraphSelector {
+    fn redact(&self) -> Option<&Redact> {
+        match self {
+            SubgraphSelector::SubgraphOperationName { redact, .. }
+            | SubgraphSelector::SubgraphQuery { redact, .. }
+            | SubgraphSelector::SubgraphQueryVariable { redact, .. }
+            | SubgraphSelector::SubgraphResponseBody { redact, .. }
+            | SubgraphSelector::SubgraphResponseBodyPath { redact, .. }
+            | SubgraphSelector::ErrorExtension { redact, .. }
+            | SubgraphSelector::SubgraphRequestHeader { redact, .. }
+            | SubgraphSelector::SubgraphResponseHeader { redact, .. }
+            | SubgraphSelector::SupergraphOperationName { redact, .. }
+            | SubgraphSelector::SupergraphOperationSignature { redact, .. }
+            | SubgraphSelector::SupergraphQuery { redact, .. }
+            | SubgraphSelector::SupergraphQueryVariable { redact, .. }
+            | SubgraphSelector::SupergraphRequestHeader { redact, .. }
+            | SubgraphSelector::RequestContext { redact, .. }
+            | SubgraphSelector::ResponseContext { redact, .. }
+            | SubgraphSelector::Baggage { redact, .. }
+            | SubgraphSelector::Env { redact, .. } => redact.as_ref(),
+            _ => None,
         }
     }
 }
@@ -603,23 +1314,16 @@ impl Selector for SubgraphSelector {
     type Response = subgraph::Response;
 
     fn on_request(&self, request: &subgraph::Request) -> Option<opentelemetry::Value> {
-        match self {
+        let value = match self {
             SubgraphSelector::SubgraphOperationName {
                 subgraph_operation_name,
                 default,
                 ..
             } => {
                 let op_name = request.subgraph_request.body().operation_name.clone();
-                match subgraph_operation_name {
-                    OperationName::String => op_name.or_else(|| default.clone()),
-                    OperationName::Hash => op_name.or_else(|| default.clone()).map(|op_name| {
-                        let mut hasher = sha2::Sha256::new();
-                        hasher.update(op_name.as_bytes());
-                        let result = hasher.finalize();
-                        hex::encode(result)
-                    }),
-                }
-                .map(opentelemetry::Value::from)
+                subgraph_operation_name
+                    .format(op_name.or_else(|| default.clone()))
+                    .map(opentelemetry::Value::from)
             }
             SubgraphSelector::SupergraphOperationName {
                 supergraph_operation_name,
@@ -627,17 +1331,17 @@ impl Selector for SubgraphSelector {
                 ..
             } => {
                 let op_name = request.context.get(OPERATION_NAME).ok().flatten();
-                match supergraph_operation_name {
-                    OperationName::String => op_name.or_else(|| default.clone()),
-                    OperationName::Hash => op_name.or_else(|| default.clone()).map(|op_name| {
-                        let mut hasher = sha2::Sha256::new();
-                        hasher.update(op_name.as_bytes());
-                        let result = hasher.finalize();
-                        hex::encode(result)
-                    }),
-                }
-                .map(opentelemetry::Value::from)
+                supergraph_operation_name
+                    .format(op_name.or_else(|| default.clone()))
+                    .map(opentelemetry::Value::from)
             }
+            SubgraphSelector::SupergraphOperationSignature {
+                supergraph_operation_signature,
+                default,
+                ..
+            } => operation_signature(&request.context, supergraph_operation_signature)
+                .or_else(|| default.clone())
+                .map(opentelemetry::Value::from),
             SubgraphSelector::SubgraphOperationKind { .. } => request
                 .context
                 .get::<_, String>(OPERATION_KIND)
@@ -669,47 +1373,45 @@ impl Selector for SubgraphSelector {
                 subgraph_query_variable,
                 default,
                 ..
-            } => request
-                .subgraph_request
-                .body()
-                .variables
-                .get(&ByteString::from(subgraph_query_variable.as_str()))
-                .and_then(|v| v.maybe_to_otel_value())
-                .or_else(|| default.maybe_to_otel_value()),
+            } => query_variable_value(
+                &request.context,
+                &request.subgraph_request.body().variables,
+                subgraph_query_variable,
+            )
+            .or_else(|| default.maybe_to_otel_value()),
 
             SubgraphSelector::SupergraphQueryVariable {
                 supergraph_query_variable,
                 default,
                 ..
-            } => request
-                .supergraph_request
-                .body()
-                .variables
-                .get(&ByteString::from(supergraph_query_variable.as_str()))
-                .and_then(|v| v.maybe_to_otel_value())
-                .or_else(|| default.maybe_to_otel_value()),
+            } => query_variable_value(
+                &request.context,
+                &request.supergraph_request.body().variables,
+                supergraph_query_variable,
+            )
+            .or_else(|| default.maybe_to_otel_value()),
             SubgraphSelector::SubgraphRequestHeader {
                 subgraph_request_header,
                 default,
+                typed,
                 ..
             } => request
                 .subgraph_request
                 .headers()
                 .get(subgraph_request_header)
-                .and_then(|h| Some(h.to_str().ok()?.to_string()))
-                .or_else(|| default.clone())
-                .map(opentelemetry::Value::from),
+                .and_then(|value| selected_header_value(value, *typed))
+                .or_else(|| default.clone().map(opentelemetry::Value::from)),
             SubgraphSelector::SupergraphRequestHeader {
                 supergraph_request_header,
                 default,
+                typed,
                 ..
             } => request
                 .supergraph_request
                 .headers()
                 .get(supergraph_request_header)
-                .and_then(|h| Some(h.to_str().ok()?.to_string()))
-                .or_else(|| default.clone())
-                .map(opentelemetry::Value::from),
+                .and_then(|value| selected_header_value(value, *typed))
+                .or_else(|| default.clone().map(opentelemetry::Value::from)),
             SubgraphSelector::RequestContext {
                 request_context,
                 default,
@@ -734,22 +1436,23 @@ impl Selector for SubgraphSelector {
 
             // For response
             _ => None,
-        }
+        };
+        redact(self.redact(), value)
     }
 
     fn on_response(&self, response: &subgraph::Response) -> Option<opentelemetry::Value> {
-        match self {
+        let value = match self {
             SubgraphSelector::SubgraphResponseHeader {
                 subgraph_response_header,
                 default,
+                typed,
                 ..
             } => response
                 .response
                 .headers()
                 .get(subgraph_response_header)
-                .and_then(|h| Some(h.to_str().ok()?.to_string()))
-                .or_else(|| default.clone())
-                .map(opentelemetry::Value::from),
+                .and_then(|value| selected_header_value(value, *typed))
+                .or_else(|| default.clone().map(opentelemetry::Value::from)),
             SubgraphSelector::SubgraphResponseStatus {
                 subgraph_response_status: response_status,
             } => match response_status {
@@ -762,6 +1465,23 @@ impl Selector for SubgraphSelector {
                     .canonical_reason()
                     .map(|reason| reason.into()),
             },
+            SubgraphSelector::SubgraphResponseErrors {
+                subgraph_response_errors,
+                default,
+            } => subgraph_response_errors
+                .select(&response.response.body().errors)
+                .or_else(|| default.maybe_to_otel_value()),
+            SubgraphSelector::ErrorExtension {
+                error_extension,
+                code,
+                default,
+                ..
+            } => select_error_extension(
+                &response.response.body().errors,
+                error_extension,
+                code.as_deref(),
+            )
+            .or_else(|| default.maybe_to_otel_value()),
             SubgraphSelector::SubgraphResponseBody {
                 subgraph_response_body,
                 default,
@@ -773,6 +1493,15 @@ impl Selector for SubgraphSelector {
                 .as_ref()
                 .and_then(|v| v.maybe_to_otel_value())
                 .or_else(|| default.maybe_to_otel_value()),
+            SubgraphSelector::SubgraphResponseBodyPath {
+                subgraph_response_body_path,
+                aggregation,
+                default,
+                ..
+            } => SubgraphResponseJson::select(response, subgraph_response_body_path, aggregation)
+                .as_ref()
+                .and_then(|v| v.maybe_to_otel_value())
+                .or_else(|| default.maybe_to_otel_value()),
             SubgraphSelector::ResponseContext {
                 response_context,
                 default,
@@ -785,16 +1514,137 @@ impl Selector for SubgraphSelector {
                 .as_ref()
                 .and_then(|v| v.maybe_to_otel_value())
                 .or_else(|| default.maybe_to_otel_value()),
+            SubgraphSelector::Elapsed { elapsed } => elapsed.select(response.response.extensions()),
             // For request
             _ => None,
+        };
+        redact(self.redact(), value)
+    }
+
+    fn on_error(&self, error: &BoxError) -> Option<opentelemetry::Value> {
+        let value = match self {
+            SubgraphSelector::ErrorExtension {
+                error_extension,
+                code,
+                default,
+                ..
+            } => fetch_error(error)
+                .and_then(|error| {
+                    select_error_extension(&[error], error_extension, code.as_deref())
+                })
+                .or_else(|| default.maybe_to_otel_value()),
+            _ => None,
+        };
+        redact(self.redact(), value)
+    }
+
+    fn on_elapsed(&self, elapsed: Duration) -> Option<opentelemetry::Value> {
+        match self {
+            SubgraphSelector::Elapsed { elapsed: unit } => Some(unit.value(elapsed)),
+            _ => None,
         }
     }
 }
 
+impl ExecutionSelector {
+    fn redact(&self) -> Option<&Redact> {
+        match self {
+            ExecutionSelector::OperationName { redact, .. }
+            | ExecutionSelector::OperationSignature { redact, .. }
+            | ExecutionSelector::RequestHeader { redact, .. }
+            | ExecutionSelector::RequestContext { redact, .. }
+            | ExecutionSelector::ResponseContext { redact, .. }
+            | ExecutionSelector::Baggage { redact, .. }
+            | ExecutionSelector::Env { redact, .. } => redact.as_ref(),
+        }
+    }
+}
+
+impl Selector for ExecutionSelector {
+    type Request = execution::Request;
+    type Response = execution::Response;
+
+    fn on_request(&self, request: &execution::Request) -> Option<opentelemetry::Value> {
+        let value = match self {
+            ExecutionSelector::OperationName {
+                operation_name,
+                default,
+                ..
+            } => {
+                let op_name = request.context.get(OPERATION_NAME).ok().flatten();
+                operation_name
+                    .format(op_name.or_else(|| default.clone()))
+                    .map(opentelemetry::Value::from)
+            }
+            ExecutionSelector::OperationSignature {
+                operation_signature: signature,
+                default,
+                ..
+            } => operation_signature(&request.context, signature)
+                .or_else(|| default.clone())
+                .map(opentelemetry::Value::from),
+            ExecutionSelector::RequestHeader {
+                request_header,
+                default,
+                typed,
+                ..
+            } => request
+                .supergraph_request
+                .headers()
+                .get(request_header)
+                .and_then(|value| selected_header_value(value, *typed))
+                .or_else(|| default.clone().map(opentelemetry::Value::from)),
+            ExecutionSelector::RequestContext {
+                request_context,
+                default,
+                ..
+            } => request
+                .context
+                .get::<_, serde_json_bytes::Value>(request_context)
+                .ok()
+                .flatten()
+                .as_ref()
+                .and_then(|v| v.maybe_to_otel_value())
+                .or_else(|| default.maybe_to_otel_value()),
+            ExecutionSelector::Baggage {
+                baggage, default, ..
+            } => get_baggage(baggage).or_else(|| default.maybe_to_otel_value()),
+            ExecutionSelector::Env { env, default, .. } => std::env::var(env)
+                .ok()
+                .or_else(|| default.clone())
+                .map(opentelemetry::Value::from),
+            // For response
+            ExecutionSelector::ResponseContext { .. } => None,
+        };
+        redact(self.redact(), value)
+    }
+
+    fn on_response(&self, response: &execution::Response) -> Option<opentelemetry::Value> {
+        let value = match self {
+            ExecutionSelector::ResponseContext {
+                response_context,
+                default,
+                ..
+            } => response
+                .context
+                .get::<_, serde_json_bytes::Value>(response_context)
+                .ok()
+                .flatten()
+                .as_ref()
+                .and_then(|v| v.maybe_to_otel_value())
+                .or_else(|| default.maybe_to_otel_value()),
+            // For request
+            _ => None,
+        };
+        redact(self.redact(), value)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
 
+    use access_json::JSONQuery;
     use http::StatusCode;
     use opentelemetry::baggage::BaggageExt;
     use opentelemetry::trace::SpanContext;
@@ -805,7 +1655,10 @@ mod test {
     use opentelemetry::trace::TraceState;
     use opentelemetry::Context;
     use opentelemetry::KeyValue;
+    use router_bridge::planner::UsageReporting;
     use serde_json::json;
+    use sha2::Digest;
+    use tower::BoxError;
     use tracing::span;
     use tracing::subscriber;
     use tracing_subscriber::layer::SubscriberExt;
@@ -814,21 +1667,147 @@ mod test {
     use crate::context::OPERATION_NAME;
     use crate::graphql;
     use crate::plugins::telemetry::config::AttributeValue;
+    use crate::plugins::telemetry::config::VariableRedactionConfig;
+    use crate::plugins::telemetry::config_new::json_path::JsonPathAggregation;
+    use crate::plugins::telemetry::config_new::selectors::Elapsed;
+    use crate::plugins::telemetry::config_new::selectors::ExecutionSelector;
+    use crate::plugins::telemetry::config_new::selectors::FirstResponseData;
+    use crate::plugins::telemetry::config_new::selectors::FirstResponseErrors;
     use crate::plugins::telemetry::config_new::selectors::OperationKind;
     use crate::plugins::telemetry::config_new::selectors::OperationName;
+    use crate::plugins::telemetry::config_new::selectors::OperationSignature;
     use crate::plugins::telemetry::config_new::selectors::Query;
+    use crate::plugins::telemetry::config_new::selectors::ResponseErrors;
     use crate::plugins::telemetry::config_new::selectors::ResponseStatus;
+    use crate::plugins::telemetry::config_new::selectors::RouterRequestBody;
     use crate::plugins::telemetry::config_new::selectors::RouterSelector;
+    use crate::plugins::telemetry::config_new::selectors::StageElapsed;
+    use crate::plugins::telemetry::config_new::selectors::SubgraphResponseJson;
     use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
     use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
     use crate::plugins::telemetry::config_new::selectors::TraceIdFormat;
     use crate::plugins::telemetry::config_new::Selector;
+    use crate::plugins::telemetry::variable_redaction::VariableRedaction;
+    use crate::plugins::telemetry::variable_redaction::REDACTED_VALUE;
+    use crate::query_planner::stats_report_key_hash;
+
+    #[tokio::test]
+    async fn router_request_body() {
+        let selector = RouterSelector::RequestBody {
+            request_body: JSONQuery::parse(".extensions.clientInfo.tenant").unwrap(),
+            redact: None,
+            default: Some("defaulted".into()),
+        };
+        assert!(selector.needs_request_body());
+        let body = json!({
+            "query": "{ me { id } }",
+            "extensions": { "clientInfo": { "tenant": "acme" } }
+        })
+        .to_string();
+        let request = RouterRequestBody::buffer(
+            crate::services::RouterRequest::fake_builder()
+                .method(http::Method::POST)
+                .header(http::header::CONTENT_LENGTH, body.len().to_string())
+                .body(body.clone())
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(selector.on_request(&request).unwrap(), "acme".into());
+        // The body is still available to the router service
+        let buffered = hyper::body::to_bytes(request.router_request.into_body())
+            .await
+            .unwrap();
+        assert_eq!(buffered, body);
+
+        // The body is not buffered without a content length
+        let request = RouterRequestBody::buffer(
+            crate::services::RouterRequest::fake_builder()
+                .method(http::Method::POST)
+                .body(body)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(selector.on_request(&request).unwrap(), "defaulted".into());
+    }
+
+    #[tokio::test]
+    async fn router_request_body_path() {
+        let selector = RouterSelector::RequestBodyPath {
+            request_body_path: "$.variables.ids[?(@ > 1)]".parse().unwrap(),
+            aggregation: JsonPathAggregation::Count,
+            redact: None,
+            default: None,
+        };
+        assert!(selector.needs_request_body());
+        let body = json!({
+            "query": "query($ids: [Int!]) { products(ids: $ids) { id } }",
+            "variables": { "ids": [1, 2, 3] }
+        })
+        .to_string();
+        let request = RouterRequestBody::buffer(
+            crate::services::RouterRequest::fake_builder()
+                .method(http::Method::POST)
+                .header(http::header::CONTENT_LENGTH, body.len().to_string())
+                .body(body)
+                .build()
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            selector.on_request(&request),
+            Some(opentelemetry::Value::I64(2))
+        );
+    }
+
+    #[test]
+    fn router_request_header_redacted() {
+        let request = crate::services::RouterRequest::fake_builder()
+            .header("authorization", "Bearer secret-token")
+            .build()
+            .unwrap();
+        let redacted = |redact: serde_json::Value| {
+            let selector: RouterSelector = serde_json::from_value(json!({
+                "request_header": "authorization",
+                "redact": redact
+            }))
+            .unwrap();
+            selector.on_request(&request).unwrap()
+        };
+
+        assert_eq!(
+            redacted(json!({ "replace": { "pattern": "Bearer .+", "replacement": "Bearer ***" } })),
+            "Bearer ***".into()
+        );
+        assert_eq!(
+            redacted(json!({ "replace": { "pattern": "secret" } })),
+            "Bearer [REDACTED]-token".into()
+        );
+        assert_eq!(redacted(json!({ "truncate": 6 })), "Bearer".into());
+        assert_eq!(
+            redacted(json!({ "bucket": { "buckets": 10, "salt": "canary" } })),
+            opentelemetry::Value::I64(crate::plugins::telemetry::config_new::bucketing::bucket(
+                "canary",
+                "Bearer secret-token",
+                10
+            ) as i64)
+        );
+        assert_eq!(
+            redacted(json!("hash")),
+            hex::encode(sha2::Sha256::digest("Bearer secret-token")).into()
+        );
+    }
 
     #[test]
     fn router_request_header() {
         let selector = RouterSelector::RequestHeader {
             request_header: "header_key".to_string(),
             redact: None,
+            typed: false,
             default: Some("defaulted".into()),
         };
         assert_eq!(
@@ -866,11 +1845,38 @@ mod test {
             None
         );
     }
+    #[test]
+    fn router_request_header_typed() {
+        let value = |typed, header| {
+            RouterSelector::RequestHeader {
+                request_header: "header_key".to_string(),
+                redact: None,
+                typed,
+                default: None,
+            }
+            .on_request(
+                &crate::services::RouterRequest::fake_builder()
+                    .header("header_key", header)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        // Header values are strings unless the selector is typed
+        assert_eq!(value(false, "42"), "42".into());
+        assert_eq!(value(false, "true"), "true".into());
+        assert_eq!(value(true, "42"), 42.into());
+        assert_eq!(value(true, "0.5"), 0.5.into());
+        assert_eq!(value(true, "true"), true.into());
+        assert_eq!(value(true, "header_value"), "header_value".into());
+    }
+
     #[test]
     fn router_response_header() {
         let selector = RouterSelector::ResponseHeader {
             response_header: "header_key".to_string(),
             redact: None,
+            typed: false,
             default: Some("defaulted".into()),
         };
         assert_eq!(
@@ -912,6 +1918,7 @@ mod test {
         let selector = SupergraphSelector::RequestHeader {
             request_header: "header_key".to_string(),
             redact: None,
+            typed: false,
             default: Some("defaulted".into()),
         };
         assert_eq!(
@@ -952,6 +1959,7 @@ mod test {
         let selector = SupergraphSelector::ResponseHeader {
             response_header: "header_key".to_string(),
             redact: None,
+            typed: false,
             default: Some("defaulted".into()),
         };
         assert_eq!(
@@ -993,6 +2001,7 @@ mod test {
         let selector = SubgraphSelector::SupergraphRequestHeader {
             supergraph_request_header: "header_key".to_string(),
             redact: None,
+            typed: false,
             default: Some("defaulted".into()),
         };
         assert_eq!(
@@ -1034,6 +2043,7 @@ mod test {
         let selector = SubgraphSelector::SubgraphRequestHeader {
             subgraph_request_header: "header_key".to_string(),
             redact: None,
+            typed: false,
             default: Some("defaulted".into()),
         };
         assert_eq!(
@@ -1075,6 +2085,7 @@ mod test {
         let selector = SubgraphSelector::SubgraphResponseHeader {
             subgraph_response_header: "header_key".to_string(),
             redact: None,
+            typed: false,
             default: Some("defaulted".into()),
         };
         assert_eq!(
@@ -1481,6 +2492,21 @@ mod test {
                     .unwrap(),
                 opentelemetry::Value::String("42".into())
             );
+
+            let selector = RouterSelector::TraceId {
+                trace_id: TraceIdFormat::Datadog128bit,
+            };
+
+            assert_eq!(
+                selector
+                    .on_request(
+                        &crate::services::RouterRequest::fake_builder()
+                            .build()
+                            .unwrap(),
+                    )
+                    .unwrap(),
+                "0000000000000000000000000000002a".into()
+            );
         });
     }
 
@@ -1881,6 +2907,232 @@ mod test {
         );
     }
 
+    fn errors() -> Vec<graphql::Error> {
+        vec![
+            graphql::Error::builder()
+                .message("forbidden")
+                .extension_code("FORBIDDEN")
+                .extension("service", serde_json_bytes::json!({ "name": "accounts" }))
+                .build(),
+            graphql::Error::builder()
+                .message("not found")
+                .extension_code("NOT_FOUND")
+                .build(),
+        ]
+    }
+
+    #[test]
+    fn supergraph_response_errors() {
+        let response = crate::services::SupergraphResponse::fake_builder()
+            .build()
+            .unwrap();
+        let selector = |response_errors| SupergraphSelector::ResponseErrors {
+            response_errors,
+            default: Some(AttributeValue::String("none".to_string())),
+        };
+        assert_eq!(
+            selector(ResponseErrors::Count).on_response(&response),
+            Some(opentelemetry::Value::I64(0))
+        );
+        assert_eq!(
+            selector(ResponseErrors::Code).on_response(&response),
+            Some("none".into())
+        );
+
+        response
+            .context
+            .private_entries
+            .lock()
+            .insert(FirstResponseErrors(Arc::new(errors())));
+        assert_eq!(
+            selector(ResponseErrors::Count).on_response(&response),
+            Some(opentelemetry::Value::I64(2))
+        );
+        assert_eq!(
+            selector(ResponseErrors::Code).on_response(&response),
+            Some("FORBIDDEN".into())
+        );
+        assert_eq!(
+            selector(ResponseErrors::Extension("service.name".to_string())).on_response(&response),
+            Some("accounts".into())
+        );
+        assert_eq!(
+            selector(ResponseErrors::Extension("service.version".to_string()))
+                .on_response(&response),
+            Some("none".into())
+        );
+    }
+
+    #[test]
+    fn subgraph_subgraph_response_body_path() {
+        let selector = |path: &str, aggregation| SubgraphSelector::SubgraphResponseBodyPath {
+            subgraph_response_body_path: path.parse().unwrap(),
+            aggregation,
+            redact: None,
+            default: Some("none".into()),
+        };
+        let response = crate::services::SubgraphResponse::fake_builder()
+            .errors(errors())
+            .build();
+        let forbidden = "$.errors[?(@.extensions.code == 'FORBIDDEN')].message";
+        assert_eq!(
+            selector(forbidden, JsonPathAggregation::First).on_response(&response),
+            Some("forbidden".into())
+        );
+        assert_eq!(
+            selector("$.errors[*]", JsonPathAggregation::Count).on_response(&response),
+            Some(opentelemetry::Value::I64(2))
+        );
+        assert_eq!(
+            selector("$.errors[*].extensions.code", JsonPathAggregation::All)
+                .on_response(&response),
+            Some(opentelemetry::Value::Array(opentelemetry::Array::String(
+                vec!["FORBIDDEN".into(), "NOT_FOUND".into()]
+            )))
+        );
+        assert_eq!(
+            selector("$.data.me", JsonPathAggregation::First).on_response(&response),
+            Some("none".into())
+        );
+
+        // The body is converted once, then shared by the selectors of the response
+        let mut response = response;
+        response
+            .response
+            .extensions_mut()
+            .insert(SubgraphResponseJson::default());
+        assert_eq!(
+            selector("$.errors[*]", JsonPathAggregation::Count).on_response(&response),
+            Some(opentelemetry::Value::I64(2))
+        );
+        let json = response.response.extensions().get::<SubgraphResponseJson>();
+        assert!(json.unwrap().0.get().is_some());
+    }
+
+    #[test]
+    fn supergraph_response_data() {
+        let selector = |path: &str| SupergraphSelector::ResponseData {
+            response_data: path.to_string(),
+            redact: None,
+            default: Some(AttributeValue::String("none".to_string())),
+        };
+        assert!(selector("checkout").needs_response_data());
+        let response = crate::services::SupergraphResponse::fake_builder()
+            .build()
+            .unwrap();
+        response
+            .context
+            .private_entries
+            .lock()
+            .insert(FirstResponseData(Arc::new(serde_json_bytes::json!({
+                "checkout": { "totalAmount": 42, "items": [{ "sku": "a" }, { "sku": "b" }] }
+            }))));
+        assert_eq!(
+            selector("checkout.totalAmount").on_response(&response),
+            Some(opentelemetry::Value::I64(42))
+        );
+        assert_eq!(
+            selector("checkout.items.1.sku").on_response(&response),
+            Some("b".into())
+        );
+        assert_eq!(
+            selector("checkout.discount").on_response(&response),
+            Some("none".into())
+        );
+
+        // Deferred fields are selected in the incremental payloads
+        let deferred = graphql::Response::builder()
+            .incremental(vec![graphql::IncrementalResponse::builder()
+                .path(crate::json_ext::Path::from("checkout"))
+                .data(serde_json_bytes::json!({ "shipping": { "carrier": "ups" } }))
+                .build()])
+            .build();
+        assert_eq!(
+            selector("checkout.shipping.carrier").on_deferred_response(&deferred),
+            Some("ups".into())
+        );
+        assert_eq!(
+            selector("cart.shipping").on_deferred_response(&deferred),
+            None
+        );
+    }
+
+    #[test]
+    fn subgraph_error_extension() {
+        let selector = |code: Option<&str>| SubgraphSelector::ErrorExtension {
+            error_extension: "upstream.code".to_string(),
+            code: code.map(str::to_string),
+            redact: None,
+            default: None,
+        };
+        let response = crate::services::SubgraphResponse::fake_builder()
+            .errors(vec![
+                graphql::Error::builder()
+                    .message("forbidden")
+                    .extension_code("FORBIDDEN")
+                    .build(),
+                graphql::Error::builder()
+                    .message("payment failed")
+                    .extension_code("UPSTREAM_ERROR")
+                    .extension(
+                        "upstream",
+                        serde_json_bytes::json!({ "code": "card_declined" }),
+                    )
+                    .build(),
+            ])
+            .build();
+        // The first error has no `upstream` extension
+        assert_eq!(selector(None).on_response(&response), None);
+        assert_eq!(
+            selector(Some("UPSTREAM_ERROR")).on_response(&response),
+            Some("card_declined".into())
+        );
+
+        let selector = SubgraphSelector::ErrorExtension {
+            error_extension: "http.status".to_string(),
+            code: None,
+            redact: None,
+            default: Some("none".into()),
+        };
+        let error: BoxError = Box::new(crate::error::FetchError::SubrequestHttpError {
+            status_code: Some(503),
+            service: "products".to_string(),
+            reason: "service unavailable".to_string(),
+        });
+        assert_eq!(
+            selector.on_error(&error),
+            Some(opentelemetry::Value::I64(503))
+        );
+        assert_eq!(
+            selector.on_error(&BoxError::from("not a fetch error")),
+            Some("none".into())
+        );
+    }
+
+    #[test]
+    fn subgraph_subgraph_response_errors() {
+        let selector = |subgraph_response_errors| SubgraphSelector::SubgraphResponseErrors {
+            subgraph_response_errors,
+            default: None,
+        };
+        let response = crate::services::SubgraphResponse::fake_builder()
+            .errors(errors())
+            .build();
+        assert_eq!(
+            selector(ResponseErrors::Count).on_response(&response),
+            Some(opentelemetry::Value::I64(2))
+        );
+        assert_eq!(
+            selector(ResponseErrors::Code).on_response(&response),
+            Some("FORBIDDEN".into())
+        );
+        assert_eq!(
+            selector(ResponseErrors::Code)
+                .on_request(&crate::services::SubgraphRequest::fake_builder().build()),
+            None
+        );
+    }
+
     #[test]
     fn subgraph_subgraph_response_status_code() {
         let selector = SubgraphSelector::SubgraphResponseStatus {
@@ -1898,6 +3150,40 @@ mod test {
         );
     }
 
+    #[test]
+    fn subgraph_elapsed() {
+        let mut response = crate::services::SubgraphResponse::fake_builder().build();
+        let selector = SubgraphSelector::Elapsed {
+            elapsed: Elapsed::Milliseconds,
+        };
+        assert_eq!(selector.on_response(&response), None);
+
+        response
+            .response
+            .extensions_mut()
+            .insert(StageElapsed(std::time::Duration::from_millis(1500)));
+        assert_eq!(
+            selector.on_response(&response),
+            Some(opentelemetry::Value::F64(1500.0))
+        );
+        assert_eq!(
+            SubgraphSelector::Elapsed {
+                elapsed: Elapsed::Seconds,
+            }
+            .on_response(&response),
+            Some(opentelemetry::Value::F64(1.5))
+        );
+        assert_eq!(
+            selector.on_request(&crate::services::SubgraphRequest::fake_builder().build()),
+            None
+        );
+        // Without a response, the elapsed time is known when the subgraph failed
+        assert_eq!(
+            selector.on_elapsed(std::time::Duration::from_millis(1500)),
+            Some(opentelemetry::Value::F64(1500.0))
+        );
+    }
+
     #[test]
     fn router_response_status_reason() {
         let selector = RouterSelector::ResponseStatus {
@@ -1960,6 +3246,45 @@ mod test {
         );
     }
 
+    #[test]
+    fn supergraph_query_variable_redacted() {
+        let selector = SupergraphSelector::QueryVariable {
+            query_variable: "password".to_string(),
+            redact: None,
+            default: Some(AttributeValue::String("default".to_string())),
+        };
+        let context = crate::context::Context::new();
+        VariableRedaction::new(
+            &VariableRedactionConfig {
+                directive: None,
+                names: vec!["password".to_string()],
+            },
+            "",
+        )
+        .unwrap()
+        .populate_context(&context);
+        assert_eq!(
+            selector.on_request(
+                &crate::services::SupergraphRequest::fake_builder()
+                    .variable("password", "secret")
+                    .context(context.clone())
+                    .build()
+                    .unwrap(),
+            ),
+            Some(REDACTED_VALUE.into())
+        );
+
+        assert_eq!(
+            selector.on_request(
+                &crate::services::SupergraphRequest::fake_builder()
+                    .context(context)
+                    .build()
+                    .unwrap(),
+            ),
+            Some("default".into())
+        );
+    }
+
     #[test]
     fn subgraph_supergraph_query_variable() {
         let selector = SubgraphSelector::SupergraphQueryVariable {
@@ -2019,4 +3344,176 @@ mod test {
             Some("default".into())
         );
     }
+
+    #[test]
+    fn execution_request_header() {
+        let selector = ExecutionSelector::RequestHeader {
+            request_header: "header_key".to_string(),
+            redact: None,
+            typed: false,
+            default: Some("defaulted".into()),
+        };
+        assert_eq!(
+            selector
+                .on_request(
+                    &crate::services::ExecutionRequest::fake_builder()
+                        .supergraph_request(
+                            http::Request::builder()
+                                .header("header_key", "header_value")
+                                .body(crate::graphql::Request::default())
+                                .unwrap()
+                        )
+                        .build()
+                )
+                .unwrap(),
+            "header_value".into()
+        );
+
+        assert_eq!(
+            selector
+                .on_request(&crate::services::ExecutionRequest::fake_builder().build())
+                .unwrap(),
+            "defaulted".into()
+        );
+
+        assert_eq!(
+            selector.on_response(
+                &crate::services::ExecutionResponse::fake_builder()
+                    .build()
+                    .unwrap()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn execution_response_context() {
+        let selector = ExecutionSelector::ResponseContext {
+            response_context: "context_key".to_string(),
+            redact: None,
+            default: Some("defaulted".into()),
+        };
+        let context = crate::context::Context::new();
+        let _ = context.insert("context_key".to_string(), "context_value".to_string());
+        assert_eq!(
+            selector
+                .on_response(
+                    &crate::services::ExecutionResponse::fake_builder()
+                        .context(context.clone())
+                        .build()
+                        .unwrap()
+                )
+                .unwrap(),
+            "context_value".into()
+        );
+
+        assert_eq!(
+            selector
+                .on_response(
+                    &crate::services::ExecutionResponse::fake_builder()
+                        .build()
+                        .unwrap()
+                )
+                .unwrap(),
+            "defaulted".into()
+        );
+        assert_eq!(
+            selector.on_request(
+                &crate::services::ExecutionRequest::fake_builder()
+                    .context(context)
+                    .build()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn execution_operation_name_string() {
+        let selector = ExecutionSelector::OperationName {
+            operation_name: OperationName::String,
+            redact: None,
+            default: Some("defaulted".to_string()),
+        };
+        let context = crate::context::Context::new();
+        assert_eq!(
+            selector.on_request(
+                &crate::services::ExecutionRequest::fake_builder()
+                    .context(context.clone())
+                    .build()
+            ),
+            Some("defaulted".into())
+        );
+        let _ = context.insert(OPERATION_NAME, "topProducts".to_string());
+        assert_eq!(
+            selector.on_request(
+                &crate::services::ExecutionRequest::fake_builder()
+                    .context(context)
+                    .build()
+            ),
+            Some("topProducts".into())
+        );
+    }
+
+    #[test]
+    fn supergraph_operation_signature() {
+        let selector = SupergraphSelector::OperationSignature {
+            operation_signature: OperationSignature::String,
+            redact: None,
+            default: Some("defaulted".to_string()),
+        };
+        let context = crate::context::Context::new();
+        assert_eq!(
+            selector.on_response(
+                &crate::services::SupergraphResponse::fake_builder()
+                    .context(context.clone())
+                    .build()
+                    .unwrap(),
+            ),
+            Some("defaulted".into())
+        );
+        context.private_entries.lock().insert(UsageReporting {
+            stats_report_key: "# MyQuery\nquery MyQuery{me{name}}".to_string(),
+            referenced_fields_by_type: Default::default(),
+        });
+        assert_eq!(
+            selector.on_request(
+                &crate::services::SupergraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build()
+                    .unwrap(),
+            ),
+            None
+        );
+        assert_eq!(
+            selector.on_response(
+                &crate::services::SupergraphResponse::fake_builder()
+                    .context(context)
+                    .build()
+                    .unwrap(),
+            ),
+            Some("# MyQuery\nquery MyQuery{me{name}}".into())
+        );
+    }
+
+    #[test]
+    fn subgraph_supergraph_operation_signature_hash() {
+        let selector = SubgraphSelector::SupergraphOperationSignature {
+            supergraph_operation_signature: OperationSignature::Hash,
+            redact: None,
+            default: None,
+        };
+        let context = crate::context::Context::new();
+        context.private_entries.lock().insert(UsageReporting {
+            stats_report_key: "# MyQuery\nquery MyQuery{me{name}}".to_string(),
+            referenced_fields_by_type: Default::default(),
+        });
+        assert_eq!(
+            selector.on_request(
+                &crate::services::SubgraphRequest::fake_builder()
+                    .context(context)
+                    .build(),
+            ),
+            Some(stats_report_key_hash("# MyQuery\nquery MyQuery{me{name}}").into())
+        );
+    }
 }
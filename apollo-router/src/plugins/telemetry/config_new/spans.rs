@@ -1,11 +1,17 @@
 use schemars::JsonSchema;
 use serde::Deserialize;
+use serde::Serialize;
 
+use crate::configuration::subgraph::SubgraphConfiguration;
 use crate::plugins::telemetry::config_new::attributes::DefaultAttributeRequirementLevel;
+use crate::plugins::telemetry::config_new::attributes::ExecutionAttributes;
+use crate::plugins::telemetry::config_new::attributes::QueryPlanningAttributes;
 use crate::plugins::telemetry::config_new::attributes::RouterAttributes;
 use crate::plugins::telemetry::config_new::attributes::SubgraphAttributes;
 use crate::plugins::telemetry::config_new::attributes::SupergraphAttributes;
 use crate::plugins::telemetry::config_new::extendable::Extendable;
+use crate::plugins::telemetry::config_new::field_events::FieldEvents;
+use crate::plugins::telemetry::config_new::selectors::ExecutionSelector;
 use crate::plugins::telemetry::config_new::selectors::RouterSelector;
 use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
 use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
@@ -33,6 +39,14 @@ pub(crate) struct Spans {
     /// Attributes to include on the subgraph span.
     /// Subgraph spans contain information about the subgraph request and response and therefore contain subgraph specific attributes.
     pub(crate) subgraph: SubgraphSpans,
+
+    /// Attributes to include on the execution span.
+    /// Execution spans contain information about the execution of the query plan.
+    pub(crate) execution: ExecutionSpans,
+
+    /// Attributes to include on the query planning span.
+    /// Query planning spans contain information about how the query plan was obtained and its shape.
+    pub(crate) query_planning: QueryPlanningSpans,
 }
 
 impl Spans {
@@ -44,6 +58,10 @@ impl Spans {
             .defaults_for_levels(self.default_attribute_requirement_level);
         self.subgraph
             .defaults_for_levels(self.default_attribute_requirement_level);
+        self.execution
+            .defaults_for_levels(self.default_attribute_requirement_level);
+        self.query_planning
+            .defaults_for_levels(self.default_attribute_requirement_level);
     }
 }
 
@@ -65,6 +83,9 @@ impl DefaultForLevel for RouterSpans {
 pub(crate) struct SupergraphSpans {
     /// Custom attributes that are attached to the supergraph span.
     pub(crate) attributes: Extendable<SupergraphAttributes, SupergraphSelector>,
+
+    /// Events describing the top-level fields of the operation, added to the supergraph span of a sample of the traced requests.
+    pub(crate) experimental_field_events: FieldEvents,
 }
 impl DefaultForLevel for SupergraphSpans {
     fn defaults_for_level(&mut self, requirement_level: DefaultAttributeRequirementLevel) {
@@ -77,6 +98,32 @@ impl DefaultForLevel for SupergraphSpans {
 pub(crate) struct SubgraphSpans {
     /// Custom attributes that are attached to the subgraph span.
     pub(crate) attributes: Extendable<SubgraphAttributes, SubgraphSelector>,
+
+    /// Override the `service.name` of subgraph spans, so that APM tools display each subgraph as its own service.
+    pub(crate) service_name: SubgraphConfiguration<SubgraphServiceName>,
+}
+
+impl SubgraphSpans {
+    /// The `service.name` to report for spans of this subgraph, if it is overridden.
+    pub(crate) fn service_name(&self, subgraph_name: &str) -> Option<String> {
+        let config = self.service_name.get(subgraph_name);
+        config.enabled.then(|| {
+            config
+                .name
+                .clone()
+                .unwrap_or_else(|| subgraph_name.to_string())
+        })
+    }
+}
+
+/// Service name override for subgraph spans
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SubgraphServiceName {
+    /// Set to true to report subgraph spans under their own `service.name`
+    pub(crate) enabled: bool,
+    /// The service name to report. Defaults to the subgraph name
+    pub(crate) name: Option<String>,
 }
 
 impl DefaultForLevel for SubgraphSpans {
@@ -85,6 +132,32 @@ impl DefaultForLevel for SubgraphSpans {
     }
 }
 
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ExecutionSpans {
+    /// Custom attributes that are attached to the execution span.
+    pub(crate) attributes: Extendable<ExecutionAttributes, ExecutionSelector>,
+}
+
+impl DefaultForLevel for ExecutionSpans {
+    fn defaults_for_level(&mut self, requirement_level: DefaultAttributeRequirementLevel) {
+        self.attributes.defaults_for_level(requirement_level);
+    }
+}
+
+#[derive(Deserialize, JsonSchema, Clone, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct QueryPlanningSpans {
+    /// Standard attributes that are attached to the query planning span.
+    pub(crate) attributes: QueryPlanningAttributes,
+}
+
+impl DefaultForLevel for QueryPlanningSpans {
+    fn defaults_for_level(&mut self, requirement_level: DefaultAttributeRequirementLevel) {
+        self.attributes.defaults_for_level(requirement_level);
+    }
+}
+
 #[cfg(test)]
 mod test {
     use http::header::USER_AGENT;
@@ -101,6 +174,7 @@ mod test {
     use crate::plugins::telemetry::config_new::selectors::SubgraphSelector;
     use crate::plugins::telemetry::config_new::selectors::SupergraphSelector;
     use crate::plugins::telemetry::config_new::spans::RouterSpans;
+    use crate::plugins::telemetry::config_new::spans::SubgraphServiceName;
     use crate::plugins::telemetry::config_new::spans::SubgraphSpans;
     use crate::plugins::telemetry::config_new::spans::SupergraphSpans;
     use crate::plugins::telemetry::config_new::DefaultForLevel;
@@ -290,6 +364,7 @@ mod test {
             RouterSelector::RequestHeader {
                 request_header: "my-header".to_string(),
                 redact: None,
+                typed: false,
                 default: None,
             },
         );
@@ -313,6 +388,7 @@ mod test {
             RouterSelector::ResponseHeader {
                 response_header: "my-header".to_string(),
                 redact: None,
+                typed: false,
                 default: None,
             },
         );
@@ -335,6 +411,7 @@ mod test {
             SupergraphSelector::RequestHeader {
                 request_header: "my-header".to_string(),
                 redact: None,
+                typed: false,
                 default: None,
             },
         );
@@ -358,6 +435,7 @@ mod test {
             SupergraphSelector::ResponseHeader {
                 response_header: "my-header".to_string(),
                 redact: None,
+                typed: false,
                 default: None,
             },
         );
@@ -380,6 +458,7 @@ mod test {
             SubgraphSelector::SubgraphRequestHeader {
                 subgraph_request_header: "my-header".to_string(),
                 redact: None,
+                typed: false,
                 default: None,
             },
         );
@@ -411,6 +490,7 @@ mod test {
             SubgraphSelector::SubgraphResponseHeader {
                 subgraph_response_header: "my-header".to_string(),
                 redact: None,
+                typed: false,
                 default: None,
             },
         );
@@ -424,4 +504,35 @@ mod test {
             .iter()
             .any(|key_val| key_val.key == opentelemetry::Key::from_static_str("test")));
     }
+
+    #[test]
+    fn test_subgraph_spans_service_name() {
+        let mut spans = SubgraphSpans::default();
+        assert_eq!(spans.service_name("products"), None);
+
+        spans.service_name.all = SubgraphServiceName {
+            enabled: true,
+            name: None,
+        };
+        spans.service_name.subgraphs.insert(
+            "reviews".to_string(),
+            SubgraphServiceName {
+                enabled: true,
+                name: Some("reviews-service".to_string()),
+            },
+        );
+        spans.service_name.subgraphs.insert(
+            "accounts".to_string(),
+            SubgraphServiceName {
+                enabled: false,
+                name: None,
+            },
+        );
+        assert_eq!(spans.service_name("products").as_deref(), Some("products"));
+        assert_eq!(
+            spans.service_name("reviews").as_deref(),
+            Some("reviews-service")
+        );
+        assert_eq!(spans.service_name("accounts"), None);
+    }
 }
@@ -0,0 +1,341 @@
+//! Debug tokens force the tracing of a single request, whatever the sampling, along with verbose
+//! events, so that support engineers can capture a rich trace of a request of a customer without
+//! raising the sampling of every request.
+//!
+//! A token is `<expiry>:<subject>:<signature>`. The expiry is a unix timestamp in seconds, the
+//! subject is the value of the configured JWT claim of the only user allowed to use the token, or
+//! empty for any user, and the signature is the hex encoded HMAC-SHA256 of `<expiry>:<subject>`
+//! with one of the configured keys.
+//!
+//! The signature is verified once, when the root span of the request is created. The subject can
+//! only be checked once the request is authenticated, so the sampling decision of the traces of
+//! debug tokens is deferred until then, like that of the sampling rules.
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use hmac::Hmac;
+use hmac::Mac;
+use http::header::HeaderName;
+use opentelemetry::propagation::text_map_propagator::FieldIter;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::propagation::Injector;
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::SpanContext;
+use opentelemetry::trace::TraceContextExt;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::plugin::serde::deserialize_header_name;
+use crate::plugins::authentication::APOLLO_AUTHENTICATION_JWT_CLAIMS;
+use crate::Context;
+
+type HmacSha256 = Hmac<sha2::Sha256>;
+
+/// Force the tracing of requests with a signed debug token
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct DebugToken {
+    /// Set to true to trace every request with a valid debug token, along with verbose events.
+    pub(crate) enabled: bool,
+    /// The request header of the debug token (default: apollo-debug-token).
+    #[schemars(with = "String")]
+    #[serde(deserialize_with = "deserialize_header_name")]
+    header_name: HeaderName,
+    /// The claim of the authenticated JWT that the subject of a token is compared with (default:
+    /// sub).
+    claim: String,
+    /// The keys signing the debug tokens. Tokens signed with any of them are valid, so that a new
+    /// key can be added before the previous one is removed.
+    keys: Vec<String>,
+    /// The longest validity of a token: tokens expiring later are rejected (default: 1h).
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    max_ttl: Duration,
+}
+
+impl Default for DebugToken {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            header_name: HeaderName::from_static("apollo-debug-token"),
+            claim: "sub".to_string(),
+            keys: Vec::new(),
+            max_ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Set in the opentelemetry context the root span of the requests with a validly signed debug
+/// token is created in, with the subject of the token, and in the context of the requests the
+/// token belongs to
+#[derive(Clone, Debug)]
+pub(crate) struct ForcedSampling {
+    subject: Option<Arc<str>>,
+}
+
+/// Whether the trace of the current span was forced by a validly signed debug token, whoever it
+/// belongs to
+pub(crate) fn is_trace_forced() -> bool {
+    Span::current().context().get::<ForcedSampling>().is_some()
+}
+
+/// Whether a request has a valid debug token that belongs to it
+pub(crate) fn is_forced(context: &Context) -> bool {
+    context
+        .private_entries
+        .lock()
+        .contains_key::<ForcedSampling>()
+}
+
+/// Verifies the debug tokens of requests
+#[derive(Clone)]
+pub(crate) struct DebugTokenVerifier {
+    header_name: HeaderName,
+    claim: String,
+    keys: Arc<Vec<HmacSha256>>,
+    max_ttl: Duration,
+}
+
+impl DebugTokenVerifier {
+    /// The verifier of the configured debug tokens, `None` if they are disabled
+    pub(crate) fn new(config: &DebugToken) -> Result<Option<Self>, BoxError> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        if config.keys.is_empty() {
+            return Err(
+                "telemetry.exporters.tracing.experimental_debug_token requires at least one key"
+                    .into(),
+            );
+        }
+        let keys = config
+            .keys
+            .iter()
+            .map(|key| HmacSha256::new_from_slice(key.as_bytes()))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Self {
+            header_name: config.header_name.clone(),
+            claim: config.claim.clone(),
+            keys: Arc::new(keys),
+            max_ttl: config.max_ttl,
+        }))
+    }
+
+    /// Marks the context of an authenticated request if the debug token that forced its trace
+    /// belongs to it, from the claims of its JWT
+    pub(crate) fn authorize(&self, context: &Context) {
+        let Some(forced) = Span::current().context().get::<ForcedSampling>().cloned() else {
+            return;
+        };
+        let claims = context.get_json_value(APOLLO_AUTHENTICATION_JWT_CLAIMS);
+        if self.belongs_to(forced.subject.as_deref(), claims.as_ref()) {
+            context.private_entries.lock().insert(forced);
+        } else {
+            ::tracing::debug!("ignoring debug token: the token belongs to another subject");
+        }
+    }
+
+    fn belongs_to(&self, subject: Option<&str>, claims: Option<&Value>) -> bool {
+        let Some(subject) = subject else {
+            return true;
+        };
+        claims
+            .and_then(|claims| claims.get(self.claim.as_str()))
+            .and_then(|claim| claim.as_str())
+            == Some(subject)
+    }
+
+    /// The subject of a validly signed token, `None` if the token belongs to any subject
+    fn verify(&self, token: Option<&str>) -> Option<Option<Arc<str>>> {
+        match self.verify_token(token?, SystemTime::now()) {
+            Ok(subject) => Some(subject),
+            Err(reason) => {
+                ::tracing::debug!("ignoring debug token: {reason}");
+                None
+            }
+        }
+    }
+
+    fn verify_token(&self, token: &str, now: SystemTime) -> Result<Option<Arc<str>>, &'static str> {
+        let (payload, signature) = token.rsplit_once(':').ok_or("malformed token")?;
+        let (expiry, subject) = payload.split_once(':').ok_or("malformed token")?;
+        let signature = hex::decode(signature).map_err(|_| "malformed signature")?;
+        if !self.keys.iter().any(|key| {
+            let mut mac = key.clone();
+            mac.update(payload.as_bytes());
+            mac.verify_slice(&signature).is_ok()
+        }) {
+            return Err("invalid signature");
+        }
+        let expiry =
+            UNIX_EPOCH + Duration::from_secs(expiry.parse().map_err(|_| "malformed expiry")?);
+        if expiry < now {
+            return Err("expired token");
+        }
+        if expiry > now + self.max_ttl {
+            return Err("the token expires after the maximum ttl");
+        }
+        Ok((!subject.is_empty()).then(|| subject.into()))
+    }
+}
+
+/// Marks the opentelemetry context of the requests with a validly signed debug token, so that
+/// their root span is enabled
+pub(crate) struct DebugTokenPropagator(DebugTokenVerifier);
+
+impl DebugTokenPropagator {
+    pub(crate) fn new(verifier: DebugTokenVerifier) -> Self {
+        Self(verifier)
+    }
+}
+
+impl fmt::Debug for DebugTokenPropagator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DebugTokenPropagator")
+            .field("header_name", &self.0.header_name)
+            .finish()
+    }
+}
+
+impl TextMapPropagator for DebugTokenPropagator {
+    fn inject_context(&self, _cx: &opentelemetry::Context, _injector: &mut dyn Injector) {}
+
+    fn extract_with_context(
+        &self,
+        cx: &opentelemetry::Context,
+        extractor: &dyn Extractor,
+    ) -> opentelemetry::Context {
+        let Some(subject) = self.0.verify(extractor.get(self.0.header_name.as_str())) else {
+            return cx.clone();
+        };
+        // A remote parent that is not sampled would prevent the sampling of the trace
+        let span_context = cx.span().span_context().clone();
+        let cx = if span_context.is_valid() && !span_context.is_sampled() {
+            cx.with_remote_span_context(SpanContext::new(
+                span_context.trace_id(),
+                span_context.span_id(),
+                span_context.trace_flags().with_sampled(true),
+                true,
+                span_context.trace_state().clone(),
+            ))
+        } else {
+            cx.clone()
+        };
+        cx.with_value(ForcedSampling { subject })
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(&[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderMap;
+    use serde_json::json;
+
+    use super::*;
+
+    fn sign(key: &str, payload: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(key.as_bytes()).unwrap();
+        mac.update(payload.as_bytes());
+        format!("{payload}:{}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn verifier() -> DebugTokenVerifier {
+        let config: DebugToken = serde_json::from_value(json!({
+            "enabled": true,
+            "keys": ["current", "previous"],
+            "max_ttl": "1h"
+        }))
+        .unwrap();
+        DebugTokenVerifier::new(&config).unwrap().unwrap()
+    }
+
+    #[test]
+    fn it_verifies_debug_tokens() {
+        let verifier = verifier();
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        assert_eq!(
+            verifier.verify_token(&sign("current", "1700000600:acme"), now),
+            Ok(Some("acme".into()))
+        );
+        // Tokens signed with a previous key remain valid during the rotation
+        assert_eq!(
+            verifier.verify_token(&sign("previous", "1700000600:"), now),
+            Ok(None)
+        );
+        assert_eq!(
+            verifier.verify_token(&sign("unknown", "1700000600:acme"), now),
+            Err("invalid signature")
+        );
+        assert_eq!(
+            verifier.verify_token(&sign("current", "1699999999:acme"), now),
+            Err("expired token")
+        );
+        assert_eq!(
+            verifier.verify_token(&sign("current", "1700007200:acme"), now),
+            Err("the token expires after the maximum ttl")
+        );
+        assert_eq!(
+            verifier.verify_token("1700000600:acme", now),
+            Err("malformed token")
+        );
+    }
+
+    #[test]
+    fn it_binds_tokens_to_the_subject_of_the_jwt() {
+        let verifier = verifier();
+        let claims = serde_json_bytes::json!({ "sub": "acme", "scope": "read" });
+        assert!(verifier.belongs_to(Some("acme"), Some(&claims)));
+        assert!(!verifier.belongs_to(
+            Some("acme"),
+            Some(&serde_json_bytes::json!({ "sub": "other" }))
+        ));
+        // Without a verified JWT, only the tokens of any subject are accepted
+        assert!(!verifier.belongs_to(Some("acme"), None));
+        assert!(verifier.belongs_to(None, None));
+    }
+
+    #[test]
+    fn it_marks_the_context_of_requests_with_a_validly_signed_token() {
+        let propagator = DebugTokenPropagator::new(verifier());
+        let expiry = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 60;
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "apollo-debug-token",
+            sign("current", &format!("{expiry}:acme")).parse().unwrap(),
+        );
+        let cx = propagator.extract(&opentelemetry_http::HeaderExtractor(&headers));
+        assert_eq!(
+            cx.get::<ForcedSampling>()
+                .and_then(|forced| forced.subject.as_deref()),
+            Some("acme")
+        );
+
+        headers.insert(
+            "apollo-debug-token",
+            sign("unknown", &format!("{expiry}:acme")).parse().unwrap(),
+        );
+        let cx = propagator.extract(&opentelemetry_http::HeaderExtractor(&headers));
+        assert!(cx.get::<ForcedSampling>().is_none());
+    }
+
+    #[test]
+    fn it_requires_a_key() {
+        let config: DebugToken = serde_json::from_value(json!({ "enabled": true })).unwrap();
+        assert!(DebugTokenVerifier::new(&config).is_err());
+    }
+}
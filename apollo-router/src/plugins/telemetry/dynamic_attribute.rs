@@ -1,5 +1,8 @@
+use std::cell::RefCell;
 use std::collections::LinkedList;
+use std::time::SystemTime;
 
+use opentelemetry::trace::Event;
 use opentelemetry::Key;
 use opentelemetry::KeyValue;
 use tracing_opentelemetry::OtelData;
@@ -30,6 +33,31 @@ impl LogAttributes {
     }
 }
 
+thread_local! {
+    static EVENT_ATTRIBUTES: RefCell<Vec<KeyValue>> = RefCell::new(Vec::new());
+}
+
+/// Attributes of an event whose fields are only known at runtime.
+///
+/// Events are formatted on the thread that logs them, so the attributes are scoped to that thread
+/// rather than stored in the span: concurrent events in the same span never see each other's attributes.
+pub(crate) struct EventAttributes;
+
+impl EventAttributes {
+    /// Runs `f` with `attributes` as the attributes of the events it logs.
+    pub(crate) fn scope<R>(attributes: Vec<KeyValue>, f: impl FnOnce() -> R) -> R {
+        let previous = EVENT_ATTRIBUTES.with(|current| current.replace(attributes));
+        let result = f();
+        EVENT_ATTRIBUTES.with(|current| current.replace(previous));
+        result
+    }
+
+    /// Calls `f` with the attributes of the event being formatted.
+    pub(crate) fn with_current<R>(f: impl FnOnce(&[KeyValue]) -> R) -> R {
+        EVENT_ATTRIBUTES.with(|current| f(&current.borrow()))
+    }
+}
+
 pub(crate) struct DynAttributeLayer;
 
 impl<S> Layer<S> for DynAttributeLayer
@@ -168,3 +196,42 @@ impl DynAttribute for ::tracing::Span {
         });
     }
 }
+
+pub(crate) trait DynSpanEvent {
+    /// Adds an event to the span if it is sampled, for events whose name and attributes are only
+    /// known at runtime
+    fn add_dyn_event(&self, name: &'static str, attributes: Vec<KeyValue>);
+}
+
+impl DynSpanEvent for ::tracing::Span {
+    fn add_dyn_event(&self, name: &'static str, attributes: Vec<KeyValue>) {
+        self.with_subscriber(move |(id, dispatch)| {
+            if let Some(reg) = dispatch.downcast_ref::<Registry>() {
+                match reg.span(id) {
+                    None => eprintln!("no spanref, this is a bug"),
+                    Some(s) => {
+                        if !s.is_sampled() {
+                            return;
+                        }
+                        let mut extensions = s.extensions_mut();
+                        match extensions.get_mut::<OtelData>() {
+                            Some(otel_data) => {
+                                otel_data
+                                    .builder
+                                    .events
+                                    .get_or_insert_with(Vec::new)
+                                    .push(Event::new(name, SystemTime::now(), attributes, 0));
+                            }
+                            None => {
+                                // Can't use ::tracing::error! because it could create deadlock on extensions
+                                eprintln!("no OtelData, this is a bug");
+                            }
+                        }
+                    }
+                };
+            } else {
+                ::tracing::error!("no Registry, this is a bug");
+            }
+        });
+    }
+}
@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::marker::PhantomData;
 
+use opentelemetry::sdk::logs::LoggerProvider;
 use opentelemetry::Key;
 use opentelemetry::KeyValue;
 use tracing::field;
@@ -18,6 +19,7 @@ use super::config_new::ToOtelValue;
 use super::dynamic_attribute::LogAttributes;
 use super::formatters::EventFormatter;
 use super::formatters::EXCLUDED_ATTRIBUTES;
+use super::otlp_logs::OtlpLogLayer;
 use super::reload::IsSampled;
 use crate::plugins::telemetry::config;
 use crate::plugins::telemetry::config_new::logging::Format;
@@ -31,13 +33,15 @@ use crate::plugins::telemetry::resource::ConfigResource;
 
 pub(crate) fn create_fmt_layer(
     config: &config::Conf,
+    logger_provider: Option<&LoggerProvider>,
 ) -> Box<dyn Layer<LayeredTracer> + Send + Sync> {
-    match &config.exporters.logging.stdout {
+    let layer = match &config.exporters.logging.stdout {
         StdOut { enabled, format } if *enabled => match format {
             Format::Json(format_config) => {
                 let format = Json::new(
                     config.exporters.logging.common.to_resource(),
                     format_config.clone(),
+                    config.exporters.logging.redaction.clone(),
                 );
                 FmtLayer::new(
                     FilteringFormatter::new(format, filter_metric_events),
@@ -50,6 +54,7 @@ pub(crate) fn create_fmt_layer(
                 let format = Text::new(
                     config.exporters.logging.common.to_resource(),
                     format_config.clone(),
+                    config.exporters.logging.redaction.clone(),
                 );
                 FmtLayer::new(
                     FilteringFormatter::new(format, filter_metric_events),
@@ -59,6 +64,15 @@ pub(crate) fn create_fmt_layer(
             }
         },
         _ => NoOpLayer.boxed(),
+    };
+    match logger_provider {
+        Some(logger_provider) => layer
+            .and_then(OtlpLogLayer::new(
+                logger_provider,
+                config.exporters.logging.redaction.clone(),
+            ))
+            .boxed(),
+        None => layer,
     }
 }
 
@@ -256,6 +270,8 @@ mod tests {
     use tracing_subscriber::layer::SubscriberExt;
 
     use super::*;
+    use crate::plugins::telemetry::config_new::events::log_event;
+    use crate::plugins::telemetry::config_new::events::EventLevel;
     use crate::plugins::telemetry::config_new::logging::JsonFormat;
     use crate::plugins::telemetry::config_new::logging::TextFormat;
     use crate::plugins::telemetry::dynamic_attribute::DynAttribute;
@@ -328,6 +344,21 @@ mod tests {
         info!(event_attr = "foo", "Hello from test");
     }
 
+    fn generate_events_with_attributes() {
+        let test_span = info_span!("test", first = "one");
+        let _enter = test_span.enter();
+        log_event(
+            EventLevel::Error,
+            "subgraph.error",
+            vec![
+                KeyValue::new("subgraph.name", "products"),
+                KeyValue::new("http.response.status_code", 500),
+            ],
+            "Subgraph request failed",
+        );
+        info!("Hello from test");
+    }
+
     #[tokio::test]
     async fn test_text_logging_attributes() {
         let buff = LogBuffer::default();
@@ -407,7 +438,30 @@ mod tests {
             display_resource: false,
             ..Default::default()
         };
-        let format = Json::new(Default::default(), json_format);
+        let format = Json::new(Default::default(), json_format, Default::default());
+        let fmt_layer = FmtLayer::new(
+            FilteringFormatter::new(format, filter_metric_events),
+            buff.clone(),
+        )
+        .boxed();
+
+        ::tracing::subscriber::with_default(
+            fmt::Subscriber::new().with(fmt_layer),
+            generate_nested_spans,
+        );
+
+        insta::assert_display_snapshot!(buff.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_json_logging_with_redaction() {
+        let buff = LogBuffer::default();
+        let redaction = serde_json::from_value(serde_json::json!({
+            "drop": ["first", "inner"],
+            "hash": ["http.method"]
+        }))
+        .unwrap();
+        let format = Json::new(Default::default(), Default::default(), redaction);
         let fmt_layer = FmtLayer::new(
             FilteringFormatter::new(format, filter_metric_events),
             buff.clone(),
@@ -432,7 +486,7 @@ mod tests {
             ansi_escape_codes: false,
             ..Default::default()
         };
-        let format = Text::new(Default::default(), text_format);
+        let format = Text::new(Default::default(), text_format, Default::default());
         let fmt_layer = FmtLayer::new(
             FilteringFormatter::new(format, filter_metric_events),
             buff.clone(),
@@ -446,4 +500,44 @@ mod tests {
 
         insta::assert_display_snapshot!(buff.to_string());
     }
+
+    #[tokio::test]
+    async fn test_json_logging_event_attributes() {
+        let buff = LogBuffer::default();
+        let format = Json::default();
+        let fmt_layer = FmtLayer::new(
+            FilteringFormatter::new(format, filter_metric_events),
+            buff.clone(),
+        )
+        .boxed();
+
+        ::tracing::subscriber::with_default(
+            fmt::Subscriber::new().with(fmt_layer),
+            generate_events_with_attributes,
+        );
+
+        insta::assert_display_snapshot!(buff.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_text_logging_event_attributes() {
+        let buff = LogBuffer::default();
+        let text_format = TextFormat {
+            ansi_escape_codes: false,
+            ..Default::default()
+        };
+        let format = Text::new(Default::default(), text_format, Default::default());
+        let fmt_layer = FmtLayer::new(
+            FilteringFormatter::new(format, filter_metric_events),
+            buff.clone(),
+        )
+        .boxed();
+
+        ::tracing::subscriber::with_default(
+            fmt::Subscriber::new().with(fmt_layer),
+            generate_events_with_attributes,
+        );
+
+        insta::assert_display_snapshot!(buff.to_string());
+    }
 }
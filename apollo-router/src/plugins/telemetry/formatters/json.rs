@@ -5,6 +5,7 @@ use std::io;
 
 use opentelemetry::sdk::Resource;
 use opentelemetry::Array;
+use opentelemetry::KeyValue;
 use opentelemetry::Value;
 use serde::ser::SerializeMap;
 use serde::ser::Serializer as _;
@@ -17,10 +18,13 @@ use tracing_subscriber::layer::Context;
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::registry::SpanRef;
 
+use super::redaction::RedactingVisitor;
+use super::redaction::Redaction;
 use super::EventFormatter;
 use super::APOLLO_PRIVATE_PREFIX;
 use super::EXCLUDED_ATTRIBUTES;
 use crate::plugins::telemetry::config_new::logging::JsonFormat;
+use crate::plugins::telemetry::dynamic_attribute::EventAttributes;
 use crate::plugins::telemetry::dynamic_attribute::LogAttributes;
 use crate::plugins::telemetry::formatters::to_list;
 
@@ -29,14 +33,16 @@ pub(crate) struct Json {
     config: JsonFormat,
     resource: LinkedList<(String, serde_json::Value)>,
     excluded_attributes: HashSet<&'static str>,
+    redaction: Redaction,
 }
 
 impl Json {
-    pub(crate) fn new(resource: Resource, config: JsonFormat) -> Self {
+    pub(crate) fn new(resource: Resource, config: JsonFormat, redaction: Redaction) -> Self {
         Self {
             resource: to_list(resource),
             config,
             excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+            redaction,
         }
     }
 }
@@ -47,6 +53,7 @@ impl Default for Json {
             config: Default::default(),
             resource: Default::default(),
             excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+            redaction: Default::default(),
         }
     }
 }
@@ -68,7 +75,11 @@ impl<'a> serde::ser::Serialize for SerializableResources<'a> {
     }
 }
 
-struct SerializableContext<'a, 'b, Span>(Option<SpanRef<'a, Span>>, &'b HashSet<&'static str>)
+struct SerializableContext<'a, 'b, Span>(
+    Option<SpanRef<'a, Span>>,
+    &'b HashSet<&'static str>,
+    &'b Redaction,
+)
 where
     Span: Subscriber + for<'lookup> tracing_subscriber::registry::LookupSpan<'lookup>;
 
@@ -86,7 +97,7 @@ where
         if let Some(leaf_span) = &self.0 {
             for span in leaf_span.scope().from_root() {
                 // TODO: Here in the future we could try to memoize parent spans of the current span to not re serialize eveything if another log happens in the same span
-                serializer.serialize_element(&SerializableSpan(&span, self.1))?;
+                serializer.serialize_element(&SerializableSpan(&span, self.1, self.2))?;
             }
         }
 
@@ -97,6 +108,7 @@ where
 struct SerializableSpan<'a, 'b, Span>(
     &'b tracing_subscriber::registry::SpanRef<'a, Span>,
     &'b HashSet<&'static str>,
+    &'b Redaction,
 )
 where
     Span: for<'lookup> tracing_subscriber::registry::LookupSpan<'lookup>;
@@ -123,7 +135,9 @@ where
                     let key_name = key.as_str();
                     !key_name.starts_with(APOLLO_PRIVATE_PREFIX) && !self.1.contains(&key_name)
                 }) {
-                    serializer.serialize_entry(key.as_str(), &value.as_str())?;
+                    if let Some(value) = self.2.redact(key.as_str(), value) {
+                        serializer.serialize_entry(key.as_str(), &value.as_str())?;
+                    }
                 }
             }
         }
@@ -142,33 +156,7 @@ where
                     let key_name = kv.key.as_str();
                     !key_name.starts_with(APOLLO_PRIVATE_PREFIX) && !self.1.contains(&key_name)
                 }) {
-                    match &kv.value {
-                        Value::Bool(value) => {
-                            serializer.serialize_entry(kv.key.as_str(), value)?;
-                        }
-                        Value::I64(value) => {
-                            serializer.serialize_entry(kv.key.as_str(), value)?;
-                        }
-                        Value::F64(value) => {
-                            serializer.serialize_entry(kv.key.as_str(), value)?;
-                        }
-                        Value::String(value) => {
-                            serializer.serialize_entry(kv.key.as_str(), value.as_str())?;
-                        }
-                        Value::Array(Array::Bool(array)) => {
-                            serializer.serialize_entry(kv.key.as_str(), array)?;
-                        }
-                        Value::Array(Array::I64(array)) => {
-                            serializer.serialize_entry(kv.key.as_str(), array)?;
-                        }
-                        Value::Array(Array::F64(array)) => {
-                            serializer.serialize_entry(kv.key.as_str(), array)?;
-                        }
-                        Value::Array(Array::String(array)) => {
-                            let array = array.iter().map(|a| a.as_str()).collect::<Vec<_>>();
-                            serializer.serialize_entry(kv.key.as_str(), &array)?;
-                        }
-                    }
+                    serialize_key_value(&mut serializer, kv, self.2)?;
                 }
             }
         }
@@ -178,6 +166,33 @@ where
     }
 }
 
+fn serialize_key_value<M>(
+    serializer: &mut M,
+    kv: &KeyValue,
+    redaction: &Redaction,
+) -> Result<(), M::Error>
+where
+    M: SerializeMap,
+{
+    let key = kv.key.as_str();
+    let Some(value) = redaction.redact(key, &kv.value) else {
+        return Ok(());
+    };
+    match value.as_ref() {
+        Value::Bool(value) => serializer.serialize_entry(key, value),
+        Value::I64(value) => serializer.serialize_entry(key, value),
+        Value::F64(value) => serializer.serialize_entry(key, value),
+        Value::String(value) => serializer.serialize_entry(key, value.as_str()),
+        Value::Array(Array::Bool(array)) => serializer.serialize_entry(key, array),
+        Value::Array(Array::I64(array)) => serializer.serialize_entry(key, array),
+        Value::Array(Array::F64(array)) => serializer.serialize_entry(key, array),
+        Value::Array(Array::String(array)) => {
+            let array = array.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+            serializer.serialize_entry(key, &array)
+        }
+    }
+}
+
 impl<S> EventFormatter<S> for Json
 where
     S: Subscriber + for<'lookup> LookupSpan<'lookup>,
@@ -221,10 +236,19 @@ where
                 .parent()
                 .and_then(|id| ctx.span(id))
                 .or_else(|| ctx.lookup_current());
-            let mut visitor = tracing_serde::SerdeMapVisitor::new(serializer);
+            let mut visitor = RedactingVisitor::new(
+                tracing_serde::SerdeMapVisitor::new(serializer),
+                &self.redaction,
+            );
             event.record(&mut visitor);
 
-            serializer = visitor.take_serializer()?;
+            serializer = visitor.into_inner().take_serializer()?;
+
+            EventAttributes::with_current(|attributes| {
+                attributes
+                    .iter()
+                    .try_for_each(|kv| serialize_key_value(&mut serializer, kv, &self.redaction))
+            })?;
 
             if self.config.display_target {
                 serializer.serialize_entry("target", meta.target())?;
@@ -245,7 +269,10 @@ where
             if self.config.display_current_span {
                 if let Some(ref span) = current_span {
                     serializer
-                        .serialize_entry("span", &SerializableSpan(span, &self.excluded_attributes))
+                        .serialize_entry(
+                            "span",
+                            &SerializableSpan(span, &self.excluded_attributes, &self.redaction),
+                        )
                         .unwrap_or(());
                 }
             }
@@ -253,7 +280,11 @@ where
             if self.config.display_span_list && current_span.is_some() {
                 serializer.serialize_entry(
                     "spans",
-                    &SerializableContext(ctx.lookup_current(), &self.excluded_attributes),
+                    &SerializableContext(
+                        ctx.lookup_current(),
+                        &self.excluded_attributes,
+                        &self.redaction,
+                    ),
                 )?;
             }
 
@@ -1,5 +1,6 @@
 //! Our formatters and visitors used for logging
 pub(crate) mod json;
+pub(crate) mod redaction;
 pub(crate) mod text;
 
 use std::collections::LinkedList;
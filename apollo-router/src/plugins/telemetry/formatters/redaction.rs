@@ -0,0 +1,166 @@
+//! Redaction of the fields of the logs, applied by the formatters to every event so that the logs
+//! of plugins and Rhai scripts are covered too.
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+
+use opentelemetry::Value;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tracing_core::Field;
+use tracing_subscriber::field::Visit;
+
+/// The field holding the message of events, which is never redacted
+const MESSAGE_FIELD: &str = "message";
+
+/// Redaction of the fields of the logs
+#[derive(Deserialize, JsonSchema, Clone, Default, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct Redaction {
+    /// Fields removed from the logs, such as `http.request.header.authorization`.
+    drop: HashSet<String>,
+    /// Fields whose values are replaced by their SHA-256 hash, so that they can still be correlated.
+    hash: HashSet<String>,
+    /// If set, the only fields kept in the logs besides the message of events. The other fields
+    /// are removed.
+    allow: Option<HashSet<String>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Keep,
+    Drop,
+    Hash,
+}
+
+impl Redaction {
+    fn action(&self, field: &str) -> Action {
+        if field == MESSAGE_FIELD {
+            Action::Keep
+        } else if self.drop.contains(field)
+            || matches!(&self.allow, Some(allow) if !allow.contains(field))
+        {
+            Action::Drop
+        } else if self.hash.contains(field) {
+            Action::Hash
+        } else {
+            Action::Keep
+        }
+    }
+
+    /// The value of a field as it should be logged, or `None` if the field should not be logged
+    pub(crate) fn redact<'a>(&self, field: &str, value: &'a Value) -> Option<Cow<'a, Value>> {
+        match self.action(field) {
+            Action::Keep => Some(Cow::Borrowed(value)),
+            Action::Drop => None,
+            Action::Hash => Some(Cow::Owned(hash(&value.as_str()).into())),
+        }
+    }
+}
+
+fn hash(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
+
+/// Redacts the fields of events before passing them to another visitor
+pub(crate) struct RedactingVisitor<'a, N> {
+    inner: N,
+    redaction: &'a Redaction,
+}
+
+impl<'a, N> RedactingVisitor<'a, N>
+where
+    N: Visit,
+{
+    pub(crate) fn new(inner: N, redaction: &'a Redaction) -> Self {
+        Self { inner, redaction }
+    }
+
+    pub(crate) fn into_inner(self) -> N {
+        self.inner
+    }
+
+    /// Records a field through the inner visitor unless it is redacted, in which case its hash is
+    /// recorded instead or it is skipped
+    fn record(&mut self, field: &Field, value: impl fmt::Debug, record: impl FnOnce(&mut N)) {
+        match self.redaction.action(field.name()) {
+            Action::Keep => record(&mut self.inner),
+            Action::Drop => {}
+            Action::Hash => self.inner.record_str(field, &hash(&format!("{value:?}"))),
+        }
+    }
+}
+
+impl<'a, N> Visit for RedactingVisitor<'a, N>
+where
+    N: Visit,
+{
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, value, |inner| inner.record_debug(field, value))
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        // Hash the string itself rather than its debug representation, which is quoted
+        match self.redaction.action(field.name()) {
+            Action::Keep => self.inner.record_str(field, value),
+            Action::Drop => {}
+            Action::Hash => self.inner.record_str(field, &hash(value)),
+        }
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.record(field, value, |inner| inner.record_error(field, value))
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record(field, value, |inner| inner.record_f64(field, value))
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, value, |inner| inner.record_i64(field, value))
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.record(field, value, |inner| inner.record_u64(field, value))
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, value, |inner| inner.record_bool(field, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_drops_hashes_and_allows_fields() {
+        let redaction: Redaction = serde_json::from_value(serde_json::json!({
+            "drop": ["http.request.header.authorization"],
+            "hash": ["user.id"]
+        }))
+        .unwrap();
+        let value = Value::from("secret");
+        assert!(redaction
+            .redact("http.request.header.authorization", &value)
+            .is_none());
+        assert_eq!(
+            redaction.redact("user.id", &value).unwrap().as_str(),
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        );
+        assert_eq!(
+            redaction.redact("http.route", &value).unwrap().as_str(),
+            "secret"
+        );
+
+        let redaction: Redaction = serde_json::from_value(serde_json::json!({
+            "allow": ["http.route"]
+        }))
+        .unwrap();
+        assert!(redaction.redact("http.route", &value).is_some());
+        assert!(redaction.redact("user.id", &value).is_none());
+        assert_eq!(redaction.action(MESSAGE_FIELD), Action::Keep);
+    }
+}
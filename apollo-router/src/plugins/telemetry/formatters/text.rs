@@ -12,8 +12,6 @@ use tracing_core::Event;
 use tracing_core::Level;
 use tracing_core::Subscriber;
 use tracing_opentelemetry::OtelData;
-use tracing_subscriber::field;
-use tracing_subscriber::field::Visit;
 use tracing_subscriber::fmt::format::DefaultVisitor;
 use tracing_subscriber::fmt::format::Writer;
 #[cfg(not(test))]
@@ -23,9 +21,12 @@ use tracing_subscriber::layer::Context;
 use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::registry::SpanRef;
 
+use super::redaction::RedactingVisitor;
+use super::redaction::Redaction;
 use super::EventFormatter;
 use super::EXCLUDED_ATTRIBUTES;
 use crate::plugins::telemetry::config_new::logging::TextFormat;
+use crate::plugins::telemetry::dynamic_attribute::EventAttributes;
 use crate::plugins::telemetry::dynamic_attribute::LogAttributes;
 use crate::plugins::telemetry::formatters::to_list;
 use crate::plugins::telemetry::tracing::APOLLO_PRIVATE_PREFIX;
@@ -36,6 +37,7 @@ pub(crate) struct Text {
     resource: LinkedList<(String, Value)>,
     config: TextFormat,
     excluded_attributes: HashSet<&'static str>,
+    redaction: Redaction,
 }
 
 impl Default for Text {
@@ -45,6 +47,7 @@ impl Default for Text {
             resource: Default::default(),
             config: Default::default(),
             excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+            redaction: Default::default(),
         }
     }
 }
@@ -56,12 +59,13 @@ impl Text {
     const WARN_STR: &'static str = "WARN";
     const ERROR_STR: &'static str = "ERROR";
 
-    pub(crate) fn new(resource: Resource, config: TextFormat) -> Self {
+    pub(crate) fn new(resource: Resource, config: TextFormat, redaction: Redaction) -> Self {
         Self {
             timer: Default::default(),
             config,
             resource: to_list(resource),
             excluded_attributes: EXCLUDED_ATTRIBUTES.into(),
+            redaction,
         }
     }
 
@@ -184,6 +188,17 @@ impl Text {
         Ok(())
     }
 
+    fn format_event_attributes(&self, writer: &mut Writer<'_>) -> fmt::Result {
+        EventAttributes::with_current(|attributes| {
+            for kv in attributes {
+                if let Some(value) = self.redaction.redact(kv.key.as_str(), &kv.value) {
+                    write!(writer, " {}={}", kv.key, value)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
     fn write_span<S>(&self, writer: &mut Writer, span: &SpanRef<S>) -> fmt::Result
     where
         S: Subscriber + for<'a> LookupSpan<'a>,
@@ -218,8 +233,9 @@ impl Text {
             };
             for kv in attrs {
                 let key = &kv.key;
-                let value = &kv.value;
-                write!(writer, "{key}={value},")?;
+                if let Some(value) = self.redaction.redact(key.as_str(), &kv.value) {
+                    write!(writer, "{key}={value},")?;
+                }
             }
         }
 
@@ -242,7 +258,9 @@ impl Text {
             #[cfg(test)]
             let attrs: BTreeMap<&opentelemetry::Key, &opentelemetry::Value> = attrs.collect();
             for (key, value) in attrs {
-                write!(writer, "{key}={value},")?;
+                if let Some(value) = self.redaction.redact(key.as_str(), value) {
+                    write!(writer, "{key}={value},")?;
+                }
             }
         }
 
@@ -341,62 +359,15 @@ where
         }
         self.format_location(event, &mut writer)?;
 
-        let mut visitor = CustomVisitor::new(DefaultVisitor::new(writer.by_ref(), true));
+        let mut visitor =
+            RedactingVisitor::new(DefaultVisitor::new(writer.by_ref(), true), &self.redaction);
         event.record(&mut visitor);
+        self.format_event_attributes(&mut writer)?;
 
         writeln!(writer)
     }
 }
 
-struct CustomVisitor<N>(N);
-
-impl<N> CustomVisitor<N>
-where
-    N: field::Visit,
-{
-    fn new(inner: N) -> Self {
-        Self(inner)
-    }
-}
-
-// TODO we are now able to filter fields here, for now it's just a passthrough
-impl<N> Visit for CustomVisitor<N>
-where
-    N: Visit,
-{
-    fn record_debug(&mut self, field: &tracing_core::Field, value: &dyn fmt::Debug) {
-        self.0.record_debug(field, value)
-    }
-
-    fn record_str(&mut self, field: &tracing_core::Field, value: &str) {
-        self.0.record_str(field, value)
-    }
-
-    fn record_error(
-        &mut self,
-        field: &tracing_core::Field,
-        value: &(dyn std::error::Error + 'static),
-    ) {
-        self.0.record_error(field, value)
-    }
-
-    fn record_f64(&mut self, field: &tracing_core::Field, value: f64) {
-        self.0.record_f64(field, value)
-    }
-
-    fn record_i64(&mut self, field: &tracing_core::Field, value: i64) {
-        self.0.record_i64(field, value)
-    }
-
-    fn record_u64(&mut self, field: &tracing_core::Field, value: u64) {
-        self.0.record_u64(field, value)
-    }
-
-    fn record_bool(&mut self, field: &tracing_core::Field, value: bool) {
-        self.0.record_bool(field, value)
-    }
-}
-
 struct FmtThreadName<'a> {
     name: &'a str,
 }
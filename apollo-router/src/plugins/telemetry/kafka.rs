@@ -0,0 +1,93 @@
+//! Produces records to Kafka topics through a Kafka REST proxy, with the v2 API of the Confluent
+//! REST proxy.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Value;
+use tower::BoxError;
+use url::Url;
+
+const KAFKA_REST_CONTENT_TYPE: &str = "application/vnd.kafka.json.v2+json";
+
+/// A record of a Kafka topic
+pub(crate) struct KafkaRecord {
+    /// The partitioning key of the record
+    pub(crate) key: Option<String>,
+    pub(crate) value: Value,
+}
+
+#[derive(Clone)]
+pub(crate) struct KafkaRestProducer {
+    client: reqwest::Client,
+    url: Url,
+    headers: http::HeaderMap,
+}
+
+/// The response of the proxy, with an offset per record
+#[derive(Deserialize)]
+struct ProduceResponse {
+    #[serde(default)]
+    offsets: Vec<ProduceOffset>,
+}
+
+#[derive(Deserialize)]
+struct ProduceOffset {
+    error_code: Option<i64>,
+    error: Option<String>,
+}
+
+impl KafkaRestProducer {
+    pub(crate) fn new(
+        rest_proxy: &Url,
+        topic: &str,
+        headers: &HashMap<String, String>,
+    ) -> Result<Self, BoxError> {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| Ok((name.parse()?, value.parse()?)))
+            .collect::<Result<_, BoxError>>()?;
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()?,
+            url: rest_proxy.join(&format!("topics/{topic}"))?,
+            headers,
+        })
+    }
+
+    /// Produces the records, failing if the proxy did not accept every one of them
+    pub(crate) async fn produce(&self, records: &[KafkaRecord]) -> Result<(), BoxError> {
+        let records: Vec<Value> = records
+            .iter()
+            .map(|record| match &record.key {
+                Some(key) => json!({ "key": key, "value": record.value }),
+                None => json!({ "value": record.value }),
+            })
+            .collect();
+        let response: ProduceResponse = self
+            .client
+            .post(self.url.clone())
+            .headers(self.headers.clone())
+            .header(http::header::CONTENT_TYPE, KAFKA_REST_CONTENT_TYPE)
+            .body(json!({ "records": records }).to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match response
+            .offsets
+            .iter()
+            .find(|offset| offset.error_code.is_some())
+        {
+            Some(offset) => Err(format!(
+                "the Kafka REST proxy rejected records: {}",
+                offset.error.as_deref().unwrap_or("unknown error")
+            )
+            .into()),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,355 @@
+//! Export of the telemetry events to a Kafka topic, for consumers such as SIEMs that ingest from
+//! Kafka rather than OTLP.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use opentelemetry::KeyValue;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Map;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tower::BoxError;
+use url::Url;
+
+use super::access_log::json_value;
+use super::batching;
+use super::config_new::events::EventLevel;
+use super::kafka::KafkaRecord;
+use super::kafka::KafkaRestProducer;
+
+/// Events waiting to be produced, beyond which new events are dropped
+const QUEUE_CAPACITY: usize = 10_000;
+/// The maximum delay between two attempts to produce a batch with retried delivery
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How long the batches are still retried once the exporter is dropped, such as on shutdown or
+/// reload
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Export of the events to a Kafka topic through a Kafka REST proxy
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct KafkaEvents {
+    /// Set to true to export the events.
+    pub(crate) enabled: bool,
+    /// The URL of the Kafka REST proxy.
+    rest_proxy: Option<Url>,
+    /// The topic of the events.
+    topic: String,
+    /// Headers of the requests to the proxy, such as credentials.
+    headers: HashMap<String, String>,
+    /// What happens to the events that could not be produced (default: at_most_once).
+    delivery: Delivery,
+    /// The maximum number of events produced at once (default: 100).
+    batch_size: usize,
+    /// The maximum delay before queued events are produced (default: 1s).
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    flush_interval: Duration,
+}
+
+impl Default for KafkaEvents {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rest_proxy: None,
+            topic: String::new(),
+            headers: HashMap::new(),
+            delivery: Delivery::default(),
+            batch_size: 100,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// What happens to the events that could not be produced
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Delivery {
+    /// Events that could not be produced are dropped.
+    #[default]
+    AtMostOnce,
+    /// Events that could not be produced are retried, in order, and may be produced more than
+    /// once. This is best effort: events are still dropped when the queue is full, or when they
+    /// could not be produced within 30 seconds of a shutdown or reload.
+    Retry,
+}
+
+impl KafkaEvents {
+    pub(crate) fn validate(&self) -> Result<(), BoxError> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.rest_proxy.is_none() || self.topic.is_empty() {
+            return Err(
+                "telemetry.exporters.logging.kafka requires a rest_proxy and a topic".into(),
+            );
+        }
+        if self.batch_size == 0 {
+            return Err("telemetry.exporters.logging.kafka.batch_size must be positive".into());
+        }
+        Ok(())
+    }
+}
+
+/// Produces the events from a background task, so that requests never wait on Kafka
+pub(crate) struct EventExporter {
+    sender: mpsc::Sender<KafkaRecord>,
+}
+
+impl EventExporter {
+    pub(crate) fn new(config: &KafkaEvents) -> Result<Self, BoxError> {
+        config.validate()?;
+        let producer = KafkaRestProducer::new(
+            config.rest_proxy.as_ref().expect("validated"),
+            &config.topic,
+            &config.headers,
+        )?;
+        let (sender, receiver) = mpsc::channel(QUEUE_CAPACITY);
+        let batches = Arc::new(Batches {
+            producer,
+            delivery: config.delivery,
+            exporter: sender.downgrade(),
+            drain_deadline: Mutex::new(None),
+        });
+        tokio::spawn(batching::run(
+            receiver,
+            config.batch_size,
+            config.flush_interval,
+            move |batch| {
+                let batches = batches.clone();
+                async move { batches.produce(batch).await }
+            },
+        ));
+        Ok(Self { sender })
+    }
+
+    pub(crate) fn export(
+        &self,
+        level: EventLevel,
+        kind: &str,
+        attributes: &[KeyValue],
+        message: &str,
+        key: Option<String>,
+    ) {
+        let attributes: Map<String, serde_json::Value> = attributes
+            .iter()
+            .map(|KeyValue { key, value }| (key.to_string(), json_value(value.clone())))
+            .collect();
+        let value = json!({
+            "timestamp": OffsetDateTime::now_utc()
+                .format(&time::format_description::well_known::Iso8601::DEFAULT)
+                .unwrap_or_default(),
+            "type": kind,
+            "level": level,
+            "message": message,
+            "attributes": attributes,
+        });
+        if self.sender.try_send(KafkaRecord { key, value }).is_err() {
+            ::tracing::warn!("the Kafka events queue is full, dropping event");
+        }
+    }
+}
+
+/// Produces the batches of events of an exporter
+struct Batches {
+    producer: KafkaRestProducer,
+    delivery: Delivery,
+    exporter: mpsc::WeakSender<KafkaRecord>,
+    drain_deadline: Mutex<Option<Instant>>,
+}
+
+impl Batches {
+    /// Produces a batch. With retried delivery, a batch that could not be produced is retried
+    /// before the next batches, so that the events of each key stay in order, while new events
+    /// wait in the queue.
+    async fn produce(&self, batch: Vec<KafkaRecord>) {
+        let mut delay = Duration::from_millis(100);
+        while let Err(e) = self.producer.produce(&batch).await {
+            if self.delivery == Delivery::AtMostOnce {
+                ::tracing::error!("could not produce {} events to Kafka: {e}", batch.len());
+                return;
+            }
+            if self.drained() {
+                ::tracing::error!(
+                    "could not produce {} events to Kafka before the exporter was dropped: {e}",
+                    batch.len()
+                );
+                return;
+            }
+            ::tracing::warn!(
+                "could not produce {} events to Kafka, retrying in {delay:?}: {e}",
+                batch.len()
+            );
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    }
+
+    /// Whether the exporter was dropped for longer than `DRAIN_TIMEOUT`
+    fn drained(&self) -> bool {
+        let mut drain_deadline = self.drain_deadline.lock();
+        if drain_deadline.is_none() && self.exporter.upgrade().is_none() {
+            *drain_deadline = Some(Instant::now() + DRAIN_TIMEOUT);
+        }
+        drain_deadline.map_or(false, |deadline| Instant::now() >= deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use wiremock::matchers::method;
+    use wiremock::matchers::path;
+    use wiremock::Mock;
+    use wiremock::MockServer;
+    use wiremock::ResponseTemplate;
+
+    use super::*;
+
+    fn exporter(server: &MockServer, delivery: &str) -> EventExporter {
+        EventExporter::new(
+            &serde_json::from_value(json!({
+                "enabled": true,
+                "rest_proxy": server.uri(),
+                "topic": "audit",
+                "delivery": delivery,
+                "flush_interval": "10ms"
+            }))
+            .unwrap(),
+        )
+        .unwrap()
+    }
+
+    async fn produced(server: &MockServer, requests: usize) -> Vec<Value> {
+        for _ in 0..100 {
+            let received = server.received_requests().await.unwrap_or_default();
+            if received.len() >= requests {
+                return received
+                    .iter()
+                    .map(|request| serde_json::from_slice(&request.body).unwrap())
+                    .collect();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the events were not produced")
+    }
+
+    #[tokio::test]
+    async fn it_produces_events_with_their_key() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/topics/audit"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "offsets": [{ "partition": 0, "offset": 0 }] })),
+            )
+            .mount(&server)
+            .await;
+        let exporter = exporter(&server, "at_most_once");
+        exporter.export(
+            EventLevel::Warn,
+            "acme.denied",
+            &[KeyValue::new("tenant", "acme")],
+            "access denied",
+            Some("acme".to_string()),
+        );
+
+        let body = &produced(&server, 1).await[0];
+        let record = &body["records"][0];
+        assert_eq!(record["key"], "acme");
+        assert_eq!(record["value"]["type"], "acme.denied");
+        assert_eq!(record["value"]["level"], "warn");
+        assert_eq!(record["value"]["message"], "access denied");
+        assert_eq!(record["value"]["attributes"], json!({ "tenant": "acme" }));
+    }
+
+    #[tokio::test]
+    async fn it_retries_the_events_that_could_not_be_produced() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "offsets": [{ "error_code": 50003, "error": "leader not available" }]
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "offsets": [{ "partition": 0, "offset": 1 }] })),
+            )
+            .mount(&server)
+            .await;
+        let exporter = exporter(&server, "retry");
+        exporter.export(EventLevel::Info, "audit", &[], "login", None);
+
+        let bodies = produced(&server, 2).await;
+        assert_eq!(bodies[0], bodies[1]);
+        assert_eq!(bodies[1]["records"][0]["value"]["message"], "login");
+        assert!(bodies[1]["records"][0].get("key").is_none());
+    }
+
+    #[tokio::test]
+    async fn it_produces_new_events_after_the_retried_ones() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "offsets": [{ "partition": 0, "offset": 1 }] })),
+            )
+            .mount(&server)
+            .await;
+        let exporter = exporter(&server, "retry");
+        exporter.export(EventLevel::Info, "audit", &[], "login", None);
+        produced(&server, 1).await;
+        exporter.export(EventLevel::Info, "audit", &[], "logout", None);
+
+        // The new event waits for the retried one, so that the events stay in order
+        let bodies = produced(&server, 3).await;
+        let messages: Vec<_> = bodies
+            .iter()
+            .map(|body| body["records"][0]["value"]["message"].clone())
+            .collect();
+        assert_eq!(messages, ["login", "login", "logout"]);
+    }
+
+    #[tokio::test]
+    async fn it_retries_the_queued_events_once_the_exporter_is_dropped() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "offsets": [{ "partition": 0, "offset": 1 }] })),
+            )
+            .mount(&server)
+            .await;
+        let exporter = exporter(&server, "retry");
+        exporter.export(EventLevel::Info, "audit", &[], "login", None);
+        drop(exporter);
+
+        let bodies = produced(&server, 2).await;
+        assert_eq!(bodies[0], bodies[1]);
+    }
+
+    #[test]
+    fn it_requires_a_proxy_and_a_topic() {
+        let config: KafkaEvents =
+            serde_json::from_value(json!({ "enabled": true, "topic": "audit" })).unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -4,7 +4,6 @@ use std::sync::atomic::Ordering;
 use std::sync::OnceLock;
 use std::time::Duration;
 
-use opentelemetry::runtime;
 use opentelemetry::sdk::metrics::PeriodicReader;
 use opentelemetry::sdk::Resource;
 use opentelemetry_api::KeyValue;
@@ -24,6 +23,7 @@ use crate::plugins::telemetry::metrics::CustomAggregationSelector;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
 use crate::plugins::telemetry::otlp::CustomTemporalitySelector;
+use crate::plugins::telemetry::runtime::ExportRuntime;
 use crate::plugins::telemetry::tracing::BatchProcessorConfig;
 
 mod duration_histogram;
@@ -126,7 +126,7 @@ impl Config {
                     .build(),
             ),
         )?;
-        let reader = PeriodicReader::builder(exporter, runtime::Tokio)
+        let reader = PeriodicReader::builder(exporter, ExportRuntime)
             .with_interval(Duration::from_secs(60))
             .build();
 
@@ -196,6 +196,7 @@ mod test {
     use crate::plugins::telemetry::apollo_exporter::Sender;
     use crate::plugins::telemetry::Telemetry;
     use crate::plugins::telemetry::STUDIO_EXCLUDE;
+    use crate::plugins::telemetry::SYNTHETIC_TRAFFIC;
     use crate::query_planner::OperationKind;
     use crate::services::SupergraphRequest;
     use crate::Context;
@@ -339,6 +340,17 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn apollo_metrics_synthetic_traffic() -> Result<(), BoxError> {
+        let query = "query {topProducts{name}}";
+        let context = Context::new();
+        context.insert(SYNTHETIC_TRAFFIC, true)?;
+        let results = get_metrics_for_request(query, None, Some(context), false).await?;
+        assert!(results.is_empty());
+
+        Ok(())
+    }
+
     async fn get_metrics_for_request(
         query: &str,
         operation_name: Option<&str>,
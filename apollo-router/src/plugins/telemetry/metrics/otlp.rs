@@ -1,14 +1,17 @@
-use opentelemetry::runtime;
 use opentelemetry::sdk::metrics::PeriodicReader;
 use opentelemetry_otlp::HttpExporterBuilder;
 use opentelemetry_otlp::MetricsExporterBuilder;
 use opentelemetry_otlp::TonicExporterBuilder;
+use schemars::JsonSchema;
+use serde::Deserialize;
 use tower::BoxError;
 
+use crate::plugins::telemetry::config::MetricView;
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::CustomAggregationSelector;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
+use crate::plugins::telemetry::runtime::ExportRuntime;
 
 // TODO Remove MetricExporterBuilder once upstream issue is fixed
 // This has to exist because Http is not currently supported for metrics export
@@ -31,9 +34,21 @@ impl From<HttpExporterBuilder> for MetricExporterBuilder {
     }
 }
 
-impl MetricsConfigurator for super::super::otlp::Config {
+// this is a wrapper struct to add metrics specific options over the otlp exporter configuration
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(flatten)]
+    pub(crate) exporter: super::super::otlp::Config,
+
+    /// Views applied to the metrics of this exporter only, after the common views
+    #[serde(default)]
+    pub(crate) views: Vec<MetricView>,
+}
+
+impl MetricsConfigurator for Config {
     fn enabled(&self) -> bool {
-        self.enabled
+        self.exporter.enabled
     }
 
     fn apply(
@@ -41,14 +56,14 @@ impl MetricsConfigurator for super::super::otlp::Config {
         mut builder: MetricsBuilder,
         metrics_config: &MetricsCommon,
     ) -> Result<MetricsBuilder, BoxError> {
-        let exporter: MetricExporterBuilder = self.exporter()?;
-        if !self.enabled {
+        let exporter: MetricExporterBuilder = self.exporter.exporter()?;
+        if !self.exporter.enabled {
             return Ok(builder);
         }
         match exporter.exporter {
             Some(exporter) => {
                 let exporter = MetricsExporterBuilder::Tonic(exporter).build_metrics_exporter(
-                    (&self.temporality).into(),
+                    (&self.exporter.temporality).into(),
                     Box::new(
                         CustomAggregationSelector::builder()
                             .boundaries(metrics_config.buckets.clone())
@@ -58,11 +73,18 @@ impl MetricsConfigurator for super::super::otlp::Config {
 
                 builder.public_meter_provider_builder =
                     builder.public_meter_provider_builder.with_reader(
-                        PeriodicReader::builder(exporter, runtime::Tokio)
-                            .with_interval(self.batch_processor.scheduled_delay)
-                            .with_timeout(self.batch_processor.max_export_timeout)
+                        PeriodicReader::builder(exporter, ExportRuntime)
+                            .with_interval(self.exporter.batch_processor.scheduled_delay)
+                            .with_timeout(self.exporter.batch_processor.max_export_timeout)
                             .build(),
                     );
+                // The public meter provider only has the otlp reader, so its views are those of otlp
+                let views = metrics_config.views_of(&self.views);
+                if !views.is_empty() {
+                    builder.public_meter_provider_builder = builder
+                        .public_meter_provider_builder
+                        .with_view(MetricView::to_view(views));
+                }
                 Ok(builder)
             }
             None => Err("otlp metric export does not support http yet".into()),
@@ -1,12 +1,25 @@
+use std::fmt::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::task::Context;
 use std::task::Poll;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
 use futures::future::BoxFuture;
 use http::StatusCode;
+use itertools::Itertools;
 use once_cell::sync::Lazy;
 use opentelemetry::sdk::metrics::MeterProvider;
 use opentelemetry::sdk::Resource;
+use opentelemetry::KeyValue;
+use prometheus::proto::LabelPair;
+use prometheus::proto::MetricFamily;
+use prometheus::proto::MetricType;
 use prometheus::Encoder;
 use prometheus::Registry;
 use prometheus::TextEncoder;
@@ -16,12 +29,14 @@ use tower::BoxError;
 use tower::ServiceExt;
 use tower_service::Service;
 
+use crate::plugins::telemetry::config::MetricView;
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::CustomAggregationSelector;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
 use crate::router_factory::Endpoint;
 use crate::services::router;
+use crate::tracer::TraceId;
 use crate::ListenAddr;
 
 /// Prometheus configuration
@@ -34,6 +49,11 @@ pub(crate) struct Config {
     pub(crate) listen: ListenAddr,
     /// The path where prometheus will be exposed
     pub(crate) path: String,
+    /// Attach the trace id of sampled requests as exemplars to the request duration histogram buckets.
+    /// Exemplars are only exposed to scrapers requesting the OpenMetrics format.
+    pub(crate) exemplars: bool,
+    /// Views applied to the metrics of this exporter only, after the common views
+    pub(crate) views: Vec<MetricView>,
 }
 
 impl Default for Config {
@@ -42,6 +62,8 @@ impl Default for Config {
             enabled: false,
             listen: ListenAddr::SocketAddr("127.0.0.1:9090".parse().expect("valid listenAddr")),
             path: "/metrics".to_string(),
+            exemplars: false,
+            views: Vec::new(),
         }
     }
 }
@@ -53,11 +75,16 @@ static EXISTING_PROMETHEUS: Lazy<Mutex<Option<(PrometheusConfig, Registry)>>> =
     Lazy::new(Default::default);
 static NEW_PROMETHEUS: Lazy<Mutex<Option<(PrometheusConfig, Registry)>>> =
     Lazy::new(Default::default);
+// The most recent exemplar of each histogram bucket, keyed by metric name and labels.
+static EXEMPLARS: Lazy<Exemplars> = Lazy::new(Default::default);
+// Exemplars are dropped for new series past this limit so that high cardinality attributes cannot grow it unbounded.
+const MAX_EXEMPLAR_SERIES: usize = 10_000;
 
 #[derive(PartialEq, Clone)]
 struct PrometheusConfig {
     resource: Resource,
     buckets: Vec<f64>,
+    views: Vec<MetricView>,
 }
 
 pub(crate) fn commit_prometheus() {
@@ -86,7 +113,9 @@ impl MetricsConfigurator for Config {
         let prometheus_config = PrometheusConfig {
             resource: builder.resource.clone(),
             buckets: metrics_config.buckets.clone(),
+            views: metrics_config.views_of(&self.views),
         };
+        EXEMPLARS.configure(self.exemplars, metrics_config, &prometheus_config.views);
 
         // Check the last registry to see if the resources are the same, if they are we can use it as is.
         // Otherwise go with the new controller and store it so that it can be committed during telemetry activation.
@@ -104,6 +133,7 @@ impl MetricsConfigurator for Config {
                         self.path.clone(),
                         PrometheusService {
                             registry: last_registry.clone(),
+                            exemplars: self.exemplars,
                         }
                         .boxed(),
                     ),
@@ -131,16 +161,21 @@ impl MetricsConfigurator for Config {
             .with_registry(registry.clone())
             .build()?;
 
-        let meter_provider = MeterProvider::builder()
+        let mut meter_provider_builder = MeterProvider::builder()
             .with_reader(exporter)
-            .with_resource(builder.resource.clone())
-            .build();
+            .with_resource(builder.resource.clone());
+        if !prometheus_config.views.is_empty() {
+            meter_provider_builder = meter_provider_builder
+                .with_view(MetricView::to_view(prometheus_config.views.clone()));
+        }
+        let meter_provider = meter_provider_builder.build();
         builder.custom_endpoints.insert(
             self.listen.clone(),
             Endpoint::from_router_service(
                 self.path.clone(),
                 PrometheusService {
                     registry: registry.clone(),
+                    exemplars: self.exemplars,
                 }
                 .boxed(),
             ),
@@ -165,6 +200,7 @@ impl MetricsConfigurator for Config {
 #[derive(Clone)]
 pub(crate) struct PrometheusService {
     registry: Registry,
+    exemplars: bool,
 }
 
 impl Service<router::Request> for PrometheusService {
@@ -178,6 +214,27 @@ impl Service<router::Request> for PrometheusService {
 
     fn call(&mut self, req: router::Request) -> Self::Future {
         let metric_families = self.registry.gather();
+        let open_metrics = self.exemplars
+            && req
+                .router_request
+                .headers()
+                .get_all(http::header::ACCEPT)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .any(|value| value.contains(OPEN_METRICS_MEDIA_TYPE));
+        if open_metrics {
+            let body = encode_open_metrics(&metric_families, &EXEMPLARS);
+            return Box::pin(async move {
+                Ok(router::Response {
+                    response: http::Response::builder()
+                        .status(StatusCode::OK)
+                        .header(http::header::CONTENT_TYPE, OPEN_METRICS_CONTENT_TYPE)
+                        .body::<hyper::Body>(body.into())
+                        .map_err(BoxError::from)?,
+                    context: req.context,
+                })
+            });
+        }
         Box::pin(async move {
             let encoder = TextEncoder::new();
             let mut result = Vec::new();
@@ -197,3 +254,513 @@ impl Service<router::Request> for PrometheusService {
         })
     }
 }
+
+const OPEN_METRICS_MEDIA_TYPE: &str = "application/openmetrics-text";
+const OPEN_METRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+// Labels added by the opentelemetry exporter that are not part of the recorded attributes
+const OTEL_SCOPE_LABELS: [&str; 2] = ["otel_scope_name", "otel_scope_version"];
+
+/// Whether the Prometheus exporter serves exemplars, histograms only record them when it does
+pub(crate) fn exemplars_enabled() -> bool {
+    EXEMPLARS.enabled.load(Ordering::Relaxed)
+}
+
+/// Record the trace id of the current span as an exemplar of the histogram bucket the value falls in.
+/// This is a no-op unless the current trace is sampled, callers check [`exemplars_enabled`] first.
+pub(crate) fn record_exemplar(instrument: &str, unit: &str, attributes: &[KeyValue], value: f64) {
+    if let Some(trace_id) = TraceId::maybe_new() {
+        EXEMPLARS.record(instrument, unit, attributes, value, trace_id.to_string());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Exemplar {
+    trace_id: String,
+    value: f64,
+    timestamp: SystemTime,
+}
+
+/// A metric name and its sorted label pairs
+type SeriesKey = (String, Vec<(String, String)>);
+
+#[derive(Default)]
+struct Exemplars {
+    enabled: AtomicBool,
+    settings: ArcSwap<ExemplarSettings>,
+    /// Latest exemplar of each histogram bucket, per series
+    series: DashMap<SeriesKey, Vec<Option<Exemplar>>>,
+}
+
+#[derive(Default)]
+struct ExemplarSettings {
+    buckets: Vec<f64>,
+    /// Views of the prometheus exporter, overriding the buckets and attributes of instruments
+    views: Vec<MetricView>,
+    /// The settings of the instruments recorded so far, resolved from the views once per
+    /// instrument rather than for every exemplar
+    instruments: DashMap<String, Arc<InstrumentSettings>>,
+}
+
+/// How the exemplars of an instrument are recorded
+struct InstrumentSettings {
+    /// The name of the instrument in the Prometheus output
+    name: String,
+    buckets: Vec<f64>,
+    /// The attributes kept by the views of the instrument, or all of them if `None`
+    allowed_attributes: Option<Vec<String>>,
+}
+
+impl ExemplarSettings {
+    fn instrument(&self, instrument: &str, unit: &str) -> Arc<InstrumentSettings> {
+        if let Some(settings) = self.instruments.get(instrument) {
+            return settings.clone();
+        }
+        let settings = Arc::new(InstrumentSettings {
+            name: prometheus_metric_name(instrument, unit),
+            buckets: MetricView::buckets_of(&self.views, instrument)
+                .unwrap_or(&self.buckets)
+                .to_vec(),
+            allowed_attributes: MetricView::allowed_attributes_of(&self.views, instrument)
+                .map(<[String]>::to_vec),
+        });
+        self.instruments
+            .insert(instrument.to_string(), settings.clone());
+        settings
+    }
+}
+
+impl Exemplars {
+    fn configure(&self, enabled: bool, metrics_config: &MetricsCommon, views: &[MetricView]) {
+        let settings = self.settings.load();
+        if settings.buckets != metrics_config.buckets || settings.views != views {
+            self.settings.store(Arc::new(ExemplarSettings {
+                buckets: metrics_config.buckets.clone(),
+                views: views.to_vec(),
+                instruments: Default::default(),
+            }));
+            self.series.clear();
+        }
+        if !enabled {
+            self.series.clear();
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    fn record(
+        &self,
+        instrument: &str,
+        unit: &str,
+        attributes: &[KeyValue],
+        value: f64,
+        trace_id: String,
+    ) {
+        let settings = self.settings.load().instrument(instrument, unit);
+        let index = settings
+            .buckets
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(settings.buckets.len());
+        let bucket_count = settings.buckets.len() + 1;
+        let labels = attributes
+            .iter()
+            .filter(|kv| {
+                settings
+                    .allowed_attributes
+                    .as_ref()
+                    .map_or(true, |allowed| {
+                        allowed.iter().any(|key| key.as_str() == kv.key.as_str())
+                    })
+            })
+            .map(|kv| {
+                (
+                    sanitize_label_name(kv.key.as_str()),
+                    kv.value.as_str().into_owned(),
+                )
+            })
+            .sorted()
+            .collect();
+        let exemplar = Some(Exemplar {
+            trace_id,
+            value,
+            timestamp: SystemTime::now(),
+        });
+        // The series key is built before locking its shard of the series map
+        let key = (settings.name.clone(), labels);
+        if let Some(mut series) = self.series.get_mut(&key) {
+            if let Some(bucket) = series.get_mut(index) {
+                *bucket = exemplar;
+            }
+        } else if self.series.len() < MAX_EXEMPLAR_SERIES {
+            let mut series = vec![None; bucket_count];
+            series[index] = exemplar;
+            self.series.insert(key, series);
+        }
+    }
+
+    fn get(&self, name: &str, labels: &[LabelPair]) -> Option<Vec<Option<Exemplar>>> {
+        let labels = labels
+            .iter()
+            .filter(|label| !OTEL_SCOPE_LABELS.contains(&label.get_name()))
+            .map(|label| (label.get_name().to_string(), label.get_value().to_string()))
+            .sorted()
+            .collect();
+        self.series
+            .get(&(name.to_string(), labels))
+            .map(|series| series.value().clone())
+    }
+}
+
+/// The name of an instrument in the Prometheus output: invalid characters are replaced and time units
+/// are added as a suffix, the same way as the opentelemetry prometheus exporter does.
+fn prometheus_metric_name(name: &str, unit: &str) -> String {
+    let prefix = if name.starts_with(|c: char| c.is_ascii_digit()) {
+        "_"
+    } else {
+        ""
+    };
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let suffix = match unit {
+        "d" => "_days",
+        "h" => "_hours",
+        "min" => "_minutes",
+        "s" => "_seconds",
+        "ms" => "_milliseconds",
+        "us" => "_microseconds",
+        "ns" => "_nanoseconds",
+        _ => "",
+    };
+    format!("{prefix}{name}{suffix}")
+}
+
+/// Label names are sanitized the same way as the opentelemetry prometheus exporter does.
+fn sanitize_label_name(name: &str) -> String {
+    name.chars()
+        .enumerate()
+        .map(|(i, c)| {
+            if c.is_ascii_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit()) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Encode metric families in the [OpenMetrics](https://github.com/OpenObservability/OpenMetrics/blob/main/specification/OpenMetrics.md)
+/// text format, adding exemplars to histogram buckets.
+fn encode_open_metrics(families: &[MetricFamily], exemplars: &Exemplars) -> String {
+    let mut output = String::new();
+    for family in families {
+        let metric_type = family.get_field_type();
+        let name = match metric_type {
+            // OpenMetrics counter samples have a _total suffix that must not be part of the name
+            MetricType::COUNTER => family.get_name().trim_end_matches("_total"),
+            _ => family.get_name(),
+        };
+        let type_name = match metric_type {
+            MetricType::COUNTER => "counter",
+            MetricType::GAUGE => "gauge",
+            MetricType::HISTOGRAM => "histogram",
+            MetricType::SUMMARY => "summary",
+            MetricType::UNTYPED => "unknown",
+        };
+        let _ = writeln!(output, "# HELP {name} {}", escape(family.get_help(), false));
+        let _ = writeln!(output, "# TYPE {name} {type_name}");
+
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            match metric_type {
+                MetricType::COUNTER => {
+                    write_sample(
+                        &mut output,
+                        &format!("{name}_total"),
+                        labels,
+                        None,
+                        metric.get_counter().get_value(),
+                    );
+                }
+                MetricType::GAUGE => {
+                    write_sample(
+                        &mut output,
+                        name,
+                        labels,
+                        None,
+                        metric.get_gauge().get_value(),
+                    );
+                }
+                MetricType::UNTYPED => {
+                    write_sample(
+                        &mut output,
+                        name,
+                        labels,
+                        None,
+                        metric.get_untyped().get_value(),
+                    );
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        write_sample(
+                            &mut output,
+                            name,
+                            labels,
+                            Some(("quantile", quantile.get_quantile())),
+                            quantile.get_value(),
+                        );
+                    }
+                    write_sample(
+                        &mut output,
+                        &format!("{name}_sum"),
+                        labels,
+                        None,
+                        summary.get_sample_sum(),
+                    );
+                    write_sample(
+                        &mut output,
+                        &format!("{name}_count"),
+                        labels,
+                        None,
+                        summary.get_sample_count() as f64,
+                    );
+                }
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let series_exemplars = exemplars.get(name, labels);
+                    let bucket_name = format!("{name}_bucket");
+                    let mut buckets: Vec<(f64, u64)> = histogram
+                        .get_bucket()
+                        .iter()
+                        .map(|bucket| (bucket.get_upper_bound(), bucket.get_cumulative_count()))
+                        .collect();
+                    if buckets.last().map(|(bound, _)| *bound) != Some(f64::INFINITY) {
+                        buckets.push((f64::INFINITY, histogram.get_sample_count()));
+                    }
+                    for (index, (bound, count)) in buckets.into_iter().enumerate() {
+                        write_sample(
+                            &mut output,
+                            &bucket_name,
+                            labels,
+                            Some(("le", bound)),
+                            count as f64,
+                        );
+                        let exemplar = series_exemplars
+                            .as_ref()
+                            .and_then(|series| series.get(index))
+                            .and_then(Option::as_ref);
+                        if let Some(exemplar) = exemplar {
+                            // Replace the line break with the exemplar
+                            output.pop();
+                            let timestamp = exemplar
+                                .timestamp
+                                .duration_since(UNIX_EPOCH)
+                                .unwrap_or_default()
+                                .as_secs_f64();
+                            let _ = writeln!(
+                                output,
+                                " # {{trace_id=\"{}\"}} {} {:.3}",
+                                exemplar.trace_id,
+                                format_float(exemplar.value),
+                                timestamp
+                            );
+                        }
+                    }
+                    write_sample(
+                        &mut output,
+                        &format!("{name}_sum"),
+                        labels,
+                        None,
+                        histogram.get_sample_sum(),
+                    );
+                    write_sample(
+                        &mut output,
+                        &format!("{name}_count"),
+                        labels,
+                        None,
+                        histogram.get_sample_count() as f64,
+                    );
+                }
+            }
+        }
+    }
+    output.push_str("# EOF\n");
+    output
+}
+
+fn write_sample(
+    output: &mut String,
+    name: &str,
+    labels: &[LabelPair],
+    extra_label: Option<(&str, f64)>,
+    value: f64,
+) {
+    output.push_str(name);
+    if !labels.is_empty() || extra_label.is_some() {
+        let labels = labels
+            .iter()
+            .map(|label| {
+                format!(
+                    "{}=\"{}\"",
+                    label.get_name(),
+                    escape(label.get_value(), true)
+                )
+            })
+            .chain(extra_label.map(|(name, value)| format!("{name}=\"{}\"", format_float(value))))
+            .join(",");
+        let _ = write!(output, "{{{labels}}}");
+    }
+    let _ = writeln!(output, " {}", format_float(value));
+}
+
+fn format_float(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else if value.is_nan() {
+        "NaN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape(value: &str, quotes: bool) -> String {
+    let value = value.replace('\\', "\\\\").replace('\n', "\\n");
+    if quotes {
+        value.replace('"', "\\\"")
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use prometheus::HistogramOpts;
+    use prometheus::HistogramVec;
+    use prometheus::IntCounterVec;
+    use prometheus::Opts;
+
+    use super::*;
+
+    #[test]
+    fn it_encodes_open_metrics_with_exemplars() {
+        let registry = Registry::new();
+        let histogram = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "Duration of requests.")
+                .buckets(vec![0.1, 1.0]),
+            &["status", "otel_scope_name"],
+        )
+        .unwrap();
+        registry.register(Box::new(histogram.clone())).unwrap();
+        let counter = IntCounterVec::new(
+            Opts::new("requests_total", "Number of \"requests\"."),
+            &["status"],
+        )
+        .unwrap();
+        registry.register(Box::new(counter.clone())).unwrap();
+
+        histogram
+            .with_label_values(&["200", "apollo/router"])
+            .observe(0.05);
+        histogram
+            .with_label_values(&["200", "apollo/router"])
+            .observe(2.0);
+        counter.with_label_values(&["200"]).inc();
+
+        let exemplars = Exemplars::default();
+        exemplars.configure(
+            true,
+            &MetricsCommon {
+                buckets: vec![0.1, 1.0],
+                ..Default::default()
+            },
+            &[],
+        );
+        exemplars.record(
+            "request_duration_seconds",
+            "",
+            &[KeyValue::new("status", "200")],
+            2.0,
+            "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+        );
+
+        let output = encode_open_metrics(&registry.gather(), &exemplars);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines.contains(&"# TYPE request_duration_seconds histogram"));
+        assert!(lines.contains(
+            &r#"request_duration_seconds_bucket{otel_scope_name="apollo/router",status="200",le="0.1"} 1"#
+        ));
+        assert!(lines.contains(
+            &r#"request_duration_seconds_bucket{otel_scope_name="apollo/router",status="200",le="1"} 1"#
+        ));
+        let inf_bucket = lines
+            .iter()
+            .find(|line| line.contains(r#"le="+Inf""#))
+            .unwrap();
+        assert!(inf_bucket.starts_with(
+            r#"request_duration_seconds_bucket{otel_scope_name="apollo/router",status="200",le="+Inf"} 2 # {trace_id="4bf92f3577b34da6a3ce929d0e0e4736"} 2 "#
+        ));
+        assert!(lines.contains(
+            &r#"request_duration_seconds_count{otel_scope_name="apollo/router",status="200"} 2"#
+        ));
+        assert!(lines.contains(&"# TYPE requests counter"));
+        assert!(lines.contains(&r#"# HELP requests Number of "requests"."#));
+        assert!(lines.contains(&r#"requests_total{status="200"} 1"#));
+        assert_eq!(lines.last(), Some(&"# EOF"));
+    }
+
+    #[test]
+    fn it_bounds_the_exemplar_series() {
+        let exemplars = Exemplars::default();
+        exemplars.configure(
+            true,
+            &MetricsCommon {
+                buckets: vec![0.1, 1.0],
+                ..Default::default()
+            },
+            &[],
+        );
+        for i in 0..=MAX_EXEMPLAR_SERIES {
+            exemplars.record(
+                "request_duration_seconds",
+                "",
+                &[KeyValue::new("id", i.to_string())],
+                0.5,
+                "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+            );
+        }
+        assert_eq!(exemplars.series.len(), MAX_EXEMPLAR_SERIES);
+
+        exemplars.configure(
+            false,
+            &MetricsCommon {
+                buckets: vec![0.1, 1.0],
+                ..Default::default()
+            },
+            &[],
+        );
+        assert!(exemplars.series.is_empty());
+    }
+
+    #[test]
+    fn it_names_metrics_like_the_exporter() {
+        assert_eq!(
+            prometheus_metric_name("http.server.request.duration", "s"),
+            "http_server_request_duration_seconds"
+        );
+        assert_eq!(
+            prometheus_metric_name("acme.request_duration", "ms"),
+            "acme_request_duration_milliseconds"
+        );
+        assert_eq!(prometheus_metric_name("1st.duration", ""), "_1st_duration");
+    }
+}
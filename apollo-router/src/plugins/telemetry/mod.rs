@@ -3,6 +3,7 @@ use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::LinkedList;
 use std::fmt;
+use std::ops::ControlFlow;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
@@ -16,6 +17,7 @@ use dashmap::DashMap;
 use futures::future::ready;
 use futures::future::BoxFuture;
 use futures::stream::once;
+use futures::FutureExt;
 use futures::StreamExt;
 use http::header;
 use http::HeaderMap;
@@ -28,6 +30,7 @@ use opentelemetry::propagation::text_map_propagator::FieldIter;
 use opentelemetry::propagation::Extractor;
 use opentelemetry::propagation::Injector;
 use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::sdk::logs::LoggerProvider;
 use opentelemetry::sdk::propagation::TextMapCompositePropagator;
 use opentelemetry::sdk::trace::Builder;
 use opentelemetry::trace::SpanContext;
@@ -52,27 +55,61 @@ use tower::ServiceBuilder;
 use tower::ServiceExt;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
 
+use self::access_log::AccessLogEntry;
+use self::access_log::AccessLogger;
+use self::analytics::AnalyticsEntry;
+use self::analytics::AnalyticsSink;
+use self::analytics::SubgraphBreakdown;
+use self::apollo::ContextMetadata;
 use self::apollo::ForwardValues;
 use self::apollo::LicensedOperationCountByType;
 use self::apollo::OperationSubType;
 use self::apollo::SingleReport;
 use self::apollo_exporter::proto;
 use self::apollo_exporter::Sender;
+use self::baggage::FilteredBaggagePropagator;
+use self::config::BaggagePropagation;
 use self::config::Conf;
+use self::config::FieldLevelInstrumentationRatios;
 use self::config::Sampler;
 use self::config::SamplerOption;
+use self::config_new::attributes::RouterAttributes;
+use self::config_new::attributes::SubgraphAttributes;
+use self::config_new::attributes::SupergraphAttributes;
+use self::config_new::events::EventsState;
+use self::config_new::field_usage::FieldUsageCounter;
+use self::config_new::instruments::CustomInstruments;
+use self::config_new::instruments::InstrumentsState;
+use self::config_new::selectors::RouterRequestBody;
+use self::config_new::selectors::RouterSelector;
+use self::config_new::selectors::StageElapsed;
+use self::config_new::selectors::SubgraphResponseJson;
+use self::config_new::selectors::SubgraphSelector;
+use self::config_new::selectors::SupergraphSelector;
 use self::config_new::spans::Spans;
+use self::debug_token::DebugTokenPropagator;
+use self::debug_token::DebugTokenVerifier;
+use self::kafka_events::EventExporter;
 use self::metrics::apollo::studio::SingleTypeStat;
 use self::metrics::AttributesForwardConf;
 use self::reload::reload_fmt;
+use self::reload::trace_sampling;
 use self::reload::SamplingFilter;
+pub(crate) use self::config_new::shared_schemas;
 pub(crate) use self::span_factory::SpanMode;
+use self::tracing::apollo_telemetry::APOLLO_PRIVATE_CONTEXT_METADATA;
 use self::tracing::apollo_telemetry::APOLLO_PRIVATE_DURATION_NS;
 use self::tracing::apollo_telemetry::CLIENT_NAME_KEY;
 use self::tracing::apollo_telemetry::CLIENT_VERSION_KEY;
+use self::tracing::datadog::Datadog128bitPropagator;
+use self::tracing::sampling;
+use self::tracing::sampling::SamplingRules;
+use self::variable_redaction::RedactedVariables;
+use self::variable_redaction::VariableRedaction;
 use crate::axum_factory::utils::REQUEST_SPAN_NAME;
 use crate::context::OPERATION_KIND;
 use crate::context::OPERATION_NAME;
+use crate::layers::async_checkpoint::OneShotAsyncCheckpointLayer;
 use crate::layers::instrument::InstrumentLayer;
 use crate::layers::ServiceBuilderExt;
 use crate::metrics::aggregation::MeterProviderType;
@@ -89,6 +126,9 @@ use crate::plugins::telemetry::apollo_exporter::proto::reports::StatsContext;
 use crate::plugins::telemetry::config::AttributeValue;
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::config::TracingCommon;
+use crate::plugins::telemetry::config_new::attributes::QueryPlanningAttributes;
+use crate::plugins::telemetry::config_new::selectors::FirstResponseData;
+use crate::plugins::telemetry::config_new::selectors::FirstResponseErrors;
 use crate::plugins::telemetry::dynamic_attribute::DynAttribute;
 use crate::plugins::telemetry::fmt_layer::create_fmt_layer;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleContextualizedStats;
@@ -99,13 +139,18 @@ use crate::plugins::telemetry::metrics::apollo::studio::SingleStatsReport;
 use crate::plugins::telemetry::metrics::prometheus::commit_prometheus;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
+use crate::plugins::telemetry::otlp_logs::create_logger_provider;
 use crate::plugins::telemetry::reload::metrics_layer;
 use crate::plugins::telemetry::reload::OPENTELEMETRY_TRACER_HANDLE;
 use crate::plugins::telemetry::tracing::apollo_telemetry::decode_ftv1_trace;
+use crate::plugins::telemetry::tracing::apollo_telemetry::APOLLO_PRIVATE_FIELD_EXECUTION_WEIGHT;
+use crate::plugins::telemetry::tracing::apollo_telemetry::APOLLO_PRIVATE_FTV1_FIELD_EXECUTION_WEIGHT;
 use crate::plugins::telemetry::tracing::apollo_telemetry::APOLLO_PRIVATE_OPERATION_SIGNATURE;
 use crate::plugins::telemetry::tracing::TracingConfigurator;
+use crate::plugins::telemetry::tracing::APOLLO_PRIVATE_SERVICE_NAME;
 use crate::plugins::telemetry::utils::TracingUtils;
 use crate::query_planner::OperationKind;
+use crate::query_planner::QueryPlan;
 use crate::register_plugin;
 use crate::router_factory::Endpoint;
 use crate::services::execution;
@@ -115,6 +160,8 @@ use crate::services::subgraph::Request;
 use crate::services::subgraph::Response;
 use crate::services::supergraph;
 use crate::services::ExecutionRequest;
+use crate::services::ExecutionResponse;
+use crate::services::QueryPlannerResponse;
 use crate::services::SubgraphRequest;
 use crate::services::SubgraphResponse;
 use crate::services::SupergraphRequest;
@@ -124,36 +171,55 @@ use crate::tracer::TraceId;
 use crate::Context;
 use crate::ListenAddr;
 
+mod access_log;
+mod analytics;
 pub(crate) mod apollo;
 pub(crate) mod apollo_exporter;
+mod baggage;
+mod batching;
 pub(crate) mod config;
 mod config_new;
+pub(crate) mod debug_token;
 pub(crate) mod dynamic_attribute;
 mod endpoint;
 mod fmt_layer;
 pub(crate) mod formatters;
+mod kafka;
+mod kafka_events;
 pub(crate) mod metrics;
 mod otlp;
+mod otlp_logs;
 pub(crate) mod reload;
 mod resource;
+pub(crate) mod runtime;
 mod span_factory;
+pub(crate) mod subgraph_bodies;
 pub(crate) mod tracing;
 pub(crate) mod utils;
+pub(crate) mod variable_redaction;
 
 // Tracing consts
 pub(crate) const SUPERGRAPH_SPAN_NAME: &str = "supergraph";
 pub(crate) const SUBGRAPH_SPAN_NAME: &str = "subgraph";
 pub(crate) const ROUTER_SPAN_NAME: &str = "router";
 pub(crate) const EXECUTION_SPAN_NAME: &str = "execution";
-const CLIENT_NAME: &str = "apollo_telemetry::client_name";
-const CLIENT_VERSION: &str = "apollo_telemetry::client_version";
+pub(crate) const CLIENT_NAME: &str = "apollo_telemetry::client_name";
+pub(crate) const CLIENT_VERSION: &str = "apollo_telemetry::client_version";
 const SUBGRAPH_FTV1: &str = "apollo_telemetry::subgraph_ftv1";
 pub(crate) const STUDIO_EXCLUDE: &str = "apollo_telemetry::studio::exclude";
+pub(crate) const SYNTHETIC_TRAFFIC: &str = "apollo_telemetry::synthetic_traffic";
+pub(crate) const SYNTHETIC_TRAFFIC_ATTRIBUTE: &str = "apollo.synthetic";
 pub(crate) const LOGGING_DISPLAY_HEADERS: &str = "apollo_telemetry::logging::display_headers";
 pub(crate) const LOGGING_DISPLAY_BODY: &str = "apollo_telemetry::logging::display_body";
 pub(crate) const OTEL_STATUS_CODE: &str = "otel.status_code";
 const GLOBAL_TRACER_NAME: &str = "apollo-router";
 const DEFAULT_EXPOSE_TRACE_ID_HEADER: &str = "apollo-trace-id";
+const OPERATION_SIGNATURE_EXTENSION: &str = "apolloOperationSignature";
+
+/// Marks the subgraph requests which must carry the Zipkin B3 multi headers along with the
+/// single `b3` header
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ZipkinMultiHeader;
 static DEFAULT_EXPOSE_TRACE_ID_HEADER_NAME: HeaderName =
     HeaderName::from_static(DEFAULT_EXPOSE_TRACE_ID_HEADER);
 static FTV1_HEADER_NAME: HeaderName = HeaderName::from_static("apollo-federation-include-trace");
@@ -164,8 +230,10 @@ pub(crate) struct Telemetry {
     config: Arc<config::Conf>,
     custom_endpoints: MultiMap<ListenAddr, Endpoint>,
     apollo_metrics_sender: apollo_exporter::Sender,
-    field_level_instrumentation_ratio: f64,
+    field_level_instrumentation_ratios: Arc<FieldLevelInstrumentationRatios>,
+    variable_redaction: Arc<VariableRedaction>,
     sampling_filter_ratio: SamplerOption,
+    sampling_rules: Option<Arc<SamplingRules>>,
 
     tracer_provider: Option<opentelemetry::sdk::trace::TracerProvider>,
     // We have to have separate meter providers for prometheus metrics so that they don't get zapped on router reload.
@@ -173,6 +241,17 @@ pub(crate) struct Telemetry {
     public_prometheus_meter_provider: Option<FilterMeterProvider>,
     private_meter_provider: Option<FilterMeterProvider>,
     counter: Option<Arc<Mutex<CacheCounter>>>,
+    access_logger: Option<Arc<AccessLogger>>,
+    analytics_sink: Option<Arc<AnalyticsSink>>,
+    event_exporter: Option<Arc<EventExporter>>,
+    logger_provider: Option<LoggerProvider>,
+    debug_token: Option<DebugTokenVerifier>,
+    // The custom instruments are shared by the services of every pipeline and subgraph, so that
+    // their cardinality limits apply to the instrument as a whole
+    router_instruments: CustomInstruments<RouterAttributes, RouterSelector>,
+    supergraph_instruments: CustomInstruments<SupergraphAttributes, SupergraphSelector>,
+    subgraph_instruments: CustomInstruments<SubgraphAttributes, SubgraphSelector>,
+    field_usage: Option<FieldUsageCounter>,
 }
 
 #[derive(Debug)]
@@ -221,6 +300,12 @@ impl Drop for Telemetry {
         if let Some(tracer_provider) = self.tracer_provider.take() {
             Self::checked_tracer_shutdown(tracer_provider);
         }
+
+        if let Some(logger_provider) = self.logger_provider.take() {
+            Self::checked_spawn_task(Box::new(move || {
+                drop(logger_provider);
+            }));
+        }
     }
 }
 
@@ -235,9 +320,14 @@ impl Plugin for Telemetry {
         let mut config = init.config;
         config.instrumentation.spans.update_defaults();
         config.exporters.logging.validate()?;
-
-        let field_level_instrumentation_ratio =
-            config.calculate_field_level_instrumentation_ratio()?;
+        config.instrumentation.instruments.validate()?;
+
+        let field_level_instrumentation_ratios =
+            Arc::new(config.calculate_field_level_instrumentation_ratios()?);
+        let variable_redaction = Arc::new(VariableRedaction::new(
+            &config.instrumentation.variable_redaction,
+            &init.supergraph_sdl,
+        )?);
         // TODO move cache metrics to cache plugin.
         let metrics_builder = Self::create_metrics_builder(&config)?;
 
@@ -259,7 +349,34 @@ impl Plugin for Telemetry {
         } else {
             None
         };
-        let (sampling_filter_ratio, tracer_provider) = Self::create_tracer_provider(&config)?;
+        let access_logger = if config.exporters.logging.access_log.enabled {
+            Some(Arc::new(AccessLogger::new(
+                &config.exporters.logging.access_log,
+            )?))
+        } else {
+            None
+        };
+        let analytics_sink = if config.exporters.analytics.enabled {
+            Some(Arc::new(AnalyticsSink::new(&config.exporters.analytics)?))
+        } else {
+            None
+        };
+        let event_exporter = if config.exporters.logging.kafka.enabled {
+            Some(Arc::new(EventExporter::new(
+                &config.exporters.logging.kafka,
+            )?))
+        } else {
+            None
+        };
+        let logger_provider = create_logger_provider(&config.exporters.logging)?;
+        let debug_token = DebugTokenVerifier::new(&config.exporters.tracing.debug_token)?;
+        let (sampling_filter_ratio, sampling_rules, tracer_provider) =
+            Self::create_tracer_provider(&config)?;
+        let instruments = &config.instrumentation.instruments;
+        let router_instruments = instruments.new_router_instruments();
+        let supergraph_instruments = instruments.new_supergraph_instruments();
+        let subgraph_instruments = instruments.new_subgraph_instruments();
+        let field_usage = instruments.new_field_usage_counter();
 
         if config.instrumentation.spans.mode == SpanMode::Deprecated {
             ::tracing::warn!("telemetry.instrumentation.spans.mode is currently set to 'deprecated', either explicitly or via defaulting. Set telemetry.instrumentation.spans.mode explicitly in your router.yaml to 'spec_compliant' for log and span attributes that follow OpenTelemetry semantic conventions. This option will be defaulted to 'spec_compliant' in a future release and eventually removed altogether");
@@ -268,7 +385,8 @@ impl Plugin for Telemetry {
         Ok(Telemetry {
             custom_endpoints: metrics_builder.custom_endpoints,
             apollo_metrics_sender: metrics_builder.apollo_metrics_sender,
-            field_level_instrumentation_ratio,
+            field_level_instrumentation_ratios,
+            variable_redaction,
             tracer_provider: Some(tracer_provider),
             public_meter_provider: Some(FilterMeterProvider::public(
                 metrics_builder.public_meter_provider_builder.build(),
@@ -280,8 +398,18 @@ impl Plugin for Telemetry {
                 .prometheus_meter_provider
                 .map(FilterMeterProvider::public),
             sampling_filter_ratio,
+            sampling_rules,
             config: Arc::new(config),
             counter,
+            access_logger,
+            analytics_sink,
+            event_exporter,
+            logger_provider,
+            debug_token,
+            router_instruments,
+            supergraph_instruments,
+            subgraph_instruments,
+            field_usage,
         })
     }
 
@@ -292,6 +420,22 @@ impl Plugin for Telemetry {
         let span_mode = config.instrumentation.spans.mode;
         let use_legacy_request_span =
             matches!(config.instrumentation.spans.mode, SpanMode::Deprecated);
+        let buffer_request_body = config
+            .instrumentation
+            .spans
+            .router
+            .attributes
+            .custom
+            .values()
+            .any(RouterSelector::needs_request_body);
+        let router_instruments = self.router_instruments.clone();
+        let router_events = config
+            .instrumentation
+            .events
+            .new_router_events()
+            .with_exporter(self.event_exporter.clone());
+        let access_logger = self.access_logger.clone();
+        let synthetic_traffic = config.instrumentation.synthetic_traffic.clone();
 
         ServiceBuilder::new()
             .map_response(move |response: router::Response| {
@@ -328,6 +472,60 @@ impl Plugin for Telemetry {
                     span_mode.create_router(&request.router_request)
                 })
             }))
+            .option_layer(buffer_request_body.then(|| {
+                OneShotAsyncCheckpointLayer::new(|request: router::Request| {
+                    async move {
+                        RouterRequestBody::buffer(request)
+                            .await
+                            .map(ControlFlow::Continue)
+                    }
+                    .boxed()
+                })
+            }))
+            .map_request(move |mut request: router::Request| {
+                if synthetic_traffic.take_synthetic(request.router_request.headers_mut()) {
+                    let _ = request.context.insert(SYNTHETIC_TRAFFIC, true);
+                }
+                request
+            })
+            .map_future_with_request_data(
+                move |request: &router::Request| {
+                    (
+                        router_instruments.on_request(request),
+                        router_events
+                            .for_context(&request.context)
+                            .on_request(request),
+                        access_logger
+                            .clone()
+                            .map(|logger| (logger.on_request(request), logger)),
+                    )
+                },
+                |(instruments, events, access_log): (
+                    InstrumentsState<_, _>,
+                    EventsState<_, _>,
+                    Option<(AccessLogEntry, Arc<AccessLogger>)>,
+                ),
+                 fut| async move {
+                    let result: Result<router::Response, BoxError> = fut.await;
+                    match &result {
+                        Ok(response) => {
+                            instruments.on_response(response);
+                            events.on_response(response);
+                            if let Some((entry, logger)) = access_log {
+                                logger.on_response(entry, response);
+                            }
+                        }
+                        Err(err) => {
+                            instruments.on_error(err);
+                            events.on_error(err);
+                            if let Some((entry, logger)) = access_log {
+                                logger.on_error(entry, err);
+                            }
+                        }
+                    }
+                    result
+                },
+            )
             .map_future_with_request_data(
                 move |request: &router::Request| {
                     if !use_legacy_request_span {
@@ -358,6 +556,10 @@ impl Plugin for Telemetry {
                         .router
                         .attributes
                         .on_request(request);
+                    if is_synthetic_traffic(&request.context) {
+                        custom_attributes
+                            .push_back(KeyValue::new(SYNTHETIC_TRAFFIC_ATTRIBUTE, true));
+                    }
                     custom_attributes.extend([
                         KeyValue::new(CLIENT_NAME_KEY, client_name.to_string()),
                         KeyValue::new(CLIENT_VERSION_KEY, client_version.to_string()),
@@ -398,6 +600,13 @@ impl Plugin for Telemetry {
                                     .attributes
                                     .on_response(response),
                             );
+                            // Context entries are read once the pipeline has populated them
+                            if !config.apollo.send_context.is_empty() {
+                                span.set_dyn_attributes([KeyValue::new(
+                                    APOLLO_PRIVATE_CONTEXT_METADATA,
+                                    filter_context(&response.context, &config.apollo.send_context),
+                                )]);
+                            }
                             if expose_trace_id.enabled {
                                 if let Some(header_name) = &expose_trace_id.header_name {
                                     let mut headers: HashMap<String, Vec<String>> =
@@ -427,7 +636,12 @@ impl Plugin for Telemetry {
                         } else if let Err(err) = &response {
                             span.record(OTEL_STATUS_CODE, "Error");
                             span.set_dyn_attributes(
-                                config.instrumentation.spans.router.attributes.on_error(err),
+                                config
+                                    .instrumentation
+                                    .spans
+                                    .router
+                                    .attributes
+                                    .on_error_after(err, start.elapsed()),
                             );
                         }
 
@@ -435,6 +649,17 @@ impl Plugin for Telemetry {
                     }
                 },
             )
+            .map_future(|fut| {
+                let start = Instant::now();
+                async move {
+                    let mut response: router::Response = fut.await?;
+                    response
+                        .response
+                        .extensions_mut()
+                        .insert(StageElapsed(start.elapsed()));
+                    Ok::<_, BoxError>(response)
+                }
+            })
             .service(service)
             .boxed()
     }
@@ -446,24 +671,95 @@ impl Plugin for Telemetry {
         let config_instrument = self.config.clone();
         let config_map_res_first = config.clone();
         let config_map_res = config.clone();
-        let field_level_instrumentation_ratio = self.field_level_instrumentation_ratio;
+        let field_level_instrumentation_ratios = self.field_level_instrumentation_ratios.clone();
+        let field_level_instrumentation_ratio = field_level_instrumentation_ratios.all;
+        let variable_redaction = self.variable_redaction.clone();
+        let supergraph_instruments = self.supergraph_instruments.clone();
+        let field_usage = self.field_usage.clone();
+        let supergraph_events = config
+            .instrumentation
+            .events
+            .new_supergraph_events()
+            .with_exporter(self.event_exporter.clone());
+        let analytics_sink = self.analytics_sink.clone();
+        let sampling_rules = self.sampling_rules.clone();
+        let debug_token = self.debug_token.clone();
+        let record_response_data = config
+            .instrumentation
+            .spans
+            .supergraph
+            .attributes
+            .custom
+            .values()
+            .any(SupergraphSelector::needs_response_data);
+        let record_response_errors = config
+            .instrumentation
+            .spans
+            .supergraph
+            .experimental_field_events
+            .enabled
+            || config
+                .instrumentation
+                .spans
+                .supergraph
+                .attributes
+                .custom
+                .values()
+                .any(SupergraphSelector::needs_response_errors)
+            || config
+                .instrumentation
+                .events
+                .supergraph_selects(SupergraphSelector::needs_response_errors)
+            || config
+                .instrumentation
+                .instruments
+                .supergraph_selects(SupergraphSelector::needs_response_errors)
+            // The analytics records include the errors of the first response
+            || config.exporters.analytics.enabled;
         ServiceBuilder::new()
             .instrument(move |supergraph_req: &SupergraphRequest| span_mode.create_supergraph(
                 &config_instrument.apollo,
                 supergraph_req,
                 field_level_instrumentation_ratio,
+                &variable_redaction.populate_context(&supergraph_req.context),
             ))
+            .map_request(move |req: SupergraphRequest| {
+                // Requests are authenticated by now, so the subject of their debug token is checked
+                if let Some(debug_token) = &debug_token {
+                    debug_token.authorize(&req.context);
+                }
+                req
+            })
             .map_response(move |mut resp: SupergraphResponse| {
                 let config = config_map_res_first.clone();
-                if let Some(usage_reporting) =
-                    resp.context.private_entries.lock().get::<UsageReporting>()
-                {
+                // Requests that were not executed, such as invalid ones, are sampled without
+                // their query plan
+                if let Some(rules) = &sampling_rules {
+                    Self::apply_sampling_rules(rules, &resp.context, None);
+                }
+                // The usage reporting is cloned out so that the fields are recorded without
+                // holding the lock of the context
+                let (operation_signature, usage_reporting) = {
+                    let private_entries = resp.context.private_entries.lock();
+                    let usage_reporting = private_entries.get::<UsageReporting>();
+                    (
+                        usage_reporting.map(|usage_reporting| usage_reporting.stats_report_key.clone()),
+                        usage_reporting.filter(|_| field_usage.is_some()).cloned(),
+                    )
+                };
+                if let (Some(field_usage), Some(usage_reporting)) = (&field_usage, &usage_reporting) {
+                    field_usage.record(usage_reporting);
+                }
+                if let Some(operation_signature) = &operation_signature {
                     // Record the operation signature on the router span
                     Span::current().record(
                         APOLLO_PRIVATE_OPERATION_SIGNATURE.as_str(),
-                        usage_reporting.stats_report_key.as_str(),
+                        operation_signature.as_str(),
                     );
                 }
+                // Only the first response of the stream carries the signature
+                let mut operation_signature =
+                    operation_signature.filter(|_| config.apollo.expose_operation_signature);
                 // To expose trace_id or not
                 let expose_trace_id_header = config.exporters.tracing.response_trace_id.enabled.then(|| {
                     config.exporters.tracing.response_trace_id
@@ -483,31 +779,83 @@ impl Plugin for Telemetry {
                     ::tracing::info!(http.response.headers = ?resp.response.headers(), "Supergraph response headers");
                 }
                 let display_body = resp.context.contains_key(LOGGING_DISPLAY_BODY);
-                resp.map_stream(move |gql_response| {
+                resp.map_stream(move |mut gql_response| {
+                    if let Some(operation_signature) = operation_signature.take() {
+                        gql_response.extensions.insert(
+                            OPERATION_SIGNATURE_EXTENSION,
+                            operation_signature.into(),
+                        );
+                    }
                     if display_body {
                         ::tracing::info!(http.response.body = ?gql_response, "Supergraph GraphQL response");
                     }
                     gql_response
                 })
             })
+            .map_future_with_request_data(
+                move |req: &SupergraphRequest| {
+                    (
+                        supergraph_instruments.on_request(req),
+                        supergraph_events.for_context(&req.context).on_request(req),
+                        analytics_sink
+                            .clone()
+                            .and_then(|sink| Some((sink.on_request(req)?, sink))),
+                    )
+                },
+                |(instruments, events, analytics): (
+                    InstrumentsState<_, _>,
+                    EventsState<_, _>,
+                    Option<(AnalyticsEntry, Arc<AnalyticsSink>)>,
+                ), fut| async move {
+                    let result: Result<SupergraphResponse, BoxError> = fut.await;
+                    match &result {
+                        Ok(resp) => {
+                            instruments.on_response(resp);
+                            events.on_response(resp);
+                            if let Some((entry, sink)) = analytics {
+                                sink.on_response(entry, resp);
+                            }
+                        }
+                        Err(err) => {
+                            instruments.on_error(err);
+                            events.on_error(err);
+                            if let Some((entry, sink)) = analytics {
+                                sink.on_error(entry);
+                            }
+                        }
+                    }
+                    result
+                },
+            )
             .map_future_with_request_data(
                 move |req: &SupergraphRequest| {
                     let custom_attributes = config.instrumentation.spans.supergraph.attributes.on_request(req);
-                    Self::populate_context(config.clone(), field_level_instrumentation_ratio, req);
+                    Self::populate_context(config.clone(), req);
                     (req.context.clone(), custom_attributes)
                 },
                 move |(ctx, custom_attributes): (Context, LinkedList<KeyValue>), fut| {
                     let config = config_map_res.clone();
                     let sender = metrics_sender.clone();
+                    let field_level_instrumentation_ratios = field_level_instrumentation_ratios.clone();
                     let start = Instant::now();
 
                     async move {
                         let span = Span::current();
                         span.set_dyn_attributes(custom_attributes);
                         let mut result: Result<SupergraphResponse, BoxError> = fut.await;
+                        span.set_dyn_attribute(
+                            APOLLO_PRIVATE_FIELD_EXECUTION_WEIGHT,
+                            field_execution_weight(&ctx, field_level_instrumentation_ratios.all).into(),
+                        );
                         match &result {
-                            Ok(resp) => span.set_dyn_attributes(config.instrumentation.spans.supergraph.attributes.on_response(resp)),
-                            Err(err) => span.set_dyn_attributes(config.instrumentation.spans.supergraph.attributes.on_error(err)),
+                            Ok(resp) => {
+                                span.set_dyn_attributes(config.instrumentation.spans.supergraph.attributes.on_response(resp));
+                                config.instrumentation.spans.supergraph.experimental_field_events.emit(&span, &resp.context);
+                            }
+                            Err(err) => span.set_dyn_attributes(config.instrumentation.spans.supergraph.attributes.on_error_after(err, start.elapsed())),
+                        }
+                        if record_response_data {
+                            result = result.map(|resp| Self::record_deferred_response_data(config.clone(), span, resp));
                         }
                         result = Self::update_otel_metrics(
                             config.clone(),
@@ -517,17 +865,47 @@ impl Plugin for Telemetry {
                         )
                         .await;
                         Self::update_metrics_on_response_events(
-                            &ctx, config, field_level_instrumentation_ratio, sender, start, result,
+                            &ctx, config, field_level_instrumentation_ratios, sender, start, result,
                         )
                     }
                 },
             )
+            .map_future(|fut| {
+                let start = Instant::now();
+                async move {
+                    let mut response: SupergraphResponse = fut.await?;
+                    response
+                        .response
+                        .extensions_mut()
+                        .insert(StageElapsed(start.elapsed()));
+                    Ok::<_, BoxError>(response)
+                }
+            })
+            .map_future(move |fut| async move {
+                let response: SupergraphResponse = fut.await?;
+                Ok::<_, BoxError>(
+                    Self::record_first_response(
+                        response,
+                        record_response_errors,
+                        record_response_data,
+                    )
+                    .await,
+                )
+            })
             .service(service)
             .boxed()
     }
 
     fn execution_service(&self, service: execution::BoxService) -> execution::BoxService {
+        let config = self.config.clone();
+        let sampling_rules = self.sampling_rules.clone();
         ServiceBuilder::new()
+            .map_request(move |req: ExecutionRequest| {
+                if let Some(rules) = &sampling_rules {
+                    Self::apply_sampling_rules(rules, &req.context, Some(&req.query_plan));
+                }
+                req
+            })
             .instrument(move |req: &ExecutionRequest| {
                 let operation_kind = req
                     .query_plan
@@ -548,6 +926,43 @@ impl Plugin for Telemetry {
                     }
                 }
             })
+            .map_future_with_request_data(
+                move |req: &ExecutionRequest| {
+                    (
+                        config.clone(),
+                        config
+                            .instrumentation
+                            .spans
+                            .execution
+                            .attributes
+                            .on_request(req),
+                    )
+                },
+                |(config, custom_attributes): (Arc<Conf>, LinkedList<KeyValue>), fut| async move {
+                    let span = Span::current();
+                    span.set_dyn_attributes(custom_attributes);
+                    let result: Result<ExecutionResponse, BoxError> = fut.await;
+                    match &result {
+                        Ok(resp) => span.set_dyn_attributes(
+                            config
+                                .instrumentation
+                                .spans
+                                .execution
+                                .attributes
+                                .on_response(resp),
+                        ),
+                        Err(err) => span.set_dyn_attributes(
+                            config
+                                .instrumentation
+                                .spans
+                                .execution
+                                .attributes
+                                .on_error(err),
+                        ),
+                    }
+                    result
+                },
+            )
             .service(service)
             .boxed()
     }
@@ -564,6 +979,37 @@ impl Plugin for Telemetry {
         let counter = self.counter.clone();
         let name = name.to_owned();
         let subgraph_name_arc = Arc::new(name.to_owned());
+        let subgraph_breakdown_name = subgraph_name_arc.clone();
+        let field_level_instrumentation_ratio =
+            self.field_level_instrumentation_ratios.subgraph(&name);
+        let subgraph_instruments = self.subgraph_instruments.clone();
+        let subgraph_events = self
+            .config
+            .instrumentation
+            .events
+            .new_subgraph_events()
+            .with_exporter(self.event_exporter.clone());
+        let service_name_attribute = self
+            .config
+            .instrumentation
+            .spans
+            .subgraph
+            .service_name(&name)
+            .map(|service_name| KeyValue::new(APOLLO_PRIVATE_SERVICE_NAME, service_name));
+        let zipkin_multi_header = self
+            .config
+            .exporters
+            .tracing
+            .propagation
+            .zipkin_multi_header
+            .get(&name)
+            .enabled;
+        let body_logger = self
+            .config
+            .exporters
+            .logging
+            .experimental_subgraph_bodies
+            .logger(&name);
         ServiceBuilder::new()
             .instrument(move |req: &SubgraphRequest| span_mode.create_subgraph(name.as_str(), req))
             .map_request(move |mut req: SubgraphRequest| {
@@ -573,10 +1019,68 @@ impl Plugin for Telemetry {
                 if let Some(cache_attributes) = cache_attributes {
                     req.context.private_entries.lock().insert(cache_attributes);
                 }
+                if zipkin_multi_header {
+                    req.subgraph_request
+                        .extensions_mut()
+                        .insert(ZipkinMultiHeader);
+                }
+                if let Some(body_logger) = body_logger.as_ref().filter(|logger| logger.sample()) {
+                    req.subgraph_request
+                        .extensions_mut()
+                        .insert(body_logger.clone());
+                }
 
-                request_ftv1(req)
+                request_ftv1(req, field_level_instrumentation_ratio)
+            })
+            .map_response(move |resp| {
+                store_ftv1(&subgraph_name, field_level_instrumentation_ratio, resp)
             })
-            .map_response(move |resp| store_ftv1(&subgraph_name, resp))
+            .map_future_with_request_data(
+                move |sub_request: &SubgraphRequest| {
+                    (
+                        subgraph_instruments.on_request(sub_request),
+                        subgraph_events
+                            .for_context(&sub_request.context)
+                            .on_request(sub_request),
+                    )
+                },
+                |(instruments, events): (InstrumentsState<_, _>, EventsState<_, _>), f| async move {
+                    let result: Result<SubgraphResponse, BoxError> = f.await;
+                    match &result {
+                        Ok(resp) => {
+                            instruments.on_response(resp);
+                            events.on_response(resp);
+                        }
+                        Err(err) => {
+                            instruments.on_error(err);
+                            events.on_error(err);
+                        }
+                    }
+                    result
+                },
+            )
+            .map_future_with_request_data(
+                // Only operations sampled by the analytics sink have a breakdown
+                move |sub_request: &SubgraphRequest| {
+                    sub_request
+                        .context
+                        .private_entries
+                        .lock()
+                        .get::<SubgraphBreakdown>()
+                        .cloned()
+                },
+                move |breakdown: Option<SubgraphBreakdown>, f| {
+                    let subgraph_name = subgraph_breakdown_name.clone();
+                    async move {
+                        let start = Instant::now();
+                        let result: Result<SubgraphResponse, BoxError> = f.await;
+                        if let Some(breakdown) = breakdown {
+                            breakdown.record(&subgraph_name, start.elapsed(), &result);
+                        }
+                        result
+                    }
+                },
+            )
             .map_future_with_request_data(
                 move |sub_request: &SubgraphRequest| {
                     Self::store_subgraph_request_attributes(
@@ -604,6 +1108,7 @@ impl Plugin for Telemetry {
                 ),
                       f: BoxFuture<'static, Result<SubgraphResponse, BoxError>>| {
                     let subgraph_attribute = subgraph_attribute.clone();
+                    let service_name_attribute = service_name_attribute.clone();
                     let subgraph_metrics_conf = subgraph_metrics_conf_resp.clone();
                     let counter = counter.clone();
                     let conf = conf.clone();
@@ -612,6 +1117,7 @@ impl Plugin for Telemetry {
                     async move {
                         let span = Span::current();
                         span.set_dyn_attributes(custom_attributes);
+                        span.set_dyn_attributes(service_name_attribute);
                         let result: Result<SubgraphResponse, BoxError> = f.await;
 
                         match &result {
@@ -633,7 +1139,11 @@ impl Plugin for Telemetry {
                                 span.record(OTEL_STATUS_CODE, "Error");
 
                                 span.set_dyn_attributes(
-                                    conf.instrumentation.spans.subgraph.attributes.on_error(err),
+                                    conf.instrumentation
+                                        .spans
+                                        .subgraph
+                                        .attributes
+                                        .on_error_after(err, now.elapsed()),
                                 );
                             }
                         }
@@ -651,6 +1161,19 @@ impl Plugin for Telemetry {
                     }
                 },
             )
+            .map_future(
+                |fut: BoxFuture<'static, Result<SubgraphResponse, BoxError>>| {
+                    let start = Instant::now();
+                    async move {
+                        let mut response = fut.await?;
+                        let extensions = response.response.extensions_mut();
+                        extensions.insert(StageElapsed(start.elapsed()));
+                        extensions.insert(SubgraphResponseJson::default());
+                        Ok(response)
+                    }
+                    .boxed()
+                },
+            )
             .service(service)
             .boxed()
     }
@@ -665,7 +1188,14 @@ impl Telemetry {
         // Only apply things if we were executing in the context of a vanilla the Apollo executable.
         // Users that are rolling their own routers will need to set up telemetry themselves.
         if let Some(hot_tracer) = OPENTELEMETRY_TRACER_HANDLE.get() {
-            SamplingFilter::configure(&self.sampling_filter_ratio);
+            // Sampling rules must be in place before root spans get enabled with their ratio
+            hot_tracer.defer_sampling(self.sampling_rules.as_ref().map(|rules| {
+                sampling::conditional_ratio(rules.default_ratio(), rules.max_ratio())
+            }));
+            SamplingFilter::configure(
+                &self.sampling_filter_ratio,
+                self.sampling_rules.as_ref().map(|rules| rules.max_ratio()),
+            );
 
             // The reason that this has to happen here is that we are interacting with global state.
             // If we do this logic during plugin init then if a subsequent plugin fails to init then we
@@ -688,15 +1218,73 @@ impl Telemetry {
 
             Self::checked_global_tracer_shutdown(last_provider);
 
-            opentelemetry::global::set_text_map_propagator(Self::create_propagator(&self.config));
+            opentelemetry::global::set_text_map_propagator(Self::create_propagator(
+                &self.config,
+                self.debug_token.clone(),
+            ));
         }
 
         self.reload_metrics();
 
-        reload_fmt(create_fmt_layer(&self.config));
+        reload_fmt(create_fmt_layer(
+            &self.config,
+            self.logger_provider.as_ref(),
+        ));
+    }
+
+    /// Make the sampling decision of the current trace with the sampling rules, unless it was
+    /// already made
+    fn apply_sampling_rules(
+        rules: &SamplingRules,
+        context: &Context,
+        query_plan: Option<&QueryPlan>,
+    ) {
+        let Some(hot_tracer) = OPENTELEMETRY_TRACER_HANDLE.get() else {
+            return;
+        };
+        let trace_id = Span::current().context().span().span_context().trace_id();
+        if trace_id == opentelemetry::trace::TraceId::INVALID
+            || hot_tracer.sampling_decision(trace_id).is_some()
+        {
+            return;
+        }
+        let operation_name: Option<String> = context.get(OPERATION_NAME).ok().flatten();
+        let client_name: Option<String> = context.get(CLIENT_NAME).ok().flatten();
+        let subgraphs: Option<Vec<&str>> =
+            query_plan.map(|query_plan| query_plan.root.service_usage().collect());
+        // Root spans were enabled with the highest ratio, or the override if it is higher, and
+        // whatever the ratio with a validly signed debug token
+        let overridden = trace_sampling().ratio();
+        let enabled_ratio = if debug_token::is_trace_forced() {
+            1.0
+        } else {
+            overridden.map_or(rules.max_ratio(), |ratio| ratio.max(rules.max_ratio()))
+        };
+        let ratio = if debug_token::is_forced(context) {
+            1.0
+        } else {
+            overridden.unwrap_or_else(|| {
+                rules.ratio(
+                    operation_name.as_deref(),
+                    client_name.as_deref(),
+                    subgraphs.as_deref(),
+                )
+            })
+        };
+        context
+            .private_entries
+            .lock()
+            .insert(TraceSamplingRatio(ratio));
+        hot_tracer.decide_sampling(
+            trace_id,
+            sampling::sample(sampling::conditional_ratio(ratio, enabled_ratio)),
+        );
     }
 
-    fn create_propagator(config: &config::Conf) -> TextMapCompositePropagator {
+    fn create_propagator(
+        config: &config::Conf,
+        debug_token: Option<DebugTokenVerifier>,
+    ) -> TextMapCompositePropagator {
         let propagation = &config.exporters.tracing.propagation;
 
         let tracing = &config.exporters.tracing;
@@ -708,8 +1296,15 @@ impl Telemetry {
         if propagation.jaeger || tracing.jaeger.enabled() {
             propagators.push(Box::<opentelemetry_jaeger::Propagator>::default());
         }
-        if propagation.baggage {
-            propagators.push(Box::<opentelemetry::sdk::propagation::BaggagePropagator>::default());
+        match &propagation.baggage {
+            BaggagePropagation::Enabled(true) => {
+                propagators
+                    .push(Box::<opentelemetry::sdk::propagation::BaggagePropagator>::default());
+            }
+            BaggagePropagation::Enabled(false) => {}
+            BaggagePropagation::Filtered(filter) => {
+                propagators.push(Box::new(FilteredBaggagePropagator::new(filter)));
+            }
         }
         if propagation.trace_context || tracing.otlp.enabled {
             propagators
@@ -719,7 +1314,11 @@ impl Telemetry {
             propagators.push(Box::<opentelemetry_zipkin::Propagator>::default());
         }
         if propagation.datadog || tracing.datadog.enabled {
-            propagators.push(Box::<opentelemetry_datadog::DatadogPropagator>::default());
+            if tracing.datadog.enable_128bit_trace_id {
+                propagators.push(Box::<Datadog128bitPropagator>::default());
+            } else {
+                propagators.push(Box::<opentelemetry_datadog::DatadogPropagator>::default());
+            }
         }
         if propagation.aws_xray {
             propagators.push(Box::<opentelemetry_aws::XrayPropagator>::default());
@@ -729,17 +1328,32 @@ impl Telemetry {
                 from_request_header.to_string(),
             )));
         }
+        // Last, as it samples the trace context extracted by the other propagators
+        if let Some(debug_token) = debug_token {
+            propagators.push(Box::new(DebugTokenPropagator::new(debug_token)));
+        }
 
         TextMapCompositePropagator::new(propagators)
     }
 
     fn create_tracer_provider(
         config: &config::Conf,
-    ) -> Result<(SamplerOption, opentelemetry::sdk::trace::TracerProvider), BoxError> {
+    ) -> Result<
+        (
+            SamplerOption,
+            Option<Arc<SamplingRules>>,
+            opentelemetry::sdk::trace::TracerProvider,
+        ),
+        BoxError,
+    > {
         let tracing_config = &config.exporters.tracing;
         let spans_config = &config.instrumentation.spans;
         let mut common = tracing_config.common.clone();
         let mut sampler = common.sampler.clone();
+        // The subject of debug tokens is only checked once the request is authenticated, so the
+        // sampling decision of their traces is deferred like that of the sampling rules
+        let mut sampling_rules =
+            SamplingRules::new(&common, tracing_config.debug_token.enabled)?.map(Arc::new);
         // set it to AlwaysOn: it is now done in the SamplingFilter, so whatever is sent to an exporter
         // should be accepted
         common.sampler = SamplerOption::Always(Sampler::AlwaysOn);
@@ -760,15 +1374,19 @@ impl Telemetry {
             && !TracingConfigurator::enabled(&config.apollo)
         {
             sampler = SamplerOption::Always(Sampler::AlwaysOff);
+            sampling_rules = None;
         }
 
         let tracer_provider = builder.build();
-        Ok((sampler, tracer_provider))
+        Ok((sampler, sampling_rules, tracer_provider))
     }
 
     fn create_metrics_builder(config: &config::Conf) -> Result<MetricsBuilder, BoxError> {
         let metrics_config = &config.exporters.metrics;
         let metrics_common_config = &metrics_config.common;
+        metrics_common_config.validate()?;
+        config::validate_views(&metrics_config.prometheus.views)?;
+        config::validate_views(&metrics_config.otlp.views)?;
         let mut builder = MetricsBuilder::new(config);
         builder = setup_metrics_exporter(builder, &config.apollo, metrics_common_config)?;
         builder =
@@ -780,18 +1398,23 @@ impl Telemetry {
     fn filter_variables_values(
         variables: &Map<ByteString, Value>,
         forward_rules: &ForwardValues,
+        redacted_variables: &RedactedVariables,
     ) -> String {
         let nb_var = variables.len();
         #[allow(clippy::mutable_key_type)] // False positive lint
         let variables = variables
             .iter()
             .map(|(name, value)| {
-                if match &forward_rules {
-                    ForwardValues::None => false,
-                    ForwardValues::All => true,
-                    ForwardValues::Only(only) => only.contains(&name.as_str().to_string()),
-                    ForwardValues::Except(except) => !except.contains(&name.as_str().to_string()),
-                } {
+                if !redacted_variables.contains(name.as_str())
+                    && match &forward_rules {
+                        ForwardValues::None => false,
+                        ForwardValues::All => true,
+                        ForwardValues::Only(only) => only.contains(&name.as_str().to_string()),
+                        ForwardValues::Except(except) => {
+                            !except.contains(&name.as_str().to_string())
+                        }
+                    }
+                {
                     (
                         name,
                         serde_json::to_string(value).unwrap_or_else(|_| "<unknown>".to_string()),
@@ -837,6 +1460,9 @@ impl Telemetry {
                 .collect::<Vec<KeyValue>>()
         })
         .unwrap_or_default();
+        if is_synthetic_traffic(&context) {
+            metric_attrs.push(KeyValue::new(SYNTHETIC_TRAFFIC_ATTRIBUTE, true));
+        }
         let res = match result {
             Ok(response) => {
                 metric_attrs.push(KeyValue::new(
@@ -890,14 +1516,96 @@ impl Telemetry {
             request_duration.as_secs_f64(),
             metric_attrs
         );
+        if metrics::prometheus::exemplars_enabled() {
+            metrics::prometheus::record_exemplar(
+                "apollo_router_http_request_duration_seconds",
+                "",
+                &metric_attrs,
+                request_duration.as_secs_f64(),
+            );
+        }
         res
     }
 
-    fn populate_context(
+    /// Stores the errors and the data of the first response in the context, for the selectors
+    /// that need them. The response is left untouched when none do.
+    async fn record_first_response(
+        response: SupergraphResponse,
+        record_errors: bool,
+        record_data: bool,
+    ) -> SupergraphResponse {
+        if !record_errors && !record_data {
+            return response;
+        }
+        let SupergraphResponse { context, response } = response;
+        let (parts, mut stream) = response.into_parts();
+        let first = stream.next().await;
+        if let Some(first) = &first {
+            if record_errors && !first.errors.is_empty() {
+                context
+                    .private_entries
+                    .lock()
+                    .insert(FirstResponseErrors(Arc::new(first.errors.clone())));
+            }
+            if let (true, Some(data)) = (record_data, &first.data) {
+                context
+                    .private_entries
+                    .lock()
+                    .insert(FirstResponseData(Arc::new(data.clone())));
+            }
+        }
+        SupergraphResponse {
+            context,
+            response: http::Response::from_parts(
+                parts,
+                futures::stream::iter(first).chain(stream).boxed(),
+            ),
+        }
+    }
+
+    /// Sets the `response_data` attributes selected in the deferred responses on the supergraph
+    /// span, which is kept open until the last response
+    fn record_deferred_response_data(
         config: Arc<Conf>,
-        field_level_instrumentation_ratio: f64,
-        req: &SupergraphRequest,
-    ) {
+        span: Span,
+        response: SupergraphResponse,
+    ) -> SupergraphResponse {
+        response.map_stream(move |response| {
+            if !response.incremental.is_empty() {
+                span.set_dyn_attributes(
+                    config
+                        .instrumentation
+                        .spans
+                        .supergraph
+                        .attributes
+                        .custom
+                        .iter()
+                        .filter_map(|(name, selector)| {
+                            Some(KeyValue::new(
+                                name.clone(),
+                                selector.on_deferred_response(&response)?,
+                            ))
+                        }),
+                );
+            }
+            response
+        })
+    }
+
+    /// Sets the custom attributes of the query planning span, which is created by the supergraph service
+    pub(crate) fn set_query_planning_attributes(span: &Span, response: &QueryPlannerResponse) {
+        let attributes = response
+            .context
+            .private_entries
+            .lock()
+            .get::<QueryPlanningAttributes>()
+            .cloned();
+        if let Some(attributes) = attributes {
+            span.set_dyn_attributes(attributes.on_response(response));
+        }
+    }
+
+    fn populate_context(config: Arc<Conf>, req: &SupergraphRequest) {
         let apollo_config = &config.apollo;
         let context = &req.context;
         let http_request = &req.supergraph_request;
@@ -920,6 +1628,16 @@ impl Telemetry {
             let _ = context.insert(CLIENT_VERSION, version);
         }
 
+        // The query planning span is created by the supergraph service
+        context.private_entries.lock().insert(
+            config
+                .instrumentation
+                .spans
+                .query_planning
+                .attributes
+                .clone(),
+        );
+
         let (should_log_headers, should_log_body) = config.exporters.logging.should_log(req);
         if should_log_headers {
             ::tracing::info!(http.request.headers = ?req.supergraph_request.headers(), "Supergraph request headers");
@@ -927,7 +1645,7 @@ impl Telemetry {
             let _ = req.context.insert(LOGGING_DISPLAY_HEADERS, true);
         }
         if should_log_body {
-            ::tracing::info!(http.request.body = ?req.supergraph_request.body(), "Supergraph request body");
+            ::tracing::info!(http.request.body = ?RedactedVariables::redact_body(context, req.supergraph_request.body()), "Supergraph request body");
 
             let _ = req.context.insert(LOGGING_DISPLAY_BODY, true);
         }
@@ -952,9 +1670,24 @@ impl Telemetry {
             .private_entries
             .lock()
             .insert(MetricsAttributes(attributes));
-        if rand::thread_rng().gen_bool(field_level_instrumentation_ratio) {
-            context.private_entries.lock().insert(EnableSubgraphFtv1);
-        }
+        // A single draw per request, compared to the ratio of each subgraph, so that the
+        // subgraphs with the same ratio are all traced for the same requests
+        context
+            .private_entries
+            .lock()
+            .insert(SubgraphFtv1Sample(rand::thread_rng().gen()));
+        // Overridden by the sampling rules when they make the sampling decision
+        let trace_ratio = if debug_token::is_forced(context) {
+            1.0
+        } else {
+            trace_sampling()
+                .ratio()
+                .unwrap_or_else(|| config.exporters.tracing.common.sampler.ratio())
+        };
+        context
+            .private_entries
+            .lock()
+            .insert(TraceSamplingRatio(trace_ratio));
     }
 
     fn create_subgraph_metrics_conf(&self, name: &str) -> Arc<AttributesForwardConf> {
@@ -1164,19 +1897,28 @@ impl Telemetry {
                 );
             }
         }
+        let request_duration = now.elapsed().as_secs_f64();
         f64_histogram!(
             "apollo_router_http_request_duration_seconds",
             "Duration of HTTP requests.",
-            now.elapsed().as_secs_f64(),
+            request_duration,
             metric_attrs
         );
+        if metrics::prometheus::exemplars_enabled() {
+            metrics::prometheus::record_exemplar(
+                "apollo_router_http_request_duration_seconds",
+                "",
+                &metric_attrs,
+                request_duration,
+            );
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
     fn update_metrics_on_response_events(
         ctx: &Context,
         config: Arc<Conf>,
-        field_level_instrumentation_ratio: f64,
+        field_level_instrumentation_ratios: Arc<FieldLevelInstrumentationRatios>,
         sender: Sender,
         start: Instant,
         result: Result<supergraph::Response, BoxError>,
@@ -1191,7 +1933,7 @@ impl Telemetry {
                         .then_some(OperationSubType::SubscriptionRequest);
                     Self::update_apollo_metrics(
                         ctx,
-                        field_level_instrumentation_ratio,
+                        &field_level_instrumentation_ratios,
                         sender,
                         true,
                         start.elapsed(),
@@ -1230,7 +1972,7 @@ impl Telemetry {
                 if operation_kind == OperationKind::Subscription && !http_status_is_success {
                     Self::update_apollo_metrics(
                         ctx,
-                        field_level_instrumentation_ratio,
+                        &field_level_instrumentation_ratios,
                         sender.clone(),
                         true,
                         start.elapsed(),
@@ -1255,7 +1997,7 @@ impl Telemetry {
                                         if http_status_is_success {
                                             Self::update_apollo_metrics(
                                                 &ctx,
-                                                field_level_instrumentation_ratio,
+                                                &field_level_instrumentation_ratios,
                                                 sender.clone(),
                                                 has_errors,
                                                 start.elapsed(),
@@ -1267,7 +2009,7 @@ impl Telemetry {
                                         // Only for subscription events
                                         Self::update_apollo_metrics(
                                             &ctx,
-                                            field_level_instrumentation_ratio,
+                                            &field_level_instrumentation_ratios,
                                             sender.clone(),
                                             has_errors,
                                             response
@@ -1283,7 +2025,7 @@ impl Telemetry {
                                     if !response.has_next.unwrap_or(false) {
                                         Self::update_apollo_metrics(
                                             &ctx,
-                                            field_level_instrumentation_ratio,
+                                            &field_level_instrumentation_ratios,
                                             sender.clone(),
                                             has_errors,
                                             start.elapsed(),
@@ -1304,13 +2046,17 @@ impl Telemetry {
 
     fn update_apollo_metrics(
         context: &Context,
-        field_level_instrumentation_ratio: f64,
+        field_level_instrumentation_ratios: &FieldLevelInstrumentationRatios,
         sender: Sender,
         has_errors: bool,
         duration: Duration,
         operation_kind: OperationKind,
         operation_subtype: Option<OperationSubType>,
     ) {
+        // Synthetic traffic is not reported to Apollo
+        if is_synthetic_traffic(context) {
+            return;
+        }
         let metrics = if let Some(usage_reporting) = context
             .private_entries
             .lock()
@@ -1340,7 +2086,8 @@ impl Telemetry {
                 }
             } else {
                 let traces = Self::subgraph_ftv1_traces(context);
-                let per_type_stat = Self::per_type_stat(&traces, field_level_instrumentation_ratio);
+                let per_type_stat =
+                    Self::per_type_stat(&traces, field_level_instrumentation_ratios);
                 let root_error_stats = Self::per_path_error_stats(&traces);
                 SingleStatsReport {
                     request_id: uuid::Uuid::from_bytes(
@@ -1431,7 +2178,7 @@ impl Telemetry {
     // https://github.com/apollographql/apollo-server/blob/6ff88e87c52/packages/server/src/plugin/usageReporting/stats.ts#L283
     fn per_type_stat(
         traces: &[(ByteString, proto::reports::Trace)],
-        field_level_instrumentation_ratio: f64,
+        field_level_instrumentation_ratios: &FieldLevelInstrumentationRatios,
     ) -> HashMap<String, SingleTypeStat> {
         fn recur(
             per_type: &mut HashMap<String, SingleTypeStat>,
@@ -1482,15 +2229,15 @@ impl Telemetry {
             }
         }
 
-        // For example, `field_level_instrumentation_ratio == 0.03` means we send a
-        // `apollo-federation-include-trace: ftv1` header with 3% of subgraph requests.
-        // To compensate, assume that each trace we recieve is representative of 33.3… requests.
-        // Metrics that recieve this treatment are kept as floating point values in memory,
-        // and converted to integers after aggregating values for a number of requests.
-        let field_execution_weight = 1.0 / field_level_instrumentation_ratio;
-
         let mut per_type = HashMap::new();
-        for (_subgraph_name, trace) in traces {
+        for (subgraph_name, trace) in traces {
+            // For example, a field level instrumentation ratio of 0.03 means we send a
+            // `apollo-federation-include-trace: ftv1` header with 3% of subgraph requests.
+            // To compensate, assume that each trace we recieve is representative of 33.3… requests.
+            // Metrics that recieve this treatment are kept as floating point values in memory,
+            // and converted to integers after aggregating values for a number of requests.
+            let field_execution_weight =
+                1.0 / field_level_instrumentation_ratios.subgraph(subgraph_name.as_str());
             if let Some(node) = &trace.root {
                 recur(&mut per_type, field_execution_weight, node)
             }
@@ -1737,6 +2484,29 @@ impl CacheCounter {
     }
 }
 
+fn is_synthetic_traffic(context: &Context) -> bool {
+    context
+        .get(SYNTHETIC_TRAFFIC)
+        .map_or(false, |x| x.unwrap_or_default())
+}
+
+fn filter_context(context: &Context, send_context: &[ContextMetadata]) -> String {
+    let metadata: BTreeMap<&str, String> = send_context
+        .iter()
+        .filter_map(|metadata| Some((metadata.name.as_str(), metadata.value(context)?)))
+        .collect();
+
+    match serde_json::to_string(&metadata) {
+        Ok(result) => result,
+        Err(_err) => {
+            ::tracing::warn!(
+                "could not serialize context, trace will not have context information"
+            );
+            Default::default()
+        }
+    }
+}
+
 fn filter_headers(headers: &HeaderMap, forward_rules: &ForwardHeaders) -> String {
     if let ForwardHeaders::None = forward_rules {
         return String::from("{}");
@@ -1868,12 +2638,35 @@ fn handle_error_internal<T: Into<opentelemetry::global::Error>>(
 
 register_plugin!("apollo", "telemetry", Telemetry);
 
-fn request_ftv1(mut req: SubgraphRequest) -> SubgraphRequest {
-    if req
-        .context
+fn ftv1_enabled(context: &Context, field_level_instrumentation_ratio: f64) -> bool {
+    context
+        .private_entries
+        .lock()
+        .get::<SubgraphFtv1Sample>()
+        .map_or(false, |sample| sample.0 < field_level_instrumentation_ratio)
+}
+
+/// The number of requests represented by each ftv1 trace of a request, the inverse of the ratio
+/// of requests for which ftv1 traces are collected
+fn field_execution_weight(context: &Context, field_level_instrumentation_ratio: f64) -> f64 {
+    let trace_ratio = context
         .private_entries
         .lock()
-        .contains_key::<EnableSubgraphFtv1>()
+        .get::<TraceSamplingRatio>()
+        .map_or(1.0, |ratio| ratio.0);
+    let ratio = trace_ratio * field_level_instrumentation_ratio;
+    if ratio > 0.0 {
+        1.0 / ratio
+    } else {
+        0.0
+    }
+}
+
+fn request_ftv1(
+    mut req: SubgraphRequest,
+    field_level_instrumentation_ratio: f64,
+) -> SubgraphRequest {
+    if ftv1_enabled(&req.context, field_level_instrumentation_ratio)
         && Span::current().context().span().span_context().is_sampled()
     {
         req.subgraph_request
@@ -1883,19 +2676,23 @@ fn request_ftv1(mut req: SubgraphRequest) -> SubgraphRequest {
     req
 }
 
-fn store_ftv1(subgraph_name: &ByteString, resp: SubgraphResponse) -> SubgraphResponse {
+fn store_ftv1(
+    subgraph_name: &ByteString,
+    field_level_instrumentation_ratio: f64,
+    resp: SubgraphResponse,
+) -> SubgraphResponse {
     // Stash the FTV1 data
-    if resp
-        .context
-        .private_entries
-        .lock()
-        .contains_key::<EnableSubgraphFtv1>()
-    {
+    if ftv1_enabled(&resp.context, field_level_instrumentation_ratio) {
         if let Some(serde_json_bytes::Value::String(ftv1)) =
             resp.response.body().extensions.get("ftv1")
         {
             // Record the ftv1 trace for processing later
-            Span::current().record("apollo_private.ftv1", ftv1.as_str());
+            let span = Span::current();
+            span.record("apollo_private.ftv1", ftv1.as_str());
+            span.set_dyn_attribute(
+                APOLLO_PRIVATE_FTV1_FIELD_EXECUTION_WEIGHT,
+                field_execution_weight(&resp.context, field_level_instrumentation_ratio).into(),
+            );
             resp.context
                 .upsert_json_value(SUBGRAPH_FTV1, move |value: Value| {
                     let mut vec = match value {
@@ -1983,7 +2780,11 @@ struct MetricsAttributes(HashMap<String, AttributeValue>);
 #[derive(Clone)]
 struct SubgraphMetricsAttributes(HashMap<String, AttributeValue>);
 
-struct EnableSubgraphFtv1;
+/// The draw deciding which subgraphs are asked for ftv1 traces for a request
+struct SubgraphFtv1Sample(f64);
+
+/// The sampling ratio applied to the trace of a request
+struct TraceSamplingRatio(f64);
 //
 // Please ensure that any tests added to the tests module use the tokio multi-threaded test executor.
 //
@@ -2002,6 +2803,7 @@ mod tests {
     use http::StatusCode;
     use insta::assert_snapshot;
     use itertools::Itertools;
+    use router_bridge::planner::UsageReporting;
     use serde_json::Value;
     use serde_json_bytes::json;
     use serde_json_bytes::ByteString;
@@ -2019,6 +2821,7 @@ mod tests {
 
     use super::apollo::ForwardHeaders;
     use super::Telemetry;
+    use super::OPERATION_SIGNATURE_EXTENSION;
     use crate::error::FetchError;
     use crate::graphql::Error;
     use crate::graphql::Request;
@@ -2211,6 +3014,46 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_supergraph_operation_signature_extension() {
+        let plugin = create_plugin_with_config(
+            "telemetry:\n  apollo:\n    expose_operation_signature: true",
+        )
+        .await;
+
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SupergraphRequest| {
+                req.context.private_entries.lock().insert(UsageReporting {
+                    stats_report_key: "# MyQuery\nquery MyQuery{me{name}}".to_string(),
+                    referenced_fields_by_type: Default::default(),
+                });
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .data(json!({"me": {"name": "Ada"}}))
+                    .build()
+                    .unwrap())
+            });
+
+        let mut supergraph_service = plugin.supergraph_service(BoxService::new(mock_service));
+        let response = supergraph_service
+            .ready()
+            .await
+            .unwrap()
+            .call(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.extensions.get(OPERATION_SIGNATURE_EXTENSION),
+            Some(&"# MyQuery\nquery MyQuery{me{name}}".into())
+        );
+    }
+
     #[tokio::test]
     async fn test_subgraph_metrics_ok() {
         async {
@@ -2284,6 +3127,56 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn it_shares_the_cardinality_limit_of_instruments_between_subgraphs() {
+        async {
+            let plugin = create_plugin_with_config(
+                r#"
+telemetry:
+  instrumentation:
+    instruments:
+      cardinality_limit: 1
+      subgraph:
+        acme.subgraph.requests:
+          type: counter
+          value: unit
+          unit: request
+          description: "Subgraph requests"
+          attributes:
+            subgraph.name: true
+"#,
+            )
+            .await;
+
+            for name in ["products", "reviews"] {
+                let mut mock_subgraph_service = MockSubgraphService::new();
+                mock_subgraph_service
+                    .expect_call()
+                    .times(1)
+                    .returning(|req: SubgraphRequest| {
+                        Ok(SubgraphResponse::fake_builder()
+                            .context(req.context)
+                            .build())
+                    });
+                let mut subgraph_service =
+                    plugin.subgraph_service(name, BoxService::new(mock_subgraph_service));
+                subgraph_service
+                    .ready()
+                    .await
+                    .unwrap()
+                    .call(SubgraphRequest::fake_builder().subgraph_name(name).build())
+                    .await
+                    .unwrap();
+            }
+
+            // The services of both subgraphs count towards the same limit
+            assert_counter!("acme.subgraph.requests", 1, "subgraph.name" = "products");
+            assert_counter!("acme.subgraph.requests", 1, "subgraph.name" = "other");
+        }
+        .with_metrics()
+        .await;
+    }
+
     #[tokio::test]
     async fn test_subgraph_metrics_http_error() {
         async {
@@ -2463,6 +3356,117 @@ mod tests {
         .await;
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_test_prometheus_metrics_custom_view() {
+        async {
+            let plugin = create_plugin_with_config(include_str!(
+                "testdata/prometheus_custom_view.router.yaml"
+            ))
+            .await;
+            make_supergraph_request(plugin.as_ref()).await;
+            let prometheus_metrics = get_prometheus_metrics(plugin.as_ref()).await;
+
+            assert_snapshot!(prometheus_metrics);
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_test_prometheus_metrics_exporter_view() {
+        async {
+            let plugin = create_plugin_with_config(include_str!(
+                "testdata/prometheus_exporter_view.router.yaml"
+            ))
+            .await;
+            make_supergraph_request(plugin.as_ref()).await;
+            let prometheus_metrics = get_prometheus_metrics(plugin.as_ref()).await;
+
+            assert_snapshot!(prometheus_metrics);
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[test]
+    fn it_merges_the_views_of_an_instrument() {
+        use opentelemetry::sdk::metrics::Aggregation;
+        use opentelemetry::sdk::metrics::Instrument;
+        use opentelemetry::sdk::metrics::InstrumentKind;
+        use opentelemetry::sdk::metrics::View;
+
+        let config: super::config::Metrics = serde_json::from_value(serde_json::json!({
+            "common": {
+                "views": [{
+                    "name": "http.server.request.duration",
+                    "aggregation": { "histogram": { "buckets": [0.1, 1.0] } }
+                }]
+            },
+            "prometheus": {
+                "views": [{
+                    "name": "http.server.*",
+                    "allowed_attributes": ["http.response.status_code"]
+                }]
+            }
+        }))
+        .unwrap();
+        let view =
+            super::config::MetricView::to_view(config.common.views_of(&config.prometheus.views));
+        let mut histogram = Instrument::new().name("http.server.request.duration");
+        histogram.kind = Some(InstrumentKind::Histogram);
+        let stream = view.match_inst(&histogram).unwrap();
+        assert!(matches!(
+            stream.aggregation,
+            Some(Aggregation::ExplicitBucketHistogram { boundaries, .. }) if boundaries == [0.1, 1.0]
+        ));
+        assert_eq!(
+            stream
+                .allowed_attribute_keys
+                .unwrap()
+                .iter()
+                .map(|key| key.as_str())
+                .collect::<Vec<_>>(),
+            ["http.response.status_code"]
+        );
+
+        let mut counter = Instrument::new().name("http.server.active_requests");
+        counter.kind = Some(InstrumentKind::UpDownCounter);
+        let stream = view.match_inst(&counter).unwrap();
+        assert!(stream.aggregation.is_none());
+        assert!(stream.allowed_attribute_keys.is_some());
+
+        assert!(view
+            .match_inst(&Instrument::new().name("apollo_router_cache_size"))
+            .is_none());
+    }
+
+    #[test]
+    fn it_rejects_unsorted_view_buckets() {
+        let config: super::config::MetricsCommon = serde_json::from_value(serde_json::json!({
+            "views": [{
+                "name": "apollo_router_http_request_duration_seconds",
+                "aggregation": { "histogram": { "buckets": [1.0, 0.5] } }
+            }]
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn it_weights_the_field_executions_with_the_ratio_applied_to_the_trace() {
+        let context = crate::Context::new();
+        // Without sampling decision, the trace is considered sampled
+        assert_eq!(super::field_execution_weight(&context, 0.5), 2.0);
+
+        context
+            .private_entries
+            .lock()
+            .insert(super::TraceSamplingRatio(0.25));
+        assert_eq!(super::field_execution_weight(&context, 1.0), 4.0);
+        assert_eq!(super::field_execution_weight(&context, 0.5), 8.0);
+        assert_eq!(super::field_execution_weight(&context, 0.0), 0.0);
+    }
+
     #[test]
     fn it_test_send_headers_to_studio() {
         let fw_headers = ForwardHeaders::Only(vec![
@@ -2499,6 +3503,47 @@ mod tests {
         assert_eq!(filtered_headers.as_str(), "{}");
     }
 
+    #[test]
+    fn it_test_send_context_to_studio() {
+        let send_context: Vec<super::apollo::ContextMetadata> =
+            serde_json::from_value(serde_json::json!([
+                {
+                    "key": "apollo_authentication::JWT::claims",
+                    "pointer": "/tenant",
+                    "name": "tenant"
+                },
+                {
+                    "key": "apollo_authentication::JWT::claims",
+                    "pointer": "/sub",
+                    "name": "user",
+                    "redact": { "truncate": 3 }
+                },
+                {
+                    "key": "client_id",
+                    "name": "client"
+                },
+                {
+                    "key": "missing",
+                    "name": "missing"
+                }
+            ]))
+            .unwrap();
+        let context = crate::Context::new();
+        context
+            .insert(
+                "apollo_authentication::JWT::claims",
+                serde_json::json!({ "tenant": "acme", "sub": "user-42" }),
+            )
+            .unwrap();
+        context.insert("client_id", 42).unwrap();
+
+        assert_eq!(
+            super::filter_context(&context, &send_context),
+            r#"{"client":"42","tenant":"acme","user":"use"}"#
+        );
+        assert_eq!(super::filter_context(&context, &[]), "{}");
+    }
+
     #[tokio::test]
     async fn test_handle_error_throttling() {
         let error_map = DashMap::new();
@@ -2571,4 +3616,93 @@ mod tests {
         .await;
         test_layer.assert_log_entry_count("other error", 2);
     }
+
+    #[tokio::test]
+    async fn it_records_the_errors_of_the_first_response_for_analytics() {
+        let plugin = create_plugin_with_config(
+            r#"
+telemetry:
+  exporters:
+    analytics:
+      enabled: true
+      target:
+        http:
+          endpoint: http://127.0.0.1:1/analytics
+"#,
+        )
+        .await;
+
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .error(
+                        crate::graphql::Error::builder()
+                            .message("forbidden")
+                            .extension_code("FORBIDDEN")
+                            .build(),
+                    )
+                    .build()
+                    .unwrap())
+            });
+
+        // No selector needs the errors, the analytics records do
+        let request = SupergraphRequest::fake_builder().build().unwrap();
+        let context = request.context.clone();
+        let _response = plugin
+            .supergraph_service(BoxService::new(mock_service))
+            .oneshot(request)
+            .await
+            .unwrap();
+        let errors = context
+            .private_entries
+            .lock()
+            .get::<crate::plugins::telemetry::config_new::selectors::FirstResponseErrors>()
+            .cloned()
+            .expect("the errors should be recorded");
+        assert_eq!(errors.0.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn it_records_the_errors_of_the_first_response() {
+        let response = || {
+            SupergraphResponse::fake_builder()
+                .error(
+                    crate::graphql::Error::builder()
+                        .message("forbidden")
+                        .extension_code("FORBIDDEN")
+                        .build(),
+                )
+                .build()
+                .unwrap()
+        };
+
+        // Nothing is recorded unless a selector needs it
+        let response_without_selectors =
+            Telemetry::record_first_response(response(), false, false).await;
+        assert!(!response_without_selectors
+            .context
+            .private_entries
+            .lock()
+            .contains_key::<crate::plugins::telemetry::config_new::selectors::FirstResponseErrors>(
+        ));
+
+        let mut response = Telemetry::record_first_response(response(), true, false).await;
+        let errors = response
+            .context
+            .private_entries
+            .lock()
+            .get::<crate::plugins::telemetry::config_new::selectors::FirstResponseErrors>()
+            .cloned()
+            .unwrap();
+        assert_eq!(errors.0.len(), 1);
+        // The first response is still sent
+        assert_eq!(
+            response.next_response().await.unwrap().errors[0].message,
+            "forbidden"
+        );
+    }
 }
@@ -0,0 +1,268 @@
+//! Export of the router logs with the OpenTelemetry logs signal.
+//!
+//! Events are turned into log records carrying the trace and span ids of their span, so that a
+//! collector no longer has to tail stdout to correlate the logs with the traces.
+use std::fmt;
+
+use opentelemetry::logs::AnyValue;
+use opentelemetry::logs::LogRecord;
+use opentelemetry::logs::Logger as _;
+use opentelemetry::logs::LoggerProvider as _;
+use opentelemetry::logs::Severity;
+use opentelemetry::sdk::logs::BatchLogProcessor;
+use opentelemetry::sdk::logs::Config;
+use opentelemetry::sdk::logs::Logger;
+use opentelemetry::sdk::logs::LoggerProvider;
+use opentelemetry::trace::SpanContext;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::TraceState;
+use opentelemetry::Key;
+use opentelemetry_otlp::LogExporterBuilder;
+use tower::BoxError;
+use tracing_core::Event;
+use tracing_core::Field;
+use tracing_core::Level;
+use tracing_core::Subscriber;
+use tracing_opentelemetry::OtelData;
+use tracing_subscriber::field::Visit;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use super::config_new::logging::Logging;
+use super::formatters::filter_metric_events;
+use super::formatters::redaction::RedactingVisitor;
+use super::formatters::redaction::Redaction;
+use super::formatters::APOLLO_PRIVATE_PREFIX;
+use super::resource::ConfigResource;
+use super::runtime::ExportRuntime;
+
+/// The field holding the message of events, exported as the body of log records
+const MESSAGE_FIELD: &str = "message";
+
+/// The logger provider exporting the logs to an OTLP endpoint, if it is enabled
+pub(crate) fn create_logger_provider(config: &Logging) -> Result<Option<LoggerProvider>, BoxError> {
+    let otlp = &config.otlp;
+    if !otlp.enabled {
+        return Ok(None);
+    }
+    tracing::info!("Configuring Otlp logging: {}", otlp.batch_processor);
+    let exporter: LogExporterBuilder = otlp.exporter()?;
+    let processor = BatchLogProcessor::builder(exporter.build_log_exporter()?, ExportRuntime)
+        .with_scheduled_delay(otlp.batch_processor.scheduled_delay)
+        .with_max_queue_size(otlp.batch_processor.max_queue_size)
+        .with_max_export_batch_size(otlp.batch_processor.max_export_batch_size)
+        .with_max_timeout(otlp.batch_processor.max_export_timeout)
+        .build();
+    Ok(Some(
+        LoggerProvider::builder()
+            .with_log_processor(processor)
+            .with_config(Config::default().with_resource(config.common.to_resource()))
+            .build(),
+    ))
+}
+
+/// Emits the events as OpenTelemetry log records.
+///
+/// The layer only holds a logger, which stops emitting once its provider is dropped, so that the
+/// provider is shut down with the telemetry plugin rather than on a reload of the layers.
+pub(crate) struct OtlpLogLayer {
+    logger: Logger,
+    redaction: Redaction,
+}
+
+impl OtlpLogLayer {
+    pub(crate) fn new(provider: &LoggerProvider, redaction: Redaction) -> Self {
+        Self {
+            logger: provider.versioned_logger(
+                "apollo-router",
+                Some(env!("CARGO_PKG_VERSION").into()),
+                None,
+                None,
+            ),
+            redaction,
+        }
+    }
+}
+
+impl<S> Layer<S> for OtlpLogLayer
+where
+    S: Subscriber + for<'lookup> LookupSpan<'lookup>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !filter_metric_events(event) {
+            return;
+        }
+        let metadata = event.metadata();
+        let mut visitor = RedactingVisitor::new(LogRecordVisitor::default(), &self.redaction);
+        event.record(&mut visitor);
+        let LogRecordVisitor { body, attributes } = visitor.into_inner();
+
+        let mut builder = LogRecord::builder()
+            .with_timestamp(std::time::SystemTime::now())
+            .with_severity_number(severity(metadata.level()))
+            .with_severity_text(metadata.level().as_str())
+            .with_attributes(attributes)
+            .with_attribute("code.namespace", metadata.target());
+        if let Some(body) = body {
+            builder = builder.with_body(body.into());
+        }
+        let span_context = ctx.event_span(event).and_then(|span| {
+            let extensions = span.extensions();
+            let otel_data = extensions.get::<OtelData>()?;
+            let parent = otel_data.parent_cx.span();
+            let parent = parent.span_context();
+            Some(SpanContext::new(
+                otel_data.builder.trace_id.unwrap_or(parent.trace_id()),
+                otel_data.builder.span_id?,
+                parent.trace_flags(),
+                false,
+                TraceState::default(),
+            ))
+        });
+        if let Some(span_context) = &span_context {
+            builder = builder.with_span_context(span_context);
+        }
+        self.logger.emit(builder.build());
+    }
+}
+
+fn severity(level: &Level) -> Severity {
+    match *level {
+        Level::TRACE => Severity::Trace,
+        Level::DEBUG => Severity::Debug,
+        Level::INFO => Severity::Info,
+        Level::WARN => Severity::Warn,
+        Level::ERROR => Severity::Error,
+    }
+}
+
+/// Collects the message of an event as the body of its log record, and its other fields as
+/// attributes
+#[derive(Default)]
+struct LogRecordVisitor {
+    body: Option<String>,
+    attributes: Vec<(Key, AnyValue)>,
+}
+
+impl LogRecordVisitor {
+    fn record(&mut self, field: &Field, value: AnyValue) {
+        let name = field.name();
+        if name.starts_with(APOLLO_PRIVATE_PREFIX) {
+            return;
+        }
+        if name == MESSAGE_FIELD {
+            if let AnyValue::String(value) = value {
+                self.body = Some(value.to_string());
+            }
+        } else {
+            self.attributes.push((Key::new(name), value));
+        }
+    }
+}
+
+impl Visit for LogRecordVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{value:?}").into())
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_string().into())
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.record(field, value.into())
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record(field, value.into())
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match i64::try_from(value) {
+            Ok(value) => self.record(field, value.into()),
+            Err(_) => self.record(field, value.to_string().into()),
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.record(field, value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use opentelemetry::sdk::export::logs::LogData;
+    use opentelemetry::sdk::export::logs::LogExporter;
+    use opentelemetry::trace::TracerProvider as _;
+    use parking_lot::Mutex;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[derive(Clone, Debug, Default)]
+    struct InMemoryExporter(Arc<Mutex<Vec<LogData>>>);
+
+    #[async_trait::async_trait]
+    impl LogExporter for InMemoryExporter {
+        async fn export(&mut self, batch: Vec<LogData>) -> opentelemetry::logs::LogResult<()> {
+            self.0.lock().extend(batch);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn it_exports_events_as_log_records() {
+        let exporter = InMemoryExporter::default();
+        let provider = LoggerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let redaction: Redaction = serde_json::from_value(serde_json::json!({
+            "drop": ["user.email"]
+        }))
+        .unwrap();
+        let tracer_provider = opentelemetry::sdk::trace::TracerProvider::default();
+        let tracer = tracer_provider.tracer("test");
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .with(OtlpLogLayer::new(&provider, redaction));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(
+                user.email = "me@example.com",
+                http.response.status_code = 503u64,
+                "subgraph unavailable"
+            );
+            tracing::info!(monotonic_counter.requests = 1u64, "metric");
+            tracing::info_span!("request").in_scope(|| tracing::info!("in a span"));
+        });
+        // Shutting the provider down waits for the export
+        drop(provider);
+
+        let logs = exporter.0.lock();
+        assert_eq!(logs.len(), 2);
+        let record = &logs[0].record;
+        assert_eq!(record.severity_number, Some(Severity::Warn));
+        assert!(
+            matches!(&record.body, Some(AnyValue::String(body)) if body.as_str() == "subgraph unavailable")
+        );
+        let attributes = record.attributes.as_ref().unwrap();
+        assert!(attributes
+            .iter()
+            .all(|(key, _)| key.as_str() != "user.email"));
+        assert!(attributes
+            .iter()
+            .any(|(key, value)| key.as_str() == "http.response.status_code"
+                && matches!(value, AnyValue::Int(503))));
+        assert!(record.trace_context.is_none());
+
+        // Events in a span are correlated with its trace
+        let trace_context = logs[1].record.trace_context.as_ref().unwrap();
+        assert_ne!(
+            trace_context.trace_id,
+            opentelemetry::trace::TraceId::INVALID
+        );
+        assert_ne!(trace_context.span_id, opentelemetry::trace::SpanId::INVALID);
+    }
+}
@@ -1,6 +1,9 @@
 use std::io::IsTerminal;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::anyhow;
 use anyhow::Result;
@@ -8,6 +11,7 @@ use once_cell::sync::OnceCell;
 use opentelemetry::sdk::trace::Tracer;
 use opentelemetry::trace::TraceContextExt;
 use opentelemetry::trace::TracerProvider;
+use parking_lot::Mutex;
 use rand::thread_rng;
 use rand::Rng;
 use tower::BoxError;
@@ -35,6 +39,7 @@ use super::ROUTER_SPAN_NAME;
 use crate::axum_factory::utils::REQUEST_SPAN_NAME;
 use crate::metrics::layer::MetricsLayer;
 use crate::metrics::meter_provider;
+use crate::plugins::telemetry::debug_token::ForcedSampling;
 use crate::plugins::telemetry::formatters::filter_metric_events;
 use crate::plugins::telemetry::formatters::text::Text;
 use crate::plugins::telemetry::formatters::FilteringFormatter;
@@ -61,7 +66,13 @@ static FMT_LAYER_HANDLE: OnceCell<
     Handle<Box<dyn Layer<LayeredTracer> + Send + Sync>, LayeredTracer>,
 > = OnceCell::new();
 
+static LOG_LEVELS: OnceCell<Arc<LogLevels>> = OnceCell::new();
+
 pub(super) static SPAN_SAMPLING_RATE: AtomicU64 = AtomicU64::new(0);
+/// Whether the sampling decision of root spans is deferred to sampling rules
+static SPAN_SAMPLING_DEFERRED: AtomicBool = AtomicBool::new(false);
+
+static TRACE_SAMPLING: OnceCell<Arc<TraceSampling>> = OnceCell::new();
 
 pub(super) static METRICS_LAYER: OnceCell<MetricsLayer> = OnceCell::new();
 pub(crate) fn metrics_layer() -> &'static MetricsLayer {
@@ -105,6 +116,8 @@ pub(crate) fn init_telemetry(log_level: &str) -> Result<()> {
         .get_or_try_init(move || {
             // manually filter salsa logs because some of them run at the INFO level https://github.com/salsa-rs/salsa/issues/425
             let log_level = format!("{log_level},salsa=error");
+            let (env_filter, env_filter_handle) =
+                tracing_subscriber::reload::Layer::new(EnvFilter::try_new(&log_level)?);
 
             // Env filter is separate because of https://github.com/tokio-rs/tracing/issues/1629
             // the tracing registry is only created once
@@ -114,9 +127,14 @@ pub(crate) fn init_telemetry(log_level: &str) -> Result<()> {
                 .with(opentelemetry_layer)
                 .with(fmt_layer)
                 .with(metrics_layer.clone())
-                .with(EnvFilter::try_new(log_level)?)
+                .with(env_filter)
                 .try_init()?;
 
+            let _ = LOG_LEVELS.set(Arc::new(LogLevels::new(
+                log_level,
+                Box::new(move |filter| Ok(env_filter_handle.reload(filter)?)),
+            )));
+
             Ok(hot_tracer)
         })
         .map_err(|e: BoxError| anyhow!("failed to set OpenTelemetry tracer: {e}"))?;
@@ -133,6 +151,179 @@ pub(super) fn reload_fmt(layer: Box<dyn Layer<LayeredTracer> + Send + Sync>) {
     }
 }
 
+/// The log level of the router, which can be changed at runtime
+pub(crate) fn log_levels() -> Option<Arc<LogLevels>> {
+    LOG_LEVELS.get().cloned()
+}
+
+type ReloadFilter = Box<dyn Fn(EnvFilter) -> Result<(), BoxError> + Send + Sync>;
+
+/// `RUST_LOG` style directives applied on top of the log level the router was started with
+pub(crate) struct LogLevels {
+    base: String,
+    reload: ReloadFilter,
+    /// The directives added to the base ones, and the number of changes to expire only the latest
+    overrides: Mutex<(Option<String>, u64)>,
+}
+
+impl LogLevels {
+    pub(crate) fn new(base: String, reload: ReloadFilter) -> Self {
+        Self {
+            base,
+            reload,
+            overrides: Default::default(),
+        }
+    }
+
+    /// The directives in effect
+    pub(crate) fn current(&self) -> String {
+        match &self.overrides.lock().0 {
+            Some(overrides) => format!("{},{overrides}", self.base),
+            None => self.base.clone(),
+        }
+    }
+
+    /// Adds directives to the base ones, such as `apollo_router::query_planner=trace`, until they
+    /// are reset or for a duration
+    pub(crate) fn set(
+        self: &Arc<Self>,
+        directives: &str,
+        duration: Option<Duration>,
+    ) -> Result<(), BoxError> {
+        let filter = EnvFilter::try_new(format!("{},{directives}", self.base))?;
+        let generation = {
+            let mut overrides = self.overrides.lock();
+            (self.reload)(filter)?;
+            overrides.0 = Some(directives.to_string());
+            overrides.1 += 1;
+            overrides.1
+        };
+        tracing::info!("log level changed to '{}'", self.current());
+
+        if let Some(duration) = duration {
+            let log_levels = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(duration).await;
+                // Only reset if the directives did not change since
+                if let Err(e) = log_levels.reset_generation(Some(generation)) {
+                    tracing::error!("could not reset the log level: {e}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Goes back to the log level the router was started with
+    pub(crate) fn reset(&self) -> Result<(), BoxError> {
+        self.reset_generation(None)
+    }
+
+    /// Resets the directives, if they are still the ones of `generation` when there is one. The
+    /// check and the reset happen under the same lock, so that a concurrent change is kept
+    fn reset_generation(&self, generation: Option<u64>) -> Result<(), BoxError> {
+        {
+            let mut overrides = self.overrides.lock();
+            if generation.is_some_and(|generation| generation != overrides.1) {
+                return Ok(());
+            }
+            (self.reload)(EnvFilter::try_new(&self.base)?)?;
+            overrides.0 = None;
+            overrides.1 += 1;
+        }
+        tracing::info!("log level reset to '{}'", self.base);
+        Ok(())
+    }
+}
+
+/// The trace sampling override of the router, which can be changed at runtime
+pub(crate) fn trace_sampling() -> Arc<TraceSampling> {
+    TRACE_SAMPLING.get_or_init(Default::default).clone()
+}
+
+/// The bits of the ratio of [`TraceSampling`] without override, which are not those of a ratio
+const NO_RATIO: u64 = u64::MAX;
+
+/// A sampling ratio replacing the configured sampler and sampling rules for a while, such as to
+/// sample every request during an incident investigation
+pub(crate) struct TraceSampling {
+    /// The override in effect, and the number of changes to expire only the latest
+    overridden: Mutex<(Option<TraceSamplingOverride>, u64)>,
+    /// The bits of the ratio of the override in effect, read without locking for every root span
+    ratio: AtomicU64,
+}
+
+impl Default for TraceSampling {
+    fn default() -> Self {
+        Self {
+            overridden: Default::default(),
+            ratio: AtomicU64::new(NO_RATIO),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct TraceSamplingOverride {
+    pub(crate) ratio: f64,
+    pub(crate) until: std::time::SystemTime,
+}
+
+impl TraceSampling {
+    /// The override in effect, if any
+    pub(crate) fn current(&self) -> Option<TraceSamplingOverride> {
+        self.overridden.lock().0
+    }
+
+    /// The ratio replacing the configured sampling, if any
+    pub(crate) fn ratio(&self) -> Option<f64> {
+        let ratio = self.ratio.load(Ordering::Relaxed);
+        (ratio != NO_RATIO).then(|| f64::from_bits(ratio))
+    }
+
+    /// Replaces the configured sampling with a ratio for a duration, after which the configured
+    /// sampling applies again
+    pub(crate) fn set(self: &Arc<Self>, ratio: f64, duration: Duration) -> TraceSamplingOverride {
+        let current = TraceSamplingOverride {
+            ratio,
+            until: std::time::SystemTime::now() + duration,
+        };
+        let generation = {
+            let mut overridden = self.overridden.lock();
+            overridden.0 = Some(current);
+            overridden.1 += 1;
+            self.ratio.store(ratio.to_bits(), Ordering::Relaxed);
+            overridden.1
+        };
+        let trace_sampling = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            // Only reset if the ratio did not change since
+            if trace_sampling.reset_generation(Some(generation)) {
+                tracing::info!("trace sampling override expired");
+            }
+        });
+        current
+    }
+
+    /// Goes back to the configured sampling
+    pub(crate) fn reset(&self) {
+        self.reset_generation(None);
+    }
+
+    /// Resets the override, if it is still the one of `generation` when there is one, and returns
+    /// whether it did. The check and the reset happen under the same lock, so that a concurrent
+    /// change is kept
+    fn reset_generation(&self, generation: Option<u64>) -> bool {
+        let mut overridden = self.overridden.lock();
+        if generation.is_some_and(|generation| generation != overridden.1) {
+            return false;
+        }
+        overridden.0 = None;
+        overridden.1 += 1;
+        self.ratio.store(NO_RATIO, Ordering::Relaxed);
+        true
+    }
+}
+
 pub(crate) struct SamplingFilter;
 
 #[allow(dead_code)]
@@ -141,28 +332,25 @@ impl SamplingFilter {
         Self {}
     }
 
-    pub(super) fn configure(sampler: &SamplerOption) {
-        let ratio = match sampler {
-            SamplerOption::TraceIdRatioBased(ratio) => {
-                // can't use std::cmp::min because f64 is not Ord
-                if *ratio > 1.0 {
-                    1.0
-                } else {
-                    *ratio
-                }
-            }
-            SamplerOption::Always(s) => match s {
-                super::config::Sampler::AlwaysOn => 1f64,
-                super::config::Sampler::AlwaysOff => 0f64,
-            },
-        };
-
+    /// With a `deferred` ratio, root spans are enabled with it: the sampling decision is made
+    /// later, by sampling rules
+    pub(super) fn configure(sampler: &SamplerOption, deferred: Option<f64>) {
+        let ratio = deferred.unwrap_or_else(|| sampler.ratio());
         SPAN_SAMPLING_RATE.store(f64::to_bits(ratio), Ordering::Relaxed);
+        SPAN_SAMPLING_DEFERRED.store(deferred.is_some(), Ordering::Relaxed);
     }
 
     fn sample(&self) -> bool {
+        let configured = f64::from_bits(SPAN_SAMPLING_RATE.load(Ordering::Relaxed));
+        let ratio = match trace_sampling().ratio() {
+            // With sampling rules, the override applies when the rules would, among enough
+            // enabled root spans
+            Some(ratio) if SPAN_SAMPLING_DEFERRED.load(Ordering::Relaxed) => ratio.max(configured),
+            Some(ratio) => ratio,
+            None => configured,
+        };
         let s: f64 = thread_rng().gen_range(0.0..=1.0);
-        s <= f64::from_bits(SPAN_SAMPLING_RATE.load(Ordering::Relaxed))
+        s <= ratio
     }
 }
 
@@ -184,6 +372,15 @@ where
         // then that trace is sampled
         let current_otel_context = opentelemetry::Context::current();
         if current_otel_context.span().span_context().is_sampled() {
+            // the sampling rules must not override the decision of the client
+            if cx.current_span().id().is_none() {
+                if let Some(hot_tracer) = OPENTELEMETRY_TRACER_HANDLE.get() {
+                    hot_tracer.decide_sampling(
+                        current_otel_context.span().span_context().trace_id(),
+                        true,
+                    );
+                }
+            }
             return true;
         }
 
@@ -203,8 +400,9 @@ where
             return false;
         }
 
-        // - there's no parent span (it's the root), so we make the sampling decision
-        self.sample()
+        // - there's no parent span (it's the root), so we make the sampling decision, unless the
+        // request has a valid debug token
+        current_otel_context.get::<ForcedSampling>().is_some() || self.sample()
     }
 
     fn on_new_span(
@@ -0,0 +1,74 @@
+//! The runtime used by batch span processors and periodic metric readers to export telemetry.
+//!
+//! By default telemetry is exported from the router's main Tokio runtime. A dedicated runtime
+//! can be started instead, so that exporting telemetry does not compete with request handling.
+use std::fmt::Debug;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use once_cell::sync::OnceCell;
+use opentelemetry::runtime::Runtime;
+use opentelemetry::runtime::RuntimeChannel;
+use tokio::runtime::Handle;
+use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::wrappers::ReceiverStream;
+
+static EXPORT_RUNTIME: OnceCell<tokio::runtime::Runtime> = OnceCell::new();
+
+/// Start the dedicated telemetry export runtime. Must be called before telemetry is configured.
+pub(crate) fn init_export_runtime(worker_threads: usize) -> std::io::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .thread_name("telemetry-export")
+        .enable_all()
+        .build()?;
+    if EXPORT_RUNTIME.set(runtime).is_err() {
+        tracing::warn!("the telemetry export runtime was already started");
+    }
+    Ok(())
+}
+
+/// Spawns telemetry export tasks on the dedicated export runtime if it was started,
+/// or on the current runtime otherwise, like [`opentelemetry::runtime::Tokio`].
+#[derive(Clone, Debug)]
+pub(crate) struct ExportRuntime;
+
+impl ExportRuntime {
+    fn handle(&self) -> Handle {
+        EXPORT_RUNTIME
+            .get()
+            .map(|runtime| runtime.handle().clone())
+            .unwrap_or_else(Handle::current)
+    }
+}
+
+impl Runtime for ExportRuntime {
+    type Interval = IntervalStream;
+    type Delay = std::pin::Pin<Box<tokio::time::Sleep>>;
+
+    fn interval(&self, duration: Duration) -> Self::Interval {
+        // Timers are registered with the runtime that is entered when they are created
+        let _guard = self.handle().enter();
+        IntervalStream::new(tokio::time::interval(duration))
+    }
+
+    fn spawn(&self, future: BoxFuture<'static, ()>) {
+        #[allow(clippy::let_underscore_future)]
+        let _ = self.handle().spawn(future);
+    }
+
+    fn delay(&self, duration: Duration) -> Self::Delay {
+        let _guard = self.handle().enter();
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+impl<T: Debug + Send> RuntimeChannel<T> for ExportRuntime {
+    type Receiver = ReceiverStream<T>;
+    type Sender = tokio::sync::mpsc::Sender<T>;
+
+    fn batch_message_channel(&self, capacity: usize) -> (Self::Sender, Self::Receiver) {
+        let (sender, receiver) = tokio::sync::mpsc::channel(capacity);
+        (sender, ReceiverStream::new(receiver))
+    }
+}
@@ -5,6 +5,7 @@ use tracing::info_span;
 
 use crate::axum_factory::utils::REQUEST_SPAN_NAME;
 use crate::context::OPERATION_NAME;
+use crate::plugins::telemetry::variable_redaction::RedactedVariables;
 use crate::plugins::telemetry::Telemetry;
 use crate::plugins::telemetry::ROUTER_SPAN_NAME;
 use crate::plugins::telemetry::SUBGRAPH_SPAN_NAME;
@@ -118,6 +119,7 @@ impl SpanMode {
         config: &crate::plugins::telemetry::apollo::Config,
         request: &SupergraphRequest,
         field_level_instrumentation_ratio: f64,
+        redacted_variables: &RedactedVariables,
     ) -> ::tracing::span::Span {
         match self {
             SpanMode::Deprecated => {
@@ -138,6 +140,7 @@ impl SpanMode {
                     apollo_private.graphql.variables = Telemetry::filter_variables_values(
                         &request.supergraph_request.body().variables,
                         &send_variable_values,
+                        redacted_variables,
                     ),
                 );
 
@@ -161,6 +164,7 @@ impl SpanMode {
                     apollo_private.graphql.variables = Telemetry::filter_variables_values(
                         &request.supergraph_request.body().variables,
                         &send_variable_values,
+                        redacted_variables,
                     )
                 )
             }
@@ -0,0 +1,167 @@
+//! Opt-in logging of the bodies of subgraph requests and responses, sampled, truncated and
+//! redacted, to debug subgraphs without capturing their traffic.
+use std::sync::Arc;
+
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::config_new::json_path::JsonPath;
+use super::tracing::sampling::sample;
+use crate::graphql;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Logging of the bodies of subgraph requests and responses
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct SubgraphBodies {
+    /// Set to true to log the bodies of sampled subgraph requests and responses.
+    pub(crate) enabled: bool,
+    /// The ratio of subgraph requests whose bodies are logged (default: 0.01).
+    sampler: f64,
+    /// The maximum number of bytes logged per body, beyond which bodies are truncated (default: 4096).
+    max_bytes: usize,
+    /// The subgraphs whose bodies are logged, all of them if empty.
+    subgraphs: Vec<String>,
+    /// JSONPath expressions of the values replaced by `[REDACTED]` in the bodies, such as
+    /// `$.variables.password` or `$..email`.
+    #[schemars(with = "Vec<String>")]
+    redact: Vec<JsonPath>,
+}
+
+impl Default for SubgraphBodies {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sampler: 0.01,
+            max_bytes: 4096,
+            subgraphs: Vec::new(),
+            redact: Vec::new(),
+        }
+    }
+}
+
+impl SubgraphBodies {
+    /// The logger of the bodies of a subgraph, if they are logged
+    pub(crate) fn logger(&self, subgraph: &str) -> Option<SubgraphBodyLogger> {
+        (self.enabled
+            && (self.subgraphs.is_empty() || self.subgraphs.iter().any(|name| name == subgraph)))
+        .then(|| SubgraphBodyLogger(Arc::new(self.clone())))
+    }
+}
+
+/// Logs the bodies of a subgraph request and of its response. Set by the telemetry plugin in the
+/// extensions of the sampled subgraph requests.
+#[derive(Clone)]
+pub(crate) struct SubgraphBodyLogger(Arc<SubgraphBodies>);
+
+impl SubgraphBodyLogger {
+    /// Whether the bodies of a request are logged
+    pub(crate) fn sample(&self) -> bool {
+        sample(self.0.sampler)
+    }
+
+    pub(crate) fn log_request(&self, service_name: &str, body: &graphql::Request) {
+        let body = match serde_json::to_value(body) {
+            Ok(body) => self.redact(body),
+            Err(_) => return,
+        };
+        ::tracing::info!(
+            http.request.body = %body,
+            apollo.subgraph.name = %service_name,
+            "Sampled request body to subgraph {service_name:?}"
+        );
+    }
+
+    pub(crate) fn log_response(&self, service_name: &str, status: StatusCode, body: &[u8]) {
+        let body = match serde_json::from_slice::<Value>(body) {
+            Ok(body) => self.redact(body),
+            // Malformed bodies can't be redacted
+            Err(_) if self.0.redact.is_empty() => {
+                self.truncate(String::from_utf8_lossy(body).into_owned())
+            }
+            Err(_) => format!(
+                "<{} bytes that are not JSON, not logged as they can't be redacted>",
+                body.len()
+            ),
+        };
+        ::tracing::info!(
+            http.response.status_code = status.as_u16(),
+            http.response.body = %body,
+            apollo.subgraph.name = %service_name,
+            "Sampled response body from subgraph {service_name:?}"
+        );
+    }
+
+    fn redact(&self, mut body: Value) -> String {
+        let redacted = Value::from(REDACTED);
+        for path in &self.0.redact {
+            path.replace(&mut body, &redacted);
+        }
+        self.truncate(body.to_string())
+    }
+
+    fn truncate(&self, mut body: String) -> String {
+        let length = body.len();
+        if length <= self.0.max_bytes {
+            return body;
+        }
+        let mut end = self.0.max_bytes;
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        body.truncate(end);
+        body.push_str(&format!("... (truncated, {length} bytes)"));
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn logger(config: Value) -> SubgraphBodyLogger {
+        serde_json::from_value::<SubgraphBodies>(config)
+            .unwrap()
+            .logger("accounts")
+            .unwrap()
+    }
+
+    #[test]
+    fn it_logs_the_bodies_of_selected_subgraphs() {
+        let config: SubgraphBodies =
+            serde_json::from_value(json!({ "enabled": true, "subgraphs": ["accounts"] })).unwrap();
+        assert!(config.logger("accounts").is_some());
+        assert!(config.logger("products").is_none());
+        let disabled: SubgraphBodies =
+            serde_json::from_value(json!({ "subgraphs": ["accounts"] })).unwrap();
+        assert!(disabled.logger("accounts").is_none());
+    }
+
+    #[test]
+    fn it_redacts_and_truncates_bodies() {
+        let logger = logger(json!({
+            "enabled": true,
+            "max_bytes": 40,
+            "redact": ["$..email", "$.variables.password"]
+        }));
+        assert_eq!(
+            logger.redact(json!({ "variables": { "password": "hunter2" } })),
+            r#"{"variables":{"password":"[REDACTED]"}}"#
+        );
+        assert_eq!(
+            logger.redact(
+                json!({ "data": { "me": { "email": "a@example.com", "name": "Ada Lovelace" } } })
+            ),
+            r#"{"data":{"me":{"email":"[REDACTED]","nam... (truncated, 60 bytes)"#
+        );
+        assert_eq!(
+            logger.truncate("é".repeat(30)),
+            format!("{}... (truncated, 60 bytes)", "é".repeat(20))
+        );
+    }
+}
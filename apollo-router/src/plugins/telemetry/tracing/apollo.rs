@@ -8,6 +8,7 @@ use crate::plugins::telemetry::apollo::Config;
 use crate::plugins::telemetry::apollo_exporter::proto::reports::Trace;
 use crate::plugins::telemetry::config;
 use crate::plugins::telemetry::config_new::spans::Spans;
+use crate::plugins::telemetry::runtime::ExportRuntime;
 use crate::plugins::telemetry::span_factory::SpanMode;
 use crate::plugins::telemetry::tracing::apollo_telemetry;
 use crate::plugins::telemetry::tracing::TracingConfigurator;
@@ -38,13 +39,12 @@ impl TracingConfigurator for Config {
             )
             .schema_id(&self.schema_id)
             .buffer_size(self.buffer_size)
-            .field_execution_sampler(&self.field_level_instrumentation_sampler)
             .batch_config(&self.batch_processor)
             .errors_configuration(&self.errors)
             .use_legacy_request_span(matches!(spans_config.mode, SpanMode::Deprecated))
             .build()?;
         Ok(builder.with_span_processor(
-            BatchSpanProcessor::builder(exporter, opentelemetry::runtime::Tokio)
+            BatchSpanProcessor::builder(exporter, ExportRuntime)
                 .with_batch_config(self.batch_processor.clone().into())
                 .build(),
         ))
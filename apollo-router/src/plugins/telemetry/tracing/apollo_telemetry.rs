@@ -53,8 +53,6 @@ use crate::plugins::telemetry::apollo_exporter::proto::reports::trace::Details;
 use crate::plugins::telemetry::apollo_exporter::proto::reports::trace::Http;
 use crate::plugins::telemetry::apollo_exporter::proto::reports::trace::QueryPlanNode;
 use crate::plugins::telemetry::apollo_exporter::ApolloExporter;
-use crate::plugins::telemetry::config::Sampler;
-use crate::plugins::telemetry::config::SamplerOption;
 use crate::plugins::telemetry::tracing::apollo::TracesReport;
 use crate::plugins::telemetry::tracing::BatchProcessorConfig;
 use crate::plugins::telemetry::BoxError;
@@ -62,6 +60,7 @@ use crate::plugins::telemetry::EXECUTION_SPAN_NAME;
 use crate::plugins::telemetry::ROUTER_SPAN_NAME;
 use crate::plugins::telemetry::SUBGRAPH_SPAN_NAME;
 use crate::plugins::telemetry::SUPERGRAPH_SPAN_NAME;
+use crate::plugins::telemetry::SYNTHETIC_TRAFFIC_ATTRIBUTE;
 use crate::query_planner::subscription::SUBSCRIPTION_EVENT_SPAN_NAME;
 use crate::query_planner::OperationKind;
 use crate::query_planner::CONDITION_ELSE_SPAN_NAME;
@@ -86,9 +85,17 @@ const APOLLO_PRIVATE_HTTP_REQUEST_HEADERS: Key =
     Key::from_static_str("apollo_private.http.request_headers");
 const APOLLO_PRIVATE_HTTP_RESPONSE_HEADERS: Key =
     Key::from_static_str("apollo_private.http.response_headers");
+pub(crate) const APOLLO_PRIVATE_CONTEXT_METADATA: Key =
+    Key::from_static_str("apollo_private.context_metadata");
+/// Prefix of the context metadata sent with the request headers of traces
+const CONTEXT_METADATA_PREFIX: &str = "context:";
 pub(crate) const APOLLO_PRIVATE_OPERATION_SIGNATURE: Key =
     Key::from_static_str("apollo_private.operation_signature");
 const APOLLO_PRIVATE_FTV1: Key = Key::from_static_str("apollo_private.ftv1");
+pub(crate) const APOLLO_PRIVATE_FIELD_EXECUTION_WEIGHT: Key =
+    Key::from_static_str("apollo_private.field_execution_weight");
+pub(crate) const APOLLO_PRIVATE_FTV1_FIELD_EXECUTION_WEIGHT: Key =
+    Key::from_static_str("apollo_private.ftv1.field_execution_weight");
 const PATH: Key = Key::from_static_str("graphql.path");
 const SUBGRAPH_NAME: Key = Key::from_static_str("apollo.subgraph.name");
 pub(crate) const CLIENT_NAME_KEY: Key = Key::from_static_str("client.name");
@@ -98,6 +105,7 @@ const LABEL: Key = Key::from_static_str("graphql.label");
 const CONDITION: Key = Key::from_static_str("graphql.condition");
 const OPERATION_NAME: Key = Key::from_static_str("graphql.operation.name");
 const OPERATION_TYPE: Key = Key::from_static_str("graphql.operation.type");
+const SYNTHETIC_TRAFFIC: Key = Key::from_static_str(SYNTHETIC_TRAFFIC_ATTRIBUTE);
 const INCLUDE_SPANS: [&str; 15] = [
     PARALLEL_SPAN_NAME,
     SEQUENCE_SPAN_NAME,
@@ -165,7 +173,6 @@ pub(crate) struct Exporter {
     spans_by_parent_id: LruCache<SpanId, LruCache<usize, LightSpanData>>,
     #[derivative(Debug = "ignore")]
     report_exporter: Arc<ApolloExporter>,
-    field_execution_weight: f64,
     errors_configuration: ErrorsConfiguration,
     use_legacy_request_span: bool,
     include_span_names: HashSet<&'static str>,
@@ -180,11 +187,13 @@ enum TreeData {
         client_name: Option<String>,
         client_version: Option<String>,
         duration_ns: u64,
+        synthetic: bool,
     },
     Supergraph {
         operation_signature: String,
         operation_name: String,
         variables_json: HashMap<String, String>,
+        field_execution_weight: f64,
     },
     QueryPlanNode(QueryPlanNode),
     DeferPrimary(DeferNodePrimary),
@@ -204,7 +213,6 @@ impl Exporter {
         apollo_graph_ref: &'a str,
         schema_id: &'a str,
         buffer_size: NonZeroUsize,
-        field_execution_sampler: &'a SamplerOption,
         errors_configuration: &'a ErrorsConfiguration,
         batch_config: &'a BatchProcessorConfig,
         use_legacy_request_span: Option<bool>,
@@ -220,11 +228,6 @@ impl Exporter {
                 schema_id,
             )?),
 
-            field_execution_weight: match field_execution_sampler {
-                SamplerOption::Always(Sampler::AlwaysOn) => 1.0,
-                SamplerOption::Always(Sampler::AlwaysOff) => 0.0,
-                SamplerOption::TraceIdRatioBased(ratio) => 1.0 / ratio,
-            },
             errors_configuration: errors_configuration.clone(),
             use_legacy_request_span: use_legacy_request_span.unwrap_or_default(),
             include_span_names: INCLUDE_SPANS.into(),
@@ -258,7 +261,12 @@ impl Exporter {
                     client_name,
                     client_version,
                     duration_ns,
+                    synthetic,
                 } => {
+                    if synthetic {
+                        // Synthetic traffic is not reported to Apollo
+                        return Ok(vec![]);
+                    }
                     for trace in results.iter_mut() {
                         if http.method != Method::Unknown as i32 {
                             let root_http = trace
@@ -277,8 +285,9 @@ impl Exporter {
                     operation_signature,
                     operation_name,
                     variables_json,
+                    field_execution_weight,
                 } => {
-                    root_trace.field_execution_weight = self.field_execution_weight;
+                    root_trace.field_execution_weight = field_execution_weight;
                     root_trace.signature = operation_signature;
                     root_trace.details = Some(Details {
                         variables_json,
@@ -426,10 +435,24 @@ impl Exporter {
                     .errors_configuration
                     .subgraph
                     .get_error_config(&subgraph_name);
+                let field_execution_weight = span
+                    .attributes
+                    .get(&APOLLO_PRIVATE_FTV1_FIELD_EXECUTION_WEIGHT)
+                    .and_then(extract_f64);
                 vec![TreeData::Trace(
                     span.attributes
                         .get(&APOLLO_PRIVATE_FTV1)
-                        .and_then(|t| extract_ftv1_trace(t, error_configuration)),
+                        .and_then(|t| extract_ftv1_trace(t, error_configuration))
+                        .map(|trace| {
+                            trace.map(|mut trace| {
+                                // The subgraphs can have their own field level instrumentation
+                                // ratio, their traces are weighted accordingly
+                                if let Some(weight) = field_execution_weight {
+                                    trace.field_execution_weight = weight;
+                                }
+                                trace
+                            })
+                        }),
                 )]
             }
             SUPERGRAPH_SPAN_NAME => {
@@ -450,6 +473,12 @@ impl Exporter {
                         .get(&APOLLO_PRIVATE_GRAPHQL_VARIABLES)
                         .and_then(extract_json)
                         .unwrap_or_default(),
+                    // Computed from the sampling ratio applied to this trace
+                    field_execution_weight: span
+                        .attributes
+                        .get(&APOLLO_PRIVATE_FIELD_EXECUTION_WEIGHT)
+                        .and_then(extract_f64)
+                        .unwrap_or(1.0),
                 });
                 child_nodes
             }
@@ -470,6 +499,7 @@ impl Exporter {
                         .and_then(extract_i64)
                         .map(|e| e as u64)
                         .unwrap_or_default(),
+                    synthetic: span.attributes.get(&SYNTHETIC_TRAFFIC).is_some(),
                 });
                 if self.use_legacy_request_span {
                     child_nodes
@@ -498,6 +528,7 @@ impl Exporter {
                             .and_then(extract_i64)
                             .map(|e| e as u64)
                             .unwrap_or_default(),
+                        synthetic: span.attributes.get(&SYNTHETIC_TRAFFIC).is_some(),
                     });
                 }
 
@@ -597,6 +628,7 @@ impl Exporter {
                         .and_then(extract_i64)
                         .map(|e| e as u64)
                         .unwrap_or_default(),
+                    synthetic: span.attributes.get(&SYNTHETIC_TRAFFIC).is_some(),
                 });
 
                 // To put the signature and operation name
@@ -612,6 +644,11 @@ impl Exporter {
                         .and_then(extract_string)
                         .unwrap_or_default(),
                     variables_json: HashMap::new(),
+                    field_execution_weight: span
+                        .attributes
+                        .get(&APOLLO_PRIVATE_FIELD_EXECUTION_WEIGHT)
+                        .and_then(extract_f64)
+                        .unwrap_or(1.0),
                 });
 
                 child_nodes.push(TreeData::Execution(
@@ -674,6 +711,14 @@ fn extract_path(v: &Value) -> Vec<ResponsePathElement> {
         .unwrap_or_default()
 }
 
+fn extract_f64(v: &Value) -> Option<f64> {
+    if let Value::F64(v) = v {
+        Some(*v)
+    } else {
+        None
+    }
+}
+
 fn extract_i64(v: &Value) -> Option<i64> {
     if let Value::I64(v) = v {
         Some(*v)
@@ -739,6 +784,21 @@ fn extract_http_data(span: &LightSpanData) -> Http {
         "PATCH" => proto::reports::trace::http::Method::Patch,
         _ => proto::reports::trace::http::Method::Unknown,
     };
+    // Context metadata is shown with the request headers, which Studio displays for each trace.
+    // Its names are prefixed with `context:`, which is not valid in a header name, so they can't
+    // collide with the headers of the request
+    let context_metadata = span
+        .attributes
+        .get(&APOLLO_PRIVATE_CONTEXT_METADATA)
+        .and_then(extract_json::<HashMap<String, String>>)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, value)| {
+            (
+                format!("{CONTEXT_METADATA_PREFIX}{name}"),
+                Values { value: vec![value] },
+            )
+        });
     let request_headers = span
         .attributes
         .get(&APOLLO_PRIVATE_HTTP_REQUEST_HEADERS)
@@ -746,6 +806,7 @@ fn extract_http_data(span: &LightSpanData) -> Http {
         .unwrap_or_default()
         .into_iter()
         .map(|(header_name, value)| (header_name.to_lowercase(), Values { value }))
+        .chain(context_metadata)
         .collect();
     let response_headers = span
         .attributes
@@ -2,13 +2,26 @@
 
 use std::collections::HashMap;
 
+use futures::future::BoxFuture;
 use http::Uri;
 use lazy_static::lazy_static;
+use opentelemetry::propagation::text_map_propagator::FieldIter;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::propagation::Injector;
+use opentelemetry::propagation::TextMapPropagator;
 use opentelemetry::sdk;
+use opentelemetry::sdk::export::trace::ExportResult;
+use opentelemetry::sdk::export::trace::SpanData;
+use opentelemetry::sdk::export::trace::SpanExporter;
 use opentelemetry::sdk::trace::BatchSpanProcessor;
 use opentelemetry::sdk::trace::Builder;
+use opentelemetry::trace::SpanContext;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::TraceId;
+use opentelemetry::KeyValue;
 use opentelemetry::Value;
 use opentelemetry_api::Key;
+use opentelemetry_datadog::DatadogPropagator;
 use opentelemetry_semantic_conventions::resource::SERVICE_NAME;
 use opentelemetry_semantic_conventions::resource::SERVICE_VERSION;
 use schemars::JsonSchema;
@@ -19,6 +32,7 @@ use crate::plugins::telemetry::config::GenericWith;
 use crate::plugins::telemetry::config::TracingCommon;
 use crate::plugins::telemetry::config_new::spans::Spans;
 use crate::plugins::telemetry::endpoint::UriEndpoint;
+use crate::plugins::telemetry::runtime::ExportRuntime;
 use crate::plugins::telemetry::tracing::BatchProcessorConfig;
 use crate::plugins::telemetry::tracing::SpanProcessorExt;
 use crate::plugins::telemetry::tracing::TracingConfigurator;
@@ -34,8 +48,20 @@ lazy_static! {
         map
     };
     static ref DEFAULT_ENDPOINT: Uri = Uri::from_static("http://localhost:8126/v0.4/traces");
+    static ref DATADOG_HEADER_FIELDS: [String; 4] = [
+        "x-datadog-trace-id".to_string(),
+        "x-datadog-parent-id".to_string(),
+        "x-datadog-sampling-priority".to_string(),
+        DATADOG_TAGS_HEADER.to_string(),
+    ];
 }
 
+const DATADOG_TAGS_HEADER: &str = "x-datadog-tags";
+/// The tag holding the upper 64 bits of 128-bit trace ids, in hexadecimal
+const TRACE_ID_HIGH_TAG: &str = "_dd.p.tid";
+/// Prefix of the tags propagated to downstream services, such as the `_dd.p.dm` sampling mechanism
+const PROPAGATION_TAG_PREFIX: &str = "_dd.p.";
+
 #[derive(Debug, Clone, Deserialize, JsonSchema, Default)]
 #[serde(deny_unknown_fields)]
 pub(crate) struct Config {
@@ -53,6 +79,10 @@ pub(crate) struct Config {
     /// Enable datadog span mapping for span name and resource name.
     #[serde(default)]
     pub(crate) enable_span_mapping: bool,
+
+    /// Send and propagate 128-bit trace ids, with their upper 64 bits in the `_dd.p.tid` tag.
+    #[serde(default)]
+    pub(crate) enable_128bit_trace_id: bool,
 }
 
 impl TracingConfigurator for Config {
@@ -87,6 +117,17 @@ impl TracingConfigurator for Config {
                             .unwrap_or(span.name.as_ref())
                     })
             })
+            .with_service_name_mapping(|span, model_config| {
+                // Subgraph spans may override the service name
+                span.resource
+                    .iter()
+                    .find(|(key, _)| **key == SERVICE_NAME)
+                    .and_then(|(_, value)| match value {
+                        Value::String(value) => Some(value.as_str()),
+                        _ => None,
+                    })
+                    .unwrap_or(model_config.service_name.as_str())
+            })
             .with(
                 &common.resource.get(SERVICE_NAME),
                 |builder, service_name| {
@@ -104,11 +145,218 @@ impl TracingConfigurator for Config {
             )
             .with_trace_config(common)
             .build_exporter()?;
-        Ok(builder.with_span_processor(
-            BatchSpanProcessor::builder(exporter, opentelemetry::runtime::Tokio)
-                .with_batch_config(self.batch_processor.clone().into())
+        let batch_config = self.batch_processor.clone().into();
+        let processor = if self.enable_128bit_trace_id {
+            BatchSpanProcessor::builder(TraceIdHighTagExporter(exporter), ExportRuntime)
+                .with_batch_config(batch_config)
                 .build()
-                .filtered(),
-        ))
+        } else {
+            BatchSpanProcessor::builder(exporter, ExportRuntime)
+                .with_batch_config(batch_config)
+                .build()
+        };
+        Ok(builder.with_span_processor(processor.filtered()))
+    }
+}
+
+fn trace_id_high(trace_id: TraceId) -> u64 {
+    (u128::from_be_bytes(trace_id.to_bytes()) >> 64) as u64
+}
+
+/// The Datadog exporter only sends the lower 64 bits of trace ids: the upper 64 bits are added to
+/// every span in the `_dd.p.tid` tag
+#[derive(Debug)]
+struct TraceIdHighTagExporter<E>(E);
+
+impl<E: SpanExporter> SpanExporter for TraceIdHighTagExporter<E> {
+    fn export(&mut self, mut batch: Vec<SpanData>) -> BoxFuture<'static, ExportResult> {
+        for span in &mut batch {
+            let high = trace_id_high(span.span_context.trace_id());
+            if high != 0 {
+                span.attributes
+                    .insert(KeyValue::new(TRACE_ID_HIGH_TAG, format!("{high:016x}")));
+            }
+        }
+        self.0.export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        self.0.shutdown()
+    }
+
+    fn force_flush(&mut self) -> BoxFuture<'static, ExportResult> {
+        self.0.force_flush()
+    }
+}
+
+/// Datadog propagation of 128-bit trace ids: the `x-datadog-trace-id` header holds the lower 64
+/// bits, and the `_dd.p.tid` tag of the `x-datadog-tags` header the upper 64 bits. The other
+/// propagation tags of the `x-datadog-tags` header are forwarded as they were received
+#[derive(Debug, Default)]
+pub(crate) struct Datadog128bitPropagator {
+    inner: DatadogPropagator,
+}
+
+/// The propagation tags of the incoming request, except the trace id tag
+#[derive(Clone, Debug)]
+struct PropagationTags(Vec<String>);
+
+impl TextMapPropagator for Datadog128bitPropagator {
+    fn inject_context(&self, cx: &opentelemetry::Context, injector: &mut dyn Injector) {
+        self.inner.inject_context(cx, injector);
+        let span = cx.span();
+        let span_context = span.span_context();
+        if !span_context.is_valid() {
+            return;
+        }
+        let mut tags = cx
+            .get::<PropagationTags>()
+            .map(|tags| tags.0.clone())
+            .unwrap_or_default();
+        let high = trace_id_high(span_context.trace_id());
+        if high != 0 {
+            tags.push(format!("{TRACE_ID_HIGH_TAG}={high:016x}"));
+        }
+        if !tags.is_empty() {
+            injector.set(DATADOG_TAGS_HEADER, tags.join(","));
+        }
+    }
+
+    fn extract_with_context(
+        &self,
+        cx: &opentelemetry::Context,
+        extractor: &dyn Extractor,
+    ) -> opentelemetry::Context {
+        let mut cx = self.inner.extract_with_context(cx, extractor);
+        let tags: Vec<(&str, &str)> = extractor
+            .get(DATADOG_TAGS_HEADER)
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|tag| tag.trim().split_once('='))
+            .filter(|(key, _)| key.starts_with(PROPAGATION_TAG_PREFIX))
+            .collect();
+        let high = tags
+            .iter()
+            .find(|(key, _)| *key == TRACE_ID_HIGH_TAG)
+            .and_then(|(_, value)| u64::from_str_radix(value, 16).ok());
+        let propagated: Vec<String> = tags
+            .iter()
+            .filter(|(key, _)| *key != TRACE_ID_HIGH_TAG)
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        if !propagated.is_empty() {
+            cx = cx.with_value(PropagationTags(propagated));
+        }
+        let span_context = cx.span().span_context().clone();
+        match high {
+            Some(high)
+                if span_context.is_remote()
+                    && span_context.is_valid()
+                    && trace_id_high(span_context.trace_id()) == 0 =>
+            {
+                let low = u128::from_be_bytes(span_context.trace_id().to_bytes());
+                cx.with_remote_span_context(SpanContext::new(
+                    TraceId::from(((high as u128) << 64) | low),
+                    span_context.span_id(),
+                    span_context.trace_flags(),
+                    true,
+                    span_context.trace_state().clone(),
+                ))
+            }
+            _ => cx,
+        }
+    }
+
+    fn fields(&self) -> FieldIter<'_> {
+        FieldIter::new(DATADOG_HEADER_FIELDS.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use opentelemetry::trace::SpanId;
+    use opentelemetry::trace::TraceFlags;
+    use opentelemetry::trace::TraceState;
+
+    use super::*;
+
+    #[test]
+    fn it_propagates_128bit_trace_ids() {
+        let propagator = Datadog128bitPropagator::default();
+        let headers: HashMap<String, String> = [
+            ("x-datadog-trace-id", "11426947331925830382"),
+            ("x-datadog-parent-id", "42"),
+            ("x-datadog-sampling-priority", "1"),
+            ("x-datadog-tags", "_dd.p.dm=-1,_dd.p.tid=234e10d9e749a0a1"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let cx = propagator.extract(&headers);
+        assert_eq!(
+            cx.span().span_context().trace_id(),
+            TraceId::from_hex("234e10d9e749a0a19e94ac0e4a896aee").unwrap()
+        );
+
+        let mut injected = HashMap::new();
+        propagator.inject_context(&cx, &mut injected);
+        assert_eq!(injected["x-datadog-trace-id"], "11426947331925830382");
+        assert_eq!(
+            injected["x-datadog-tags"],
+            "_dd.p.dm=-1,_dd.p.tid=234e10d9e749a0a1"
+        );
+    }
+
+    #[test]
+    fn it_forwards_propagation_tags() {
+        let propagator = Datadog128bitPropagator::default();
+        let headers: HashMap<String, String> = [
+            ("x-datadog-trace-id", "1234"),
+            ("x-datadog-parent-id", "12"),
+            ("x-datadog-sampling-priority", "2"),
+            (
+                "x-datadog-tags",
+                "_dd.p.dm=-4,_dd.p.usr.id=baz64,other=value",
+            ),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let cx = propagator.extract(&headers);
+
+        let mut injected = HashMap::new();
+        propagator.inject_context(&cx, &mut injected);
+        assert_eq!(injected["x-datadog-tags"], "_dd.p.dm=-4,_dd.p.usr.id=baz64");
+    }
+
+    #[test]
+    fn it_propagates_64bit_trace_ids() {
+        let propagator = Datadog128bitPropagator::default();
+        let headers: HashMap<String, String> = [
+            ("x-datadog-trace-id", "1234"),
+            ("x-datadog-parent-id", "12"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let cx = propagator.extract(&headers);
+        assert_eq!(
+            cx.span().span_context().trace_id(),
+            TraceId::from_u128(1234)
+        );
+
+        let mut injected = HashMap::new();
+        let cx = opentelemetry::Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_u128(1234),
+            SpanId::from_u64(12),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ));
+        propagator.inject_context(&cx, &mut injected);
+        assert_eq!(injected["x-datadog-trace-id"], "1234");
+        assert!(!injected.contains_key("x-datadog-tags"));
     }
 }
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::time::Duration;
@@ -8,9 +9,12 @@ use opentelemetry::sdk::trace::Builder;
 use opentelemetry::sdk::trace::EvictedHashMap;
 use opentelemetry::sdk::trace::Span;
 use opentelemetry::sdk::trace::SpanProcessor;
+use opentelemetry::sdk::Resource;
 use opentelemetry::trace::TraceResult;
 use opentelemetry::Context;
+use opentelemetry::Key;
 use opentelemetry::KeyValue;
+use opentelemetry_semantic_conventions::resource::SERVICE_NAME;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use tower::BoxError;
@@ -24,6 +28,7 @@ pub(crate) mod datadog;
 pub(crate) mod jaeger;
 pub(crate) mod otlp;
 pub(crate) mod reload;
+pub(crate) mod sampling;
 pub(crate) mod zipkin;
 
 pub(crate) trait TracingConfigurator {
@@ -42,6 +47,8 @@ struct ApolloFilterSpanProcessor<T: SpanProcessor> {
 }
 
 pub(crate) static APOLLO_PRIVATE_PREFIX: &str = "apollo_private.";
+/// Private span attribute overriding the `service.name` resource attribute of a span
+pub(crate) const APOLLO_PRIVATE_SERVICE_NAME: &str = "apollo_private.service_name";
 
 impl<T: SpanProcessor> SpanProcessor for ApolloFilterSpanProcessor<T> {
     fn on_start(&self, span: &mut Span, cx: &Context) {
@@ -55,6 +62,18 @@ impl<T: SpanProcessor> SpanProcessor for ApolloFilterSpanProcessor<T> {
             .any(|(key, _)| key.as_str().starts_with(APOLLO_PRIVATE_PREFIX))
         {
             let attributes_len = span.attributes.len();
+            let resource = match span
+                .attributes
+                .get(&Key::from_static_str(APOLLO_PRIVATE_SERVICE_NAME))
+            {
+                Some(service_name) => {
+                    Cow::Owned(span.resource.merge(&Resource::new([KeyValue::new(
+                        SERVICE_NAME,
+                        service_name.to_string(),
+                    )])))
+                }
+                None => span.resource,
+            };
             let span = SpanData {
                 attributes: span
                     .attributes
@@ -67,6 +86,7 @@ impl<T: SpanProcessor> SpanProcessor for ApolloFilterSpanProcessor<T> {
                             m
                         },
                     ),
+                resource,
                 ..span
             };
 
@@ -8,6 +8,7 @@ use tower::BoxError;
 
 use crate::plugins::telemetry::config::TracingCommon;
 use crate::plugins::telemetry::config_new::spans::Spans;
+use crate::plugins::telemetry::runtime::ExportRuntime;
 use crate::plugins::telemetry::tracing::SpanProcessorExt;
 use crate::plugins::telemetry::tracing::TracingConfigurator;
 
@@ -25,13 +26,10 @@ impl TracingConfigurator for super::super::otlp::Config {
         tracing::info!("Configuring Otlp tracing: {}", self.batch_processor);
         let exporter: SpanExporterBuilder = self.exporter()?;
         Ok(builder.with_span_processor(
-            BatchSpanProcessor::builder(
-                exporter.build_span_exporter()?,
-                opentelemetry::runtime::Tokio,
-            )
-            .with_batch_config(self.batch_processor.clone().into())
-            .build()
-            .filtered(),
+            BatchSpanProcessor::builder(exporter.build_span_exporter()?, ExportRuntime)
+                .with_batch_config(self.batch_processor.clone().into())
+                .build()
+                .filtered(),
         ))
     }
 }
@@ -2,13 +2,29 @@ use std::borrow::Cow;
 use std::sync::Arc;
 use std::sync::RwLock;
 
+use opentelemetry::trace::SamplingDecision;
+use opentelemetry::trace::SamplingResult;
 use opentelemetry::trace::SpanBuilder;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::TraceId;
 use opentelemetry::trace::Tracer;
 use tracing_opentelemetry::PreSampledTracer;
 
+use super::sampling::DeferredSampling;
+use super::sampling::Ended;
+
 #[derive(Clone)]
 pub(crate) struct ReloadTracer<S> {
     parent: Arc<RwLock<S>>,
+    deferred: Arc<DeferredSampling>,
+}
+
+fn dropped(builder: SpanBuilder) -> SpanBuilder {
+    builder.with_sampling_result(SamplingResult {
+        decision: SamplingDecision::Drop,
+        attributes: Vec::new(),
+        trace_state: Default::default(),
+    })
 }
 
 impl<S: PreSampledTracer> PreSampledTracer for ReloadTracer<S> {
@@ -16,6 +32,17 @@ impl<S: PreSampledTracer> PreSampledTracer for ReloadTracer<S> {
         &self,
         data: &mut tracing_opentelemetry::OtelData,
     ) -> opentelemetry::Context {
+        // Spans of the traces that were not sampled by sampling rules are not sampled either, so
+        // that their children and the subgraph requests are not sampled
+        if self.deferred.is_enabled() && data.builder.sampling_result.is_none() {
+            let trace_id = data
+                .builder
+                .trace_id
+                .unwrap_or_else(|| data.parent_cx.span().span_context().trace_id());
+            if self.deferred.sampled(trace_id) == Some(false) {
+                data.builder = dropped(std::mem::take(&mut data.builder));
+            }
+        }
         self.parent
             .read()
             .expect("parent tracer must be available")
@@ -37,6 +64,22 @@ impl<S: PreSampledTracer> PreSampledTracer for ReloadTracer<S> {
     }
 }
 
+impl<S: Tracer> ReloadTracer<S> {
+    /// Record the deferred sampling decision of a trace, and export the spans it held back
+    pub(crate) fn decide_sampling(&self, trace_id: TraceId, sampled: bool) {
+        if !self.deferred.is_enabled() {
+            return;
+        }
+        let held = self.deferred.decide(trace_id, sampled);
+        if !held.is_empty() {
+            let parent = self.parent.read().expect("parent tracer must be available");
+            for (builder, cx) in held {
+                parent.build_with_context(builder, &cx);
+            }
+        }
+    }
+}
+
 impl<S: Tracer> Tracer for ReloadTracer<S> {
     type Span = S::Span;
 
@@ -82,10 +125,18 @@ impl<S: Tracer> Tracer for ReloadTracer<S> {
         builder: SpanBuilder,
         parent_cx: &opentelemetry::Context,
     ) -> Self::Span {
-        self.parent
-            .read()
-            .expect("parent tracer must be available")
-            .build_with_context(builder, parent_cx)
+        let parent = self.parent.read().expect("parent tracer must be available");
+        match self.deferred.end(builder, parent_cx) {
+            Ended::Export(builder) => parent.build_with_context(builder, parent_cx),
+            Ended::Flush(held) => held
+                .into_iter()
+                .map(|(builder, cx)| parent.build_with_context(builder, &cx))
+                .last()
+                .expect("the flushed spans end with the root span; qed"),
+            Ended::Discard => {
+                parent.build_with_context(dropped(SpanBuilder::from_name("")), parent_cx)
+            }
+        }
     }
 
     fn in_span<T, F, N>(&self, name: N, f: F) -> T
@@ -105,7 +156,22 @@ impl<S> ReloadTracer<S> {
     pub(crate) fn new(parent: S) -> Self {
         Self {
             parent: Arc::new(RwLock::new(parent)),
+            deferred: Default::default(),
+        }
+    }
+
+    /// Defer the sampling decisions to sampling rules, with the sampling ratio of the traces
+    /// without a decision, or stop deferring them with `None`
+    pub(crate) fn defer_sampling(&self, default_ratio: Option<f64>) {
+        self.deferred.configure(default_ratio)
+    }
+
+    /// The sampling decision of a trace, if it was deferred and made
+    pub(crate) fn sampling_decision(&self, trace_id: TraceId) -> Option<bool> {
+        if !self.deferred.is_enabled() {
+            return None;
         }
+        self.deferred.sampled(trace_id)
     }
 
     pub(crate) fn reload(&self, new: S) {
@@ -0,0 +1,531 @@
+//! Sampling rules.
+//!
+//! The sampler of a request can depend on its operation name, its client and the subgraphs its
+//! query plan fetches from, which are only known once the request is planned, while traces are
+//! normally sampled when their root span is created. When sampling rules are configured, root
+//! spans are enabled with the highest ratio of the rules, and the spans of a trace that end
+//! before its sampling decision are held back, then exported or dropped once the decision is made.
+//! The traces waiting for a decision are sharded by trace id and bounded, spans that can't be held
+//! back are dropped.
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use opentelemetry::trace::SpanBuilder;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::trace::TraceId;
+use opentelemetry::Context;
+use parking_lot::Mutex;
+use rand::thread_rng;
+use rand::Rng;
+use regex::Regex;
+use tower::BoxError;
+
+use crate::plugins::telemetry::config::TracingCommon;
+
+/// How long the decision of a trace is kept after its root span ended, for the spans that end
+/// after it, such as those of deferred responses
+const DECIDED_TRACE_TTL: Duration = Duration::from_secs(30);
+/// How long the spans of a trace are held back without a decision before being dropped
+const PENDING_TRACE_TTL: Duration = Duration::from_secs(30);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5);
+const SHARDS: usize = 64;
+/// The number of traces tracked by each shard
+const MAX_TRACES_PER_SHARD: usize = 1024;
+/// The number of spans held back for a trace
+const MAX_HELD_SPANS: usize = 512;
+
+/// Sampling rules, the first rule matching a request sets its sampling ratio
+#[derive(Debug)]
+pub(crate) struct SamplingRules {
+    rules: Vec<Rule>,
+    default_ratio: f64,
+}
+
+#[derive(Debug)]
+struct Rule {
+    operation_name: Option<Regex>,
+    client_name: Option<String>,
+    subgraph: Option<String>,
+    ratio: f64,
+}
+
+impl SamplingRules {
+    /// The sampling rules of the configuration, `None` if there are none and the sampling
+    /// decisions are not `deferred` anyway
+    pub(crate) fn new(common: &TracingCommon, deferred: bool) -> Result<Option<Self>, BoxError> {
+        if common.sampling_rules.is_empty() && !deferred {
+            return Ok(None);
+        }
+        let rules = common
+            .sampling_rules
+            .iter()
+            .map(|rule| {
+                Ok(Rule {
+                    operation_name: rule
+                        .operation_name
+                        .as_deref()
+                        .map(|pattern| {
+                            Regex::new(pattern).map_err(|e| {
+                                format!("invalid sampling rule operation name '{pattern}': {e}")
+                            })
+                        })
+                        .transpose()?,
+                    client_name: rule.client_name.clone(),
+                    subgraph: rule.subgraph.clone(),
+                    ratio: rule.sampler.ratio(),
+                })
+            })
+            .collect::<Result<Vec<_>, BoxError>>()?;
+        Ok(Some(Self {
+            rules,
+            default_ratio: common.sampler.ratio(),
+        }))
+    }
+
+    pub(crate) fn default_ratio(&self) -> f64 {
+        self.default_ratio
+    }
+
+    /// The highest ratio of the rules, root spans are enabled with it
+    pub(crate) fn max_ratio(&self) -> f64 {
+        self.rules
+            .iter()
+            .map(|rule| rule.ratio)
+            .fold(self.default_ratio, f64::max)
+    }
+
+    /// The sampling ratio of a request. Without its query plan, the rules matching subgraphs do
+    /// not match
+    pub(crate) fn ratio(
+        &self,
+        operation_name: Option<&str>,
+        client_name: Option<&str>,
+        subgraphs: Option<&[&str]>,
+    ) -> f64 {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.operation_name.as_ref().map_or(true, |regex| {
+                    operation_name.is_some_and(|n| regex.is_match(n))
+                }) && rule
+                    .client_name
+                    .as_deref()
+                    .map_or(true, |name| client_name == Some(name))
+                    && rule.subgraph.as_deref().map_or(true, |subgraph| {
+                        subgraphs.is_some_and(|subgraphs| subgraphs.contains(&subgraph))
+                    })
+            })
+            .map_or(self.default_ratio, |rule| rule.ratio)
+    }
+}
+
+pub(crate) fn sample(ratio: f64) -> bool {
+    thread_rng().gen_range(0.0..=1.0) <= ratio
+}
+
+/// The ratio to sample the traces whose root span was enabled with `enabled_ratio` with, so that
+/// `ratio` of every trace is sampled
+pub(crate) fn conditional_ratio(ratio: f64, enabled_ratio: f64) -> f64 {
+    if enabled_ratio > 0.0 {
+        ratio / enabled_ratio
+    } else {
+        0.0
+    }
+}
+
+/// Spans held back until the sampling decision of their trace
+pub(crate) type HeldSpans = Vec<(SpanBuilder, Context)>;
+
+/// What to do with a span that ended
+#[allow(clippy::large_enum_variant)] // Moved once per span
+pub(crate) enum Ended {
+    /// Export the span
+    Export(SpanBuilder),
+    /// Export the spans held back for the trace, ending with this root span
+    Flush(HeldSpans),
+    /// The span is dropped, or held back until the decision of its trace
+    Discard,
+}
+
+/// Sampling decisions made after the creation of the root spans
+pub(crate) struct DeferredSampling {
+    enabled: AtomicBool,
+    /// Bits of the sampling ratio of the traces whose root span ended without a decision
+    default_ratio: AtomicU64,
+    shards: Box<[Mutex<Traces>]>,
+}
+
+impl Default for DeferredSampling {
+    fn default() -> Self {
+        Self {
+            enabled: AtomicBool::default(),
+            default_ratio: AtomicU64::default(),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Traces {
+    traces: HashMap<TraceId, DeferredTrace>,
+    pruned_at: Option<Instant>,
+}
+
+struct DeferredTrace {
+    sampled: Option<bool>,
+    held: HeldSpans,
+    root_ended: bool,
+    updated: Instant,
+}
+
+impl DeferredTrace {
+    fn new(now: Instant) -> Self {
+        Self {
+            sampled: None,
+            held: Vec::new(),
+            root_ended: false,
+            updated: now,
+        }
+    }
+}
+
+impl DeferredSampling {
+    /// Defer the sampling decisions, with the sampling ratio of the enabled traces without a
+    /// decision, or stop deferring them with `None`
+    pub(crate) fn configure(&self, default_ratio: Option<f64>) {
+        match default_ratio {
+            Some(ratio) => {
+                self.default_ratio
+                    .store(f64::to_bits(ratio), Ordering::Relaxed);
+                self.enabled.store(true, Ordering::Relaxed);
+            }
+            None => {
+                self.enabled.store(false, Ordering::Relaxed);
+                for shard in self.shards.iter() {
+                    shard.lock().traces.clear();
+                }
+            }
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn shard(&self, trace_id: TraceId) -> &Mutex<Traces> {
+        let index = u128::from_be_bytes(trace_id.to_bytes()) % SHARDS as u128;
+        &self.shards[index as usize]
+    }
+
+    /// The decision of a trace, if it was made
+    pub(crate) fn sampled(&self, trace_id: TraceId) -> Option<bool> {
+        self.shard(trace_id)
+            .lock()
+            .traces
+            .get(&trace_id)
+            .and_then(|trace| trace.sampled)
+    }
+
+    /// Record the decision of a trace, returns the held spans to export. The first decision of a
+    /// trace is kept, and it is not recorded if the shard of the trace is full
+    pub(crate) fn decide(&self, trace_id: TraceId, sampled: bool) -> HeldSpans {
+        self.decide_at(trace_id, sampled, Instant::now())
+    }
+
+    fn decide_at(&self, trace_id: TraceId, sampled: bool, now: Instant) -> HeldSpans {
+        let mut traces = self.shard(trace_id).lock();
+        traces.prune(now);
+        let Some(trace) = traces.get_or_insert(trace_id, now) else {
+            return Vec::new();
+        };
+        if trace.sampled.is_some() {
+            return Vec::new();
+        }
+        trace.sampled = Some(sampled);
+        let held = std::mem::take(&mut trace.held);
+        if sampled {
+            held
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Handle the end of a span
+    pub(crate) fn end(&self, builder: SpanBuilder, parent_cx: &Context) -> Ended {
+        self.end_at(builder, parent_cx, Instant::now())
+    }
+
+    fn end_at(&self, builder: SpanBuilder, parent_cx: &Context, now: Instant) -> Ended {
+        if !self.is_enabled() {
+            return Ended::Export(builder);
+        }
+        let parent = parent_cx.span();
+        let parent = parent.span_context();
+        let is_root = !parent_cx.has_active_span() || parent.is_remote();
+        let Some(trace_id) = builder
+            .trace_id
+            .or_else(|| parent_cx.has_active_span().then(|| parent.trace_id()))
+        else {
+            return Ended::Export(builder);
+        };
+
+        let mut traces = self.shard(trace_id).lock();
+        traces.prune(now);
+        let Some(trace) = traces.get_or_insert(trace_id, now) else {
+            // Too many traces are waiting for a decision: the root span is sampled on its own
+            return if is_root && sample(self.default_ratio()) {
+                Ended::Export(builder)
+            } else {
+                Ended::Discard
+            };
+        };
+        trace.root_ended |= is_root;
+        match trace.sampled {
+            Some(true) => Ended::Export(builder),
+            Some(false) => Ended::Discard,
+            None if is_root => {
+                let sampled = sample(self.default_ratio());
+                trace.sampled = Some(sampled);
+                let mut held = std::mem::take(&mut trace.held);
+                if sampled {
+                    held.push((builder, parent_cx.clone()));
+                    Ended::Flush(held)
+                } else {
+                    Ended::Discard
+                }
+            }
+            None => {
+                if trace.held.len() < MAX_HELD_SPANS {
+                    trace.held.push((builder, parent_cx.clone()));
+                }
+                Ended::Discard
+            }
+        }
+    }
+
+    fn default_ratio(&self) -> f64 {
+        f64::from_bits(self.default_ratio.load(Ordering::Relaxed))
+    }
+}
+
+impl Traces {
+    /// The trace, tracked if the shard is not full
+    fn get_or_insert(&mut self, trace_id: TraceId, now: Instant) -> Option<&mut DeferredTrace> {
+        if self.traces.len() >= MAX_TRACES_PER_SHARD && !self.traces.contains_key(&trace_id) {
+            return None;
+        }
+        let trace = self
+            .traces
+            .entry(trace_id)
+            .or_insert_with(|| DeferredTrace::new(now));
+        trace.updated = now;
+        Some(trace)
+    }
+
+    fn prune(&mut self, now: Instant) {
+        if self
+            .pruned_at
+            .is_some_and(|pruned_at| now.duration_since(pruned_at) < PRUNE_INTERVAL)
+        {
+            return;
+        }
+        self.pruned_at = Some(now);
+        self.traces.retain(|_, trace| {
+            let ttl = if trace.root_ended {
+                DECIDED_TRACE_TTL
+            } else {
+                PENDING_TRACE_TTL
+            };
+            now.duration_since(trace.updated) < ttl
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::trace::SpanContext;
+    use opentelemetry::trace::SpanId;
+    use opentelemetry::trace::TraceFlags;
+    use opentelemetry::trace::TraceState;
+
+    use super::*;
+    use crate::plugins::telemetry::config::SamplerOption;
+    use crate::plugins::telemetry::config::SamplingRule;
+
+    const TRACE_ID: TraceId = TraceId::from_bytes(42u128.to_be_bytes());
+
+    fn rules() -> SamplingRules {
+        let rule = |operation_name: Option<&str>,
+                    client_name: Option<&str>,
+                    subgraph: Option<&str>,
+                    ratio: f64| SamplingRule {
+            operation_name: operation_name.map(str::to_string),
+            client_name: client_name.map(str::to_string),
+            subgraph: subgraph.map(str::to_string),
+            sampler: SamplerOption::TraceIdRatioBased(ratio),
+        };
+        SamplingRules::new(
+            &TracingCommon {
+                sampler: SamplerOption::TraceIdRatioBased(0.1),
+                sampling_rules: vec![
+                    rule(Some("^Checkout"), None, None, 1.0),
+                    rule(None, Some("dashboard"), None, 0.01),
+                    rule(None, None, Some("inventory"), 0.5),
+                ],
+                ..Default::default()
+            },
+            false,
+        )
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn it_selects_the_ratio_of_the_first_matching_rule() {
+        let rules = rules();
+        let subgraphs: &[&str] = &["products", "inventory"];
+        assert_eq!(
+            rules.ratio(Some("CheckoutCart"), Some("dashboard"), Some(subgraphs)),
+            1.0
+        );
+        assert_eq!(
+            rules.ratio(Some("Cart"), Some("dashboard"), Some(subgraphs)),
+            0.01
+        );
+        assert_eq!(rules.ratio(None, Some("web"), Some(subgraphs)), 0.5);
+        assert_eq!(rules.ratio(None, Some("web"), None), 0.1);
+        assert_eq!(rules.ratio(Some("Cart"), None, Some(&["products"])), 0.1);
+
+        assert!(SamplingRules::new(&TracingCommon::default(), false)
+            .unwrap()
+            .is_none());
+        // Without rules, deferred decisions use the sampler
+        let deferred = SamplingRules::new(&TracingCommon::default(), true)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            deferred.ratio(Some("Cart"), None, None),
+            deferred.default_ratio()
+        );
+        assert_eq!(deferred.max_ratio(), deferred.default_ratio());
+    }
+
+    #[test]
+    fn it_enables_root_spans_with_the_highest_ratio() {
+        assert_eq!(rules().max_ratio(), 1.0);
+        assert_eq!(conditional_ratio(0.1, 0.5), 0.2);
+        assert_eq!(conditional_ratio(0.0, 0.0), 0.0);
+    }
+
+    fn span(root: bool) -> (SpanBuilder, Context) {
+        let mut builder = SpanBuilder::from_name("span");
+        if root {
+            builder.trace_id = Some(TRACE_ID);
+            (builder, Context::new())
+        } else {
+            // A local parent span
+            let parent = SpanContext::new(
+                TRACE_ID,
+                SpanId::from_u64(1),
+                TraceFlags::SAMPLED,
+                false,
+                TraceState::default(),
+            );
+            (builder, Context::new().with_remote_span_context(parent))
+        }
+    }
+
+    #[test]
+    fn it_holds_spans_until_the_decision() {
+        let deferred = DeferredSampling::default();
+        let (builder, cx) = span(false);
+        assert!(matches!(deferred.end(builder, &cx), Ended::Export(_)));
+
+        deferred.configure(Some(0.0));
+        let (builder, cx) = span(false);
+        assert!(matches!(deferred.end(builder, &cx), Ended::Discard));
+        assert_eq!(deferred.decide(TRACE_ID, true).len(), 1);
+        // The first decision is kept
+        assert!(deferred.decide(TRACE_ID, false).is_empty());
+        assert_eq!(deferred.sampled(TRACE_ID), Some(true));
+        let (builder, cx) = span(false);
+        assert!(matches!(deferred.end(builder, &cx), Ended::Export(_)));
+    }
+
+    #[test]
+    fn it_decides_when_the_root_span_ends() {
+        let deferred = DeferredSampling::default();
+        deferred.configure(Some(1.0));
+        let (builder, cx) = span(false);
+        assert!(matches!(deferred.end(builder, &cx), Ended::Discard));
+        let (builder, cx) = span(true);
+        assert!(matches!(deferred.end(builder, &cx), Ended::Flush(held) if held.len() == 2));
+
+        let deferred = DeferredSampling::default();
+        deferred.configure(Some(0.0));
+        let (builder, cx) = span(false);
+        assert!(matches!(deferred.end(builder, &cx), Ended::Discard));
+        let (builder, cx) = span(true);
+        assert!(matches!(deferred.end(builder, &cx), Ended::Discard));
+        assert_eq!(deferred.sampled(TRACE_ID), Some(false));
+    }
+
+    #[test]
+    fn it_forgets_traces() {
+        let deferred = DeferredSampling::default();
+        deferred.configure(Some(1.0));
+        let now = Instant::now();
+        let (builder, cx) = span(true);
+        deferred.end_at(builder, &cx, now);
+        assert_eq!(deferred.shard(TRACE_ID).lock().traces.len(), 1);
+
+        let later = now + DECIDED_TRACE_TTL;
+        deferred.decide_at(TraceId::from_u128(7), true, later);
+        let (mut builder, cx) = span(false);
+        builder.trace_id = Some(TRACE_ID);
+        deferred.end_at(builder, &cx, later);
+        let tracked = |trace_id| {
+            deferred
+                .shard(trace_id)
+                .lock()
+                .traces
+                .contains_key(&trace_id)
+        };
+        assert!(tracked(TRACE_ID));
+        assert!(tracked(TraceId::from_u128(7)));
+        // The root span of the new trace has not ended yet
+        assert_eq!(deferred.sampled(TRACE_ID), None);
+    }
+
+    #[test]
+    fn it_bounds_the_held_spans() {
+        let deferred = DeferredSampling::default();
+        deferred.configure(Some(1.0));
+        for _ in 0..MAX_HELD_SPANS + 1 {
+            let (builder, cx) = span(false);
+            deferred.end(builder, &cx);
+        }
+        assert_eq!(deferred.decide(TRACE_ID, true).len(), MAX_HELD_SPANS);
+
+        // Trace ids in the same shard
+        let trace_id = |i: usize| TraceId::from_u128((i * SHARDS) as u128 + 1);
+        for i in 0..MAX_TRACES_PER_SHARD {
+            deferred.decide(trace_id(i), false);
+        }
+        let (mut builder, cx) = span(false);
+        builder.trace_id = Some(trace_id(MAX_TRACES_PER_SHARD));
+        assert!(matches!(deferred.end(builder, &cx), Ended::Discard));
+        assert_eq!(deferred.sampled(trace_id(MAX_TRACES_PER_SHARD)), None);
+        // Its root span is sampled on its own
+        let (mut builder, _) = span(true);
+        builder.trace_id = Some(trace_id(MAX_TRACES_PER_SHARD));
+        assert!(matches!(
+            deferred.end(builder, &Context::new()),
+            Ended::Export(_)
+        ));
+    }
+}
@@ -0,0 +1,381 @@
+//! Redaction of sensitive GraphQL variables in logs and traces.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use apollo_compiler::ast;
+use apollo_compiler::schema::ExtendedType;
+use tower::BoxError;
+
+use crate::graphql;
+use crate::json_ext::Object;
+use crate::plugins::telemetry::config::VariableRedactionConfig;
+use crate::services::layers::query_analysis::ParsedDocument;
+use crate::spec::query::traverse;
+use crate::Context;
+
+/// The value logged and exported instead of a redacted variable.
+pub(crate) const REDACTED_VALUE: &str = "[REDACTED]";
+
+/// The variables of a request that must not be logged or exported, stored in the context's private entries
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RedactedVariables(HashSet<String>);
+
+impl RedactedVariables {
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.0.contains(name)
+    }
+
+    /// The variables, with the values of redacted variables replaced
+    pub(crate) fn redact<'a>(&self, variables: &'a Object) -> Cow<'a, Object> {
+        if !variables.keys().any(|name| self.contains(name.as_str())) {
+            return Cow::Borrowed(variables);
+        }
+        Cow::Owned(
+            variables
+                .iter()
+                .map(|(name, value)| {
+                    if self.contains(name.as_str()) {
+                        (name.clone(), REDACTED_VALUE.into())
+                    } else {
+                        (name.clone(), value.clone())
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// The request body to log, with the values of the variables redacted for this request replaced
+    pub(crate) fn redact_body<'a>(
+        context: &Context,
+        body: &'a graphql::Request,
+    ) -> Cow<'a, graphql::Request> {
+        let redacted_variables = context.private_entries.lock().get::<Self>().cloned();
+        match redacted_variables
+            .as_ref()
+            .map(|redacted_variables| redacted_variables.redact(&body.variables))
+        {
+            Some(Cow::Owned(variables)) => {
+                let mut body = body.clone();
+                body.variables = variables;
+                Cow::Owned(body)
+            }
+            _ => Cow::Borrowed(body),
+        }
+    }
+}
+
+/// Finds the variables of requests that must be redacted
+#[derive(Default)]
+pub(crate) struct VariableRedaction {
+    names: HashSet<String>,
+    directive: Option<DirectiveRedaction>,
+}
+
+struct DirectiveRedaction {
+    directive: String,
+    schema: apollo_compiler::Schema,
+    /// Input object types with a marked input field, directly or in a nested input object
+    sensitive_input_types: HashSet<String>,
+}
+
+impl VariableRedaction {
+    pub(crate) fn new(
+        config: &VariableRedactionConfig,
+        supergraph_sdl: &str,
+    ) -> Result<Self, BoxError> {
+        let directive = match &config.directive {
+            Some(directive) => {
+                let directive = directive.trim_start_matches('@').to_string();
+                let schema = apollo_compiler::Schema::parse(supergraph_sdl, "schema.graphql")
+                    .map_err(|e| {
+                        format!("cannot parse the supergraph schema for variable redaction: {e}")
+                    })?;
+                let sensitive_input_types = sensitive_input_types(&schema, &directive);
+                Some(DirectiveRedaction {
+                    directive,
+                    schema,
+                    sensitive_input_types,
+                })
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            names: config.names.iter().cloned().collect(),
+            directive,
+        })
+    }
+
+    /// Store the variables of the request that must be redacted in its context
+    pub(crate) fn populate_context(&self, context: &Context) -> RedactedVariables {
+        if self.names.is_empty() && self.directive.is_none() {
+            return RedactedVariables::default();
+        }
+
+        let mut variables = self.names.clone();
+        let document = context
+            .private_entries
+            .lock()
+            .get::<ParsedDocument>()
+            .cloned();
+        if let (Some(redaction), Some(document)) = (&self.directive, document) {
+            let mut visitor = SensitiveVariables {
+                redaction,
+                variables: &mut variables,
+            };
+            if let Err(err) = traverse::document(&mut visitor, &document.ast) {
+                ::tracing::debug!("cannot find the sensitive variables of the operation: {err}");
+            }
+        }
+        let redacted_variables = RedactedVariables(variables);
+        context
+            .private_entries
+            .lock()
+            .insert(redacted_variables.clone());
+        redacted_variables
+    }
+}
+
+fn sensitive_input_types(schema: &apollo_compiler::Schema, directive: &str) -> HashSet<String> {
+    let mut sensitive = HashSet::new();
+    loop {
+        let mut changed = false;
+        for (name, ty) in &schema.types {
+            if let ExtendedType::InputObject(input) = ty {
+                if !sensitive.contains(name.as_str())
+                    && input.fields.values().any(|field| {
+                        field.directives.has(directive)
+                            || sensitive.contains(field.ty.inner_named_type().as_str())
+                    })
+                {
+                    sensitive.insert(name.to_string());
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return sensitive;
+        }
+    }
+}
+
+struct SensitiveVariables<'a> {
+    redaction: &'a DirectiveRedaction,
+    variables: &'a mut HashSet<String>,
+}
+
+impl<'a> SensitiveVariables<'a> {
+    fn value(&mut self, value: &ast::Value, definition: &ast::InputValueDefinition) {
+        if definition.directives.has(&self.redaction.directive) {
+            collect_variables(value, self.variables);
+            return;
+        }
+        let type_name = definition.ty.inner_named_type();
+        match value {
+            // The whole value of the variable is redacted when it contains a marked input field
+            ast::Value::Variable(name) => {
+                if self
+                    .redaction
+                    .sensitive_input_types
+                    .contains(type_name.as_str())
+                {
+                    self.variables.insert(name.to_string());
+                }
+            }
+            ast::Value::List(items) => {
+                for item in items {
+                    self.value(item, definition);
+                }
+            }
+            ast::Value::Object(fields) => {
+                if let Some(ExtendedType::InputObject(input)) =
+                    self.redaction.schema.types.get(type_name)
+                {
+                    for (name, value) in fields {
+                        if let Some(field_definition) = input.fields.get(name) {
+                            self.value(value, field_definition);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'a> traverse::Visitor for SensitiveVariables<'a> {
+    fn field(
+        &mut self,
+        _parent_type: &str,
+        field_def: &ast::FieldDefinition,
+        def: &ast::Field,
+    ) -> Result<(), BoxError> {
+        for argument in &def.arguments {
+            if let Some(argument_def) = field_def
+                .arguments
+                .iter()
+                .find(|argument_def| argument_def.name == argument.name)
+            {
+                self.value(&argument.value, argument_def);
+            }
+        }
+        traverse::field(self, field_def, def)
+    }
+
+    fn schema(&self) -> &apollo_compiler::Schema {
+        &self.redaction.schema
+    }
+}
+
+fn collect_variables(value: &ast::Value, variables: &mut HashSet<String>) {
+    match value {
+        ast::Value::Variable(name) => {
+            variables.insert(name.to_string());
+        }
+        ast::Value::List(items) => {
+            for item in items {
+                collect_variables(item, variables);
+            }
+        }
+        ast::Value::Object(fields) => {
+            for (_, value) in fields {
+                collect_variables(value, variables);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::spec::Query;
+    use crate::spec::Schema;
+    use crate::Configuration;
+
+    const SCHEMA: &str = r#"
+        schema
+          @link(url: "https://specs.apollo.dev/link/v1.0")
+          @link(url: "https://specs.apollo.dev/join/v0.3", for: EXECUTION)
+        {
+          query: Query
+          mutation: Mutation
+        }
+        directive @join__field(graph: join__Graph, requires: join__FieldSet, provides: join__FieldSet, type: String, external: Boolean, override: String, usedOverridden: Boolean) repeatable on FIELD_DEFINITION | INPUT_FIELD_DEFINITION
+        directive @join__graph(name: String!, url: String!) on ENUM_VALUE
+        directive @join__type(graph: join__Graph!, key: join__FieldSet, extension: Boolean! = false, resolvable: Boolean! = true, isInterfaceObject: Boolean! = false) repeatable on OBJECT | INTERFACE | UNION | ENUM | INPUT_OBJECT | SCALAR
+        directive @link(url: String, as: String, for: link__Purpose, import: [link__Import]) repeatable on SCHEMA
+        directive @sensitive on ARGUMENT_DEFINITION | INPUT_FIELD_DEFINITION
+        scalar join__FieldSet
+        scalar link__Import
+        enum join__Graph {
+          USERS @join__graph(name: "users", url: "http://localhost:4001")
+        }
+        enum link__Purpose {
+          SECURITY
+          EXECUTION
+        }
+        input Credentials {
+          login: String!
+          password: String! @sensitive
+        }
+        input SignUp {
+          credentials: Credentials!
+          name: String
+        }
+        type Query {
+          user(id: ID!, token: String @sensitive): User
+        }
+        type Mutation {
+          signUp(input: SignUp!): User
+          rename(id: ID!, name: String!): User
+        }
+        type User {
+          id: ID!
+          name: String
+        }
+    "#;
+
+    fn context(query: &str) -> Context {
+        let schema = Schema::parse(SCHEMA, &Configuration::default()).unwrap();
+        let context = Context::new();
+        context
+            .private_entries
+            .lock()
+            .insert::<ParsedDocument>(Query::parse_document(
+                query,
+                &schema,
+                &Configuration::default(),
+            ));
+        context
+    }
+
+    fn redaction(directive: Option<&str>, names: &[&str]) -> VariableRedaction {
+        VariableRedaction::new(
+            &VariableRedactionConfig {
+                directive: directive.map(|directive| directive.to_string()),
+                names: names.iter().map(|name| name.to_string()).collect(),
+            },
+            SCHEMA,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn redacts_variables_of_marked_arguments() {
+        let context =
+            context("query($id: ID!, $token: String) { user(id: $id, token: $token) { name } }");
+        let redacted = redaction(Some("@sensitive"), &[]).populate_context(&context);
+        assert!(redacted.contains("token"));
+        assert!(!redacted.contains("id"));
+    }
+
+    #[test]
+    fn redacts_variables_containing_marked_input_fields() {
+        let context = context(
+            "mutation($input: SignUp!, $password: String!, $id: ID!, $name: String!) {
+                signUp(input: $input) { id }
+                other: signUp(input: { credentials: { login: \"ada\", password: $password }, name: $name }) { id }
+                rename(id: $id, name: $name) { id }
+            }",
+        );
+        let redacted = redaction(Some("sensitive"), &[]).populate_context(&context);
+        assert!(redacted.contains("input"));
+        assert!(redacted.contains("password"));
+        assert!(!redacted.contains("id"));
+        assert!(!redacted.contains("name"));
+    }
+
+    #[test]
+    fn redacts_variables_by_name() {
+        let context = context("query($id: ID!) { user(id: $id) { name } }");
+        let redacted = redaction(None, &["id"]).populate_context(&context);
+        assert!(redacted.contains("id"));
+
+        let body = graphql::Request::fake_builder()
+            .query("query($id: ID!) { user(id: $id) { name } }")
+            .variables(json!({"id": "1", "other": 2}).as_object().unwrap().clone())
+            .build();
+        let redacted_body = RedactedVariables::redact_body(&context, &body);
+        assert_eq!(
+            serde_json_bytes::Value::Object(redacted_body.variables.clone()),
+            json!({"id": REDACTED_VALUE, "other": 2})
+        );
+    }
+
+    #[test]
+    fn nothing_redacted_without_configuration() {
+        let context =
+            context("query($id: ID!, $token: String) { user(id: $id, token: $token) { name } }");
+        let redacted = VariableRedaction::default().populate_context(&context);
+        assert!(!redacted.contains("token"));
+        assert!(context
+            .private_entries
+            .lock()
+            .get::<RedactedVariables>()
+            .is_none());
+    }
+}
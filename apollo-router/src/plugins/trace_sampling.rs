@@ -0,0 +1,328 @@
+//! An endpoint overriding the trace sampling of the router at runtime, such as to sample every
+//! request for the next ten minutes during an incident investigation, without a configuration
+//! rollout. Overrides always expire, after which the configured sampling applies again.
+//!
+//! The endpoint is disabled by default and served on its own listener, which should only be
+//! reachable by administrators. Every change is recorded in an audit log entry.
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::axum_factory::utils::ConnectionInfo;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::telemetry::reload::trace_sampling;
+use crate::plugins::telemetry::reload::TraceSampling;
+use crate::plugins::telemetry::reload::TraceSamplingOverride;
+use crate::register_plugin;
+use crate::services::router;
+use crate::Endpoint;
+use crate::ListenAddr;
+
+register_plugin!(
+    "apollo",
+    "experimental_trace_sampling",
+    TraceSamplingEndpoint
+);
+
+/// Expose an endpoint overriding the trace sampling ratio at runtime
+#[derive(Clone, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct Config {
+    /// Set to true to enable the trace sampling endpoint
+    enabled: bool,
+    /// The listen address of the trace sampling endpoint. It should only be reachable by
+    /// administrators
+    listen: ListenAddr,
+    /// The path of the trace sampling endpoint
+    path: String,
+    /// The longest duration of an override, which is also the duration of overrides without one
+    /// (default: 1h)
+    #[serde(with = "humantime_serde")]
+    #[schemars(with = "String")]
+    max_duration: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: ListenAddr::SocketAddr("127.0.0.1:6060".parse().expect("valid listenAddr")),
+            path: "/trace_sampling".to_string(),
+            max_duration: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+struct TraceSamplingEndpoint {
+    config: Config,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct TraceSamplingParameters {
+    /// The ratio of sampled requests, every request by default
+    ratio: Option<f64>,
+    /// How long the ratio applies, such as `10m`, before going back to the configured sampling
+    duration: Option<String>,
+    /// Why the sampling changed, for the audit log
+    reason: Option<String>,
+}
+
+fn response(
+    status: StatusCode,
+    body: serde_json::Value,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    Ok(router::Response {
+        response: http::Response::builder()
+            .status(status)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string().into())?,
+        context,
+    })
+}
+
+fn error(
+    status: StatusCode,
+    message: impl Into<String>,
+    context: crate::Context,
+) -> Result<router::Response, BoxError> {
+    response(status, json!({ "error": message.into() }), context)
+}
+
+/// The override in effect, or `null` when the configured sampling applies
+fn current(trace_sampling: &TraceSampling) -> serde_json::Value {
+    match trace_sampling.current() {
+        Some(TraceSamplingOverride { ratio, until }) => json!({
+            "ratio": ratio,
+            "remaining": humantime::format_duration(Duration::from_secs(
+                until
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default()
+                    .as_secs()
+            ))
+            .to_string(),
+        }),
+        None => json!({ "ratio": null }),
+    }
+}
+
+/// `GET` returns the override in effect, `PUT` overrides the sampling with a `ratio` for a
+/// `duration`, and `DELETE` goes back to the configured sampling
+async fn trace_sampling_endpoint(
+    request: router::Request,
+    trace_sampling: Arc<TraceSampling>,
+    max_duration: Duration,
+) -> Result<router::Response, BoxError> {
+    let context = request.context;
+    let method = request.router_request.method().clone();
+    let peer_address = request
+        .router_request
+        .extensions()
+        .get::<ConnectionInfo>()
+        .and_then(|info| info.peer_address)
+        .map(|address| address.to_string())
+        .unwrap_or_default();
+    let parameters: TraceSamplingParameters = match serde_urlencoded::from_str(
+        request.router_request.uri().query().unwrap_or_default(),
+    ) {
+        Ok(parameters) => parameters,
+        Err(e) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                format!("invalid parameters: {e}"),
+                context,
+            )
+        }
+    };
+    let reason = parameters.reason.unwrap_or_default();
+
+    if method == Method::GET {
+    } else if method == Method::PUT || method == Method::POST {
+        let ratio = parameters.ratio.unwrap_or(1.0);
+        if !(0.0..=1.0).contains(&ratio) {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "the ratio must be between 0 and 1",
+                context,
+            );
+        }
+        let duration = match parameters
+            .duration
+            .as_deref()
+            .map(humantime::parse_duration)
+            .transpose()
+        {
+            Ok(duration) => duration.unwrap_or(max_duration).min(max_duration),
+            Err(e) => {
+                return error(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid duration: {e}"),
+                    context,
+                )
+            }
+        };
+        trace_sampling.set(ratio, duration);
+        tracing::info!(
+            audit = true,
+            trace_sampling.ratio = ratio,
+            trace_sampling.duration = %humantime::format_duration(duration),
+            client.address = %peer_address,
+            reason = %reason,
+            "trace sampling overridden"
+        );
+    } else if method == Method::DELETE {
+        trace_sampling.reset();
+        tracing::info!(
+            audit = true,
+            client.address = %peer_address,
+            reason = %reason,
+            "trace sampling override removed"
+        );
+    } else {
+        return error(StatusCode::METHOD_NOT_ALLOWED, "", context);
+    }
+    response(StatusCode::OK, current(&trace_sampling), context)
+}
+
+#[async_trait::async_trait]
+impl Plugin for TraceSamplingEndpoint {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(TraceSamplingEndpoint {
+            config: init.config,
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut map = MultiMap::new();
+        if !self.config.enabled {
+            return map;
+        }
+
+        let max_duration = self.config.max_duration;
+        map.insert(
+            self.config.listen.clone(),
+            Endpoint::from_router_service(
+                self.config.path.clone(),
+                tower::service_fn(move |request| {
+                    trace_sampling_endpoint(request, trace_sampling(), max_duration)
+                })
+                .boxed(),
+            ),
+        );
+        tracing::info!(
+            "Trace sampling endpoint exposed at {}{}",
+            self.config.listen,
+            self.config.path
+        );
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    async fn call(
+        trace_sampling: &Arc<TraceSampling>,
+        method: Method,
+        uri: &str,
+    ) -> (StatusCode, Value) {
+        let request: router::Request = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(router::Body::empty())
+            .unwrap()
+            .into();
+        let response =
+            trace_sampling_endpoint(request, trace_sampling.clone(), Duration::from_secs(3600))
+                .await
+                .unwrap()
+                .response;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_overrides_the_trace_sampling() {
+        let trace_sampling = Arc::new(TraceSampling::default());
+
+        assert_eq!(
+            call(&trace_sampling, Method::GET, "/trace_sampling").await,
+            (StatusCode::OK, json!({ "ratio": null }))
+        );
+        let (status, body) = call(
+            &trace_sampling,
+            Method::PUT,
+            "/trace_sampling?duration=10m&reason=incident",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ratio"], 1.0);
+        assert_eq!(trace_sampling.ratio(), Some(1.0));
+
+        // Overrides never last longer than the maximum duration
+        let (_, body) = call(&trace_sampling, Method::PUT, "/trace_sampling?ratio=0.5").await;
+        assert_eq!(body["ratio"], 0.5);
+        assert!(body["remaining"].as_str().unwrap().starts_with("59m"));
+
+        assert_eq!(
+            call(&trace_sampling, Method::PUT, "/trace_sampling?ratio=2")
+                .await
+                .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            call(
+                &trace_sampling,
+                Method::PUT,
+                "/trace_sampling?duration=soon"
+            )
+            .await
+            .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            call(&trace_sampling, Method::DELETE, "/trace_sampling").await,
+            (StatusCode::OK, json!({ "ratio": null }))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_reverts_expired_overrides() {
+        let trace_sampling = Arc::new(TraceSampling::default());
+        call(
+            &trace_sampling,
+            Method::PUT,
+            "/trace_sampling?duration=50ms",
+        )
+        .await;
+        assert_eq!(trace_sampling.ratio(), Some(1.0));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(trace_sampling.ratio(), None);
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_overrides_changed_before_the_expiration() {
+        let trace_sampling = Arc::new(TraceSampling::default());
+        trace_sampling.set(1.0, Duration::from_millis(50));
+        trace_sampling.set(0.5, Duration::from_secs(3600));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(trace_sampling.ratio(), Some(0.5));
+    }
+}
@@ -0,0 +1,678 @@
+//! Circuit breaking: when too many requests to a subgraph fail, the circuit opens and requests
+//! are answered with a fallback without reaching the subgraph. Once the circuit has been open for
+//! a while, a few probe requests are let through, and the circuit closes again if they succeed.
+use std::error;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use super::concurrency_limit::ConcurrencyLimited;
+use crate::cache::key::cache_key_extension;
+use crate::configuration::CacheKeyConfig;
+use crate::error::ConfigurationError;
+use crate::graphql;
+use crate::query_planner::OperationKind;
+use crate::services::subgraph;
+
+const DEFAULT_ERROR_RATE: f64 = 0.5;
+const DEFAULT_MIN_REQUESTS: u32 = 20;
+const DEFAULT_WINDOW: Duration = Duration::from_secs(10);
+const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+const DEFAULT_HALF_OPEN_REQUESTS: u32 = 3;
+/// Number of query responses kept for each subgraph by the `cached` fallback
+const FALLBACK_CACHE_SIZE: usize = 512;
+/// The dimensions of the keys of the `cached` fallback that identify the user: the headers
+/// carrying their credentials, the scopes of their JWT and the cache key context entry
+static USER_CACHE_KEY: Lazy<CacheKeyConfig> = Lazy::new(|| CacheKeyConfig {
+    request_headers: vec![
+        http::header::AUTHORIZATION.to_string(),
+        http::header::PROXY_AUTHORIZATION.to_string(),
+        http::header::COOKIE.to_string(),
+    ],
+    scopes: true,
+});
+
+/// Circuit breaker configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct CircuitBreakerConfig {
+    /// Proportion of failed requests over a window above which the circuit opens, between 0
+    /// and 1 (default: 0.5). A request fails when it cannot be sent, when the subgraph answers
+    /// with a 5xx status code, or when it is slower than `slow_request_duration`
+    error_rate: f64,
+    /// Minimum number of requests over a window before the circuit can open (default: 20)
+    min_requests: u32,
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// Duration of the window over which the error rate is computed (default: 10s)
+    window: Duration,
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// Requests slower than this duration count as failed (disabled by default)
+    slow_request_duration: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// How long the circuit stays open before probe requests are sent (default: 30s)
+    open_duration: Duration,
+    /// Number of probe requests let through when the circuit is half open. The circuit closes
+    /// once they all succeed, and opens again as soon as one fails (default: 3)
+    half_open_requests: u32,
+    /// What requests receive while the circuit is open (default: error)
+    fallback: CircuitBreakerFallback,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            error_rate: DEFAULT_ERROR_RATE,
+            min_requests: DEFAULT_MIN_REQUESTS,
+            window: DEFAULT_WINDOW,
+            slow_request_duration: None,
+            open_duration: DEFAULT_OPEN_DURATION,
+            half_open_requests: DEFAULT_HALF_OPEN_REQUESTS,
+            fallback: CircuitBreakerFallback::default(),
+        }
+    }
+}
+
+/// What requests receive while the circuit is open
+#[derive(PartialEq, Default, Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CircuitBreakerFallback {
+    /// A `SUBGRAPH_CIRCUIT_OPEN` error
+    #[default]
+    Error,
+    /// The last successful response to the same query, or a `SUBGRAPH_CIRCUIT_OPEN` error if
+    /// there is none
+    Cached,
+}
+
+impl CircuitBreakerConfig {
+    pub(crate) fn validate(&self) -> Result<(), ConfigurationError> {
+        let invalid = |error: &str| ConfigurationError::InvalidConfiguration {
+            message: "bad configuration for traffic_shaping plugin",
+            error: error.to_string(),
+        };
+        if !(0.0..=1.0).contains(&self.error_rate) {
+            return Err(invalid(
+                "the circuit breaker error_rate must be between 0 and 1",
+            ));
+        }
+        if self.half_open_requests == 0 {
+            return Err(invalid(
+                "the circuit breaker half_open_requests must be greater than 0",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The error of the requests rejected while the circuit of their subgraph is open
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct CircuitOpen;
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("the circuit breaker of the subgraph is open")
+    }
+}
+
+impl error::Error for CircuitOpen {}
+
+#[derive(Clone)]
+pub(crate) struct CircuitBreakerLayer {
+    state: Arc<CircuitBreakerState>,
+}
+
+impl CircuitBreakerLayer {
+    /// The configuration must have been validated
+    pub(crate) fn new(config: &CircuitBreakerConfig, subgraph_name: String) -> Self {
+        Self {
+            state: Arc::new(CircuitBreakerState {
+                config: config.clone(),
+                circuit: Mutex::new(Circuit::closed(Instant::now())),
+                responses: (config.fallback == CircuitBreakerFallback::Cached).then(|| {
+                    Mutex::new(LruCache::new(
+                        NonZeroUsize::new(FALLBACK_CACHE_SIZE).expect("cache size is not zero"),
+                    ))
+                }),
+                subgraph_name,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for CircuitBreakerLayer {
+    type Service = CircuitBreaker<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CircuitBreaker {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Circuit {
+    Closed {
+        window_start: Instant,
+        requests: u32,
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        probes: u32,
+        successes: u32,
+    },
+}
+
+impl Circuit {
+    fn closed(now: Instant) -> Self {
+        Circuit::Closed {
+            window_start: now,
+            requests: 0,
+            failures: 0,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Circuit::Closed { .. } => "closed",
+            Circuit::Open { .. } => "open",
+            Circuit::HalfOpen { .. } => "half_open",
+        }
+    }
+}
+
+/// Why a request was let through
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Permit {
+    Closed,
+    Probe,
+}
+
+struct CircuitBreakerState {
+    config: CircuitBreakerConfig,
+    circuit: Mutex<Circuit>,
+    /// The last successful responses to queries, by hash of their request body and of the
+    /// inputs that identify the user
+    responses: Option<Mutex<LruCache<String, graphql::Response>>>,
+    subgraph_name: String,
+}
+
+impl CircuitBreakerState {
+    /// Whether a request can be sent to the subgraph
+    fn acquire(&self, now: Instant) -> Option<Permit> {
+        let mut circuit = self.circuit.lock().expect("lock poisoned");
+        if let Circuit::Open { until } = *circuit {
+            if now < until {
+                return None;
+            }
+            self.transition(
+                &mut circuit,
+                Circuit::HalfOpen {
+                    probes: 0,
+                    successes: 0,
+                },
+            );
+        }
+        match &mut *circuit {
+            Circuit::Closed { .. } => Some(Permit::Closed),
+            Circuit::HalfOpen { probes, .. } if *probes < self.config.half_open_requests => {
+                *probes += 1;
+                Some(Permit::Probe)
+            }
+            _ => None,
+        }
+    }
+
+    fn record(&self, permit: Permit, success: bool, now: Instant) {
+        let mut circuit = self.circuit.lock().expect("lock poisoned");
+        let next = match (&mut *circuit, permit) {
+            (
+                Circuit::Closed {
+                    window_start,
+                    requests,
+                    failures,
+                },
+                Permit::Closed,
+            ) => {
+                if now.duration_since(*window_start) >= self.config.window {
+                    *window_start = now;
+                    *requests = 0;
+                    *failures = 0;
+                }
+                *requests += 1;
+                if !success {
+                    *failures += 1;
+                }
+                let error_rate = f64::from(*failures) / f64::from(*requests);
+                if *failures > 0
+                    && *requests >= self.config.min_requests
+                    && error_rate >= self.config.error_rate
+                {
+                    Some(Circuit::Open {
+                        until: now + self.config.open_duration,
+                    })
+                } else {
+                    None
+                }
+            }
+            (Circuit::HalfOpen { successes, .. }, Permit::Probe) => {
+                if success {
+                    *successes += 1;
+                    if *successes >= self.config.half_open_requests {
+                        Some(Circuit::closed(now))
+                    } else {
+                        None
+                    }
+                } else {
+                    Some(Circuit::Open {
+                        until: now + self.config.open_duration,
+                    })
+                }
+            }
+            // The circuit changed while the request was in flight
+            _ => None,
+        };
+        if let Some(next) = next {
+            self.transition(&mut circuit, next);
+        }
+    }
+
+    /// Gives back a permit whose request never reached the subgraph
+    fn release(&self, permit: Permit) {
+        let mut circuit = self.circuit.lock().expect("lock poisoned");
+        if let (Circuit::HalfOpen { probes, .. }, Permit::Probe) = (&mut *circuit, permit) {
+            *probes = probes.saturating_sub(1);
+        }
+    }
+
+    fn transition(&self, circuit: &mut Circuit, next: Circuit) {
+        let from = circuit.name();
+        let to = next.name();
+        *circuit = next;
+        tracing::info!(
+            monotonic_counter.apollo_router_subgraph_circuit_breaker_transitions_total = 1u64,
+            subgraph = %self.subgraph_name,
+            from = from,
+            to = to,
+        );
+        tracing::warn!(
+            subgraph = %self.subgraph_name,
+            "subgraph circuit breaker changed from {} to {}",
+            from,
+            to
+        );
+    }
+
+    /// The response to a request rejected while the circuit is open
+    fn fallback(
+        &self,
+        cache_key: Option<String>,
+        context: crate::Context,
+    ) -> Result<subgraph::Response, BoxError> {
+        let cached = cache_key.and_then(|key| {
+            self.responses
+                .as_ref()?
+                .lock()
+                .expect("lock poisoned")
+                .get(&key)
+                .cloned()
+        });
+        tracing::info!(
+            monotonic_counter.apollo_router_subgraph_circuit_breaker_rejections_total = 1u64,
+            subgraph = %self.subgraph_name,
+            fallback = if cached.is_some() { "cached" } else { "error" },
+        );
+        match cached {
+            Some(response) => Ok(subgraph::Response::new_from_response(
+                http::Response::new(response),
+                context,
+            )),
+            None => Err(CircuitOpen.into()),
+        }
+    }
+}
+
+/// A request let through the circuit breaker. It is recorded as failed if it is dropped before
+/// completing, for example when it times out
+struct Call {
+    state: Arc<CircuitBreakerState>,
+    permit: Permit,
+    start: Instant,
+    completed: bool,
+}
+
+impl Call {
+    fn new(state: Arc<CircuitBreakerState>, permit: Permit) -> Self {
+        Self {
+            state,
+            permit,
+            start: Instant::now(),
+            completed: false,
+        }
+    }
+
+    fn complete(mut self, success: bool) {
+        self.completed = true;
+        let now = Instant::now();
+        let slow = self
+            .state
+            .config
+            .slow_request_duration
+            .is_some_and(|duration| now.duration_since(self.start) > duration);
+        self.state.record(self.permit, success && !slow, now);
+    }
+
+    fn release(mut self) {
+        self.completed = true;
+        self.state.release(self.permit);
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.state.record(self.permit, false, Instant::now());
+        }
+    }
+}
+
+/// The key of the response to a query in the cache of the `cached` fallback. Like the keys of
+/// the entity cache, it includes the authorization status of the request, so that a response is
+/// never served to another user
+fn cache_key(req: &subgraph::Request) -> String {
+    let mut digest = Sha256::new();
+    digest.update(
+        serde_json::to_vec(req.subgraph_request.body()).expect("serialization should not fail"),
+    );
+    digest.update(&[0u8; 1][..]);
+    digest.update(
+        serde_json::to_vec(req.authorization.as_ref()).expect("serialization should not fail"),
+    );
+    if let Some(extension) = cache_key_extension(
+        &USER_CACHE_KEY,
+        req.subgraph_request.headers(),
+        &req.context,
+    ) {
+        digest.update(&[0u8; 1][..]);
+        digest.update(extension.as_bytes());
+    }
+    hex::encode(digest.finalize())
+}
+
+/// Stops sending requests to a subgraph while too many of them fail
+#[derive(Clone)]
+pub(crate) struct CircuitBreaker<S> {
+    inner: S,
+    state: Arc<CircuitBreakerState>,
+}
+
+impl<S> Service<subgraph::Request> for CircuitBreaker<S>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    <S as Service<subgraph::Request>>::Future: Send,
+{
+    type Response = subgraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<subgraph::Response, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Every request is sent through a clone of the inner service, see `call`
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: subgraph::Request) -> Self::Future {
+        let service = self.inner.clone();
+        let state = self.state.clone();
+        // Only the responses to queries are kept for the fallback
+        let cache_key = (state.responses.is_some() && req.operation_kind == OperationKind::Query)
+            .then(|| cache_key(&req));
+
+        Box::pin(async move {
+            let Some(permit) = state.acquire(Instant::now()) else {
+                return state.fallback(cache_key, req.context);
+            };
+            let call = Call::new(state.clone(), permit);
+            let result = service.oneshot(req).await;
+            // Requests rejected by the concurrency limit tell nothing about the subgraph health
+            if matches!(&result, Err(e) if e.is::<ConcurrencyLimited>()) {
+                call.release();
+                return result;
+            }
+            let success = match &result {
+                Ok(response) => !response.response.status().is_server_error(),
+                Err(_) => false,
+            };
+            call.complete(success);
+
+            if let (Some(key), Some(responses), Ok(response)) =
+                (cache_key, &state.responses, &result)
+            {
+                if success && response.response.body().errors.is_empty() {
+                    responses
+                        .lock()
+                        .expect("lock poisoned")
+                        .put(key, response.response.body().clone());
+                }
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn layer(config: serde_json::Value) -> CircuitBreakerLayer {
+        let config: CircuitBreakerConfig = serde_json::from_value(config).unwrap();
+        config.validate().unwrap();
+        CircuitBreakerLayer::new(&config, "products".to_string())
+    }
+
+    #[test]
+    fn it_opens_on_errors_and_closes_after_probing() {
+        let state = layer(serde_json::json!({
+            "error_rate": 0.5,
+            "min_requests": 4,
+            "open_duration": "10s",
+            "half_open_requests": 2
+        }))
+        .state;
+        let now = Instant::now();
+        for success in [false, false, true] {
+            let permit = state.acquire(now).unwrap();
+            state.record(permit, success, now);
+        }
+        // Not enough requests yet
+        assert!(matches!(
+            *state.circuit.lock().unwrap(),
+            Circuit::Closed { .. }
+        ));
+        let permit = state.acquire(now).unwrap();
+        state.record(permit, true, now);
+        assert!(matches!(
+            *state.circuit.lock().unwrap(),
+            Circuit::Open { .. }
+        ));
+        assert_eq!(state.acquire(now), None);
+
+        // Half open: only two probes are let through
+        let later = now + Duration::from_secs(10);
+        let first = state.acquire(later).unwrap();
+        let second = state.acquire(later).unwrap();
+        assert_eq!((first, second), (Permit::Probe, Permit::Probe));
+        assert_eq!(state.acquire(later), None);
+        state.record(first, true, later);
+        state.record(second, true, later);
+        assert_eq!(state.acquire(later), Some(Permit::Closed));
+    }
+
+    #[test]
+    fn it_opens_again_when_a_probe_fails() {
+        let state = layer(serde_json::json!({ "min_requests": 1, "open_duration": "10s" })).state;
+        let now = Instant::now();
+        let permit = state.acquire(now).unwrap();
+        state.record(permit, false, now);
+        assert_eq!(state.acquire(now), None);
+
+        let later = now + Duration::from_secs(10);
+        let probe = state.acquire(later).unwrap();
+        // A request sent before the circuit opened does not count
+        state.record(Permit::Closed, true, later);
+        state.record(probe, false, later);
+        assert_eq!(state.acquire(later), None);
+    }
+
+    #[test]
+    fn it_gives_back_the_probes_of_requests_not_sent() {
+        let state = layer(serde_json::json!({ "min_requests": 1, "half_open_requests": 1 })).state;
+        let now = Instant::now();
+        let permit = state.acquire(now).unwrap();
+        state.record(permit, false, now);
+
+        let later = now + DEFAULT_OPEN_DURATION;
+        let probe = state.acquire(later).unwrap();
+        assert_eq!(state.acquire(later), None);
+        state.release(probe);
+        assert_eq!(state.acquire(later), Some(Permit::Probe));
+    }
+
+    #[test]
+    fn it_computes_the_error_rate_over_a_window() {
+        let state = layer(serde_json::json!({ "min_requests": 2, "window": "1s" })).state;
+        let now = Instant::now();
+        let permit = state.acquire(now).unwrap();
+        state.record(permit, false, now);
+        let later = now + Duration::from_secs(1);
+        for _ in 0..2 {
+            let permit = state.acquire(later).unwrap();
+            state.record(permit, true, later);
+        }
+        assert_eq!(state.acquire(later), Some(Permit::Closed));
+    }
+
+    #[tokio::test]
+    async fn it_serves_cached_responses_while_open() {
+        let layer = layer(serde_json::json!({ "min_requests": 1, "fallback": "cached" }));
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut service = layer.layer(tower::service_fn({
+            let fail = fail.clone();
+            move |req: subgraph::Request| {
+                let fail = fail.load(std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    if fail {
+                        Err::<_, BoxError>("subgraph error".into())
+                    } else {
+                        Ok(subgraph::Response::fake_builder()
+                            .data(serde_json_bytes::json!({ "me": "cached" }))
+                            .context(req.context)
+                            .build())
+                    }
+                }
+            }
+        }));
+        let request = |query: &str| {
+            subgraph::Request::fake_builder()
+                .subgraph_request(
+                    http::Request::builder()
+                        .body(graphql::Request::fake_builder().query(query).build())
+                        .unwrap(),
+                )
+                .build()
+        };
+
+        assert!(service.call(request("{ me }")).await.is_ok());
+        fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(service.call(request("{ other }")).await.is_err());
+
+        // The circuit is open
+        let response = service.call(request("{ me }")).await.unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(serde_json_bytes::json!({ "me": "cached" }))
+        );
+        let error = service.call(request("{ other }")).await.unwrap_err();
+        assert!(error.is::<CircuitOpen>());
+    }
+
+    #[tokio::test]
+    async fn it_does_not_share_cached_responses_between_users() {
+        let layer = layer(serde_json::json!({ "min_requests": 1, "fallback": "cached" }));
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut service = layer.layer(tower::service_fn({
+            let fail = fail.clone();
+            move |req: subgraph::Request| {
+                let fail = fail.load(std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    if fail {
+                        Err::<_, BoxError>("subgraph error".into())
+                    } else {
+                        Ok(subgraph::Response::fake_builder()
+                            .data(serde_json_bytes::json!({ "me": "alice" }))
+                            .context(req.context)
+                            .build())
+                    }
+                }
+            }
+        }));
+        let request = |authorization: &str| {
+            subgraph::Request::fake_builder()
+                .subgraph_request(
+                    http::Request::builder()
+                        .header(http::header::AUTHORIZATION, authorization)
+                        .body(graphql::Request::fake_builder().query("{ me }").build())
+                        .unwrap(),
+                )
+                .build()
+        };
+
+        assert!(service.call(request("Bearer alice")).await.is_ok());
+        fail.store(true, std::sync::atomic::Ordering::SeqCst);
+        assert!(service.call(request("Bearer alice")).await.is_err());
+
+        // The circuit is open
+        let response = service.call(request("Bearer alice")).await.unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(serde_json_bytes::json!({ "me": "alice" }))
+        );
+        let error = service.call(request("Bearer bob")).await.unwrap_err();
+        assert!(error.is::<CircuitOpen>());
+    }
+
+    #[test]
+    fn it_rejects_invalid_configurations() {
+        let config: CircuitBreakerConfig =
+            serde_json::from_value(serde_json::json!({ "error_rate": 2.0 })).unwrap();
+        assert!(config.validate().is_err());
+        let config: CircuitBreakerConfig =
+            serde_json::from_value(serde_json::json!({ "half_open_requests": 0 })).unwrap();
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,589 @@
+//! Adaptive concurrency limiting: the number of requests in flight to a subgraph is limited, and
+//! the limit is continuously adjusted from the observed latency, so that it follows the capacity
+//! of the subgraph instead of being set once for both quiet and peak hours. Requests over the
+//! limit are rejected right away instead of queuing up.
+use std::error;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use super::circuit_breaker::CircuitOpen;
+use super::Merge;
+use super::RateLimited;
+use crate::error::ConfigurationError;
+use crate::services::subgraph;
+
+const DEFAULT_INITIAL_LIMIT: u32 = 20;
+const DEFAULT_MIN_LIMIT: u32 = 1;
+const DEFAULT_MAX_LIMIT: u32 = 1000;
+const DEFAULT_BACKOFF_RATIO: f64 = 0.9;
+/// Number of latencies averaged by the gradient algorithm before each update of the limit
+const GRADIENT_WINDOW: u32 = 10;
+/// Number of windows over which the gradient algorithm averages the long term latency
+const GRADIENT_LONG_WINDOWS: f64 = 600.0;
+/// How much the recent latency can exceed the long term latency before the limit shrinks
+const GRADIENT_TOLERANCE: f64 = 1.5;
+/// Weight of each update of the gradient algorithm, so that the limit does not jump around
+const GRADIENT_SMOOTHING: f64 = 0.2;
+/// Lower bound of the latencies, in seconds, so that the gradient is always defined
+const MIN_LATENCY: f64 = 1e-6;
+
+/// Adaptive concurrency limit configuration
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ConcurrencyLimitConfig {
+    /// How the limit is adjusted (default: gradient)
+    algorithm: Option<ConcurrencyLimitAlgorithm>,
+    /// Limit before the first requests are observed (default: 20)
+    initial_limit: Option<u32>,
+    /// Lowest value of the limit (default: 1)
+    min_limit: Option<u32>,
+    /// Highest value of the limit (default: 1000)
+    max_limit: Option<u32>,
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// With `aimd`, requests slower than this duration count as dropped (disabled by default)
+    slow_request_duration: Option<Duration>,
+    /// With `aimd`, factor applied to the limit when a request is dropped, between 0 and 1
+    /// (default: 0.9)
+    backoff_ratio: Option<f64>,
+}
+
+impl Merge for ConcurrencyLimitConfig {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => ConcurrencyLimitConfig {
+                algorithm: self.algorithm.or(fallback.algorithm),
+                initial_limit: self.initial_limit.or(fallback.initial_limit),
+                min_limit: self.min_limit.or(fallback.min_limit),
+                max_limit: self.max_limit.or(fallback.max_limit),
+                slow_request_duration: self
+                    .slow_request_duration
+                    .or(fallback.slow_request_duration),
+                backoff_ratio: self.backoff_ratio.or(fallback.backoff_ratio),
+            },
+        }
+    }
+}
+
+/// The concurrency limit configuration, with the defaults applied
+#[derive(Debug, Clone)]
+struct ConcurrencyLimitSettings {
+    algorithm: ConcurrencyLimitAlgorithm,
+    initial_limit: u32,
+    min_limit: u32,
+    max_limit: u32,
+    slow_request_duration: Option<Duration>,
+    backoff_ratio: f64,
+}
+
+/// How the concurrency limit is adjusted
+#[derive(PartialEq, Default, Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ConcurrencyLimitAlgorithm {
+    /// The limit follows the ratio between the long term and the recent latency: it grows while
+    /// the latency is stable, and shrinks as soon as requests start queuing up in the subgraph
+    #[default]
+    Gradient,
+    /// Additive increase, multiplicative decrease: the limit grows by one with each successful
+    /// request, and is multiplied by `backoff_ratio` with each dropped request
+    Aimd,
+}
+
+impl ConcurrencyLimitConfig {
+    fn settings(&self) -> ConcurrencyLimitSettings {
+        ConcurrencyLimitSettings {
+            algorithm: self.algorithm.unwrap_or_default(),
+            initial_limit: self.initial_limit.unwrap_or(DEFAULT_INITIAL_LIMIT),
+            min_limit: self.min_limit.unwrap_or(DEFAULT_MIN_LIMIT),
+            max_limit: self.max_limit.unwrap_or(DEFAULT_MAX_LIMIT),
+            slow_request_duration: self.slow_request_duration,
+            backoff_ratio: self.backoff_ratio.unwrap_or(DEFAULT_BACKOFF_RATIO),
+        }
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), ConfigurationError> {
+        let settings = self.settings();
+        let invalid = |error: &str| ConfigurationError::InvalidConfiguration {
+            message: "bad configuration for traffic_shaping plugin",
+            error: error.to_string(),
+        };
+        if settings.min_limit == 0 {
+            return Err(invalid(
+                "the concurrency limit min_limit must be greater than 0",
+            ));
+        }
+        if !(settings.min_limit..=settings.max_limit).contains(&settings.initial_limit) {
+            return Err(invalid(
+                "the concurrency limit initial_limit must be between min_limit and max_limit",
+            ));
+        }
+        if settings.backoff_ratio <= 0.0 || settings.backoff_ratio >= 1.0 {
+            return Err(invalid(
+                "the concurrency limit backoff_ratio must be between 0 and 1",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The error of the requests rejected because the concurrency limit of their subgraph is reached
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ConcurrencyLimited;
+
+impl fmt::Display for ConcurrencyLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("the concurrency limit of the subgraph is reached")
+    }
+}
+
+impl error::Error for ConcurrencyLimited {}
+
+#[derive(Clone)]
+pub(crate) struct ConcurrencyLimitLayer {
+    state: Arc<ConcurrencyLimitState>,
+}
+
+impl ConcurrencyLimitLayer {
+    /// The configuration must have been validated
+    pub(crate) fn new(config: &ConcurrencyLimitConfig, subgraph_name: String) -> Self {
+        let config = config.settings();
+        tracing::info!(
+            value.apollo_router_subgraph_concurrency_limit = u64::from(config.initial_limit),
+            subgraph = %subgraph_name,
+        );
+        Self {
+            state: Arc::new(ConcurrencyLimitState {
+                limiter: Mutex::new(Limiter {
+                    limit: f64::from(config.initial_limit),
+                    in_flight: 0,
+                    long_latency: None,
+                    window_latency: 0.0,
+                    window_samples: 0,
+                    window_max_in_flight: 0,
+                }),
+                config,
+                subgraph_name,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for ConcurrencyLimitLayer {
+    type Service = ConcurrencyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ConcurrencyLimit {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+struct Limiter {
+    /// Maximum number of requests in flight, rounded down
+    limit: f64,
+    in_flight: u32,
+    /// Gradient: average latency over the long term, in seconds
+    long_latency: Option<f64>,
+    /// Gradient: sum of the latencies of the current window, in seconds
+    window_latency: f64,
+    window_samples: u32,
+    /// Gradient: highest number of requests in flight in the current window
+    window_max_in_flight: u32,
+}
+
+impl Limiter {
+    fn limit(&self) -> u32 {
+        self.limit as u32
+    }
+
+    fn aimd(&mut self, in_flight: u32, dropped: bool, config: &ConcurrencyLimitSettings) {
+        if dropped {
+            self.limit = (self.limit * config.backoff_ratio).max(f64::from(config.min_limit));
+        } else if in_flight * 2 >= self.limit() {
+            // The limit only grows when it is actually used
+            self.limit = (self.limit + 1.0).min(f64::from(config.max_limit));
+        }
+    }
+
+    fn gradient(&mut self, in_flight: u32, latency: Duration, config: &ConcurrencyLimitSettings) {
+        self.window_latency += latency.as_secs_f64();
+        self.window_samples += 1;
+        self.window_max_in_flight = self.window_max_in_flight.max(in_flight);
+        if self.window_samples < GRADIENT_WINDOW {
+            return;
+        }
+        let short = (self.window_latency / f64::from(self.window_samples)).max(MIN_LATENCY);
+        let max_in_flight = self.window_max_in_flight;
+        self.window_latency = 0.0;
+        self.window_samples = 0;
+        self.window_max_in_flight = 0;
+
+        let long = self.long_latency.get_or_insert(short);
+        *long += (short - *long) / GRADIENT_LONG_WINDOWS;
+        // After a long period of high latency, the long term latency catches up faster
+        if *long / short > 2.0 {
+            *long *= 0.95;
+        }
+        let long = *long;
+
+        // The limit does not change while the subgraph receives too few requests to reach it
+        if f64::from(max_in_flight) < self.limit / 2.0 {
+            return;
+        }
+        let gradient = (GRADIENT_TOLERANCE * long / short).clamp(0.5, 1.0);
+        let target = self.limit * gradient + self.limit.sqrt();
+        self.limit = (self.limit * (1.0 - GRADIENT_SMOOTHING) + target * GRADIENT_SMOOTHING)
+            .clamp(f64::from(config.min_limit), f64::from(config.max_limit));
+    }
+}
+
+/// How a request let through the concurrency limit ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    /// The subgraph responded successfully
+    Success,
+    /// The subgraph responded with an error, or could not be reached
+    Failure,
+    /// The request was dropped before the subgraph responded, for example when it timed out
+    Cancelled,
+}
+
+struct ConcurrencyLimitState {
+    config: ConcurrencyLimitSettings,
+    limiter: Mutex<Limiter>,
+    subgraph_name: String,
+}
+
+impl ConcurrencyLimitState {
+    /// The number of requests in flight including this one, if it can be sent to the subgraph
+    fn acquire(&self) -> Option<u32> {
+        let mut limiter = self.limiter.lock().expect("lock poisoned");
+        if limiter.in_flight >= limiter.limit() {
+            return None;
+        }
+        limiter.in_flight += 1;
+        Some(limiter.in_flight)
+    }
+
+    /// Frees the place of a request that tells nothing about the subgraph
+    fn release(&self) {
+        let mut limiter = self.limiter.lock().expect("lock poisoned");
+        limiter.in_flight = limiter.in_flight.saturating_sub(1);
+    }
+
+    fn record(&self, in_flight: u32, latency: Duration, outcome: Outcome) {
+        let mut limiter = self.limiter.lock().expect("lock poisoned");
+        limiter.in_flight = limiter.in_flight.saturating_sub(1);
+        let previous = limiter.limit();
+        match self.config.algorithm {
+            ConcurrencyLimitAlgorithm::Aimd => {
+                let slow = self
+                    .config
+                    .slow_request_duration
+                    .is_some_and(|duration| latency > duration);
+                let dropped = outcome != Outcome::Success || slow;
+                limiter.aimd(in_flight, dropped, &self.config)
+            }
+            // The latency of the failed requests says nothing about the load of the subgraph, but
+            // a cancelled request took at least as long as it ran, so that timeouts are not missed
+            ConcurrencyLimitAlgorithm::Gradient if outcome != Outcome::Failure => {
+                limiter.gradient(in_flight, latency, &self.config)
+            }
+            ConcurrencyLimitAlgorithm::Gradient => {}
+        }
+        let limit = limiter.limit();
+        if limit != previous {
+            tracing::info!(
+                value.apollo_router_subgraph_concurrency_limit = u64::from(limit),
+                subgraph = %self.subgraph_name,
+            );
+        }
+    }
+}
+
+/// A request let through the concurrency limit. It is recorded as cancelled if it is dropped
+/// before completing, for example when it times out
+struct Call {
+    state: Arc<ConcurrencyLimitState>,
+    in_flight: u32,
+    start: Instant,
+    completed: bool,
+}
+
+impl Call {
+    fn new(state: Arc<ConcurrencyLimitState>, in_flight: u32) -> Self {
+        Self {
+            state,
+            in_flight,
+            start: Instant::now(),
+            completed: false,
+        }
+    }
+
+    fn complete(mut self, outcome: Outcome) {
+        self.completed = true;
+        self.state
+            .record(self.in_flight, self.start.elapsed(), outcome);
+    }
+
+    fn release(mut self) {
+        self.completed = true;
+        self.state.release();
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.state
+                .record(self.in_flight, self.start.elapsed(), Outcome::Cancelled);
+        }
+    }
+}
+
+/// Limits the number of requests in flight to a subgraph, with a limit adjusted to its latency
+#[derive(Clone)]
+pub(crate) struct ConcurrencyLimit<S> {
+    inner: S,
+    state: Arc<ConcurrencyLimitState>,
+}
+
+impl<S> Service<subgraph::Request> for ConcurrencyLimit<S>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    <S as Service<subgraph::Request>>::Future: Send,
+{
+    type Response = subgraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<subgraph::Response, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Every request is sent through a clone of the inner service, see `call`
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: subgraph::Request) -> Self::Future {
+        let service = self.inner.clone();
+        let state = self.state.clone();
+
+        Box::pin(async move {
+            let Some(in_flight) = state.acquire() else {
+                tracing::info!(
+                    monotonic_counter.apollo_router_subgraph_concurrency_limit_rejections_total =
+                        1u64,
+                    subgraph = %state.subgraph_name,
+                );
+                return Err(ConcurrencyLimited.into());
+            };
+            let call = Call::new(state.clone(), in_flight);
+            let result = service.oneshot(req).await;
+            // Requests rejected by the other traffic shaping layers never reached the subgraph
+            if matches!(&result, Err(e) if e.is::<RateLimited>() || e.is::<CircuitOpen>()) {
+                call.release();
+                return result;
+            }
+            let outcome = match &result {
+                Ok(response) if response.response.status().is_server_error() => Outcome::Failure,
+                Ok(_) => Outcome::Success,
+                Err(_) => Outcome::Failure,
+            };
+            call.complete(outcome);
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn state(config: serde_json::Value) -> Arc<ConcurrencyLimitState> {
+        let config: ConcurrencyLimitConfig = serde_json::from_value(config).unwrap();
+        config.validate().unwrap();
+        ConcurrencyLimitLayer::new(&config, "products".to_string()).state
+    }
+
+    fn limit(state: &ConcurrencyLimitState) -> u32 {
+        state.limiter.lock().unwrap().limit()
+    }
+
+    /// Sends as many requests as the limit allows, and completes them with the same latency
+    fn saturate(state: &ConcurrencyLimitState, latency: Duration, outcome: Outcome) {
+        let requests: Vec<u32> = std::iter::from_fn(|| state.acquire()).collect();
+        for in_flight in requests.into_iter().rev() {
+            state.record(in_flight, latency, outcome);
+        }
+    }
+
+    #[test]
+    fn it_rejects_requests_over_the_limit() {
+        let state = state(serde_json::json!({ "initial_limit": 2 }));
+        assert_eq!(state.acquire(), Some(1));
+        assert_eq!(state.acquire(), Some(2));
+        assert_eq!(state.acquire(), None);
+        state.record(2, Duration::from_millis(10), Outcome::Success);
+        assert_eq!(state.acquire(), Some(2));
+    }
+
+    #[test]
+    fn it_adjusts_the_limit_with_aimd() {
+        let state = state(serde_json::json!({
+            "algorithm": "aimd",
+            "initial_limit": 10,
+            "min_limit": 2,
+            "max_limit": 12
+        }));
+        // The limit does not grow while few requests are in flight
+        state.record(
+            state.acquire().unwrap(),
+            Duration::from_millis(10),
+            Outcome::Success,
+        );
+        assert_eq!(limit(&state), 10);
+
+        saturate(&state, Duration::from_millis(10), Outcome::Success);
+        assert_eq!(limit(&state), 12);
+
+        // 12 * 0.9^12
+        saturate(&state, Duration::from_millis(10), Outcome::Failure);
+        assert_eq!(limit(&state), 3);
+        saturate(&state, Duration::from_millis(10), Outcome::Failure);
+        assert_eq!(limit(&state), 2);
+    }
+
+    #[test]
+    fn it_adjusts_the_limit_with_the_gradient() {
+        let state = state(serde_json::json!({ "initial_limit": 20 }));
+        for _ in 0..10 {
+            saturate(&state, Duration::from_millis(10), Outcome::Success);
+        }
+        let grown = limit(&state);
+        assert!(grown > 20, "{grown}");
+
+        // Requests queue up in the subgraph
+        for _ in 0..10 {
+            saturate(&state, Duration::from_millis(100), Outcome::Success);
+        }
+        let shrunk = limit(&state);
+        assert!(shrunk < grown / 2, "{shrunk}");
+
+        // Failed requests are ignored
+        saturate(&state, Duration::from_millis(10), Outcome::Failure);
+        assert_eq!(limit(&state), shrunk);
+    }
+
+    #[test]
+    fn it_adjusts_the_gradient_to_timed_out_requests() {
+        let state = state(serde_json::json!({ "initial_limit": 20 }));
+        for _ in 0..10 {
+            saturate(&state, Duration::from_millis(10), Outcome::Success);
+        }
+        let grown = limit(&state);
+
+        // The requests time out, dropping their calls before the subgraph responds
+        for _ in 0..10 {
+            let calls: Vec<Call> = std::iter::from_fn(|| state.acquire())
+                .map(|in_flight| {
+                    let mut call = Call::new(state.clone(), in_flight);
+                    call.start -= Duration::from_millis(100);
+                    call
+                })
+                .collect();
+            drop(calls);
+        }
+        let shrunk = limit(&state);
+        assert!(shrunk < grown / 2, "{shrunk}");
+        assert_eq!(state.limiter.lock().unwrap().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn it_limits_the_requests_in_flight() {
+        let layer = ConcurrencyLimitLayer::new(
+            &serde_json::from_value(serde_json::json!({ "initial_limit": 1 })).unwrap(),
+            "products".to_string(),
+        );
+        let service = layer.layer(tower::service_fn(|req: subgraph::Request| async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(subgraph::Response::fake_builder()
+                .context(req.context)
+                .build())
+        }));
+
+        let first = tokio::spawn(
+            service
+                .clone()
+                .oneshot(subgraph::Request::fake_builder().build()),
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let error = service
+            .clone()
+            .oneshot(subgraph::Request::fake_builder().build())
+            .await
+            .unwrap_err();
+        assert!(error.is::<ConcurrencyLimited>());
+
+        assert!(first.await.unwrap().is_ok());
+        assert!(service
+            .oneshot(subgraph::Request::fake_builder().build())
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn it_ignores_rate_limited_requests() {
+        let layer = ConcurrencyLimitLayer::new(
+            &serde_json::from_value(serde_json::json!({
+                "algorithm": "aimd",
+                "initial_limit": 10
+            }))
+            .unwrap(),
+            "products".to_string(),
+        );
+        let state = layer.state.clone();
+        let service = layer.layer(tower::service_fn(|_: subgraph::Request| async {
+            Err::<subgraph::Response, BoxError>(RateLimited::new().into())
+        }));
+
+        for _ in 0..20 {
+            let error = service
+                .clone()
+                .oneshot(subgraph::Request::fake_builder().build())
+                .await
+                .unwrap_err();
+            assert!(error.is::<RateLimited>());
+        }
+        assert_eq!(limit(&state), 10);
+        assert_eq!(state.limiter.lock().unwrap().in_flight, 0);
+    }
+
+    #[test]
+    fn it_rejects_invalid_configurations() {
+        for config in [
+            serde_json::json!({ "min_limit": 0 }),
+            serde_json::json!({ "initial_limit": 5, "min_limit": 10 }),
+            serde_json::json!({ "initial_limit": 50, "max_limit": 10 }),
+            serde_json::json!({ "backoff_ratio": 1.5 }),
+        ] {
+            let config: ConcurrencyLimitConfig = serde_json::from_value(config).unwrap();
+            assert!(config.validate().is_err());
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Request hedging: when a subgraph query takes longer than most recent queries to that
+//! subgraph, an identical request is sent and the first successful response is used.
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use tower::retry::budget::Budget;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use crate::query_planner::OperationKind;
+use crate::services::subgraph;
+
+/// Number of recent query latencies used to compute the hedging delay
+const WINDOW_SIZE: usize = 1000;
+/// The hedging delay is computed again every time this number of latencies is recorded
+const RECOMPUTE_INTERVAL: usize = 20;
+/// No request is hedged until this number of latencies has been recorded
+const MIN_SAMPLES: usize = 20;
+
+const DEFAULT_PERCENTILE: f64 = 0.95;
+const DEFAULT_MIN_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(1);
+const DEFAULT_HEDGE_PERCENT: f32 = 0.1;
+const BUDGET_TTL: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub(crate) struct HedgingLayer {
+    state: Arc<HedgingState>,
+}
+
+impl HedgingLayer {
+    pub(crate) fn new(
+        percentile: Option<f64>,
+        min_delay: Option<Duration>,
+        max_delay: Option<Duration>,
+        hedge_percent: Option<f32>,
+        subgraph_name: String,
+    ) -> Self {
+        Self {
+            state: Arc::new(HedgingState {
+                percentile: percentile.unwrap_or(DEFAULT_PERCENTILE),
+                min_delay: min_delay.unwrap_or(DEFAULT_MIN_DELAY),
+                max_delay: max_delay.unwrap_or(DEFAULT_MAX_DELAY),
+                budget: Budget::new(
+                    BUDGET_TTL,
+                    0,
+                    hedge_percent.unwrap_or(DEFAULT_HEDGE_PERCENT),
+                ),
+                latencies: Mutex::new(Latencies::default()),
+                subgraph_name,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for HedgingLayer {
+    type Service = Hedge<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Hedge {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+struct HedgingState {
+    percentile: f64,
+    min_delay: Duration,
+    max_delay: Duration,
+    budget: Budget,
+    latencies: Mutex<Latencies>,
+    subgraph_name: String,
+}
+
+#[derive(Default)]
+struct Latencies {
+    samples: VecDeque<Duration>,
+    recorded: usize,
+    delay: Option<Duration>,
+}
+
+impl HedgingState {
+    fn delay(&self) -> Option<Duration> {
+        self.latencies.lock().expect("lock poisoned").delay
+    }
+
+    fn record(&self, latency: Duration) {
+        let mut latencies = self.latencies.lock().expect("lock poisoned");
+        if latencies.samples.len() == WINDOW_SIZE {
+            latencies.samples.pop_front();
+        }
+        latencies.samples.push_back(latency);
+        latencies.recorded += 1;
+
+        if latencies.samples.len() >= MIN_SAMPLES && latencies.recorded >= RECOMPUTE_INTERVAL {
+            latencies.recorded = 0;
+            let mut sorted: Vec<Duration> = latencies.samples.iter().copied().collect();
+            sorted.sort_unstable();
+            let index = ((sorted.len() as f64 * self.percentile).ceil() as usize)
+                .clamp(1, sorted.len())
+                - 1;
+            latencies.delay = Some(sorted[index].clamp(self.min_delay, self.max_delay));
+        }
+    }
+}
+
+/// Sends a second request for slow subgraph queries, and returns the first successful response
+#[derive(Clone)]
+pub(crate) struct Hedge<S> {
+    inner: S,
+    state: Arc<HedgingState>,
+}
+
+impl<S> Service<subgraph::Request> for Hedge<S>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    <S as Service<subgraph::Request>>::Future: Send,
+{
+    type Response = subgraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<subgraph::Response, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Every request is sent through a clone of the inner service, see `call`
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: subgraph::Request) -> Self::Future {
+        let service = self.inner.clone();
+
+        // Only queries are idempotent
+        if req.operation_kind != OperationKind::Query {
+            return Box::pin(service.oneshot(req));
+        }
+
+        let state = self.state.clone();
+        state.budget.deposit();
+        let delay = state.delay();
+
+        Box::pin(async move {
+            let _latency = LatencyRecorder {
+                start: Instant::now(),
+                state: state.clone(),
+            };
+            match delay {
+                Some(delay) => hedged_call(service, req, delay, &state).await,
+                None => service.oneshot(req).await,
+            }
+        })
+    }
+}
+
+/// Records the latency of a query when it completes, or when it is cancelled:
+/// queries dropped because they are too slow must still count towards the hedging delay
+struct LatencyRecorder {
+    start: Instant,
+    state: Arc<HedgingState>,
+}
+
+impl Drop for LatencyRecorder {
+    fn drop(&mut self) {
+        self.state.record(self.start.elapsed());
+    }
+}
+
+async fn hedged_call<S>(
+    service: S,
+    req: subgraph::Request,
+    delay: Duration,
+    state: &HedgingState,
+) -> Result<subgraph::Response, BoxError>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response, Error = BoxError> + Clone,
+{
+    let hedge_request = req.clone();
+    let primary = service.clone().oneshot(req);
+    tokio::pin!(primary);
+
+    tokio::select! {
+        result = &mut primary => return result,
+        _ = tokio::time::sleep(delay) => {}
+    }
+
+    if state.budget.withdraw().is_err() {
+        tracing::info!(
+            monotonic_counter.apollo_router_http_request_hedge_total = 1u64,
+            status = "aborted",
+            subgraph = %state.subgraph_name,
+        );
+        return primary.await;
+    }
+    tracing::info!(
+        monotonic_counter.apollo_router_http_request_hedge_total = 1u64,
+        subgraph = %state.subgraph_name,
+    );
+
+    let hedge = service.oneshot(hedge_request);
+    tokio::pin!(hedge);
+
+    // If the first request to finish failed, wait for the other one
+    tokio::select! {
+        result = &mut primary => match result {
+            Ok(response) => Ok(response),
+            Err(_) => hedge_won(hedge.await, state),
+        },
+        result = &mut hedge => match result {
+            Ok(response) => hedge_won(Ok(response), state),
+            Err(_) => primary.await,
+        },
+    }
+}
+
+fn hedge_won(
+    result: Result<subgraph::Response, BoxError>,
+    state: &HedgingState,
+) -> Result<subgraph::Response, BoxError> {
+    if result.is_ok() {
+        tracing::info!(
+            monotonic_counter.apollo_router_http_request_hedge_won_total = 1u64,
+            subgraph = %state.subgraph_name,
+        );
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn it_records_the_latency_of_cancelled_queries() {
+        let layer = HedgingLayer::new(None, None, None, None, "test".to_string());
+        let service = layer.layer(tower::service_fn(|_req: subgraph::Request| async {
+            futures::future::pending::<Result<subgraph::Response, BoxError>>().await
+        }));
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            service.oneshot(subgraph::Request::fake_builder().build()),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(
+            layer
+                .state
+                .latencies
+                .lock()
+                .expect("lock poisoned")
+                .samples
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,419 @@
+//! Client-side load balancing: requests to a subgraph are spread over several routing URLs,
+//! and URLs that keep failing are ejected for a while.
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use http::Uri;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+use tower::ServiceExt;
+
+use crate::error::ConfigurationError;
+use crate::services::subgraph;
+
+const DEFAULT_CONSECUTIVE_FAILURES: u32 = 5;
+const DEFAULT_EJECTION_DURATION: Duration = Duration::from_secs(30);
+
+/// Load balancing configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LoadBalancingConfig {
+    /// Routing URLs of the subgraph. They replace the routing URL from the supergraph schema
+    urls: Vec<url::Url>,
+    /// How the URL of each request is chosen (default: round_robin)
+    #[serde(default)]
+    strategy: LoadBalancingStrategy,
+    /// Ejection of the URLs that keep failing
+    #[serde(default)]
+    ejection: EjectionConfig,
+}
+
+/// How the URL of each request is chosen
+#[derive(PartialEq, Default, Debug, Clone, Copy, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LoadBalancingStrategy {
+    /// Each URL in turn
+    #[default]
+    RoundRobin,
+    /// The URL with the fewest requests in flight
+    LeastRequests,
+}
+
+/// Ejection configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct EjectionConfig {
+    /// Number of consecutive failed requests after which a URL is ejected (default: 5). A request
+    /// fails when it cannot be sent or when the subgraph answers with a 5xx status code
+    consecutive_failures: u32,
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// How long an ejected URL receives no requests (default: 30s)
+    duration: Duration,
+}
+
+impl Default for EjectionConfig {
+    fn default() -> Self {
+        Self {
+            consecutive_failures: DEFAULT_CONSECUTIVE_FAILURES,
+            duration: DEFAULT_EJECTION_DURATION,
+        }
+    }
+}
+
+impl LoadBalancingConfig {
+    pub(crate) fn validate(&self) -> Result<(), ConfigurationError> {
+        let invalid = |error: String| ConfigurationError::InvalidConfiguration {
+            message: "bad configuration for traffic_shaping plugin",
+            error,
+        };
+        if self.urls.is_empty() {
+            return Err(invalid(
+                "load balancing requires at least one URL".to_string(),
+            ));
+        }
+        if self.ejection.consecutive_failures == 0 {
+            return Err(invalid(
+                "the load balancing consecutive_failures must be greater than 0".to_string(),
+            ));
+        }
+        for url in &self.urls {
+            url.as_str()
+                .parse::<Uri>()
+                .map_err(|e| invalid(format!("invalid load balancing URL '{url}': {e}")))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct LoadBalancingLayer {
+    state: Arc<LoadBalancingState>,
+}
+
+impl LoadBalancingLayer {
+    /// The configuration must have been validated
+    pub(crate) fn new(config: &LoadBalancingConfig, subgraph_name: String) -> Self {
+        Self {
+            state: Arc::new(LoadBalancingState {
+                endpoints: config
+                    .urls
+                    .iter()
+                    .map(|url| Endpoint {
+                        uri: url
+                            .as_str()
+                            .parse()
+                            .expect("load balancing URLs were validated; qed"),
+                        url: url.to_string(),
+                        in_flight: AtomicUsize::new(0),
+                        health: Mutex::new(Health::default()),
+                    })
+                    .collect(),
+                strategy: config.strategy,
+                consecutive_failures: config.ejection.consecutive_failures,
+                ejection_duration: config.ejection.duration,
+                next: AtomicUsize::new(0),
+                subgraph_name,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for LoadBalancingLayer {
+    type Service = LoadBalance<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadBalance {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+struct LoadBalancingState {
+    endpoints: Vec<Endpoint>,
+    strategy: LoadBalancingStrategy,
+    consecutive_failures: u32,
+    ejection_duration: Duration,
+    next: AtomicUsize,
+    subgraph_name: String,
+}
+
+struct Endpoint {
+    uri: Uri,
+    url: String,
+    in_flight: AtomicUsize,
+    health: Mutex<Health>,
+}
+
+#[derive(Default)]
+struct Health {
+    consecutive_failures: u32,
+    ejected_until: Option<Instant>,
+}
+
+impl Endpoint {
+    fn is_ejected(&self, now: Instant) -> bool {
+        self.health
+            .lock()
+            .expect("lock poisoned")
+            .ejected_until
+            .is_some_and(|until| now < until)
+    }
+}
+
+impl LoadBalancingState {
+    /// The index of the endpoint of the next request. Ejected endpoints are skipped, unless all
+    /// of them are ejected
+    fn select(&self, now: Instant) -> usize {
+        let len = self.endpoints.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % len;
+        let candidates = (0..len).map(|offset| (start + offset) % len);
+
+        let healthy = || {
+            candidates
+                .clone()
+                .filter(|i| !self.endpoints[*i].is_ejected(now))
+        };
+        let selected = match self.strategy {
+            LoadBalancingStrategy::RoundRobin => healthy().next(),
+            LoadBalancingStrategy::LeastRequests => {
+                healthy().min_by_key(|i| self.endpoints[*i].in_flight.load(Ordering::Relaxed))
+            }
+        };
+        selected.unwrap_or(start)
+    }
+
+    fn record(&self, index: usize, success: bool, now: Instant) {
+        let endpoint = &self.endpoints[index];
+        tracing::info!(
+            monotonic_counter.apollo_router_subgraph_endpoint_requests_total = 1u64,
+            subgraph = %self.subgraph_name,
+            url = %endpoint.url,
+            status = if success { "success" } else { "failure" },
+        );
+
+        let mut health = endpoint.health.lock().expect("lock poisoned");
+        if success {
+            health.consecutive_failures = 0;
+            return;
+        }
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= self.consecutive_failures {
+            health.consecutive_failures = 0;
+            health.ejected_until = Some(now + self.ejection_duration);
+            tracing::info!(
+                monotonic_counter.apollo_router_subgraph_endpoint_ejections_total = 1u64,
+                subgraph = %self.subgraph_name,
+                url = %endpoint.url,
+            );
+            tracing::warn!(
+                subgraph = %self.subgraph_name,
+                url = %endpoint.url,
+                "subgraph URL ejected for {:?} after {} consecutive failures",
+                self.ejection_duration,
+                self.consecutive_failures
+            );
+        }
+    }
+}
+
+/// Counts a request in flight to an endpoint until it is dropped
+struct InFlight<'a>(&'a Endpoint);
+
+impl<'a> InFlight<'a> {
+    fn new(endpoint: &'a Endpoint) -> Self {
+        endpoint.in_flight.fetch_add(1, Ordering::Relaxed);
+        Self(endpoint)
+    }
+}
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Records the outcome of a request to an endpoint when it is dropped: requests dropped before
+/// they complete, such as those cancelled by the subgraph timeout, count as failures so that an
+/// endpoint that stopped answering is ejected
+struct OutcomeRecorder {
+    state: Arc<LoadBalancingState>,
+    index: usize,
+    success: bool,
+}
+
+impl Drop for OutcomeRecorder {
+    fn drop(&mut self) {
+        self.state.record(self.index, self.success, Instant::now());
+    }
+}
+
+/// Sends each subgraph request to one of the routing URLs of the subgraph
+#[derive(Clone)]
+pub(crate) struct LoadBalance<S> {
+    inner: S,
+    state: Arc<LoadBalancingState>,
+}
+
+impl<S> Service<subgraph::Request> for LoadBalance<S>
+where
+    S: Service<subgraph::Request, Response = subgraph::Response, Error = BoxError>
+        + Clone
+        + Send
+        + 'static,
+    <S as Service<subgraph::Request>>::Future: Send,
+{
+    type Response = subgraph::Response;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<subgraph::Response, BoxError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Every request is sent through a clone of the inner service, see `call`
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, mut req: subgraph::Request) -> Self::Future {
+        let service = self.inner.clone();
+        let state = self.state.clone();
+        let index = state.select(Instant::now());
+        *req.subgraph_request.uri_mut() = state.endpoints[index].uri.clone();
+
+        Box::pin(async move {
+            let mut outcome = OutcomeRecorder {
+                state: state.clone(),
+                index,
+                success: false,
+            };
+            let result = {
+                let _in_flight = InFlight::new(&state.endpoints[index]);
+                service.oneshot(req).await
+            };
+            outcome.success = match &result {
+                Ok(response) => !response.response.status().is_server_error(),
+                Err(_) => false,
+            };
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn layer(strategy: LoadBalancingStrategy) -> LoadBalancingLayer {
+        let config: LoadBalancingConfig = serde_json::from_value(serde_json::json!({
+            "urls": ["http://a:4001/graphql", "http://b:4001/graphql", "http://c:4001/graphql"],
+            "ejection": { "consecutive_failures": 2, "duration": "10s" }
+        }))
+        .unwrap();
+        config.validate().unwrap();
+        LoadBalancingLayer::new(
+            &LoadBalancingConfig { strategy, ..config },
+            "products".to_string(),
+        )
+    }
+
+    #[test]
+    fn it_rotates_and_ejects_failing_urls() {
+        let state = layer(LoadBalancingStrategy::RoundRobin).state;
+        let now = Instant::now();
+        let selected: Vec<usize> = (0..4).map(|_| state.select(now)).collect();
+        assert_eq!(selected, vec![0, 1, 2, 0]);
+
+        state.record(1, false, now);
+        state.record(1, false, now);
+        let selected: Vec<usize> = (0..4).map(|_| state.select(now)).collect();
+        assert_eq!(selected, vec![2, 2, 0, 2]);
+
+        // The URL is back after the ejection
+        let later = now + Duration::from_secs(10);
+        let selected: Vec<usize> = (0..3).map(|_| state.select(later)).collect();
+        assert_eq!(selected, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn it_uses_every_url_when_all_are_ejected() {
+        let state = layer(LoadBalancingStrategy::RoundRobin).state;
+        let now = Instant::now();
+        for index in 0..3 {
+            state.record(index, false, now);
+            state.record(index, true, now);
+            state.record(index, false, now);
+            assert!(!state.endpoints[index].is_ejected(now));
+            state.record(index, false, now);
+            assert!(state.endpoints[index].is_ejected(now));
+        }
+        let selected: Vec<usize> = (0..3).map(|_| state.select(now)).collect();
+        assert_eq!(selected, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn it_selects_the_url_with_least_requests() {
+        let state = layer(LoadBalancingStrategy::LeastRequests).state;
+        let now = Instant::now();
+        let _first = InFlight::new(&state.endpoints[0]);
+        let _second = InFlight::new(&state.endpoints[0]);
+        let third = InFlight::new(&state.endpoints[2]);
+        assert_eq!(state.select(now), 1);
+        assert_eq!(state.select(now), 1);
+        drop(third);
+        assert_eq!(state.select(now), 2);
+    }
+
+    #[tokio::test]
+    async fn it_records_requests_cancelled_by_a_timeout_as_failures() {
+        let layer = layer(LoadBalancingStrategy::RoundRobin);
+        let state = layer.state.clone();
+        let mut service = layer.layer(tower::service_fn(|_req: subgraph::Request| {
+            futures::future::pending::<Result<subgraph::Response, BoxError>>()
+        }));
+
+        for _ in 0..3 {
+            let call = service.call(subgraph::Request::fake_builder().build());
+            assert!(tokio::time::timeout(Duration::from_millis(10), call)
+                .await
+                .is_err());
+        }
+        assert_eq!(state.endpoints[0].in_flight.load(Ordering::Relaxed), 0);
+        assert_eq!(
+            state.endpoints[0]
+                .health
+                .lock()
+                .unwrap()
+                .consecutive_failures,
+            1
+        );
+
+        // The URL is ejected after the second timeout
+        let call = service.call(subgraph::Request::fake_builder().build());
+        assert!(tokio::time::timeout(Duration::from_millis(10), call)
+            .await
+            .is_err());
+        assert!(state.endpoints[0].is_ejected(Instant::now()));
+    }
+
+    #[test]
+    fn it_rejects_invalid_configurations() {
+        let config: LoadBalancingConfig =
+            serde_json::from_value(serde_json::json!({ "urls": [] })).unwrap();
+        assert!(config.validate().is_err());
+        let config: LoadBalancingConfig = serde_json::from_value(serde_json::json!({
+            "urls": ["http://a:4001"],
+            "ejection": { "consecutive_failures": 0 }
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
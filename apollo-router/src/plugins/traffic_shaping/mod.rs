@@ -5,18 +5,31 @@
 //! * Timeout
 //! * Compression
 //! * Rate limiting
+//! * Request hedging
+//! * Circuit breaking
+//! * Adaptive concurrency limiting
+//! * Load balancing
 //!
+mod circuit_breaker;
+mod concurrency_limit;
 mod deduplication;
+mod hedging;
+mod load_balancing;
+mod operation_cost;
 pub(crate) mod rate;
 mod retry;
 pub(crate) mod timeout;
 
 use std::collections::HashMap;
 use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
+use std::ops::ControlFlow;
+use std::sync::Arc;
 use std::sync::Mutex;
 use std::time::Duration;
 
 use futures::future::BoxFuture;
+use http::header::HeaderName;
 use http::header::CONTENT_ENCODING;
 use http::HeaderValue;
 use schemars::JsonSchema;
@@ -29,23 +42,49 @@ use tower::Service;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 
+use self::circuit_breaker::CircuitBreaker;
+use self::circuit_breaker::CircuitBreakerConfig;
+use self::circuit_breaker::CircuitBreakerLayer;
+pub(crate) use self::circuit_breaker::CircuitOpen;
+use self::concurrency_limit::ConcurrencyLimit;
+use self::concurrency_limit::ConcurrencyLimitConfig;
+use self::concurrency_limit::ConcurrencyLimitLayer;
+pub(crate) use self::concurrency_limit::ConcurrencyLimited;
 use self::deduplication::QueryDeduplicationLayer;
+use self::hedging::Hedge;
+use self::hedging::HedgingLayer;
+use self::load_balancing::LoadBalance;
+use self::load_balancing::LoadBalancingConfig;
+use self::load_balancing::LoadBalancingLayer;
+use self::rate::ClientCostLimiter;
+use self::rate::ClientRateLimiter;
+use self::rate::RateLimitHeaders;
 use self::rate::RateLimitLayer;
 pub(crate) use self::rate::RateLimited;
+use self::retry::Backoff;
+pub(crate) use self::retry::ResendCount;
 pub(crate) use self::retry::RetryPolicy;
 pub(crate) use self::timeout::Elapsed;
 use self::timeout::TimeoutLayer;
+use crate::axum_factory::utils::ConnectionInfo;
+use crate::configuration::client_address::ClientAddress;
 use crate::error::ConfigurationError;
+use crate::layers::ServiceBuilderExt;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::register_plugin;
+use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::subgraph;
 use crate::services::subgraph_service::Compression;
+use crate::services::subgraph_service::ResponseLimits;
 use crate::services::supergraph;
 use crate::services::SubgraphRequest;
+use crate::Context;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 pub(crate) const APOLLO_TRAFFIC_SHAPING: &str = "apollo.traffic_shaping";
+/// Context key of the operation cost used by the client cost limit, instead of the estimated cost
+const OPERATION_COST: &str = "apollo_traffic_shaping::operation_cost";
 
 trait Merge {
     fn merge(&self, fallback: Option<&Self>) -> Self;
@@ -65,11 +104,26 @@ struct Shaping {
     #[schemars(with = "String", default)]
     /// Enable timeout for incoming requests
     timeout: Option<Duration>,
+    /// Maximum size of a subgraph response body, in bytes. Larger responses are aborted
+    max_response_bytes: Option<usize>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Abort the subgraph response if no data was received from its body for this duration
+    body_read_timeout: Option<Duration>,
     /// Retry configuration
     //  *experimental feature*: Enables request retry
     experimental_retry: Option<RetryConfig>,
     /// Enable HTTP2 for subgraphs
     experimental_http2: Option<Http2Config>,
+    /// Hedging configuration
+    //  *experimental feature*: Enables request hedging
+    experimental_hedging: Option<HedgingConfig>,
+    /// Circuit breaker configuration
+    //  *experimental feature*: Enables circuit breaking
+    experimental_circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Adaptive concurrency limit configuration
+    //  *experimental feature*: Enables adaptive concurrency limiting
+    experimental_concurrency_limit: Option<ConcurrencyLimitConfig>,
 }
 
 #[derive(PartialEq, Default, Debug, Clone, Deserialize, JsonSchema)]
@@ -92,6 +146,8 @@ impl Merge for Shaping {
                 deduplicate_query: self.deduplicate_query.or(fallback.deduplicate_query),
                 compression: self.compression.or(fallback.compression),
                 timeout: self.timeout.or(fallback.timeout),
+                max_response_bytes: self.max_response_bytes.or(fallback.max_response_bytes),
+                body_read_timeout: self.body_read_timeout.or(fallback.body_read_timeout),
                 global_rate_limit: self
                     .global_rate_limit
                     .as_ref()
@@ -107,6 +163,23 @@ impl Merge for Shaping {
                     .as_ref()
                     .or(fallback.experimental_http2.as_ref())
                     .cloned(),
+                experimental_hedging: self
+                    .experimental_hedging
+                    .as_ref()
+                    .map(|hedging| hedging.merge(fallback.experimental_hedging.as_ref()))
+                    .or_else(|| fallback.experimental_hedging.clone()),
+                experimental_circuit_breaker: self
+                    .experimental_circuit_breaker
+                    .as_ref()
+                    .or(fallback.experimental_circuit_breaker.as_ref())
+                    .cloned(),
+                experimental_concurrency_limit: self
+                    .experimental_concurrency_limit
+                    .as_ref()
+                    .map(|concurrency_limit| {
+                        concurrency_limit.merge(fallback.experimental_concurrency_limit.as_ref())
+                    })
+                    .or_else(|| fallback.experimental_concurrency_limit.clone()),
             },
         }
     }
@@ -130,8 +203,14 @@ struct RetryConfig {
     /// is 0.2
     retry_percent: Option<f32>,
     /// allows request retries on mutations. This should only be activated if mutations
-    /// are idempotent. Disabled by default
+    /// are idempotent. Disabled by default: only queries are retried, unless the
+    /// `apollo_retry::idempotent` context entry of the request is set to `true`
     retry_mutations: Option<bool>,
+    /// maximum number of attempts of a request, including the first one. By default,
+    /// requests are retried as long as the retry budget allows it
+    max_attempts: Option<NonZeroUsize>,
+    /// exponential backoff between attempts. By default, requests are retried immediately
+    backoff: Option<BackoffConfig>,
 }
 
 impl Merge for RetryConfig {
@@ -143,17 +222,113 @@ impl Merge for RetryConfig {
                 min_per_sec: self.min_per_sec.or(fallback.min_per_sec),
                 retry_percent: self.retry_percent.or(fallback.retry_percent),
                 retry_mutations: self.retry_mutations.or(fallback.retry_mutations),
+                max_attempts: self.max_attempts.or(fallback.max_attempts),
+                backoff: self.backoff.as_ref().or(fallback.backoff.as_ref()).cloned(),
+            },
+        }
+    }
+}
+
+impl RetryConfig {
+    fn validate(&self) -> Result<(), ConfigurationError> {
+        if let Some(BackoffConfig {
+            min_delay: Some(min),
+            max_delay: Some(max),
+        }) = self.backoff
+        {
+            if min > max {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "bad configuration for traffic_shaping plugin",
+                    error: "the retry backoff min_delay cannot be greater than max_delay"
+                        .to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Exponential backoff between retries. The delay doubles with each retry, from `min_delay` up
+/// to `max_delay`, and is randomly reduced by up to half so that retries spread out
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct BackoffConfig {
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// delay before the first retry, default value is 100ms
+    min_delay: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// maximum delay between retries, default value is 5s
+    max_delay: Option<Duration>,
+}
+
+/// Hedging configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct HedgingConfig {
+    /// latency percentile of the recent queries to the subgraph after which a query is sent
+    /// again. Must be between 0 and 1, default value is 0.95
+    percentile: Option<f64>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// minimum delay before sending the second request, default value is 10ms
+    min_delay: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// maximum delay before sending the second request, default value is 1s
+    max_delay: Option<Duration>,
+    /// maximum proportion of queries that can be hedged. Must be between 0 and 1, default
+    /// value is 0.1
+    hedge_percent: Option<f32>,
+}
+
+impl Merge for HedgingConfig {
+    fn merge(&self, fallback: Option<&Self>) -> Self {
+        match fallback {
+            None => self.clone(),
+            Some(fallback) => HedgingConfig {
+                percentile: self.percentile.or(fallback.percentile),
+                min_delay: self.min_delay.or(fallback.min_delay),
+                max_delay: self.max_delay.or(fallback.max_delay),
+                hedge_percent: self.hedge_percent.or(fallback.hedge_percent),
             },
         }
     }
 }
 
+impl HedgingConfig {
+    fn validate(&self) -> Result<(), ConfigurationError> {
+        let invalid = |error: &str| ConfigurationError::InvalidConfiguration {
+            message: "bad configuration for traffic_shaping plugin",
+            error: error.to_string(),
+        };
+        if matches!(self.percentile, Some(p) if !(0.0..=1.0).contains(&p)) {
+            return Err(invalid("the hedging percentile must be between 0 and 1"));
+        }
+        if matches!(self.hedge_percent, Some(p) if !(0.0..=1.0).contains(&p)) {
+            return Err(invalid("the hedging hedge_percent must be between 0 and 1"));
+        }
+        if let (Some(min), Some(max)) = (self.min_delay, self.max_delay) {
+            if min > max {
+                return Err(invalid(
+                    "the hedging min_delay cannot be greater than max_delay",
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 // this is a wrapper struct to add subgraph specific options over Shaping
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct SubgraphShaping {
     #[serde(flatten)]
     shaping: Shaping,
+    /// Load balancing over several routing URLs of the subgraph
+    //  *experimental feature*: Enables client-side load balancing
+    experimental_load_balancing: Option<LoadBalancingConfig>,
 }
 
 impl Merge for SubgraphShaping {
@@ -162,6 +337,8 @@ impl Merge for SubgraphShaping {
             None => self.clone(),
             Some(fallback) => SubgraphShaping {
                 shaping: self.shaping.merge(Some(&fallback.shaping)),
+                // The routing URLs are specific to each subgraph
+                experimental_load_balancing: self.experimental_load_balancing.clone(),
             },
         }
     }
@@ -172,6 +349,11 @@ impl Merge for SubgraphShaping {
 struct RouterShaping {
     /// Enable global rate limiting
     global_rate_limit: Option<RateLimitConf>,
+    /// Enable rate limiting of each client address, as determined by `supergraph.client_address`
+    client_rate_limit: Option<RateLimitConf>,
+    /// Enable limiting the cost of the operations of each client address, as determined by
+    /// `supergraph.client_address`
+    experimental_client_cost_limit: Option<CostLimitConf>,
     #[serde(deserialize_with = "humantime_serde::deserialize", default)]
     #[schemars(with = "String", default)]
     /// Enable timeout for incoming requests
@@ -192,6 +374,57 @@ pub(crate) struct Config {
     subgraphs: HashMap<String, SubgraphShaping>,
     /// DEPRECATED, now always enabled: Enable variable deduplication optimization when sending requests to subgraphs (https://github.com/apollographql/router/issues/87)
     deduplicate_variables: Option<bool>,
+    /// Add rate limit headers to the client response when a router or subgraph rate limit is reached
+    rate_limit_headers: Option<RateLimitHeadersConf>,
+}
+
+/// Rate limit response headers configuration
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+struct RateLimitHeadersConf {
+    /// Enable rate limit headers (default: true)
+    enabled: bool,
+    /// Name of the header containing the number of requests allowed per interval (default: `RateLimit-Limit`)
+    limit: String,
+    /// Name of the header containing the number of requests left in the current interval (default: `RateLimit-Remaining`)
+    remaining: String,
+    /// Name of the header containing the number of seconds until the interval resets (default: `RateLimit-Reset`)
+    reset: String,
+    /// Name of the header containing the number of seconds to wait before retrying (default: `Retry-After`)
+    retry_after: String,
+}
+
+impl Default for RateLimitHeadersConf {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            limit: "RateLimit-Limit".to_string(),
+            remaining: "RateLimit-Remaining".to_string(),
+            reset: "RateLimit-Reset".to_string(),
+            retry_after: "Retry-After".to_string(),
+        }
+    }
+}
+
+impl RateLimitHeadersConf {
+    fn header_names(&self) -> Result<Option<RateLimitHeaders>, ConfigurationError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+        let header_name = |name: &str| {
+            HeaderName::try_from(name).map_err(|e| ConfigurationError::InvalidConfiguration {
+                message: "bad configuration for traffic_shaping plugin",
+                error: format!("invalid rate limit header name '{name}': {e}"),
+            })
+        };
+
+        Ok(Some(RateLimitHeaders {
+            limit: header_name(&self.limit)?,
+            remaining: header_name(&self.remaining)?,
+            reset: header_name(&self.reset)?,
+            retry_after: header_name(&self.retry_after)?,
+        }))
+    }
 }
 
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
@@ -217,12 +450,42 @@ impl Merge for RateLimitConf {
     }
 }
 
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct CostLimitConf {
+    /// Cost of the operations allowed. The cost of an operation is estimated from the fields it
+    /// selects and the size of the lists they return, unless the
+    /// `apollo_traffic_shaping::operation_cost` context entry is set
+    capacity: NonZeroU64,
+    #[serde(deserialize_with = "humantime_serde::deserialize")]
+    #[schemars(with = "String")]
+    /// Per interval
+    interval: Duration,
+    /// The size assumed for the lists returned by fields without a `first`, `last` or `limit`
+    /// argument (default: 10)
+    #[serde(default = "default_list_size")]
+    list_size: u64,
+}
+
+const fn default_list_size() -> u64 {
+    10
+}
+
 // FIXME: This struct is pub(crate) because we need its configuration in the query planner service.
 // Remove this once the configuration yml changes.
 pub(crate) struct TrafficShaping {
     config: Config,
     rate_limit_router: Option<RateLimitLayer>,
+    rate_limit_clients: Option<ClientRateLimiter>,
+    cost_limit_clients: Option<ClientCostLimiter>,
+    cost_list_size: u64,
     rate_limit_subgraphs: Mutex<HashMap<String, RateLimitLayer>>,
+    rate_limit_headers: Option<Arc<RateLimitHeaders>>,
+    retry_subgraphs: Mutex<HashMap<String, RetryPolicy>>,
+    hedging_subgraphs: Mutex<HashMap<String, HedgingLayer>>,
+    circuit_breaker_subgraphs: Mutex<HashMap<String, CircuitBreakerLayer>>,
+    concurrency_limit_subgraphs: Mutex<HashMap<String, ConcurrencyLimitLayer>>,
+    load_balancing_subgraphs: Mutex<HashMap<String, LoadBalancingLayer>>,
 }
 
 #[async_trait::async_trait]
@@ -230,6 +493,15 @@ impl Plugin for TrafficShaping {
     type Config = Config;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let rate_limit_headers = init
+            .config
+            .rate_limit_headers
+            .as_ref()
+            .map(|conf| conf.header_names())
+            .transpose()?
+            .flatten()
+            .map(Arc::new);
+
         let rate_limit_router = init
             .config
             .router
@@ -248,29 +520,225 @@ impl Plugin for TrafficShaping {
                     Ok(RateLimitLayer::new(
                         router_rate_limit_conf.capacity,
                         router_rate_limit_conf.interval,
-                    ))
+                    )
+                    .with_headers(rate_limit_headers.clone()))
                 }
             })
             .transpose()?;
 
+        let rate_limit_clients = init
+            .config
+            .router
+            .as_ref()
+            .and_then(|r| r.client_rate_limit.as_ref())
+            .map(|client_rate_limit_conf| {
+                ClientRateLimiter::new(
+                    client_rate_limit_conf.capacity,
+                    client_rate_limit_conf.interval,
+                )
+                .with_headers(rate_limit_headers.clone())
+            });
+
+        let cost_limit_clients = init
+            .config
+            .router
+            .as_ref()
+            .and_then(|r| r.experimental_client_cost_limit.as_ref())
+            .map(|client_cost_limit_conf| {
+                ClientCostLimiter::new(
+                    client_cost_limit_conf.capacity,
+                    client_cost_limit_conf.interval,
+                )
+                .with_headers(rate_limit_headers.clone())
+            });
+        let cost_list_size = init
+            .config
+            .router
+            .as_ref()
+            .and_then(|r| r.experimental_client_cost_limit.as_ref())
+            .map_or_else(default_list_size, |conf| conf.list_size);
+
+        for shaping in init.config.all.iter().chain(init.config.subgraphs.values()) {
+            if let Some(retry) = &shaping.shaping.experimental_retry {
+                retry.validate()?;
+            }
+            if let Some(hedging) = &shaping.shaping.experimental_hedging {
+                hedging.validate()?;
+            }
+            if let Some(circuit_breaker) = &shaping.shaping.experimental_circuit_breaker {
+                circuit_breaker.validate()?;
+            }
+            if let Some(load_balancing) = &shaping.experimental_load_balancing {
+                load_balancing.validate()?;
+            }
+        }
+        // The concurrency limit of a subgraph is validated once merged with the one of `all`
+        let all_concurrency_limit = init
+            .config
+            .all
+            .as_ref()
+            .and_then(|all| all.shaping.experimental_concurrency_limit.as_ref());
+        if let Some(concurrency_limit) = all_concurrency_limit {
+            concurrency_limit.validate()?;
+        }
+        for shaping in init.config.subgraphs.values() {
+            if let Some(concurrency_limit) = &shaping.shaping.experimental_concurrency_limit {
+                concurrency_limit.merge(all_concurrency_limit).validate()?;
+            }
+        }
+        if matches!(&init.config.all, Some(all) if all.experimental_load_balancing.is_some()) {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "bad configuration for traffic_shaping plugin",
+                error: "load balancing can only be configured for specific subgraphs".to_string(),
+            }
+            .into());
+        }
+
         {
             Ok(Self {
                 config: init.config,
                 rate_limit_router,
+                rate_limit_clients,
+                cost_limit_clients,
+                cost_list_size,
                 rate_limit_subgraphs: Mutex::new(HashMap::new()),
+                rate_limit_headers,
+                retry_subgraphs: Mutex::new(HashMap::new()),
+                hedging_subgraphs: Mutex::new(HashMap::new()),
+                circuit_breaker_subgraphs: Mutex::new(HashMap::new()),
+                concurrency_limit_subgraphs: Mutex::new(HashMap::new()),
+                load_balancing_subgraphs: Mutex::new(HashMap::new()),
             })
         }
     }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let rate_limit_clients = self.rate_limit_clients.clone();
+        let cost_limit_clients = self.cost_limit_clients.clone();
+        let cost_list_size = self.cost_list_size;
+        let service = if rate_limit_clients.is_some() || cost_limit_clients.is_some() {
+            ServiceBuilder::new()
+                .checkpoint(move |req: supergraph::Request| {
+                    let extensions = req.supergraph_request.extensions();
+                    let client = extensions
+                        .get::<ClientAddress>()
+                        .map(|client| client.ip)
+                        .or_else(|| {
+                            extensions
+                                .get::<ConnectionInfo>()?
+                                .peer_address
+                                .map(|peer| peer.ip())
+                        });
+                    if let Some(client) = client {
+                        if let Some(limiter) = &rate_limit_clients {
+                            limiter.check(client)?;
+                        }
+                        if let Some(limiter) = &cost_limit_clients {
+                            limiter.check(client, operation_cost(&req, cost_list_size))?;
+                        }
+                    }
+                    Ok(ControlFlow::Continue(req))
+                })
+                .service(service)
+                .boxed()
+        } else {
+            service
+        };
+        if self.rate_limit_headers.is_none() {
+            return service;
+        }
+
+        // Subgraph rate limits do not fail the client request, the headers recorded in the
+        // context by the subgraph service are added to the response instead
+        ServiceBuilder::new()
+            .map_response(|mut response: supergraph::Response| {
+                let rate_limited = response
+                    .context
+                    .private_entries
+                    .lock()
+                    .get::<RateLimited>()
+                    .cloned();
+                if let Some(rate_limited) = rate_limited {
+                    response
+                        .response
+                        .headers_mut()
+                        .extend(rate_limited.headers().clone());
+                }
+                response
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, _name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        if self.rate_limit_headers.is_none() {
+            return service;
+        }
+
+        ServiceBuilder::new()
+            .map_future_with_request_data(
+                |req: &subgraph::Request| req.context.clone(),
+                |context: Context, fut| async move {
+                    let result: Result<subgraph::Response, BoxError> = fut.await;
+                    if let Err(error) = &result {
+                        if let Some(rate_limited) = error.downcast_ref::<RateLimited>() {
+                            context.private_entries.lock().insert(rate_limited.clone());
+                        }
+                    }
+                    result
+                },
+            )
+            .service(service)
+            .boxed()
+    }
+}
+
+/// The cost of the operation of a request: the cost recorded in the context, or else its
+/// estimated cost
+fn operation_cost(req: &supergraph::Request, list_size: u64) -> u64 {
+    if let Ok(Some(cost)) = req.context.get::<_, u64>(OPERATION_COST) {
+        return cost;
+    }
+    let document = req
+        .context
+        .private_entries
+        .lock()
+        .get::<ParsedDocument>()
+        .cloned();
+    document
+        .and_then(|document| {
+            let body = req.supergraph_request.body();
+            operation_cost::estimate(
+                &document.executable,
+                body.operation_name.as_deref(),
+                &body.variables,
+                list_size,
+            )
+        })
+        .unwrap_or(1)
 }
 
+type BalancedSubgraphService<S> = Either<LoadBalance<S>, S>;
+
+type RateLimitedSubgraphService<S> = Either<rate::service::RateLimit<S>, S>;
+
+type ConcurrencyLimitedSubgraphService<S> =
+    Either<ConcurrencyLimit<RateLimitedSubgraphService<S>>, RateLimitedSubgraphService<S>>;
+
+type HedgedSubgraphService<S> =
+    Either<Hedge<ConcurrencyLimitedSubgraphService<S>>, ConcurrencyLimitedSubgraphService<S>>;
+
+type GuardedSubgraphService<S> =
+    Either<CircuitBreaker<HedgedSubgraphService<S>>, HedgedSubgraphService<S>>;
+
 pub(crate) type TrafficShapingSubgraphFuture<S> = Either<
     Either<
         BoxFuture<'static, Result<subgraph::Response, BoxError>>,
         timeout::future::ResponseFuture<
             Oneshot<
                 Either<
-                    Retry<RetryPolicy, Either<rate::service::RateLimit<S>, S>>,
-                    Either<rate::service::RateLimit<S>, S>,
+                    Retry<RetryPolicy, GuardedSubgraphService<BalancedSubgraphService<S>>>,
+                    GuardedSubgraphService<BalancedSubgraphService<S>>,
                 >,
                 subgraph::Request,
             >,
@@ -360,21 +828,84 @@ impl TrafficShaping {
                         .entry(name.to_string())
                         .or_insert_with(|| {
                             RateLimitLayer::new(rate_limit_conf.capacity, rate_limit_conf.interval)
+                                .with_headers(self.rate_limit_headers.clone())
                         })
                         .clone()
                 });
 
+            // the retry budget is shared by all the requests to the subgraph
             let retry = config.shaping.experimental_retry.as_ref().map(|config| {
-                let retry_policy = RetryPolicy::new(
-                    config.ttl,
-                    config.min_per_sec,
-                    config.retry_percent,
-                    config.retry_mutations,
-                    name.to_string(),
-                );
+                let backoff = config
+                    .backoff
+                    .as_ref()
+                    .map(|backoff| Backoff::new(backoff.min_delay, backoff.max_delay));
+                let retry_policy = self
+                    .retry_subgraphs
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_insert_with(|| {
+                        RetryPolicy::new(
+                            config.ttl,
+                            config.min_per_sec,
+                            config.retry_percent,
+                            config.retry_mutations,
+                            config.max_attempts,
+                            backoff,
+                            name.to_string(),
+                        )
+                    })
+                    .clone();
                 tower::retry::RetryLayer::new(retry_policy)
             });
 
+            let hedging = config.shaping.experimental_hedging.as_ref().map(|config| {
+                self.hedging_subgraphs
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_insert_with(|| {
+                        HedgingLayer::new(
+                            config.percentile,
+                            config.min_delay,
+                            config.max_delay,
+                            config.hedge_percent,
+                            name.to_string(),
+                        )
+                    })
+                    .clone()
+            });
+
+            let circuit_breaker = config.shaping.experimental_circuit_breaker.as_ref();
+            let circuit_breaker = circuit_breaker.map(|config| {
+                self.circuit_breaker_subgraphs
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_insert_with(|| CircuitBreakerLayer::new(config, name.to_string()))
+                    .clone()
+            });
+
+            // the limit is below hedging, so that hedged requests count as requests in flight
+            let concurrency_limit = config.shaping.experimental_concurrency_limit.as_ref();
+            let concurrency_limit = concurrency_limit.map(|config| {
+                self.concurrency_limit_subgraphs
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_insert_with(|| ConcurrencyLimitLayer::new(config, name.to_string()))
+                    .clone()
+            });
+
+            let load_balancing = config.experimental_load_balancing.as_ref().map(|config| {
+                self.load_balancing_subgraphs
+                    .lock()
+                    .unwrap()
+                    .entry(name.to_string())
+                    .or_insert_with(|| LoadBalancingLayer::new(config, name.to_string()))
+                    .clone()
+            });
+
             Either::A(ServiceBuilder::new()
 
                 .option_layer(config.shaping.deduplicate_query.unwrap_or_default().then(
@@ -386,7 +917,11 @@ impl TrafficShaping {
                         .unwrap_or(DEFAULT_TIMEOUT),
                     ))
                     .option_layer(retry)
+                    .option_layer(circuit_breaker)
+                    .option_layer(hedging)
+                    .option_layer(concurrency_limit)
                     .option_layer(rate_limit)
+                    .option_layer(load_balancing)
                 .service(service)
                 .map_request(move |mut req: SubgraphRequest| {
                     if let Some(compression) = config.shaping.compression {
@@ -409,6 +944,18 @@ impl TrafficShaping {
         .and_then(|config| config.shaping.experimental_http2)
         .unwrap_or(Http2Config::Enable)
     }
+
+    pub(crate) fn subgraph_response_limits(&self, service_name: &str) -> ResponseLimits {
+        Self::merge_config(
+            self.config.all.as_ref(),
+            self.config.subgraphs.get(service_name),
+        )
+        .map(|config| ResponseLimits {
+            max_size: config.shaping.max_response_bytes,
+            read_timeout: config.shaping.body_read_timeout,
+        })
+        .unwrap_or_default()
+    }
 }
 
 register_plugin!("apollo", "traffic_shaping", TrafficShaping);
@@ -430,6 +977,7 @@ mod test {
     use crate::plugin::test::MockSupergraphService;
     use crate::plugin::DynPlugin;
     use crate::query_planner::BridgeQueryPlanner;
+    use crate::query_planner::OperationKind;
     use crate::router_factory::create_plugins;
     use crate::services::layers::persisted_queries::PersistedQueryLayer;
     use crate::services::layers::query_analysis::QueryAnalysisLayer;
@@ -439,6 +987,8 @@ mod test {
     use crate::services::PluggableSupergraphServiceBuilder;
     use crate::services::SupergraphRequest;
     use crate::services::SupergraphResponse;
+    use crate::spec::Query;
+    use crate::spec::Schema;
     use crate::Configuration;
 
     static EXPECTED_RESPONSE: Lazy<Bytes> = Lazy::new(|| {
@@ -838,4 +1388,571 @@ mod test {
             .await
             .unwrap();
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_adds_rate_limit_headers_to_router_errors() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        router:
+            global_rate_limit:
+                capacity: 1
+                interval: 10s
+        rate_limit_headers:
+            retry_after: X-Retry-After
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_clone().returning(|| {
+            let mut mock_service = MockSupergraphService::new();
+            mock_service.expect_clone().returning(|| {
+                let mut mock_service = MockSupergraphService::new();
+                mock_service.expect_call().times(0..2).returning(move |_| {
+                    Ok(SupergraphResponse::fake_builder()
+                        .data(json!({ "test": 1234_u32 }))
+                        .build()
+                        .unwrap())
+                });
+                mock_service
+            });
+            mock_service
+        });
+
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+        let _response = shaping
+            .supergraph_service_internal(mock_service.clone())
+            .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        let error = shaping
+            .supergraph_service_internal(mock_service.clone())
+            .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .err()
+            .expect("should be rate limited");
+        let rate_limited = error
+            .downcast_ref::<RateLimited>()
+            .expect("should be a rate limit error");
+
+        let headers = rate_limited.headers();
+        assert_eq!(headers.get("ratelimit-limit").unwrap(), "1");
+        assert_eq!(headers.get("ratelimit-remaining").unwrap(), "0");
+        assert!(headers.get("ratelimit-reset").is_some());
+        assert!(headers.get("x-retry-after").is_some());
+        assert!(headers.get(http::header::RETRY_AFTER).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_rate_limits_each_client() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        router:
+            client_rate_limit:
+                capacity: 1
+                interval: 10s
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(3).returning(move |_| {
+            Ok(SupergraphResponse::fake_builder()
+                .data(json!({ "test": 1234_u32 }))
+                .build()
+                .unwrap())
+        });
+        let mut service = plugin.supergraph_service(mock_service.boxed());
+
+        let request = |client: Option<&str>| {
+            let mut request = SupergraphRequest::fake_builder().build().unwrap();
+            if let Some(client) = client {
+                request
+                    .supergraph_request
+                    .extensions_mut()
+                    .insert(ClientAddress {
+                        ip: client.parse().unwrap(),
+                        port: None,
+                    });
+            }
+            request
+        };
+
+        assert!(service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Some("10.0.0.1")))
+            .await
+            .is_ok());
+        let error = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Some("10.0.0.1")))
+            .await
+            .err()
+            .expect("should be rate limited");
+        assert!(error.downcast_ref::<RateLimited>().is_some());
+        assert!(service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(Some("10.0.0.2")))
+            .await
+            .is_ok());
+        // Requests without a client address are not limited
+        assert!(service
+            .ready()
+            .await
+            .unwrap()
+            .call(request(None))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_rate_limits_the_peer_address_without_trusted_proxies() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        router:
+            client_rate_limit:
+                capacity: 1
+                interval: 10s
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(move |_| {
+            Ok(SupergraphResponse::fake_builder()
+                .data(json!({ "test": 1234_u32 }))
+                .build()
+                .unwrap())
+        });
+        let mut service = plugin.supergraph_service(mock_service.boxed());
+
+        // The client address is only resolved from the forwarded headers with trusted proxies,
+        // a client changing its Forwarded header is still limited on its peer address
+        let request = |forwarded: &str| {
+            let mut request = SupergraphRequest::fake_builder()
+                .header("forwarded", forwarded)
+                .build()
+                .unwrap();
+            request
+                .supergraph_request
+                .extensions_mut()
+                .insert(ConnectionInfo {
+                    peer_address: Some("10.0.0.1:6060".parse().unwrap()),
+                    server_address: None,
+                });
+            request
+        };
+
+        assert!(service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("for=1.1.1.1"))
+            .await
+            .is_ok());
+        let error = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("for=2.2.2.2"))
+            .await
+            .err()
+            .expect("should be rate limited");
+        assert!(error.downcast_ref::<RateLimited>().is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_limits_the_cost_of_each_client() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        router:
+            experimental_client_cost_limit:
+                capacity: 5
+                interval: 10s
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(3).returning(move |_| {
+            Ok(SupergraphResponse::fake_builder()
+                .data(json!({ "test": 1234_u32 }))
+                .build()
+                .unwrap())
+        });
+        let mut service = plugin.supergraph_service(mock_service.boxed());
+
+        let schema = Schema::parse(
+            include_str!("../../testdata/supergraph.graphql"),
+            &Default::default(),
+        )
+        .unwrap();
+        let request = |query: &str, cost: Option<u64>| {
+            let mut request = SupergraphRequest::fake_builder()
+                .query(query)
+                .build()
+                .unwrap();
+            request
+                .supergraph_request
+                .extensions_mut()
+                .insert(ClientAddress {
+                    ip: "10.0.0.1".parse().unwrap(),
+                    port: None,
+                });
+            request
+                .context
+                .private_entries
+                .lock()
+                .insert::<ParsedDocument>(Query::parse_document(
+                    query,
+                    &schema,
+                    &Default::default(),
+                ));
+            if let Some(cost) = cost {
+                request.context.insert(OPERATION_COST, cost).unwrap();
+            }
+            request
+        };
+
+        // A list of 3 products
+        assert!(service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("{ topProducts(first: 3) { upc } }", None))
+            .await
+            .is_ok());
+        let error = service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("{ topProducts(first: 3) { upc } }", None))
+            .await
+            .err()
+            .expect("should be rate limited");
+        assert!(error.downcast_ref::<RateLimited>().is_some());
+        // The cost recorded in the context replaces the estimate
+        assert!(service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("{ me { id name } }", Some(2)))
+            .await
+            .is_ok());
+        assert!(service
+            .ready()
+            .await
+            .unwrap()
+            .call(request("{ me { id } }", Some(0)))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_records_subgraph_rate_limit_headers_in_context() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                global_rate_limit:
+                    capacity: 1
+                    interval: 10s
+        rate_limit_headers:
+            enabled: true
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+        let test_service = MockSubgraph::new(HashMap::new());
+
+        let context = Context::new();
+        let _response = plugin
+            .subgraph_service(
+                "test",
+                shaping
+                    .subgraph_service_internal("test", test_service.clone())
+                    .boxed(),
+            )
+            .oneshot(
+                SubgraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert!(context
+            .private_entries
+            .lock()
+            .get::<RateLimited>()
+            .is_none());
+
+        let _response = plugin
+            .subgraph_service(
+                "test",
+                shaping
+                    .subgraph_service_internal("test", test_service.clone())
+                    .boxed(),
+            )
+            .oneshot(
+                SubgraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build(),
+            )
+            .await
+            .expect_err("should be rate limited");
+        let entries = context.private_entries.lock();
+        let rate_limited = entries
+            .get::<RateLimited>()
+            .expect("rate limit should be recorded");
+        assert_eq!(rate_limited.headers().get("ratelimit-limit").unwrap(), "1");
+        assert!(rate_limited
+            .headers()
+            .get(http::header::RETRY_AFTER)
+            .is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn it_hedges_slow_subgraph_queries() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                experimental_hedging:
+                    min_delay: 50ms
+                    max_delay: 50ms
+                    hedge_percent: 1.0
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        // every tenth call is slow, it will be hedged once the latency window is filled
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let test_service = {
+            let calls = calls.clone();
+            tower::service_fn(move |_req: subgraph::Request| {
+                let call = calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                async move {
+                    if call % 10 == 0 {
+                        tokio::time::sleep(Duration::from_secs(5)).await;
+                    }
+                    Ok::<_, BoxError>(
+                        subgraph::Response::fake_builder()
+                            .data(json!({ "call": call }))
+                            .build(),
+                    )
+                }
+            })
+        };
+
+        for _ in 0..20 {
+            calls.store(1, std::sync::atomic::Ordering::SeqCst);
+            shaping
+                .subgraph_service_internal("test", test_service.clone())
+                .oneshot(SubgraphRequest::fake_builder().build())
+                .await
+                .unwrap();
+        }
+
+        calls.store(0, std::sync::atomic::Ordering::SeqCst);
+        let response = tokio::time::timeout(
+            Duration::from_secs(2),
+            shaping
+                .subgraph_service_internal("test", test_service.clone())
+                .oneshot(SubgraphRequest::fake_builder().build()),
+        )
+        .await
+        .expect("the hedged request should answer first")
+        .unwrap();
+        assert_eq!(response.response.body().data, Some(json!({ "call": 1 })));
+
+        // mutations are never hedged
+        calls.store(0, std::sync::atomic::Ordering::SeqCst);
+        let response = tokio::time::timeout(
+            Duration::from_secs(1),
+            shaping
+                .subgraph_service_internal("test", test_service.clone())
+                .oneshot(
+                    SubgraphRequest::fake_builder()
+                        .operation_kind(OperationKind::Mutation)
+                        .build(),
+                ),
+        )
+        .await;
+        assert!(response.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_hedging_configuration() {
+        let config = serde_yaml::from_str::<Config>(
+            r#"
+        all:
+            experimental_hedging:
+                percentile: 1.5
+        "#,
+        )
+        .unwrap();
+
+        assert!(
+            TrafficShaping::new(PluginInit::fake_builder().config(config).build())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_merge_concurrency_limit() {
+        let config = serde_yaml::from_str::<Config>(
+            r#"
+        all:
+          experimental_concurrency_limit:
+            algorithm: aimd
+            initial_limit: 50
+            max_limit: 100
+        subgraphs:
+          products:
+            experimental_concurrency_limit:
+              max_limit: 200
+          reviews:
+            experimental_concurrency_limit:
+              min_limit: 60
+        "#,
+        )
+        .unwrap();
+
+        let concurrency_limit = |subgraph: &str| {
+            TrafficShaping::merge_config(config.all.as_ref(), config.subgraphs.get(subgraph))
+                .unwrap()
+                .shaping
+                .experimental_concurrency_limit
+                .unwrap()
+        };
+        let expected: ConcurrencyLimitConfig = serde_json::from_value(serde_json::json!({
+            "algorithm": "aimd",
+            "initial_limit": 50,
+            "max_limit": 200
+        }))
+        .unwrap();
+        assert_eq!(concurrency_limit("products"), expected);
+        assert!(concurrency_limit("products").validate().is_ok());
+        // the initial limit of `all` is below the minimum of the subgraph
+        assert!(concurrency_limit("reviews").validate().is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_shares_the_concurrency_limit_of_a_subgraph() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                experimental_concurrency_limit:
+                    initial_limit: 1
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+        let test_service = tower::service_fn(|_req: subgraph::Request| async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok::<_, BoxError>(subgraph::Response::fake_builder().build())
+        });
+
+        // every fetch creates a new subgraph service, the requests in flight are still counted
+        let first = tokio::spawn(
+            shaping
+                .subgraph_service_internal("test", test_service)
+                .oneshot(SubgraphRequest::fake_builder().build()),
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let error = shaping
+            .subgraph_service_internal("test", test_service)
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .expect_err("should be concurrency limited");
+        assert!(error.is::<ConcurrencyLimited>());
+        assert!(first.await.unwrap().is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_balances_subgraph_requests_over_urls() {
+        let config = serde_yaml::from_str::<serde_json::Value>(
+            r#"
+        subgraphs:
+            test:
+                experimental_load_balancing:
+                    urls:
+                        - http://replica-1:4001/graphql
+                        - http://replica-2:4001/graphql
+                    ejection:
+                        consecutive_failures: 1
+        "#,
+        )
+        .unwrap();
+
+        let plugin = get_traffic_shaping_plugin(&config).await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        // the first replica always fails
+        let test_service = tower::service_fn(move |req: subgraph::Request| async move {
+            let uri = req.subgraph_request.uri().to_string();
+            if uri.contains("replica-1") {
+                return Err::<subgraph::Response, BoxError>("connection refused".into());
+            }
+            Ok(subgraph::Response::fake_builder()
+                .data(json!({ "uri": uri }))
+                .build())
+        });
+
+        let mut uris = Vec::new();
+        for _ in 0..4 {
+            let response = shaping
+                .subgraph_service_internal("test", test_service)
+                .oneshot(SubgraphRequest::fake_builder().build())
+                .await;
+            uris.push(
+                response
+                    .map(|response| response.response.body().data.clone())
+                    .ok(),
+            );
+        }
+        let replica_2 = Some(Some(json!({ "uri": "http://replica-2:4001/graphql" })));
+        assert_eq!(
+            uris,
+            vec![None, replica_2.clone(), replica_2.clone(), replica_2]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_load_balancing_for_all_subgraphs() {
+        let config = serde_yaml::from_str::<Config>(
+            r#"
+        all:
+            experimental_load_balancing:
+                urls:
+                    - http://replica-1:4001/graphql
+        "#,
+        )
+        .unwrap();
+
+        assert!(
+            TrafficShaping::new(PluginInit::fake_builder().config(config).build())
+                .await
+                .is_err()
+        );
+    }
 }
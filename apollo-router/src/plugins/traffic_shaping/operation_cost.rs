@@ -0,0 +1,194 @@
+//! Static estimate of the cost of an operation, for the client cost limits.
+//!
+//! A field of a composite type costs 1 plus the cost of its selections, while fields of leaf
+//! types are free. A field returning a list costs its cost times the size of the list, read from
+//! its `first`, `last` or `limit` argument or from the default value of the argument, or assumed
+//! when the field has none. Each root field of a mutation costs an extra 10, as mutations have
+//! side effects.
+
+use std::collections::HashSet;
+
+use apollo_compiler::executable;
+use apollo_compiler::ExecutableDocument;
+
+use crate::json_ext::Object;
+
+const MUTATION_FIELD_COST: u64 = 10;
+const LIST_SIZE_ARGUMENTS: [&str; 3] = ["first", "last", "limit"];
+
+/// The estimated cost of an operation.
+///
+/// Returns `None` if the operation name is undefined or ambiguous.
+pub(crate) fn estimate(
+    document: &ExecutableDocument,
+    operation_name: Option<&str>,
+    variables: &Object,
+    list_size: u64,
+) -> Option<u64> {
+    let operation = document.get_operation(operation_name).ok()?;
+    let estimator = Estimator {
+        document,
+        variables,
+        list_size,
+    };
+    let mut cost = estimator.selection_set(&operation.selection_set, &mut HashSet::new());
+    if operation.operation_type == executable::OperationType::Mutation {
+        let root_fields = operation.selection_set.selections.len() as u64;
+        cost = cost.saturating_add(root_fields.saturating_mul(MUTATION_FIELD_COST));
+    }
+    Some(cost)
+}
+
+struct Estimator<'a> {
+    document: &'a ExecutableDocument,
+    variables: &'a Object,
+    list_size: u64,
+}
+
+impl<'a> Estimator<'a> {
+    fn selection_set(
+        &self,
+        selection_set: &'a executable::SelectionSet,
+        fragments: &mut HashSet<&'a executable::Name>,
+    ) -> u64 {
+        let mut cost = 0u64;
+        for selection in &selection_set.selections {
+            let nested = match selection {
+                executable::Selection::Field(field) => self.field(field, fragments),
+                executable::Selection::InlineFragment(fragment) => {
+                    self.selection_set(&fragment.selection_set, fragments)
+                }
+                executable::Selection::FragmentSpread(spread) => {
+                    let name = &spread.fragment_name;
+                    // Undefined or cyclic fragments make the operation invalid, it will be
+                    // rejected by some other part of the router
+                    let Some(fragment) = self.document.fragments.get(name) else {
+                        continue;
+                    };
+                    if !fragments.insert(name) {
+                        continue;
+                    }
+                    let nested = self.selection_set(&fragment.selection_set, fragments);
+                    fragments.remove(name);
+                    nested
+                }
+            };
+            cost = cost.saturating_add(nested);
+        }
+        cost
+    }
+
+    fn field(
+        &self,
+        field: &'a executable::Field,
+        fragments: &mut HashSet<&'a executable::Name>,
+    ) -> u64 {
+        // Fields of leaf types have no selections
+        if field.selection_set.selections.is_empty() {
+            return 0;
+        }
+        let cost = self
+            .selection_set(&field.selection_set, fragments)
+            .saturating_add(1);
+        if field.definition.ty.is_list() {
+            cost.saturating_mul(self.list_size(field))
+        } else {
+            cost
+        }
+    }
+
+    /// The size of the list returned by a field, from its slicing argument if it has one
+    fn list_size(&self, field: &executable::Field) -> u64 {
+        let is_list_size = |name: &executable::Name| LIST_SIZE_ARGUMENTS.contains(&name.as_str());
+        let argument = field
+            .arguments
+            .iter()
+            .find(|argument| is_list_size(&argument.name))
+            .map(|argument| &argument.value);
+        let default = || {
+            field
+                .definition
+                .arguments
+                .iter()
+                .find(|argument| is_list_size(&argument.name))?
+                .default_value
+                .as_ref()
+        };
+        argument
+            .or_else(default)
+            .and_then(|value| match value.as_ref() {
+                executable::Value::Int(value) => value.as_str().parse().ok(),
+                executable::Value::Variable(name) => self.variables.get(name.as_str())?.as_u64(),
+                _ => None,
+            })
+            .unwrap_or(self.list_size)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::spec::Query;
+    use crate::spec::Schema;
+
+    fn estimate_query(query: &str, variables: serde_json_bytes::Value) -> u64 {
+        let schema = Schema::parse(
+            include_str!("../../testdata/starstuff@current.graphql"),
+            &Default::default(),
+        )
+        .unwrap();
+        let document = Query::parse_document(query, &schema, &Default::default());
+        let variables = variables.as_object().cloned().unwrap_or_default();
+        estimate(&document.executable, None, &variables, 10).unwrap()
+    }
+
+    #[test]
+    fn it_counts_the_composite_fields() {
+        // Leaf fields are free
+        assert_eq!(estimate_query("{ me { id name } }", json!({})), 1);
+        assert_eq!(
+            estimate_query(
+                "{ me { ...Reviewed } } fragment Reviewed on User { reviews { id } }",
+                json!({})
+            ),
+            // The reviews of the user are a list of the assumed size
+            1 + 10
+        );
+    }
+
+    #[test]
+    fn it_multiplies_lists_by_their_size() {
+        assert_eq!(
+            estimate_query("{ topProducts { upc reviews { id } } }", json!({})),
+            // The default of 5 products, with the assumed 10 reviews each
+            5 * (1 + 10)
+        );
+        assert_eq!(
+            estimate_query(
+                "{ topProducts(first: 2) { upc reviews { id } } }",
+                json!({})
+            ),
+            2 * (1 + 10)
+        );
+        assert_eq!(
+            estimate_query(
+                "query($first: Int) { topProducts(first: $first) { upc } }",
+                json!({ "first": 3 })
+            ),
+            3
+        );
+    }
+
+    #[test]
+    fn it_adds_the_cost_of_mutations() {
+        assert_eq!(
+            estimate_query(
+                "mutation { createProduct(upc: \"1\", name: \"test\") { upc } }",
+                json!({})
+            ),
+            1 + MUTATION_FIELD_COST
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::net::IpAddr;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+use super::clients::Clients;
+use super::clients::MAX_CLIENTS;
+use super::Rate;
+use super::RateLimitHeaders;
+use super::RateLimited;
+
+/// Limits the rate of requests of each client address, with a fixed window per client.
+#[derive(Debug, Clone)]
+pub(crate) struct ClientRateLimiter {
+    rate: Rate,
+    windows: Arc<Mutex<Clients<Window>>>,
+    headers: Option<Arc<RateLimitHeaders>>,
+}
+
+#[derive(Debug)]
+struct Window {
+    start: Instant,
+    requests: u64,
+}
+
+impl ClientRateLimiter {
+    pub(crate) fn new(num: NonZeroU64, per: Duration) -> Self {
+        Self {
+            rate: Rate::new(num, per),
+            windows: Arc::new(Mutex::new(Clients::new(MAX_CLIENTS))),
+            headers: None,
+        }
+    }
+
+    /// Add rate limit headers to the errors returned when the limit is reached.
+    pub(crate) fn with_headers(mut self, headers: Option<Arc<RateLimitHeaders>>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Count a request of the client, failing if the client reached its limit
+    pub(crate) fn check(&self, client: IpAddr) -> Result<(), RateLimited> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, now: Instant) -> Result<(), RateLimited> {
+        let per = self.rate.per();
+        let mut windows = self.windows.lock();
+
+        let Some(window) = windows.get_or_insert(
+            client,
+            |window| now.duration_since(window.start) >= per,
+            || Window {
+                start: now,
+                requests: 0,
+            },
+        ) else {
+            tracing::debug!("too many clients to rate limit, refusing a new client");
+            return Err(self.rate_limited(per));
+        };
+        if now.duration_since(window.start) >= per {
+            window.start = now;
+            window.requests = 0;
+        }
+        if window.requests >= self.rate.num() {
+            tracing::trace!("client rate limit exceeded");
+            return Err(self.rate_limited(per.saturating_sub(now.duration_since(window.start))));
+        }
+        window.requests += 1;
+        Ok(())
+    }
+
+    fn rate_limited(&self, reset: Duration) -> RateLimited {
+        match &self.headers {
+            Some(headers) => RateLimited::with_headers(headers.header_map(&self.rate, 0, reset)),
+            None => RateLimited::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_limits_each_client() {
+        let limiter = ClientRateLimiter::new(NonZeroU64::new(2).unwrap(), Duration::from_secs(1))
+            .with_headers(Some(Arc::new(RateLimitHeaders::default())));
+        let first: IpAddr = "10.0.0.1".parse().unwrap();
+        let second: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.check_at(first, now).is_ok());
+        assert!(limiter.check_at(first, now).is_ok());
+        let error = limiter
+            .check_at(first, now + Duration::from_millis(400))
+            .unwrap_err();
+        assert_eq!(error.headers().get("retry-after").unwrap(), "1");
+        assert!(limiter.check_at(second, now).is_ok());
+
+        // The window of the client ended
+        assert!(limiter
+            .check_at(first, now + Duration::from_millis(1000))
+            .is_ok());
+    }
+
+    #[test]
+    fn it_forgets_clients_after_their_window() {
+        let limiter = ClientRateLimiter::new(NonZeroU64::new(1).unwrap(), Duration::from_secs(1));
+        *limiter.windows.lock() = Clients::new(2);
+        let now = Instant::now();
+        let first: IpAddr = "10.0.0.1".parse().unwrap();
+        assert!(limiter.check_at(first, now).is_ok());
+        assert!(limiter
+            .check_at(
+                "10.0.0.2".parse().unwrap(),
+                now + Duration::from_millis(500)
+            )
+            .is_ok());
+
+        // The window of the first client ended, it makes room for a new client
+        assert!(limiter
+            .check_at("10.0.0.3".parse().unwrap(), now + Duration::from_secs(1))
+            .is_ok());
+        assert_eq!(limiter.windows.lock().len(), 2);
+        assert!(limiter
+            .check_at(first, now + Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn it_refuses_new_clients_when_the_tracked_clients_are_active() {
+        let limiter = ClientRateLimiter::new(NonZeroU64::new(1).unwrap(), Duration::from_secs(1));
+        *limiter.windows.lock() = Clients::new(2);
+        let now = Instant::now();
+        let first: IpAddr = "10.0.0.1".parse().unwrap();
+        let second: IpAddr = "10.0.0.2".parse().unwrap();
+        let third: IpAddr = "10.0.0.3".parse().unwrap();
+        assert!(limiter.check_at(first, now).is_ok());
+        assert!(limiter.check_at(second, now).is_ok());
+
+        // A flood of new addresses can't evict the limited clients
+        assert!(limiter.check_at(third, now).is_err());
+        assert!(limiter.check_at(first, now).is_err());
+        assert!(limiter.check_at(second, now).is_err());
+        assert!(limiter
+            .check_at(third, now + Duration::from_secs(1))
+            .is_ok());
+    }
+}
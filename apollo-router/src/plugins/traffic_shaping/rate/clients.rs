@@ -0,0 +1,83 @@
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+
+use lru::LruCache;
+
+/// The number of clients tracked at once by the client limiters.
+pub(super) const MAX_CLIENTS: usize = 100_000;
+
+/// The limiting state of the most recently seen clients, bounded so that a flood of addresses
+/// can't grow it without bound.
+///
+/// When it is full, the least recently seen client is forgotten if its state expired. Otherwise
+/// the new client is refused, so that a flood of addresses can't evict active clients to reset
+/// their limits.
+#[derive(Debug)]
+pub(super) struct Clients<T> {
+    states: LruCache<IpAddr, T>,
+}
+
+impl<T> Clients<T> {
+    pub(super) fn new(max_clients: usize) -> Self {
+        Self {
+            states: LruCache::new(NonZeroUsize::new(max_clients).unwrap_or(NonZeroUsize::MIN)),
+        }
+    }
+
+    /// The state of a client, created if the client is not tracked yet. Returns `None` if the
+    /// client can't be tracked because all the tracked states are still live.
+    pub(super) fn get_or_insert(
+        &mut self,
+        client: IpAddr,
+        expired: impl Fn(&T) -> bool,
+        new: impl FnOnce() -> T,
+    ) -> Option<&mut T> {
+        if !self.states.contains(&client) {
+            if self.states.len() == self.states.cap().get() {
+                let (_, least_recent) = self.states.peek_lru()?;
+                if !expired(least_recent) {
+                    return None;
+                }
+                self.states.pop_lru();
+            }
+            self.states.put(client, new());
+        }
+        self.states.get_mut(&client)
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.states.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_evicts_the_least_recent_expired_client() {
+        let mut clients = Clients::new(2);
+        let client = |i| IpAddr::from([10, 0, 0, i]);
+        *clients.get_or_insert(client(1), |_| false, || 1).unwrap() += 1;
+        clients.get_or_insert(client(2), |_| false, || 20).unwrap();
+        // The first client is now the most recent one
+        assert_eq!(
+            clients.get_or_insert(client(1), |_| false, || 0),
+            Some(&mut 2)
+        );
+
+        // New clients are refused while the least recent client is live
+        assert_eq!(clients.get_or_insert(client(3), |_| false, || 3), None);
+        assert_eq!(
+            clients.get_or_insert(client(3), |state| *state == 20, || 3),
+            Some(&mut 3)
+        );
+        assert_eq!(clients.len(), 2);
+        // The second client was evicted, not the first one
+        assert_eq!(
+            clients.get_or_insert(client(1), |_| true, || 0),
+            Some(&mut 2)
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use std::net::IpAddr;
+use std::num::NonZeroU64;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use parking_lot::Mutex;
+
+use super::clients::Clients;
+use super::clients::MAX_CLIENTS;
+use super::Rate;
+use super::RateLimitHeaders;
+use super::RateLimited;
+
+/// Limits the cost of the operations of each client address, with a token bucket per client.
+///
+/// Each bucket holds up to `num` cost units and is refilled continuously, at `num` units per
+/// `per`. An operation is accepted if the bucket holds at least its cost, which is then taken
+/// from the bucket. Operations costing more than the capacity cost the whole capacity, so that
+/// they can still run when the bucket is full.
+#[derive(Debug, Clone)]
+pub(crate) struct ClientCostLimiter {
+    rate: Rate,
+    buckets: Arc<Mutex<Clients<Bucket>>>,
+    headers: Option<Arc<RateLimitHeaders>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl ClientCostLimiter {
+    pub(crate) fn new(num: NonZeroU64, per: Duration) -> Self {
+        Self {
+            rate: Rate::new(num, per),
+            buckets: Arc::new(Mutex::new(Clients::new(MAX_CLIENTS))),
+            headers: None,
+        }
+    }
+
+    /// Add rate limit headers to the errors returned when the limit is reached.
+    pub(crate) fn with_headers(mut self, headers: Option<Arc<RateLimitHeaders>>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Take the cost of an operation from the bucket of the client, failing if the bucket does
+    /// not hold enough
+    pub(crate) fn check(&self, client: IpAddr, cost: u64) -> Result<(), RateLimited> {
+        self.check_at(client, cost, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, cost: u64, now: Instant) -> Result<(), RateLimited> {
+        let per = self.rate.per();
+        let capacity = self.rate.num() as f64;
+        let mut buckets = self.buckets.lock();
+
+        // A bucket is full again after one interval, it can be forgotten
+        let Some(bucket) = buckets.get_or_insert(
+            client,
+            |bucket| now.duration_since(bucket.updated) >= per,
+            || Bucket {
+                tokens: capacity,
+                updated: now,
+            },
+        ) else {
+            tracing::debug!("too many clients to cost limit, refusing a new client");
+            return Err(self.rate_limited(0, per));
+        };
+        let refill = now.duration_since(bucket.updated).as_secs_f64() / per.as_secs_f64();
+        bucket.tokens = (bucket.tokens + refill * capacity).min(capacity);
+        bucket.updated = now;
+
+        let cost = (cost as f64).min(capacity);
+        if bucket.tokens < cost {
+            tracing::trace!("client cost limit exceeded");
+            let reset = per.mul_f64((cost - bucket.tokens) / capacity);
+            return Err(self.rate_limited(bucket.tokens as u64, reset));
+        }
+        bucket.tokens -= cost;
+        Ok(())
+    }
+
+    fn rate_limited(&self, remaining: u64, reset: Duration) -> RateLimited {
+        match &self.headers {
+            Some(headers) => {
+                RateLimited::with_headers(headers.header_map(&self.rate, remaining, reset))
+            }
+            None => RateLimited::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn it_limits_the_cost_of_each_client() {
+        let limiter = ClientCostLimiter::new(NonZeroU64::new(10).unwrap(), Duration::from_secs(10))
+            .with_headers(Some(Arc::new(RateLimitHeaders::default())));
+        let first: IpAddr = "10.0.0.1".parse().unwrap();
+        let second: IpAddr = "10.0.0.2".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.check_at(first, 6, now).is_ok());
+        assert!(limiter.check_at(first, 3, now).is_ok());
+        let error = limiter.check_at(first, 4, now).unwrap_err();
+        assert_eq!(error.headers().get("ratelimit-remaining").unwrap(), "1");
+        assert_eq!(error.headers().get("retry-after").unwrap(), "3");
+        // Cheap operations still fit in the bucket
+        assert!(limiter.check_at(first, 1, now).is_ok());
+        assert!(limiter.check_at(second, 10, now).is_ok());
+
+        // The bucket is refilled over time
+        assert!(limiter
+            .check_at(first, 4, now + Duration::from_secs(3))
+            .is_err());
+        assert!(limiter
+            .check_at(first, 4, now + Duration::from_secs(4))
+            .is_ok());
+    }
+
+    #[test]
+    fn it_accepts_operations_costing_more_than_the_capacity() {
+        let limiter = ClientCostLimiter::new(NonZeroU64::new(10).unwrap(), Duration::from_secs(10));
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(limiter.check_at(client, 100, now).is_ok());
+        assert!(limiter.check_at(client, 1, now).is_err());
+        assert!(limiter
+            .check_at(client, 100, now + Duration::from_secs(10))
+            .is_ok());
+    }
+
+    #[test]
+    fn it_forgets_full_buckets() {
+        let limiter = ClientCostLimiter::new(NonZeroU64::new(1).unwrap(), Duration::from_secs(1));
+        *limiter.buckets.lock() = Clients::new(2);
+        let now = Instant::now();
+        let first: IpAddr = "10.0.0.1".parse().unwrap();
+        let second: IpAddr = "10.0.0.2".parse().unwrap();
+        assert!(limiter.check_at(first, 1, now).is_ok());
+        assert!(limiter.check_at(second, 1, now).is_ok());
+
+        // A flood of new addresses can't evict the clients whose bucket is not full again
+        assert!(limiter
+            .check_at("10.0.0.3".parse().unwrap(), 1, now)
+            .is_err());
+        assert!(limiter.check_at(first, 1, now).is_err());
+
+        // The bucket of the second client is full again, it makes room for a new client
+        assert!(limiter
+            .check_at("10.0.0.3".parse().unwrap(), 1, now + Duration::from_secs(1))
+            .is_ok());
+        assert_eq!(limiter.buckets.lock().len(), 2);
+    }
+}
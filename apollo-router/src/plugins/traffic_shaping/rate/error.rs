@@ -4,16 +4,35 @@ use std::error;
 use std::fmt;
 
 use axum::response::IntoResponse;
+use axum::Json;
+use http::HeaderMap;
 use http::StatusCode;
 
+use crate::graphql;
+
 /// The rate limit error.
-#[derive(Debug, Default)]
-pub(crate) struct RateLimited;
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RateLimited {
+    /// Rate limit headers computed from the limiter state, empty if disabled
+    headers: HeaderMap,
+}
 
 impl RateLimited {
     /// Construct a new RateLimited error
     pub(crate) fn new() -> Self {
-        RateLimited {}
+        RateLimited {
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Construct a new RateLimited error carrying rate limit headers
+    pub(crate) fn with_headers(headers: HeaderMap) -> Self {
+        RateLimited { headers }
+    }
+
+    /// Rate limit headers to add to the client response
+    pub(crate) fn headers(&self) -> &HeaderMap {
+        &self.headers
     }
 }
 
@@ -25,7 +44,17 @@ impl fmt::Display for RateLimited {
 
 impl IntoResponse for RateLimited {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::TOO_MANY_REQUESTS, self.to_string()).into_response()
+        let body = graphql::Response::builder()
+            .error(
+                graphql::Error::builder()
+                    .message(self.to_string())
+                    .extension_code("REQUEST_RATE_LIMITED")
+                    .build(),
+            )
+            .build();
+        let mut response = (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response();
+        response.headers_mut().extend(self.headers);
+        response
     }
 }
 
@@ -0,0 +1,65 @@
+//! Rate limit response headers
+
+use std::time::Duration;
+
+use http::header::HeaderName;
+use http::header::RETRY_AFTER;
+use http::HeaderMap;
+use http::HeaderValue;
+
+use super::Rate;
+
+/// Names of the headers describing the limiter state to clients.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimitHeaders {
+    pub(crate) limit: HeaderName,
+    pub(crate) remaining: HeaderName,
+    pub(crate) reset: HeaderName,
+    pub(crate) retry_after: HeaderName,
+}
+
+impl Default for RateLimitHeaders {
+    fn default() -> Self {
+        Self {
+            limit: HeaderName::from_static("ratelimit-limit"),
+            remaining: HeaderName::from_static("ratelimit-remaining"),
+            reset: HeaderName::from_static("ratelimit-reset"),
+            retry_after: RETRY_AFTER,
+        }
+    }
+}
+
+impl RateLimitHeaders {
+    /// Compute the header values for the given limiter state.
+    ///
+    /// `reset` is the time left before the current window ends, it is rounded up to the second
+    /// so that clients waiting for `Retry-After` will not be rejected again.
+    pub(crate) fn header_map(&self, rate: &Rate, remaining: u64, reset: Duration) -> HeaderMap {
+        let reset_secs = reset.as_secs() + u64::from(reset.subsec_nanos() > 0);
+        let mut headers = HeaderMap::with_capacity(4);
+        headers.insert(self.limit.clone(), HeaderValue::from(rate.num()));
+        headers.insert(self.remaining.clone(), HeaderValue::from(remaining));
+        headers.insert(self.reset.clone(), HeaderValue::from(reset_secs));
+        headers.insert(self.retry_after.clone(), HeaderValue::from(reset_secs));
+        headers
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU64;
+
+    use super::*;
+
+    #[test]
+    fn it_computes_headers_from_limiter_state() {
+        let rate = Rate::new(NonZeroU64::new(10).unwrap(), Duration::from_secs(60));
+        let headers =
+            RateLimitHeaders::default().header_map(&rate, 0, Duration::from_millis(12_300));
+
+        assert_eq!(headers.get("ratelimit-limit").unwrap(), "10");
+        assert_eq!(headers.get("ratelimit-remaining").unwrap(), "0");
+        assert_eq!(headers.get("ratelimit-reset").unwrap(), "13");
+        assert_eq!(headers.get(RETRY_AFTER).unwrap(), "13");
+    }
+}
@@ -10,6 +10,7 @@ use tower::Layer;
 
 use super::Rate;
 use super::RateLimit;
+use super::RateLimitHeaders;
 /// Enforces a rate limit on the number of requests the underlying
 /// service can handle over a period of time.
 #[derive(Debug, Clone)]
@@ -18,6 +19,7 @@ pub(crate) struct RateLimitLayer {
     window_start: Arc<AtomicU64>,
     previous_nb_requests: Arc<AtomicUsize>,
     current_nb_requests: Arc<AtomicUsize>,
+    headers: Option<Arc<RateLimitHeaders>>,
 }
 
 impl RateLimitLayer {
@@ -34,8 +36,15 @@ impl RateLimitLayer {
             )),
             previous_nb_requests: Arc::default(),
             current_nb_requests: Arc::new(AtomicUsize::new(1)),
+            headers: None,
         }
     }
+
+    /// Add rate limit headers to the errors returned when the limit is reached.
+    pub(crate) fn with_headers(mut self, headers: Option<Arc<RateLimitHeaders>>) -> Self {
+        self.headers = headers;
+        self
+    }
 }
 
 impl<S> Layer<S> for RateLimitLayer {
@@ -48,6 +57,7 @@ impl<S> Layer<S> for RateLimitLayer {
             window_start: self.window_start.clone(),
             previous_nb_requests: self.previous_nb_requests.clone(),
             current_nb_requests: self.current_nb_requests.clone(),
+            headers: self.headers.clone(),
         }
     }
 }
@@ -1,13 +1,20 @@
 //! Limit the rate at which requests are processed.
 
+mod client;
+mod clients;
+mod cost;
 mod error;
 pub(crate) mod future;
+mod headers;
 mod layer;
 #[allow(clippy::module_inception)]
 mod rate;
 pub(crate) mod service;
 
+pub(crate) use self::client::ClientRateLimiter;
+pub(crate) use self::cost::ClientCostLimiter;
 pub(crate) use self::error::RateLimited;
+pub(crate) use self::headers::RateLimitHeaders;
 pub(crate) use self::layer::RateLimitLayer;
 pub(crate) use self::rate::Rate;
 pub(crate) use self::service::RateLimit;
@@ -4,6 +4,7 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
@@ -12,6 +13,7 @@ use tower::Service;
 
 use super::future::ResponseFuture;
 use super::Rate;
+use super::RateLimitHeaders;
 use crate::plugins::traffic_shaping::rate::error::RateLimited;
 
 #[derive(Debug, Clone)]
@@ -24,6 +26,8 @@ pub(crate) struct RateLimit<T> {
     pub(crate) window_start: Arc<AtomicU64>,
     pub(crate) previous_nb_requests: Arc<AtomicUsize>,
     pub(crate) current_nb_requests: Arc<AtomicUsize>,
+    /// Headers describing the limiter state, added to the error when the limit is reached
+    pub(crate) headers: Option<Arc<RateLimitHeaders>>,
 }
 
 impl<S, Request> Service<Request> for RateLimit<S>
@@ -69,7 +73,20 @@ where
 
         if estimated_cap as u64 > self.rate.num() {
             tracing::trace!("rate limit exceeded; sleeping.");
-            return Poll::Ready(Err(RateLimited::new().into()));
+            let error = match &self.headers {
+                Some(headers) => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .expect("system time must be after EPOCH")
+                        .as_millis() as u64;
+                    let window_end = self.window_start.load(Ordering::SeqCst) + time_unit;
+                    let reset = Duration::from_millis(window_end.saturating_sub(now));
+                    let remaining = self.rate.num().saturating_sub(estimated_cap as u64);
+                    RateLimited::with_headers(headers.header_map(&self.rate, remaining, reset))
+                }
+                None => RateLimited::new(),
+            };
+            return Poll::Ready(Err(error.into()));
         }
 
         self.current_nb_requests.fetch_add(1, Ordering::SeqCst);
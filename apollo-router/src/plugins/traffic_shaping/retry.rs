@@ -1,18 +1,68 @@
-use std::future;
+use std::num::NonZeroUsize;
 use std::sync::Arc;
 use std::time::Duration;
 
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rand::Rng;
 use tower::retry::budget::Budget;
 use tower::retry::Policy;
+use tower::BoxError;
 
+use super::circuit_breaker::CircuitOpen;
+use super::concurrency_limit::ConcurrencyLimited;
 use crate::query_planner::OperationKind;
 use crate::services::subgraph;
 
+/// A context entry that Rhai scripts and coprocessors set to `true` to flag the operations of a
+/// request as idempotent, so that its mutations can be retried
+pub(crate) const CONTEXT_IDEMPOTENT: &str = "apollo_retry::idempotent";
+
+/// The ordinal number of the attempt of a retried subgraph request, starting at 1 for the first
+/// retry. It is added to the extensions of the HTTP request, and recorded as the
+/// `http.resend_count` attribute of the `subgraph_request` span
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ResendCount(pub(crate) usize);
+
+/// Exponential backoff between attempts, with jitter
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Backoff {
+    min_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(min_delay: Option<Duration>, max_delay: Option<Duration>) -> Self {
+        let min_delay = min_delay.unwrap_or_else(|| Duration::from_millis(100));
+        Self {
+            min_delay,
+            max_delay: max_delay
+                .unwrap_or_else(|| Duration::from_secs(5))
+                .max(min_delay),
+        }
+    }
+
+    /// The delay before a retry, doubling from `min_delay` with each retry up to `max_delay`,
+    /// then randomly reduced by up to half so that the retries of concurrent requests spread out
+    fn delay(&self, retry: usize) -> Duration {
+        let exponent = u32::try_from(retry.saturating_sub(1)).unwrap_or(u32::MAX);
+        let delay = self
+            .min_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay);
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
 #[derive(Clone, Default)]
 pub(crate) struct RetryPolicy {
     budget: Arc<Budget>,
     retry_mutations: bool,
+    max_attempts: Option<NonZeroUsize>,
+    backoff: Option<Backoff>,
     subgraph_name: String,
+    /// number of retries of the current request
+    retries: usize,
 }
 
 impl RetryPolicy {
@@ -21,6 +71,8 @@ impl RetryPolicy {
         min_per_sec: Option<u32>,
         retry_percent: Option<f32>,
         retry_mutations: Option<bool>,
+        max_attempts: Option<NonZeroUsize>,
+        backoff: Option<Backoff>,
         subgraph_name: String,
     ) -> Self {
         Self {
@@ -30,15 +82,36 @@ impl RetryPolicy {
                 retry_percent.unwrap_or(0.2),
             )),
             retry_mutations: retry_mutations.unwrap_or(false),
+            max_attempts,
+            backoff,
             subgraph_name,
+            retries: 0,
+        }
+    }
+
+    /// Only queries are retried, unless the operation is flagged as idempotent
+    fn is_idempotent(&self, req: &subgraph::Request) -> bool {
+        match req.operation_kind {
+            OperationKind::Query => true,
+            OperationKind::Mutation if self.retry_mutations => true,
+            _ => req
+                .context
+                .get::<_, bool>(CONTEXT_IDEMPOTENT)
+                .ok()
+                .flatten()
+                .unwrap_or(false),
         }
     }
 }
 
-impl<Res, E> Policy<subgraph::Request, Res, E> for RetryPolicy {
-    type Future = future::Ready<Self>;
+impl<Res> Policy<subgraph::Request, Res, BoxError> for RetryPolicy {
+    type Future = BoxFuture<'static, Self>;
 
-    fn retry(&self, req: &subgraph::Request, result: Result<&Res, &E>) -> Option<Self::Future> {
+    fn retry(
+        &self,
+        req: &subgraph::Request,
+        result: Result<&Res, &BoxError>,
+    ) -> Option<Self::Future> {
         match result {
             Ok(_) => {
                 // Treat all `Response`s as success,
@@ -46,10 +119,26 @@ impl<Res, E> Policy<subgraph::Request, Res, E> for RetryPolicy {
                 self.budget.deposit();
                 None
             }
-            Err(_e) => {
-                if req.operation_kind == OperationKind::Mutation && !self.retry_mutations {
+            Err(e) => {
+                // The rejections of the circuit breaker and of the concurrency limit would repeat
+                if e.is::<CircuitOpen>() || e.is::<ConcurrencyLimited>() {
                     return None;
                 }
+                if !self.is_idempotent(req) {
+                    return None;
+                }
+
+                if let Some(max_attempts) = self.max_attempts {
+                    if self.retries + 1 >= max_attempts.get() {
+                        tracing::info!(
+                            monotonic_counter.apollo_router_http_request_retry_total = 1u64,
+                            status = "exhausted",
+                            subgraph = %self.subgraph_name,
+                        );
+
+                        return None;
+                    }
+                }
 
                 let withdrew = self.budget.withdraw();
                 if withdrew.is_err() {
@@ -67,12 +156,152 @@ impl<Res, E> Policy<subgraph::Request, Res, E> for RetryPolicy {
                     subgraph = %self.subgraph_name,
                 );
 
-                Some(future::ready(self.clone()))
+                let mut policy = self.clone();
+                policy.retries += 1;
+                match self.backoff {
+                    Some(backoff) => {
+                        let delay = backoff.delay(policy.retries);
+                        Some(
+                            async move {
+                                tokio::time::sleep(delay).await;
+                                policy
+                            }
+                            .boxed(),
+                        )
+                    }
+                    None => Some(futures::future::ready(policy).boxed()),
+                }
             }
         }
     }
 
     fn clone_request(&self, req: &subgraph::Request) -> Option<subgraph::Request> {
-        Some(req.clone())
+        // the clone is sent if this attempt fails, as the next retry
+        let mut req = req.clone();
+        req.subgraph_request
+            .extensions_mut()
+            .insert(ResendCount(self.retries + 1));
+        Some(req)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::Context;
+
+    /// Sends a request to a subgraph that always fails, and returns the resend counts it saw
+    async fn attempts(policy: RetryPolicy, request: subgraph::Request) -> Vec<Option<usize>> {
+        attempts_with_error(policy, request, || "subgraph error".into()).await
+    }
+
+    async fn attempts_with_error(
+        policy: RetryPolicy,
+        request: subgraph::Request,
+        error: fn() -> BoxError,
+    ) -> Vec<Option<usize>> {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let service = tower::service_fn({
+            let seen = seen.clone();
+            move |req: subgraph::Request| {
+                seen.lock().unwrap().push(
+                    req.subgraph_request
+                        .extensions()
+                        .get::<ResendCount>()
+                        .map(|count| count.0),
+                );
+                async move { Err::<subgraph::Response, BoxError>(error()) }
+            }
+        });
+        let _ = tower::retry::Retry::new(policy, service)
+            .oneshot(request)
+            .await;
+        let mut attempts = Vec::new();
+        attempts.append(&mut seen.lock().unwrap());
+        attempts
+    }
+
+    fn policy(max_attempts: usize, backoff: Option<Backoff>) -> RetryPolicy {
+        RetryPolicy::new(
+            None,
+            Some(100),
+            None,
+            None,
+            NonZeroUsize::new(max_attempts),
+            backoff,
+            "products".to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn it_retries_queries_up_to_max_attempts() {
+        let request = subgraph::Request::fake_builder().build();
+        assert_eq!(
+            attempts(policy(3, None), request).await,
+            vec![None, Some(1), Some(2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_only_retries_idempotent_mutations() {
+        let mutation = || {
+            subgraph::Request::fake_builder()
+                .operation_kind(OperationKind::Mutation)
+                .build()
+        };
+        assert_eq!(attempts(policy(3, None), mutation()).await, vec![None]);
+
+        let context = Context::new();
+        context.insert(CONTEXT_IDEMPOTENT, true).unwrap();
+        let request = subgraph::Request::fake_builder()
+            .operation_kind(OperationKind::Mutation)
+            .context(context)
+            .build();
+        assert_eq!(
+            attempts(policy(2, None), request).await,
+            vec![None, Some(1)]
+        );
+
+        let mut retry_mutations = policy(2, None);
+        retry_mutations.retry_mutations = true;
+        assert_eq!(
+            attempts(retry_mutations, mutation()).await,
+            vec![None, Some(1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_does_not_retry_rejections_of_the_traffic_shaping_layers() {
+        let request = || subgraph::Request::fake_builder().build();
+        assert_eq!(
+            attempts_with_error(policy(3, None), request(), || CircuitOpen.into()).await,
+            vec![None]
+        );
+        assert_eq!(
+            attempts_with_error(policy(3, None), request(), || ConcurrencyLimited.into()).await,
+            vec![None]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_backs_off_between_attempts() {
+        let backoff = Backoff::new(
+            Some(Duration::from_millis(10)),
+            Some(Duration::from_millis(30)),
+        );
+        for (retry, max) in [(1, 10), (2, 20), (3, 30), (10, 30)] {
+            let delay = backoff.delay(retry);
+            assert!(delay >= Duration::from_millis(max / 2));
+            assert!(delay <= Duration::from_millis(max));
+        }
+
+        let start = std::time::Instant::now();
+        let request = subgraph::Request::fake_builder().build();
+        assert_eq!(attempts(policy(3, Some(backoff)), request).await.len(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(15));
     }
 }
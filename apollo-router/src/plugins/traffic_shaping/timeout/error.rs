@@ -4,8 +4,11 @@ use std::error;
 use std::fmt;
 
 use axum::response::IntoResponse;
+use axum::Json;
 use http::StatusCode;
 
+use crate::graphql;
+
 /// The timeout elapsed.
 #[derive(Debug, Default)]
 pub(crate) struct Elapsed;
@@ -25,7 +28,15 @@ impl fmt::Display for Elapsed {
 
 impl IntoResponse for Elapsed {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::GATEWAY_TIMEOUT, self.to_string()).into_response()
+        let response = graphql::Response::builder()
+            .error(
+                graphql::Error::builder()
+                    .message(self.to_string())
+                    .extension_code("ROUTER_TIMEOUT")
+                    .build(),
+            )
+            .build();
+        (StatusCode::GATEWAY_TIMEOUT, Json(response)).into_response()
     }
 }
 
@@ -195,7 +195,14 @@ impl BridgeQueryPlanner {
 
         let schema = Arc::new(schema.with_api_schema(api_schema));
         let introspection = if configuration.supergraph.introspection {
-            Some(Arc::new(Introspection::new(planner.clone()).await))
+            Some(Arc::new(
+                Introspection::with_hidden_coordinates(
+                    planner.clone(),
+                    schema.clone(),
+                    &configuration,
+                )
+                .await?,
+            ))
         } else {
             None
         };
@@ -251,7 +258,14 @@ impl BridgeQueryPlanner {
         let schema = Arc::new(Schema::parse(&schema, &configuration)?.with_api_schema(api_schema));
 
         let introspection = if configuration.supergraph.introspection {
-            Some(Arc::new(Introspection::new(planner.clone()).await))
+            Some(Arc::new(
+                Introspection::with_hidden_coordinates(
+                    planner.clone(),
+                    schema.clone(),
+                    &configuration,
+                )
+                .await?,
+            ))
         } else {
             None
         };
@@ -545,6 +559,7 @@ impl Service<QueryPlannerRequest> for BridgeQueryPlanner {
                         // TODO: check the latter?
                         parse_errors: doc.parse_errors.clone(),
                         validation_errors: doc.validation_errors.clone(),
+                        rule_violations: doc.rule_violations.clone(),
                     });
                     context
                         .private_entries
@@ -657,6 +672,7 @@ impl BridgeQueryPlanner {
                 // TODO: check the latter?
                 parse_errors: doc.parse_errors.clone(),
                 validation_errors: doc.validation_errors.clone(),
+                rule_violations: doc.rule_violations.clone(),
             });
             selections.unauthorized.paths = unauthorized_paths;
         }
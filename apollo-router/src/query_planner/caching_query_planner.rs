@@ -1,23 +1,33 @@
 use std::collections::HashMap;
 use std::ops::Deref;
+use std::path::Path;
 use std::sync::Arc;
 use std::task;
+use std::time::Duration;
+use std::time::Instant;
 
 use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
 use indexmap::IndexMap;
 use query_planner::QueryPlannerPlugin;
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use router_bridge::planner::Planner;
 use router_bridge::planner::UsageReporting;
+use serde::Deserialize;
 use sha2::Digest;
 use sha2::Sha256;
+use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
 use tower_service::Service;
 use tracing::Instrument;
 
+use crate::cache::key::cache_key_extension;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::CacheKeyConfig;
+use crate::configuration::WarmUp;
 use crate::error::CacheResolverError;
 use crate::error::QueryPlannerError;
 use crate::plugins::authorization::AuthorizationPlugin;
@@ -27,6 +37,7 @@ use crate::query_planner::labeler::add_defer_labels;
 use crate::query_planner::BridgeQueryPlanner;
 use crate::query_planner::QueryPlanResult;
 use crate::services::layers::persisted_queries::PersistedQueryLayer;
+use crate::services::layers::persisted_queries::SignedUrlChunk;
 use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::layers::query_analysis::QueryAnalysisLayer;
 use crate::services::query_planner;
@@ -39,9 +50,25 @@ use crate::spec::SpecError;
 use crate::Configuration;
 use crate::Context;
 
+/// The version of the query plan cache keys, to increment when the format of the cached query
+/// plans changes, so that routers do not read plans they cannot deserialize from the distributed
+/// cache
+pub(crate) const CACHE_KEY_VERSION: usize = 1;
+
 /// An [`IndexMap`] of available plugins.
 pub(crate) type Plugins = IndexMap<String, Box<dyn QueryPlannerPlugin>>;
 
+/// How the query plan of a request was obtained, stored in the context's private entries
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PlanSource {
+    /// The plan was found in the cache
+    CacheHit,
+    /// The plan was computed by a concurrent request for the same query
+    Reused,
+    /// The plan was computed for this request
+    Planned,
+}
+
 /// A query planner wrapper that caches results.
 ///
 /// The query planner performs LRU caching.
@@ -54,8 +81,14 @@ pub(crate) struct CachingQueryPlanner<T: Clone> {
     schema: Arc<Schema>,
     plugins: Arc<Plugins>,
     enable_authorization_directives: bool,
+    warm_up: WarmUp,
+    key: CacheKeyConfig,
 }
 
+/// The extra dimensions of the cache key of a request, stored in the context's private entries
+#[derive(Clone, Debug)]
+struct CacheKeyExtension(String);
+
 impl<T: Clone + 'static> CachingQueryPlanner<T>
 where
     T: tower::Service<
@@ -88,6 +121,28 @@ where
             schema,
             plugins: Arc::new(plugins),
             enable_authorization_directives,
+            warm_up: configuration
+                .supergraph
+                .query_planning
+                .experimental_warmup
+                .clone(),
+            key: configuration
+                .supergraph
+                .query_planning
+                .experimental_cache
+                .key
+                .clone(),
+        }
+    }
+
+    /// Adds the extra dimensions of the cache key of a request to its context, before it is
+    /// planned
+    pub(crate) fn extend_cache_key(&self, headers: &http::HeaderMap, context: &Context) {
+        if let Some(extension) = cache_key_extension(&self.key, headers, context) {
+            context
+                .private_entries
+                .lock()
+                .insert(CacheKeyExtension(extension));
         }
     }
 
@@ -100,6 +155,7 @@ where
                 query: key.query,
                 operation: key.operation,
                 metadata: key.metadata,
+                extension: key.extension,
             })
             .collect()
     }
@@ -115,6 +171,10 @@ where
                 histogram.apollo.router.query.planning.warmup.duration = duration.as_secs_f64()
             );
         });
+        let deadline = self
+            .warm_up
+            .time_budget
+            .map(|budget| Instant::now() + budget);
         let schema_id = self.schema.schema_id.clone();
 
         let mut service = ServiceBuilder::new().service(
@@ -129,8 +189,19 @@ where
         cache_keys.shuffle(&mut thread_rng());
 
         let persisted_queries_operations = persisted_query_layer.all_operations();
+        let manifest_keys = match &self.warm_up.manifest {
+            Some(path) => read_warm_up_manifest(path).unwrap_or_else(|e| {
+                tracing::error!(
+                    "could not read the query plan warm up manifest at {}: {e}",
+                    path.display()
+                );
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
 
         let capacity = cache_keys.len()
+            + manifest_keys.len()
             + persisted_queries_operations
                 .as_ref()
                 .map(|ops| ops.len())
@@ -149,24 +220,44 @@ where
                     query,
                     operation: None,
                     metadata: CacheKeyMetadata::default(),
+                    extension: None,
                 });
             }
         }
 
+        all_cache_keys.extend(manifest_keys.into_iter());
         all_cache_keys.extend(cache_keys.into_iter());
 
+        let concurrency = self.warm_up.concurrency.get();
+        let mut in_flight = FuturesUnordered::new();
         let mut count = 0usize;
-        for WarmUpCachingQueryKey {
+        let mut planned = 0u64;
+        let mut errors = 0u64;
+        let mut skipped = 0u64;
+        let mut all_cache_keys = all_cache_keys.into_iter();
+        while let Some(WarmUpCachingQueryKey {
             mut query,
             operation,
             metadata,
-        } in all_cache_keys
+            extension,
+        }) = all_cache_keys.next()
         {
+            // the plans already started are kept, the remaining queries will be planned on their
+            // first request
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                skipped = 1 + all_cache_keys.len() as u64;
+                tracing::warn!(
+                    "the query plan cache warm up exceeded its time budget, {skipped} queries were not planned"
+                );
+                break;
+            }
+
             let caching_key = CachingQueryKey {
                 schema_id: schema_id.clone(),
                 query: query.clone(),
                 operation: operation.clone(),
                 metadata,
+                extension,
             };
             let context = Context::new();
 
@@ -175,6 +266,7 @@ where
                 let doc = query_analysis.parse_document(&query);
                 let err_res = Query::check_errors(&doc);
                 if let Err(error) = err_res {
+                    errors += 1;
                     let e = Arc::new(QueryPlannerError::SpecError(error));
                     entry.insert(Err(e)).await;
                     continue;
@@ -196,30 +288,78 @@ where
                 };
 
                 let res = match service.ready().await {
-                    Ok(service) => service.call(request).await,
+                    Ok(service) => service.call(request),
                     Err(_) => break,
                 };
 
-                match res {
-                    Ok(QueryPlannerResponse { content, .. }) => {
-                        if let Some(content) = &content {
-                            count += 1;
-                            entry.insert(Ok(content.clone())).await;
+                in_flight.push(async move {
+                    match res.await {
+                        Ok(QueryPlannerResponse { content, .. }) => match content {
+                            Some(content) => {
+                                entry.insert(Ok(content)).await;
+                                Some(true)
+                            }
+                            None => None,
+                        },
+                        Err(error) => {
+                            entry.insert(Err(Arc::new(error))).await;
+                            Some(false)
                         }
                     }
-                    Err(error) => {
-                        count += 1;
-                        let e = Arc::new(error);
-                        entry.insert(Err(e.clone())).await;
+                });
+
+                while in_flight.len() >= concurrency {
+                    if let Some(result) = in_flight.next().await {
+                        count_warm_up_result(result, &mut count, &mut planned, &mut errors);
                     }
                 }
             }
         }
 
+        while let Some(result) = in_flight.next().await {
+            count_warm_up_result(result, &mut count, &mut planned, &mut errors);
+        }
+
+        for (result, queries) in [
+            ("planned", planned),
+            ("error", errors),
+            ("skipped", skipped),
+        ] {
+            if queries > 0 {
+                u64_counter!(
+                    "apollo.router.query_planning.warmup.queries",
+                    "Number of queries planned while warming up the query plan cache",
+                    queries,
+                    "result" = result
+                );
+            }
+        }
+
         tracing::debug!("warmed up the query planner cache with {count} queries");
     }
 }
 
+/// Counts the result of a warm up plan: `Some(true)` if a plan was stored, `Some(false)` for an
+/// error, `None` if the planner returned nothing
+fn count_warm_up_result(
+    result: Option<bool>,
+    count: &mut usize,
+    planned: &mut u64,
+    errors: &mut u64,
+) {
+    match result {
+        Some(true) => {
+            *count += 1;
+            *planned += 1;
+        }
+        Some(false) => {
+            *count += 1;
+            *errors += 1;
+        }
+        None => {}
+    }
+}
+
 impl CachingQueryPlanner<BridgeQueryPlanner> {
     pub(crate) fn planner(&self) -> Arc<Planner<QueryPlanResult>> {
         self.delegate.planner()
@@ -248,21 +388,84 @@ where
         let qp = self.clone();
         Box::pin(async move {
             let context = request.context.clone();
-            qp.plan(request).await.map(|response| {
-                if let Some(usage_reporting) =
-                    context.private_entries.lock().get::<UsageReporting>()
-                {
-                    let _ = response.context.insert(
-                        "apollo_operation_id",
-                        stats_report_key_hash(usage_reporting.stats_report_key.as_str()),
-                    );
-                }
-                response
-            })
+            let start = Instant::now();
+            let result = qp.plan(request).await;
+            // planned queries store the usage reporting in the context of the planner response
+            let operation_id = result
+                .as_ref()
+                .map_or(&context, |response| &response.context)
+                .private_entries
+                .lock()
+                .get::<UsageReporting>()
+                .map(|usage_reporting| {
+                    stats_report_key_hash(usage_reporting.stats_report_key.as_str())
+                });
+            if let (Ok(response), Some(operation_id)) = (&result, &operation_id) {
+                let _ = response
+                    .context
+                    .insert(APOLLO_OPERATION_ID, operation_id.clone());
+            }
+            record_planning_metrics(
+                &context,
+                result.as_ref().ok(),
+                operation_id.unwrap_or_default(),
+                start.elapsed(),
+            );
+            result
         })
     }
 }
 
+/// Records how the plan of a request was obtained and, for newly computed plans, how long
+/// planning took and the shape of the plan
+fn record_planning_metrics(
+    context: &Context,
+    response: Option<&QueryPlannerResponse>,
+    operation_id: String,
+    duration: Duration,
+) {
+    let Some(plan_source) = context.private_entries.lock().get::<PlanSource>().copied() else {
+        return;
+    };
+    let cache_status = match plan_source {
+        PlanSource::CacheHit => "hit",
+        PlanSource::Reused => "reused",
+        PlanSource::Planned => "miss",
+    };
+    u64_counter!(
+        "apollo.router.query_planning.cache",
+        "Number of query plan cache lookups",
+        1,
+        "cache.status" = cache_status,
+        "apollo.operation.id" = operation_id.clone()
+    );
+    if plan_source != PlanSource::Planned {
+        return;
+    }
+    f64_histogram!(
+        "apollo.router.query_planning.plan.duration",
+        "Duration of the computation of a query plan, in seconds",
+        duration.as_secs_f64(),
+        "apollo.operation.id" = operation_id.clone()
+    );
+    if let Some(QueryPlannerContent::Plan { plan, .. }) =
+        response.and_then(|response| response.content.as_ref())
+    {
+        u64_histogram!(
+            "apollo.router.query_planning.plan.fetch_nodes",
+            "Number of fetch nodes of a computed query plan",
+            plan.root.subgraph_fetches() as u64,
+            "apollo.operation.id" = operation_id.clone()
+        );
+        u64_histogram!(
+            "apollo.router.query_planning.plan.flatten_nodes",
+            "Number of flatten nodes of a computed query plan",
+            plan.root.flatten_nodes() as u64,
+            "apollo.operation.id" = operation_id
+        );
+    }
+}
+
 impl<T> CachingQueryPlanner<T>
 where
     T: tower::Service<
@@ -295,10 +498,24 @@ where
                 .get::<CacheKeyMetadata>()
                 .cloned()
                 .unwrap_or_default(),
+            extension: request
+                .context
+                .private_entries
+                .lock()
+                .get::<CacheKeyExtension>()
+                .map(|extension| extension.0.clone()),
         };
 
         let context = request.context.clone();
         let entry = self.cache.get(&caching_key).await;
+        let plan_source = if entry.is_first() {
+            PlanSource::Planned
+        } else if entry.is_pending() {
+            PlanSource::Reused
+        } else {
+            PlanSource::CacheHit
+        };
+        context.private_entries.lock().insert(plan_source);
         if entry.is_first() {
             let query_planner::CachingRequest {
                 mut query,
@@ -438,7 +655,10 @@ where
     }
 }
 
-fn stats_report_key_hash(stats_report_key: &str) -> String {
+/// Context key of the GraphOS operation ID, the hash of the operation signature
+pub(crate) const APOLLO_OPERATION_ID: &str = "apollo_operation_id";
+
+pub(crate) fn stats_report_key_hash(stats_report_key: &str) -> String {
     let mut hasher = sha1::Sha1::new();
     hasher.update(stats_report_key.as_bytes());
     let result = hasher.finalize();
@@ -451,6 +671,8 @@ pub(crate) struct CachingQueryKey {
     pub(crate) query: String,
     pub(crate) operation: Option<String>,
     pub(crate) metadata: CacheKeyMetadata,
+    /// The extra dimensions of the key, from [`CacheKeyConfig`]
+    pub(crate) extension: Option<String>,
 }
 
 impl std::fmt::Display for CachingQueryKey {
@@ -469,12 +691,17 @@ impl std::fmt::Display for CachingQueryKey {
 
         write!(
             f,
-            "plan.{}.{}.{}.{}",
+            "plan.v{}.{}.{}.{}.{}",
+            CACHE_KEY_VERSION,
             self.schema_id.as_deref().unwrap_or("-"),
             query,
             operation,
             metadata,
-        )
+        )?;
+        if let Some(extension) = &self.extension {
+            write!(f, ".{extension}")?;
+        }
+        Ok(())
     }
 }
 
@@ -483,6 +710,56 @@ pub(crate) struct WarmUpCachingQueryKey {
     pub(crate) query: String,
     pub(crate) operation: Option<String>,
     pub(crate) metadata: CacheKeyMetadata,
+    pub(crate) extension: Option<String>,
+}
+
+/// The operations planned when warming up the cache
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WarmUpManifest {
+    PersistedQueries(SignedUrlChunk),
+    Operations(Vec<WarmUpOperation>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WarmUpOperation {
+    query: String,
+    operation_name: Option<String>,
+}
+
+/// Reads the operations of a persisted query manifest, or of a JSON array of operations
+pub(crate) fn read_warm_up_manifest(path: &Path) -> Result<Vec<WarmUpCachingQueryKey>, BoxError> {
+    let manifest: WarmUpManifest = serde_json::from_slice(&std::fs::read(path)?)?;
+    Ok(match manifest {
+        WarmUpManifest::PersistedQueries(chunk) => {
+            if chunk.format != "apollo-persisted-query-manifest" {
+                return Err("manifest format is not 'apollo-persisted-query-manifest'".into());
+            }
+            if chunk.version != 1 {
+                return Err("persisted query manifest version is not 1".into());
+            }
+            chunk
+                .operations
+                .into_iter()
+                .map(|operation| WarmUpCachingQueryKey {
+                    query: operation.body,
+                    operation: None,
+                    metadata: CacheKeyMetadata::default(),
+                    extension: None,
+                })
+                .collect()
+        }
+        WarmUpManifest::Operations(operations) => operations
+            .into_iter()
+            .map(|operation| WarmUpCachingQueryKey {
+                query: operation.query,
+                operation: operation.operation_name,
+                metadata: CacheKeyMetadata::default(),
+                extension: None,
+            })
+            .collect(),
+    })
 }
 
 #[cfg(test)]
@@ -495,6 +772,7 @@ mod tests {
 
     use super::*;
     use crate::error::PlanErrors;
+    use crate::metrics::FutureMetricsExt;
     use crate::query_planner::QueryPlan;
     use crate::spec::Query;
     use crate::spec::Schema;
@@ -667,6 +945,239 @@ mod tests {
         }
     }
 
+    #[test(tokio::test)]
+    async fn test_planning_metrics() {
+        async {
+            let mut delegate = MockMyQueryPlanner::new();
+            delegate.expect_clone().returning(|| {
+                let mut planner = MockMyQueryPlanner::new();
+                planner.expect_sync_call().times(0..2).returning(|_| {
+                    let query_plan: QueryPlan = QueryPlan {
+                        formatted_query_plan: Default::default(),
+                        root: serde_json::from_str(test_query_plan!()).unwrap(),
+                        usage_reporting: UsageReporting {
+                            stats_report_key: "this is a test report key".to_string(),
+                            referenced_fields_by_type: Default::default(),
+                        },
+                        query: Arc::new(Query::empty()),
+                    };
+                    Ok(QueryPlannerResponse::builder()
+                        .content(QueryPlannerContent::Plan {
+                            plan: Arc::new(query_plan),
+                        })
+                        .context(Context::new())
+                        .build())
+                });
+                planner
+            });
+
+            let configuration = Configuration::default();
+            let schema =
+                Schema::parse(include_str!("testdata/schema.graphql"), &configuration).unwrap();
+            let doc =
+                Query::parse_document("query Me { me { username } }", &schema, &configuration);
+            let mut planner = CachingQueryPlanner::new(
+                delegate,
+                Arc::new(schema),
+                &configuration,
+                IndexMap::new(),
+            )
+            .await;
+
+            for _ in 0..3 {
+                let context = Context::new();
+                context
+                    .private_entries
+                    .lock()
+                    .insert::<ParsedDocument>(doc.clone());
+                planner
+                    .call(query_planner::CachingRequest::new(
+                        "query Me { me { username } }".to_string(),
+                        Some("".into()),
+                        context,
+                    ))
+                    .await
+                    .unwrap();
+            }
+
+            let operation_id = stats_report_key_hash("this is a test report key");
+            assert_counter!(
+                "apollo.router.query_planning.cache",
+                1,
+                "cache.status" = "miss",
+                "apollo.operation.id" = operation_id.clone()
+            );
+            assert_counter!(
+                "apollo.router.query_planning.cache",
+                2,
+                "cache.status" = "hit",
+                "apollo.operation.id" = operation_id.clone()
+            );
+            // a single plan was computed
+            assert_histogram!(
+                "apollo.router.query_planning.plan.duration",
+                1,
+                "apollo.operation.id" = operation_id.clone()
+            );
+            assert_histogram!(
+                "apollo.router.query_planning.plan.fetch_nodes",
+                1,
+                "apollo.operation.id" = operation_id.clone()
+            );
+            assert_histogram!(
+                "apollo.router.query_planning.plan.flatten_nodes",
+                1,
+                "apollo.operation.id" = operation_id
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[test]
+    fn it_reads_warm_up_manifests() {
+        let directory = tempfile::tempdir().unwrap();
+        let persisted_queries = directory.path().join("persisted-query-manifest.json");
+        std::fs::write(
+            &persisted_queries,
+            serde_json::json!({
+                "format": "apollo-persisted-query-manifest",
+                "version": 1,
+                "operations": [{ "id": "1", "body": "query Me { me { username } }" }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            read_warm_up_manifest(&persisted_queries).unwrap(),
+            vec![WarmUpCachingQueryKey {
+                query: "query Me { me { username } }".to_string(),
+                operation: None,
+                metadata: CacheKeyMetadata::default(),
+                extension: None,
+            }]
+        );
+
+        let operations = directory.path().join("operations.json");
+        std::fs::write(
+            &operations,
+            serde_json::json!([
+                { "query": "query Me { me { username } }", "operationName": "Me" },
+                { "query": "{ me { name { first } } }" }
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        let keys = read_warm_up_manifest(&operations).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].operation.as_deref(), Some("Me"));
+        assert_eq!(keys[1].operation, None);
+
+        assert!(read_warm_up_manifest(&directory.path().join("missing.json")).is_err());
+    }
+
+    async fn warm_up_from_manifest(time_budget: &str) {
+        let mut delegate = MockMyQueryPlanner::new();
+        delegate.expect_clone().returning(|| {
+            let mut planner = MockMyQueryPlanner::new();
+            planner.expect_sync_call().returning(|_| {
+                let query_plan: QueryPlan = QueryPlan {
+                    formatted_query_plan: Default::default(),
+                    root: serde_json::from_str(test_query_plan!()).unwrap(),
+                    usage_reporting: UsageReporting {
+                        stats_report_key: "this is a test report key".to_string(),
+                        referenced_fields_by_type: Default::default(),
+                    },
+                    query: Arc::new(Query::empty()),
+                };
+                Ok(QueryPlannerResponse::builder()
+                    .content(QueryPlannerContent::Plan {
+                        plan: Arc::new(query_plan),
+                    })
+                    .context(Context::new())
+                    .build())
+            });
+            planner
+        });
+
+        let directory = tempfile::tempdir().unwrap();
+        let manifest = directory.path().join("operations.json");
+        std::fs::write(
+            &manifest,
+            serde_json::json!([
+                { "query": "query Me { me { username } }" },
+                { "query": "query Name { me { name { first } } }" },
+                { "query": "query Invalid { me {" }
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        let configuration: Configuration = serde_json::from_value(serde_json::json!({
+            "supergraph": {
+                "query_planning": {
+                    "experimental_warmup": {
+                        "manifest": manifest,
+                        "concurrency": 2,
+                        "time_budget": time_budget
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let configuration = Arc::new(configuration);
+        let schema = Arc::new(
+            Schema::parse(include_str!("testdata/schema.graphql"), &configuration).unwrap(),
+        );
+        let query_analysis = QueryAnalysisLayer::new(schema.clone(), configuration.clone()).await;
+        let persisted_query_layer = PersistedQueryLayer::new(&configuration).await.unwrap();
+        let mut planner =
+            CachingQueryPlanner::new(delegate, schema, &configuration, IndexMap::new()).await;
+
+        planner
+            .warm_up(&query_analysis, &persisted_query_layer, Vec::new())
+            .await;
+    }
+
+    #[test(tokio::test)]
+    async fn it_warms_up_from_a_manifest() {
+        async {
+            warm_up_from_manifest("1m").await;
+            assert_counter!(
+                "apollo.router.query_planning.warmup.queries",
+                2,
+                "result" = "planned"
+            );
+            assert_counter!(
+                "apollo.router.query_planning.warmup.queries",
+                1,
+                "result" = "error"
+            );
+            // nothing was skipped
+            assert!(!crate::metrics::collect_metrics().assert(
+                "apollo.router.query_planning.warmup.queries",
+                crate::metrics::test_utils::MetricType::Counter,
+                0,
+                &[opentelemetry::KeyValue::new("result", "skipped")],
+            ));
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[test(tokio::test)]
+    async fn warm_up_stops_after_its_time_budget() {
+        async {
+            warm_up_from_manifest("0s").await;
+            assert_counter!(
+                "apollo.router.query_planning.warmup.queries",
+                3,
+                "result" = "skipped"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
     #[test]
     fn apollo_operation_id_hash() {
         assert_eq!(
@@ -674,4 +1185,22 @@ mod tests {
             stats_report_key_hash("# IgnitionMeQuery\nquery IgnitionMeQuery{me{id}}")
         );
     }
+
+    #[test]
+    fn cache_keys_are_versioned_by_schema() {
+        let key = |schema_id: &str, extension: Option<&str>| {
+            CachingQueryKey {
+                schema_id: Some(schema_id.to_string()),
+                query: "{ me }".to_string(),
+                operation: None,
+                metadata: Default::default(),
+                extension: extension.map(|extension| extension.to_string()),
+            }
+            .to_string()
+        };
+        assert!(key("schema1", None).starts_with(&format!("plan.v{CACHE_KEY_VERSION}.schema1.")));
+        assert_ne!(key("schema1", None), key("schema2", None));
+        assert!(key("schema1", Some("tenant")).ends_with(".tenant"));
+        assert_ne!(key("schema1", Some("tenant")), key("schema1", None));
+    }
 }
@@ -2,7 +2,6 @@ use std::fmt::Display;
 use std::sync::Arc;
 
 use apollo_compiler::ast::Document;
-use indexmap::IndexSet;
 use serde::Deserialize;
 use serde::Serialize;
 use tower::ServiceExt;
@@ -10,6 +9,7 @@ use tracing::instrument;
 use tracing::Instrument;
 
 use super::execution::ExecutionParameters;
+use super::representations;
 use super::rewrites;
 use super::selection::execute_selection_set;
 use super::selection::Selection;
@@ -25,6 +25,11 @@ use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
 use crate::plugins::authorization::AuthorizationPlugin;
 use crate::plugins::authorization::CacheKeyMetadata;
+use crate::plugins::traffic_shaping::CircuitOpen;
+use crate::plugins::traffic_shaping::ConcurrencyLimited;
+use crate::plugins::traffic_shaping::Elapsed;
+use crate::plugins::traffic_shaping::RateLimited;
+use crate::services::subgraph_service::record_connection_error;
 use crate::services::SubgraphRequest;
 use crate::spec::query::change::QueryHashVisitor;
 use crate::spec::query::traverse;
@@ -167,31 +172,21 @@ impl Variables {
                     .map(|(variable_key, value)| (variable_key.clone(), value.clone()))
             }));
 
-            let mut inverted_paths: Vec<Vec<Path>> = Vec::new();
-            let mut values: IndexSet<Value> = IndexSet::new();
-
-            data.select_values_and_paths(schema, current_dir, |path, value| {
-                let mut value = execute_selection_set(value, requires, schema, None);
-                if value.as_object().map(|o| !o.is_empty()).unwrap_or(false) {
-                    rewrites::apply_rewrites(schema, &mut value, input_rewrites);
-                    match values.get_index_of(&value) {
-                        Some(index) => {
-                            inverted_paths[index].push(path.clone());
-                        }
-                        None => {
-                            inverted_paths.push(vec![path.clone()]);
-                            values.insert(value);
-                            debug_assert!(inverted_paths.len() == values.len());
-                        }
+            let (values, inverted_paths) = representations::collect(|representations| {
+                data.select_values_and_paths(schema, current_dir, |path, value| {
+                    let mut value = execute_selection_set(value, requires, schema, None);
+                    if value.as_object().map(|o| !o.is_empty()).unwrap_or(false) {
+                        rewrites::apply_rewrites(schema, &mut value, input_rewrites);
+                        representations.insert(value, path);
                     }
-                }
+                });
             });
 
             if values.is_empty() {
                 return None;
             }
 
-            let representations = Value::Array(Vec::from_iter(values));
+            let representations = Value::Array(values);
 
             variables.insert("representations", representations);
 
@@ -320,6 +315,23 @@ impl FetchNode {
                         reason: inner.to_string(),
                     },
                 },
+                Err(e) if e.is::<Elapsed>() => {
+                    record_connection_error(service_name, "timeout");
+                    FetchError::SubrequestTimeout {
+                        service: service_name.to_string(),
+                    }
+                }
+                Err(e) if e.is::<RateLimited>() => FetchError::SubrequestRateLimited {
+                    service: service_name.to_string(),
+                },
+                Err(e) if e.is::<CircuitOpen>() => FetchError::SubrequestCircuitOpen {
+                    service: service_name.to_string(),
+                },
+                Err(e) if e.is::<ConcurrencyLimited>() => {
+                    FetchError::SubrequestConcurrencyLimited {
+                        service: service_name.to_string(),
+                    }
+                }
                 Err(e) => FetchError::SubrequestHttpError {
                     status_code: None,
                     service: service_name.to_string(),
@@ -473,7 +485,6 @@ impl FetchNode {
         }
     }
 
-    #[cfg(test)]
     pub(crate) fn service_name(&self) -> &str {
         &self.service_name
     }
@@ -221,6 +221,33 @@ impl PlanNode {
         }
     }
 
+    /// The number of flatten nodes of the plan, counted like `subgraph_fetches`
+    pub(crate) fn flatten_nodes(&self) -> usize {
+        match self {
+            PlanNode::Sequence { nodes } | PlanNode::Parallel { nodes } => {
+                nodes.iter().map(|n| n.flatten_nodes()).sum()
+            }
+            PlanNode::Fetch(_) => 0,
+            PlanNode::Flatten(node) => node.node.flatten_nodes() + 1,
+            PlanNode::Defer { primary, deferred } => {
+                primary.node.as_ref().map_or(0, |n| n.flatten_nodes())
+                    + deferred
+                        .iter()
+                        .map(|n| n.node.as_ref().map_or(0, |n| n.flatten_nodes()))
+                        .sum::<usize>()
+            }
+            PlanNode::Subscription { rest, .. } => rest.as_ref().map_or(0, |n| n.flatten_nodes()),
+            PlanNode::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => std::cmp::max(
+                if_clause.as_ref().map_or(0, |n| n.flatten_nodes()),
+                else_clause.as_ref().map_or(0, |n| n.flatten_nodes()),
+            ),
+        }
+    }
+
     pub(crate) fn hash_subqueries(&mut self, schema: &apollo_compiler::Schema) {
         match self {
             PlanNode::Fetch(fetch_node) => {
@@ -270,7 +297,6 @@ impl PlanNode {
         }
     }
 
-    #[cfg(test)]
     /// Retrieves all the services used across all plan nodes.
     ///
     /// Note that duplicates are not filtered.
@@ -0,0 +1,148 @@
+//! Deduplication of the representations of the entities fetched from a subgraph.
+//!
+//! Each distinct representation is sent once, along with the paths of all the entities it
+//! represents. With the `arena-allocation` feature, the hash table finding the duplicates is
+//! allocated in the arena of the thread, as it is only needed while the representations are
+//! collected.
+
+#[cfg(feature = "arena-allocation")]
+use std::collections::hash_map::RandomState;
+#[cfg(feature = "arena-allocation")]
+use std::hash::BuildHasher;
+
+#[cfg(feature = "arena-allocation")]
+use bumpalo::Bump;
+#[cfg(not(feature = "arena-allocation"))]
+use indexmap::IndexSet;
+
+use crate::json_ext::Path;
+use crate::json_ext::Value;
+
+/// Collects the distinct representations inserted by `f`, in the order they were first inserted,
+/// and the paths of the entities of each one
+#[cfg(not(feature = "arena-allocation"))]
+pub(super) fn collect(f: impl FnOnce(&mut Representations)) -> (Vec<Value>, Vec<Vec<Path>>) {
+    let mut representations = Representations {
+        values: IndexSet::new(),
+        paths: Vec::new(),
+    };
+    f(&mut representations);
+    (
+        Vec::from_iter(representations.values),
+        representations.paths,
+    )
+}
+
+#[cfg(not(feature = "arena-allocation"))]
+pub(super) struct Representations {
+    values: IndexSet<Value>,
+    paths: Vec<Vec<Path>>,
+}
+
+#[cfg(not(feature = "arena-allocation"))]
+impl Representations {
+    pub(super) fn insert(&mut self, value: Value, path: &Path) {
+        match self.values.get_index_of(&value) {
+            Some(index) => self.paths[index].push(path.clone()),
+            None => {
+                self.paths.push(vec![path.clone()]);
+                self.values.insert(value);
+            }
+        }
+    }
+}
+
+/// Collects the distinct representations inserted by `f`, in the order they were first inserted,
+/// and the paths of the entities of each one
+#[cfg(feature = "arena-allocation")]
+pub(super) fn collect(f: impl FnOnce(&mut Representations<'_>)) -> (Vec<Value>, Vec<Vec<Path>>) {
+    crate::arena::with_scratch(|arena| {
+        let mut representations = Representations {
+            values: Vec::new(),
+            paths: Vec::new(),
+            hasher: RandomState::new(),
+            slots: arena.alloc_slice_fill_copy(INITIAL_SLOTS, None),
+            arena,
+        };
+        f(&mut representations);
+        (representations.values, representations.paths)
+    })
+}
+
+/// Number of slots of the hash table before it first grows, a power of two
+#[cfg(feature = "arena-allocation")]
+const INITIAL_SLOTS: usize = 16;
+
+#[cfg(feature = "arena-allocation")]
+pub(super) struct Representations<'arena> {
+    values: Vec<Value>,
+    paths: Vec<Vec<Path>>,
+    hasher: RandomState,
+    /// Open addressing hash table of the hashes of the values and their index, at most half full
+    slots: &'arena mut [Option<(u64, usize)>],
+    arena: &'arena Bump,
+}
+
+#[cfg(feature = "arena-allocation")]
+impl<'arena> Representations<'arena> {
+    pub(super) fn insert(&mut self, value: Value, path: &Path) {
+        let hash = self.hasher.hash_one(&value);
+        let mask = self.slots.len() - 1;
+        let mut slot = hash as usize & mask;
+        while let Some((slot_hash, index)) = self.slots[slot] {
+            if slot_hash == hash && self.values[index] == value {
+                self.paths[index].push(path.clone());
+                return;
+            }
+            slot = (slot + 1) & mask;
+        }
+
+        self.slots[slot] = Some((hash, self.values.len()));
+        self.values.push(value);
+        self.paths.push(vec![path.clone()]);
+        if self.values.len() * 2 > self.slots.len() {
+            self.grow();
+        }
+    }
+
+    /// Doubles the number of slots. The previous slots stay in the arena until it is reset
+    fn grow(&mut self) {
+        let slots = self.arena.alloc_slice_fill_copy(self.slots.len() * 2, None);
+        let mask = slots.len() - 1;
+        for (hash, index) in self.slots.iter().flatten() {
+            let mut slot = *hash as usize & mask;
+            while slots[slot].is_some() {
+                slot = (slot + 1) & mask;
+            }
+            slots[slot] = Some((*hash, *index));
+        }
+        self.slots = slots;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+
+    #[test]
+    fn it_deduplicates_representations_in_order() {
+        let (values, paths) = collect(|representations| {
+            for i in 0..100 {
+                let path = Path::from(format!("topProducts/{i}"));
+                representations.insert(json!({ "__typename": "Product", "upc": i % 30 }), &path);
+            }
+        });
+        assert_eq!(values.len(), 30);
+        assert_eq!(values[0], json!({ "__typename": "Product", "upc": 0 }));
+        assert_eq!(values[29], json!({ "__typename": "Product", "upc": 29 }));
+        assert_eq!(
+            paths[1],
+            [1, 31, 61, 91]
+                .map(|i| Path::from(format!("topProducts/{i}")))
+                .to_vec()
+        );
+        assert_eq!(paths[29].len(), 3);
+    }
+}
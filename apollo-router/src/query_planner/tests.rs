@@ -8,6 +8,7 @@ use http::Method;
 use router_bridge::planner::UsageReporting;
 use serde_json_bytes::json;
 use tokio_stream::wrappers::ReceiverStream;
+use tower::BoxError;
 use tower::ServiceExt;
 
 use super::DeferredNode;
@@ -19,8 +20,11 @@ use super::Primary;
 use super::QueryPlan;
 use crate::json_ext::Path;
 use crate::json_ext::PathElement;
+use crate::metrics::FutureMetricsExt;
 use crate::plugin;
 use crate::plugin::test::MockSubgraph;
+use crate::plugins::traffic_shaping::Elapsed;
+use crate::plugins::traffic_shaping::RateLimited;
 use crate::query_planner;
 use crate::query_planner::fetch::FetchNode;
 use crate::request;
@@ -64,6 +68,13 @@ fn service_usage() {
     );
 }
 
+#[test]
+fn plan_node_counts() {
+    let query_plan: PlanNode = serde_json::from_str(test_query_plan!()).unwrap();
+    assert_eq!(query_plan.subgraph_fetches(), 5);
+    assert_eq!(query_plan.flatten_nodes(), 4);
+}
+
 /// This test panics in the product subgraph. HOWEVER, this does not result in a panic in the
 /// test, since the buffer() functionality in the tower stack "loses" the panic and we end up
 /// with a closed service.
@@ -182,6 +193,84 @@ async fn fetch_includes_operation_name() {
     assert!(succeeded.load(Ordering::SeqCst), "incorrect operation name");
 }
 
+async fn fetch_error_code(error: fn() -> BoxError) -> String {
+    let query_plan: QueryPlan = QueryPlan {
+        root: serde_json::from_str(test_query_plan!()).unwrap(),
+        formatted_query_plan: Default::default(),
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::empty()),
+    };
+
+    let mut mock_products_service = plugin::test::MockSubgraphService::new();
+    mock_products_service.expect_clone().return_once(move || {
+        let mut mock_products_service = plugin::test::MockSubgraphService::new();
+        mock_products_service
+            .expect_call()
+            .times(1)
+            .returning(move |_| Err(error()));
+        mock_products_service
+    });
+
+    let (sender, _) = tokio::sync::mpsc::channel(10);
+
+    let sf = Arc::new(SubgraphServiceFactory {
+        services: Arc::new(HashMap::from([(
+            "product".into(),
+            Arc::new(mock_products_service) as Arc<dyn MakeSubgraphService>,
+        )])),
+        plugins: Default::default(),
+    });
+
+    let result = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &Arc::new(Schema::parse_test(test_schema!(), &Default::default()).unwrap()),
+            sender,
+            None,
+            &None,
+            None,
+        )
+        .await;
+    assert_eq!(result.errors.len(), 1);
+    serde_json_bytes::from_value(result.errors[0].extensions.get("code").unwrap().clone()).unwrap()
+}
+
+#[tokio::test]
+async fn fetch_reports_traffic_shaping_errors() {
+    assert_eq!(
+        fetch_error_code(|| Box::new(Elapsed::new())).await,
+        "SUBGRAPH_TIMEOUT"
+    );
+    assert_eq!(
+        fetch_error_code(|| Box::new(RateLimited::new())).await,
+        "SUBGRAPH_RATE_LIMITED"
+    );
+    assert_eq!(
+        fetch_error_code(|| "connection refused".into()).await,
+        "SUBREQUEST_HTTP_ERROR"
+    );
+}
+
+#[tokio::test]
+async fn fetch_counts_subgraph_timeouts() {
+    async {
+        fetch_error_code(|| Box::new(Elapsed::new())).await;
+        assert_counter!(
+            "apollo_router_subgraph_connection_errors_total",
+            1,
+            "subgraph" = "product",
+            "error_class" = "timeout"
+        );
+    }
+    .with_metrics()
+    .await;
+}
+
 #[tokio::test]
 async fn fetch_makes_post_requests() {
     let query_plan: QueryPlan = QueryPlan {
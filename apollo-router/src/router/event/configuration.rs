@@ -48,6 +48,21 @@ pub enum ConfigurationSource {
         #[deprecated]
         delay: Option<Duration>,
     },
+
+    /// Several yaml files or directories of yaml files, merged in order, that may be watched
+    /// for changes
+    ///
+    /// The mappings are merged recursively, and any other value of a later file replaces the one
+    /// of the earlier files. A `null` value removes the key. The yaml files of a directory are
+    /// merged in the alphabetical order of their names.
+    #[display(fmt = "Files")]
+    Files {
+        /// The paths of the configuration files or directories.
+        paths: Vec<PathBuf>,
+
+        /// `true` to watch the files for changes and hot apply them.
+        watch: bool,
+    },
 }
 
 impl Default for ConfigurationSource {
@@ -123,6 +138,54 @@ impl ConfigurationSource {
                     }
                 }
             }
+            ConfigurationSource::Files { paths, watch } => {
+                // Sanity check, do the config files exist, if they don't then bail.
+                if let Some(path) = paths.iter().find(|path| !path.exists()) {
+                    tracing::error!(
+                        "configuration file at path '{}' does not exist.",
+                        path.to_string_lossy()
+                    );
+                    stream::empty().boxed()
+                } else {
+                    match ConfigurationSource::read_configs(&paths) {
+                        Ok(mut configuration) => {
+                            if watch {
+                                // The files added to the directories later are not watched
+                                let files =
+                                    crate::configuration::config_files(&paths).unwrap_or_default();
+                                stream::select_all(
+                                    files.iter().map(|file| crate::files::watch(file).boxed()),
+                                )
+                                .filter_map(move |_| {
+                                    let paths = paths.clone();
+                                    let uplink_config = uplink_config.clone();
+                                    async move {
+                                        match ConfigurationSource::read_configs(&paths) {
+                                            Ok(mut configuration) => {
+                                                configuration.uplink = uplink_config.clone();
+                                                Some(UpdateConfiguration(configuration))
+                                            }
+                                            Err(err) => {
+                                                tracing::error!("{}", err);
+                                                None
+                                            }
+                                        }
+                                    }
+                                })
+                                .boxed()
+                            } else {
+                                configuration.uplink = uplink_config.clone();
+                                stream::once(future::ready(UpdateConfiguration(configuration)))
+                                    .boxed()
+                            }
+                        }
+                        Err(err) => {
+                            tracing::error!("Failed to read configuration: {}", err);
+                            stream::empty().boxed()
+                        }
+                    }
+                }
+            }
         }
         .chain(stream::iter(vec![NoMoreConfiguration]))
         .boxed()
@@ -136,6 +199,12 @@ impl ConfigurationSource {
         let config = tokio::fs::read_to_string(path).await?;
         config.parse().map_err(ReadConfigError::Validation)
     }
+
+    fn read_configs(paths: &[PathBuf]) -> Result<Configuration, ReadConfigError> {
+        crate::configuration::merge_config_files(paths)?
+            .parse()
+            .map_err(ReadConfigError::Validation)
+    }
 }
 
 #[derive(From, Display)]
@@ -235,4 +304,36 @@ mod tests {
         ));
         assert!(matches!(stream.next().await.unwrap(), NoMoreConfiguration));
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn config_by_files_watching() {
+        let (base_path, mut base_file) = create_temp_file();
+        let contents = include_str!("../../testdata/supergraph_config.router.yaml");
+        write_and_flush(&mut base_file, contents).await;
+        let (overrides_path, mut overrides_file) = create_temp_file();
+        write_and_flush(&mut overrides_file, "supergraph:\n  introspection: true").await;
+        let mut stream = ConfigurationSource::Files {
+            paths: vec![base_path, overrides_path],
+            watch: true,
+        }
+        .into_stream(Some(UplinkConfig::default()))
+        .boxed();
+
+        // The files are merged
+        let UpdateConfiguration(configuration) = stream.next().await.unwrap() else {
+            panic!("the merged configuration should be valid");
+        };
+        assert!(configuration.supergraph.introspection);
+        assert_eq!(
+            configuration.supergraph.listen.to_string(),
+            "127.0.0.1:0".to_string()
+        );
+
+        // Modifying any file updates the configuration
+        write_and_flush(&mut overrides_file, "supergraph:\n  introspection: false").await;
+        let UpdateConfiguration(configuration) = stream.next().await.unwrap() else {
+            panic!("the merged configuration should be valid");
+        };
+        assert!(!configuration.supergraph.introspection);
+    }
 }
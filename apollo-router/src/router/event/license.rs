@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use derivative::Derivative;
 use derive_more::Display;
@@ -8,6 +9,8 @@ use derive_more::From;
 use futures::prelude::*;
 use thiserror::Error;
 
+use super::provider::poll;
+use super::LicenseProvider;
 use crate::router::Event;
 use crate::router::Event::NoMoreLicense;
 use crate::uplink::license_enforcement::Audience;
@@ -58,6 +61,10 @@ pub enum LicenseSource {
     /// Apollo uplink.
     #[display(fmt = "Registry")]
     Registry(UplinkConfig),
+
+    /// A provider of licenses, polled like Apollo uplink. EXPERIMENTAL and not subject to semver.
+    #[display(fmt = "Provider")]
+    Provider(#[derivative(Debug = "ignore")] Arc<dyn LicenseProvider>),
 }
 
 impl Default for LicenseSource {
@@ -159,6 +166,28 @@ impl LicenseSource {
                     })
                     .boxed()
             }
+            LicenseSource::Provider(provider) => poll(
+                "license",
+                provider.poll_interval(),
+                provider.timeout(),
+                move || {
+                    let provider = provider.clone();
+                    async move { provider.fetch().await }
+                },
+            )
+            .filter_map(|license| async move {
+                let result = license.parse();
+                if let Err(e) = &result {
+                    tracing::error!(
+                        code = APOLLO_ROUTER_LICENSE_INVALID,
+                        "failed to parse license, {}",
+                        e
+                    );
+                }
+                result.ok()
+            })
+            .validate_audience(VALID_AUDIENCES_USER_SUPLIED_LICENSES)
+            .boxed(),
             LicenseSource::Env => {
                 // EXPERIMENTAL and not subject to semver.
                 match std::env::var("APOLLO_ROUTER_LICENSE").map(|e| License::from_str(&e)) {
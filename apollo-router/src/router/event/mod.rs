@@ -1,5 +1,6 @@
 mod configuration;
 mod license;
+mod provider;
 mod reload;
 mod schema;
 mod shutdown;
@@ -9,6 +10,8 @@ use std::fmt::Formatter;
 
 pub use configuration::ConfigurationSource;
 pub use license::LicenseSource;
+pub use provider::LicenseProvider;
+pub use provider::SchemaProvider;
 pub(crate) use reload::ReloadSource;
 pub use schema::SchemaSource;
 pub use shutdown::ShutdownSource;
@@ -0,0 +1,121 @@
+//! Schemas and licenses supplied by embedders, such as from their own control plane.
+use std::future::Future;
+use std::time::Duration;
+
+use futures::prelude::*;
+use tower::BoxError;
+
+/// A provider of supergraph schemas, polled by the router like Apollo Uplink.
+///
+/// Every new schema is hot reloaded. If a fetch fails or times out, the router keeps its current
+/// schema and tries again after the poll interval.
+#[async_trait::async_trait]
+pub trait SchemaProvider: Send + Sync + 'static {
+    /// Fetch the supergraph schema, or `None` if it did not change since the previous fetch.
+    async fn fetch(&self) -> Result<Option<String>, BoxError>;
+
+    /// The delay between two fetches.
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(10)
+    }
+
+    /// The maximum duration of a fetch.
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+}
+
+/// A provider of licenses, as base64 encoded JWTs, polled by the router like Apollo Uplink.
+/// This API is experimental and is subject to change outside of semver.
+///
+/// Every new license is hot reloaded. If a fetch fails or times out, the router keeps its current
+/// license and tries again after the poll interval.
+#[async_trait::async_trait]
+pub trait LicenseProvider: Send + Sync + 'static {
+    /// Fetch the license, or `None` if it did not change since the previous fetch.
+    async fn fetch(&self) -> Result<Option<String>, BoxError>;
+
+    /// The delay between two fetches.
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(10)
+    }
+
+    /// The maximum duration of a fetch.
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+}
+
+/// Fetch immediately, then after each poll interval, skipping the failed fetches
+pub(crate) fn poll<F, Fut>(
+    what: &'static str,
+    poll_interval: Duration,
+    timeout: Duration,
+    fetch: F,
+) -> impl Stream<Item = String>
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Option<String>, BoxError>> + Send + 'static,
+{
+    stream::unfold(true, move |first| {
+        let fetching = fetch();
+        async move {
+            if !first {
+                tokio::time::sleep(poll_interval).await;
+            }
+            let fetched = match tokio::time::timeout(timeout, fetching).await {
+                Ok(Ok(fetched)) => fetched,
+                Ok(Err(err)) => {
+                    tracing::error!(reason = %err, "failed to fetch {what}");
+                    None
+                }
+                Err(_) => {
+                    tracing::error!("failed to fetch {what}: timed out after {timeout:?}");
+                    None
+                }
+            };
+            Some((fetched, false))
+        }
+    })
+    .filter_map(future::ready)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_polls_and_skips_failed_and_unchanged_fetches() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let fetch_calls = calls.clone();
+        let fetched: Vec<String> = poll(
+            "supergraph schema",
+            Duration::from_millis(10),
+            Duration::from_millis(100),
+            move || {
+                let call = fetch_calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match call {
+                        0 => Ok(Some("first".to_string())),
+                        1 => Err("unavailable".into()),
+                        2 => Ok(None),
+                        3 => {
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            Ok(Some("too late".to_string()))
+                        }
+                        _ => Ok(Some("second".to_string())),
+                    }
+                }
+            },
+        )
+        .take(2)
+        .collect()
+        .await;
+        assert_eq!(fetched, vec!["first", "second"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+    }
+}
@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
 use derivative::Derivative;
@@ -8,6 +9,8 @@ use derive_more::From;
 use futures::prelude::*;
 use url::Url;
 
+use super::provider::poll;
+use super::SchemaProvider;
 use crate::router::Event;
 use crate::router::Event::NoMoreSchema;
 use crate::router::Event::UpdateSchema;
@@ -49,6 +52,10 @@ pub enum SchemaSource {
     #[display(fmt = "Registry")]
     Registry(UplinkConfig),
 
+    /// A provider of schemas, such as a control plane, polled like Apollo managed federation.
+    #[display(fmt = "Provider")]
+    Provider(#[derivative(Debug = "ignore")] Arc<dyn SchemaProvider>),
+
     /// A list of URLs to fetch the schema from.
     #[display(fmt = "URLs")]
     URLs {
@@ -133,6 +140,17 @@ impl SchemaSource {
                     })
                     .boxed()
             }
+            SchemaSource::Provider(provider) => poll(
+                "supergraph schema",
+                provider.poll_interval(),
+                provider.timeout(),
+                move || {
+                    let provider = provider.clone();
+                    async move { provider.fetch().await }
+                },
+            )
+            .map(UpdateSchema)
+            .boxed(),
             SchemaSource::URLs {
                 urls,
                 watch,
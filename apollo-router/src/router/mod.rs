@@ -12,8 +12,10 @@ use std::task::Poll;
 pub use error::ApolloRouterError;
 pub use event::ConfigurationSource;
 pub(crate) use event::Event;
+pub use event::LicenseProvider;
 pub use event::LicenseSource;
 pub(crate) use event::ReloadSource;
+pub use event::SchemaProvider;
 pub use event::SchemaSource;
 pub use event::ShutdownSource;
 #[cfg(test)]
@@ -374,6 +376,37 @@ mod tests {
         router_handle.shutdown().await.unwrap();
     }
 
+    struct TestSchemaProvider;
+
+    #[async_trait::async_trait]
+    impl SchemaProvider for TestSchemaProvider {
+        async fn fetch(&self) -> Result<Option<String>, tower::BoxError> {
+            Ok(Some(
+                include_str!("../testdata/supergraph.graphql").to_string(),
+            ))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn schema_provider_request() {
+        let configuration =
+            Configuration::from_str(include_str!("../testdata/supergraph_config.router.yaml"))
+                .unwrap();
+        let mut router_handle = RouterHttpServer::builder()
+            .configuration(configuration)
+            .schema(SchemaSource::Provider(std::sync::Arc::new(
+                TestSchemaProvider,
+            )))
+            .start();
+        let listen_address = router_handle
+            .listen_address()
+            .await
+            .expect("router failed to start");
+
+        assert_federated_response(&listen_address, r#"{ topProducts { name } }"#).await;
+        router_handle.shutdown().await.unwrap();
+    }
+
     async fn assert_federated_response(listen_addr: &ListenAddr, request: &str) {
         let request = Request::builder().query(request).build();
         let expected = query(listen_addr, &request).await.unwrap();
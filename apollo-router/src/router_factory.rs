@@ -21,7 +21,10 @@ use crate::configuration::TlsClient;
 use crate::configuration::APOLLO_PLUGIN_PREFIX;
 use crate::plugin::DynPlugin;
 use crate::plugin::Handler;
+use crate::plugin::LifecycleEvent;
 use crate::plugin::PluginFactory;
+use crate::plugins::shadow::ShadowPlugin;
+use crate::plugins::shadow::NOT_SHADOWABLE;
 use crate::plugins::subscription::Subscription;
 use crate::plugins::subscription::APOLLO_SUBSCRIPTION_PLUGIN;
 use crate::plugins::traffic_shaping::TrafficShaping;
@@ -115,6 +118,9 @@ pub(crate) trait RouterFactory:
     type Future: Send;
 
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
+
+    /// Notify plugins of a router lifecycle transition.
+    fn lifecycle_event(&self, _event: &LifecycleEvent) {}
 }
 
 /// Factory for creating a RouterFactory
@@ -199,10 +205,22 @@ impl RouterSuperServiceFactory for YamlRouterFactory {
 
         let persisted_query_layer = Arc::new(PersistedQueryLayer::new(&configuration).await?);
 
-        if let Some(previous_router) = previous_router {
-            let cache_keys = previous_router
-                .cache_keys(configuration.supergraph.query_planning.warmed_up_queries)
-                .await;
+        // on startup, the cache is only warmed up if a manifest of operations is configured
+        let warm_up_manifest = configuration
+            .supergraph
+            .query_planning
+            .experimental_warmup
+            .manifest
+            .is_some();
+        if previous_router.is_some() || warm_up_manifest {
+            let cache_keys = match previous_router {
+                Some(previous_router) => {
+                    previous_router
+                        .cache_keys(configuration.supergraph.query_planning.warmed_up_queries)
+                        .await
+                }
+                None => Vec::new(),
+            };
 
             supergraph_creator
                 .warm_up_query_planner(&query_analysis_layer, &persisted_query_layer, cache_keys)
@@ -269,7 +287,8 @@ pub(crate) async fn create_subgraph_services(
                 &tls_root_store,
                 shaping.enable_subgraph_http2(name),
                 subscription_plugin_conf.clone(),
-            )?,
+            )?
+            .with_response_limits(shaping.subgraph_response_limits(name)),
         );
         subgraph_services.insert(name.clone(), subgraph_service);
     }
@@ -476,11 +495,22 @@ pub(crate) async fn create_plugins(
     add_mandatory_apollo_plugin!("telemetry");
     add_mandatory_apollo_plugin!("traffic_shaping");
     add_optional_apollo_plugin!("forbid_mutations");
+    add_optional_apollo_plugin!("subgraph_error_mapping");
     add_optional_apollo_plugin!("subscription");
     add_optional_apollo_plugin!("override_subgraph_url");
     add_optional_apollo_plugin!("authorization");
     add_optional_apollo_plugin!("authentication");
     add_optional_apollo_plugin!("experimental_entity_cache");
+    add_optional_apollo_plugin!("schema_change_webhook");
+    add_optional_apollo_plugin!("experimental_profiling");
+    add_optional_apollo_plugin!("experimental_schema_endpoint");
+    add_optional_apollo_plugin!("experimental_serve_as_subgraph");
+    add_optional_apollo_plugin!("experimental_operation_stubs");
+    add_optional_apollo_plugin!("experimental_server_timing");
+    add_optional_apollo_plugin!("experimental_log_levels");
+    add_optional_apollo_plugin!("experimental_trace_sampling");
+    add_optional_apollo_plugin!("experimental_client_protocols");
+    add_optional_apollo_plugin!("experimental_deprecation_enforcement");
 
     // This relative ordering is documented in `docs/source/customizations/native.mdx`:
     add_optional_apollo_plugin!("rhai");
@@ -497,6 +527,53 @@ pub(crate) async fn create_plugins(
         )
     }
 
+    // Plugins in shadow mode run on copies of the requests, and cannot change the responses
+    let shadow_plugins = &configuration.experimental_shadow_plugins;
+    if !(0.0..=1.0).contains(&shadow_plugins.sample_rate) {
+        errors.push(ConfigurationError::InvalidConfiguration {
+            message: "invalid experimental_shadow_plugins configuration",
+            error: "the sample rate must be between 0 and 1".to_string(),
+        });
+    }
+    let configured_name = |name: &str| {
+        name.strip_prefix(APOLLO_PLUGIN_PREFIX)
+            .unwrap_or(name)
+            .to_string()
+    };
+    for shadowed in &shadow_plugins.plugins {
+        if NOT_SHADOWABLE.contains(&shadowed.as_str()) {
+            errors.push(ConfigurationError::PluginConfiguration {
+                plugin: shadowed.clone(),
+                error: "this plugin cannot be evaluated in shadow mode".to_string(),
+            });
+        } else if !plugin_instances
+            .iter()
+            .any(|(name, _)| &configured_name(name) == shadowed)
+        {
+            errors.push(ConfigurationError::PluginConfiguration {
+                plugin: shadowed.clone(),
+                error: "the plugin is evaluated in shadow mode but it is not configured"
+                    .to_string(),
+            });
+        }
+    }
+    let plugin_instances = plugin_instances
+        .into_iter()
+        .map(|(name, plugin)| {
+            let configured_name = configured_name(&name);
+            if shadow_plugins.plugins.contains(&configured_name)
+                && !NOT_SHADOWABLE.contains(&configured_name.as_str())
+            {
+                tracing::info!("the plugin {configured_name} is evaluated in shadow mode");
+                let plugin: Box<dyn DynPlugin> =
+                    Box::new(ShadowPlugin::new(&configured_name, plugin, shadow_plugins));
+                (name, plugin)
+            } else {
+                (name, plugin)
+            }
+        })
+        .collect::<Vec<_>>();
+
     let plugin_details = plugin_instances
         .iter()
         .map(|(name, plugin)| (name, plugin.name()))
@@ -674,6 +751,57 @@ mod test {
         assert!(service.is_err())
     }
 
+    #[tokio::test]
+    async fn test_yaml_shadow_plugins() {
+        let config: Configuration = serde_yaml::from_str(
+            r#"
+            plugins:
+                test.always_starts_and_stops:
+                    name: albert
+            experimental_shadow_plugins:
+                plugins:
+                    - test.always_starts_and_stops
+                sample_rate: 0.1
+        "#,
+        )
+        .unwrap();
+        assert!(create_service(config).await.is_ok());
+
+        let config: Configuration = serde_yaml::from_str(
+            r#"
+            experimental_shadow_plugins:
+                plugins:
+                    - test.always_starts_and_stops
+        "#,
+        )
+        .unwrap();
+        assert!(create_service(config).await.is_err());
+
+        let config: Configuration = serde_yaml::from_str(
+            r#"
+            experimental_shadow_plugins:
+                plugins:
+                    - telemetry
+        "#,
+        )
+        .unwrap();
+        assert!(create_service(config).await.is_err());
+
+        let config: Configuration = serde_yaml::from_str(
+            r#"
+            plugins:
+                test.always_starts_and_stops:
+                    name: albert
+            experimental_shadow_plugins:
+                plugins:
+                    - test.always_starts_and_stops
+                sample_rate: 2
+        "#,
+        )
+        .unwrap();
+        assert!(create_service(config).await.is_err());
+    }
+
     async fn create_service(config: Configuration) -> Result<(), BoxError> {
         let schema = include_str!("testdata/supergraph.graphql");
 
@@ -38,6 +38,7 @@ pub(crate) enum PipelineStep {
     ExecutionResponse,
     SubgraphRequest,
     SubgraphResponse,
+    LifecycleEvent,
 }
 
 #[derive(Clone, Debug, Default, Display, Deserialize, PartialEq, Serialize, JsonSchema)]
@@ -211,6 +212,30 @@ where
         }
     }
 
+    #[builder(visibility = "pub(crate)")]
+    /// This is the constructor (or builder) to use when constructing a Lifecycle
+    /// `Externalizable`.
+    ///
+    fn lifecycle_new(stage: PipelineStep, id: String, body: Option<T>) -> Self {
+        assert!(matches!(stage, PipelineStep::LifecycleEvent));
+        Externalizable {
+            version: EXTERNALIZABLE_VERSION,
+            stage: stage.to_string(),
+            control: None,
+            id: Some(id),
+            headers: None,
+            body,
+            context: None,
+            status_code: None,
+            sdl: None,
+            uri: None,
+            path: None,
+            method: None,
+            service_name: None,
+            has_next: None,
+        }
+    }
+
     pub(crate) async fn call<C>(self, mut client: C, uri: &str) -> Result<Self, BoxError>
     where
         C: Service<hyper::Request<Body>, Response = hyper::Response<Body>, Error = BoxError>
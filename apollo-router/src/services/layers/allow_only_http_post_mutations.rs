@@ -289,6 +289,7 @@ mod forbid_http_get_mutations_tests {
                 executable,
                 parse_errors: None,
                 validation_errors: None,
+                rule_violations: Vec::new(),
             }));
 
         SupergraphRequest::fake_builder()
@@ -12,7 +12,9 @@ use serde_json_bytes::Value;
 use sha2::Digest;
 use sha2::Sha256;
 
+use crate::cache::key::cache_key_extension;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::CacheKeyConfig;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 
@@ -52,15 +54,26 @@ impl PersistedQuery {
 pub(crate) struct APQLayer {
     /// set to None if APQ is disabled
     cache: Option<DeduplicatingCache<String, String>>,
+    /// extra dimensions of the cache keys
+    key: CacheKeyConfig,
 }
 
 impl APQLayer {
-    pub(crate) fn with_cache(cache: DeduplicatingCache<String, String>) -> Self {
-        Self { cache: Some(cache) }
+    pub(crate) fn with_cache(
+        cache: DeduplicatingCache<String, String>,
+        key: CacheKeyConfig,
+    ) -> Self {
+        Self {
+            cache: Some(cache),
+            key,
+        }
     }
 
     pub(crate) fn disabled() -> Self {
-        Self { cache: None }
+        Self {
+            cache: None,
+            key: CacheKeyConfig::default(),
+        }
     }
 
     pub(crate) async fn supergraph_request(
@@ -68,7 +81,7 @@ impl APQLayer {
         request: SupergraphRequest,
     ) -> Result<SupergraphRequest, SupergraphResponse> {
         match self.cache.as_ref() {
-            Some(cache) => apq_request(cache, request).await,
+            Some(cache) => apq_request(cache, &self.key, request).await,
             None => disabled_apq_request(request).await,
         }
     }
@@ -76,10 +89,13 @@ impl APQLayer {
 
 async fn apq_request(
     cache: &DeduplicatingCache<String, String>,
+    key: &CacheKeyConfig,
     mut request: SupergraphRequest,
 ) -> Result<SupergraphRequest, SupergraphResponse> {
     let maybe_query_hash =
         PersistedQuery::maybe_from_request(&request).and_then(PersistedQuery::decode_hash);
+    let extension =
+        cache_key_extension(key, request.supergraph_request.headers(), &request.context);
 
     let body_query = request.supergraph_request.body().query.clone();
 
@@ -88,7 +104,9 @@ async fn apq_request(
             if query_matches_hash(query.as_str(), query_hash_bytes.as_slice()) {
                 tracing::trace!("apq: cache insert");
                 let _ = request.context.insert("persisted_query_register", true);
-                cache.insert(redis_key(&query_hash), query).await;
+                cache
+                    .insert(redis_key(&query_hash, extension.as_deref()), query)
+                    .await;
                 Ok(request)
             } else {
                 tracing::debug!("apq: graphql request doesn't match provided sha256Hash");
@@ -112,7 +130,12 @@ async fn apq_request(
             }
         }
         (Some((apq_hash, _)), _) => {
-            if let Ok(cached_query) = cache.get(&redis_key(&apq_hash)).await.get().await {
+            if let Ok(cached_query) = cache
+                .get(&redis_key(&apq_hash, extension.as_deref()))
+                .await
+                .get()
+                .await
+            {
                 let _ = request.context.insert("persisted_query_hit", true);
                 tracing::trace!("apq: cache hit");
                 request.supergraph_request.body_mut().query = Some(cached_query);
@@ -153,8 +176,11 @@ fn query_matches_hash(query: &str, hash: &[u8]) -> bool {
     hash == digest.finalize().as_slice()
 }
 
-fn redis_key(query_hash: &str) -> String {
-    format!("apq\0{query_hash}")
+fn redis_key(query_hash: &str, extension: Option<&str>) -> String {
+    match extension {
+        Some(extension) => format!("apq\0{query_hash}\0{extension}"),
+        None => format!("apq\0{query_hash}"),
+    }
 }
 
 pub(crate) fn calculate_hash_for_query(query: &str) -> String {
@@ -534,6 +560,65 @@ mod apq_tests {
         assert!(without_apq_graphql_response.errors.is_empty());
     }
 
+    #[tokio::test]
+    async fn it_extends_cache_keys() {
+        let mut config = Configuration::default();
+        config.apq.router.cache.key.request_headers = vec!["x-tenant".to_string()];
+
+        let mut router_service = from_supergraph_mock_callback_and_configuration(
+            move |req| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .expect("expecting valid request"))
+            },
+            Arc::new(config),
+        )
+        .await;
+
+        let persisted = json!({
+            "version" : 1,
+            "sha256Hash" : "ecf4edb46db40b5132295c0291d62fb65d6759a9eedfa4d5d612dd5ec54a6b38"
+        });
+        let request = |tenant: &str, query: Option<&str>| {
+            SupergraphRequest::fake_builder()
+                .extension("persistedQuery", persisted.clone())
+                .and_query(query.map(|query| query.to_string()))
+                .header("x-tenant", tenant)
+                .context(new_context())
+                .build()
+                .expect("expecting valid request")
+                .try_into()
+                .unwrap()
+        };
+        let errors = |response: crate::services::router::Response| async move {
+            response
+                .into_graphql_response_stream()
+                .await
+                .next()
+                .await
+                .unwrap()
+                .unwrap()
+                .errors
+        };
+
+        let response = router_service
+            .call(request("a", Some("{__typename}")))
+            .await
+            .unwrap();
+        assert!(errors(response).await.is_empty());
+
+        let response = router_service.call(request("a", None)).await.unwrap();
+        assert!(errors(response).await.is_empty());
+
+        // the query registered by another tenant is not found
+        let response = router_service.call(request("b", None)).await.unwrap();
+        assert_eq!(
+            errors(response).await[0].message,
+            "PersistedQueryNotFound".to_string()
+        );
+    }
+
     fn assert_error_matches(expected_error: &Error, res: Response) {
         assert_eq!(&res.errors[0], expected_error);
     }
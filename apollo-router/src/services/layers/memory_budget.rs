@@ -0,0 +1,267 @@
+//! Per-request memory accounting.
+//!
+//! The layer gives each supergraph request a [`MemoryBudget`], stored in the context's private
+//! entries. The bodies buffered for subgraph fetches are counted against it, and once it is
+//! exhausted the request is aborted, so that a single pathological operation cannot exhaust the
+//! memory of the whole instance.
+//!
+//! Accounting is approximate: the size of a buffered body stands for the values built from it.
+
+use std::future::ready;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Poll;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use futures::StreamExt;
+use http::StatusCode;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::graphql;
+use crate::services::SupergraphRequest;
+use crate::services::SupergraphResponse;
+
+pub(crate) const MEMORY_LIMIT_EXCEEDED: &str = "MEMORY_LIMIT_EXCEEDED";
+
+/// Memory budget of a request, shared by its subgraph fetches
+#[derive(Clone, Debug)]
+pub(crate) struct MemoryBudget {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    limit: usize,
+    used: AtomicUsize,
+    exceeded: AtomicBool,
+}
+
+impl MemoryBudget {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limit,
+                used: AtomicUsize::new(0),
+                exceeded: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    /// Counts `bytes` against the budget, returns false if the budget is exhausted
+    pub(crate) fn charge(&self, bytes: usize) -> bool {
+        if self.is_exceeded() {
+            return false;
+        }
+        let used = self.inner.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if used > self.inner.limit {
+            self.inner.exceeded.store(true, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+
+    /// Once exceeded, the budget stays exceeded for the rest of the request
+    pub(crate) fn is_exceeded(&self) -> bool {
+        self.inner.exceeded.load(Ordering::Relaxed)
+    }
+
+    /// The memory buffered for a response is released once it is sent to the client
+    fn release(&self) {
+        self.inner.used.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Gives each request a [`MemoryBudget`] of `limit` bytes, and aborts it when it is exceeded
+pub(crate) struct MemoryBudgetLayer {
+    limit: usize,
+}
+
+impl MemoryBudgetLayer {
+    pub(crate) fn new(limit: usize) -> Self {
+        Self { limit }
+    }
+}
+
+impl<S> Layer<S> for MemoryBudgetLayer {
+    type Service = MemoryBudgetService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        MemoryBudgetService {
+            service,
+            limit: self.limit,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MemoryBudgetService<S> {
+    service: S,
+    limit: usize,
+}
+
+impl<S> Service<SupergraphRequest> for MemoryBudgetService<S>
+where
+    S: Service<SupergraphRequest, Response = SupergraphResponse, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = SupergraphResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: SupergraphRequest) -> Self::Future {
+        let budget = MemoryBudget::new(self.limit);
+        request
+            .context
+            .private_entries
+            .lock()
+            .insert(budget.clone());
+        self.service
+            .call(request)
+            .map(move |response| response.map(|response| enforce(budget, response)))
+            .boxed()
+    }
+}
+
+fn enforce(budget: MemoryBudget, response: SupergraphResponse) -> SupergraphResponse {
+    // The primary response is computed before the supergraph service returns
+    if budget.is_exceeded() {
+        record(&budget);
+        let mut response = SupergraphResponse::new_from_graphql_response(
+            error_response(&budget),
+            response.context,
+        );
+        *response.response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        return response;
+    }
+
+    let SupergraphResponse { context, response } = response;
+    let (parts, stream) = response.into_parts();
+    // Deferred responses and subscription events are checked as they are sent
+    let stream = stream.scan(false, move |aborted, response| {
+        if *aborted {
+            return ready(None);
+        }
+        if budget.is_exceeded() {
+            record(&budget);
+            *aborted = true;
+            return ready(Some(error_response(&budget)));
+        }
+        budget.release();
+        ready(Some(response))
+    });
+    SupergraphResponse {
+        context,
+        response: http::Response::from_parts(parts, stream.boxed()),
+    }
+}
+
+fn record(budget: &MemoryBudget) {
+    u64_counter!(
+        "apollo_router_request_memory_limit_exceeded_total",
+        "Number of requests aborted because they exceeded their memory budget",
+        1
+    );
+    tracing::warn!(
+        limit = budget.limit(),
+        "request aborted: it exceeded its memory budget"
+    );
+}
+
+fn error_response(budget: &MemoryBudget) -> graphql::Response {
+    graphql::Response::builder()
+        .error(
+            graphql::Error::builder()
+                .message(format!(
+                    "the request exceeded its memory budget of {} bytes",
+                    budget.limit()
+                ))
+                .extension_code(MEMORY_LIMIT_EXCEEDED)
+                .build(),
+        )
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+
+    #[test]
+    fn it_charges_the_budget() {
+        let budget = MemoryBudget::new(10);
+        assert!(budget.charge(6));
+        assert!(budget.charge(4));
+        assert!(!budget.charge(1));
+        assert!(budget.is_exceeded());
+
+        // An exceeded budget is not restored by releasing memory
+        budget.release();
+        assert!(!budget.charge(1));
+    }
+
+    #[tokio::test]
+    async fn it_aborts_requests_exceeding_their_budget() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(2).returning(|request| {
+            let budget = request
+                .context
+                .private_entries
+                .lock()
+                .get::<MemoryBudget>()
+                .cloned()
+                .unwrap();
+            budget.charge(100);
+            Ok(SupergraphResponse::fake_builder()
+                .data(serde_json::json!({ "me": { "name": "Ada" } }))
+                .context(request.context)
+                .build()
+                .unwrap())
+        });
+        let mut service = MemoryBudgetLayer::new(100).layer(mock_service);
+
+        let mut response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+        let response = response.next_response().await.unwrap();
+        assert!(response.errors.is_empty());
+
+        let mut service = MemoryBudgetLayer::new(99).layer(service.service);
+        let mut response = service
+            .ready()
+            .await
+            .unwrap()
+            .call(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        let response = response.next_response().await.unwrap();
+        assert_eq!(response.data, None);
+        assert_eq!(
+            response.errors[0].extensions.get("code").unwrap(),
+            MEMORY_LIMIT_EXCEEDED
+        );
+    }
+}
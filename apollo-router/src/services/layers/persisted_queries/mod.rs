@@ -8,6 +8,7 @@ use http::header::CACHE_CONTROL;
 use http::HeaderValue;
 use id_extractor::PersistedQueryIdExtractor;
 pub(crate) use manifest_poller::PersistedQueryManifestPoller;
+pub(crate) use manifest_poller::SignedUrlChunk;
 use tower::BoxError;
 
 use self::manifest_poller::FreeformGraphQLAction;
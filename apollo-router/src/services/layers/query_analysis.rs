@@ -1,20 +1,41 @@
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use apollo_compiler::ast;
 use apollo_compiler::validation::DiagnosticList;
 use apollo_compiler::ExecutableDocument;
 use http::StatusCode;
 use lru::LruCache;
+use router_bridge::planner::UsageReporting;
 use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
+use tracing::Instrument;
 
+use crate::axum_factory::utils::ConnectionInfo;
+use crate::cache::registry;
+use crate::cache::registry::CacheStats;
+use crate::cache::registry::InMemoryCache;
+use crate::cache::registry::KeyHits;
+use crate::configuration::DocumentCache;
+use crate::configuration::ParserOffload;
 use crate::context::OPERATION_KIND;
 use crate::context::OPERATION_NAME;
+use crate::error::ValidationErrors;
+use crate::graphql::IntoGraphQLErrors;
 use crate::plugins::authorization::AuthorizationPlugin;
 use crate::query_planner::OperationKind;
+use crate::services::layers::apq::calculate_hash_for_query;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
+use crate::spec::document_limits;
+use crate::spec::validation_rules::RuleViolation;
 use crate::spec::Query;
 use crate::spec::Schema;
+use crate::spec::GRAPHQL_VALIDATION_FAILURE_ERROR_KEY;
 use crate::Configuration;
 use crate::Context;
 
@@ -25,7 +46,9 @@ pub(crate) struct QueryAnalysisLayer {
     pub(crate) schema: Arc<Schema>,
     configuration: Arc<Configuration>,
     cache: Arc<Mutex<LruCache<QueryAnalysisKey, (Context, ParsedDocument)>>>,
+    documents: Arc<ParsedDocumentCache>,
     enable_authorization_directives: bool,
+    parsing_pool: Option<Arc<ParsingPool>>,
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
@@ -48,7 +71,12 @@ impl QueryAnalysisLayer {
                     .in_memory
                     .limit,
             ))),
+            documents: ParsedDocumentCache::new(
+                &configuration.supergraph.experimental_document_cache,
+            ),
             enable_authorization_directives,
+            parsing_pool: ParsingPool::new(&configuration.limits.experimental_parser_offload)
+                .map(Arc::new),
             configuration,
         }
     }
@@ -57,6 +85,20 @@ impl QueryAnalysisLayer {
         Query::parse_document(query, self.schema.api_schema(), &self.configuration)
     }
 
+    /// Parse and validate the document, on the parsing pool if it is large enough
+    async fn parse_document_offloaded(&self, query: &str) -> Result<ParsedDocument, ParsingError> {
+        match &self.parsing_pool {
+            Some(pool) if query.len() >= pool.min_document_bytes => {
+                let query = query.to_string();
+                let schema = self.schema.clone();
+                let configuration = self.configuration.clone();
+                pool.run(move || Query::parse_document(&query, schema.api_schema(), &configuration))
+                    .await
+            }
+            _ => Ok(self.parse_document(query)),
+        }
+    }
+
     pub(crate) async fn supergraph_request(
         &self,
         request: SupergraphRequest,
@@ -91,6 +133,32 @@ impl QueryAnalysisLayer {
             .query
             .clone()
             .expect("query presence was already checked");
+
+        // reject oversized and deeply nested documents before any parsing
+        let limits = &self.configuration.limits;
+        if let Err(exceeded) = document_limits::check_bytes(limits, &query)
+            .and_then(|()| document_limits::check_nesting(limits, &query))
+        {
+            let errors = vec![crate::error::Error::builder()
+                .message(exceeded.message().to_string())
+                .extension_code(exceeded.code())
+                .build()];
+            u64_counter!(
+                "apollo_router_http_requests_total",
+                "Total number of HTTP requests made.",
+                1,
+                status = StatusCode::BAD_REQUEST.as_u16() as i64,
+                error = exceeded.message()
+            );
+
+            return Err(SupergraphResponse::builder()
+                .errors(errors)
+                .status_code(StatusCode::BAD_REQUEST)
+                .context(request.context)
+                .build()
+                .expect("response is valid"));
+        }
+
         let entry = self
             .cache
             .lock()
@@ -103,8 +171,33 @@ impl QueryAnalysisLayer {
 
         let (context, doc) = match entry {
             None => {
-                let span = tracing::info_span!("parse_query", "otel.kind" = "INTERNAL");
-                let doc = span.in_scope(|| self.parse_document(&query));
+                // another operation of the same document may have been parsed already
+                let hash = calculate_hash_for_query(&query);
+                let doc = match self.documents.get(&hash) {
+                    Some(doc) => doc,
+                    None => {
+                        let span = tracing::info_span!("parse_query", "otel.kind" = "INTERNAL");
+                        match self.parse_document_offloaded(&query).instrument(span).await {
+                            Ok(doc) => {
+                                self.documents.insert(hash, query.len(), doc.clone());
+                                doc
+                            }
+                            Err(error) => {
+                                return Err(SupergraphResponse::builder()
+                                    .error(
+                                        crate::error::Error::builder()
+                                            .message(error.to_string())
+                                            .extension_code(error.code())
+                                            .build(),
+                                    )
+                                    .status_code(StatusCode::SERVICE_UNAVAILABLE)
+                                    .context(request.context)
+                                    .build()
+                                    .expect("response is valid"))
+                            }
+                        }
+                    }
+                };
 
                 let context = Context::new();
 
@@ -141,6 +234,33 @@ impl QueryAnalysisLayer {
             Some(c) => c,
         };
 
+        let server_address = request
+            .supergraph_request
+            .extensions()
+            .get::<ConnectionInfo>()
+            .and_then(|connection| connection.server_address);
+        if let Some(errors) = self
+            .configuration
+            .experimental_validation_rules
+            .for_address(server_address)
+            .check(&doc.rule_violations)
+        {
+            request
+                .context
+                .private_entries
+                .lock()
+                .insert(UsageReporting {
+                    stats_report_key: GRAPHQL_VALIDATION_FAILURE_ERROR_KEY.to_string(),
+                    referenced_fields_by_type: HashMap::new(),
+                });
+            return Err(SupergraphResponse::builder()
+                .errors(errors.into_graphql_errors().unwrap_or_default())
+                .status_code(StatusCode::BAD_REQUEST)
+                .context(request.context)
+                .build()
+                .expect("response is valid"));
+        }
+
         request.context.extend(&context);
         request
             .context
@@ -155,11 +275,402 @@ impl QueryAnalysisLayer {
     }
 }
 
+const DOCUMENT_CACHE: &str = "document";
+const MEMORY_STORAGE: &str = "memory";
+
+/// The parsed and validated documents, keyed by the hash of their source, so that the operations
+/// of a document skip parsing and validation even when their own entries were evicted
+struct ParsedDocumentCache {
+    entries: parking_lot::Mutex<LruCache<String, DocumentEntry>>,
+    max_document_bytes: Option<usize>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+struct DocumentEntry {
+    document: ParsedDocument,
+    /// How many times the entry was found
+    hits: u64,
+}
+
+impl ParsedDocumentCache {
+    fn new(configuration: &DocumentCache) -> Arc<Self> {
+        let cache = Arc::new(Self {
+            entries: parking_lot::Mutex::new(LruCache::new(configuration.limit)),
+            max_document_bytes: configuration.max_document_bytes,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        });
+        registry::register(Arc::downgrade(&cache) as _);
+        cache
+    }
+
+    fn get(&self, hash: &str) -> Option<ParsedDocument> {
+        let document = self.entries.lock().get_mut(hash).map(|entry| {
+            entry.hits += 1;
+            entry.document.clone()
+        });
+        if document.is_some() {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            tracing::info!(
+                monotonic_counter.apollo_router_cache_hit_count = 1u64,
+                kind = DOCUMENT_CACHE,
+                storage = MEMORY_STORAGE,
+            );
+        } else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            tracing::info!(
+                monotonic_counter.apollo_router_cache_miss_count = 1u64,
+                kind = DOCUMENT_CACHE,
+                storage = MEMORY_STORAGE,
+            );
+        }
+        document
+    }
+
+    fn insert(&self, hash: String, document_bytes: usize, document: ParsedDocument) {
+        if self
+            .max_document_bytes
+            .map_or(false, |max| document_bytes > max)
+        {
+            return;
+        }
+        let mut entries = self.entries.lock();
+        // pushing an existing key replaces its entry, any other key pushed out was evicted
+        let evicted = entries
+            .push(hash.clone(), DocumentEntry { document, hits: 0 })
+            .map_or(false, |(previous, _)| previous != hash);
+        let size = entries.len() as u64;
+        drop(entries);
+        tracing::info!(
+            value.apollo_router_cache_size = size,
+            kind = DOCUMENT_CACHE,
+            storage = MEMORY_STORAGE,
+        );
+        if evicted {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            u64_counter!(
+                "apollo_router_cache_eviction_count",
+                "Number of entries evicted from an in-memory cache",
+                1,
+                kind = DOCUMENT_CACHE,
+                storage = MEMORY_STORAGE
+            );
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl InMemoryCache for ParsedDocumentCache {
+    fn name(&self) -> &str {
+        DOCUMENT_CACHE
+    }
+
+    async fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock();
+        CacheStats {
+            name: DOCUMENT_CACHE.to_string(),
+            size: entries.len(),
+            capacity: entries.cap().get(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn hottest_keys(&self, limit: usize) -> Vec<KeyHits> {
+        let entries = self.entries.lock();
+        let mut keys = entries
+            .iter()
+            .map(|(hash, entry)| (hash, entry.hits))
+            .collect::<Vec<_>>();
+        keys.sort_by(|(_, a), (_, b)| b.cmp(a));
+        keys.into_iter()
+            .take(limit)
+            .map(|(hash, hits)| KeyHits {
+                key: hash.clone(),
+                hits,
+            })
+            .collect()
+    }
+
+    async fn clear(&self) {
+        self.entries.lock().clear();
+    }
+}
+
+/// A bounded pool of blocking threads parsing and validating large documents
+struct ParsingPool {
+    min_document_bytes: usize,
+    max_queue_depth: usize,
+    timeout: Option<Duration>,
+    permits: Arc<Semaphore>,
+    queue_depth: Arc<AtomicUsize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+enum ParsingError {
+    /// too many documents are waiting to be parsed
+    #[error("too many documents are waiting to be parsed")]
+    QueueFull,
+    /// the document could not be parsed in time
+    #[error("the document could not be parsed in time")]
+    Timeout,
+}
+
+impl ParsingError {
+    fn code(&self) -> &'static str {
+        match self {
+            ParsingError::QueueFull => "PARSING_QUEUE_FULL",
+            ParsingError::Timeout => "PARSING_TIMEOUT",
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self {
+            ParsingError::QueueFull => "queue_full",
+            ParsingError::Timeout => "timeout",
+        }
+    }
+}
+
+/// Counts a document as queued until it gets a thread of the pool
+struct Queued(Arc<AtomicUsize>);
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+        i64_up_down_counter!(
+            "apollo_router_parsing_queue_depth",
+            "Number of documents waiting to be parsed on the parsing pool",
+            -1,
+            []
+        );
+    }
+}
+
+impl ParsingPool {
+    fn new(configuration: &ParserOffload) -> Option<Self> {
+        Some(Self {
+            min_document_bytes: configuration.min_document_bytes?,
+            max_queue_depth: configuration.max_queue_depth,
+            timeout: configuration.timeout,
+            permits: Arc::new(Semaphore::new(configuration.max_concurrency.get())),
+            queue_depth: Default::default(),
+        })
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T, ParsingError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = self.run_inner(f).await;
+        if let Err(error) = &result {
+            u64_counter!(
+                "apollo_router_parsing_rejected_total",
+                "Number of documents rejected by the parsing pool",
+                1,
+                reason = error.reason()
+            );
+        }
+        result
+    }
+
+    async fn run_inner<T, F>(&self, f: F) -> Result<T, ParsingError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.queue_depth.fetch_add(1, Ordering::SeqCst) >= self.max_queue_depth {
+            self.queue_depth.fetch_sub(1, Ordering::SeqCst);
+            return Err(ParsingError::QueueFull);
+        }
+        i64_up_down_counter!(
+            "apollo_router_parsing_queue_depth",
+            "Number of documents waiting to be parsed on the parsing pool",
+            1,
+            []
+        );
+        let queued = Queued(self.queue_depth.clone());
+
+        let permits = self.permits.clone();
+        let task = async move {
+            let permit = permits
+                .acquire_owned()
+                .await
+                .expect("the semaphore is never closed");
+            drop(queued);
+            // The permit is released when parsing is done, even if the request timed out
+            tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                f()
+            })
+            .await
+        };
+
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, task)
+                .await
+                .map_err(|_| ParsingError::Timeout)?,
+            None => task.await,
+        };
+        result.map_err(|error| std::panic::resume_unwind(error.into_panic()))
+    }
+}
+
 pub(crate) type ParsedDocument = Arc<ParsedDocumentInner>;
 
 pub(crate) struct ParsedDocumentInner {
     pub(crate) ast: ast::Document,
     pub(crate) executable: ExecutableDocument,
     pub(crate) parse_errors: Option<DiagnosticList>,
-    pub(crate) validation_errors: Option<DiagnosticList>,
+    pub(crate) validation_errors: Option<ValidationErrors>,
+    /// Parts of the document removed because they do not pass a configurable validation rule
+    pub(crate) rule_violations: Vec<RuleViolation>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+
+    fn parsing_pool(max_queue_depth: usize, timeout: Option<Duration>) -> Arc<ParsingPool> {
+        Arc::new(
+            ParsingPool::new(&ParserOffload {
+                min_document_bytes: Some(0),
+                max_concurrency: NonZeroUsize::new(1).unwrap(),
+                max_queue_depth,
+                timeout,
+            })
+            .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn parsing_pool_rejects_documents_when_the_queue_is_full() {
+        let pool = parsing_pool(1, None);
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+
+        // Occupy the only thread of the pool
+        let running = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || receiver.recv().unwrap()).await }
+        });
+        while pool.permits.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| ()).await }
+        });
+        while pool.queue_depth.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(pool.run(|| ()).await, Err(ParsingError::QueueFull));
+
+        sender.send(()).unwrap();
+        assert_eq!(running.await.unwrap(), Ok(()));
+        assert_eq!(queued.await.unwrap(), Ok(()));
+        assert_eq!(pool.queue_depth.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn parsing_pool_enforces_the_time_budget() {
+        let pool = parsing_pool(1, Some(Duration::from_millis(10)));
+        assert_eq!(
+            pool.run(|| std::thread::sleep(Duration::from_millis(200)))
+                .await,
+            Err(ParsingError::Timeout)
+        );
+
+        let pool = parsing_pool(1, Some(Duration::from_secs(5)));
+        assert_eq!(pool.run(|| 42).await, Ok(42));
+    }
+
+    #[tokio::test]
+    async fn documents_are_shared_by_their_operations() {
+        let mut configuration = Configuration::default();
+        configuration.supergraph.experimental_document_cache = DocumentCache {
+            limit: NonZeroUsize::new(1).unwrap(),
+            max_document_bytes: Some(100),
+        };
+        let configuration = Arc::new(configuration);
+        let schema = Schema::parse_test(
+            include_str!("../../testdata/minimal_supergraph.graphql"),
+            &configuration,
+        )
+        .unwrap();
+        let layer = QueryAnalysisLayer::new(Arc::new(schema), configuration).await;
+        let analyze = |query: &str, operation_name: &str| {
+            let request = SupergraphRequest::fake_builder()
+                .query(query)
+                .operation_name(operation_name)
+                .build()
+                .unwrap();
+            let layer = layer.clone();
+            async move { layer.supergraph_request(request).await.is_ok() }
+        };
+
+        let query = "query A { me } query B { me }";
+        assert!(analyze(query, "A").await);
+        assert!(analyze(query, "B").await);
+        let stats = layer.documents.stats().await;
+        assert_eq!((stats.size, stats.hits, stats.misses), (1, 1, 1));
+
+        assert!(analyze("query C { me }", "C").await);
+        assert_eq!(layer.documents.stats().await.evictions, 1);
+
+        // large documents are not cached
+        let large = format!("query D {{ me }} query E {{ me }} # {}", "a".repeat(100));
+        assert!(analyze(&large, "D").await);
+        assert!(analyze(&large, "E").await);
+        let stats = layer.documents.stats().await;
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 4, 1));
+    }
+
+    #[tokio::test]
+    async fn oversized_and_deeply_nested_documents_are_rejected() {
+        let mut configuration = Configuration::default();
+        configuration.limits.experimental_max_document_bytes = Some(100);
+        configuration.limits.experimental_max_nesting = Some(3);
+        let configuration = Arc::new(configuration);
+        let schema = Schema::parse_test(
+            include_str!("../../testdata/minimal_supergraph.graphql"),
+            &configuration,
+        )
+        .unwrap();
+        let layer = QueryAnalysisLayer::new(Arc::new(schema), configuration).await;
+        let analyze = |query: String| {
+            let request = SupergraphRequest::fake_builder()
+                .query(query)
+                .build()
+                .unwrap();
+            let layer = layer.clone();
+            async move {
+                match layer.supergraph_request(request).await {
+                    Ok(_) => None,
+                    Err(mut response) => {
+                        let response = response.next_response().await.unwrap();
+                        response.errors[0].extensions.get("code").cloned()
+                    }
+                }
+            }
+        };
+
+        assert_eq!(analyze("{ me }".to_string()).await, None);
+        assert_eq!(
+            analyze(format!("{{ me }} # {}", "a".repeat(100))).await,
+            Some("MAX_DOCUMENT_BYTES_LIMIT".into())
+        );
+        assert_eq!(
+            analyze(format!("{}{}", "{ a ".repeat(4), "}".repeat(4))).await,
+            Some("MAX_NESTING_LIMIT".into())
+        );
+        assert_eq!(layer.documents.stats().await.misses, 1);
+    }
 }
@@ -42,6 +42,7 @@ use crate::graphql;
 use crate::http_ext;
 #[cfg(test)]
 use crate::plugin::test::MockSupergraphService;
+use crate::plugin::LifecycleEvent;
 use crate::protocols::multipart::Multipart;
 use crate::protocols::multipart::ProtocolMode;
 use crate::query_planner::QueryPlanResult;
@@ -720,6 +721,13 @@ impl RouterFactory for RouterCreator {
             .for_each(|p| mm.extend(p.web_endpoints()));
         mm
     }
+
+    fn lifecycle_event(&self, event: &LifecycleEvent) {
+        self.supergraph_creator
+            .plugins()
+            .values()
+            .for_each(|p| p.lifecycle_event(event));
+    }
 }
 
 impl RouterCreator {
@@ -734,6 +742,7 @@ impl RouterCreator {
             APQLayer::with_cache(
                 DeduplicatingCache::from_configuration(&configuration.apq.router.cache, "APQ")
                     .await,
+                configuration.apq.router.cache.key.clone(),
             )
         } else {
             APQLayer::disabled()
@@ -1,5 +1,6 @@
 //! Tower fetcher for subgraphs.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::Display;
 use std::sync::atomic::AtomicBool;
@@ -13,6 +14,7 @@ use async_compression::tokio::write::BrotliEncoder;
 use async_compression::tokio::write::GzipEncoder;
 use async_compression::tokio::write::ZlibEncoder;
 use bytes::Bytes;
+use bytes::BytesMut;
 use futures::future::BoxFuture;
 use futures::SinkExt;
 use futures::StreamExt;
@@ -27,6 +29,7 @@ use http::response::Parts;
 use http::HeaderMap;
 use http::HeaderValue;
 use http::Request;
+use http_body::Body as HttpBody;
 use hyper::client::HttpConnector;
 use hyper::Body;
 use hyper_rustls::ConfigBuilderExt;
@@ -36,6 +39,7 @@ use mediatype::names::JSON;
 use mediatype::MediaType;
 use mime::APPLICATION_JSON;
 use opentelemetry::global;
+use opentelemetry::propagation::TextMapPropagator;
 use rustls::ClientConfig;
 use rustls::RootCertStore;
 use schemars::JsonSchema;
@@ -53,6 +57,7 @@ use tower_http::decompression::Decompression;
 use tower_http::decompression::DecompressionLayer;
 use tracing::Instrument;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
+use trust_dns_resolver::error::ResolveError;
 use uuid::Uuid;
 
 use super::layers::content_negotiation::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
@@ -69,13 +74,18 @@ use crate::plugins::subscription::SubscriptionConfig;
 use crate::plugins::subscription::SubscriptionMode;
 use crate::plugins::subscription::WebSocketConfiguration;
 use crate::plugins::subscription::SUBSCRIPTION_WS_CUSTOM_CONNECTION_PARAMS;
+use crate::plugins::telemetry::subgraph_bodies::SubgraphBodyLogger;
+use crate::plugins::telemetry::variable_redaction::RedactedVariables;
+use crate::plugins::telemetry::ZipkinMultiHeader;
 use crate::plugins::telemetry::LOGGING_DISPLAY_BODY;
 use crate::plugins::telemetry::LOGGING_DISPLAY_HEADERS;
 use crate::plugins::traffic_shaping::Http2Config;
+use crate::plugins::traffic_shaping::ResendCount;
 use crate::protocols::websocket::convert_websocket_stream;
 use crate::protocols::websocket::GraphqlWebSocket;
 use crate::query_planner::OperationKind;
 use crate::services::layers::apq;
+use crate::services::layers::memory_budget::MemoryBudget;
 use crate::services::trust_dns_connector::new_async_http_connector;
 use crate::services::trust_dns_connector::AsyncHyperResolver;
 use crate::services::SubgraphRequest;
@@ -98,6 +108,7 @@ const HASH_VERSION_VALUE: i32 = 1;
 const HASH_KEY: &str = "sha256Hash";
 const GRAPHQL_RESPONSE: mediatype::Name = mediatype::Name::new_unchecked("graphql-response");
 const POOL_IDLE_TIMEOUT_DURATION: Option<Duration> = Some(Duration::from_secs(5));
+const CONNECT_TIMEOUT_DURATION: Option<Duration> = Some(Duration::from_secs(10));
 
 // interior mutability is not a concern here, the value is never modified
 #[allow(clippy::declare_interior_mutable_const)]
@@ -165,6 +176,17 @@ pub(crate) struct SubgraphService {
     /// Subscription config if enabled
     subscription_config: Option<SubscriptionConfig>,
     notify: Notify<String, graphql::Response>,
+    /// Limits applied when reading the response body
+    response_limits: ResponseLimits,
+}
+
+/// Limits applied when reading a subgraph response body
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct ResponseLimits {
+    /// Maximum size of the response body, in bytes
+    pub(crate) max_size: Option<usize>,
+    /// Maximum time to wait for the next chunk of the response body
+    pub(crate) read_timeout: Option<Duration>,
 }
 
 impl SubgraphService {
@@ -229,6 +251,7 @@ impl SubgraphService {
         let mut http_connector = new_async_http_connector()?;
         http_connector.set_nodelay(true);
         http_connector.set_keepalive(Some(std::time::Duration::from_secs(60)));
+        http_connector.set_connect_timeout(CONNECT_TIMEOUT_DURATION);
         http_connector.enforce_http(false);
 
         let builder = hyper_rustls::HttpsConnectorBuilder::new()
@@ -254,8 +277,15 @@ impl SubgraphService {
             apq: Arc::new(<AtomicBool>::new(enable_apq)),
             subscription_config,
             notify,
+            response_limits: ResponseLimits::default(),
         })
     }
+
+    /// Set the limits applied when reading response bodies
+    pub(crate) fn with_response_limits(mut self, response_limits: ResponseLimits) -> Self {
+        self.response_limits = response_limits;
+        self
+    }
 }
 
 pub(crate) fn generate_tls_client_config(
@@ -332,6 +362,7 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
         let client = std::mem::replace(&mut self.client, clone);
 
         let arc_apq_enabled = self.apq.clone();
+        let response_limits = self.response_limits;
 
         let mut notify = self.notify.clone();
         let make_calls = async move {
@@ -461,7 +492,15 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
             // with the same request body.
             let apq_enabled = arc_apq_enabled.as_ref();
             if !apq_enabled.load(Relaxed) {
-                return call_http(request, body, context, client, &service_name).await;
+                return call_http(
+                    request,
+                    body,
+                    context,
+                    client,
+                    &service_name,
+                    response_limits,
+                )
+                .await;
             }
 
             // Else, if APQ is enabled,
@@ -497,6 +536,7 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
                 context.clone(),
                 client.clone(),
                 &service_name,
+                response_limits,
             )
             .await?;
 
@@ -508,11 +548,27 @@ impl tower::Service<SubgraphRequest> for SubgraphService {
             match get_apq_error(gql_response) {
                 APQError::PersistedQueryNotSupported => {
                     apq_enabled.store(false, Relaxed);
-                    call_http(request, body, context, client, &service_name).await
+                    call_http(
+                        request,
+                        body,
+                        context,
+                        client,
+                        &service_name,
+                        response_limits,
+                    )
+                    .await
                 }
                 APQError::PersistedQueryNotFound => {
                     apq_body.query = query;
-                    call_http(request, apq_body, context, client, &service_name).await
+                    call_http(
+                        request,
+                        apq_body,
+                        context,
+                        client,
+                        &service_name,
+                        response_limits,
+                    )
+                    .await
                 }
                 _ => Ok(response),
             }
@@ -538,6 +594,8 @@ async fn call_websocket(
         .clone()
         .unwrap_or_default();
 
+    let replica = subgraph_cfg.replica(&request, &subscription_hash).cloned();
+
     let SubgraphRequest {
         subgraph_request,
         subscription_stream,
@@ -572,7 +630,16 @@ async fn call_websocket(
             .build());
     }
 
-    let (parts, body) = subgraph_request.into_parts();
+    let (mut parts, body) = subgraph_request.into_parts();
+    if let Some(replica) = replica {
+        parts.uri = replica
+            .as_str()
+            .parse()
+            .map_err(|_| FetchError::SubrequestWsError {
+                service: service_name.clone(),
+                reason: format!("cannot parse the replica url {replica}"),
+            })?;
+    }
 
     // Check context key and Authorization header (context key takes precedence) to set connection params if needed
     let connection_params = match (
@@ -638,6 +705,14 @@ async fn call_websocket(
         "apollo.subgraph.name" = %service_name,
         "graphql.operation.name" = %operation_name,
     );
+    // Propagate the trace context in the handshake, as for the HTTP requests to subgraphs
+    let mut request = request;
+    get_text_map_propagator(|propagator| {
+        propagator.inject_context(
+            &subgraph_req_span.context(),
+            &mut opentelemetry_http::HeaderInjector(request.headers_mut()),
+        );
+    });
 
     let (ws_stream, mut resp) = match request.uri().scheme_str() {
         Some("wss") => {
@@ -716,6 +791,7 @@ async fn call_http(
     context: Context,
     client: HTTPClientService,
     service_name: &str,
+    response_limits: ResponseLimits,
 ) -> Result<SubgraphResponse, BoxError> {
     let SubgraphRequest {
         subgraph_request, ..
@@ -728,7 +804,38 @@ async fn call_http(
         .unwrap_or_default();
     let (parts, _) = subgraph_request.into_parts();
 
+    let body_logger = parts.extensions.get::<SubgraphBodyLogger>().cloned();
+    if let Some(body_logger) = &body_logger {
+        body_logger.log_request(
+            service_name,
+            &RedactedVariables::redact_body(&context, &body),
+        );
+    }
+
+    // Logged instead of the serialized body when some of its variables must be redacted
+    let redacted_body = if context.contains_key(LOGGING_DISPLAY_BODY) {
+        match RedactedVariables::redact_body(&context, &body) {
+            Cow::Owned(redacted_body) => Some(redacted_body),
+            Cow::Borrowed(_) => None,
+        }
+    } else {
+        None
+    };
     let body = serde_json::to_string(&body).expect("JSON serialization should not fail");
+    let memory_budget = context
+        .private_entries
+        .lock()
+        .get::<MemoryBudget>()
+        .cloned();
+    if let Some(memory_budget) = &memory_budget {
+        if !memory_budget.charge(body.len()) {
+            return Err(FetchError::SubrequestMemoryLimitExceeded {
+                service: service_name.to_string(),
+                limit: memory_budget.limit(),
+            }
+            .into());
+        }
+    }
     let compressed_body = compress(body, &parts.headers)
         .instrument(tracing::debug_span!("body_compression"))
         .await
@@ -780,13 +887,27 @@ async fn call_http(
         "net.transport" = "ip_tcp",
         "apollo.subgraph.name" = %service_name,
         "graphql.operation.name" = %operation_name,
+        "http.resend_count" = tracing::field::Empty,
     );
+    // only set on the retries of the traffic shaping plugin
+    if let Some(ResendCount(resend_count)) = request.extensions().get::<ResendCount>() {
+        subgraph_req_span.record("http.resend_count", *resend_count as i64);
+    }
     get_text_map_propagator(|propagator| {
         propagator.inject_context(
             &subgraph_req_span.context(),
             &mut opentelemetry_http::HeaderInjector(request.headers_mut()),
         );
     });
+    if request.extensions().get::<ZipkinMultiHeader>().is_some() {
+        opentelemetry_zipkin::Propagator::with_encoding(
+            opentelemetry_zipkin::B3Encoding::SingleAndMultiHeader,
+        )
+        .inject_context(
+            &subgraph_req_span.context(),
+            &mut opentelemetry_http::HeaderInjector(request.headers_mut()),
+        );
+    }
 
     // The graphql spec is lax about what strategy to use for processing responses: https://github.com/graphql/graphql-over-http/blob/main/spec/GraphQLOverHTTP.md#processing-the-response
     //
@@ -822,7 +943,14 @@ async fn call_http(
         tracing::info!(http.request.headers = ?request.headers(), apollo.subgraph.name = %service_name, "Request headers to subgraph {service_name:?}");
     }
     if display_body {
-        tracing::info!(http.request.body = ?request.body(), apollo.subgraph.name = %service_name, "Request body to subgraph {service_name:?}");
+        match &redacted_body {
+            Some(body) => {
+                tracing::info!(http.request.body = ?body, apollo.subgraph.name = %service_name, "Request body to subgraph {service_name:?}");
+            }
+            None => {
+                tracing::info!(http.request.body = ?request.body(), apollo.subgraph.name = %service_name, "Request body to subgraph {service_name:?}");
+            }
+        }
     }
 
     // Perform the actual fetch. If this fails then we didn't manage to make the call at all, so we can't do anything with it.
@@ -833,6 +961,7 @@ async fn call_http(
         request,
         display_headers,
         display_body,
+        response_limits,
     )
     .instrument(subgraph_req_span)
     .await?;
@@ -841,6 +970,9 @@ async fn call_http(
     if display_headers {
         tracing::info!(response.headers = ?parts.headers, apollo.subgraph.name = %service_name, "Response headers from subgraph {service_name:?}");
     }
+    if let (Some(body_logger), Some(Ok(body))) = (&body_logger, &body) {
+        body_logger.log_response(service_name, parts.status, body);
+    }
     if display_body {
         if let Some(Ok(b)) = &body {
             tracing::info!(
@@ -959,6 +1091,52 @@ fn get_graphql_content_type(service_name: &str, parts: &Parts) -> Result<Content
     }
 }
 
+/// The class of the failure to connect to a subgraph: `dns`, `tls`, `connection_refused`, `timeout`
+/// or `other`, or `None` if the error happened after the connection was established
+fn connection_error_class(error: &hyper::Error) -> Option<&'static str> {
+    if !error.is_connect() {
+        return None;
+    }
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if error.is::<rustls::Error>() {
+            return Some("tls");
+        }
+        if error.is::<ResolveError>() {
+            return Some("dns");
+        }
+        if let Some(error) = error.downcast_ref::<std::io::Error>() {
+            // the cause of an io error is not its source, but the error it wraps
+            if let Some(inner) = error.get_ref() {
+                if inner.is::<rustls::Error>() {
+                    return Some("tls");
+                }
+                if inner.is::<ResolveError>() {
+                    return Some("dns");
+                }
+            }
+            match error.kind() {
+                std::io::ErrorKind::ConnectionRefused => return Some("connection_refused"),
+                std::io::ErrorKind::TimedOut => return Some("timeout"),
+                _ => {}
+            }
+        }
+        source = error.source();
+    }
+    Some("other")
+}
+
+/// Counts a subgraph request that failed to connect to the subgraph, or that timed out
+pub(crate) fn record_connection_error(service_name: &str, error_class: &'static str) {
+    u64_counter!(
+        "apollo_router_subgraph_connection_errors_total",
+        "Number of subgraph requests that failed to connect to the subgraph",
+        1,
+        subgraph = service_name.to_string(),
+        error_class = error_class
+    );
+}
+
 async fn do_fetch(
     mut client: HTTPClientService,
     context: &Context,
@@ -966,6 +1144,7 @@ async fn do_fetch(
     request: Request<Body>,
     display_headers: bool,
     display_body: bool,
+    response_limits: ResponseLimits,
 ) -> Result<
     (
         Parts,
@@ -979,6 +1158,9 @@ async fn do_fetch(
         .call(request)
         .map_err(|err| {
             tracing::error!(fetch_error = ?err);
+            if let Some(error_class) = connection_error_class(&err) {
+                record_connection_error(service_name, error_class);
+            }
             FetchError::SubrequestHttpError {
                 status_code: None,
                 service: service_name.to_string(),
@@ -995,19 +1177,17 @@ async fn do_fetch(
         );
     }
     let content_type = get_graphql_content_type(service_name, &parts);
+    let memory_budget = context
+        .private_entries
+        .lock()
+        .get::<MemoryBudget>()
+        .cloned();
+    let memory_budget = memory_budget.as_ref();
 
     let body = if content_type.is_ok() {
-        let body = hyper::body::to_bytes(body)
+        let body = read_body(body, response_limits, memory_budget, service_name, &parts)
             .instrument(tracing::debug_span!("aggregate_response_data"))
-            .await
-            .map_err(|err| {
-                tracing::error!(fetch_error = ?err);
-                FetchError::SubrequestHttpError {
-                    status_code: Some(parts.status.as_u16()),
-                    service: service_name.to_string(),
-                    reason: err.to_string(),
-                }
-            });
+            .await;
         if let Ok(body) = &body {
             if display_body {
                 tracing::info!(
@@ -1018,17 +1198,9 @@ async fn do_fetch(
         Some(body)
     } else {
         if display_body {
-            let body = hyper::body::to_bytes(body)
+            let body = read_body(body, response_limits, memory_budget, service_name, &parts)
                 .instrument(tracing::debug_span!("aggregate_response_data"))
-                .await
-                .map_err(|err| {
-                    tracing::error!(fetch_error = ?err);
-                    FetchError::SubrequestHttpError {
-                        status_code: Some(parts.status.as_u16()),
-                        service: service_name.to_string(),
-                        reason: err.to_string(),
-                    }
-                });
+                .await;
             if let Ok(body) = &body {
                 tracing::info!(
                     http.response.body = %String::from_utf8_lossy(body), apollo.subgraph.name = %service_name, "Raw response body from subgraph {service_name:?} received"
@@ -1040,6 +1212,69 @@ async fn do_fetch(
     Ok((parts, content_type, body))
 }
 
+/// Aggregate the response body, aborting if it exceeds the configured size or the request's
+/// memory budget, or if no data was received for longer than the read timeout
+async fn read_body<B>(
+    body: B,
+    response_limits: ResponseLimits,
+    memory_budget: Option<&MemoryBudget>,
+    service_name: &str,
+    parts: &Parts,
+) -> Result<Bytes, FetchError>
+where
+    B: HttpBody<Data = Bytes>,
+    B::Error: Display + std::fmt::Debug,
+{
+    let http_error = |err: B::Error| {
+        tracing::error!(fetch_error = ?err);
+        FetchError::SubrequestHttpError {
+            status_code: Some(parts.status.as_u16()),
+            service: service_name.to_string(),
+            reason: err.to_string(),
+        }
+    };
+
+    if response_limits == ResponseLimits::default() && memory_budget.is_none() {
+        return hyper::body::to_bytes(body).await.map_err(http_error);
+    }
+
+    tokio::pin!(body);
+    let mut data = BytesMut::new();
+    loop {
+        let chunk = match response_limits.read_timeout {
+            Some(read_timeout) => tokio::time::timeout(read_timeout, body.data())
+                .await
+                .map_err(|_| FetchError::SubrequestBodyReadTimeout {
+                    service: service_name.to_string(),
+                    timeout_ms: read_timeout.as_millis() as u64,
+                })?,
+            None => body.data().await,
+        };
+        let chunk = match chunk {
+            Some(chunk) => chunk.map_err(http_error)?,
+            None => break,
+        };
+        if let Some(max_size) = response_limits.max_size {
+            if data.len() + chunk.len() > max_size {
+                return Err(FetchError::SubrequestResponseTooLarge {
+                    service: service_name.to_string(),
+                    limit: max_size,
+                });
+            }
+        }
+        if let Some(memory_budget) = memory_budget {
+            if !memory_budget.charge(chunk.len()) {
+                return Err(FetchError::SubrequestMemoryLimitExceeded {
+                    service: service_name.to_string(),
+                    limit: memory_budget.limit(),
+                });
+            }
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data.freeze())
+}
+
 fn get_websocket_request(
     service_name: String,
     mut parts: http::request::Parts,
@@ -1252,6 +1487,7 @@ mod tests {
     use crate::graphql::Error;
     use crate::graphql::Request;
     use crate::graphql::Response;
+    use crate::metrics::FutureMetricsExt;
     use crate::plugins::subscription::Disabled;
     use crate::plugins::subscription::SubgraphPassthroughMode;
     use crate::plugins::subscription::SubscriptionModeConfig;
@@ -1381,6 +1617,29 @@ mod tests {
         server.await.unwrap();
     }
 
+    // starts a local server emulating a subgraph expecting the single and multi B3 headers
+    async fn emulate_subgraph_b3_multi_header(listener: TcpListener) {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            let headers = request.headers();
+            let trace_id = headers.get("x-b3-traceid").unwrap().to_str().unwrap();
+            let span_id = headers.get("x-b3-spanid").unwrap().to_str().unwrap();
+            assert_eq!(headers.get("x-b3-sampled").unwrap(), "1");
+            assert_eq!(
+                headers.get("b3").unwrap().to_str().unwrap(),
+                format!("{trace_id}-{span_id}-1")
+            );
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .status(StatusCode::OK)
+                .body(r#"{"data": null}"#.into())
+                .unwrap())
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+        server.await.unwrap();
+    }
+
     // starts a local server emulating a subgraph returning bad response format
     async fn emulate_subgraph_bad_response_format(listener: TcpListener) {
         async fn handle(_request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
@@ -1396,6 +1655,30 @@ mod tests {
         server.await.unwrap();
     }
 
+    // starts a local server emulating a subgraph that stops sending its response body
+    async fn emulate_subgraph_stalled_response(listener: TcpListener) {
+        async fn handle(_request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            let (mut sender, body) = Body::channel();
+            tokio::task::spawn(async move {
+                sender
+                    .send_data(Bytes::from_static(br#"{"data": "#))
+                    .await
+                    .unwrap();
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                let _ = sender.send_data(Bytes::from_static(b"null}")).await;
+            });
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+                .status(StatusCode::OK)
+                .body(body)
+                .unwrap())
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+        server.await.unwrap();
+    }
+
     // starts a local server emulating a subgraph returning compressed response
     async fn emulate_subgraph_compressed_response(listener: TcpListener) {
         async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
@@ -1892,6 +2175,7 @@ mod tests {
                         WebSocketConfiguration {
                             path: Some(String::from("/ws")),
                             protocol: WebSocketProtocol::default(),
+                            ..Default::default()
                         },
                     )]
                     .into(),
@@ -1900,6 +2184,7 @@ mod tests {
             enable_deduplication: true,
             max_opened_subscriptions: None,
             queue_capacity: None,
+            experimental_event_batching: Vec::new(),
         }
     }
 
@@ -2255,6 +2540,80 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_subgraph_service_websocket_propagates_trace_context() {
+        use opentelemetry::trace::TraceContextExt;
+        use opentelemetry::trace::TracerProvider as _;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        global::set_text_map_propagator(
+            opentelemetry::sdk::propagation::TraceContextPropagator::new(),
+        );
+        let tracer_provider = opentelemetry::sdk::trace::TracerProvider::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        // The subgraph rejects the handshake once it received its headers
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let (headers_tx, mut headers_rx) = mpsc::channel(1);
+        let app = Router::new().route(
+            "/ws",
+            get(move |headers: HeaderMap| {
+                let headers_tx = headers_tx.clone();
+                async move {
+                    let _ = headers_tx.send(headers).await;
+                    StatusCode::BAD_REQUEST
+                }
+            }),
+        );
+        tokio::task::spawn(
+            Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        let subgraph_service = SubgraphService::new(
+            "test",
+            true,
+            Http2Config::Disable,
+            subscription_config().into(),
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_native_roots()
+                .with_no_client_auth(),
+            Notify::builder().build(),
+        )
+        .expect("can create a SubgraphService");
+        let (tx, _rx) = mpsc::channel(2);
+
+        let span = tracing::info_span!("subscribe");
+        let trace_id = span.context().span().span_context().trace_id();
+        let url = Uri::from_str(&format!("ws://{socket_addr}")).unwrap();
+        let _ = subgraph_service
+            .oneshot(
+                SubgraphRequest::builder()
+                    .supergraph_request(supergraph_request(
+                        "subscription {\n  userWasCreated {\n    username\n  }\n}",
+                    ))
+                    .subgraph_request(subgraph_http_request(
+                        url,
+                        "subscription {\n  userWasCreated {\n    username\n  }\n}",
+                    ))
+                    .operation_kind(OperationKind::Subscription)
+                    .subscription_stream(tx)
+                    .subgraph_name(String::from("test"))
+                    .context(Context::new())
+                    .build(),
+            )
+            .instrument(span)
+            .await;
+
+        let headers = headers_rx.recv().await.unwrap();
+        let traceparent = headers.get("traceparent").unwrap().to_str().unwrap();
+        assert!(traceparent.contains(&trace_id.to_string()));
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_bad_status_code_should_not_fail() {
         let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
@@ -2334,6 +2693,275 @@ mod tests {
         );
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_response_too_large() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        tokio::task::spawn(emulate_subgraph_application_json_response(listener));
+
+        let subgraph_service = SubgraphService::new(
+            "test",
+            true,
+            Http2Config::Enable,
+            None,
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_native_roots()
+                .with_no_client_auth(),
+            Notify::default(),
+        )
+        .expect("can create a SubgraphService")
+        .with_response_limits(ResponseLimits {
+            max_size: Some(5),
+            read_timeout: None,
+        });
+
+        let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
+        let response = subgraph_service
+            .oneshot(
+                SubgraphRequest::builder()
+                    .supergraph_request(supergraph_request("query"))
+                    .subgraph_request(subgraph_http_request(url, "query"))
+                    .operation_kind(OperationKind::Query)
+                    .subgraph_name(String::from("test"))
+                    .context(Context::new())
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let error = &response.response.body().errors[0];
+        assert_eq!(
+            error.message,
+            "response from 'test' exceeded the maximum size of 5 bytes"
+        );
+        assert_eq!(
+            error.extensions.get("code").unwrap(),
+            "SUBREQUEST_RESPONSE_TOO_LARGE"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_memory_budget_exceeded() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        tokio::task::spawn(emulate_subgraph_application_json_response(listener));
+
+        let subgraph_service = SubgraphService::new(
+            "test",
+            true,
+            Http2Config::Enable,
+            None,
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_native_roots()
+                .with_no_client_auth(),
+            Notify::default(),
+        )
+        .expect("can create a SubgraphService");
+
+        let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
+        let request = |memory_budget: MemoryBudget| {
+            let context = Context::new();
+            context.private_entries.lock().insert(memory_budget);
+            SubgraphRequest::builder()
+                .supergraph_request(supergraph_request("query"))
+                .subgraph_request(subgraph_http_request(url.clone(), "query"))
+                .operation_kind(OperationKind::Query)
+                .subgraph_name(String::from("test"))
+                .context(context)
+                .build()
+        };
+        let request_body_size =
+            serde_json::to_string(request(MemoryBudget::new(0)).subgraph_request.body())
+                .unwrap()
+                .len();
+
+        // The request body does not fit in the budget, it is not sent
+        let error = subgraph_service
+            .clone()
+            .oneshot(request(MemoryBudget::new(request_body_size - 1)))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the request exceeded its memory budget of {} bytes while fetching from 'test'",
+                request_body_size - 1
+            )
+        );
+
+        // The response body does not fit in the rest of the budget
+        let memory_budget = MemoryBudget::new(request_body_size + 5);
+        let error = subgraph_service
+            .oneshot(request(memory_budget.clone()))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the request exceeded its memory budget of {} bytes while fetching from 'test'",
+                request_body_size + 5
+            )
+        );
+        assert!(memory_budget.is_exceeded());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_response_body_read_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        tokio::task::spawn(emulate_subgraph_stalled_response(listener));
+
+        let subgraph_service = SubgraphService::new(
+            "test",
+            true,
+            Http2Config::Enable,
+            None,
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_native_roots()
+                .with_no_client_auth(),
+            Notify::default(),
+        )
+        .expect("can create a SubgraphService")
+        .with_response_limits(ResponseLimits {
+            max_size: None,
+            read_timeout: Some(Duration::from_millis(100)),
+        });
+
+        let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
+        let response = subgraph_service
+            .oneshot(
+                SubgraphRequest::builder()
+                    .supergraph_request(supergraph_request("query"))
+                    .subgraph_request(subgraph_http_request(url, "query"))
+                    .operation_kind(OperationKind::Query)
+                    .subgraph_name(String::from("test"))
+                    .context(Context::new())
+                    .build(),
+            )
+            .await
+            .unwrap();
+        let error = &response.response.body().errors[0];
+        assert_eq!(
+            error.message,
+            "response body from 'test' stalled: no data received for 100ms"
+        );
+        assert_eq!(
+            error.extensions.get("code").unwrap(),
+            "SUBREQUEST_BODY_READ_TIMEOUT"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_connection_error_metrics() {
+        async {
+            // nothing listens on the port of a dropped listener
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let socket_addr = listener.local_addr().unwrap();
+            drop(listener);
+
+            let subgraph_service = SubgraphService::new(
+                "test",
+                true,
+                Http2Config::Enable,
+                None,
+                ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_native_roots()
+                    .with_no_client_auth(),
+                Notify::default(),
+            )
+            .expect("can create a SubgraphService");
+
+            for url in [
+                format!("http://{socket_addr}"),
+                "http://subgraph.invalid".to_string(),
+            ] {
+                let url = Uri::from_str(&url).unwrap();
+                let response = subgraph_service
+                    .clone()
+                    .oneshot(
+                        SubgraphRequest::builder()
+                            .supergraph_request(supergraph_request("query"))
+                            .subgraph_request(subgraph_http_request(url, "query"))
+                            .operation_kind(OperationKind::Query)
+                            .subgraph_name(String::from("test"))
+                            .context(Context::new())
+                            .build(),
+                    )
+                    .await;
+                assert!(response.is_err());
+            }
+
+            assert_counter!(
+                "apollo_router_subgraph_connection_errors_total",
+                1,
+                "subgraph" = "test",
+                "error_class" = "connection_refused"
+            );
+            assert_counter!(
+                "apollo_router_subgraph_connection_errors_total",
+                1,
+                "subgraph" = "test",
+                "error_class" = "dns"
+            );
+        }
+        .with_metrics()
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_zipkin_multi_header() {
+        use opentelemetry::trace::TracerProvider as _;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let tracer_provider = opentelemetry::sdk::trace::TracerProvider::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        tokio::task::spawn(emulate_subgraph_b3_multi_header(listener));
+        let subgraph_service = SubgraphService::new(
+            "test",
+            false,
+            Http2Config::Enable,
+            None,
+            ClientConfig::builder()
+                .with_safe_defaults()
+                .with_native_roots()
+                .with_no_client_auth(),
+            Notify::default(),
+        )
+        .expect("can create a SubgraphService");
+
+        let url = Uri::from_str(&format!("http://{socket_addr}")).unwrap();
+        let mut subgraph_request = http::Request::builder()
+            .header(HOST, "rhost")
+            .header(CONTENT_TYPE, APPLICATION_JSON.essence_str())
+            .uri(url)
+            .body(Request::builder().query("query".to_string()).build())
+            .expect("expecting valid request");
+        subgraph_request.extensions_mut().insert(ZipkinMultiHeader);
+        let resp = subgraph_service
+            .oneshot(SubgraphRequest {
+                supergraph_request: supergraph_request("query"),
+                subgraph_request,
+                operation_kind: OperationKind::Query,
+                context: Context::new(),
+                subgraph_name: String::from("test").into(),
+                subscription_stream: None,
+                connection_closed_signal: None,
+                query_hash: Default::default(),
+                authorization: Default::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(resp.response.status(), StatusCode::OK);
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_compressed_request_response_body() {
         let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
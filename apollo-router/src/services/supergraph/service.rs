@@ -10,10 +10,11 @@ use futures::stream::StreamExt;
 use futures::TryFutureExt;
 use http::StatusCode;
 use indexmap::IndexMap;
+use opentelemetry::trace::SpanContext;
+use opentelemetry::trace::TraceContextExt;
 use router_bridge::planner::Planner;
 use router_bridge::planner::UsageReporting;
 use tokio::sync::mpsc;
-use tokio::sync::mpsc::error::SendError;
 use tokio_stream::wrappers::ReceiverStream;
 use tower::BoxError;
 use tower::Layer;
@@ -23,6 +24,7 @@ use tower_service::Service;
 use tracing::field;
 use tracing::Span;
 use tracing_futures::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::configuration::Batching;
 use crate::context::OPERATION_NAME;
@@ -50,6 +52,7 @@ use crate::router_factory::create_subgraph_services;
 use crate::services::execution::QueryPlan;
 use crate::services::layers::allow_only_http_post_mutations::AllowOnlyHttpPostMutationsLayer;
 use crate::services::layers::content_negotiation;
+use crate::services::layers::memory_budget::MemoryBudgetLayer;
 use crate::services::layers::persisted_queries::PersistedQueryLayer;
 use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::layers::query_analysis::QueryAnalysisLayer;
@@ -68,6 +71,7 @@ use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
 use crate::spec::Query;
 use crate::spec::Schema;
+use crate::spec::Selection;
 use crate::Configuration;
 use crate::Context;
 use crate::Notify;
@@ -163,6 +167,7 @@ async fn service_call(
     notify: Notify<String, graphql::Response>,
 ) -> Result<SupergraphResponse, BoxError> {
     let context = req.context;
+    planning.extend_cache_key(req.supergraph_request.headers(), &context);
     let body = req.supergraph_request.body();
     let variables = body.variables.clone();
 
@@ -294,6 +299,7 @@ async fn service_call(
                     let execution_service_factory_cloned = execution_service_factory.clone();
                     let cloned_supergraph_req =
                         clone_supergraph_request(&req.supergraph_request, context.clone())?;
+                    let subscribe_span = Span::current().context().span().span_context().clone();
                     // Spawn task for subscription
                     tokio::spawn(async move {
                         subscription_task(
@@ -303,6 +309,7 @@ async fn service_call(
                             subs_rx,
                             notify,
                             cloned_supergraph_req,
+                            subscribe_span,
                         )
                         .await;
                     });
@@ -352,6 +359,7 @@ async fn subscription_task(
     mut rx: mpsc::Receiver<SubscriptionTaskParams>,
     notify: Notify<String, graphql::Response>,
     supergraph_req: SupergraphRequest,
+    subscribe_span: SpanContext,
 ) {
     let sub_params = match rx.recv().await {
         Some(sub_params) => sub_params,
@@ -365,6 +373,20 @@ async fn subscription_task(
     let mut receiver = sub_params.stream_rx;
     let sender = sub_params.client_sender;
 
+    // The subscription root field selects the batching of its events
+    let event_batching = query_plan
+        .query
+        .operation(context.get::<_, String>(OPERATION_NAME).ok().flatten())
+        .and_then(|operation| operation.selection_set.first())
+        .and_then(|selection| match selection {
+            Selection::Field { name, .. } => subscription_config
+                .experimental_event_batching
+                .iter()
+                .find(|batching| batching.matching.is_match(name.as_str()))
+                .cloned(),
+            _ => None,
+        });
+
     // Get the rest of the query_plan to execute for subscription events
     let query_plan = match &query_plan.root {
         crate::query_planner::PlanNode::Subscription { rest, .. } => rest.clone().map(|r| {
@@ -408,13 +430,17 @@ async fn subscription_task(
         .unwrap_or_default();
     let display_body = context.contains_key(LOGGING_DISPLAY_BODY);
 
-    let mut receiver = match receiver.next().await {
+    let receiver = match receiver.next().await {
         Some(receiver) => receiver,
         None => {
             tracing::trace!("receiver channel closed");
             return;
         }
     };
+    let mut receiver = match event_batching {
+        Some(batching) => batching.batch(receiver),
+        None => receiver.map(|event| vec![event]).boxed(),
+    };
 
     if limit_is_set {
         OPENED_SUBSCRIPTIONS.fetch_add(1, Ordering::Relaxed);
@@ -449,20 +475,39 @@ async fn subscription_task(
             },
             message = receiver.next() => {
                 match message {
-                    Some(mut val) => {
-                        if display_body {
-                            tracing::info!(http.request.body = ?val, apollo.subgraph.name = %service_name, "Subscription event body from subgraph {service_name:?}");
-                        }
-                        val.created_at = Some(Instant::now());
-                        let res = dispatch_event(&supergraph_req, &execution_service_factory, query_plan.as_ref(), context.clone(), val, sender.clone())
-                            .instrument(tracing::info_span!(SUBSCRIPTION_EVENT_SPAN_NAME,
+                    Some(batch) => {
+                        let mut responses = Vec::with_capacity(batch.len());
+                        for mut val in batch {
+                            if display_body {
+                                tracing::info!(http.request.body = ?val, apollo.subgraph.name = %service_name, "Subscription event body from subgraph {service_name:?}");
+                            }
+                            val.created_at = Some(Instant::now());
+                            let event_span = tracing::info_span!(SUBSCRIPTION_EVENT_SPAN_NAME,
                                 graphql.operation.name = %operation_name,
                                 otel.kind = "INTERNAL",
                                 apollo_private.operation_signature = %operation_signature,
-                                apollo_private.duration_ns = field::Empty,)
-                            ).await;
-                        if let Err(err) = res {
-                                tracing::error!("cannot send the subscription to the client: {err:?}");
+                                apollo_private.duration_ns = field::Empty,);
+                            // Events get their own trace, as the subscribe request may have ended long
+                            // ago, but are linked to it
+                            if subscribe_span.is_valid() {
+                                event_span.add_link(subscribe_span.clone());
+                            }
+                            responses.extend(
+                                dispatch_event(&supergraph_req, &execution_service_factory, query_plan.as_ref(), context.clone(), val)
+                                    .instrument(event_span)
+                                    .await,
+                            );
+                        }
+                        // The events of a batch are delivered together, once they were all executed
+                        let mut sent = Ok(());
+                        for response in responses {
+                            sent = sender.send(response).await;
+                            if sent.is_err() {
+                                break;
+                            }
+                        }
+                        if let Err(err) = sent {
+                            tracing::error!("cannot send the subscription to the client: {err:?}");
                             break;
                         }
                     }
@@ -514,14 +559,15 @@ async fn subscription_task(
     }
 }
 
+/// Executes the rest of the query plan for a subscription event, returns the response to send to
+/// the client
 async fn dispatch_event(
     supergraph_req: &SupergraphRequest,
     execution_service_factory: &ExecutionServiceFactory,
     query_plan: Option<&Arc<QueryPlan>>,
     context: Context,
     mut val: graphql::Response,
-    sender: mpsc::Sender<Response>,
-) -> Result<(), SendError<Response>> {
+) -> Option<Response> {
     let start = Instant::now();
     let span = Span::current();
     let res = match query_plan {
@@ -545,34 +591,28 @@ async fn dispatch_event(
                 Ok(mut execution_response) => execution_response.next_response().await,
                 Err(err) => {
                     tracing::error!("cannot execute the subscription event: {err:?}");
-                    let _ = sender
-                        .send(
-                            graphql::Response::builder()
-                                .error(
-                                    graphql::Error::builder()
-                                        .message("cannot execute the subscription event")
-                                        .extension_code("SUBSCRIPTION_EXECUTION_ERROR")
-                                        .build(),
-                                )
-                                .build(),
-                        )
-                        .await;
-                    return Ok(());
+                    return Some(
+                        graphql::Response::builder()
+                            .error(
+                                graphql::Error::builder()
+                                    .message("cannot execute the subscription event")
+                                    .extension_code("SUBSCRIPTION_EXECUTION_ERROR")
+                                    .build(),
+                            )
+                            .build(),
+                    );
                 }
             };
 
-            if let Some(mut next_response) = next_response {
+            next_response.map(|mut next_response| {
                 next_response.created_at = val.created_at;
                 next_response.subscribed = val.subscribed;
                 val.errors.append(&mut next_response.errors);
                 next_response.errors = val.errors;
-
-                sender.send(next_response).await
-            } else {
-                Ok(())
-            }
+                next_response
+            })
         }
-        None => sender.send(val).await,
+        None => Some(val),
     };
     span.record(
         APOLLO_PRIVATE_DURATION_NS,
@@ -605,7 +645,8 @@ async fn plan_query(
         drop(entries);
     }
 
-    planning
+    let span = tracing::info_span!(QUERY_PLANNING_SPAN_NAME, "otel.kind" = "INTERNAL");
+    let result = planning
         .call(
             query_planner::CachingRequest::builder()
                 .query(query_str)
@@ -613,11 +654,13 @@ async fn plan_query(
                 .context(context)
                 .build(),
         )
-        .instrument(tracing::info_span!(
-            QUERY_PLANNING_SPAN_NAME,
-            "otel.kind" = "INTERNAL"
-        ))
-        .await
+        .instrument(span.clone())
+        .await;
+    if let Ok(response) = &result {
+        Telemetry::set_query_planning_attributes(&span, response);
+    }
+
+    result
 }
 
 fn clone_supergraph_request(
@@ -809,6 +852,14 @@ impl SupergraphCreator {
 
         let supergraph_service = AllowOnlyHttpPostMutationsLayer::default()
             .layer(shaping.supergraph_service_internal(supergraph_service));
+        let supergraph_service = ServiceBuilder::new()
+            .option_layer(
+                self.config
+                    .limits
+                    .experimental_max_request_memory
+                    .map(MemoryBudgetLayer::new),
+            )
+            .service(supergraph_service);
 
         ServiceBuilder::new()
             .layer(content_negotiation::SupergraphLayer::default())
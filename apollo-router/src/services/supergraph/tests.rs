@@ -604,6 +604,78 @@ async fn subscription_with_callback() {
     insta::assert_json_snapshot!(stream.next_response().await.unwrap());
 }
 
+#[tokio::test]
+async fn subscription_event_span_is_linked_to_the_subscribe_span() {
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry::trace::TracerProvider as _;
+    use tracing::Instrument;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let (exporter, spans, _) = opentelemetry::sdk::testing::trace::new_test_exporter();
+    let tracer_provider = opentelemetry::sdk::trace::TracerProvider::builder()
+        .with_simple_exporter(exporter)
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let mut notify = Notify::builder().build();
+    let (handle, _) = notify
+        .create_or_subscribe("TEST_TOPIC".to_string(), false)
+        .await
+        .unwrap();
+    let subgraphs = MockedSubgraphs([
+            ("user", MockSubgraph::builder().with_json(
+                    serde_json::json!{{"query":"subscription{userWasCreated{name}}"}},
+                    serde_json::json!{{"data": {"userWasCreated": { "__typename": "User", "id": "1" }}}}
+                ).with_subscription_stream(handle.clone()).build()),
+        ].into_iter().collect());
+
+    let mut configuration: Configuration = serde_json::from_value(serde_json::json!({"subscription": { "enabled": true, "mode": {"callback": {"public_url": "http://localhost:4545/callback"}}}})).unwrap();
+    configuration.notify = notify.clone();
+    let service = TestHarness::builder()
+        .configuration(Arc::new(configuration))
+        .schema(SCHEMA)
+        .extra_plugin(subgraphs)
+        .build_supergraph()
+        .await
+        .unwrap();
+
+    let request = supergraph::Request::fake_builder()
+        .query("subscription { userWasCreated { name } }")
+        .context(subscription_context())
+        .build()
+        .unwrap();
+    let subscribe_span = tracing::info_span!("subscribe");
+    let subscribe_trace_id = subscribe_span.context().span().span_context().trace_id();
+    let mut stream = service
+        .oneshot(request)
+        .instrument(subscribe_span)
+        .await
+        .unwrap();
+    stream.next_response().await.unwrap();
+    notify
+        .broadcast(
+            graphql::Response::builder()
+                .data(serde_json_bytes::json!({"userWasCreated": { "name": "test" }}))
+                .build(),
+        )
+        .await
+        .unwrap();
+    stream.next_response().await.unwrap();
+
+    let event_span = std::iter::from_fn(|| spans.recv_timeout(Duration::from_secs(5)).ok())
+        .find(|span| span.name == "subscription_event")
+        .expect("the event span is exported");
+    // The event has its own trace, linked to the subscribe request
+    assert_ne!(event_span.span_context.trace_id(), subscribe_trace_id);
+    assert!(event_span
+        .links
+        .iter()
+        .any(|link| link.span_context.trace_id() == subscribe_trace_id));
+}
+
 #[tokio::test]
 async fn subscription_callback_schema_reload() {
     let mut notify = Notify::builder().build();
@@ -0,0 +1,131 @@
+//! Limits checked on the source of a document before it is parsed, so that oversized or deeply
+//! nested documents are rejected without the cost of a full parse.
+use crate::configuration::Limits;
+
+/// A limit exceeded by the source of a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DocumentLimitExceeded {
+    Bytes,
+    Nesting,
+}
+
+impl DocumentLimitExceeded {
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            DocumentLimitExceeded::Bytes => "MAX_DOCUMENT_BYTES_LIMIT",
+            DocumentLimitExceeded::Nesting => "MAX_NESTING_LIMIT",
+        }
+    }
+
+    pub(crate) fn message(&self) -> &'static str {
+        match self {
+            DocumentLimitExceeded::Bytes => "Maximum document size limit exceeded",
+            DocumentLimitExceeded::Nesting => "Maximum nesting limit exceeded in this document",
+        }
+    }
+}
+
+/// Checks the size of a document
+pub(crate) fn check_bytes(limits: &Limits, source: &str) -> Result<(), DocumentLimitExceeded> {
+    match limits.experimental_max_document_bytes {
+        Some(max) if source.len() > max => Err(DocumentLimitExceeded::Bytes),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the nesting of a document, in a single pass over its source
+pub(crate) fn check_nesting(limits: &Limits, source: &str) -> Result<(), DocumentLimitExceeded> {
+    match limits.experimental_max_nesting {
+        Some(max) if exceeds_nesting(source, max) => Err(DocumentLimitExceeded::Nesting),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the selection sets, arguments, lists and objects of a document are nested deeper than
+/// `max`, skipping strings and comments. The document does not need to be valid
+fn exceeds_nesting(source: &str, max: usize) -> bool {
+    let bytes = source.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'#' => {
+                while i < bytes.len() && bytes[i] != b'\n' && bytes[i] != b'\r' {
+                    i += 1;
+                }
+            }
+            b'"' if bytes[i..].starts_with(b"\"\"\"") => {
+                i += 3;
+                while i < bytes.len() && !bytes[i..].starts_with(b"\"\"\"") {
+                    if bytes[i..].starts_with(b"\\\"\"\"") {
+                        i += 4;
+                    } else {
+                        i += 1;
+                    }
+                }
+                i += 3;
+                continue;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' && bytes[i] != b'\n' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            b'{' | b'(' | b'[' => {
+                depth += 1;
+                if depth > max {
+                    return true;
+                }
+            }
+            b'}' | b')' | b']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        i += 1;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_measures_nesting() {
+        assert!(!exceeds_nesting("{ a { b } }", 2));
+        assert!(exceeds_nesting("{ a { b { c } } }", 2));
+        assert!(exceeds_nesting("{ a(arg: [[1]]) }", 3));
+        assert!(!exceeds_nesting("{ a(arg: [1]) } { b { c } }", 3));
+
+        // strings and comments are skipped
+        assert!(!exceeds_nesting(r#"{ a(arg: "{{{\"{{") }"#, 2));
+        assert!(!exceeds_nesting("{ a(arg: \"\"\"{{{\\\"\"\"{{\"\"\") }", 2));
+        assert!(!exceeds_nesting("{ a # {{{{\n }", 1));
+
+        // documents are rejected before they are parsed
+        assert!(exceeds_nesting(&"{ a ".repeat(10_000), 100));
+    }
+
+    #[test]
+    fn it_checks_limits() {
+        let limits = Limits {
+            experimental_max_document_bytes: Some(10),
+            experimental_max_nesting: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(check_bytes(&limits, "{ a }"), Ok(()));
+        assert_eq!(
+            check_bytes(&limits, "{ a b c d e }"),
+            Err(DocumentLimitExceeded::Bytes)
+        );
+        assert_eq!(check_nesting(&limits, "{ a }"), Ok(()));
+        assert_eq!(
+            check_nesting(&limits, "{ a { b } }"),
+            Err(DocumentLimitExceeded::Nesting)
+        );
+        assert_eq!(check_nesting(&Limits::default(), "{ a { b } }"), Ok(()));
+    }
+}
@@ -2,12 +2,14 @@
 #![cfg_attr(not(test), deny(clippy::expect_used))]
 #![cfg_attr(not(test), deny(clippy::panic))]
 
+pub(crate) mod document_limits;
 mod field_type;
 mod fragments;
 pub(crate) mod operation_limits;
 pub(crate) mod query;
 mod schema;
 mod selection;
+pub(crate) mod validation_rules;
 
 use displaydoc::Display;
 pub(crate) use field_type::*;
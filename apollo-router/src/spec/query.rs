@@ -37,6 +37,7 @@ use crate::plugins::authorization::UnauthorizedPaths;
 use crate::query_planner::fetch::OperationKind;
 use crate::services::layers::query_analysis::ParsedDocument;
 use crate::services::layers::query_analysis::ParsedDocumentInner;
+use crate::spec::validation_rules;
 use crate::spec::FieldType;
 use crate::spec::Fragments;
 use crate::spec::InvalidValue;
@@ -283,19 +284,40 @@ impl Query {
         let parser = &mut apollo_compiler::Parser::new()
             .recursion_limit(configuration.limits.parser_max_recursion)
             .token_limit(configuration.limits.parser_max_tokens);
-        let (ast, parse_errors) = match parser.parse_ast(query, "query.graphql") {
+        let (mut ast, parse_errors) = match parser.parse_ast(query, "query.graphql") {
             Ok(ast) => (ast, None),
             Err(WithErrors { partial, errors }) => (partial, Some(errors)),
         };
         let schema = &schema.api_schema().definitions;
         let validate =
             configuration.experimental_graphql_validation_mode != GraphQLValidationMode::Legacy;
+        let mut rule_violations = Vec::new();
         // Stretch the meaning of "assume valid" to "we’ll check later"
         let (executable, validation_errors) = if validate {
-            match ast.to_executable_validate(schema) {
+            // The configurable rules are checked for each request, on the listener it was
+            // received on, so the compiler only validates the rest of the document
+            let rules =
+                configuration.experimental_graphql_validation_mode == GraphQLValidationMode::New;
+            if rules {
+                rule_violations = validation_rules::remove_violations(&mut ast, schema);
+            }
+            let (executable, validation_errors) = match ast.to_executable_validate(schema) {
                 Ok(doc) => (doc.into_inner(), None),
-                Err(WithErrors { partial, errors }) => (partial, Some(errors)),
+                Err(WithErrors { partial, errors }) => (
+                    partial,
+                    Some(ValidationErrors {
+                        errors: errors.iter().map(|error| error.to_json()).collect(),
+                    }),
+                ),
+            };
+            if rules
+                && configuration
+                    .experimental_validation_rules
+                    .enables(validation_rules::Rule::DeprecatedFields)
+            {
+                rule_violations.extend(validation_rules::deprecated_fields(&executable));
             }
+            (executable, validation_errors)
         } else {
             match ast.to_executable(schema) {
                 Ok(doc) => (doc, None),
@@ -312,6 +334,7 @@ impl Query {
             executable,
             parse_errors,
             validation_errors,
+            rule_violations,
         })
     }
 
@@ -352,7 +375,7 @@ impl Query {
     /// Check for validation errors in a query in the compiler.
     pub(crate) fn validate_query(document: &ParsedDocument) -> Result<(), ValidationErrors> {
         match document.validation_errors.clone() {
-            Some(errors) => Err(ValidationErrors { errors }),
+            Some(errors) => Err(errors),
             None => Ok(()),
         }
     }
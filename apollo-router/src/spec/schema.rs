@@ -76,6 +76,13 @@ impl Schema {
         })
     }
 
+    /// Compute the hash identifying a supergraph schema from its SDL.
+    pub(crate) fn schema_id(sdl: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(sdl.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
     pub(crate) fn parse(sdl: &str, configuration: &Configuration) -> Result<Self, SchemaError> {
         let start = Instant::now();
         let ast = Self::parse_ast(sdl)?;
@@ -98,7 +105,7 @@ impl Schema {
         // validation as authoritative and only use the new result for comparison
         if configuration.experimental_graphql_validation_mode == GraphQLValidationMode::New {
             if let Some(errors) = diagnostics {
-                return Err(SchemaError::Validate(ValidationErrors { errors }));
+                return Err(SchemaError::Validate(ValidationErrors::from(errors)));
             }
         }
 
@@ -124,9 +131,7 @@ impl Schema {
             }
         }
 
-        let mut hasher = Sha256::new();
-        hasher.update(sdl.as_bytes());
-        let schema_id = Some(format!("{:x}", hasher.finalize()));
+        let schema_id = Some(Self::schema_id(sdl));
         tracing::info!(
             histogram.apollo.router.schema.load.duration = start.elapsed().as_secs_f64()
         );
@@ -0,0 +1,805 @@
+//! Configurable actions for GraphQL validation rules.
+//!
+//! Some validation rules can be downgraded, for example to accept operations from legacy clients
+//! that send directives unknown to the schema, and some lint rules can be enabled on top of the
+//! GraphQL specification's validation. Each listener address can have its own actions.
+//!
+//! The router checks the configurable rules itself when a document is parsed, and removes the
+//! parts of the document that do not pass them before the compiler validates the rest. The
+//! violations are stored with the parsed document, and each request checks them against the
+//! rules of the listener it was received on.
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+
+use apollo_compiler::ast;
+use apollo_compiler::ast::DirectiveLocation;
+use apollo_compiler::executable;
+use apollo_compiler::execution::GraphQLError;
+use apollo_compiler::execution::GraphQLLocation;
+use apollo_compiler::validation::NodeLocation;
+use apollo_compiler::ExecutableDocument;
+use apollo_compiler::Node;
+use apollo_compiler::Schema;
+use apollo_compiler::SourceMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::ValidationErrors;
+
+/// What to do when an operation does not pass a validation rule
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RuleAction {
+    /// Reject the operation
+    #[default]
+    Error,
+    /// Accept the operation and log a warning
+    Warn,
+    /// Accept the operation
+    Ignore,
+}
+
+impl RuleAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RuleAction::Error => "error",
+            RuleAction::Warn => "warn",
+            RuleAction::Ignore => "ignore",
+        }
+    }
+}
+
+/// A configurable validation rule
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Rule {
+    UnknownDirectives,
+    UnknownArguments,
+    DirectiveLocations,
+    RepeatedDirectives,
+    UnusedVariables,
+    UnusedFragments,
+    DeprecatedFields,
+}
+
+impl Rule {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Rule::UnknownDirectives => "unknown_directives",
+            Rule::UnknownArguments => "unknown_arguments",
+            Rule::DirectiveLocations => "directive_locations",
+            Rule::RepeatedDirectives => "repeated_directives",
+            Rule::UnusedVariables => "unused_variables",
+            Rule::UnusedFragments => "unused_fragments",
+            Rule::DeprecatedFields => "deprecated_fields",
+        }
+    }
+}
+
+/// A part of a document that does not pass a configurable rule
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RuleViolation {
+    pub(crate) rule: Rule,
+    pub(crate) error: GraphQLError,
+}
+
+/// Actions for GraphQL validation rules, by listener address. Requires
+/// `experimental_graphql_validation_mode: new`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ListenerValidationRules {
+    /// Rules of the requests received on an address that has no rules of its own
+    pub(crate) default: ValidationRules,
+    /// Rules of the requests received on a local address, such as `127.0.0.1:4000`. An
+    /// unspecified IP address like `0.0.0.0:4000` matches every address on its port
+    pub(crate) listeners: HashMap<SocketAddr, ValidationRules>,
+}
+
+impl ListenerValidationRules {
+    /// Returns true if every listener has the default actions
+    pub(crate) fn is_default(&self) -> bool {
+        self.default.is_default() && self.listeners.values().all(ValidationRules::is_default)
+    }
+
+    /// Returns the rules of the local address a request was received on
+    pub(crate) fn for_address(&self, address: Option<SocketAddr>) -> &ValidationRules {
+        address
+            .and_then(|address| {
+                self.listeners.get(&address).or_else(|| {
+                    self.listeners.iter().find_map(|(listen, rules)| {
+                        (listen.ip().is_unspecified() && listen.port() == address.port())
+                            .then_some(rules)
+                    })
+                })
+            })
+            .unwrap_or(&self.default)
+    }
+
+    /// Returns true if a listener enables the rule
+    pub(crate) fn enables(&self, rule: Rule) -> bool {
+        std::iter::once(&self.default)
+            .chain(self.listeners.values())
+            .any(|rules| rules.action(rule) != RuleAction::Ignore)
+    }
+}
+
+/// Actions for GraphQL validation rules
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ValidationRules {
+    /// Directives that are not defined in the schema
+    pub(crate) unknown_directives: RuleAction,
+    /// Arguments that are not defined on their field or directive
+    pub(crate) unknown_arguments: RuleAction,
+    /// Directives used in a location they are not defined for
+    pub(crate) directive_locations: RuleAction,
+    /// Non-repeatable directives used several times in the same location
+    pub(crate) repeated_directives: RuleAction,
+    /// Variables defined but not used by their operation
+    pub(crate) unused_variables: RuleAction,
+    /// Fragments defined but not used by any operation
+    pub(crate) unused_fragments: RuleAction,
+    /// Lint rule: fields marked `@deprecated` in the schema. Ignored by default
+    pub(crate) deprecated_fields: RuleAction,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        Self {
+            unknown_directives: RuleAction::Error,
+            unknown_arguments: RuleAction::Error,
+            directive_locations: RuleAction::Error,
+            repeated_directives: RuleAction::Error,
+            unused_variables: RuleAction::Error,
+            unused_fragments: RuleAction::Error,
+            deprecated_fields: RuleAction::Ignore,
+        }
+    }
+}
+
+impl ValidationRules {
+    /// Returns true if every rule has its default action
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn action(&self, rule: Rule) -> RuleAction {
+        match rule {
+            Rule::UnknownDirectives => self.unknown_directives,
+            Rule::UnknownArguments => self.unknown_arguments,
+            Rule::DirectiveLocations => self.directive_locations,
+            Rule::RepeatedDirectives => self.repeated_directives,
+            Rule::UnusedVariables => self.unused_variables,
+            Rule::UnusedFragments => self.unused_fragments,
+            Rule::DeprecatedFields => self.deprecated_fields,
+        }
+    }
+
+    /// Applies the configured actions to the violations of a document. Returns the errors
+    /// rejecting the operation, if any.
+    pub(crate) fn check(&self, violations: &[RuleViolation]) -> Option<ValidationErrors> {
+        let errors: Vec<GraphQLError> = violations
+            .iter()
+            .filter(|violation| record(violation, self.action(violation.rule)))
+            .map(|violation| violation.error.clone())
+            .collect();
+        (!errors.is_empty()).then_some(ValidationErrors { errors })
+    }
+}
+
+/// Counts a rule hit, returns true if the violation rejects the operation
+fn record(violation: &RuleViolation, action: RuleAction) -> bool {
+    if action == RuleAction::Ignore && violation.rule == Rule::DeprecatedFields {
+        // the lint rule is not a hit when it is not enabled
+        return false;
+    }
+    let rule = violation.rule.as_str();
+    u64_counter!(
+        "apollo_router_validation_rule_total",
+        "Number of validation rule hits, by rule and action",
+        1,
+        rule = rule,
+        action = action.as_str()
+    );
+    match action {
+        RuleAction::Error => true,
+        RuleAction::Warn => {
+            tracing::warn!(rule, "GraphQL validation: {}", violation.error.message);
+            false
+        }
+        RuleAction::Ignore => false,
+    }
+}
+
+/// Removes the parts of a document that do not pass the configurable rules, so that the compiler
+/// only validates the other rules, and returns them as violations.
+pub(crate) fn remove_violations(
+    document: &mut ast::Document,
+    schema: &Schema,
+) -> Vec<RuleViolation> {
+    // variables are unused if they were unused before anything was removed
+    let unused_variables: Vec<Vec<ast::Name>> = operations(document)
+        .map(|operation| {
+            let used = used_variables(document, operation);
+            operation
+                .variables
+                .iter()
+                .filter(|variable| !used.contains(&variable.name))
+                .map(|variable| variable.name.clone())
+                .collect()
+        })
+        .collect();
+
+    let mut remover = Remover {
+        schema,
+        sources: document.sources.clone(),
+        violations: Vec::new(),
+    };
+    for definition in &mut document.definitions {
+        match definition {
+            ast::Definition::OperationDefinition(operation) => {
+                let operation = operation.make_mut();
+                remover.directives(&mut operation.directives, operation.operation_type.into());
+                for variable in &mut operation.variables {
+                    remover.directives(
+                        &mut variable.make_mut().directives,
+                        DirectiveLocation::VariableDefinition,
+                    );
+                }
+                let root = schema.root_operation(operation.operation_type);
+                remover.selection_set(&mut operation.selection_set, root.cloned());
+            }
+            ast::Definition::FragmentDefinition(fragment) => {
+                let fragment = fragment.make_mut();
+                remover.directives(
+                    &mut fragment.directives,
+                    DirectiveLocation::FragmentDefinition,
+                );
+                remover.selection_set(
+                    &mut fragment.selection_set,
+                    Some(fragment.type_condition.clone()),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    // variables only used by the removed parts are removed without a violation
+    let used_variables: Vec<HashSet<ast::Name>> = operations(document)
+        .map(|operation| used_variables(document, operation))
+        .collect();
+    let used_fragments = used_fragments(document);
+    let mut variables = unused_variables.into_iter().zip(used_variables);
+    let mut unused_fragments = Vec::new();
+    document
+        .definitions
+        .retain_mut(|definition| match definition {
+            ast::Definition::OperationDefinition(operation) => {
+                let (unused, used) = variables.next().unwrap_or_default();
+                let operation = operation.make_mut();
+                operation.variables.retain(|variable| {
+                    if unused.contains(&variable.name) {
+                        remover.violation(
+                            Rule::UnusedVariables,
+                            format!("unused variable: `{}`", variable.name),
+                            variable.location(),
+                        );
+                        false
+                    } else {
+                        used.contains(&variable.name)
+                    }
+                });
+                true
+            }
+            ast::Definition::FragmentDefinition(fragment) => {
+                let used = used_fragments.contains(&fragment.name);
+                if !used {
+                    unused_fragments.push((fragment.name.clone(), fragment.location()));
+                }
+                used
+            }
+            _ => true,
+        });
+    for (name, location) in unused_fragments {
+        remover.violation(
+            Rule::UnusedFragments,
+            format!("fragment `{name}` must be used in an operation"),
+            location,
+        );
+    }
+    remover.violations
+}
+
+struct Remover<'a> {
+    schema: &'a Schema,
+    sources: SourceMap,
+    violations: Vec<RuleViolation>,
+}
+
+impl Remover<'_> {
+    fn violation(&mut self, rule: Rule, message: String, location: Option<NodeLocation>) {
+        let locations = location
+            .and_then(|location| {
+                self.sources
+                    .get(&location.file_id())?
+                    .get_line_column(location.offset())
+            })
+            .map(|(line, column)| GraphQLLocation {
+                line: line + 1,
+                column: column + 1,
+            })
+            .into_iter()
+            .collect();
+        self.violations.push(RuleViolation {
+            rule,
+            error: GraphQLError { message, locations },
+        });
+    }
+
+    fn directives(&mut self, directives: &mut ast::DirectiveList, location: DirectiveLocation) {
+        let schema = self.schema;
+        let mut seen = HashSet::new();
+        directives.retain_mut(|directive| {
+            let Some(definition) = schema.directive_definitions.get(&directive.name) else {
+                self.violation(
+                    Rule::UnknownDirectives,
+                    format!(
+                        "cannot find directive `{}` in this document",
+                        directive.name
+                    ),
+                    directive.location(),
+                );
+                return false;
+            };
+            if !definition.locations.contains(&location) {
+                self.violation(
+                    Rule::DirectiveLocations,
+                    format!(
+                        "{} directive is not supported for {} location",
+                        directive.name,
+                        location.name()
+                    ),
+                    directive.location(),
+                );
+                return false;
+            }
+            if !definition.repeatable && !seen.insert(directive.name.clone()) {
+                self.violation(
+                    Rule::RepeatedDirectives,
+                    format!(
+                        "non-repeatable directive {} can only be used once per location",
+                        directive.name
+                    ),
+                    directive.location(),
+                );
+                return false;
+            }
+            if directive
+                .arguments
+                .iter()
+                .any(|argument| !is_defined(&definition.arguments, &argument.name))
+            {
+                self.arguments(&mut directive.make_mut().arguments, &definition.arguments);
+            }
+            true
+        });
+    }
+
+    fn arguments(
+        &mut self,
+        arguments: &mut Vec<Node<ast::Argument>>,
+        definitions: &[Node<ast::InputValueDefinition>],
+    ) {
+        arguments.retain(|argument| {
+            let defined = is_defined(definitions, &argument.name);
+            if !defined {
+                self.violation(
+                    Rule::UnknownArguments,
+                    format!("the argument `{}` is not supported", argument.name),
+                    argument.location(),
+                );
+            }
+            defined
+        });
+    }
+
+    fn selection_set(&mut self, selections: &mut [ast::Selection], ty: Option<ast::NamedType>) {
+        for selection in selections {
+            match selection {
+                ast::Selection::Field(field) => {
+                    let field = field.make_mut();
+                    let definition = ty.as_ref().and_then(|ty| {
+                        self.schema
+                            .type_field(ty.as_str(), field.name.as_str())
+                            .ok()
+                    });
+                    if let Some(definition) = definition {
+                        self.arguments(&mut field.arguments, &definition.arguments);
+                    }
+                    self.directives(&mut field.directives, DirectiveLocation::Field);
+                    let ty = definition.map(|definition| definition.ty.inner_named_type().clone());
+                    self.selection_set(&mut field.selection_set, ty);
+                }
+                ast::Selection::FragmentSpread(spread) => {
+                    self.directives(
+                        &mut spread.make_mut().directives,
+                        DirectiveLocation::FragmentSpread,
+                    );
+                }
+                ast::Selection::InlineFragment(inline) => {
+                    let inline = inline.make_mut();
+                    self.directives(&mut inline.directives, DirectiveLocation::InlineFragment);
+                    let ty = inline.type_condition.clone().or_else(|| ty.clone());
+                    self.selection_set(&mut inline.selection_set, ty);
+                }
+            }
+        }
+    }
+}
+
+fn is_defined(definitions: &[Node<ast::InputValueDefinition>], name: &str) -> bool {
+    definitions.iter().any(|definition| definition.name == name)
+}
+
+fn operations(document: &ast::Document) -> impl Iterator<Item = &ast::OperationDefinition> {
+    document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            ast::Definition::OperationDefinition(operation) => Some(&**operation),
+            _ => None,
+        })
+}
+
+fn fragments(document: &ast::Document) -> HashMap<&ast::Name, &ast::FragmentDefinition> {
+    document
+        .definitions
+        .iter()
+        .filter_map(|definition| match definition {
+            ast::Definition::FragmentDefinition(fragment) => Some((&fragment.name, &**fragment)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The variables used by an operation, including in the fragments it spreads
+fn used_variables(
+    document: &ast::Document,
+    operation: &ast::OperationDefinition,
+) -> HashSet<ast::Name> {
+    let fragments = fragments(document);
+    let mut used = HashSet::new();
+    let mut visited = HashSet::new();
+    directive_variables(&operation.directives, &mut used);
+    selection_set_variables(
+        &operation.selection_set,
+        &fragments,
+        &mut visited,
+        &mut used,
+    );
+    used
+}
+
+fn selection_set_variables<'a>(
+    selections: &'a [ast::Selection],
+    fragments: &HashMap<&ast::Name, &'a ast::FragmentDefinition>,
+    visited: &mut HashSet<&'a ast::Name>,
+    used: &mut HashSet<ast::Name>,
+) {
+    for selection in selections {
+        match selection {
+            ast::Selection::Field(field) => {
+                for argument in &field.arguments {
+                    value_variables(&argument.value, used);
+                }
+                directive_variables(&field.directives, used);
+                selection_set_variables(&field.selection_set, fragments, visited, used);
+            }
+            ast::Selection::FragmentSpread(spread) => {
+                directive_variables(&spread.directives, used);
+                if visited.insert(&spread.fragment_name) {
+                    if let Some(fragment) = fragments.get(&spread.fragment_name) {
+                        directive_variables(&fragment.directives, used);
+                        selection_set_variables(&fragment.selection_set, fragments, visited, used);
+                    }
+                }
+            }
+            ast::Selection::InlineFragment(inline) => {
+                directive_variables(&inline.directives, used);
+                selection_set_variables(&inline.selection_set, fragments, visited, used);
+            }
+        }
+    }
+}
+
+fn directive_variables(directives: &ast::DirectiveList, used: &mut HashSet<ast::Name>) {
+    for directive in directives.iter() {
+        for argument in &directive.arguments {
+            value_variables(&argument.value, used);
+        }
+    }
+}
+
+fn value_variables(value: &ast::Value, used: &mut HashSet<ast::Name>) {
+    match value {
+        ast::Value::Variable(name) => {
+            used.insert(name.clone());
+        }
+        ast::Value::List(values) => {
+            for value in values {
+                value_variables(value, used);
+            }
+        }
+        ast::Value::Object(fields) => {
+            for (_, value) in fields {
+                value_variables(value, used);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The fragments spread in an operation or in another fragment
+fn used_fragments(document: &ast::Document) -> HashSet<ast::Name> {
+    fn spreads(selections: &[ast::Selection], used: &mut HashSet<ast::Name>) {
+        for selection in selections {
+            match selection {
+                ast::Selection::Field(field) => spreads(&field.selection_set, used),
+                ast::Selection::FragmentSpread(spread) => {
+                    used.insert(spread.fragment_name.clone());
+                }
+                ast::Selection::InlineFragment(inline) => spreads(&inline.selection_set, used),
+            }
+        }
+    }
+
+    let mut used = HashSet::new();
+    for definition in &document.definitions {
+        match definition {
+            ast::Definition::OperationDefinition(operation) => {
+                spreads(&operation.selection_set, &mut used)
+            }
+            ast::Definition::FragmentDefinition(fragment) => {
+                spreads(&fragment.selection_set, &mut used)
+            }
+            _ => {}
+        }
+    }
+    used
+}
+
+/// Fields marked `@deprecated` selected by the document
+pub(crate) fn deprecated_fields(document: &ExecutableDocument) -> Vec<RuleViolation> {
+    let mut violations = Vec::new();
+    // Fragments are checked once where they are defined, rather than where they are spread
+    for selection_set in document
+        .all_operations()
+        .map(|operation| &operation.selection_set)
+        .chain(
+            document
+                .fragments
+                .values()
+                .map(|fragment| &fragment.selection_set),
+        )
+    {
+        deprecated_fields_in(document, selection_set, &mut violations);
+    }
+    violations
+}
+
+fn deprecated_fields_in(
+    document: &ExecutableDocument,
+    selection_set: &executable::SelectionSet,
+    violations: &mut Vec<RuleViolation>,
+) {
+    for selection in &selection_set.selections {
+        match selection {
+            executable::Selection::Field(field) => {
+                if let Some(deprecated) = field.definition.directives.get("deprecated") {
+                    let mut message = format!(
+                        "the field `{}.{}` is deprecated",
+                        selection_set.ty, field.name
+                    );
+                    if let Some(reason) = deprecated
+                        .argument_by_name("reason")
+                        .and_then(|reason| reason.as_str())
+                    {
+                        message.push_str(": ");
+                        message.push_str(reason);
+                    }
+                    let locations = field
+                        .location()
+                        .and_then(|location| {
+                            document
+                                .sources
+                                .get(&location.file_id())?
+                                .get_line_column(location.offset())
+                        })
+                        .map(|(line, column)| GraphQLLocation {
+                            line: line + 1,
+                            column: column + 1,
+                        })
+                        .into_iter()
+                        .collect();
+                    violations.push(RuleViolation {
+                        rule: Rule::DeprecatedFields,
+                        error: GraphQLError { message, locations },
+                    });
+                }
+                deprecated_fields_in(document, &field.selection_set, violations);
+            }
+            executable::Selection::InlineFragment(inline) => {
+                deprecated_fields_in(document, &inline.selection_set, violations);
+            }
+            executable::Selection::FragmentSpread(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+        type Query {
+            me: User
+        }
+        type User {
+            id: ID!
+            name: String @deprecated(reason: "use `fullName`")
+            fullName: String
+        }
+    "#;
+
+    /// Returns the violations of the document, and the errors of the compiler on what remains
+    fn validate(query: &str) -> (Vec<RuleViolation>, Vec<String>) {
+        let schema = Schema::parse_and_validate(SCHEMA, "schema.graphql").unwrap();
+        let mut ast = ast::Document::parse(query, "query.graphql").unwrap();
+        let mut violations = remove_violations(&mut ast, &schema);
+        let (document, errors) = match ast.to_executable_validate(&schema) {
+            Ok(document) => (document.into_inner(), Vec::new()),
+            Err(invalid) => (
+                invalid.partial,
+                invalid
+                    .errors
+                    .iter()
+                    .map(|error| error.to_json().message)
+                    .collect(),
+            ),
+        };
+        violations.extend(deprecated_fields(&document));
+        (violations, errors)
+    }
+
+    fn check(rules: &ValidationRules, query: &str) -> Option<Vec<String>> {
+        let (violations, errors) = validate(query);
+        assert_eq!(errors, Vec::<String>::new());
+        rules.check(&violations).map(|errors| {
+            errors
+                .errors
+                .into_iter()
+                .map(|error| error.message)
+                .collect()
+        })
+    }
+
+    #[test]
+    fn it_downgrades_rules() {
+        let query = "query($unused: ID) { me @legacy { id } }";
+        let default = ValidationRules::default();
+        assert_eq!(
+            check(&default, query).unwrap(),
+            [
+                "cannot find directive `legacy` in this document",
+                "unused variable: `unused`"
+            ]
+        );
+
+        let rules = ValidationRules {
+            unknown_directives: RuleAction::Warn,
+            ..Default::default()
+        };
+        assert_eq!(check(&rules, query).unwrap(), ["unused variable: `unused`"]);
+
+        let rules = ValidationRules {
+            unknown_directives: RuleAction::Warn,
+            unused_variables: RuleAction::Ignore,
+            ..Default::default()
+        };
+        assert_eq!(check(&rules, query), None);
+    }
+
+    #[test]
+    fn it_removes_violations_from_the_document() {
+        let query = r#"
+            query($id: ID, $unused: ID) @skip(if: false) {
+                me @legacy(id: $id) { id(format: "short") name @include(if: true) @include(if: true) }
+            }
+            fragment user on User { id }
+        "#;
+        let (violations, errors) = validate(query);
+        assert_eq!(errors, Vec::<String>::new());
+        let rules: Vec<Rule> = violations.iter().map(|violation| violation.rule).collect();
+        assert_eq!(
+            rules,
+            [
+                Rule::DirectiveLocations,
+                Rule::UnknownDirectives,
+                Rule::UnknownArguments,
+                Rule::RepeatedDirectives,
+                Rule::UnusedVariables,
+                Rule::UnusedFragments,
+                Rule::DeprecatedFields,
+            ]
+        );
+    }
+
+    #[test]
+    fn it_keeps_other_rules() {
+        let rules = ValidationRules {
+            unknown_directives: RuleAction::Ignore,
+            ..Default::default()
+        };
+        let (violations, errors) = validate("{ me { unknown } }");
+        assert!(rules.check(&violations).is_none());
+        assert!(errors.contains(&"type `User` does not have a field `unknown`".to_string()));
+    }
+
+    #[test]
+    fn it_reports_deprecated_fields() {
+        let query = "{ me { ...user } } fragment user on User { id name }";
+        let rules = ValidationRules {
+            deprecated_fields: RuleAction::Ignore,
+            ..Default::default()
+        };
+        assert!(rules.is_default());
+        assert_eq!(check(&rules, query), None);
+
+        let rules = ValidationRules {
+            deprecated_fields: RuleAction::Warn,
+            ..Default::default()
+        };
+        assert_eq!(check(&rules, query), None);
+
+        let rules = ValidationRules {
+            deprecated_fields: RuleAction::Error,
+            ..Default::default()
+        };
+        assert_eq!(
+            check(&rules, query).unwrap(),
+            ["the field `User.name` is deprecated: use `fullName`"]
+        );
+    }
+
+    #[test]
+    fn it_selects_the_rules_of_the_listener() {
+        let lenient = ValidationRules {
+            unknown_directives: RuleAction::Ignore,
+            ..Default::default()
+        };
+        let rules = ListenerValidationRules {
+            default: Default::default(),
+            listeners: [
+                ("127.0.0.1:4000".parse().unwrap(), lenient.clone()),
+                ("0.0.0.0:4001".parse().unwrap(), lenient.clone()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        assert!(!rules.is_default());
+        assert_eq!(
+            rules.for_address(Some("127.0.0.1:4000".parse().unwrap())),
+            &lenient
+        );
+        assert_eq!(
+            rules.for_address(Some("10.0.0.1:4001".parse().unwrap())),
+            &lenient
+        );
+        assert!(rules
+            .for_address(Some("10.0.0.1:4000".parse().unwrap()))
+            .is_default());
+        assert!(rules.for_address(None).is_default());
+        assert!(!rules.enables(Rule::DeprecatedFields));
+    }
+}
@@ -31,6 +31,7 @@ use crate::configuration::metrics::Metrics;
 use crate::configuration::Configuration;
 use crate::configuration::Discussed;
 use crate::configuration::ListenAddr;
+use crate::plugin::LifecycleEvent;
 use crate::router::Event::UpdateLicense;
 use crate::router_factory::RouterFactory;
 use crate::router_factory::RouterSuperServiceFactory;
@@ -182,6 +183,7 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
                 // Have things actually changed?
                 let (mut license_reload, mut schema_reload, mut configuration_reload) =
                     (false, false, false);
+                let previous_schema = schema.clone();
                 if let Some(new_configuration) = new_configuration {
                     *configuration = new_configuration;
                     configuration_reload = true;
@@ -235,6 +237,25 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
                                 event = STATE_CHANGE,
                                 "reload complete"
                             );
+                            if let Running {
+                                router_service_factory,
+                                ..
+                            } = &new_state
+                            {
+                                if schema_reload {
+                                    router_service_factory.lifecycle_event(
+                                        &LifecycleEvent::SchemaChanged {
+                                            previous_schema_id: Schema::schema_id(&previous_schema),
+                                            schema_id: Schema::schema_id(schema.as_str()),
+                                            previous_supergraph_sdl: previous_schema.clone(),
+                                        },
+                                    );
+                                }
+                                if configuration_reload {
+                                    router_service_factory
+                                        .lifecycle_event(&LifecycleEvent::ConfigurationChanged);
+                                }
+                            }
                             Some(new_state)
                         }
                         Err(e) => {
@@ -278,11 +299,13 @@ impl<FA: RouterSuperServiceFactory> State<FA> {
         match self {
             Running {
                 server_handle: Some(server_handle),
+                router_service_factory,
                 mut all_connections_stopped_signals,
                 ..
             } => {
                 // We want to set the ready state to false before we start shutting down the server.
                 http_server_factory.ready(false);
+                router_service_factory.lifecycle_event(&LifecycleEvent::Draining);
                 tracing::info!("shutting down");
                 let state = server_handle
                     .shutdown()
@@ -28,6 +28,7 @@ use apollo_router::services::router;
 use apollo_router::services::router::BoxCloneService;
 use apollo_router::services::subgraph;
 use apollo_router::services::supergraph;
+use apollo_router::Context;
 use apollo_router::TestHarness;
 use axum::body::Bytes;
 use axum::routing::post;
@@ -585,6 +586,44 @@ async fn test_send_variable_value() {
     }
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_send_context() {
+    for use_legacy_request_span in [true, false] {
+        let context = Context::new();
+        context.insert("tenant", "acme".to_string()).unwrap();
+        let request = supergraph::Request::fake_builder()
+            .query("query{topProducts{name reviews {author{name}} reviews{author{name}}}}")
+            .header("send-header", "Header value")
+            .context(context)
+            .build()
+            .unwrap();
+        let req: router::Request = request.try_into().expect("could not convert request");
+        let reports = Arc::new(Mutex::new(vec![]));
+        let report = get_trace_report(reports, req, use_legacy_request_span).await;
+        let trace = report
+            .traces_per_query
+            .values()
+            .next()
+            .expect("traces and stats required")
+            .trace
+            .first()
+            .expect("trace required");
+        let request_headers = &trace.http.as_ref().expect("http required").request_headers;
+        assert_eq!(
+            request_headers
+                .get("context:tenant")
+                .map(|values| values.value.clone()),
+            Some(vec!["acme".to_string()])
+        );
+        assert_eq!(
+            request_headers
+                .get("send-header")
+                .map(|values| values.value.clone()),
+            Some(vec!["Header value".to_string()])
+        );
+    }
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_stats() {
     let request = supergraph::Request::fake_builder()